@@ -0,0 +1,127 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --all-reports support, and the templates in --output-dir.
+//
+// This writes the reports that CI artifact steps usually collect into
+// --output-dir in one run, in a fixed layout:
+//
+// - `html/` (--html)
+// - `lcov.info` (--lcov)
+// - `coverage.json` (--json)
+// - `summary.json` (--summary-json)
+// - `manifest.json`, which describes the run and the paths of the other reports
+//
+// `{package}` and `{commit}` in --output-dir are replaced with the name of the
+// current package (or the directory name of the workspace root for virtual
+// workspaces) and the hash of the commit checked out in the workspace, so each
+// package or commit can have its own directory without extra scripting.
+
+use std::ffi::OsString;
+
+use anyhow::{bail, Context as _, Result};
+use camino::Utf8PathBuf;
+use serde_derive::Serialize;
+
+use crate::{
+    cargo::Workspace, context::Context, fs, function_filter::FunctionFilter, json::SummaryCommit,
+    Format,
+};
+
+/// The version of the schema of `manifest.json`.
+const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Manifest {
+    schema_version: u32,
+    cargo_llvm_cov_version: &'static str,
+    /// Arguments passed to cargo-llvm-cov (excluding `cargo llvm-cov`)
+    args: Vec<String>,
+    package: String,
+    commit: Option<SummaryCommit>,
+    /// Paths of the reports, relative to the output directory
+    reports: ManifestReports,
+}
+
+#[derive(Serialize)]
+struct ManifestReports {
+    html: &'static str,
+    lcov: &'static str,
+    json: &'static str,
+    summary_json: &'static str,
+}
+
+const REPORTS: ManifestReports = ManifestReports {
+    html: "html/index.html",
+    lcov: "lcov.info",
+    json: "coverage.json",
+    summary_json: "summary.json",
+};
+
+/// Replaces `{package}` and `{commit}` in the path of --output-dir.
+pub(crate) fn expand_output_dir(ws: &Workspace, dir: &Utf8PathBuf) -> Result<Utf8PathBuf> {
+    let mut expanded = dir.as_str().to_owned();
+    if expanded.contains("{package}") {
+        expanded = expanded.replace("{package}", &package_name(ws));
+    }
+    if expanded.contains("{commit}") {
+        let Some(commit) = crate::git_commit(&ws.metadata.workspace_root) else {
+            bail!("{{commit}} in --output-dir requires the workspace to be in a git repository");
+        };
+        expanded = expanded.replace("{commit}", &commit.sha);
+    }
+    Ok(expanded.into())
+}
+
+/// Returns the name of the current package, or the directory name of the
+/// workspace root if the current manifest is a virtual manifest.
+fn package_name(ws: &Workspace) -> String {
+    let metadata = &ws.metadata;
+    metadata
+        .packages
+        .values()
+        .find(|p| p.manifest_path == ws.current_manifest)
+        .map(|p| p.name.clone())
+        .or_else(|| metadata.workspace_root.file_name().map(str::to_owned))
+        .unwrap_or_default()
+}
+
+/// Writes the reports of --all-reports.
+pub(crate) fn generate(
+    cx: &mut Context,
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+    function_filter: Option<&FunctionFilter>,
+) -> Result<()> {
+    let output_dir = cx.args.cov.output_dir.clone();
+    let dir = output_dir.clone().unwrap_or_else(|| cx.ws.output_dir.clone());
+    fs::create_dir_all(&dir)?;
+    for (format, path) in [
+        (Format::Html, None),
+        (Format::LCov, Some(REPORTS.lcov)),
+        (Format::Json, Some(REPORTS.json)),
+        (Format::SummaryJson, Some(REPORTS.summary_json)),
+    ] {
+        match path {
+            Some(path) => cx.args.cov.output_path = Some(dir.join(path)),
+            None => cx.args.cov.output_dir = Some(dir.clone()),
+        }
+        let res = format.generate_report(cx, object_files, ignore_filename_regex, function_filter);
+        cx.args.cov.output_path = None;
+        cx.args.cov.output_dir.clone_from(&output_dir);
+        res.with_context(|| format!("failed to generate {}", path.unwrap_or(REPORTS.html)))?;
+    }
+
+    let manifest = Manifest {
+        schema_version: MANIFEST_SCHEMA_VERSION,
+        cargo_llvm_cov_version: env!("CARGO_PKG_VERSION"),
+        args: cx.args.raw_args.clone(),
+        package: package_name(&cx.ws),
+        commit: crate::git_commit(&cx.ws.metadata.workspace_root),
+        reports: REPORTS,
+    };
+    let path = dir.join("manifest.json");
+    fs::write(&path, serde_json::to_vec_pretty(&manifest)?)?;
+    eprintln!();
+    status!("Finished", "reports saved to {dir} (see {path})");
+    Ok(())
+}
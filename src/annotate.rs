@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// `cargo llvm-cov annotate` support.
+//
+// This writes a copy of each source file in the report with the execution
+// count of each line in a gutter column, in the same format as the `.gcov`
+// files generated by gcov: `-` for lines without coverage data, `#####` for
+// lines not executed, and the count followed by `*` for partially covered
+// lines (some regions or branches in the line were not executed). The copies
+// are written to `<output-dir>/annotate/<path relative to the workspace root>.gcov`,
+// and source files outside the workspace (e.g., dependencies) are skipped.
+//
+// Refs:
+// - https://gcc.gnu.org/onlinedocs/gcc/Invoking-Gcov.html
+
+use std::fmt::Write as _;
+
+use anyhow::Result;
+
+use crate::{
+    context::Context,
+    fs,
+    json::{LineCoverage, LlvmCovJsonExport},
+};
+
+/// Writes annotated copies of the source files in the report.
+pub(crate) fn write(cx: &Context, json: &LlvmCovJsonExport) -> Result<()> {
+    let output_dir = cx.args.cov.output_dir.as_ref().unwrap_or(&cx.ws.output_dir).join("annotate");
+    fs::remove_dir_all(&output_dir)?;
+    fs::create_dir_all(&output_dir)?;
+    for file in json.file_names() {
+        let Some(relative) = cx.relative_source_path(file) else { continue };
+        let Some(lines) = json.get_line_coverage(file) else { continue };
+        let source = fs::read_to_string(cx.source_path(file))?;
+        let out = output_dir.join(format!("{relative}.gcov"));
+        fs::create_dir_all(out.parent().unwrap())?;
+        fs::write(out, gcov(relative.as_str(), &source, &lines))?;
+    }
+    status!("Finished", "annotated sources saved to {output_dir}");
+    Ok(())
+}
+
+fn gcov(path: &str, source: &str, lines: &[LineCoverage]) -> String {
+    let mut out = format!("{:>9}:{:>5}:Source:{path}\n", "-", 0);
+    let mut lines = lines.iter().peekable();
+    for (i, text) in (1..).zip(source.lines()) {
+        while lines.next_if(|l| l.line < i).is_some() {}
+        let count = match lines.next_if(|l| l.line == i) {
+            None => "-".to_owned(),
+            Some(l) if l.count == 0 => "#####".to_owned(),
+            Some(l) if l.partial => format!("{}*", l.count),
+            Some(l) => l.count.to_string(),
+        };
+        let _ = writeln!(out, "{count:>9}:{i:>5}:{text}");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gcov_format() {
+        let source = "fn a() {\n    b();\n}\n\n// comment\n";
+        let lines = [(1, 12, false), (2, 12, true), (3, 0, false)]
+            .map(|(line, count, partial)| LineCoverage { line, count, partial });
+        assert_eq!(
+            gcov("src/lib.rs", source, &lines),
+            "        -:    0:Source:src/lib.rs\n       12:    1:fn a() {\n      12*:    2:    \
+             b();\n    #####:    3:}\n        -:    4:\n        -:    5:// comment\n"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{bail, Context as _, Result};
+
+use crate::{
+    cli::{Args, Subcommand},
+    env, fs,
+    json::LlvmCovJsonExport,
+    term,
+};
+
+/// A builder for running cargo-llvm-cov programmatically.
+///
+/// This runs the same pipeline as `cargo llvm-cov` (building and running tests
+/// with instrumentation, merging profile data, and exporting coverage data), and
+/// returns the report as [`LlvmCovJsonExport`] ([`run`](Self::run)) or as a
+/// string in the format selected by [`format`](Self::format)
+/// ([`render`](Self::render)) instead of printing it.
+///
+/// Status messages, warnings, and the output of cargo and tests are printed to
+/// the standard error and standard output as with the CLI.
+///
+/// # Examples
+///
+/// ```no_run
+/// use cargo_llvm_cov::{json::CoverageKind, Coverage, ReportFormat};
+///
+/// let report = Coverage::new().workspace("path/to/workspace").arg("--workspace").run()?;
+/// println!("{:.2}%", report.get_coverage_percent(CoverageKind::Lines)?);
+///
+/// let lcov = Coverage::new().format(ReportFormat::Lcov).render()?;
+/// std::fs::write("lcov.info", lcov)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct Coverage {
+    subcommand: Option<String>,
+    manifest_path: Option<PathBuf>,
+    summary_only: bool,
+    format: ReportFormat,
+    args: Vec<OsString>,
+}
+
+/// The format of the report rendered by [`Coverage::render`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReportFormat {
+    /// The JSON format exported by llvm-cov (`--json`)
+    #[default]
+    Json,
+    /// The lcov tracefile format (`--lcov`)
+    Lcov,
+    /// Cobertura XML (`--cobertura`)
+    Cobertura,
+    /// The Codecov custom coverage format (`--codecov`)
+    Codecov,
+    /// The stable summary schema of `--summary-json`
+    SummaryJson,
+    /// Source code annotated with execution counts (`--text`)
+    Text,
+}
+
+impl ReportFormat {
+    const fn flag(self) -> &'static str {
+        match self {
+            Self::Json => "--json",
+            Self::Lcov => "--lcov",
+            Self::Cobertura => "--cobertura",
+            Self::Codecov => "--codecov",
+            Self::SummaryJson => "--summary-json",
+            Self::Text => "--text",
+        }
+    }
+}
+
+impl Coverage {
+    /// Creates a new `Coverage` builder, which runs `cargo llvm-cov` in the
+    /// current directory by default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the subcommand to run: `test` (default), `run`, `nextest`, or `report`.
+    pub fn subcommand(mut self, subcommand: impl Into<String>) -> Self {
+        self.subcommand = Some(subcommand.into());
+        self
+    }
+
+    /// Sets the path to the workspace root directory or its `Cargo.toml`.
+    pub fn workspace(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        self.manifest_path =
+            Some(if path.is_dir() { path.join("Cargo.toml") } else { path.into() });
+        self
+    }
+
+    /// Exports only summary information for each file in the report (same as `--summary-only`).
+    pub fn summary_only(mut self, summary_only: bool) -> Self {
+        self.summary_only = summary_only;
+        self
+    }
+
+    /// Sets the format of the report returned by [`render`](Self::render)
+    /// (default to [`ReportFormat::Json`]).
+    pub fn format(mut self, format: ReportFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Adds a flag of `cargo llvm-cov` (e.g., `--workspace`, `--fail-under-lines=80`).
+    ///
+    /// The flags that select the report format and output location (e.g.,
+    /// `--lcov`, `--output-path`) cannot be used because the report is returned
+    /// to the caller; use [`format`](Self::format) instead.
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Adds multiple flags of `cargo llvm-cov`. See [`arg`](Self::arg) for more.
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<OsString>>) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Runs the pipeline and returns the coverage report.
+    ///
+    /// Returns an error if any step fails, or if errors were reported while
+    /// running the pipeline (e.g., the coverage is less than the value specified
+    /// by `--fail-under-lines`). This also returns an error if a format other
+    /// than [`ReportFormat::Json`] is selected; use [`render`](Self::render) for
+    /// them.
+    pub fn run(&self) -> Result<LlvmCovJsonExport> {
+        if self.format != ReportFormat::Json {
+            bail!("Coverage::run only supports JSON format; use Coverage::render instead");
+        }
+        serde_json::from_str(&self.render()?).context("failed to parse coverage report")
+    }
+
+    /// Runs the pipeline and returns the coverage report rendered in the format
+    /// selected by [`format`](Self::format).
+    ///
+    /// Returns an error in the same cases as [`run`](Self::run).
+    pub fn render(&self) -> Result<String> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let output_path = env::temp_dir().join(format!(
+            "cargo-llvm-cov-{}-{}.out",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let mut args: Vec<OsString> = vec!["cargo".into(), "llvm-cov".into()];
+        args.extend(self.subcommand.iter().map(Into::into));
+        args.push(self.format.flag().into());
+        args.push("--output-path".into());
+        args.push(output_path.clone().into());
+        if self.summary_only {
+            args.push("--summary-only".into());
+        }
+        if let Some(manifest_path) = &self.manifest_path {
+            args.push("--manifest-path".into());
+            args.push(manifest_path.into());
+        }
+        args.extend(self.args.iter().cloned());
+
+        term::init_coloring();
+        let args = Args::parse_from(args)?;
+        match args.subcommand {
+            Subcommand::None
+            | Subcommand::Test
+            | Subcommand::Run
+            | Subcommand::Nextest
+            | Subcommand::Report => {}
+            subcommand => bail!("{} subcommand is not supported", subcommand.as_str()),
+        }
+        if args.cov.no_report {
+            bail!("--no-report is not supported");
+        }
+
+        // Errors reported while running the pipeline are returned to the caller,
+        // so restore the error flag of the process once the pipeline finishes.
+        let prev_error = term::error::ignore();
+        let res = crate::run(args);
+        let reported_error = term::error();
+        drop(prev_error);
+        let out = fs::read_to_string(&output_path);
+        if out.is_ok() {
+            fs::remove_file(&output_path)?;
+        }
+        res?;
+        if reported_error {
+            bail!("cargo-llvm-cov reported errors; see the messages printed above");
+        }
+        Ok(out?)
+    }
+}
@@ -0,0 +1,224 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --blame support.
+//
+// llvm-cov has no way to add information to the html report, so the pages of
+// source files are rewritten after llvm-cov generated them: a column with the
+// author and the age of the commit that last changed each line (obtained by
+// `git blame`) is inserted between the execution count and the source code.
+// Pages of files that are not tracked by git are left as is.
+//
+// Refs:
+// - https://git-scm.com/docs/git-blame#_the_porcelain_format
+
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use camino::Utf8Path;
+use regex::{Captures, Regex};
+use walkdir::WalkDir;
+
+use crate::{context::Context, fs, json::xml_escape};
+
+const STYLE: &str = "
+.blame pre {
+  color: #888;
+  max-width: 24ch;
+  overflow: hidden;
+  text-overflow: ellipsis;
+}
+.blame.recent pre {
+  color: #c05000;
+}
+";
+
+/// Lines changed within this period are highlighted as recent changes.
+const RECENT: u64 = 30 * 24 * 60 * 60;
+
+#[derive(Debug, PartialEq, Eq)]
+struct BlameLine {
+    commit: String,
+    author: String,
+    /// Seconds since the Unix epoch
+    author_time: u64,
+    summary: String,
+}
+
+/// Adds the result of `git blame` to the pages of source files in the html report.
+pub(crate) fn annotate(cx: &Context, html_dir: &Utf8Path) -> Result<()> {
+    let now = cx
+        .source_date_epoch
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()));
+    let title = Regex::new("<div class='source-name-title'><pre>([^<]*)</pre></div>").unwrap();
+    let header = "<td><pre>Count</pre></td>";
+    // The line number and the execution count of each line.
+    let row = Regex::new(concat!(
+        "<a name='L([0-9]+)' href='#L[0-9]+'><pre>[0-9]+</pre></a></td>",
+        "<td class='[a-z-]+'>(?:<pre>[^<]*</pre>)?</td>",
+    ))
+    .unwrap();
+    let mut annotated = false;
+    for entry in WalkDir::new(html_dir.join("coverage")).into_iter().filter_map(Result::ok) {
+        let path = entry.path();
+        if path.extension().is_none_or(|e| e != "html") {
+            continue;
+        }
+        let html = fs::read_to_string(path)?;
+        let Some(file) = title.captures(&html).map(|c| unescape(&c[1])) else { continue };
+        let file = cx.source_path(&file);
+        let Some(blame) = blame(&file) else { continue };
+        let html = html.replacen(header, &format!("{header}<td><pre>Blame</pre></td>"), 1);
+        let html = row.replace_all(&html, |c: &Captures<'_>| {
+            let line = c[1].parse::<usize>().ok().and_then(|i| blame.get(i.checked_sub(1)?));
+            let cell =
+                line.map_or_else(|| "<td class='blame'></td>".to_owned(), |l| cell_html(l, now));
+            format!("{}{cell}", &c[0])
+        });
+        fs::write(path, &*html)?;
+        annotated = true;
+    }
+    if annotated {
+        let style = html_dir.join("style.css");
+        let mut css = fs::read_to_string(&style)?;
+        css.push_str(STYLE);
+        fs::write(style, css)?;
+    } else {
+        warn!("--blame: no source files in the html report are tracked by git");
+    }
+    Ok(())
+}
+
+/// Runs `git blame` on the given file, and returns the information of each line.
+///
+/// Returns `None` if the file is not tracked by git or git is not available.
+fn blame(file: &Utf8Path) -> Option<Vec<BlameLine>> {
+    let out = cmd!("git", "blame", "--line-porcelain", "--", file.file_name()?)
+        .dir(file.parent()?)
+        .read()
+        .ok()?;
+    Some(parse_porcelain(&out))
+}
+
+fn parse_porcelain(out: &str) -> Vec<BlameLine> {
+    let mut lines = vec![];
+    let mut headers: HashMap<&str, &str> = HashMap::new();
+    let mut commit = None;
+    for line in out.lines() {
+        if line.starts_with('\t') {
+            // The content of the line ends the entry.
+            lines.push(BlameLine {
+                commit: commit.take().unwrap_or_default(),
+                author: headers.get("author").copied().unwrap_or_default().to_owned(),
+                author_time: headers.get("author-time").and_then(|t| t.parse().ok()).unwrap_or(0),
+                summary: headers.get("summary").copied().unwrap_or_default().to_owned(),
+            });
+            headers.clear();
+        } else if commit.is_none() {
+            commit = line.split(' ').next().map(str::to_owned);
+        } else if let Some((key, value)) = line.split_once(' ') {
+            headers.insert(key, value);
+        }
+    }
+    lines
+}
+
+fn cell_html(line: &BlameLine, now: u64) -> String {
+    if line.commit.bytes().all(|b| b == b'0') {
+        return "<td class='blame recent'><pre>not committed</pre></td>".to_owned();
+    }
+    let age = now.saturating_sub(line.author_time);
+    let class = if age < RECENT { "blame recent" } else { "blame" };
+    let short = line.commit.get(..10).unwrap_or(&line.commit);
+    format!(
+        "<td class='{class}' title='{} {}: {}'><pre>{} ({})</pre></td>",
+        short,
+        xml_escape(&line.author),
+        xml_escape(&line.summary),
+        xml_escape(&line.author),
+        format_age(age)
+    )
+}
+
+/// Formats the age of a commit in a human-readable form (e.g., `3 months ago`).
+fn format_age(secs: u64) -> String {
+    const DAY: u64 = 24 * 60 * 60;
+    let days = secs / DAY;
+    let (n, unit) = match days {
+        0 => return "today".to_owned(),
+        1..=29 => (days, "day"),
+        30..=364 => (days / 30, "month"),
+        _ => (days / 365, "year"),
+    };
+    format!("{n} {unit}{} ago", if n == 1 { "" } else { "s" })
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn porcelain() {
+        let out = "\
+0123456789abcdef0123456789abcdef01234567 1 1 2
+author Alice
+author-mail <alice@example.com>
+author-time 1700000000
+author-tz +0000
+summary Add <foo> & bar
+filename src/lib.rs
+\tpub fn foo() {
+0123456789abcdef0123456789abcdef01234567 2 2
+author Alice
+author-time 1700000000
+summary Add <foo> & bar
+filename src/lib.rs
+\t}
+0000000000000000000000000000000000000000 3 3 1
+author Not Committed Yet
+author-time 1710000000
+summary Version of src/lib.rs from src/lib.rs
+filename src/lib.rs
+\t
+";
+        let lines = parse_porcelain(out);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], BlameLine {
+            commit: "0123456789abcdef0123456789abcdef01234567".to_owned(),
+            author: "Alice".to_owned(),
+            author_time: 1_700_000_000,
+            summary: "Add <foo> & bar".to_owned(),
+        });
+        assert_eq!(
+            cell_html(&lines[1], 1_700_000_000 + 100 * 24 * 60 * 60),
+            "<td class='blame' title='0123456789 Alice: Add &lt;foo&gt; &amp; bar'><pre>Alice (3 \
+             months ago)</pre></td>"
+        );
+        assert_eq!(
+            cell_html(&lines[2], 1_710_000_000),
+            "<td class='blame recent'><pre>not committed</pre></td>"
+        );
+    }
+
+    #[test]
+    fn age() {
+        const DAY: u64 = 24 * 60 * 60;
+        assert_eq!(format_age(100), "today");
+        assert_eq!(format_age(DAY), "1 day ago");
+        assert_eq!(format_age(29 * DAY), "29 days ago");
+        assert_eq!(format_age(45 * DAY), "1 month ago");
+        assert_eq!(format_age(364 * DAY), "12 months ago");
+        assert_eq!(format_age(800 * DAY), "2 years ago");
+    }
+}
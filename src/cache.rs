@@ -0,0 +1,275 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Caches the results of `llvm-profdata merge` and `llvm-cov export`, so that
+// running report subcommand multiple times with different formats (e.g.,
+// `--lcov` and then `--html`) on the same coverage data doesn't redo the
+// expensive work.
+//
+// The cache key is the hash of the command line and the size and modification
+// time of the input files (profraw files for llvm-profdata; profdata and object
+// files for llvm-cov). The cache is stored in the target directory and removed
+// by `cargo llvm-cov clean`.
+//
+// This also caches the list of object files found in the target directory, so
+// that report subcommand doesn't need to rescan the target directory.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    ffi::{OsStr, OsString},
+    hash::{Hash as _, Hasher as _},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{Context as _, Result};
+use camino::Utf8PathBuf;
+use walkdir::WalkDir;
+
+use crate::{
+    context::Context, fs, function_filter::FunctionFilter, json::LlvmCovJsonExport,
+    process::ProcessBuilder, term,
+};
+
+const PROFDATA_KEY: &str = "profdata";
+
+/// Computes the hash of the key (e.g., command line) and the metadata of the given files.
+fn fingerprint(key: &str, files: impl IntoIterator<Item = impl AsRef<OsStr>>) -> String {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    for path in files {
+        let path = path.as_ref();
+        path.hash(&mut hasher);
+        // If metadata is not available, the command will fail anyway.
+        if let Ok(metadata) = fs::metadata(Path::new(path)) {
+            metadata.len().hash(&mut hasher);
+            if let Ok(modified) = metadata.modified() {
+                modified
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .hash(&mut hasher);
+            }
+        }
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+/// Runs `llvm-profdata merge` command unless the profdata file generated from the
+/// same inputs exists.
+pub(crate) fn merge_profdata(
+    cx: &Context,
+    cmd: &ProcessBuilder,
+    inputs: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    merge: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    let key = fingerprint(&cmd.to_string(), inputs);
+    let key_file = cx.ws.cache_dir.join(PROFDATA_KEY);
+    if cx.ws.profdata_file.exists() && fs::read_to_string(&key_file).is_ok_and(|prev| prev == key) {
+        if term::verbose() {
+            status!("Fresh", "{}", cx.ws.profdata_file);
+        }
+        return Ok(());
+    }
+    // Reports generated from the old profdata are no longer valid.
+    fs::remove_dir_all(&cx.ws.cache_dir)?;
+    merge()?;
+    fs::create_dir_all(&cx.ws.cache_dir)?;
+    fs::write(key_file, key)?;
+    Ok(())
+}
+
+/// Returns the path to the cached output of `llvm-cov export` command, and
+/// whether the cache is fresh.
+fn export_path(
+    cx: &Context,
+    cmd: &ProcessBuilder,
+    object_files: &[impl AsRef<OsStr>],
+) -> (Utf8PathBuf, bool) {
+    let inputs =
+        [cx.ws.profdata_file.as_os_str()].into_iter().chain(object_files.iter().map(AsRef::as_ref));
+    let path = cx.ws.cache_dir.join(format!("export-{}", fingerprint(&cmd.to_string(), inputs)));
+    // The cache is only valid if the profdata file is generated by merge_profdata.
+    let fresh = cx.ws.cache_dir.join(PROFDATA_KEY).exists() && path.exists();
+    if term::verbose() {
+        if fresh {
+            status!("Fresh", "{cmd}");
+        } else {
+            status!("Running", "{cmd}");
+        }
+    }
+    (path, fresh)
+}
+
+/// Runs `llvm-cov export` command and returns the path to the file that
+/// contains its output, or returns the path to the cached output of the same
+/// command.
+fn export_file(
+    cx: &Context,
+    cmd: &mut ProcessBuilder,
+    object_files: &[impl AsRef<OsStr>],
+) -> Result<Utf8PathBuf> {
+    let (path, fresh) = export_path(cx, cmd, object_files);
+    if fresh {
+        return Ok(path);
+    }
+    // The output of llvm-cov export can be several hundred MB for large
+    // workspaces, so write it to the file directly instead of into memory.
+    fs::create_dir_all(&cx.ws.cache_dir)?;
+    let tmp = path.with_extension("tmp");
+    cmd.run_with_stdout_path(tmp.as_std_path())?;
+    fs::rename(tmp, &path)?;
+    Ok(path)
+}
+
+/// Runs `llvm-cov export` command and returns its output, or returns the cached
+/// output of the same command.
+pub(crate) fn export(
+    cx: &Context,
+    cmd: &mut ProcessBuilder,
+    object_files: &[impl AsRef<OsStr>],
+) -> Result<String> {
+    let mut out = fs::read_to_string(export_file(cx, cmd, object_files)?)?;
+    while out.ends_with('\n') || out.ends_with('\r') {
+        out.pop();
+    }
+    Ok(out)
+}
+
+/// Runs `llvm-cov export -format=text` command and parses its output, or parses
+/// the cached output of the same command.
+///
+/// The output is parsed while llvm-cov writes it (and copied to the cache at
+/// the same time), so the raw JSON is never buffered in memory. Functions
+/// excluded by `function_filter` are removed while parsing, so they are never
+/// held in memory either.
+pub(crate) fn export_json(
+    cx: &Context,
+    cmd: &mut ProcessBuilder,
+    object_files: &[impl AsRef<OsStr>],
+    function_filter: Option<&FunctionFilter>,
+) -> Result<LlvmCovJsonExport> {
+    let parse = |reader: &mut dyn Read| -> Result<LlvmCovJsonExport> {
+        let reader = BufReader::new(reader);
+        match function_filter {
+            Some(function_filter) => function_filter.read_json(reader),
+            None => serde_json::from_reader(reader).map_err(Into::into),
+        }
+        .context("failed to parse json from llvm-cov")
+    };
+    let (path, fresh) = export_path(cx, cmd, object_files);
+    if fresh {
+        return parse(&mut fs::File::open(path)?);
+    }
+    fs::create_dir_all(&cx.ws.cache_dir)?;
+    let tmp = path.with_extension("tmp");
+    let mut cache = BufWriter::new(fs::File::create(&tmp)?);
+    let cov = cmd.run_with_stdout_reader(|stdout| {
+        let mut tee = Tee { reader: stdout, writer: &mut cache };
+        let cov = parse(&mut tee)?;
+        // Make sure the cache contains the whole output.
+        io::copy(&mut tee, &mut io::sink())?;
+        Ok(cov)
+    })?;
+    cache.into_inner().map_err(io::IntoInnerError::into_error)?;
+    fs::rename(tmp, &path)?;
+    Ok(cov)
+}
+
+/// A reader that writes everything read from `reader` to `writer`.
+struct Tee<'a, R: ?Sized, W> {
+    reader: &'a mut R,
+    writer: W,
+}
+
+impl<R: Read + ?Sized, W: Write> Read for Tee<'_, R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.writer.write_all(&buf[..n])?;
+        Ok(n)
+    }
+}
+
+/// Returns the list of object files found in the previous run if the files in
+/// the given directories have not been changed since then, otherwise calls
+/// `find` and saves its result.
+///
+/// The saved list is only reused by report subcommand, because other subcommands
+/// build the binaries right before.
+pub(crate) fn object_files(
+    cx: &Context,
+    dirs: &[Utf8PathBuf],
+    find: impl FnOnce() -> Result<Vec<OsString>>,
+) -> Result<Vec<OsString>> {
+    let packages: Vec<_> =
+        cx.workspace_members.included.iter().map(|id| &cx.ws.metadata.packages[id].name).collect();
+    let key = format!(
+        "{:?}",
+        (
+            &cx.args.target,
+            &cx.args.profile,
+            cx.args.release,
+            cx.args.doctests,
+            cx.args.cov.merge_toolchains,
+            cx.args.cov.include_build_script,
+            packages,
+        )
+    );
+    let key = fingerprint(&key, candidate_files(dirs));
+    let path = cx.ws.target_dir.join(format!("{}-object-files", cx.ws.name));
+    if cx.args.subcommand.is_report_based() {
+        if let Ok(list) = fs::read_to_string(&path) {
+            let mut lines = list.lines();
+            if lines.next() == Some(&*key) {
+                let files: Vec<OsString> = lines.map(OsString::from).collect();
+                if files.iter().all(|f| Path::new(f).exists()) {
+                    if term::verbose() {
+                        status!("Fresh", "list of object files ({path})");
+                    }
+                    return Ok(files);
+                }
+            }
+        }
+    }
+    let files = find()?;
+    let mut list = key;
+    for f in &files {
+        // Don't save the list if it cannot be represented as UTF-8 text.
+        let Some(f) = f.to_str() else { return Ok(files) };
+        list.push('\n');
+        list.push_str(f);
+    }
+    fs::write(path, list)?;
+    Ok(files)
+}
+
+/// Returns the files in the given directories and in their subdirectories that
+/// may contain object files (e.g., `build/<package>-<hash>/build-script-build`
+/// and `<doctests dir>/<test>/rust_out`).
+///
+/// Overwriting an existing file doesn't change the modification time of the
+/// directory that contains it on many filesystems, so the fingerprint of the
+/// list of object files needs to cover each file, not only the directories.
+fn candidate_files(dirs: &[Utf8PathBuf]) -> Vec<PathBuf> {
+    let mut files = vec![];
+    for dir in dirs {
+        files.extend(
+            WalkDir::new(dir)
+                .min_depth(1)
+                .max_depth(2)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|e| {
+                    // Directories in `dirs` are walked separately, and others
+                    // are ignored by `find`.
+                    e.depth() != 1
+                        || !e.file_type().is_dir()
+                        || (!dirs.iter().any(|d| d == e.path())
+                            && e.file_name() != "incremental"
+                            && e.file_name() != ".fingerprint")
+                })
+                .filter_map(Result::ok)
+                .map(walkdir::DirEntry::into_path),
+        );
+    }
+    files
+}
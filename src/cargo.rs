@@ -24,6 +24,14 @@ pub(crate) struct Workspace {
     pub(crate) output_dir: Utf8PathBuf,
     pub(crate) doctests_dir: Utf8PathBuf,
     pub(crate) profdata_file: Utf8PathBuf,
+    /// Directory to cache the results of llvm-profdata and llvm-cov.
+    pub(crate) cache_dir: Utf8PathBuf,
+    /// Directory to move profraw files that llvm-profdata cannot read.
+    pub(crate) quarantine_dir: Utf8PathBuf,
+    /// Profile data merged incrementally by `cargo llvm-cov collect`.
+    pub(crate) rolling_profdata_file: Utf8PathBuf,
+    /// Directory to save snapshots by `cargo llvm-cov snapshot save`.
+    pub(crate) snapshot_dir: Utf8PathBuf,
 
     rustc: ProcessBuilder,
     pub(crate) target_for_config: cargo_config2::TargetTriple,
@@ -39,8 +47,8 @@ impl Workspace {
     pub(crate) fn new(
         options: &ManifestOptions,
         target: Option<&str>,
-        doctests: bool,
         show_env: bool,
+        no_rustc_wrapper: bool,
     ) -> Result<Self> {
         // Metadata and config
         let config = Config::load()?;
@@ -52,12 +60,13 @@ impl Workspace {
         }
         let target_for_config = target_for_config.pop().unwrap();
         let target_for_cli = config.build_target_for_cli(target)?.pop();
-        let rustc = ProcessBuilder::from(config.rustc().clone());
+        let rustc = if no_rustc_wrapper {
+            cmd!(config.build.rustc.as_deref().unwrap_or_else(|| "rustc".as_ref()))
+        } else {
+            ProcessBuilder::from(config.rustc().clone())
+        };
         let rustc_version = rustc_version(&rustc)?;
 
-        if doctests && !rustc_version.nightly {
-            bail!("--doctests flag requires nightly toolchain; consider using `cargo +nightly llvm-cov`")
-        }
         let stable_coverage =
             rustc.clone().args(["-C", "help"]).read()?.contains("instrument-coverage");
         if !stable_coverage && !rustc_version.nightly {
@@ -66,13 +75,6 @@ impl Workspace {
                  or using nightly toolchain (`cargo +nightly llvm-cov`)"
             );
         }
-        let mut need_doctest_in_workspace = false;
-        if doctests {
-            need_doctest_in_workspace = cmd!(config.cargo(), "-Z", "help")
-                .read()
-                .map_or(false, |s| s.contains("doctest-in-workspace"));
-        }
-
         let target_dir =
             if let Some(path) = env::var("CARGO_LLVM_COV_TARGET_DIR")?.map(Utf8PathBuf::from) {
                 let mut base: Utf8PathBuf = env::current_dir()?.try_into()?;
@@ -90,6 +92,10 @@ impl Workspace {
 
         let name = metadata.workspace_root.file_name().unwrap_or("default").to_owned();
         let profdata_file = target_dir.join(format!("{name}.profdata"));
+        let cache_dir = target_dir.join(format!("{name}-cache"));
+        let quarantine_dir = target_dir.join(format!("{name}-quarantine"));
+        let rolling_profdata_file = target_dir.join(format!("{name}-rolling.profdata"));
+        let snapshot_dir = target_dir.join(format!("{name}-snapshots"));
 
         Ok(Self {
             name,
@@ -100,15 +106,30 @@ impl Workspace {
             output_dir,
             doctests_dir,
             profdata_file,
+            cache_dir,
+            quarantine_dir,
+            rolling_profdata_file,
+            snapshot_dir,
             rustc,
             target_for_config,
             target_for_cli,
             rustc_version,
             stable_coverage,
-            need_doctest_in_workspace,
+            need_doctest_in_workspace: false,
         })
     }
 
+    /// Checks whether doctests can be run, and detects whether `-Z doctest-in-workspace` is needed.
+    pub(crate) fn enable_doctests(&mut self) -> Result<()> {
+        if !self.rustc_version.nightly {
+            bail!("--doctests flag requires nightly toolchain; consider using `cargo +nightly llvm-cov`")
+        }
+        self.need_doctest_in_workspace = cmd!(self.config.cargo(), "-Z", "help")
+            .read()
+            .is_ok_and(|s| s.contains("doctest-in-workspace"));
+        Ok(())
+    }
+
     pub(crate) fn cargo(&self, verbose: u8) -> ProcessBuilder {
         let mut cmd = cmd!(self.config.cargo());
         // cargo displays env vars only with -vv.
@@ -147,6 +168,8 @@ impl Workspace {
 pub(crate) struct RustcVersion {
     pub(crate) minor: u32,
     pub(crate) nightly: bool,
+    /// The version of LLVM used by rustc.
+    pub(crate) llvm: Option<String>,
 }
 
 fn rustc_version(rustc: &ProcessBuilder) -> Result<RustcVersion> {
@@ -172,7 +195,11 @@ fn rustc_version(rustc: &ProcessBuilder) -> Result<RustcVersion> {
     let nightly = channel == "nightly"
         || channel == "dev"
         || env::var("RUSTC_BOOTSTRAP")?.as_deref() == Some("1");
-    Ok(RustcVersion { minor, nightly })
+    let llvm = verbose_version
+        .lines()
+        .find_map(|line| line.strip_prefix("LLVM version: "))
+        .map(str::to_owned);
+    Ok(RustcVersion { minor, nightly, llvm })
 }
 
 fn package_root(cargo: &OsStr, manifest_path: Option<&Utf8Path>) -> Result<Utf8PathBuf> {
@@ -207,6 +234,13 @@ pub(crate) fn test_or_run_args(cx: &Context, cmd: &mut ProcessBuilder) {
         if !has_target_selection_options {
             cmd.arg("--tests");
         }
+        // Bench targets are run in test mode by `cargo test`; criterion then
+        // runs each benchmark routine only once.
+        if cx.args.criterion
+            && !(cx.args.benches || cx.args.all_targets || !cx.args.bench.is_empty())
+        {
+            cmd.arg("--benches");
+        }
     }
 
     for exclude in &cx.args.exclude_from_test {
@@ -214,18 +248,45 @@ pub(crate) fn test_or_run_args(cx: &Context, cmd: &mut ProcessBuilder) {
         cmd.arg(exclude);
     }
 
+    common_args(cx, cmd, &cx.args.cargo_args, &cx.args.rest);
+}
+
+/// Same as [`test_or_run_args`], but passes the given arguments to the binary,
+/// and selects only the given binary from the ones specified by --bin if
+/// specified, as `cargo run` can run at most one executable.
+pub(crate) fn run_args(cx: &Context, cmd: &mut ProcessBuilder, bin: Option<&str>, rest: &[String]) {
+    let Some(bin) = bin else {
+        common_args(cx, cmd, &cx.args.cargo_args, rest);
+        return;
+    };
+    let mut cargo_args = vec![];
+    let mut iter = cx.args.cargo_args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--bin" {
+            iter.next();
+        } else if !arg.starts_with("--bin=") {
+            cargo_args.push(arg.clone());
+        }
+    }
+    cargo_args.push("--bin".to_owned());
+    cargo_args.push(bin.to_owned());
+
+    common_args(cx, cmd, &cargo_args, rest);
+}
+
+fn common_args(cx: &Context, cmd: &mut ProcessBuilder, cargo_args: &[String], rest: &[String]) {
     cmd.arg("--manifest-path");
     cmd.arg(&cx.ws.current_manifest);
 
     add_target_dir(&cx.args, cmd, &cx.ws.target_dir);
 
-    for cargo_arg in &cx.args.cargo_args {
+    for cargo_arg in cargo_args {
         cmd.arg(cargo_arg);
     }
 
-    if !cx.args.rest.is_empty() {
+    if !rest.is_empty() {
         cmd.arg("--");
-        cmd.args(&cx.args.rest);
+        cmd.args(rest);
     }
 }
 
@@ -0,0 +1,204 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Handling of cargo profiles used to build the instrumented artifacts.
+//
+// If `[profile.coverage]` is defined in the workspace's Cargo.toml, it is used
+// by default, so teams can tune a profile for coverage (e.g., `debug = 0` to
+// speed up builds, as debug info is not needed for the coverage mapping)
+// without passing --profile in every invocation.
+//
+// Refs:
+// - https://doc.rust-lang.org/nightly/cargo/reference/profiles.html
+
+use anyhow::{Context as _, Result};
+use toml::{Table, Value};
+
+use crate::{
+    cargo::Workspace,
+    cli::{Args, Subcommand},
+    fs, term,
+};
+
+/// The name of the profile used by default if it is defined in the workspace's Cargo.toml.
+pub(crate) const COVERAGE_PROFILE: &str = "coverage";
+
+/// The `[profile]` table in the workspace's Cargo.toml.
+struct Profiles(Table);
+
+impl Profiles {
+    fn load(ws: &Workspace) -> Result<Self> {
+        // Profiles in Cargo.toml of workspace members (other than the root) are ignored by cargo.
+        let path = ws.metadata.workspace_root.join("Cargo.toml");
+        let mut manifest: Table = toml::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("failed to parse {path}"))?;
+        Ok(Self(match manifest.remove("profile") {
+            Some(Value::Table(profiles)) => profiles,
+            _ => Table::new(),
+        }))
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Returns the value of the given key of the profile, following `inherits`,
+    /// or the built-in profile (`dev` or `release`) the profile inherits if the
+    /// key is not set.
+    fn get<'a>(&'a self, mut name: &'a str, key: &str) -> Option<Result<&'a Value, &'a str>> {
+        // Limit the depth to avoid infinite loop on invalid configs; cargo reports them.
+        for _ in 0..32 {
+            let profile = self.0.get(name).and_then(Value::as_table);
+            if let Some(value) = profile.and_then(|p| p.get(key)) {
+                return Some(Ok(value));
+            }
+            name = match (name, profile.and_then(|p| p.get("inherits"))) {
+                (_, Some(Value::String(inherits))) => inherits,
+                ("dev" | "release", _) => return Some(Err(name)),
+                ("test", _) => "dev",
+                ("bench", _) => "release",
+                _ => return None,
+            };
+        }
+        None
+    }
+
+    /// Returns the value of `opt-level` of the profile, following `inherits`.
+    fn opt_level(&self, name: &str) -> Option<String> {
+        match self.get(name, "opt-level")? {
+            Ok(Value::Integer(level)) => Some(level.to_string()),
+            Ok(Value::String(level)) => Some(level.clone()),
+            Ok(_) => None,
+            Err("dev") => Some("0".to_owned()),
+            Err(_) => Some("3".to_owned()),
+        }
+    }
+
+    /// Returns the value of `split-debuginfo` of the profile if it is set
+    /// explicitly, following `inherits`.
+    fn split_debuginfo(&self, name: &str) -> Option<String> {
+        Some(self.get(name, "split-debuginfo")?.ok()?.as_str()?.to_owned())
+    }
+}
+
+/// Uses the coverage-dedicated profile if it is defined and no profile is
+/// specified, and warns if the profile may make the coverage inaccurate.
+pub(crate) fn apply(ws: &Workspace, args: &mut Args) -> Result<()> {
+    // With --external-runner, the command builds the artifacts with the profile it selects.
+    let building = matches!(
+        args.subcommand,
+        Subcommand::None
+            | Subcommand::Test
+            | Subcommand::Run
+            | Subcommand::Nextest
+            | Subcommand::NextestArchive
+            | Subcommand::Install
+    ) && args.external_runner.is_none();
+    if !building && !args.subcommand.is_report_based() {
+        return Ok(());
+    }
+    let profiles = Profiles::load(ws)?;
+    if args.profile.is_none() && !args.release && profiles.contains(COVERAGE_PROFILE) {
+        if building {
+            let flag = if args.subcommand.is_nextest_based() { "cargo-profile" } else { "profile" };
+            args.cargo_args.push(format!("--{flag}={COVERAGE_PROFILE}"));
+            args.profile = Some(COVERAGE_PROFILE.to_owned());
+        } else if args.nextest_archive_file.is_none() {
+            // Only use it if the artifacts were built with it (e.g., not with show-env
+            // and cargo build).
+            let mut dir = ws.target_dir.clone();
+            if let Some(target) = &args.target {
+                dir.push(target);
+            }
+            if dir.join(COVERAGE_PROFILE).is_dir() {
+                args.profile = Some(COVERAGE_PROFILE.to_owned());
+            }
+        }
+        if args.profile.is_some() && term::verbose() {
+            info!("using `{COVERAGE_PROFILE}` profile defined in Cargo.toml");
+        }
+    }
+    if !building {
+        return Ok(());
+    }
+    // https://doc.rust-lang.org/nightly/cargo/reference/profiles.html#profile-selection
+    let name = match args.profile.as_deref() {
+        Some(name) => name,
+        None if args.release => "release",
+        None if args.subcommand == Subcommand::Run => "dev",
+        None => "test",
+    };
+    if let Some(level) = profiles.opt_level(name) {
+        if matches!(&*level, "2" | "3" | "s" | "z") {
+            warn!(
+                "profile `{name}` uses opt-level={level}; optimizations may make coverage \
+                 inaccurate, as inlined or eliminated code can be reported as uncovered; \
+                 consider using opt-level=0 or 1 in a `{COVERAGE_PROFILE}` profile"
+            );
+        }
+    }
+    // The coverage mapping is always kept in the binaries, so the debug info
+    // split into separate files (dSYM bundles on macOS, dwo/dwp files on other
+    // platforms) is not needed for coverage. These files have the same names
+    // as the binaries, and running dsymutil or packaging dwp files for every
+    // test binary makes the build slower, so build instrumented artifacts
+    // without splitting debug info. (split-debuginfo=off is not supported on
+    // windows-msvc, where debug info is always in pdb files.)
+    if let Some(split) = profiles.split_debuginfo(name) {
+        if split != "off" && !ws.target_for_config.triple().contains("-windows-msvc") {
+            if term::verbose() {
+                info!(
+                    "profile `{name}` uses split-debuginfo={split}; building instrumented \
+                     artifacts with split-debuginfo=off"
+                );
+            }
+            let config = format!("--config=profile.{name}.split-debuginfo=\"off\"");
+            args.cargo_args.push(config);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profiles;
+
+    fn opt_level(profiles: &str, name: &str) -> Option<String> {
+        Profiles(toml::from_str(profiles).unwrap()).opt_level(name)
+    }
+
+    #[test]
+    fn opt_level_inherits() {
+        assert_eq!(opt_level("", "dev").as_deref(), Some("0"));
+        assert_eq!(opt_level("", "test").as_deref(), Some("0"));
+        assert_eq!(opt_level("", "bench").as_deref(), Some("3"));
+        assert_eq!(opt_level("", "coverage"), None);
+        let profiles = r#"
+            dev = { opt-level = 1 }
+            coverage = { inherits = "test" }
+            fast = { inherits = "release", opt-level = "s" }
+            fast2 = { inherits = "fast" }
+            a = { inherits = "b" }
+            b = { inherits = "a" }
+        "#;
+        assert_eq!(opt_level(profiles, "coverage").as_deref(), Some("1"));
+        assert_eq!(opt_level(profiles, "release").as_deref(), Some("3"));
+        assert_eq!(opt_level(profiles, "fast2").as_deref(), Some("s"));
+        assert_eq!(opt_level(profiles, "a"), None);
+    }
+
+    #[test]
+    fn split_debuginfo_inherits() {
+        let split_debuginfo = |profiles: &str, name: &str| {
+            Profiles(toml::from_str(profiles).unwrap()).split_debuginfo(name)
+        };
+        assert_eq!(split_debuginfo("", "test"), None);
+        let profiles = r#"
+            dev = { split-debuginfo = "packed" }
+            coverage = { inherits = "test" }
+            fast = { inherits = "release", split-debuginfo = "unpacked" }
+        "#;
+        assert_eq!(split_debuginfo(profiles, "coverage").as_deref(), Some("packed"));
+        assert_eq!(split_debuginfo(profiles, "release"), None);
+        assert_eq!(split_debuginfo(profiles, "fast").as_deref(), Some("unpacked"));
+    }
+}
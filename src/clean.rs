@@ -4,7 +4,10 @@
 // - https://doc.rust-lang.org/nightly/cargo/commands/cargo-clean.html
 // - https://github.com/rust-lang/cargo/blob/0.62.0/src/cargo/ops/cargo_clean.rs
 
-use std::path::Path;
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
 
 use anyhow::Result;
 use camino::Utf8Path;
@@ -13,17 +16,30 @@ use walkdir::WalkDir;
 use crate::{
     cargo::{self, Workspace},
     cli::{self, Args, ManifestOptions},
+    config::LlvmCovConfig,
     context::Context,
-    fs,
+    fs, lock,
     metadata::PackageId,
     regex_vec::{RegexVec, RegexVecBuilder},
-    term,
+    term, timings,
 };
 
 pub(crate) fn run(args: &mut Args) -> Result<()> {
     let ws = Workspace::new(&args.manifest, None, false, false)?;
     cli::merge_config_to_args(&ws, &mut None, &mut args.verbose, &mut args.color);
     term::set_coloring(&mut args.color);
+    let _lock =
+        if args.no_lock { None } else { Some((lock::lock(&ws)?, lock::lock_profile_data(&ws)?)) };
+
+    if args.expired {
+        let mut retention = LlvmCovConfig::load(&ws.metadata)?
+            .map(|(_, config)| config.retention())
+            .unwrap_or_default();
+        if retention.is_empty() {
+            retention.max_age = Some(DEFAULT_MAX_AGE);
+        }
+        return remove_expired(&ws, retention, args.verbose != 0);
+    }
 
     if !args.workspace {
         for dir in &[&ws.target_dir, &ws.output_dir] {
@@ -46,11 +62,25 @@ pub(crate) fn run(args: &mut Args) -> Result<()> {
 // - doctest bins
 // - old reports
 pub(crate) fn clean_partial(cx: &Context) -> Result<()> {
+    let _phase = timings::phase("clean");
+    if cx.args.append
+        && crate::profraw_files(cx)?.is_empty()
+        && !cx.ws.rolling_profdata_file.exists()
+    {
+        warn!(
+            "--append is passed, but no profile data of the previous runs was found in {}",
+            cx.ws.target_dir
+        );
+    }
     if cx.args.no_clean {
         return Ok(());
     }
 
-    clean_ws_inner(&cx.ws, &cx.workspace_members.included, cx.args.verbose > 1)?;
+    {
+        // Wait for `cargo llvm-cov collect` to finish merging.
+        let _lock = if cx.args.no_lock { None } else { Some(lock::lock_profile_data(&cx.ws)?) };
+        clean_ws_inner(&cx.ws, &cx.workspace_members.included, cx.args.verbose > 1)?;
+    }
 
     let package_args: Vec<_> = cx
         .workspace_members
@@ -119,11 +149,80 @@ fn clean_ws_inner(ws: &Workspace, pkg_ids: &[PackageId], verbose: bool) -> Resul
 
     rm_rf(&ws.doctests_dir, verbose)?;
     rm_rf(&ws.profdata_file, verbose)?;
+    rm_rf(&ws.rolling_profdata_file, verbose)?;
+    rm_rf(&ws.cache_dir, verbose)?;
+    rm_rf(&ws.quarantine_dir, verbose)?;
+    rm_rf(ws.target_dir.join(format!("{}-object-files", ws.name)), verbose)?;
+    rm_rf(ws.target_dir.join(format!("{}-coverage-mapping", ws.name)), verbose)?;
 
     clean_trybuild_artifacts(ws, pkg_ids, verbose)?;
     Ok(())
 }
 
+/// Retention policy of profile data in the target directory.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Retention {
+    /// Profile data older than this is removed.
+    pub(crate) max_age: Option<Duration>,
+    /// If the total size of profile data exceeds this, the oldest ones are removed.
+    pub(crate) max_size: Option<u64>,
+}
+
+impl Retention {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.max_age.is_none() && self.max_size.is_none()
+    }
+}
+
+/// Default retention period used by `clean --expired` if not configured.
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Removes profraw and profdata files in the target directory that exceed the
+/// retention policy.
+pub(crate) fn remove_expired(ws: &Workspace, retention: Retention, verbose: bool) -> Result<()> {
+    if retention.is_empty() {
+        return Ok(());
+    }
+    let mut files = vec![];
+    for ext in ["profraw", "profdata"] {
+        for path in glob::glob(
+            Utf8Path::new(&glob::Pattern::escape(ws.target_dir.as_str()))
+                .join(format!("*.{ext}"))
+                .as_str(),
+        )?
+        .filter_map(Result::ok)
+        {
+            let Ok(metadata) = fs::metadata(&path) else { continue };
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((modified, metadata.len(), path));
+        }
+    }
+    // Newest first.
+    files.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.2.cmp(&b.2)));
+
+    let now = SystemTime::now();
+    let mut total_size = 0_u64;
+    let (mut removed, mut removed_size) = (0_usize, 0_u64);
+    for (modified, size, path) in files {
+        let expired = retention
+            .max_age
+            .is_some_and(|max_age| now.duration_since(modified).unwrap_or_default() > max_age);
+        let exceeded =
+            retention.max_size.is_some_and(|max_size| total_size.saturating_add(size) > max_size);
+        if expired || exceeded {
+            rm_rf(&path, verbose)?;
+            removed += 1;
+            removed_size += size;
+        } else {
+            total_size += size;
+        }
+    }
+    if removed != 0 {
+        info!("removed {removed} expired profile data files ({removed_size} bytes)");
+    }
+    Ok(())
+}
+
 fn pkg_hash_re(ws: &Workspace, pkg_ids: &[PackageId]) -> RegexVec {
     let mut re = RegexVecBuilder::new("^(lib)?(", ")(-[0-9a-f]{7,})?$");
     for id in pkg_ids {
@@ -150,7 +249,7 @@ fn clean_trybuild_artifacts(ws: &Workspace, pkg_ids: &[PackageId], verbose: bool
 fn rm_rf(path: impl AsRef<Path>, verbose: bool) -> Result<()> {
     let path = path.as_ref();
     let m = fs::symlink_metadata(path);
-    if m.as_ref().map(fs::Metadata::is_dir).unwrap_or(false) {
+    if m.as_ref().is_ok_and(fs::Metadata::is_dir) {
         if verbose {
             status!("Removing", "{}", path.display());
         }
@@ -11,7 +11,13 @@ use lexopt::{
 
 use crate::{
     env,
+    gate::Gate,
+    grcov::OutputType,
+    list_uncovered::ListUncoveredFormat,
+    path_map::CanonicalizePaths,
+    percent::{self, Counts, Rounding},
     process::ProcessBuilder,
+    snapshot,
     term::{self, Coloring},
 };
 
@@ -30,6 +36,28 @@ pub(crate) struct Args {
     /// This flag is unstable.
     /// See <https://github.com/taiki-e/cargo-llvm-cov/issues/2> for more.
     pub(crate) doctests: bool,
+    /// Enable branch coverage (unstable)
+    ///
+    /// This flag requires nightly toolchain.
+    pub(crate) branch: bool,
+    /// Build with `-C strip=none` to keep the coverage mapping even if the profile strips
+    /// binaries
+    pub(crate) keep_coverage_mapping: bool,
+    /// Custom cfgs to enable during coverage builds (`cfg` key in llvm-cov.toml)
+    pub(crate) cfgs: Vec<String>,
+    /// Minimum line coverage of the source files matching each glob pattern
+    /// (`path-thresholds` table in llvm-cov.toml)
+    pub(crate) path_thresholds: Vec<(String, f64)>,
+    /// Minimum line coverage of the source files owned by each code owner
+    /// (`owner-thresholds` table in llvm-cov.toml)
+    pub(crate) owner_thresholds: Vec<(String, f64)>,
+    /// Use the named profile in the configuration file
+    ///
+    /// Keys in `[profile.<NAME>]` table of llvm-cov.toml (or `workspace.metadata.llvm-cov`
+    /// table in Cargo.toml) override the top-level keys.
+    pub(crate) cov_profile: Option<String>,
+    /// Remove only profile data that exceeds the retention policy (clean subcommand only)
+    pub(crate) expired: bool,
 
     // =========================================================================
     // `cargo test` options
@@ -42,6 +70,50 @@ pub(crate) struct Args {
     ///
     /// If tests failed but report generation succeeded, exit with a status of 0.
     pub(crate) ignore_run_fail: bool,
+    /// Run all tests regardless of failure and generate report, then exit with a status of 1
+    /// if tests failed
+    ///
+    /// Like --ignore-run-fail, but the failure is not ignored. In both cases, a report
+    /// generated from a run in which tests failed is annotated as such (a banner in the
+    /// html report, and `tests_failed` in the json report and --summary-json). Reports
+    /// generated later by `cargo llvm-cov report` are not annotated.
+    pub(crate) report_on_fail: bool,
+    /// Run tests of each workspace member separately and keep going when tests of a member fail
+    ///
+    /// The report is generated for all members, and then cargo-llvm-cov exits
+    /// with an error listing the members whose tests failed.
+    pub(crate) keep_going: bool,
+    /// Fail if no tests were executed (e.g., because the test name filter didn't match any tests)
+    pub(crate) fail_if_no_tests: bool,
+    /// Also test bench targets, running each benchmark that uses criterion only once
+    pub(crate) criterion: bool,
+    /// Run the given command instead of `cargo test`, with the environment variables needed to
+    /// build and run instrumented binaries
+    ///
+    /// This is useful for Rust libraries tested by the test runners of other languages (e.g.,
+    /// pyo3 extension modules built by `maturin develop` and tested by pytest). Cargo invoked by
+    /// the command builds instrumented artifacts into the target directory of cargo-llvm-cov.
+    ///
+    /// The command is split on whitespace and run in the workspace root, with the arguments
+    /// after `--`.
+    pub(crate) external_runner: Option<String>,
+    /// Run tests the specified number of times and report lines whose coverage differs across runs
+    pub(crate) runs: Option<usize>,
+    /// Write the results of tests and the coverage collected by running each test binary to the
+    /// given path as JUnit XML
+    pub(crate) junit: Option<Utf8PathBuf>,
+    /// Discard coverage collected by failed attempts of tests retried by nextest
+    ///
+    /// Only the final attempt of each test (the passed one, or the last one if
+    /// all attempts failed) is counted, so flaky tests don't change coverage
+    /// depending on how many times they are retried.
+    pub(crate) discard_failed_attempts: bool,
+    /// Write a ranking of tests by the number of lines covered by no other test
+    /// as JSON to the given path
+    ///
+    /// Tests that cover no such lines are redundant as far as line coverage is
+    /// concerned. This is only available for `cargo llvm-cov nextest`.
+    pub(crate) emit_test_contribution: Option<Utf8PathBuf>,
     // /// Display one character per test instead of one line
     // pub(crate) quiet: bool,
     /// Test only this package's library unit tests
@@ -79,6 +151,15 @@ pub(crate) struct Args {
     pub(crate) exclude_from_test: Vec<String>,
     /// Exclude packages from the report (but not from the test)
     pub(crate) exclude_from_report: Vec<String>,
+    /// Build only the specified workspace members with instrumentation
+    ///
+    /// Libraries of other workspace members are built without instrumentation.
+    /// Test binaries and executables are still built with instrumentation so that
+    /// they record coverage of the instrumented packages. Unless --report-include
+    /// is passed, only the instrumented packages are included in the report.
+    pub(crate) instrument: Vec<String>,
+    /// Include only the specified workspace members in the report
+    pub(crate) report_include: Vec<String>,
 
     // /// Number of parallel jobs, defaults to # of CPUs
     // // Max value is u32::MAX: https://github.com/rust-lang/cargo/blob/0.62.0/src/cargo/util/command_prelude.rs#L356
@@ -125,6 +206,18 @@ pub(crate) struct Args {
     ///
     /// Note that this does not fully compatible with doctest.
     pub(crate) remap_path_prefix: bool,
+    /// Use verbatim paths (`\\?\`) for profile data on Windows to support paths longer
+    /// than 260 characters, and strip verbatim prefixes from source paths in the report
+    ///
+    /// This also makes --remap-path-prefix and the default value of --ignore-filename-regex
+    /// match verbatim paths recorded in the coverage data.
+    pub(crate) windows_long_paths: bool,
+    /// Build without `RUSTC_WRAPPER` and `RUSTC_WORKSPACE_WRAPPER` (e.g., sccache)
+    ///
+    /// By default, rustc wrappers set via environment variables or cargo config are also used
+    /// for coverage builds. Caching wrappers key artifacts by the instrumentation flags, so
+    /// coverage builds don't reuse artifacts of normal builds.
+    pub(crate) no_rustc_wrapper: bool,
     /// Include coverage of C/C++ code linked to Rust library/binary
     ///
     /// Note that `CC`/`CXX`/`LLVM_COV`/`LLVM_PROFDATA` environment variables
@@ -135,16 +228,72 @@ pub(crate) struct Args {
     ///
     /// Note that this can cause false positives/false negatives due to old build artifacts.
     pub(crate) no_clean: bool,
+    /// Keep the profile data of the previous runs and merge the coverage of this run into it.
+    ///
+    /// This implies --no-clean and --merge-toolchains.
+    pub(crate) append: bool,
+    /// Run without locking the target directory.
+    ///
+    /// By default, cargo-llvm-cov waits for other cargo-llvm-cov processes using
+    /// the same target directory to finish, to avoid them removing or overwriting
+    /// each other's profile data.
+    pub(crate) no_lock: bool,
+    /// Print how long each phase (build and run, merge, report, etc.) took after the operation
+    pub(crate) timings: bool,
+    /// Write how long each phase took to the specified file in JSON format
+    pub(crate) timings_json: Option<Utf8PathBuf>,
+    /// Use the test binaries in the specified nextest archive to generate the report.
+    ///
+    /// The archive is extracted to the target directory if it has not been
+    /// extracted by `cargo llvm-cov nextest --archive-file` yet.
+    pub(crate) nextest_archive_file: Option<String>,
+    /// Run the binary once per line of the specified file, with the arguments on the line
+    ///
+    /// The arguments are appended to the arguments after `--`. (run subcommand only)
+    pub(crate) args_file: Option<Utf8PathBuf>,
+    /// Terminate each run of the binary that doesn't exit within the specified number of
+    /// seconds (run subcommand only)
+    pub(crate) timeout: Option<u64>,
+    /// Kill the binary if it doesn't exit within the specified number of seconds after it is
+    /// terminated by --timeout (default to 10)
+    pub(crate) kill_after: Option<u64>,
+    /// Generate the report every specified number of seconds while the binary runs (run
+    /// subcommand only)
+    pub(crate) dump_interval: Option<u64>,
+    /// Keep watching the target directory and merging new profraw files (collect subcommand
+    /// only)
+    pub(crate) daemon: bool,
+    /// Name of the snapshot to save (snapshot subcommand only)
+    pub(crate) snapshot_name: Option<String>,
+    /// The source file to print with the coverage of each line (show subcommand only)
+    pub(crate) show_file: Option<Utf8PathBuf>,
+    /// The two exported reports to compare (diff subcommand only)
+    pub(crate) diff_exports: Vec<Utf8PathBuf>,
+    /// The reports (or directories containing them) to show the trend of (trend subcommand only)
+    pub(crate) trend_files: Vec<Utf8PathBuf>,
+    /// Render the trend as an SVG chart to the specified file (trend subcommand only)
+    pub(crate) plot: Option<Utf8PathBuf>,
+    /// The report in the lcov format to render (html subcommand only)
+    pub(crate) from_lcov: Option<Utf8PathBuf>,
 
     pub(crate) manifest: ManifestOptions,
 
     pub(crate) cargo_args: Vec<String>,
+    /// Whether a test name filter is passed to cargo (e.g., `cargo llvm-cov test <TESTNAME>`)
+    pub(crate) test_filter: bool,
     /// Arguments for the test binary
     pub(crate) rest: Vec<String>,
+    /// Arguments passed to cargo-llvm-cov (excluding `cargo llvm-cov`)
+    pub(crate) raw_args: Vec<String>,
 }
 
 impl Args {
     pub(crate) fn parse() -> Result<Self> {
+        Self::parse_from(env::args_os())
+    }
+
+    /// Parses the given arguments, where the first two are `cargo` and `llvm-cov`.
+    pub(crate) fn parse_from(args: impl IntoIterator<Item = impl Into<OsString>>) -> Result<Self> {
         const SUBCMD: &str = "llvm-cov";
 
         // rustc/cargo args must be valid Unicode
@@ -159,7 +308,7 @@ impl Args {
             })
         }
 
-        let mut raw_args = handle_args(env::args_os());
+        let mut raw_args = handle_args(args);
         raw_args.next(); // cargo
         match raw_args.next().transpose()? {
             Some(arg) if arg == SUBCMD => {}
@@ -175,8 +324,14 @@ impl Args {
             args.push(arg);
         }
         let rest = raw_args.collect::<Result<Vec<_>>>()?;
+        let mut all_args = args.clone();
+        if !rest.is_empty() {
+            all_args.push("--".to_owned());
+            all_args.extend(rest.iter().cloned());
+        }
 
         let mut cargo_args = vec![];
+        let mut test_filter = false;
         let mut subcommand = Subcommand::None;
         let mut after_subcommand = false;
 
@@ -187,9 +342,22 @@ impl Args {
         let mut color = None;
 
         let mut doctests = false;
+        let mut branch = false;
+        let mut keep_coverage_mapping = false;
+        let mut cov_profile = None;
+        let mut expired = false;
         let mut no_run = false;
         let mut no_fail_fast = false;
         let mut ignore_run_fail = false;
+        let mut report_on_fail = false;
+        let mut keep_going = false;
+        let mut criterion = false;
+        let mut external_runner: Option<String> = None;
+        let mut runs = None;
+        let mut junit: Option<Utf8PathBuf> = None;
+        let mut fail_if_no_tests = false;
+        let mut discard_failed_attempts = false;
+        let mut emit_test_contribution: Option<Utf8PathBuf> = None;
         let mut lib = false;
         let mut bin = vec![];
         let mut bins = false;
@@ -207,19 +375,35 @@ impl Args {
         let mut exclude = vec![];
         let mut exclude_from_test = vec![];
         let mut exclude_from_report = vec![];
+        let mut instrument = vec![];
+        let mut report_include = vec![];
 
         // llvm-cov options
         let mut json = false;
         let mut lcov = false;
         let mut cobertura = false;
         let mut codecov = false;
+        let mut summary_json = false;
+        let mut json_lines = false;
+        let mut msgpack = false;
+        let mut exec_formatter: Option<String> = None;
         let mut text = false;
         let mut html = false;
         let mut open = false;
+        let mut open_entry = None;
+        let mut browser = None;
         let mut summary_only = false;
         let mut output_path = None;
+        let mut output_types: Option<String> = None;
+        let mut all_reports = false;
         let mut output_dir = None;
         let mut failure_mode = None;
+        let mut quarantine = false;
+        let mut find_llvm_tools = false;
+        let mut merge_toolchains = false;
+        let mut strict = false;
+        let mut llvm_cov_path = None;
+        let mut llvm_profdata_path = None;
         let mut ignore_filename_regex = None;
         let mut disable_default_ignore_filename_regex = false;
         let mut show_instantiations = false;
@@ -232,8 +416,48 @@ impl Args {
         let mut fail_uncovered_lines = None;
         let mut fail_uncovered_regions = None;
         let mut fail_uncovered_functions = None;
+        let mut fail_under_diff_lines = None;
+        let mut require_full: Vec<String> = vec![];
+        let mut gate: Option<Gate> = None;
+        let mut gate_baseline: Option<Utf8PathBuf> = None;
+        let mut precision = None;
+        let mut rounding = None;
+        let mut webhook_url = None;
+        let mut webhook_baseline: Option<Utf8PathBuf> = None;
+        let mut webhook_report_url = None;
+        let mut diff_base = None;
         let mut show_missing_lines = false;
+        let mut show_excluded = false;
+        let mut summary_line = false;
+        let mut summary_format: Option<SummaryFormat> = None;
+        let mut uncovered_spans_json = None;
+        let mut list_uncovered: Option<ListUncoveredFormat> = None;
+        let mut show_counts: Option<Counts> = None;
+        let mut coverage_off_json = None;
         let mut include_build_script = false;
+        let mut include_out_dir = false;
+        let mut exclude_derives = false;
+        let mut exclude_test_functions = false;
+        let mut dep_coverage = None;
+        let mut include_deps_matching = None;
+        let mut objects: Vec<Utf8PathBuf> = vec![];
+        let mut max_file_lines = None;
+        let mut max_file_size = None;
+        let mut path_map: Vec<String> = vec![];
+        let mut canonicalize_paths: Option<CanonicalizePaths> = None;
+        let mut lcov_relative_to = None;
+        let mut no_timestamps = false;
+        let mut blame = false;
+        let mut reproducible_html = false;
+        let mut assets_dir: Option<Utf8PathBuf> = None;
+        let mut external_reports: Vec<String> = vec![];
+        let mut demangle = false;
+        let mut demangle_keep_hashes = false;
+        let mut demangle_cpp = false;
+        let mut report_per_binary = false;
+        let mut summary_by_target_kind = false;
+        let mut summary_by_owner = false;
+        let mut summary_by_instantiation = false;
 
         // build options
         let mut release = false;
@@ -241,12 +465,35 @@ impl Args {
         let mut target = None;
         let mut coverage_target_only = false;
         let mut remap_path_prefix = false;
+        let mut windows_long_paths = false;
+        let mut no_rustc_wrapper = false;
         let mut include_ffi = false;
         let mut verbose: usize = 0;
         let mut no_clean = false;
+        let mut append = false;
+        let mut no_lock = false;
+        let mut timings = false;
+        let mut timings_json = None;
+        let mut nextest_archive_file = None;
+        let mut args_file = None;
+        let mut timeout = None;
+        let mut kill_after = None;
+        let mut dump_interval = None;
+        let mut daemon = false;
+        let mut snapshot_save = false;
+        let mut snapshot_name: Option<String> = None;
+        let mut since_snapshot: Option<String> = None;
+        let mut show_file: Option<Utf8PathBuf> = None;
+        let mut diff_exports: Vec<Utf8PathBuf> = vec![];
+        let mut trend_files: Vec<Utf8PathBuf> = vec![];
+        let mut plot = None;
+        let mut from_lcov = None;
+        // Only pre-push hook is currently supported.
+        let mut pre_push = false;
 
         // show-env options
         let mut export_prefix = false;
+        let mut separate_target_dir = false;
 
         let mut parser = lexopt::Parser::from_args(args.clone());
         while let Some(arg) = parser.next()? {
@@ -344,7 +591,20 @@ impl Args {
                 Long("offline") => parse_flag_passthrough!(offline),
 
                 Long("doctests") => parse_flag!(doctests),
+                Long("branch") => parse_flag!(branch),
+                Long("keep-coverage-mapping") => parse_flag!(keep_coverage_mapping),
+                Long("cov-profile") => parse_opt!(cov_profile),
+                Long("expired") => parse_flag!(expired),
                 Long("ignore-run-fail") => parse_flag!(ignore_run_fail),
+                Long("report-on-fail") => parse_flag!(report_on_fail),
+                Long("keep-going") => parse_flag!(keep_going),
+                Long("criterion") => parse_flag!(criterion),
+                Long("external-runner") => parse_opt!(external_runner),
+                Long("runs") => parse_opt!(runs),
+                Long("junit") => parse_opt!(junit),
+                Long("fail-if-no-tests") => parse_flag!(fail_if_no_tests),
+                Long("discard-failed-attempts") => parse_flag!(discard_failed_attempts),
+                Long("emit-test-contribution") => parse_opt!(emit_test_contribution),
                 Long("no-run") => parse_flag!(no_run),
                 Long("no-fail-fast") => parse_flag_passthrough!(no_fail_fast),
 
@@ -365,6 +625,8 @@ impl Args {
                 Long("exclude") => parse_opt_passthrough!(exclude),
                 Long("exclude-from-test") => parse_opt!(exclude_from_test),
                 Long("exclude-from-report") => parse_opt!(exclude_from_report),
+                Long("instrument") => parse_opt!(instrument),
+                Long("report-include") => parse_opt!(report_include),
 
                 // build options
                 Short('r') | Long("release") => parse_flag_passthrough!(release),
@@ -374,24 +636,66 @@ impl Args {
                 Long("cargo-profile") if subcommand.is_nextest_based() => {
                     parse_opt_passthrough!(profile);
                 }
+                Long("cargo-profile") => {
+                    // Alias of --profile, for consistency with `cargo llvm-cov nextest`.
+                    parse_opt!(profile);
+                    cargo_args.push(format!("--profile={}", profile.as_ref().unwrap()));
+                }
                 Long("target") => parse_opt_passthrough!(target),
                 Long("coverage-target-only") => parse_flag!(coverage_target_only),
                 Long("remap-path-prefix") => parse_flag!(remap_path_prefix),
+                Long("windows-long-paths") => parse_flag!(windows_long_paths),
+                Long("no-rustc-wrapper") => parse_flag!(no_rustc_wrapper),
                 Long("include-ffi") => parse_flag!(include_ffi),
                 Long("no-clean") => parse_flag!(no_clean),
+                Long("append") => parse_flag!(append),
+                Long("no-lock") => parse_flag!(no_lock),
+                Long("timings") => parse_flag!(timings),
+                Long("timings-json") => parse_opt!(timings_json),
+                Long("nextest-archive-file") => parse_opt!(nextest_archive_file),
+                Long("args-file") => parse_opt!(args_file),
+                Long("timeout") => parse_opt!(timeout),
+                Long("kill-after") => parse_opt!(kill_after),
+                Long("dump-interval") => parse_opt!(dump_interval),
+                Long("daemon") => parse_flag!(daemon),
+                Long("plot") => parse_opt!(plot),
+                Long("from-lcov") => parse_opt!(from_lcov),
+                Long("pre-push") => parse_flag!(pre_push),
 
                 // report options
                 Long("json") => parse_flag!(json),
                 Long("lcov") => parse_flag!(lcov),
                 Long("cobertura") => parse_flag!(cobertura),
                 Long("codecov") => parse_flag!(codecov),
+                Long("summary-json") => parse_flag!(summary_json),
+                Long("json-lines") => parse_flag!(json_lines),
+                Long("msgpack") => parse_flag!(msgpack),
+                Long("exec-formatter") => parse_opt!(exec_formatter),
                 Long("text") => parse_flag!(text),
                 Long("html") => parse_flag!(html),
-                Long("open") => parse_flag!(open),
+                Long("open") => {
+                    // --open or --open=<ENTRY>
+                    if mem::replace(&mut open, true) {
+                        multi_arg(&arg)?;
+                    }
+                    if let Some(entry) = parser.optional_value() {
+                        open_entry = Some(entry.string()?);
+                    }
+                    after_subcommand = false;
+                }
+                Long("browser") => parse_opt!(browser),
                 Long("summary-only") => parse_flag!(summary_only),
                 Long("output-path") => parse_opt!(output_path),
+                Long("output-types") => parse_opt!(output_types),
+                Long("all-reports") => parse_flag!(all_reports),
                 Long("output-dir") => parse_opt!(output_dir),
                 Long("failure-mode") => parse_opt!(failure_mode),
+                Long("quarantine") => parse_flag!(quarantine),
+                Long("find-llvm-tools") => parse_flag!(find_llvm_tools),
+                Long("merge-toolchains") => parse_flag!(merge_toolchains),
+                Long("strict") => parse_flag!(strict),
+                Long("llvm-cov-path") => parse_opt!(llvm_cov_path),
+                Long("llvm-profdata-path") => parse_opt!(llvm_profdata_path),
                 Long("ignore-filename-regex") => parse_opt!(ignore_filename_regex),
                 Long("disable-default-ignore-filename-regex") => {
                     parse_flag!(disable_default_ignore_filename_regex);
@@ -411,11 +715,77 @@ impl Args {
                 Long("fail-uncovered-lines") => parse_opt!(fail_uncovered_lines),
                 Long("fail-uncovered-regions") => parse_opt!(fail_uncovered_regions),
                 Long("fail-uncovered-functions") => parse_opt!(fail_uncovered_functions),
+                Long("fail-under-diff-lines") => parse_opt!(fail_under_diff_lines),
+                Long("require-full") => parse_opt!(require_full),
+                Long("gate") => parse_opt!(gate),
+                Long("gate-baseline") => parse_opt!(gate_baseline),
+                Long("precision") => parse_opt!(precision),
+                Long("rounding") => parse_opt!(rounding),
+                Long("webhook-url") => parse_opt!(webhook_url),
+                Long("webhook-baseline") => parse_opt!(webhook_baseline),
+                Long("webhook-report-url") => parse_opt!(webhook_report_url),
+                Long("diff-base") => parse_opt!(diff_base),
                 Long("show-missing-lines") => parse_flag!(show_missing_lines),
+                Long("show-excluded") => parse_flag!(show_excluded),
+                Long("summary-line") => parse_flag!(summary_line),
+                Long("summary-format") => parse_opt!(summary_format),
+                Long("show-counts") => {
+                    // --show-counts or --show-counts=<MODE>
+                    if Store::is_full(&show_counts) {
+                        multi_arg(&arg)?;
+                    }
+                    let val = parser.optional_value().map(ValueExt::string).transpose()?;
+                    Store::push(&mut show_counts, val.as_deref().unwrap_or("both"))?;
+                    after_subcommand = false;
+                }
+                Long("uncovered-spans-json") => parse_opt!(uncovered_spans_json),
+                Long("list-uncovered") => {
+                    // --list-uncovered or --list-uncovered=<FORMAT>
+                    if Store::is_full(&list_uncovered) {
+                        multi_arg(&arg)?;
+                    }
+                    let val = parser.optional_value().map(ValueExt::string).transpose()?;
+                    Store::push(&mut list_uncovered, val.as_deref().unwrap_or("text"))?;
+                    after_subcommand = false;
+                }
+                Long("since-snapshot") => parse_opt!(since_snapshot),
+                Long("coverage-off-json") => parse_opt!(coverage_off_json),
                 Long("include-build-script") => parse_flag!(include_build_script),
+                Long("include-out-dir") => parse_flag!(include_out_dir),
+                Long("exclude-derives") => parse_flag!(exclude_derives),
+                Long("exclude-test-functions") => parse_flag!(exclude_test_functions),
+                Long("dep-coverage") => parse_opt!(dep_coverage),
+                Long("include-deps-matching") => parse_opt!(include_deps_matching),
+                Long("object") => parse_opt!(objects),
+                Long("max-file-lines") => parse_opt!(max_file_lines),
+                Long("max-file-size") => parse_opt!(max_file_size),
+                Long("path-map") => parse_opt!(path_map),
+                Long("lcov-relative-to") => parse_opt!(lcov_relative_to),
+                Long("canonicalize-paths") => {
+                    if Store::is_full(&canonicalize_paths) {
+                        multi_arg(&arg)?;
+                    }
+                    // The value is optional: --canonicalize-paths is the same as --canonicalize-paths=always.
+                    let val = parser.optional_value().map(ValueExt::string).transpose()?;
+                    Store::push(&mut canonicalize_paths, val.as_deref().unwrap_or("always"))?;
+                    after_subcommand = false;
+                }
+                Long("no-timestamps") => parse_flag!(no_timestamps),
+                Long("blame") => parse_flag!(blame),
+                Long("reproducible-html") => parse_flag!(reproducible_html),
+                Long("assets-dir") => parse_opt!(assets_dir),
+                Long("external-report") => parse_opt!(external_reports),
+                Long("demangle") => parse_flag!(demangle),
+                Long("demangle-keep-hashes") => parse_flag!(demangle_keep_hashes),
+                Long("demangle-cpp") => parse_flag!(demangle_cpp),
+                Long("report-per-binary") => parse_flag!(report_per_binary),
+                Long("summary-by-target-kind") => parse_flag!(summary_by_target_kind),
+                Long("summary-by-owner") => parse_flag!(summary_by_owner),
+                Long("summary-by-instantiation") => parse_flag!(summary_by_instantiation),
 
                 // show-env options
                 Long("export-prefix") => parse_flag!(export_prefix),
+                Long("separate-target-dir") => parse_flag!(separate_target_dir),
 
                 Short('v') | Long("verbose") => {
                     verbose += 1;
@@ -473,6 +843,29 @@ impl Args {
 
                 // passthrough
                 Long(_) | Short(_) if Subcommand::can_passthrough(subcommand) => passthrough!(),
+                Value(val) if subcommand == Subcommand::Show && show_file.is_none() => {
+                    show_file = Some(val.into_string().unwrap().into());
+                    after_subcommand = false;
+                }
+                Value(val) if subcommand == Subcommand::Diff && diff_exports.len() < 2 => {
+                    diff_exports.push(val.into_string().unwrap().into());
+                    after_subcommand = false;
+                }
+                Value(val) if subcommand == Subcommand::Snapshot && snapshot_name.is_none() => {
+                    let val = val.into_string().unwrap();
+                    if snapshot_save {
+                        snapshot_name = Some(val);
+                    } else if val == "save" {
+                        snapshot_save = true;
+                    } else {
+                        bail!("unrecognized command `{val}` for subcommand 'snapshot'; expected `save`");
+                    }
+                    after_subcommand = false;
+                }
+                Value(val) if subcommand == Subcommand::Trend => {
+                    trend_files.push(val.into_string().unwrap().into());
+                    after_subcommand = false;
+                }
                 Value(val)
                     if subcommand == Subcommand::None
                         || Subcommand::can_passthrough(subcommand) =>
@@ -480,11 +873,16 @@ impl Args {
                     let val = val.into_string().unwrap();
                     if subcommand == Subcommand::None {
                         subcommand = val.parse::<Subcommand>()?;
-                        if subcommand == Subcommand::Demangle && args.len() != 1 {
-                            unexpected(
-                                args.iter().find(|&arg| arg != "demangle").unwrap(),
-                                subcommand,
-                            )?;
+                        if subcommand == Subcommand::Demangle {
+                            // llvm-cov passes the options of the demangler set by -Xdemangler.
+                            if let Some(arg) = args.iter().find(|&arg| {
+                                !matches!(
+                                    arg.as_str(),
+                                    "demangle" | "--demangle-keep-hashes" | "--demangle-cpp"
+                                )
+                            }) {
+                                unexpected(arg, subcommand)?;
+                            }
                         }
                         after_subcommand = true;
                     } else {
@@ -505,6 +903,7 @@ impl Args {
                             );
                         }
                         cargo_args.push(val);
+                        test_filter = true;
                         after_subcommand = false;
                     }
                 }
@@ -516,24 +915,211 @@ impl Args {
 
         // unexpected options
         match subcommand {
-            Subcommand::ShowEnv => {}
+            Subcommand::ShowEnv => {
+                if no_lock {
+                    unexpected("--no-lock", subcommand)?;
+                }
+            }
             _ => {
                 if export_prefix {
                     unexpected("--export-prefix", subcommand)?;
                 }
+                if separate_target_dir {
+                    unexpected("--separate-target-dir", subcommand)?;
+                }
             }
         }
         if doc || doctests {
             let flag = if doc { "--doc" } else { "--doctests" };
             match subcommand {
                 Subcommand::None | Subcommand::Test => {}
-                Subcommand::ShowEnv | Subcommand::Report if doctests => {}
+                Subcommand::ShowEnv
+                | Subcommand::Report
+                | Subcommand::Show
+                | Subcommand::Annotate
+                | Subcommand::Snapshot
+                    if doctests => {}
                 Subcommand::Nextest | Subcommand::NextestArchive => {
                     bail!("doctest is not supported for nextest")
                 }
                 _ => unexpected(flag, subcommand)?,
             }
         }
+        if branch {
+            match subcommand {
+                Subcommand::None
+                | Subcommand::Test
+                | Subcommand::Run
+                | Subcommand::ShowEnv
+                | Subcommand::Nextest
+                | Subcommand::NextestArchive
+                | Subcommand::Install => {}
+                _ => unexpected("--branch", subcommand)?,
+            }
+        }
+        if keep_coverage_mapping {
+            match subcommand {
+                Subcommand::None
+                | Subcommand::Test
+                | Subcommand::Run
+                | Subcommand::ShowEnv
+                | Subcommand::Nextest
+                | Subcommand::NextestArchive
+                | Subcommand::Install => {}
+                _ => unexpected("--keep-coverage-mapping", subcommand)?,
+            }
+        }
+        if cov_profile.is_some() && subcommand == Subcommand::Clean {
+            unexpected("--cov-profile", subcommand)?;
+        }
+        if fail_if_no_tests && subcommand == Subcommand::NextestArchive {
+            unexpected("--fail-if-no-tests", subcommand)?;
+        }
+        if expired && subcommand != Subcommand::Clean {
+            unexpected("--expired", subcommand)?;
+        }
+        if keep_going && !matches!(subcommand, Subcommand::None | Subcommand::Test) {
+            unexpected("--keep-going", subcommand)?;
+        }
+        if criterion && !matches!(subcommand, Subcommand::None | Subcommand::Test) {
+            unexpected("--criterion", subcommand)?;
+        }
+        if runs.is_some() && !matches!(subcommand, Subcommand::None | Subcommand::Test) {
+            unexpected("--runs", subcommand)?;
+        }
+        if external_runner.is_some() && !matches!(subcommand, Subcommand::None | Subcommand::Test) {
+            unexpected("--external-runner", subcommand)?;
+        }
+        if junit.is_some() && !matches!(subcommand, Subcommand::None | Subcommand::Test) {
+            unexpected("--junit", subcommand)?;
+        }
+        if discard_failed_attempts && subcommand != Subcommand::Nextest {
+            unexpected("--discard-failed-attempts", subcommand)?;
+        }
+        if emit_test_contribution.is_some() && subcommand != Subcommand::Nextest {
+            unexpected("--emit-test-contribution", subcommand)?;
+        }
+        if matches!(
+            subcommand,
+            Subcommand::Clean
+                | Subcommand::ShowEnv
+                | Subcommand::Diff
+                | Subcommand::Trend
+                | Subcommand::Html
+                | Subcommand::InstallHook
+                | Subcommand::CoverallsFinish
+                | Subcommand::Collect
+                | Subcommand::Demangle
+        ) {
+            if timings {
+                unexpected("--timings", subcommand)?;
+            }
+            if timings_json.is_some() {
+                unexpected("--timings-json", subcommand)?;
+            }
+        }
+        if args_file.is_some() && subcommand != Subcommand::Run {
+            unexpected("--args-file", subcommand)?;
+        }
+        if timeout.is_some() && subcommand != Subcommand::Run {
+            unexpected("--timeout", subcommand)?;
+        }
+        if kill_after.is_some() && timeout.is_none() {
+            requires("--kill-after", &["--timeout"])?;
+        }
+        if timeout == Some(0) {
+            bail!("--timeout must be greater than 0");
+        }
+        if dump_interval.is_some() && subcommand != Subcommand::Run {
+            unexpected("--dump-interval", subcommand)?;
+        }
+        if dump_interval == Some(0) {
+            bail!("--dump-interval must be greater than 0");
+        }
+        if daemon && subcommand != Subcommand::Collect {
+            unexpected("--daemon", subcommand)?;
+        }
+        if !objects.is_empty()
+            && !matches!(
+                subcommand,
+                Subcommand::None
+                    | Subcommand::Test
+                    | Subcommand::Run
+                    | Subcommand::Nextest
+                    | Subcommand::Report
+                    | Subcommand::Show
+                    | Subcommand::Annotate
+                    | Subcommand::Snapshot
+            )
+        {
+            unexpected("--object", subcommand)?;
+        }
+        if plot.is_some() && subcommand != Subcommand::Trend {
+            unexpected("--plot", subcommand)?;
+        }
+        if from_lcov.is_some() && subcommand != Subcommand::Html {
+            unexpected("--from-lcov", subcommand)?;
+        }
+        if pre_push && subcommand != Subcommand::InstallHook {
+            unexpected("--pre-push", subcommand)?;
+        }
+        if matches!(
+            subcommand,
+            Subcommand::Show
+                | Subcommand::Annotate
+                | Subcommand::Diff
+                | Subcommand::Trend
+                | Subcommand::Html
+                | Subcommand::Install
+                | Subcommand::Collect
+                | Subcommand::Snapshot
+        ) {
+            // These subcommands don't generate coverage report in the usual formats.
+            let has_open = matches!(subcommand, Subcommand::Diff | Subcommand::Html);
+            let has_output_dir =
+                matches!(subcommand, Subcommand::Diff | Subcommand::Annotate | Subcommand::Html);
+            for (enabled, flag) in [
+                (json, "--json"),
+                (lcov, "--lcov"),
+                (cobertura, "--cobertura"),
+                (codecov, "--codecov"),
+                (summary_json, "--summary-json"),
+                (exec_formatter.is_some(), "--exec-formatter"),
+                (text, "--text"),
+                (html, "--html"),
+                (open && !has_open, "--open"),
+                (browser.is_some() && !has_open, "--browser"),
+                (open_entry.is_some() && has_open, "--open=<ENTRY>"),
+                (summary_only, "--summary-only"),
+                (output_path.is_some(), "--output-path"),
+                (output_types.is_some(), "--output-types"),
+                (all_reports, "--all-reports"),
+                (output_dir.is_some() && !has_output_dir, "--output-dir"),
+                (report_per_binary, "--report-per-binary"),
+                (summary_by_target_kind, "--summary-by-target-kind"),
+                (summary_by_owner, "--summary-by-owner"),
+                (summary_by_instantiation, "--summary-by-instantiation"),
+                (summary_line, "--summary-line"),
+                (summary_format.is_some(), "--summary-format"),
+                (show_counts.is_some(), "--show-counts"),
+                (uncovered_spans_json.is_some(), "--uncovered-spans-json"),
+                (list_uncovered.is_some(), "--list-uncovered"),
+                (since_snapshot.is_some(), "--since-snapshot"),
+                (coverage_off_json.is_some(), "--coverage-off-json"),
+                (show_excluded, "--show-excluded"),
+                (
+                    !external_reports.is_empty() && subcommand != Subcommand::Html,
+                    "--external-report",
+                ),
+            ] {
+                if enabled {
+                    unexpected(flag, subcommand)?;
+                }
+            }
+        }
+        if nextest_archive_file.is_some() && !subcommand.is_report_based() {
+            unexpected("--nextest-archive-file", subcommand)?;
+        }
         match subcommand {
             Subcommand::None | Subcommand::Nextest | Subcommand::NextestArchive => {}
             Subcommand::Test => {
@@ -572,6 +1158,9 @@ impl Args {
                 if no_fail_fast {
                     unexpected("--no-fail-fast", subcommand)?;
                 }
+                if fail_if_no_tests {
+                    unexpected("--fail-if-no-tests", subcommand)?;
+                }
                 if !exclude.is_empty() {
                     unexpected("--exclude", subcommand)?;
                 }
@@ -585,7 +1174,8 @@ impl Args {
             | Subcommand::Test
             | Subcommand::Run
             | Subcommand::Nextest
-            | Subcommand::NextestArchive => {}
+            | Subcommand::NextestArchive
+            | Subcommand::Install => {}
             _ => {
                 if !bin.is_empty() {
                     unexpected("--bin", subcommand)?;
@@ -596,6 +1186,12 @@ impl Args {
                 if !exclude_from_report.is_empty() {
                     unexpected("--exclude-from-report", subcommand)?;
                 }
+                if !instrument.is_empty() {
+                    unexpected("--instrument", subcommand)?;
+                }
+                if !report_include.is_empty() {
+                    unexpected("--report-include", subcommand)?;
+                }
                 if no_cfg_coverage {
                     unexpected("--no-cfg-coverage", subcommand)?;
                 }
@@ -605,14 +1201,40 @@ impl Args {
                 if no_report {
                     unexpected("--no-report", subcommand)?;
                 }
+                if no_rustc_wrapper && subcommand != Subcommand::ShowEnv {
+                    unexpected("--no-rustc-wrapper", subcommand)?;
+                }
                 if no_clean {
                     unexpected("--no-clean", subcommand)?;
                 }
+                if append {
+                    unexpected("--append", subcommand)?;
+                }
                 if ignore_run_fail {
                     unexpected("--ignore-run-fail", subcommand)?;
                 }
+                if report_on_fail {
+                    unexpected("--report-on-fail", subcommand)?;
+                }
             }
         }
+        if subcommand == Subcommand::Install {
+            // `cargo llvm-cov install` doesn't run anything or generate report.
+            for (enabled, flag) in [
+                (!exclude_from_report.is_empty(), "--exclude-from-report"),
+                (!report_include.is_empty(), "--report-include"),
+                (no_report, "--no-report"),
+                (ignore_run_fail, "--ignore-run-fail"),
+                (report_on_fail, "--report-on-fail"),
+                // cargo install builds in release mode by default.
+                (release, "--release"),
+            ] {
+                if enabled {
+                    unexpected(flag, subcommand)?;
+                }
+            }
+            release = profile.is_none() && !cargo_args.iter().any(|a| a == "--debug");
+        }
         match subcommand {
             Subcommand::None
             | Subcommand::Test
@@ -637,12 +1259,91 @@ impl Args {
             if !exclude_from_test.is_empty() {
                 requires("--exclude-from-test", &["--workspace"])?;
             }
+            if keep_going {
+                requires("--keep-going", &["--workspace"])?;
+            }
         }
         if coverage_target_only && target.is_none() {
             requires("--coverage-target-only", &["--target"])?;
         }
+        if blame && !html && !open && browser.is_none() {
+            requires("--blame", &["--html", "--open"])?;
+        }
+        if reproducible_html && !html && !open && browser.is_none() {
+            requires("--reproducible-html", &["--html", "--open"])?;
+        }
+        if assets_dir.is_some() && !html && !open && browser.is_none() {
+            requires("--assets-dir", &["--html", "--open"])?;
+        }
+        if !external_reports.is_empty()
+            && !html
+            && !open
+            && browser.is_none()
+            && !cobertura
+            && subcommand != Subcommand::Html
+        {
+            requires("--external-report", &["--html", "--open", "--cobertura"])?;
+        }
+        if subcommand == Subcommand::Show && show_file.is_none() {
+            bail!("`cargo llvm-cov show` requires the path to a source file");
+        }
+        if subcommand == Subcommand::Diff && diff_exports.len() != 2 {
+            bail!("`cargo llvm-cov diff` requires the paths to two exported reports");
+        }
+        if subcommand == Subcommand::Snapshot {
+            match &snapshot_name {
+                Some(name) => snapshot::validate_name(name)?,
+                None if snapshot_save => {
+                    bail!("`cargo llvm-cov snapshot save` requires the name of the snapshot")
+                }
+                None => bail!("`cargo llvm-cov snapshot` requires a command: save"),
+            }
+        }
+        if let Some(name) = &since_snapshot {
+            snapshot::validate_name(name)?;
+        }
+        if subcommand == Subcommand::Trend && trend_files.is_empty() {
+            bail!("`cargo llvm-cov trend` requires the paths to exported reports or directories");
+        }
+        if subcommand == Subcommand::Html && from_lcov.is_none() {
+            bail!("`cargo llvm-cov html` requires the path to a report in the lcov format (--from-lcov)");
+        }
+        if subcommand == Subcommand::InstallHook {
+            if !pre_push {
+                bail!("`cargo llvm-cov install-hook` requires the kind of hook to install (--pre-push)");
+            }
+            if fail_under_diff_lines.is_none() {
+                bail!("`cargo llvm-cov install-hook` requires --fail-under-diff-lines");
+            }
+        }
+        if diff_base.is_some() && fail_under_diff_lines.is_none() {
+            requires("--diff-base", &["--fail-under-diff-lines"])?;
+        }
+        if gate_baseline.is_some() && gate.is_none() {
+            requires("--gate-baseline", &["--gate"])?;
+        }
+        if gate.as_ref().is_some_and(Gate::uses_baseline) && gate_baseline.is_none() {
+            bail!("regression() in --gate requires --gate-baseline");
+        }
+        for pattern in &require_full {
+            if let Err(e) = glob::Pattern::new(pattern) {
+                bail!("invalid --require-full `{pattern}`: {e}");
+            }
+        }
+        if lcov_relative_to.is_some() && !lcov {
+            requires("--lcov-relative-to", &["--lcov"])?;
+        }
+        if json_lines && !json {
+            requires("--json-lines", &["--json"])?;
+        }
+        if msgpack && !json {
+            requires("--msgpack", &["--json"])?;
+        }
 
         // conflicts
+        if msgpack && json_lines {
+            conflicts("--msgpack", "--json-lines")?;
+        }
         if no_report && no_run {
             conflicts("--no-report", "--no-run")?;
         }
@@ -653,10 +1354,89 @@ impl Args {
                 conflicts(flag, "--no-clean")?;
             }
         }
+        if append && no_clean {
+            // --append implicitly enable --no-clean.
+            conflicts("--append", "--no-clean")?;
+        }
+        if fail_if_no_tests && no_run {
+            conflicts("--fail-if-no-tests", "--no-run")?;
+        }
         if ignore_run_fail && no_fail_fast {
             // --ignore-run-fail implicitly enable --no-fail-fast.
             conflicts("--ignore-run-fail", "--no-fail-fast")?;
         }
+        if report_on_fail {
+            if ignore_run_fail {
+                conflicts("--report-on-fail", "--ignore-run-fail")?;
+            }
+            if no_fail_fast {
+                // --report-on-fail implicitly enable --no-fail-fast.
+                conflicts("--report-on-fail", "--no-fail-fast")?;
+            }
+            if no_report {
+                conflicts("--report-on-fail", "--no-report")?;
+            }
+        }
+        if dump_interval.is_some() && no_report {
+            conflicts("--dump-interval", "--no-report")?;
+        }
+        if keep_going {
+            if ignore_run_fail {
+                conflicts("--keep-going", "--ignore-run-fail")?;
+            }
+            if report_on_fail {
+                conflicts("--keep-going", "--report-on-fail")?;
+            }
+            if no_run {
+                conflicts("--keep-going", "--no-run")?;
+            }
+        }
+        if !external_reports.is_empty() {
+            // The merged html report is not generated by llvm-cov.
+            if blame {
+                conflicts("--external-report", "--blame")?;
+            }
+            if assets_dir.is_some() {
+                conflicts("--external-report", "--assets-dir")?;
+            }
+        }
+        if precision.is_some_and(|p| p > percent::MAX_PRECISION) {
+            bail!("--precision must be less than or equal to {}", percent::MAX_PRECISION);
+        }
+        if runs == Some(0) {
+            bail!("--runs must be greater than 0");
+        }
+        if runs.is_some() && no_report {
+            // Coverage of each run is compared when generating the report.
+            conflicts("--runs", "--no-report")?;
+        }
+        if emit_test_contribution.is_some() && no_report {
+            // Coverage of each test is compared when generating the report.
+            conflicts("--emit-test-contribution", "--no-report")?;
+        }
+        if external_runner.as_deref().is_some_and(|s| s.trim().is_empty()) {
+            bail!("empty string is not allowed in --external-runner");
+        }
+        if external_runner.is_some() {
+            // The command builds and runs the tests, instead of `cargo test`.
+            for (enabled, flag) in [
+                (doctests, "--doctests"),
+                (no_run, "--no-run"),
+                (keep_going, "--keep-going"),
+                (fail_if_no_tests, "--fail-if-no-tests"),
+                (criterion, "--criterion"),
+                (runs.is_some(), "--runs"),
+                (junit.is_some(), "--junit"),
+            ] {
+                if enabled {
+                    conflicts("--external-runner", flag)?;
+                }
+            }
+        }
+        if criterion && (doc || doctests) {
+            // Selecting bench targets disables the doctests that cargo test runs by default.
+            conflicts("--criterion", if doc { "--doc" } else { "--doctests" })?;
+        }
         if doc || doctests {
             let flag = if doc { "--doc" } else { "--doctests" };
             if lib {
@@ -726,6 +1506,101 @@ impl Args {
                 conflicts(flag, "--cobertura")?;
             }
         }
+        if summary_json {
+            let flag = "--summary-json";
+            if json {
+                conflicts(flag, "--json")?;
+            }
+            if lcov {
+                conflicts(flag, "--lcov")?;
+            }
+            if cobertura {
+                conflicts(flag, "--cobertura")?;
+            }
+            if codecov {
+                conflicts(flag, "--codecov")?;
+            }
+        }
+        if exec_formatter.is_some() {
+            let flag = "--exec-formatter";
+            for (enabled, other) in [
+                (json, "--json"),
+                (lcov, "--lcov"),
+                (cobertura, "--cobertura"),
+                (codecov, "--codecov"),
+                (summary_json, "--summary-json"),
+                (text, "--text"),
+                (html, "--html"),
+                (open, "--open"),
+                (output_dir.is_some(), "--output-dir"),
+            ] {
+                if enabled {
+                    conflicts(flag, other)?;
+                }
+            }
+        }
+        if all_reports {
+            let flag = "--all-reports";
+            for (enabled, other) in [
+                (json, "--json"),
+                (lcov, "--lcov"),
+                (cobertura, "--cobertura"),
+                (codecov, "--codecov"),
+                (summary_json, "--summary-json"),
+                (exec_formatter.is_some(), "--exec-formatter"),
+                (text, "--text"),
+                (html, "--html"),
+                (open, "--open"),
+                (output_path.is_some(), "--output-path"),
+                (output_types.is_some(), "--output-types"),
+            ] {
+                if enabled {
+                    conflicts(flag, other)?;
+                }
+            }
+        }
+        if output_types.is_some() {
+            let flag = "--output-types";
+            for (enabled, other) in [
+                (json, "--json"),
+                (lcov, "--lcov"),
+                (cobertura, "--cobertura"),
+                (codecov, "--codecov"),
+                (summary_json, "--summary-json"),
+                (exec_formatter.is_some(), "--exec-formatter"),
+                (text, "--text"),
+                (html, "--html"),
+                (open, "--open"),
+                (output_path.is_some(), "--output-path"),
+            ] {
+                if enabled {
+                    conflicts(flag, other)?;
+                }
+            }
+        }
+        for (used, flag) in [
+            (summary_by_target_kind, "--summary-by-target-kind"),
+            (summary_by_owner, "--summary-by-owner"),
+            (summary_by_instantiation, "--summary-by-instantiation"),
+        ] {
+            if !used {
+                continue;
+            }
+            for (enabled, other) in [
+                (json, "--json"),
+                (lcov, "--lcov"),
+                (cobertura, "--cobertura"),
+                (codecov, "--codecov"),
+                (exec_formatter.is_some(), "--exec-formatter"),
+                (text, "--text"),
+                (html, "--html"),
+                (open, "--open"),
+            ] {
+                if enabled {
+                    conflicts(flag, other)?;
+                }
+            }
+        }
         if text {
             let flag = "--text";
             if json {
@@ -740,7 +1615,11 @@ impl Args {
             if codecov {
                 conflicts(flag, "--codecov")?;
             }
+            if summary_json {
+                conflicts(flag, "--summary-json")?;
+            }
         }
+        open |= browser.is_some();
         if html || open {
             let flag = if html { "--html" } else { "--open" };
             if json {
@@ -755,6 +1634,9 @@ impl Args {
             if codecov {
                 conflicts(flag, "--codecov")?;
             }
+            if summary_json {
+                conflicts(flag, "--summary-json")?;
+            }
             if text {
                 conflicts(flag, "--text")?;
             }
@@ -782,12 +1664,18 @@ impl Args {
             if codecov {
                 conflicts(flag, "--codecov")?;
             }
+            if summary_json {
+                conflicts(flag, "--summary-json")?;
+            }
             if output_path.is_some() {
                 conflicts(flag, "--output-path")?;
             }
         }
 
         // forbid_empty_values
+        if cov_profile.as_deref() == Some("") {
+            bail!("empty string is not allowed in --cov-profile")
+        }
         if ignore_filename_regex.as_deref() == Some("") {
             bail!("empty string is not allowed in --ignore-filename-regex")
         }
@@ -797,6 +1685,42 @@ impl Args {
         if output_dir.as_deref() == Some(Utf8Path::new("")) {
             bail!("empty string is not allowed in --output-dir")
         }
+        if lcov_relative_to.as_deref() == Some(Utf8Path::new("")) {
+            bail!("empty string is not allowed in --lcov-relative-to")
+        }
+        if exec_formatter.as_deref().is_some_and(|s| s.trim().is_empty()) {
+            bail!("empty string is not allowed in --exec-formatter")
+        }
+        let output_types = match output_types {
+            Some(types) => {
+                let mut v: Vec<OutputType> = vec![];
+                for ty in types.split(',') {
+                    let ty = ty.parse()?;
+                    if !v.contains(&ty) {
+                        v.push(ty);
+                    }
+                }
+                if v.contains(&OutputType::Coveralls) && v.contains(&OutputType::CoverallsPlus) {
+                    bail!("coveralls and coveralls+ in --output-types may not be used together");
+                }
+                v
+            }
+            None => vec![],
+        };
+        for map in &path_map {
+            match map.split_once('=') {
+                Some((from, to)) if !from.is_empty() && !to.is_empty() => {}
+                _ => bail!("--path-map must be in the form of FROM=TO, but found `{map}`"),
+            }
+        }
+        for report in &external_reports {
+            match report.split_once('=') {
+                Some((lang, path)) if !lang.is_empty() && !path.is_empty() => {}
+                _ => bail!(
+                    "--external-report must be in the form of LANG=PATH, but found `{report}`"
+                ),
+            }
+        }
 
         if no_run {
             // The following warnings should not be promoted to an error.
@@ -810,10 +1734,14 @@ impl Args {
         }
 
         // For subsequent processing
-        if no_report || no_run {
-            // --no-report and --no-run implies --no-clean
+        if no_report || no_run || append {
+            // --no-report, --no-run, and --append implies --no-clean
             no_clean = true;
         }
+        if append {
+            // The previous runs may have used another toolchain.
+            merge_toolchains = true;
+        }
         if doc {
             // --doc implies --doctests
             doctests = true;
@@ -827,16 +1755,30 @@ impl Args {
             subcommand,
             cov: LlvmCovOptions {
                 json,
+                json_lines,
+                msgpack,
                 lcov,
                 cobertura,
                 codecov,
+                summary_json,
+                exec_formatter,
                 text,
                 html,
                 open,
+                open_entry,
+                browser,
                 summary_only,
                 output_path,
+                output_types,
+                all_reports,
                 output_dir,
                 failure_mode,
+                quarantine,
+                find_llvm_tools,
+                merge_toolchains,
+                strict,
+                llvm_cov_path,
+                llvm_profdata_path,
                 ignore_filename_regex,
                 disable_default_ignore_filename_regex,
                 show_instantiations,
@@ -849,12 +1791,69 @@ impl Args {
                 fail_uncovered_lines,
                 fail_uncovered_regions,
                 fail_uncovered_functions,
+                fail_under_diff_lines,
+                require_full,
+                gate,
+                gate_baseline,
+                precision,
+                rounding,
+                webhook_url,
+                webhook_baseline,
+                webhook_report_url,
+                diff_base,
                 show_missing_lines,
+                show_excluded,
+                summary_line,
+                summary_format,
+                show_counts,
+                uncovered_spans_json,
+                list_uncovered,
+                since_snapshot,
+                coverage_off_json,
                 include_build_script,
+                include_out_dir,
+                exclude_derives,
+                exclude_test_functions,
+                dep_coverage,
+                include_deps_matching,
+                objects,
+                max_file_lines,
+                max_file_size,
+                path_map,
+                canonicalize_paths: canonicalize_paths.unwrap_or_default(),
+                lcov_relative_to,
+                no_timestamps,
+                blame,
+                reproducible_html,
+                assets_dir,
+                external_reports,
+                demangle,
+                demangle_keep_hashes,
+                demangle_cpp,
+                report_per_binary,
+                summary_by_target_kind,
+                summary_by_owner,
+                summary_by_instantiation,
             },
-            show_env: ShowEnvOptions { export_prefix },
+            show_env: ShowEnvOptions { export_prefix, separate_target_dir },
             doctests,
+            branch,
+            keep_coverage_mapping,
+            cfgs: vec![],
+            path_thresholds: vec![],
+            owner_thresholds: vec![],
+            cov_profile,
+            expired,
             ignore_run_fail,
+            report_on_fail,
+            keep_going,
+            criterion,
+            external_runner,
+            runs,
+            junit,
+            fail_if_no_tests,
+            discard_failed_attempts,
+            emit_test_contribution,
             lib,
             bin,
             bins,
@@ -870,20 +1869,57 @@ impl Args {
             exclude,
             exclude_from_test,
             exclude_from_report,
+            instrument,
+            report_include,
             release,
             profile,
             target,
             coverage_target_only,
             verbose: verbose.try_into().unwrap_or(u8::MAX),
             color,
-            remap_path_prefix,
+            remap_path_prefix: remap_path_prefix || reproducible_html,
+            windows_long_paths,
+            no_rustc_wrapper,
             include_ffi,
             no_clean,
+            append,
+            no_lock,
+            timings,
+            timings_json,
+            nextest_archive_file,
+            args_file,
+            timeout,
+            kill_after,
+            dump_interval,
+            daemon,
+            snapshot_name,
+            show_file,
+            diff_exports,
+            trend_files,
+            plot,
+            from_lcov,
             manifest: ManifestOptions { manifest_path, frozen, locked, offline },
             cargo_args,
+            test_filter,
             rest,
+            raw_args: all_args,
         })
     }
+
+    /// Returns `true` if any of the target selection flags (--lib, --bin, etc.) is specified.
+    pub(crate) fn selects_targets(&self) -> bool {
+        self.lib
+            || !self.bin.is_empty()
+            || self.bins
+            || !self.example.is_empty()
+            || self.examples
+            || !self.test.is_empty()
+            || self.tests
+            || !self.bench.is_empty()
+            || self.benches
+            || self.all_targets
+            || self.doc
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -912,6 +1948,36 @@ pub(crate) enum Subcommand {
     /// Build and archive tests with cargo nextest
     NextestArchive,
 
+    /// Build and install binaries with instrumentation
+    Install,
+
+    /// Print a source file with the coverage of each line.
+    Show,
+
+    /// Write copies of source files annotated with the execution count of each line.
+    Annotate,
+
+    /// Generate html report of the lines whose coverage changed between two reports.
+    Diff,
+
+    /// Show the coverage over time from exported reports.
+    Trend,
+
+    /// Generate html report from a report in the lcov format.
+    Html,
+
+    /// Install a git hook that checks the coverage of changed lines.
+    InstallHook,
+
+    /// Close a parallel build on Coveralls.
+    CoverallsFinish,
+
+    /// Merge profraw files into the rolling profdata incrementally.
+    Collect,
+
+    /// Save a snapshot of the current coverage.
+    Snapshot,
+
     // internal (unstable)
     Demangle,
 }
@@ -920,15 +1986,30 @@ static CARGO_LLVM_COV_USAGE: &str = include_str!("../docs/cargo-llvm-cov.txt");
 static CARGO_LLVM_COV_TEST_USAGE: &str = include_str!("../docs/cargo-llvm-cov-test.txt");
 static CARGO_LLVM_COV_RUN_USAGE: &str = include_str!("../docs/cargo-llvm-cov-run.txt");
 static CARGO_LLVM_COV_REPORT_USAGE: &str = include_str!("../docs/cargo-llvm-cov-report.txt");
+static CARGO_LLVM_COV_SHOW_USAGE: &str = include_str!("../docs/cargo-llvm-cov-show.txt");
+static CARGO_LLVM_COV_DIFF_USAGE: &str = include_str!("../docs/cargo-llvm-cov-diff.txt");
+static CARGO_LLVM_COV_ANNOTATE_USAGE: &str = include_str!("../docs/cargo-llvm-cov-annotate.txt");
+static CARGO_LLVM_COV_TREND_USAGE: &str = include_str!("../docs/cargo-llvm-cov-trend.txt");
+static CARGO_LLVM_COV_HTML_USAGE: &str = include_str!("../docs/cargo-llvm-cov-html.txt");
+static CARGO_LLVM_COV_INSTALL_HOOK_USAGE: &str =
+    include_str!("../docs/cargo-llvm-cov-install-hook.txt");
+static CARGO_LLVM_COV_COVERALLS_FINISH_USAGE: &str =
+    include_str!("../docs/cargo-llvm-cov-coveralls-finish.txt");
+static CARGO_LLVM_COV_SNAPSHOT_USAGE: &str = include_str!("../docs/cargo-llvm-cov-snapshot.txt");
+static CARGO_LLVM_COV_COLLECT_USAGE: &str = include_str!("../docs/cargo-llvm-cov-collect.txt");
 static CARGO_LLVM_COV_CLEAN_USAGE: &str = include_str!("../docs/cargo-llvm-cov-clean.txt");
 static CARGO_LLVM_COV_SHOW_ENV_USAGE: &str = include_str!("../docs/cargo-llvm-cov-show-env.txt");
 static CARGO_LLVM_COV_NEXTEST_USAGE: &str = include_str!("../docs/cargo-llvm-cov-nextest.txt");
 static CARGO_LLVM_COV_NEXTEST_ARCHIVE_USAGE: &str =
     include_str!("../docs/cargo-llvm-cov-nextest-archive.txt");
+static CARGO_LLVM_COV_INSTALL_USAGE: &str = include_str!("../docs/cargo-llvm-cov-install.txt");
 
 impl Subcommand {
     fn can_passthrough(subcommand: Self) -> bool {
-        matches!(subcommand, Self::Test | Self::Run | Self::Nextest | Self::NextestArchive)
+        matches!(
+            subcommand,
+            Self::Test | Self::Run | Self::Nextest | Self::NextestArchive | Self::Install
+        )
     }
 
     fn help_text(subcommand: Self) -> &'static str {
@@ -937,24 +2018,44 @@ impl Subcommand {
             Self::Test => CARGO_LLVM_COV_TEST_USAGE,
             Self::Run => CARGO_LLVM_COV_RUN_USAGE,
             Self::Report => CARGO_LLVM_COV_REPORT_USAGE,
+            Self::Show => CARGO_LLVM_COV_SHOW_USAGE,
+            Self::Annotate => CARGO_LLVM_COV_ANNOTATE_USAGE,
+            Self::Diff => CARGO_LLVM_COV_DIFF_USAGE,
+            Self::Trend => CARGO_LLVM_COV_TREND_USAGE,
+            Self::Html => CARGO_LLVM_COV_HTML_USAGE,
+            Self::InstallHook => CARGO_LLVM_COV_INSTALL_HOOK_USAGE,
+            Self::CoverallsFinish => CARGO_LLVM_COV_COVERALLS_FINISH_USAGE,
+            Self::Collect => CARGO_LLVM_COV_COLLECT_USAGE,
+            Self::Snapshot => CARGO_LLVM_COV_SNAPSHOT_USAGE,
             Self::Clean => CARGO_LLVM_COV_CLEAN_USAGE,
             Self::ShowEnv => CARGO_LLVM_COV_SHOW_ENV_USAGE,
             Self::Nextest => CARGO_LLVM_COV_NEXTEST_USAGE,
             Self::NextestArchive => CARGO_LLVM_COV_NEXTEST_ARCHIVE_USAGE,
+            Self::Install => CARGO_LLVM_COV_INSTALL_USAGE,
             Self::Demangle => "", // internal API
         }
     }
 
-    fn as_str(self) -> &'static str {
+    pub(crate) fn as_str(self) -> &'static str {
         match self {
             Self::None => "",
             Self::Test => "test",
             Self::Run => "run",
             Self::Report => "report",
+            Self::Show => "show",
+            Self::Annotate => "annotate",
+            Self::Diff => "diff",
+            Self::Trend => "trend",
+            Self::Html => "html",
+            Self::InstallHook => "install-hook",
+            Self::CoverallsFinish => "coveralls-finish",
+            Self::Collect => "collect",
+            Self::Snapshot => "snapshot",
             Self::Clean => "clean",
             Self::ShowEnv => "show-env",
             Self::Nextest => "nextest",
             Self::NextestArchive => "nextest-archive",
+            Self::Install => "install",
             Self::Demangle => "demangle",
         }
     }
@@ -962,6 +2063,11 @@ impl Subcommand {
     pub(crate) fn is_nextest_based(self) -> bool {
         matches!(self, Self::Nextest | Self::NextestArchive)
     }
+
+    /// Returns `true` if the subcommand only uses the existing profile data and artifacts.
+    pub(crate) fn is_report_based(self) -> bool {
+        matches!(self, Self::Report | Self::Show | Self::Annotate | Self::Snapshot)
+    }
 }
 
 impl FromStr for Subcommand {
@@ -972,16 +2078,51 @@ impl FromStr for Subcommand {
             "test" | "t" => Ok(Self::Test),
             "run" | "r" => Ok(Self::Run),
             "report" => Ok(Self::Report),
+            "show" => Ok(Self::Show),
+            "annotate" => Ok(Self::Annotate),
+            "diff" => Ok(Self::Diff),
+            "trend" => Ok(Self::Trend),
+            "html" => Ok(Self::Html),
+            "install-hook" => Ok(Self::InstallHook),
+            "coveralls-finish" => Ok(Self::CoverallsFinish),
+            "collect" => Ok(Self::Collect),
+            "snapshot" => Ok(Self::Snapshot),
             "clean" => Ok(Self::Clean),
             "show-env" => Ok(Self::ShowEnv),
             "nextest" => Ok(Self::Nextest),
             "nextest-archive" => Ok(Self::NextestArchive),
+            "install" => Ok(Self::Install),
             "demangle" => Ok(Self::Demangle),
             _ => bail!("unrecognized subcommand {s}"),
         }
     }
 }
 
+/// How to render the summary of the coverage (--summary-format).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SummaryFormat {
+    /// The table printed by `llvm-cov report`
+    Table,
+    /// The same JSON as --summary-json
+    Json,
+    Markdown,
+    Csv,
+}
+
+impl FromStr for SummaryFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "markdown" => Ok(Self::Markdown),
+            "csv" => Ok(Self::Csv),
+            _ => bail!("--summary-format must be table, json, markdown, or csv, but found `{s}`"),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub(crate) struct LlvmCovOptions {
     /// Export coverage data in "json" format
@@ -991,6 +2132,17 @@ pub(crate) struct LlvmCovOptions {
     /// This internally calls `llvm-cov export -format=text`.
     /// See <https://llvm.org/docs/CommandGuide/llvm-cov.html#llvm-cov-export> for more.
     pub(crate) json: bool,
+    /// Write the --json report in JSON Lines format
+    ///
+    /// Instead of a single JSON document, this writes one JSON object per file (with the functions
+    /// whose first filename is the file) followed by an object with the totals, so consumers can
+    /// process the coverage of very large workspaces one file at a time.
+    pub(crate) json_lines: bool,
+    /// Write the --json report in MessagePack format
+    ///
+    /// The data is the same as the JSON, but is much smaller and faster to parse. Structs and maps
+    /// are encoded as maps with string keys, and sequences as arrays.
+    pub(crate) msgpack: bool,
     /// Export coverage data in "lcov" format
     ///
     /// If --output-path is not specified, the report will be printed to stdout.
@@ -1015,6 +2167,25 @@ pub(crate) struct LlvmCovOptions {
     /// See <https://llvm.org/docs/CommandGuide/llvm-cov.html#llvm-cov-export> for more.
     pub(crate) codecov: bool,
 
+    /// Export coverage summary in a compact JSON format
+    ///
+    /// Unlike --json, which outputs the data exported by llvm-cov as is, this outputs
+    /// totals per package and overall, the arguments used, and the commit of the
+    /// workspace, in a stable versioned schema intended for machine consumption.
+    ///
+    /// If --output-path is not specified, the report will be printed to stdout.
+    pub(crate) summary_json: bool,
+
+    /// Generate coverage report using the given command
+    ///
+    /// The command receives the coverage data in the same format as --json on stdin, and
+    /// its stdout is used as the report. This is useful for generating report formats that
+    /// cargo-llvm-cov doesn't support.
+    ///
+    /// The command is split on whitespace and run in the workspace root. If --output-path is
+    /// not specified, the report will be printed to stdout.
+    pub(crate) exec_formatter: Option<String>,
+
     /// Generate coverage report in “text” format
     ///
     /// If --output-path or --output-dir is not specified, the report will be printed to stdout.
@@ -1033,27 +2204,85 @@ pub(crate) struct LlvmCovOptions {
     ///
     /// See --html for more.
     pub(crate) open: bool,
+    /// The page to open with --open (the value of `--open=<ENTRY>`)
+    ///
+    /// This is the path to a source file, the name of a package (opens its main
+    /// source file), or the path to a page relative to the html directory.
+    pub(crate) open_entry: Option<String>,
+    /// The command to open the report with (implies --open)
+    ///
+    /// This takes precedence over `doc.browser` cargo config and `BROWSER`
+    /// environment variable.
+    pub(crate) browser: Option<String>,
 
     /// Export only summary information for each file in the coverage data
     ///
-    /// This flag can only be used together with --json, --lcov, --cobertura, or --codecov.
+    /// This flag can only be used together with --json, --lcov, --cobertura, --codecov, or
+    /// --exec-formatter.
     // If the format flag is not specified, this flag is no-op because the only summary is displayed anyway.
     pub(crate) summary_only: bool,
 
     /// Specify a file to write coverage data into.
     ///
-    /// This flag can only be used together with --json, --lcov, --cobertura, --codecov, or --text.
+    /// This flag can only be used together with --json, --lcov, --cobertura, --codecov, --summary-json,
+    /// --exec-formatter, or --text.
     /// See --output-dir for --html and --open.
     pub(crate) output_path: Option<Utf8PathBuf>,
     /// Specify a directory to write coverage report into (default to `target/llvm-cov`).
     ///
-    /// This flag can only be used together with --text, --html, or --open.
+    /// This flag can only be used together with --text, --html, --open, --output-types, or
+    /// --all-reports. `{package}` and `{commit}` in the path are replaced with the name of the
+    /// current package and the hash of the current commit (e.g., `coverage/{commit}`).
     /// See also --output-path.
     // If the format flag is not specified, this flag is no-op.
     pub(crate) output_dir: Option<Utf8PathBuf>,
+    /// Write reports of the given comma-separated output types of grcov into --output-dir.
+    ///
+    /// Supported types are lcov, cobertura, html, coveralls, and coveralls+. Like grcov's
+    /// reports written into a directory, the reports are written to `lcov`, `cobertura.xml`,
+    /// `html`, and `coveralls` (coveralls and coveralls+) in the directory, so pipelines that
+    /// consume grcov's reports can be used as is. coveralls+ is the Coveralls JSON with the
+    /// functions of each source file. The other fields of the Coveralls JSON are taken from
+    /// `COVERALLS_REPO_TOKEN`, `COVERALLS_SERVICE_NAME`, `COVERALLS_SERVICE_NUMBER`,
+    /// `COVERALLS_SERVICE_JOB_ID`, and `COVERALLS_FLAG_NAME` environment variables, and the
+    /// current commit. If `COVERALLS_PARALLEL` is `true`, the report is a job of a parallel
+    /// build, which is closed by `cargo llvm-cov coveralls-finish`.
+    pub(crate) output_types: Vec<OutputType>,
+    /// Write html, lcov, json, and summary JSON reports into --output-dir in a conventional layout
+    ///
+    /// The reports are written to `html/`, `lcov.info`, `coverage.json`, and `summary.json`,
+    /// and `manifest.json` describes the run (arguments, package, and commit) and the paths of
+    /// the reports.
+    pub(crate) all_reports: bool,
 
     /// Fail if `any` or `all` profiles cannot be merged (default to `any`)
     pub(crate) failure_mode: Option<String>,
+    /// Move profraw files that cannot be merged to the quarantine directory and
+    /// merge the rest, instead of failing.
+    ///
+    /// The list of moved files and the reasons are printed as warnings and saved in
+    /// `<target-dir>/llvm-cov-target/<workspace-name>-quarantine/quarantine.json`.
+    pub(crate) quarantine: bool,
+    /// Use llvm-tools from another installed toolchain if the profile data was
+    /// generated by a toolchain whose profile format is not supported by the llvm-tools
+    /// of the current toolchain.
+    pub(crate) find_llvm_tools: bool,
+    /// Merge profile data generated by different toolchains (e.g., tests run with stable
+    /// and doctests run with nightly)
+    ///
+    /// Profile data of each raw profile format version is merged by llvm-tools that can read
+    /// it (searched in installed toolchains as with --find-llvm-tools), and doctests are
+    /// included in the report even without --doctests. Object files whose binary ID doesn't
+    /// appear in the profile data (e.g., binaries built by another toolchain that were not
+    /// run) are excluded from the report.
+    pub(crate) merge_toolchains: bool,
+    /// Fail instead of generating an empty report if no profile data, no object
+    /// files, or no source files to be included in the report are found.
+    pub(crate) strict: bool,
+    /// Path to llvm-cov (overrides `LLVM_COV` environment variable)
+    pub(crate) llvm_cov_path: Option<Utf8PathBuf>,
+    /// Path to llvm-profdata (overrides `LLVM_PROFDATA` environment variable)
+    pub(crate) llvm_profdata_path: Option<Utf8PathBuf>,
     /// Skip source code files with file paths that match the given regular expression.
     pub(crate) ignore_filename_regex: Option<String>,
     // For debugging (unstable)
@@ -1078,10 +2307,251 @@ pub(crate) struct LlvmCovOptions {
     pub(crate) fail_uncovered_regions: Option<u64>,
     /// Exit with a status of 1 if the uncovered functions are greater than MAX.
     pub(crate) fail_uncovered_functions: Option<u64>,
+    /// Exit with a status of 1 if the coverage of the lines changed since --diff-base is less
+    /// than MIN percent.
+    pub(crate) fail_under_diff_lines: Option<f64>,
+    /// Exit with a status of 1 if any line (or branch, if --branch is used) in the source files
+    /// matching the given glob pattern is not covered.
+    pub(crate) require_full: Vec<String>,
+    /// Exit with a status of 1 if the given expression over the coverage summary is false.
+    pub(crate) gate: Option<Gate>,
+    /// Report exported by --summary-json or --json to compare with in `regression()` of --gate.
+    pub(crate) gate_baseline: Option<Utf8PathBuf>,
+    /// Number of decimal places of percentages (default to 2).
+    ///
+    /// When this or --rounding is specified, percentages are rounded before compared with
+    /// thresholds, so the percentage shown is the one that is checked.
+    pub(crate) precision: Option<u8>,
+    /// How to round percentages to --precision: nearest (default) or floor.
+    ///
+    /// With `floor`, percentages are never shown as reaching a threshold they don't reach
+    /// (e.g., 79.996% is shown as 79.99% instead of 80.00%).
+    pub(crate) rounding: Option<Rounding>,
+    /// Post a summary of the coverage to the given incoming webhook of Slack or Microsoft Teams.
+    ///
+    /// Default to CARGO_LLVM_COV_WEBHOOK_URL environment variable, which is recommended
+    /// since webhook URLs contain the credential.
+    pub(crate) webhook_url: Option<String>,
+    /// Report exported by --summary-json or --json to show the change of coverage compared with
+    /// in the webhook notification.
+    pub(crate) webhook_baseline: Option<Utf8PathBuf>,
+    /// URL of the report to link from the webhook notification.
+    pub(crate) webhook_report_url: Option<String>,
+    /// The git revision that --fail-under-diff-lines compares with.
+    ///
+    /// Default to the upstream of the current branch, or `origin/HEAD` if the
+    /// branch has no upstream.
+    pub(crate) diff_base: Option<String>,
     /// Show lines with no coverage.
     pub(crate) show_missing_lines: bool,
+    /// Show the files and functions excluded from the report, and the options
+    /// (or default rules) that exclude them.
+    ///
+    /// Items excluded by `#[coverage(off)]` (or `#[no_coverage]`) attributes are also listed.
+    pub(crate) show_excluded: bool,
+    /// Print a single line summary of the total coverage to stdout.
+    ///
+    /// e.g., `lines=82.4% functions=75.1% regions=70.3% branches=61.0%`
+    /// (`-` instead of the percentage if there is nothing to cover).
+    pub(crate) summary_line: bool,
+    /// Print the summary of the coverage in the given format: table, json, markdown, or csv.
+    ///
+    /// The summary is printed to stdout after the report, so a report written to a file
+    /// (e.g., --lcov --output-path) can be combined with the summary in any format. Without
+    /// other report formats, this replaces the default summary table.
+    pub(crate) summary_format: Option<SummaryFormat>,
+    /// Show the covered and total counts of lines, functions, regions, and branches
+    /// in summaries: both (default, next to percentages) or only (instead of percentages).
+    ///
+    /// This applies to the summary table, --summary-line, --summary-format table or
+    /// markdown, --summary-by-target-kind, and --summary-by-owner. JSON and CSV summaries
+    /// always contain the counts.
+    pub(crate) show_counts: Option<Counts>,
+    /// Write the spans of code not executed by any test to the specified file in JSON.
+    ///
+    /// This is intended for mutation testing tools such as cargo-mutants to
+    /// skip mutants in uncovered code.
+    pub(crate) uncovered_spans_json: Option<Utf8PathBuf>,
+    /// Print every code region not executed by any test to stdout, with the function
+    /// that contains it: text (default) or json.
+    ///
+    /// The text format prints one region per line as
+    /// `<file>:<line>:<column>-<line>:<column><TAB><function>`. Without other report
+    /// formats, this replaces the default summary table.
+    pub(crate) list_uncovered: Option<ListUncoveredFormat>,
+    /// Print the lines executed now but not in the snapshot saved by
+    /// `cargo llvm-cov snapshot save <NAME>`.
+    ///
+    /// Without other report formats, this replaces the default summary table.
+    pub(crate) since_snapshot: Option<String>,
+    /// Write the items excluded by `#[coverage(off)]` attributes to the specified file in JSON.
+    ///
+    /// These items are not instrumented, so they are found in the source files
+    /// of the workspace members. --show-excluded lists them as well.
+    pub(crate) coverage_off_json: Option<Utf8PathBuf>,
     /// Include build script in coverage report.
     pub(crate) include_build_script: bool,
+    /// Include code generated in OUT_DIR (e.g., by bindgen or prost) in coverage report.
+    ///
+    /// By default, source files under the target directory, including OUT_DIR
+    /// of any crate's build script, are excluded from the report.
+    pub(crate) include_out_dir: bool,
+    /// Exclude functions generated by common derive macros from coverage report.
+    ///
+    /// This is a heuristic based on demangled symbol names: trait impls of
+    /// Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, and serde's
+    /// Serialize/Deserialize are excluded, even if they are implemented manually.
+    pub(crate) exclude_derives: bool,
+    /// Exclude `#[test]` and `#[bench]` functions (and closures defined in them)
+    /// from coverage report, including the lines, regions, and branches they contain.
+    ///
+    /// Test functions are detected by looking for test attributes (e.g.,
+    /// `#[test]`, `#[tokio::test]`, `#[bench]`) before the functions in the
+    /// source code.
+    pub(crate) exclude_test_functions: bool,
+    /// Show coverage of the specified dependency instead of the crates in the workspace.
+    ///
+    /// The dependency can be a path or registry dependency. This is useful for
+    /// library authors who want to know how well the tests of a downstream
+    /// crate exercise their library.
+    pub(crate) dep_coverage: Option<String>,
+    /// Include dependencies whose package names match the specified regular
+    /// expression in coverage report, in addition to the crates in the workspace.
+    ///
+    /// This is useful for including registry or git dependencies that are
+    /// developed together with the workspace (e.g., crates from the same
+    /// organization in a multi-repository product). Paths of their source
+    /// files remapped by cargo's trim-paths are mapped back to their sources.
+    pub(crate) include_deps_matching: Option<String>,
+    /// Use the specified binary or shared library as an object file, in addition to the ones
+    /// found in the target directory.
+    ///
+    /// This is useful when tests are run by an external harness that loads or links the
+    /// library (e.g., a C test runner linked to a staticlib), and the binary that contains the
+    /// coverage mapping is outside the target directory. This flag can be specified multiple
+    /// times.
+    pub(crate) objects: Vec<Utf8PathBuf>,
+    /// Skip source files that have more than MAX lines from coverage report.
+    ///
+    /// This is useful to avoid huge reports due to enormous auto-generated files
+    /// such as bindings. The list of skipped files is shown.
+    pub(crate) max_file_lines: Option<u64>,
+    /// Skip source files that are larger than MAX bytes from coverage report.
+    ///
+    /// See --max-file-lines for more.
+    pub(crate) max_file_size: Option<u64>,
+    /// Map source file paths in coverage data that start with FROM to TO.
+    ///
+    /// This is useful when coverage data was collected in a container, sandbox, or on
+    /// another machine where the absolute paths differ from the local checkout.
+    /// TO is relative to the current directory. This flag can be specified multiple times.
+    ///
+    /// Source files are read from the mapped paths, and paths in --json, --lcov, --cobertura,
+    /// --codecov, and the summary are rewritten. File names shown in --text and --html reports
+    /// are not rewritten.
+    pub(crate) path_map: Vec<String>,
+    /// Whether to resolve symlinks in source file paths (never, always, or auto)
+    ///
+    /// `always` resolves all symlinks in the paths. `auto` rewrites the paths in the workspace
+    /// to be under the path through which the workspace is reached from the current directory,
+    /// so that the paths match the ones shown in editors when the checkout is reached via a
+    /// symlink. `--canonicalize-paths` without a value is the same as `--canonicalize-paths=always`.
+    /// Defaults to `never`, which uses the paths recorded in the coverage data as is.
+    ///
+    /// Like --path-map, this affects paths in --json, --lcov, --cobertura, --codecov, and the
+    /// summary.
+    pub(crate) canonicalize_paths: CanonicalizePaths,
+    /// Write paths of source files in --lcov report relative to DIR instead of absolute paths.
+    ///
+    /// This is useful to use the same lcov.info with tools such as genhtml, Coverage Gutters,
+    /// and Codecov regardless of where the workspace is checked out. DIR is relative to the
+    /// current directory. Paths of source files outside DIR (e.g., dependencies) are kept as is.
+    pub(crate) lcov_relative_to: Option<Utf8PathBuf>,
+    /// Omit timestamps from --html and --cobertura reports.
+    ///
+    /// If this flag is not specified, `SOURCE_DATE_EPOCH` environment variable is
+    /// respected as the time of report creation if set.
+    pub(crate) no_timestamps: bool,
+    /// Annotate each line of source files in --html report with the author and the age of the
+    /// commit that last changed it.
+    ///
+    /// This internally calls `git blame`. Lines changed in the last 30 days and uncommitted
+    /// changes are highlighted, to help prioritize uncovered code. Files not tracked by git
+    /// are not annotated.
+    pub(crate) blame: bool,
+    /// Generate --html report that is identical for identical inputs regardless of the
+    /// environment.
+    ///
+    /// This omits the time of report creation (unless `SOURCE_DATE_EPOCH` environment variable
+    /// is set) and the version of llvm-cov from the report, and implies --remap-path-prefix so
+    /// that paths in the workspace are relative to the workspace root.
+    pub(crate) reproducible_html: bool,
+    /// Copy the files in the given directory into the --html report, replacing the stylesheet
+    /// and the script generated by llvm-cov.
+    ///
+    /// This can be used to bundle custom stylesheets along with the fonts and scripts they use,
+    /// so that the report renders identically without internet access. A warning is emitted if
+    /// the report loads resources from the network.
+    pub(crate) assets_dir: Option<Utf8PathBuf>,
+    /// Merge the coverage report of code in another language into --html and --cobertura reports.
+    ///
+    /// The value is in the form of LANG=PATH, where PATH is a report in the lcov or Cobertura
+    /// format (e.g., written by coverage.py, istanbul, or gcovr), and LANG is the name of the
+    /// section in the merged report. The coverage of Rust code is in the `rust` section.
+    /// Relative paths of source files in the report are relative to the workspace root. This
+    /// flag can be specified multiple times.
+    pub(crate) external_reports: Vec<String>,
+    /// Demangle function names in --json and --lcov reports.
+    ///
+    /// Function names in --text, --html, and --cobertura reports are always demangled.
+    pub(crate) demangle: bool,
+    /// Keep hashes in demangled Rust function names
+    ///
+    /// By default, crate disambiguators of symbols in the v0 mangling scheme (e.g.,
+    /// `[1a2b3c4d5e6f7a8b]`) and the hash suffix of symbols in the legacy mangling scheme (e.g.,
+    /// `::h0123456789abcdef`) are removed.
+    pub(crate) demangle_keep_hashes: bool,
+    /// Also demangle C++ function names (e.g., of C++ code linked via FFI)
+    ///
+    /// This requires llvm-cxxfilt or c++filt in PATH.
+    pub(crate) demangle_cpp: bool,
+    /// Generate a separate report for each test binary in addition to the combined report.
+    ///
+    /// Each report only contains the coverage collected by running that binary (e.g., the
+    /// unit tests of a package or one integration test), which shows which code each
+    /// test suite actually exercises. The reports are written to `<STEM>-<BINARY>.<EXT>`
+    /// next to --output-path, or `per-binary/<BINARY>` in --output-dir if either is used,
+    /// otherwise printed to stdout.
+    ///
+    /// This is currently only supported on targets that use ELF binaries (e.g., Linux).
+    pub(crate) report_per_binary: bool,
+    /// Show the coverage collected by running each kind of target (unit tests, integration
+    /// tests, doctests, examples, benches, and binaries run from them) in addition to the
+    /// total coverage.
+    ///
+    /// The breakdown is shown after the summary table, or included in the --summary-json
+    /// output. This is useful to find out which kind of tests exercises which code.
+    ///
+    /// This flag can only be used without report format flags or together with --summary-json.
+    /// This is currently only supported on targets that use ELF binaries (e.g., Linux).
+    pub(crate) summary_by_target_kind: bool,
+    /// Show the coverage of the files owned by each code owner in the CODEOWNERS file in
+    /// addition to the total coverage.
+    ///
+    /// The CODEOWNERS file is searched for in `.github/`, the root, and `docs/` of the
+    /// repository. Files owned by multiple owners count toward each of them, and files that
+    /// have no owners are shown as `(unowned)`. The breakdown is shown after the summary
+    /// table, or included in the --summary-json output.
+    ///
+    /// This flag can only be used without report format flags or together with --summary-json.
+    pub(crate) summary_by_owner: bool,
+    /// Show the coverage of functions, lines, and regions covered in all instantiations of
+    /// generic functions, in addition to those covered in at least one instantiation.
+    ///
+    /// The summary is shown after the summary table, or included in the --summary-json output.
+    ///
+    /// This flag can only be used without report format flags or together with --summary-json.
+    pub(crate) summary_by_instantiation: bool,
 }
 
 impl LlvmCovOptions {
@@ -1094,6 +2564,13 @@ impl LlvmCovOptions {
 pub(crate) struct ShowEnvOptions {
     /// Prepend "export " to each line, so that the output is suitable to be sourced by bash.
     pub(crate) export_prefix: bool,
+    /// Use a separate target directory for coverage builds.
+    ///
+    /// Use the same target directory as other subcommands (`<target-dir>/llvm-cov-target`)
+    /// instead of the default target directory and export it as `CARGO_TARGET_DIR`, so that
+    /// coverage builds don't invalidate the build cache of normal (non-coverage) builds, and
+    /// vice versa.
+    pub(crate) separate_target_dir: bool,
 }
 
 // https://doc.rust-lang.org/nightly/cargo/commands/cargo-test.html#manifest-options
@@ -1132,7 +2609,7 @@ pub(crate) fn merge_config_to_args(
 ) {
     // CLI flags are prefer over config values.
     if target.is_none() {
-        *target = ws.target_for_cli.clone();
+        target.clone_from(&ws.target_for_cli);
     }
     if *verbose == 0 {
         *verbose = u8::from(ws.config.term.verbose.unwrap_or(false));
@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// CODEOWNERS support (--summary-by-owner and `owner-thresholds`).
+//
+// The CODEOWNERS file is searched for in `.github/`, the root, and `docs/` of
+// the repository, in the same order as GitHub. Each line is a gitignore-like
+// pattern followed by the owners of the files matching it, and the last
+// matching line takes precedence. A line without owners makes the matching
+// files unowned. Files owned by multiple owners count toward the coverage of
+// each of them.
+//
+// Refs:
+// - https://docs.github.com/en/repositories/managing-your-repositorys-settings-and-features/customizing-your-repository/about-code-owners
+// - https://docs.gitlab.com/ee/user/project/codeowners/reference.html
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{bail, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    context::Context,
+    fs,
+    json::{CoverageKind, LlvmCovJsonExport, SummaryOwner},
+    percent,
+};
+
+/// The name of the group of files that have no owners.
+pub(crate) const UNOWNED: &str = "(unowned)";
+
+const LOCATIONS: &[&str] = &[".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+pub(crate) struct CodeOwners {
+    /// The root of the repository, which patterns are relative to.
+    root: Utf8PathBuf,
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    pattern: glob::Pattern,
+    /// Whether the pattern also matches the files in the directories it
+    /// matches.
+    recursive: bool,
+    /// Whether the pattern only matches directories.
+    dir_only: bool,
+    owners: Vec<String>,
+}
+
+impl CodeOwners {
+    /// Loads the CODEOWNERS file of the repository that contains the workspace.
+    pub(crate) fn load(cx: &Context) -> Result<Self> {
+        let workspace_root = &cx.ws.metadata.workspace_root;
+        // Fall back to the workspace root if it is not in a git repository.
+        let root = workspace_root
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .unwrap_or(workspace_root);
+        for location in LOCATIONS {
+            let path = root.join(location);
+            if path.is_file() {
+                return Ok(Self::parse(root, &path, &fs::read_to_string(&path)?));
+            }
+        }
+        bail!("CODEOWNERS file not found in {root}, {root}/.github, or {root}/docs")
+    }
+
+    fn parse(root: &Utf8Path, path: &Utf8Path, text: &str) -> Self {
+        let mut rules = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            // Skip comments and section headers of GitLab (`[Section]` or `^[Section]`).
+            if line.is_empty() || line.starts_with(['#', '[', '^']) {
+                continue;
+            }
+            let mut tokens = line.split_whitespace();
+            let pattern = tokens.next().unwrap();
+            let owners = tokens.take_while(|t| !t.starts_with('#')).map(str::to_owned).collect();
+            match Rule::new(pattern, owners) {
+                Some(rule) => rules.push(rule),
+                None => warn!("ignored invalid pattern `{pattern}` at {path}:{}", i + 1),
+            }
+        }
+        Self { root: root.to_owned(), rules }
+    }
+
+    /// Returns the owners of the given source file. An empty list means the
+    /// file is unowned.
+    fn owners_of(&self, cx: &Context, filename: &str) -> &[String] {
+        let path = cx.source_path(filename);
+        let Ok(path) = path.strip_prefix(&self.root) else { return &[] };
+        self.rules.iter().rev().find(|rule| rule.matches(path)).map_or(&[], |rule| &rule.owners)
+    }
+
+    /// Returns the source files in the report grouped by owner.
+    fn files_by_owner<'a>(
+        &self,
+        cx: &Context,
+        json: &'a LlvmCovJsonExport,
+    ) -> BTreeMap<&str, BTreeSet<&'a str>> {
+        let mut files: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for filename in json.file_names() {
+            let owners = self.owners_of(cx, filename);
+            if owners.is_empty() {
+                files.entry(UNOWNED).or_default().insert(filename);
+            }
+            for owner in owners {
+                files.entry(owner).or_default().insert(filename);
+            }
+        }
+        files
+    }
+}
+
+impl Rule {
+    fn new(pattern: &str, owners: Vec<String>) -> Option<Self> {
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        // Patterns that contain a slash other than at the end are relative to
+        // the root, and others match at any depth.
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        // `dir/*` only matches the files directly in `dir`, unlike `dir/`.
+        let recursive = !pattern.ends_with("/*");
+        let pattern = if anchored { pattern.to_owned() } else { format!("**/{pattern}") };
+        Some(Self { pattern: glob::Pattern::new(&pattern).ok()?, recursive, dir_only, owners })
+    }
+
+    fn matches(&self, path: &Utf8Path) -> bool {
+        let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+        if !self.dir_only && self.pattern.matches_path_with(path.as_std_path(), options) {
+            return true;
+        }
+        self.recursive
+            && path
+                .ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_str().is_empty())
+                .any(|dir| self.pattern.matches_path_with(dir.as_std_path(), options))
+    }
+}
+
+/// Returns the coverage of the source files owned by each owner, sorted by
+/// owner.
+pub(crate) fn summary(cx: &Context, json: &LlvmCovJsonExport) -> Result<Vec<SummaryOwner>> {
+    let codeowners = CodeOwners::load(cx)?;
+    Ok(codeowners
+        .files_by_owner(cx, json)
+        .into_iter()
+        .map(|(owner, files)| SummaryOwner {
+            owner: owner.to_owned(),
+            totals: json.get_totals_of_files(|f| files.contains(f)),
+        })
+        .collect())
+}
+
+/// Checks `owner-thresholds` in the configuration file against the line
+/// coverage of the source files owned by each owner.
+pub(crate) fn check_thresholds(cx: &Context, json: &LlvmCovJsonExport) -> Result<()> {
+    if cx.args.owner_thresholds.is_empty() {
+        return Ok(());
+    }
+    let codeowners = CodeOwners::load(cx)?;
+    let files = codeowners.files_by_owner(cx, json);
+    for (owner, min) in &cx.args.owner_thresholds {
+        let Some(files) = files.get(&**owner) else {
+            warn!("no source files in the report are owned by `{owner}` in `owner-thresholds`");
+            continue;
+        };
+        let percent =
+            json.get_coverage_percent_of_files(CoverageKind::Lines, |f| files.contains(f));
+        if percent < *min {
+            error!(
+                "line coverage of files owned by `{owner}` ({}%) is less than {min}% specified in \
+                 `owner-thresholds`",
+                percent::format(percent)
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rule() {
+        let matches = |pattern: &str, path: &str| {
+            Rule::new(pattern, vec![]).unwrap().matches(Utf8Path::new(path))
+        };
+        assert!(matches("*", "src/lib.rs"));
+        assert!(matches("*.rs", "src/lib.rs"));
+        assert!(!matches("*.rs", "src/lib.c"));
+        assert!(matches("/src/", "src/a/lib.rs"));
+        assert!(!matches("/src/", "crates/src/lib.rs"));
+        assert!(matches("src/", "crates/src/lib.rs"));
+        assert!(matches("src", "src/lib.rs"));
+        assert!(!matches("lib.rs/", "src/lib.rs"));
+        assert!(matches("src/*", "src/lib.rs"));
+        assert!(!matches("src/*", "src/a/lib.rs"));
+        assert!(matches("src/**", "src/a/lib.rs"));
+        assert!(matches("**/a", "src/a/lib.rs"));
+        assert!(matches("crates/foo", "crates/foo/src/lib.rs"));
+        assert!(!matches("crates/foo", "a/crates/foo/src/lib.rs"));
+        assert!(Rule::new("/", vec![]).is_none());
+        assert!(Rule::new("src/[", vec![]).is_none());
+    }
+
+    #[test]
+    fn parse() {
+        let codeowners = CodeOwners::parse(
+            Utf8Path::new("/"),
+            Utf8Path::new("CODEOWNERS"),
+            "# comment\n* @org/core\n\n[Section]\n/src/gen/ @org/codegen @bob # generated\n\
+             /src/gen/ignored.rs\n",
+        );
+        let owners = |path: &str| {
+            let path = Utf8Path::new(path);
+            codeowners.rules.iter().rev().find(|r| r.matches(path)).map(|r| r.owners.clone())
+        };
+        assert_eq!(owners("src/lib.rs").unwrap(), ["@org/core"]);
+        assert_eq!(owners("src/gen/a.rs").unwrap(), ["@org/codegen", "@bob"]);
+        assert!(owners("src/gen/ignored.rs").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// `cargo llvm-cov collect` support.
+//
+// A long test campaign (e.g., soak tests or fuzzing over several hours) leaves
+// a large number of profraw files, and merging all of them when generating the
+// report takes a long time. `cargo llvm-cov collect` merges the profraw files in
+// the target directory into the rolling profdata and removes them, so the report
+// only needs to merge the rolling profdata with the profraw files written since
+// then. With --daemon, it keeps watching the target directory and merges new
+// profraw files as they appear, until it is terminated.
+//
+// The profiler runtime writes a profraw file when the process exits (or, in
+// continuous mode, keeps updating it while the process runs), and the file name
+// contains the process ID (%p). So a file is merged only if it has not been
+// modified recently and, on Unix, the process that wrote it has exited.
+//
+// The new rolling profdata is written to a temporary file and then renamed, so
+// terminating the daemon while merging doesn't corrupt the rolling profdata.
+// Profraw files that llvm-profdata cannot read (e.g., written by a process that
+// crashed while writing) are moved to the quarantine directory like --quarantine
+// does, so that they don't make every following pass fail.
+// Merging is serialized with the merge for the report and the removal of profile
+// data by `lock::lock_profile_data`, but `cargo llvm-cov collect` doesn't hold
+// the lock of the target directory, so it can run alongside the tests.
+
+use std::{path::Path, thread, time::Duration};
+
+use anyhow::{Context as _, Result};
+
+use crate::{context::Context, fs, lock};
+
+/// Interval between the passes of --daemon.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Profraw files modified within this duration may still be being written.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+pub(crate) fn run(cx: &Context) -> Result<()> {
+    if !cx.args.daemon {
+        let merged = merge(cx)?;
+        if merged == 0 {
+            info!("no profraw files to merge in {}", cx.ws.target_dir);
+        }
+        return Ok(());
+    }
+    status!("Watching", "{} for profraw files", cx.ws.target_dir);
+    loop {
+        // Other failures (e.g., failing to write the rolling profdata) are
+        // reported, and the files are retried in the next pass.
+        if let Err(e) = merge(cx) {
+            warn!("{e:#}");
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Merges the profraw files that are no longer written into the rolling
+/// profdata, and returns the number of the merged files.
+fn merge(cx: &Context) -> Result<usize> {
+    let _lock = if cx.args.no_lock { None } else { Some(lock::lock_profile_data(&cx.ws)?) };
+    let mut files = vec![];
+    for path in crate::profraw_files(cx)? {
+        if is_complete(&cx.ws.name, &path)? {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        return Ok(0);
+    }
+    let mut profraw_files = Vec::with_capacity(files.len());
+    for path in &files {
+        profraw_files.push(
+            path.to_str()
+                .with_context(|| format!("{} contains invalid utf-8 data", path.display()))?,
+        );
+    }
+    if let Err(e) = merge_into_rolling_profdata(cx, &profraw_files) {
+        let valid_files = crate::quarantine_profraw_files(cx, &profraw_files)?;
+        if valid_files.len() == profraw_files.len() {
+            // The failure is not caused by invalid profraw files.
+            return Err(e);
+        }
+        profraw_files = valid_files;
+        if profraw_files.is_empty() {
+            return Ok(0);
+        }
+        merge_into_rolling_profdata(cx, &profraw_files)?;
+    }
+    for path in &profraw_files {
+        fs::remove_file(path)?;
+    }
+    let rolling_profdata = &cx.ws.rolling_profdata_file;
+    status!("Merged", "{} profraw files into {rolling_profdata}", profraw_files.len());
+    Ok(profraw_files.len())
+}
+
+/// Merges the given profraw files and the rolling profdata into the new rolling
+/// profdata.
+fn merge_into_rolling_profdata(cx: &Context, profraw_files: &[&str]) -> Result<()> {
+    let rolling_profdata = &cx.ws.rolling_profdata_file;
+    let mut input_files = profraw_files.to_vec();
+    if rolling_profdata.exists() {
+        input_files.push(rolling_profdata.as_str());
+    }
+    let tmp = cx.ws.target_dir.join(format!("{}-rolling.profdata.tmp", cx.ws.name));
+    crate::merge_profdata_cmd(cx, &input_files, &tmp, "collect-list", false)?
+        .run()
+        .context("failed to merge profile data")?;
+    fs::rename(&tmp, rolling_profdata)?;
+    Ok(())
+}
+
+/// Returns `true` if the given profraw file is no longer written by a running
+/// process.
+fn is_complete(name: &str, path: &Path) -> Result<bool> {
+    let modified = fs::metadata(path)?.modified()?;
+    if modified.elapsed().unwrap_or_default() < SETTLE_TIME {
+        return Ok(false);
+    }
+    #[cfg(unix)]
+    if let Some(pid) = writer_pid(name, path).and_then(rustix::process::Pid::from_raw) {
+        // In continuous mode, the file is updated through a memory mapping
+        // while the process runs, which doesn't always update its mtime.
+        // ESRCH means that the process doesn't exist.
+        return Ok(rustix::process::test_kill_process(pid) == Err(rustix::io::Errno::SRCH));
+    }
+    #[cfg(not(unix))]
+    let _ = name;
+    Ok(true)
+}
+
+/// Returns the ID of the process that wrote the given profraw file, which is
+/// named `<name>-<pid>-<signature>_<pool>.profraw` (see `llvm_profile_file`).
+fn writer_pid(name: &str, path: &Path) -> Option<i32> {
+    let file_name = path.file_name()?.to_str()?;
+    file_name.strip_prefix(name)?.strip_prefix('-')?.split('-').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pid() {
+        let pid = |name, file| writer_pid(name, Path::new(file));
+        assert_eq!(pid("crate", "target/crate-1234-5678_0.profraw"), Some(1234));
+        assert_eq!(pid("my-crate", "target/my-crate-1234-5678_0.profraw"), Some(1234));
+        assert_eq!(pid("crate", "target/crate-a-5678_0.profraw"), None);
+        assert_eq!(pid("crate", "target/other-1234-5678_0.profraw"), None);
+    }
+}
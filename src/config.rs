@@ -0,0 +1,631 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Workspace-level configuration file (`llvm-cov.toml` or `.config/llvm-cov.toml`
+// in the workspace root, or `workspace.metadata.llvm-cov` table in Cargo.toml)
+// to avoid repeating the same flags in every invocation, and per-package
+// configuration (`package.metadata.llvm-cov` table in Cargo.toml).
+//
+// Keys are the same as the corresponding command-line flags, and command-line
+// flags always take precedence over the configuration file.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::{bail, Context as _, Result};
+use camino::Utf8Path;
+use serde_derive::Deserialize;
+
+use crate::{
+    clean::Retention,
+    cli::{Args, Subcommand},
+    fs,
+    metadata::{Metadata, Package},
+    percent::{self, Rounding},
+};
+
+const FILE_NAME: &str = "llvm-cov.toml";
+const METADATA_KEY: &str = "llvm-cov";
+/// Number of seconds in a day, the unit of `profraw-max-age`.
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct LlvmCovConfig {
+    // Report formats
+    json: Option<bool>,
+    lcov: Option<bool>,
+    cobertura: Option<bool>,
+    codecov: Option<bool>,
+    summary_json: Option<bool>,
+    text: Option<bool>,
+    html: Option<bool>,
+    summary_only: Option<bool>,
+    /// Relative to the workspace root.
+    output_path: Option<String>,
+    /// Relative to the workspace root.
+    output_dir: Option<String>,
+
+    // Report contents
+    ignore_filename_regex: Option<String>,
+    show_instantiations: Option<bool>,
+    show_missing_lines: Option<bool>,
+    include_build_script: Option<bool>,
+    include_out_dir: Option<bool>,
+    exclude_derives: Option<bool>,
+    exclude_test_functions: Option<bool>,
+    max_file_lines: Option<u64>,
+    max_file_size: Option<u64>,
+    demangle_keep_hashes: Option<bool>,
+    demangle_cpp: Option<bool>,
+
+    // Thresholds
+    fail_under_functions: Option<f64>,
+    fail_under_lines: Option<f64>,
+    fail_under_regions: Option<f64>,
+    fail_uncovered_lines: Option<u64>,
+    fail_uncovered_regions: Option<u64>,
+    fail_uncovered_functions: Option<u64>,
+    /// Minimum line coverage of the source files matching each glob pattern
+    /// (relative to the workspace root).
+    #[serde(default)]
+    path_thresholds: BTreeMap<String, f64>,
+    /// Minimum line coverage of the source files owned by each code owner in
+    /// the CODEOWNERS file.
+    #[serde(default)]
+    owner_thresholds: BTreeMap<String, f64>,
+    /// Number of decimal places of percentages.
+    precision: Option<u8>,
+    /// `nearest` or `floor`.
+    rounding: Option<String>,
+
+    // Build
+    doctests: Option<bool>,
+    branch: Option<bool>,
+    /// Custom cfgs (`name` or `name="value"`) enabled only during coverage builds.
+    cfg: Option<Vec<String>>,
+    /// Command run instead of `cargo test` (e.g., `npm test` for Node.js addons).
+    external_runner: Option<String>,
+
+    // Retention of profile data in the target directory
+    /// Days after which profraw and profdata files are removed.
+    profraw_max_age: Option<u64>,
+    /// Maximum total size in bytes of profraw and profdata files; the oldest ones
+    /// are removed first.
+    profraw_max_size: Option<u64>,
+
+    // Notifications
+    /// Rejected by validate_keys: the URL contains the credential, which must
+    /// not be committed to the repository.
+    webhook_url: Option<String>,
+    /// Relative to the workspace root.
+    webhook_baseline: Option<String>,
+    webhook_report_url: Option<String>,
+
+    /// Named sets of the above keys selected by `--cov-profile`.
+    #[serde(default)]
+    profile: BTreeMap<String, LlvmCovConfig>,
+}
+
+impl LlvmCovConfig {
+    /// Loads the configuration from the configuration file in the workspace
+    /// root or `workspace.metadata.llvm-cov` table in Cargo.toml, if it exists.
+    ///
+    /// Returns the description of where the configuration is loaded from
+    /// along with the configuration.
+    pub(crate) fn load(metadata: &Metadata) -> Result<Option<(String, Self)>> {
+        let workspace_root = &metadata.workspace_root;
+        let table = metadata.workspace_metadata.as_ref().and_then(|m| m.get(METADATA_KEY));
+        let mut candidates =
+            [workspace_root.join(FILE_NAME), workspace_root.join(".config").join(FILE_NAME)]
+                .into_iter()
+                .filter(|path| path.is_file());
+        if let Some(path) = candidates.next() {
+            if let Some(other) = candidates.next() {
+                warn!("both {path} and {other} exist; {other} will be ignored");
+            }
+            if table.is_some() {
+                warn!(
+                    "both {path} and `workspace.metadata.{METADATA_KEY}` table in Cargo.toml \
+                     exist; the latter will be ignored"
+                );
+            }
+            let config = toml::from_str(&fs::read_to_string(&path)?)
+                .map_err(Into::into)
+                .and_then(Self::validate)
+                .with_context(|| format!("failed to parse {path}"))?;
+            return Ok(Some((path.into_string(), config)));
+        }
+        let Some(table) = table else { return Ok(None) };
+        let manifest_path = workspace_root.join("Cargo.toml");
+        let config = serde_json::from_value(table.clone())
+            .map_err(Into::into)
+            .and_then(Self::validate)
+            .with_context(|| {
+                format!(
+                    "failed to parse `workspace.metadata.{METADATA_KEY}` table in {manifest_path}"
+                )
+            })?;
+        Ok(Some((format!("`workspace.metadata.{METADATA_KEY}` table in {manifest_path}"), config)))
+    }
+
+    fn validate(self) -> Result<Self> {
+        self.validate_keys()?;
+        for (name, profile) in &self.profile {
+            if !profile.profile.is_empty() {
+                bail!("profile `{name}` may not contain nested profiles");
+            }
+            profile.validate_keys().with_context(|| format!("invalid profile `{name}`"))?;
+        }
+        Ok(self)
+    }
+
+    fn validate_keys(&self) -> Result<()> {
+        let formats = [
+            ("json", self.json),
+            ("lcov", self.lcov),
+            ("cobertura", self.cobertura),
+            ("codecov", self.codecov),
+            ("summary-json", self.summary_json),
+            ("text", self.text),
+            ("html", self.html),
+        ];
+        let mut enabled =
+            formats.iter().filter(|(_, enabled)| *enabled == Some(true)).map(|(name, _)| name);
+        if let (Some(a), Some(b)) = (enabled.next(), enabled.next()) {
+            bail!("`{a}` and `{b}` may not be enabled together");
+        }
+        if self.output_path.is_some() && self.output_dir.is_some() {
+            bail!("`output-path` and `output-dir` may not be specified together");
+        }
+        if self.webhook_url.is_some() {
+            bail!(
+                "`webhook-url` may not be specified in the configuration file because the URL \
+                 contains the credential; use --webhook-url or CARGO_LLVM_COV_WEBHOOK_URL \
+                 environment variable instead"
+            );
+        }
+        for cfg in self.cfg.iter().flatten() {
+            let name = parse_cfg(cfg).with_context(|| format!("invalid cfg `{cfg}` in `cfg`"))?.0;
+            if matches!(name, "coverage" | "coverage_nightly") {
+                bail!("`{name}` cfg is set by cargo-llvm-cov and may not be specified in `cfg`");
+            }
+        }
+        for (key, value) in [
+            ("ignore-filename-regex", &self.ignore_filename_regex),
+            ("output-path", &self.output_path),
+            ("output-dir", &self.output_dir),
+            ("webhook-baseline", &self.webhook_baseline),
+            ("webhook-report-url", &self.webhook_report_url),
+            ("external-runner", &self.external_runner),
+        ] {
+            if value.as_deref() == Some("") {
+                bail!("empty string is not allowed in `{key}`");
+            }
+        }
+        for (pattern, min) in &self.path_thresholds {
+            glob::Pattern::new(pattern)
+                .with_context(|| format!("invalid pattern `{pattern}` in `path-thresholds`"))?;
+            if !(0. ..=100.).contains(min) {
+                bail!("threshold of `{pattern}` in `path-thresholds` must be between 0 and 100");
+            }
+        }
+        if self.precision.is_some_and(|p| p > percent::MAX_PRECISION) {
+            bail!("`precision` must be less than or equal to {}", percent::MAX_PRECISION);
+        }
+        if let Some(rounding) = &self.rounding {
+            if rounding.parse::<Rounding>().is_err() {
+                bail!("`rounding` must be nearest or floor, but found `{rounding}`");
+            }
+        }
+        for (owner, min) in &self.owner_thresholds {
+            if !(0. ..=100.).contains(min) {
+                bail!("threshold of `{owner}` in `owner-thresholds` must be between 0 and 100");
+            }
+        }
+        if self.profraw_max_age.is_some_and(|days| days.checked_mul(SECS_PER_DAY).is_none()) {
+            bail!("`profraw-max-age` must be less than or equal to {}", u64::MAX / SECS_PER_DAY);
+        }
+        Ok(())
+    }
+
+    /// Overrides the keys of this configuration with the ones in the given profile.
+    pub(crate) fn select_profile(&mut self, name: &str) -> Result<()> {
+        let Some(profile) = self.profile.remove(name) else {
+            let names: Vec<_> = self.profile.keys().map(|name| format!("`{name}`")).collect();
+            if names.is_empty() {
+                bail!("profile `{name}` is not defined");
+            }
+            bail!("profile `{name}` is not defined; available profiles are {}", names.join(", "));
+        };
+        self.profile.clear();
+
+        // Formats are exclusive, so a format in the profile replaces the one in the base.
+        let formats = [
+            profile.json,
+            profile.lcov,
+            profile.cobertura,
+            profile.codecov,
+            profile.summary_json,
+            profile.text,
+            profile.html,
+        ];
+        if formats.contains(&Some(true)) {
+            self.json = profile.json;
+            self.lcov = profile.lcov;
+            self.cobertura = profile.cobertura;
+            self.codecov = profile.codecov;
+            self.summary_json = profile.summary_json;
+            self.text = profile.text;
+            self.html = profile.html;
+        }
+        // So are output-path and output-dir.
+        if profile.output_path.is_some() || profile.output_dir.is_some() {
+            self.output_path = profile.output_path;
+            self.output_dir = profile.output_dir;
+        }
+        self.summary_only = profile.summary_only.or(self.summary_only);
+
+        self.ignore_filename_regex =
+            profile.ignore_filename_regex.or(self.ignore_filename_regex.take());
+        self.show_instantiations = profile.show_instantiations.or(self.show_instantiations);
+        self.show_missing_lines = profile.show_missing_lines.or(self.show_missing_lines);
+        self.include_build_script = profile.include_build_script.or(self.include_build_script);
+        self.include_out_dir = profile.include_out_dir.or(self.include_out_dir);
+        self.exclude_derives = profile.exclude_derives.or(self.exclude_derives);
+        self.exclude_test_functions =
+            profile.exclude_test_functions.or(self.exclude_test_functions);
+        self.max_file_lines = profile.max_file_lines.or(self.max_file_lines);
+        self.max_file_size = profile.max_file_size.or(self.max_file_size);
+        self.demangle_keep_hashes = profile.demangle_keep_hashes.or(self.demangle_keep_hashes);
+        self.demangle_cpp = profile.demangle_cpp.or(self.demangle_cpp);
+
+        self.fail_under_functions = profile.fail_under_functions.or(self.fail_under_functions);
+        self.fail_under_lines = profile.fail_under_lines.or(self.fail_under_lines);
+        self.fail_under_regions = profile.fail_under_regions.or(self.fail_under_regions);
+        self.fail_uncovered_lines = profile.fail_uncovered_lines.or(self.fail_uncovered_lines);
+        self.fail_uncovered_regions =
+            profile.fail_uncovered_regions.or(self.fail_uncovered_regions);
+        self.fail_uncovered_functions =
+            profile.fail_uncovered_functions.or(self.fail_uncovered_functions);
+        self.path_thresholds.extend(profile.path_thresholds);
+        self.owner_thresholds.extend(profile.owner_thresholds);
+        self.precision = profile.precision.or(self.precision);
+        self.rounding = profile.rounding.or(self.rounding.take());
+
+        self.doctests = profile.doctests.or(self.doctests);
+        self.branch = profile.branch.or(self.branch);
+        self.cfg = profile.cfg.or(self.cfg.take());
+        self.external_runner = profile.external_runner.or(self.external_runner.take());
+
+        self.profraw_max_age = profile.profraw_max_age.or(self.profraw_max_age);
+        self.profraw_max_size = profile.profraw_max_size.or(self.profraw_max_size);
+
+        self.webhook_baseline = profile.webhook_baseline.or(self.webhook_baseline.take());
+        self.webhook_report_url = profile.webhook_report_url.or(self.webhook_report_url.take());
+        Ok(())
+    }
+
+    /// Returns the retention policy of profile data specified in this configuration.
+    pub(crate) fn retention(&self) -> Retention {
+        Retention {
+            // validate_keys rejects values that overflow.
+            max_age: self
+                .profraw_max_age
+                .and_then(|days| days.checked_mul(SECS_PER_DAY))
+                .map(Duration::from_secs),
+            max_size: self.profraw_max_size,
+        }
+    }
+
+    /// Applies this configuration to the arguments that are not specified on
+    /// the command line.
+    pub(crate) fn apply(self, args: &mut Args, workspace_root: &Utf8Path) {
+        let cov = &mut args.cov;
+
+        let cli_format = cov.json
+            || cov.lcov
+            || cov.cobertura
+            || cov.codecov
+            || cov.summary_json
+            || cov.exec_formatter.is_some()
+            || cov.text
+            || cov.html
+            || cov.open;
+        if !cli_format {
+            cov.json = self.json == Some(true);
+            cov.lcov = self.lcov == Some(true);
+            cov.cobertura = self.cobertura == Some(true);
+            cov.codecov = self.codecov == Some(true);
+            cov.summary_json = self.summary_json == Some(true);
+            cov.text = self.text == Some(true);
+            cov.html = self.html == Some(true);
+        }
+        let export = cov.json || cov.lcov || cov.cobertura || cov.codecov;
+        if export {
+            cov.summary_only |= self.summary_only == Some(true);
+        }
+        if cov.output_path.is_none() && cov.output_dir.is_none() {
+            if export || cov.summary_json || cov.exec_formatter.is_some() || cov.text {
+                cov.output_path = self.output_path.map(|p| workspace_root.join(p));
+            }
+            if cov.show() || cov.open {
+                cov.output_dir = self.output_dir.map(|p| workspace_root.join(p));
+            }
+        }
+
+        if cov.ignore_filename_regex.is_none() {
+            cov.ignore_filename_regex = self.ignore_filename_regex;
+        }
+        cov.show_instantiations |= self.show_instantiations == Some(true);
+        cov.show_missing_lines |= self.show_missing_lines == Some(true);
+        cov.include_build_script |= self.include_build_script == Some(true);
+        cov.include_out_dir |= self.include_out_dir == Some(true);
+        cov.exclude_derives |= self.exclude_derives == Some(true);
+        cov.exclude_test_functions |= self.exclude_test_functions == Some(true);
+        cov.max_file_lines = cov.max_file_lines.or(self.max_file_lines);
+        cov.max_file_size = cov.max_file_size.or(self.max_file_size);
+        cov.demangle_keep_hashes |= self.demangle_keep_hashes == Some(true);
+        cov.demangle_cpp |= self.demangle_cpp == Some(true);
+
+        cov.fail_under_functions = cov.fail_under_functions.or(self.fail_under_functions);
+        cov.fail_under_lines = cov.fail_under_lines.or(self.fail_under_lines);
+        cov.fail_under_regions = cov.fail_under_regions.or(self.fail_under_regions);
+        cov.fail_uncovered_lines = cov.fail_uncovered_lines.or(self.fail_uncovered_lines);
+        cov.fail_uncovered_regions = cov.fail_uncovered_regions.or(self.fail_uncovered_regions);
+        cov.fail_uncovered_functions =
+            cov.fail_uncovered_functions.or(self.fail_uncovered_functions);
+        args.path_thresholds = self.path_thresholds.into_iter().collect();
+        args.owner_thresholds = self.owner_thresholds.into_iter().collect();
+        cov.precision = cov.precision.or(self.precision);
+        // `rounding` is validated when loading the configuration.
+        cov.rounding = cov.rounding.or_else(|| self.rounding?.parse().ok());
+
+        if cov.webhook_baseline.is_none() {
+            cov.webhook_baseline = self.webhook_baseline.map(|p| workspace_root.join(p));
+        }
+        if cov.webhook_report_url.is_none() {
+            cov.webhook_report_url = self.webhook_report_url;
+        }
+
+        // Doctests are not supported by nextest and cannot be combined with
+        // target selection flags, so ignore them in those cases.
+        if self.doctests == Some(true)
+            && matches!(
+                args.subcommand,
+                Subcommand::None
+                    | Subcommand::Test
+                    | Subcommand::Report
+                    | Subcommand::Show
+                    | Subcommand::Annotate
+                    | Subcommand::Snapshot
+                    | Subcommand::ShowEnv
+            )
+            && !args.selects_targets()
+        {
+            args.doctests = true;
+        }
+        if self.branch == Some(true)
+            && matches!(
+                args.subcommand,
+                Subcommand::None
+                    | Subcommand::Test
+                    | Subcommand::Run
+                    | Subcommand::ShowEnv
+                    | Subcommand::Nextest
+                    | Subcommand::NextestArchive
+                    | Subcommand::Install
+            )
+        {
+            args.branch = true;
+        }
+        if matches!(
+            args.subcommand,
+            Subcommand::None
+                | Subcommand::Test
+                | Subcommand::Run
+                | Subcommand::ShowEnv
+                | Subcommand::Nextest
+                | Subcommand::NextestArchive
+                | Subcommand::Install
+        ) {
+            args.cfgs = self.cfg.unwrap_or_default();
+        }
+        // Flags that only make sense for `cargo test` (see cli.rs) select it
+        // over the configured command.
+        if args.external_runner.is_none()
+            && matches!(args.subcommand, Subcommand::None | Subcommand::Test)
+            && !(args.doctests
+                || args.keep_going
+                || args.fail_if_no_tests
+                || args.criterion
+                || args.runs.is_some()
+                || args.junit.is_some())
+        {
+            args.external_runner = self.external_runner;
+        }
+    }
+}
+
+/// Parses a cfg in `name` or `name="value"` form, and returns the name and the value.
+fn parse_cfg(cfg: &str) -> Result<(&str, Option<&str>)> {
+    let (name, value) = match cfg.split_once('=') {
+        Some((name, value)) => {
+            let Some(value) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+                bail!("value must be a string literal");
+            };
+            if value.contains(['"', '\\']) {
+                bail!("value may not contain quotes or backslashes");
+            }
+            (name, Some(value))
+        }
+        None => (cfg, None),
+    };
+    let mut chars = name.chars();
+    if !chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        bail!("name must be an identifier");
+    }
+    Ok((name, value))
+}
+
+/// Returns the argument of `--check-cfg` that declares the given cfg (`name` or
+/// `name="value"`) as expected.
+pub(crate) fn check_cfg(cfg: &str) -> String {
+    match parse_cfg(cfg) {
+        Ok((name, Some(value))) => format!("cfg({name},values(\"{value}\"))"),
+        _ => format!("cfg({cfg})"),
+    }
+}
+
+/// Per-package configuration in `package.metadata.llvm-cov` table in Cargo.toml.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct PackageConfig {
+    /// Exclude this package from the report (same as `--exclude-from-report`).
+    #[serde(default)]
+    pub(crate) exclude_from_report: bool,
+    /// Skip source files in this package with file paths that match the given
+    /// regular expression, in addition to `--ignore-filename-regex`.
+    pub(crate) ignore_filename_regex: Option<String>,
+    /// Fail if the function coverage of this package is less than MIN percent.
+    pub(crate) fail_under_functions: Option<f64>,
+    /// Fail if the line coverage of this package is less than MIN percent.
+    pub(crate) fail_under_lines: Option<f64>,
+    /// Fail if the region coverage of this package is less than MIN percent.
+    pub(crate) fail_under_regions: Option<f64>,
+    /// Extra flags to pass to rustc when building this package with instrumentation,
+    /// in addition to RUSTFLAGS.
+    #[serde(default)]
+    pub(crate) rustflags: Vec<String>,
+}
+
+impl PackageConfig {
+    pub(crate) fn load(package: &Package) -> Result<Option<Self>> {
+        let Some(table) = package.metadata.as_ref().and_then(|m| m.get(METADATA_KEY)) else {
+            return Ok(None);
+        };
+        let config: Self = serde_json::from_value(table.clone()).with_context(|| {
+            format!(
+                "failed to parse `package.metadata.{METADATA_KEY}` table in {}",
+                package.manifest_path
+            )
+        })?;
+        if config.ignore_filename_regex.as_deref() == Some("") {
+            bail!(
+                "empty string is not allowed in `ignore-filename-regex` of `package.metadata.{METADATA_KEY}` table in {}",
+                package.manifest_path
+            );
+        }
+        Ok(Some(config))
+    }
+
+    pub(crate) fn has_thresholds(&self) -> bool {
+        self.fail_under_functions.is_some()
+            || self.fail_under_lines.is_some()
+            || self.fail_under_regions.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        fn parse(s: &str) -> Result<LlvmCovConfig> {
+            LlvmCovConfig::validate(toml::from_str(s)?)
+        }
+
+        let config = parse(
+            r#"
+            lcov = true
+            output-path = "lcov.info"
+            ignore-filename-regex = "generated"
+            fail-under-lines = 80
+            doctests = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.lcov, Some(true));
+        assert_eq!(config.output_path.as_deref(), Some("lcov.info"));
+        assert_eq!(config.ignore_filename_regex.as_deref(), Some("generated"));
+        assert_eq!(config.fail_under_lines, Some(80.));
+        assert_eq!(config.doctests, Some(true));
+        assert_eq!(config.branch, None);
+
+        assert!(parse("unknown = true").is_err());
+        assert!(parse("json = true\nlcov = true").is_err());
+        assert!(parse("json = true\nsummary-json = true").is_err());
+        assert!(parse("output-path = \"a\"\noutput-dir = \"b\"").is_err());
+        assert!(parse("[profile.a]\njson = true\nlcov = true").is_err());
+        assert!(parse("[profile.a.profile.b]").is_err());
+        assert!(parse("webhook-url = \"https://example.com\"").is_err());
+        assert!(parse("[profile.ci]\nwebhook-url = \"https://example.com\"").is_err());
+
+        let config =
+            parse("[path-thresholds]\n\"src/safety/**\" = 100\n\"src/ui/**\" = 50").unwrap();
+        assert_eq!(
+            config.path_thresholds.into_iter().collect::<Vec<_>>(),
+            [("src/safety/**".to_owned(), 100.), ("src/ui/**".to_owned(), 50.)]
+        );
+        assert!(parse("[path-thresholds]\n\"src/[\" = 100").is_err());
+        assert!(parse("[path-thresholds]\n\"src/**\" = 101").is_err());
+        let config = parse("[owner-thresholds]\n\"@org/core\" = 80").unwrap();
+        assert_eq!(
+            config.owner_thresholds.into_iter().collect::<Vec<_>>(),
+            [("@org/core".to_owned(), 80.)]
+        );
+        assert!(parse("[owner-thresholds]\n\"@org/core\" = -1").is_err());
+        assert!(parse("precision = 1\nrounding = \"floor\"").is_ok());
+        assert!(parse("precision = 11").is_err());
+        assert!(parse("rounding = \"up\"").is_err());
+
+        let config = parse("cfg = [\"coverage_slow_paths\", 'mode=\"fast\"']").unwrap();
+        assert_eq!(config.cfg.unwrap(), ["coverage_slow_paths", "mode=\"fast\""]);
+        assert!(parse("cfg = [\"coverage\"]").is_err());
+        assert!(parse("cfg = [\"1a\"]").is_err());
+        assert!(parse("cfg = [\"a-b\"]").is_err());
+        assert!(parse("cfg = [\"a=b\"]").is_err());
+        assert!(parse("cfg = ['a=\"b\"c\"']").is_err());
+
+        let retention = parse("profraw-max-age = 3\nprofraw-max-size = 1024").unwrap().retention();
+        assert_eq!(retention.max_age, Some(Duration::from_secs(3 * 24 * 60 * 60)));
+        assert_eq!(retention.max_size, Some(1024));
+        assert!(parse("").unwrap().retention().is_empty());
+        assert!(parse("profraw-max-age = 213503982334601").is_ok());
+        assert!(parse("profraw-max-age = 213503982334602").is_err());
+    }
+
+    #[test]
+    fn check_cfg() {
+        assert_eq!(super::check_cfg("coverage_slow_paths"), "cfg(coverage_slow_paths)");
+        assert_eq!(super::check_cfg("mode=\"fast\""), "cfg(mode,values(\"fast\"))");
+    }
+
+    #[test]
+    fn select_profile() {
+        let mut config = LlvmCovConfig::validate(
+            toml::from_str(
+                r#"
+                lcov = true
+                output-path = "lcov.info"
+                fail-under-lines = 80
+                show-missing-lines = true
+
+                [profile.local]
+                html = true
+                show-missing-lines = false
+                "#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(config.clone().select_profile("ci").is_err());
+        config.select_profile("local").unwrap();
+        assert_eq!(config.lcov, None);
+        assert_eq!(config.html, Some(true));
+        assert_eq!(config.output_path.as_deref(), Some("lcov.info"));
+        assert_eq!(config.fail_under_lines, Some(80.));
+        assert_eq!(config.show_missing_lines, Some(false));
+    }
+}
@@ -1,22 +1,33 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use std::{
+    cell::{Cell, RefCell},
     ffi::OsString,
     io::{self, Write},
     path::PathBuf,
 };
 
-use anyhow::{bail, Result};
-use camino::Utf8PathBuf;
+use anyhow::{bail, Context as _, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use regex::Regex;
 
 use crate::{
+    all_reports,
     cargo::Workspace,
+    cargo_profile, clean,
     cli::{self, Args, Subcommand},
-    env,
-    metadata::{Metadata, PackageId},
+    config::{LlvmCovConfig, PackageConfig},
+    download, env,
+    json::{DataQuality, InstantiationTotals, SummaryOwner, SummaryTargetKind},
+    junit::TestResults,
+    lock,
+    metadata::{Metadata, Package, PackageId},
+    nextest_runner::TestProfile,
+    path_map::PathMap,
+    percent,
     process::ProcessBuilder,
     regex_vec::{RegexVec, RegexVecBuilder},
-    term,
+    snapshot, term, toolchain,
 };
 
 pub(crate) struct Context {
@@ -25,7 +36,16 @@ pub(crate) struct Context {
     pub(crate) args: Args,
 
     pub(crate) workspace_members: WorkspaceMembers,
+    /// Configurations in `package.metadata.llvm-cov` table of packages to be included in the report.
+    pub(crate) package_configs: Vec<(PackageId, PackageConfig)>,
+    /// Names of workspace members with `rustflags` in `package.metadata.llvm-cov`
+    /// table, and the flags.
+    pub(crate) package_rustflags: Vec<(String, Vec<String>)>,
+    /// Names of workspace members whose libraries are built without instrumentation
+    /// (workspace members not specified by --instrument).
+    pub(crate) uninstrumented: Vec<String>,
     pub(crate) build_script_re: RegexVec,
+    pub(crate) path_map: PathMap,
     pub(crate) current_dir: PathBuf,
 
     // Paths to executables.
@@ -41,18 +61,149 @@ pub(crate) struct Context {
     /// `LLVM_PROFDATA_FLAGS` environment variable to pass additional flags to llvm-profdata.
     /// (value: space-separated list)
     pub(crate) llvm_profdata_flags: Option<String>,
+    /// `SOURCE_DATE_EPOCH` environment variable to use as the time of report creation.
+    /// (value: UNIX timestamp)
+    pub(crate) source_date_epoch: Option<u64>,
+    /// Manifest directories of the dependency specified by --dep-coverage.
+    ///
+    /// These are relative to the workspace root if --remap-path-prefix is used.
+    pub(crate) dep_coverage: Vec<Utf8PathBuf>,
+    /// Manifest directories of the dependencies matched by --include-deps-matching.
+    ///
+    /// These are relative to the workspace root if --remap-path-prefix is used.
+    pub(crate) included_deps: Vec<Utf8PathBuf>,
+    /// The workspace root on the machine where the nextest archive was
+    /// created, if it differs from the current one.
+    pub(crate) archived_workspace_root: Option<Utf8PathBuf>,
+    /// Coverage collected by running each kind of target, computed before
+    /// generating the report if --summary-by-target-kind is used.
+    pub(crate) target_kinds: Vec<SummaryTargetKind>,
+    /// Coverage of the files owned by each code owner, computed before
+    /// generating the report if --summary-by-owner is used.
+    pub(crate) owners: Vec<SummaryOwner>,
+    /// Coverage summarized over the instantiations of each function, computed
+    /// before generating the report if --summary-by-instantiation is used.
+    pub(crate) instantiations: Option<InstantiationTotals>,
+    /// Profraw files written by each run if --runs is used.
+    pub(crate) runs: Vec<Vec<PathBuf>>,
+    /// Profraw files written by each test if --emit-test-contribution is used.
+    pub(crate) test_profiles: Vec<TestProfile>,
+    /// Results of tests recorded while running tests if --junit is used.
+    pub(crate) test_results: RefCell<TestResults>,
+    /// Whether tests failed while running tests with --ignore-run-fail,
+    /// --report-on-fail, or --keep-going, in which case the report is
+    /// annotated as reflecting a failing run.
+    pub(crate) tests_failed: Cell<bool>,
+    /// Checks of the profile data, computed before generating the report.
+    pub(crate) data_quality: Option<DataQuality>,
+
+    /// The lock of the target directory, held until the end of the run.
+    _lock: Option<lock::Lock>,
 }
 
 impl Context {
     pub(crate) fn new(mut args: Args) -> Result<Self> {
-        let show_env = args.subcommand == Subcommand::ShowEnv;
-        let ws = Workspace::new(&args.manifest, args.target.as_deref(), args.doctests, show_env)?;
+        let show_env = args.subcommand == Subcommand::ShowEnv && !args.show_env.separate_target_dir;
+        let mut ws = Workspace::new(
+            &args.manifest,
+            args.target.as_deref(),
+            show_env,
+            args.no_rustc_wrapper,
+        )?;
+        // `cargo llvm-cov collect` runs alongside the other invocations and only
+        // takes the lock of the profile data while merging (see collect.rs).
+        let lock = if matches!(args.subcommand, Subcommand::ShowEnv | Subcommand::Collect)
+            || args.no_lock
+        {
+            None
+        } else {
+            Some(lock::lock(&ws)?)
+        };
         cli::merge_config_to_args(&ws, &mut args.target, &mut args.verbose, &mut args.color);
+        if let Some(name) = &args.cov.since_snapshot {
+            snapshot::check(&ws, name)?;
+        }
         term::set_coloring(&mut args.color);
         term::verbose::set(args.verbose != 0);
+        if let Some((source, mut config)) = LlvmCovConfig::load(&ws.metadata)? {
+            if term::verbose() {
+                status!("Loading", "configuration from {source}");
+            }
+            if let Some(name) = &args.cov_profile {
+                config
+                    .select_profile(name)
+                    .with_context(|| format!("failed to select profile from {source}"))?;
+            }
+            clean::remove_expired(&ws, config.retention(), args.verbose > 1)
+                .context("failed to remove expired profile data")?;
+            config.apply(&mut args, &ws.metadata.workspace_root);
+        } else if args.cov_profile.is_some() {
+            bail!(
+                "--cov-profile requires llvm-cov.toml or `workspace.metadata.llvm-cov` table in \
+                 Cargo.toml"
+            );
+        }
+        cargo_profile::apply(&ws, &mut args)?;
+        percent::set(args.cov.precision, args.cov.rounding);
+        percent::set_counts(args.cov.show_counts);
+        let mut package_configs = vec![];
+        let mut package_rustflags = vec![];
+        for id in &ws.metadata.workspace_members {
+            let package = &ws.metadata.packages[id];
+            if let Some(config) = PackageConfig::load(package)? {
+                if config.exclude_from_report && !args.exclude_from_report.contains(&package.name) {
+                    args.exclude_from_report.push(package.name.clone());
+                }
+                // Packages excluded from the report are still built with instrumentation.
+                if !config.rustflags.is_empty() {
+                    package_rustflags.push((package.name.clone(), config.rustflags.clone()));
+                }
+                package_configs.push((id.clone(), config));
+            }
+        }
+        let mut uninstrumented = vec![];
+        let member_names: Vec<_> =
+            ws.metadata.workspace_members.iter().map(|id| &ws.metadata.packages[id].name).collect();
+        for (flag, names) in
+            [("--instrument", &args.instrument), ("--report-include", &args.report_include)]
+        {
+            if let Some(name) = names.iter().find(|name| !member_names.contains(name)) {
+                bail!("`{name}` specified by {flag} is not a member of the workspace");
+            }
+        }
+        if !args.instrument.is_empty() || !args.report_include.is_empty() {
+            for id in &ws.metadata.workspace_members {
+                let name = &ws.metadata.packages[id].name;
+                let instrumented = args.instrument.is_empty() || args.instrument.contains(name);
+                if !instrumented {
+                    uninstrumented.push(name.clone());
+                }
+                // The report includes only the instrumented packages by default, because
+                // test binaries of the other packages are still instrumented.
+                let reported = if args.report_include.is_empty() {
+                    instrumented
+                } else {
+                    args.report_include.contains(name)
+                };
+                if !reported && !args.exclude_from_report.contains(name) {
+                    args.exclude_from_report.push(name.clone());
+                }
+            }
+        }
+        if args.doctests {
+            ws.enable_doctests()?;
+        }
+        if args.branch && !ws.rustc_version.nightly {
+            bail!("--branch flag requires nightly toolchain; consider using `cargo +nightly llvm-cov`")
+        }
 
         args.cov.html |= args.cov.open;
-        if args.cov.output_dir.is_some() && !args.cov.show() {
+        if args.cov.output_dir.is_some()
+            && !args.cov.show()
+            && args.cov.output_types.is_empty()
+            && !args.cov.all_reports
+            && args.subcommand != Subcommand::Annotate
+        {
             // If the format flag is not specified, this flag is no-op.
             args.cov.output_dir = None;
         }
@@ -67,6 +218,9 @@ impl Context {
             } else if args.doctests {
                 warn!("--doctests option is unstable");
             }
+            if args.branch {
+                warn!("--branch option is unstable");
+            }
         }
         if args.target.is_some() {
             info!(
@@ -74,7 +228,8 @@ impl Context {
                  not be displayed because cargo does not pass RUSTFLAGS to them"
             );
         }
-        if !matches!(args.subcommand, Subcommand::Report | Subcommand::Clean)
+        if !args.subcommand.is_report_based()
+            && args.subcommand != Subcommand::Clean
             && (!args.cov.no_cfg_coverage
                 || ws.rustc_version.nightly && !args.cov.no_cfg_coverage_nightly)
         {
@@ -98,7 +253,11 @@ impl Context {
         if args.cov.output_dir.is_none() && args.cov.html {
             args.cov.output_dir = Some(ws.output_dir.clone());
         }
-        if !matches!(args.subcommand, Subcommand::Report | Subcommand::Clean)
+        if let Some(output_dir) = &args.cov.output_dir {
+            args.cov.output_dir = Some(all_reports::expand_output_dir(&ws, output_dir)?);
+        }
+        if !args.subcommand.is_report_based()
+            && args.subcommand != Subcommand::Clean
             && env::var_os("CARGO_LLVM_COV_SHOW_ENV").is_some()
         {
             warn!(
@@ -109,16 +268,23 @@ impl Context {
             );
         }
 
+        let llvm_cov_override =
+            llvm_tool_override("--llvm-cov-path", args.cov.llvm_cov_path.as_deref(), "LLVM_COV")?;
+        let llvm_profdata_override = llvm_tool_override(
+            "--llvm-profdata-path",
+            args.cov.llvm_profdata_path.as_deref(),
+            "LLVM_PROFDATA",
+        )?;
         let (llvm_cov, llvm_profdata): (PathBuf, PathBuf) = match (
-            env::var_os("LLVM_COV").map(PathBuf::from),
-            env::var_os("LLVM_PROFDATA").map(PathBuf::from),
+            llvm_cov_override,
+            llvm_profdata_override,
         ) {
             (Some(llvm_cov), Some(llvm_profdata)) => (llvm_cov, llvm_profdata),
             (llvm_cov_env, llvm_profdata_env) => {
                 if llvm_cov_env.is_some() {
-                    warn!("setting only LLVM_COV environment variable may not work properly; consider setting both LLVM_COV and LLVM_PROFDATA environment variables");
+                    warn!("setting only llvm-cov path (LLVM_COV environment variable or --llvm-cov-path flag) may not work properly; consider setting both llvm-cov and llvm-profdata paths");
                 } else if llvm_profdata_env.is_some() {
-                    warn!("setting only LLVM_PROFDATA environment variable may not work properly; consider setting both LLVM_COV and LLVM_PROFDATA environment variables");
+                    warn!("setting only llvm-profdata path (LLVM_PROFDATA environment variable or --llvm-profdata-path flag) may not work properly; consider setting both llvm-cov and llvm-profdata paths");
                 }
                 // --print target-libdir (without --target flag) returns $sysroot/lib/rustlib/$host_triple/lib
                 // llvm-tools exists in $sysroot/lib/rustlib/$host_triple/bin
@@ -127,8 +293,8 @@ impl Context {
                 let mut rustlib: PathBuf = ws.rustc_print("target-libdir")?.into();
                 rustlib.pop(); // lib
                 rustlib.push("bin");
-                let llvm_cov = rustlib.join(format!("llvm-cov{}", env::consts::EXE_SUFFIX));
-                let llvm_profdata =
+                let mut llvm_cov = rustlib.join(format!("llvm-cov{}", env::consts::EXE_SUFFIX));
+                let mut llvm_profdata =
                     rustlib.join(format!("llvm-profdata{}", env::consts::EXE_SUFFIX));
                 // Check if required tools are installed.
                 if !llvm_cov.exists() || !llvm_profdata.exists() {
@@ -136,7 +302,7 @@ impl Context {
                     let toolchain = sysroot.file_name().unwrap();
                     if cmd!("rustup", "toolchain", "list")
                         .read()
-                        .map_or(false, |t| t.contains(toolchain))
+                        .is_ok_and(|t| t.contains(toolchain))
                     {
                         // If toolchain is installed from rustup and llvm-tools-preview is not installed,
                         // suggest installing llvm-tools-preview via rustup.
@@ -170,9 +336,27 @@ impl Context {
                             ask,
                             "install the `llvm-tools-preview` component for the selected toolchain",
                         )?;
+                    } else if let Some((cov, profdata)) = toolchain::find_system_llvm_tools(&ws)? {
+                        // If toolchain is not installed from rustup, use llvm-tools
+                        // installed alongside rustc or in PATH if available.
+                        if term::verbose() {
+                            status!("Using", "{} and {}", cov.display(), profdata.display());
+                        }
+                        llvm_cov = cov;
+                        llvm_profdata = profdata;
+                    } else if env::var("CARGO_LLVM_COV_DOWNLOAD_LLVM_TOOLS")?.as_deref()
+                        == Some("true")
+                    {
+                        // If toolchain is not installed from rustup, download
+                        // llvm-tools from the Rust distribution server if opted in.
+                        let bin_dir =
+                            download::llvm_tools(&ws).context("failed to download llvm-tools")?;
+                        llvm_cov = bin_dir.join(llvm_cov.file_name().unwrap());
+                        llvm_profdata = bin_dir.join(llvm_profdata.file_name().unwrap());
                     } else {
                         bail!(
-                            "failed to find llvm-tools-preview, please install llvm-tools-preview, or set LLVM_COV and LLVM_PROFDATA environment variables",
+                            "failed to find llvm-tools-preview or llvm-cov/llvm-profdata {}in PATH, please install llvm-tools-preview, set LLVM_COV and LLVM_PROFDATA environment variables, or set CARGO_LLVM_COV_DOWNLOAD_LLVM_TOOLS=true to download llvm-tools",
+                            ws.rustc_version.llvm.as_deref().and_then(|v| v.split('.').next()).map(|major| format!("for LLVM {major} ")).unwrap_or_default()
                         );
                     }
                 }
@@ -187,6 +371,7 @@ impl Context {
         }
 
         let build_script_re = pkg_hash_re(&ws, &workspace_members.included);
+        package_configs.retain(|(id, _)| workspace_members.included.contains(id));
 
         let mut llvm_cov_flags = env::var("LLVM_COV_FLAGS")?;
         if llvm_cov_flags.is_none() {
@@ -203,12 +388,35 @@ impl Context {
             }
         }
 
+        let source_date_epoch = env::var("SOURCE_DATE_EPOCH")?
+            .map(|v| {
+                v.parse().with_context(|| {
+                    format!("SOURCE_DATE_EPOCH must be a UNIX timestamp, but found `{v}`")
+                })
+            })
+            .transpose()?;
+
+        let deps = Deps::new(&ws, &args)?;
+
+        let current_dir = env::current_dir().unwrap();
+        let mut path_map = PathMap::new(&args.cov.path_map, &current_dir, args.windows_long_paths);
+        path_map.canonicalize_paths(
+            args.cov.canonicalize_paths,
+            ws.metadata.workspace_root.as_std_path(),
+        );
+        for (from, to) in deps.path_maps {
+            path_map.push(from, to);
+        }
         Ok(Self {
             ws,
             args,
             workspace_members,
+            package_configs,
+            package_rustflags,
+            uninstrumented,
             build_script_re,
-            current_dir: env::current_dir().unwrap(),
+            path_map,
+            current_dir,
             current_exe: match env::current_exe() {
                 Ok(exe) => exe,
                 Err(e) => {
@@ -221,6 +429,19 @@ impl Context {
             llvm_profdata,
             llvm_cov_flags,
             llvm_profdata_flags,
+            source_date_epoch,
+            dep_coverage: deps.dep_coverage,
+            included_deps: deps.included,
+            archived_workspace_root: None,
+            target_kinds: vec![],
+            owners: vec![],
+            instantiations: None,
+            runs: vec![],
+            test_profiles: vec![],
+            test_results: RefCell::default(),
+            tests_failed: Cell::default(),
+            data_quality: None,
+            _lock: lock,
         })
     }
 
@@ -233,8 +454,27 @@ impl Context {
         cmd
     }
 
+    /// Returns the path on the local file system of the given source file in
+    /// the coverage data. See [`PathMap::source_path`] for more.
+    pub(crate) fn source_path(&self, filename: &str) -> Utf8PathBuf {
+        self.path_map.source_path(&self.ws.metadata.workspace_root, filename)
+    }
+
+    /// Returns the path of the given source file in the coverage data relative
+    /// to the workspace root, or `None` if the file is outside the workspace.
+    pub(crate) fn relative_source_path(&self, filename: &str) -> Option<Utf8PathBuf> {
+        let path = self.source_path(filename);
+        Some(path.strip_prefix(&self.ws.metadata.workspace_root).ok()?.to_owned())
+    }
+
     pub(crate) fn cargo(&self) -> ProcessBuilder {
-        self.ws.cargo(self.args.verbose)
+        let mut cmd = self.ws.cargo(self.args.verbose);
+        if self.args.no_rustc_wrapper {
+            // Also apply to cargo invocations other than the coverage build (e.g., cargo clean).
+            cmd.env("RUSTC_WRAPPER", "");
+            cmd.env("RUSTC_WORKSPACE_WRAPPER", "");
+        }
+        cmd
     }
 }
 
@@ -246,6 +486,103 @@ fn pkg_hash_re(ws: &Workspace, pkg_ids: &[PackageId]) -> RegexVec {
     re.build().unwrap()
 }
 
+/// Dependencies to be included in the report by --dep-coverage and --include-deps-matching.
+struct Deps {
+    /// Manifest directories of the dependency specified by --dep-coverage.
+    dep_coverage: Vec<Utf8PathBuf>,
+    /// Manifest directories of the dependencies matched by --include-deps-matching.
+    included: Vec<Utf8PathBuf>,
+    /// (FROM, TO) mappings from the paths of dependencies remapped by cargo's
+    /// trim-paths (`<name>-<version>`) to their manifest directories.
+    path_maps: Vec<(String, String)>,
+}
+
+impl Deps {
+    fn new(ws: &Workspace, args: &Args) -> Result<Self> {
+        let mut deps = Self { dep_coverage: vec![], included: vec![], path_maps: vec![] };
+        let include_deps_re = args
+            .cov
+            .include_deps_matching
+            .as_deref()
+            .map(|re| {
+                Regex::new(re).with_context(|| format!("invalid --include-deps-matching `{re}`"))
+            })
+            .transpose()?;
+        if args.cov.dep_coverage.is_none() && include_deps_re.is_none() {
+            return Ok(deps);
+        }
+
+        let metadata = Metadata::with_deps(ws.current_manifest.as_std_path(), ws.config.cargo())?;
+        // Package names are case-sensitive, but `-` and `_` are interchangeable in crate names.
+        let normalize = |s: &str| s.replace('-', "_");
+        let mut packages: Vec<_> = metadata
+            .packages
+            .iter()
+            .filter(|(id, _)| !metadata.workspace_members.contains(id))
+            .map(|(_, pkg)| pkg)
+            .collect();
+        packages.sort_unstable_by(|a, b| a.manifest_path.cmp(&b.manifest_path));
+        // Paths in the report are relative to the workspace root only if --remap-path-prefix is used.
+        let dir = |pkg: &Package| {
+            let dir = pkg.manifest_path.parent().unwrap();
+            if args.remap_path_prefix {
+                dir.strip_prefix(&metadata.workspace_root).unwrap_or(dir).to_owned()
+            } else {
+                dir.to_owned()
+            }
+        };
+        for &pkg in &packages {
+            let dep_coverage = args
+                .cov
+                .dep_coverage
+                .as_deref()
+                .is_some_and(|n| normalize(n) == normalize(&pkg.name));
+            let included = include_deps_re.as_ref().is_some_and(|re| re.is_match(&pkg.name));
+            if dep_coverage {
+                deps.dep_coverage.push(dir(pkg));
+            }
+            if included {
+                deps.included.push(dir(pkg));
+            }
+            let manifest_dir = pkg.manifest_path.parent().unwrap();
+            if (dep_coverage || included) && !manifest_dir.starts_with(&metadata.workspace_root) {
+                // The compilation directory of the dependency is remapped as
+                // well, so the remapped paths in the coverage data may have
+                // the prefix twice.
+                let remapped = format!("{}-{}", pkg.name, pkg.version);
+                deps.path_maps.push((format!("{remapped}/{remapped}"), manifest_dir.to_string()));
+                deps.path_maps.push((remapped, manifest_dir.to_string()));
+            }
+        }
+
+        if let Some(name) = &args.cov.dep_coverage {
+            if deps.dep_coverage.is_empty() {
+                if ws
+                    .metadata
+                    .workspace_members
+                    .iter()
+                    .any(|id| ws.metadata.packages[id].name == *name)
+                {
+                    bail!(
+                        "--dep-coverage requires a dependency, but `{name}` is a member of the workspace"
+                    );
+                }
+                bail!(
+                    "--dep-coverage: no dependency named `{name}` found in the dependency graph of the workspace"
+                );
+            }
+        }
+        if let Some(re) = &args.cov.include_deps_matching {
+            if deps.included.is_empty() {
+                warn!("no dependencies matched by --include-deps-matching `{re}`");
+            } else if term::verbose() {
+                info!("including {} dependencies in the report", deps.included.len());
+            }
+        }
+        Ok(deps)
+    }
+}
+
 pub(crate) struct WorkspaceMembers {
     pub(crate) excluded: Vec<PackageId>,
     pub(crate) included: Vec<PackageId>,
@@ -277,6 +614,18 @@ impl WorkspaceMembers {
 }
 
 // Adapted from https://github.com/rust-lang/miri/blob/dba35d2be72f4b78343d1a0f0b4737306f310672/cargo-miri/src/util.rs#L181-L204
+/// Returns the path to the llvm tool specified by the flag or the environment
+/// variable. The flag takes precedence over the environment variable.
+fn llvm_tool_override(flag: &str, path: Option<&Utf8Path>, var: &str) -> Result<Option<PathBuf>> {
+    match path {
+        Some(path) if !path.is_file() => {
+            bail!("{path} specified by {flag} flag does not exist or is not a file")
+        }
+        Some(path) => Ok(Some(path.into())),
+        None => Ok(env::var_os(var).map(PathBuf::from)),
+    }
+}
+
 fn ask_to_run(cmd: &mut ProcessBuilder, ask: bool, text: &str) -> Result<()> {
     // Disable interactive prompts in CI (GitHub Actions, Travis, AppVeyor, etc).
     // Azure doesn't set `CI` though (nothing to see here, just Microsoft being Microsoft),
@@ -291,8 +640,8 @@ fn ask_to_run(cmd: &mut ProcessBuilder, ask: bool, text: &str) -> Result<()> {
             // Proceed.
             "" | "y" | "yes" => {}
             "n" | "no" => bail!("aborting as per your request"),
-            a => bail!("invalid answer `{}`", a),
-        };
+            a => bail!("invalid answer `{a}`"),
+        }
     } else {
         info!("running {} to {}", cmd, text);
     }
@@ -0,0 +1,446 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Detection and recovery of coverage mappings removed from object files.
+//
+// llvm-cov reads the coverage mapping from the `__llvm_covmap` and
+// `__llvm_covfun` sections of the object files, and silently ignores object
+// files that don't have them as long as another object file has them. These
+// sections are not loaded at runtime, so tools that remove the sections not
+// loaded at runtime (e.g., `llvm-objcopy --strip-all` or post-build steps that
+// minimize binaries) remove them, and the coverage of such binaries is lost
+// without any error.
+//
+// So object files that were built with instrumentation (have the
+// `__llvm_prf_cnts` section) but have no coverage mapping are detected here.
+// On ELF platforms, the mapping is recovered from a separate debug file (e.g.,
+// created by `objcopy --only-keep-debug`), which keeps the contents of the
+// sections not loaded at runtime: a copy of the object file with the sections
+// added by llvm-objcopy is passed to llvm-cov instead. Debug files are searched
+// in the same way as gdb. The debug info split by -C split-debuginfo (dwp files
+// and dSYM bundles) doesn't contain the coverage mapping, which rustc always
+// keeps in the binary, so it is not searched.
+//
+// Refs:
+// - https://llvm.org/docs/CoverageMappingFormat.html
+// - https://sourceware.org/gdb/current/onlinedocs/gdb.html/Separate-Debug-Files.html
+
+use std::{
+    env,
+    ffi::OsString,
+    fmt::Write as _,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{context::Context, fs, per_binary, term};
+
+const COVMAP: &str = "__llvm_covmap";
+const COVFUN: &str = "__llvm_covfun";
+const PRF_CNTS: &str = "__llvm_prf_cnts";
+
+const SHT_NOBITS: u64 = 8;
+const LC_SEGMENT: u64 = 0x1;
+const LC_SEGMENT_64: u64 = 0x19;
+const S_ZEROFILL: u64 = 0x1;
+
+/// A section of an object file.
+#[derive(Debug, PartialEq, Eq)]
+struct Section {
+    name: String,
+    offset: u64,
+    size: u64,
+    /// `false` if the contents of the section are not in the file (e.g.,
+    /// sections not loaded at runtime in debug files).
+    has_data: bool,
+}
+
+/// Replaces the object files whose coverage mapping was removed with copies
+/// whose mapping is recovered from separate debug files, and returns an error
+/// if the mapping of any of them cannot be found.
+pub(crate) fn restore(cx: &Context, object_files: Vec<OsString>) -> Result<Vec<OsString>> {
+    let dir = cx.ws.target_dir.join(format!("{}-coverage-mapping", cx.ws.name));
+    let mut files = Vec::with_capacity(object_files.len());
+    let mut missing = vec![];
+    for (i, f) in object_files.into_iter().enumerate() {
+        let path = Path::new(&f);
+        let Some(sections) = fs::File::open(path).ok().and_then(|mut file| sections(&mut file))
+        else {
+            // Unsupported format (e.g., COFF).
+            files.push(f);
+            continue;
+        };
+        match sections.iter().find(|s| s.name == PRF_CNTS) {
+            // Separate debug files (see find_debug_file) are not object files
+            // that llvm-cov can read, because they don't contain the sections
+            // loaded at runtime.
+            Some(cnts) if !cnts.has_data => continue,
+            Some(_) if !has_section(&sections, COVMAP) => {}
+            _ => {
+                files.push(f);
+                continue;
+            }
+        }
+        let Some((debug_file, debug_sections)) = find_debug_file(path, &sections)? else {
+            missing.push(f);
+            continue;
+        };
+        if !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        let restored = dir.join(format!("{i}-{}", path.file_name().unwrap().to_string_lossy()));
+        let data = fs::read(&debug_file)?;
+        let mut cmd = cx.process(llvm_objcopy(cx));
+        for s in debug_sections.iter().filter(|s| s.name == COVMAP || s.name == COVFUN) {
+            let section_file = dir.join(format!("{i}{}", s.name));
+            let start = usize::try_from(s.offset)?;
+            let end = start.checked_add(usize::try_from(s.size)?);
+            let Some(contents) = end.and_then(|end| data.get(start..end)) else {
+                bail!("{} in {} is truncated", s.name, debug_file.display());
+            };
+            fs::write(&section_file, contents)?;
+            cmd.arg("--add-section").arg(format!("{}={section_file}", s.name));
+            cmd.arg("--set-section-alignment").arg(format!("{}=8", s.name));
+        }
+        cmd.arg(path).arg(&restored);
+        if term::verbose() {
+            status!("Running", "{cmd}");
+        }
+        cmd.run_with_output()?;
+        info!(
+            "coverage mapping of {} was removed, and is recovered from {}",
+            path.display(),
+            debug_file.display()
+        );
+        files.push(restored.into_string().into());
+    }
+    if !missing.is_empty() {
+        let mut msg = format!(
+            "{} object files were built with instrumentation, but have no coverage mapping:",
+            missing.len()
+        );
+        for f in &missing {
+            let _ = write!(msg, "\n    {}", Path::new(f).display());
+        }
+        let _ = write!(
+            msg,
+            "\nthe `{COVMAP}` and `{COVFUN}` sections, which are not loaded at runtime, may have \
+             been removed by stripping (e.g., `llvm-objcopy --strip-all` or a post-build step); \
+             keep these sections, keep a separate debug file created by `objcopy \
+             --only-keep-debug` and linked by `objcopy --add-gnu-debuglink`, or pass \
+             --keep-coverage-mapping if they were removed by the `strip` setting of the profile"
+        );
+        bail!("{msg}");
+    }
+    Ok(files)
+}
+
+fn llvm_objcopy(cx: &Context) -> PathBuf {
+    // llvm-objcopy is installed with llvm-cov as part of llvm-tools.
+    cx.llvm_cov.with_file_name(format!("llvm-objcopy{}", env::consts::EXE_SUFFIX))
+}
+
+fn has_section(sections: &[Section], name: &str) -> bool {
+    sections.iter().any(|s| s.name == name)
+}
+
+/// Finds the separate debug file of the given ELF binary that contains the
+/// coverage mapping, and returns the path to it and its sections.
+fn find_debug_file(
+    path: &Path,
+    object_sections: &[Section],
+) -> Result<Option<(PathBuf, Vec<Section>)>> {
+    let data = fs::read(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut candidates = vec![];
+    if let Some(link) = object_sections.iter().find(|s| s.name == ".gnu_debuglink").and_then(|s| {
+        let start = usize::try_from(s.offset).ok()?;
+        let section = data.get(start..start.checked_add(usize::try_from(s.size).ok()?)?)?;
+        // The file name is followed by NUL, padding, and the CRC.
+        let name = section.split(|&b| b == 0).next()?;
+        Some(String::from_utf8_lossy(name).into_owned()).filter(|n| !n.is_empty())
+    }) {
+        candidates.push(dir.join(&link));
+        candidates.push(dir.join(".debug").join(&link));
+        if let Ok(abs) = fs::canonicalize(dir) {
+            let relative = abs.strip_prefix("/").unwrap_or(&abs);
+            candidates.push(Path::new("/usr/lib/debug").join(relative).join(&link));
+        }
+    }
+    if let Some(id) = per_binary::build_id(&data).filter(|id| id.len() > 2) {
+        candidates.push(
+            Path::new("/usr/lib/debug/.build-id")
+                .join(&id[..2])
+                .join(format!("{}.debug", &id[2..])),
+        );
+    }
+    let mut debug = path.as_os_str().to_owned();
+    debug.push(".debug");
+    candidates.push(debug.into());
+
+    for candidate in candidates {
+        let Some(sections) = fs::File::open(&candidate).ok().and_then(|mut f| sections(&mut f))
+        else {
+            continue;
+        };
+        if sections.iter().any(|s| s.name == COVMAP && s.has_data) {
+            return Ok(Some((candidate, sections)));
+        }
+    }
+    Ok(None)
+}
+
+fn read_at(file: &mut (impl Read + Seek), offset: u64, len: usize) -> Option<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut buf = vec![0; len];
+    file.read_exact(&mut buf).ok()?;
+    Some(buf)
+}
+
+/// Reads an unsigned integer of the given size (1-8 bytes) at the given offset.
+fn int(data: &[u8], offset: usize, size: usize, little_endian: bool) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(size)?)?;
+    let mut buf = [0; 8];
+    Some(if little_endian {
+        buf[..size].copy_from_slice(bytes);
+        u64::from_le_bytes(buf)
+    } else {
+        buf[8 - size..].copy_from_slice(bytes);
+        u64::from_be_bytes(buf)
+    })
+}
+
+/// Returns the sections of the given ELF or Mach-O object file, or `None` if
+/// the file is in another format.
+///
+/// Only the headers are read, because object files can be large.
+fn sections(file: &mut (impl Read + Seek)) -> Option<Vec<Section>> {
+    let magic = read_at(file, 0, 4)?;
+    if magic == b"\x7fELF" {
+        elf_sections(file)
+    } else if matches!(&*magic, b"\xcf\xfa\xed\xfe" | b"\xce\xfa\xed\xfe") {
+        macho_sections(file)
+    } else {
+        None
+    }
+}
+
+// https://refspecs.linuxfoundation.org/elf/gabi4+/ch4.eheader.html
+// https://refspecs.linuxfoundation.org/elf/gabi4+/ch4.sheader.html
+fn elf_sections(file: &mut (impl Read + Seek)) -> Option<Vec<Section>> {
+    let header = read_at(file, 0, 0x34)?;
+    let word = match header[4] {
+        1 => 4,
+        2 => 8,
+        _ => return None,
+    };
+    let little_endian = match header[5] {
+        1 => true,
+        2 => false,
+        _ => return None,
+    };
+    let header = if word == 8 { read_at(file, 0, 0x40)? } else { header };
+    let read = |data: &[u8], offset: usize, size: usize| int(data, offset, size, little_endian);
+    let (shoff, shentsize, shnum, shstrndx) = if word == 8 {
+        (
+            read(&header, 0x28, 8)?,
+            read(&header, 0x3A, 2)?,
+            read(&header, 0x3C, 2)?,
+            read(&header, 0x3E, 2)?,
+        )
+    } else {
+        (
+            read(&header, 0x20, 4)?,
+            read(&header, 0x2E, 2)?,
+            read(&header, 0x30, 2)?,
+            read(&header, 0x32, 2)?,
+        )
+    };
+    let (shentsize, shnum) = (usize::try_from(shentsize).ok()?, usize::try_from(shnum).ok()?);
+    if shentsize < 8 + word * 4 {
+        return None;
+    }
+    let table = read_at(file, shoff, shentsize.checked_mul(shnum)?)?;
+    // (sh_name, sh_type, sh_offset, sh_size)
+    let headers = (0..shnum)
+        .map(|i| {
+            let sh = i * shentsize;
+            Some((
+                read(&table, sh, 4)?,
+                read(&table, sh + 4, 4)?,
+                read(&table, sh + 8 + word * 2, word)?,
+                read(&table, sh + 8 + word * 3, word)?,
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let &(_, _, strtab_offset, strtab_size) = headers.get(usize::try_from(shstrndx).ok()?)?;
+    let strtab = read_at(file, strtab_offset, usize::try_from(strtab_size).ok()?)?;
+    headers
+        .into_iter()
+        .map(|(name, ty, offset, size)| {
+            let name = strtab.get(usize::try_from(name).ok()?..)?;
+            let name = name.split(|&b| b == 0).next()?;
+            Some(Section {
+                name: String::from_utf8_lossy(name).into_owned(),
+                offset,
+                size,
+                has_data: ty != SHT_NOBITS,
+            })
+        })
+        .collect()
+}
+
+// https://github.com/apple-oss-distributions/xnu/blob/main/EXTERNAL_HEADERS/mach-o/loader.h
+fn macho_sections(file: &mut (impl Read + Seek)) -> Option<Vec<Section>> {
+    let header = read_at(file, 0, 32)?;
+    let is_64 = header[0] == 0xcf;
+    let read = |data: &[u8], offset: usize, size: usize| int(data, offset, size, true);
+    let ncmds = read(&header, 16, 4)?;
+    let sizeofcmds = usize::try_from(read(&header, 20, 4)?).ok()?;
+    let cmds = read_at(file, if is_64 { 32 } else { 28 }, sizeofcmds)?;
+    let mut sections = vec![];
+    let mut pos = 0;
+    for _ in 0..ncmds {
+        let (cmd, cmdsize) =
+            (read(&cmds, pos, 4)?, usize::try_from(read(&cmds, pos + 4, 4)?).ok()?);
+        // (offset of nsects, offset of the first section, size of a section, size of addr and size)
+        let layout = match cmd {
+            LC_SEGMENT_64 => Some((64, 72, 80, 8)),
+            LC_SEGMENT => Some((48, 56, 68, 4)),
+            _ => None,
+        };
+        if let Some((nsects, first, sectsize, word)) = layout {
+            for i in 0..usize::try_from(read(&cmds, pos + nsects, 4)?).ok()? {
+                let s = pos + first + i * sectsize;
+                let name = cmds.get(s..s + 16)?;
+                let name = name.split(|&b| b == 0).next()?;
+                let offset = read(&cmds, s + 32 + word * 2, 4)?;
+                let flags = read(&cmds, s + 32 + word * 2 + 4 * 4, 4)?;
+                sections.push(Section {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    offset,
+                    size: read(&cmds, s + 32 + word, word)?,
+                    has_data: flags & 0xff != S_ZEROFILL && offset != 0,
+                });
+            }
+        }
+        if cmdsize == 0 {
+            return None;
+        }
+        pos = pos.checked_add(cmdsize)?;
+    }
+    Some(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Returns a minimal ELF file with sections of the given names and types.
+    fn elf(is_64: bool, little_endian: bool, sections: &[(&str, usize)]) -> Vec<u8> {
+        let word = if is_64 { 8 } else { 4 };
+        let int = |v: usize, size: usize| -> Vec<u8> {
+            let v = v as u64;
+            if little_endian {
+                v.to_le_bytes()[..size].to_vec()
+            } else {
+                v.to_be_bytes()[8 - size..].to_vec()
+            }
+        };
+        let header_size = if is_64 { 0x40 } else { 0x34 };
+        let shentsize = 8 + word * 4 + 8 + word * 2;
+        let mut strtab = vec![0];
+        let mut names = vec![];
+        for (name, _) in sections.iter().chain(&[(".shstrtab", 3)]) {
+            names.push(strtab.len());
+            strtab.extend(name.as_bytes());
+            strtab.push(0);
+        }
+        let shoff = header_size + strtab.len();
+        let mut data = vec![0x7f, b'E', b'L', b'F', if is_64 { 2 } else { 1 }];
+        data.push(if little_endian { 1 } else { 2 });
+        data.resize(if is_64 { 0x28 } else { 0x20 }, 0);
+        data.extend(int(shoff, word));
+        data.resize(if is_64 { 0x3A } else { 0x2E }, 0);
+        data.extend(int(shentsize, 2));
+        data.extend(int(sections.len() + 1, 2));
+        data.extend(int(sections.len(), 2));
+        data.resize(header_size, 0);
+        data.extend(&strtab);
+        for (i, &(_, ty)) in sections.iter().chain(&[(".shstrtab", 3)]).enumerate() {
+            let (offset, size) =
+                if i == sections.len() { (header_size, strtab.len()) } else { (i, 1) };
+            data.extend(int(names[i], 4));
+            data.extend(int(ty, 4));
+            data.resize(data.len() + word * 2, 0);
+            data.extend(int(offset, word));
+            data.extend(int(size, word));
+            data.resize(data.len() + 8 + word * 2, 0);
+        }
+        data
+    }
+
+    /// Returns a minimal 64-bit Mach-O file with a segment that contains
+    /// sections of the given names and offsets.
+    fn macho(sections: &[(&str, u32)]) -> Vec<u8> {
+        let nsects = u32::try_from(sections.len()).unwrap();
+        let mut cmd = vec![];
+        cmd.extend(0x19_u32.to_le_bytes());
+        cmd.extend((72 + 80 * nsects).to_le_bytes());
+        cmd.resize(64, 0);
+        cmd.extend(nsects.to_le_bytes());
+        cmd.resize(72, 0);
+        for &(name, offset) in sections {
+            let mut section = name.as_bytes().to_vec();
+            section.resize(40, 0);
+            section.extend(16_u64.to_le_bytes());
+            section.extend(offset.to_le_bytes());
+            section.resize(80, 0);
+            cmd.extend(section);
+        }
+        let mut data = vec![0xcf, 0xfa, 0xed, 0xfe];
+        data.resize(16, 0);
+        data.extend(1_u32.to_le_bytes());
+        data.extend(u32::try_from(cmd.len()).unwrap().to_le_bytes());
+        data.resize(32, 0);
+        data.extend(cmd);
+        data
+    }
+
+    fn names(data: Vec<u8>) -> Option<Vec<(String, bool)>> {
+        Some(sections(&mut Cursor::new(data))?.into_iter().map(|s| (s.name, s.has_data)).collect())
+    }
+
+    #[test]
+    fn elf_sections() {
+        for is_64 in [true, false] {
+            for little_endian in [true, false] {
+                let data = elf(is_64, little_endian, &[(PRF_CNTS, 1), (COVMAP, 8)]);
+                assert_eq!(
+                    names(data),
+                    Some(vec![
+                        (PRF_CNTS.to_owned(), true),
+                        (COVMAP.to_owned(), false),
+                        (".shstrtab".to_owned(), true)
+                    ]),
+                    "is_64={is_64}, little_endian={little_endian}"
+                );
+            }
+        }
+        let mut data = elf(true, true, &[(PRF_CNTS, 1)]);
+        data.truncate(data.len() - 1);
+        assert_eq!(names(data), None);
+    }
+
+    #[test]
+    fn macho_sections() {
+        assert_eq!(
+            names(macho(&[(PRF_CNTS, 0x1000), (COVMAP, 0)])),
+            Some(vec![(PRF_CNTS.to_owned(), true), (COVMAP.to_owned(), false)])
+        );
+        assert_eq!(names(b"MZ\x90\x00".to_vec()), None);
+    }
+}
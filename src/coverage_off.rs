@@ -0,0 +1,224 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --coverage-off-json support (also used by --show-excluded).
+//
+// Items with `#[coverage(off)]` (or `#[no_coverage]` in old nightlies) are not
+// instrumented, so they never appear in the coverage data. This finds the
+// attributes in the source files of the workspace members instead, so teams
+// can review the growth of explicitly excluded code. This is a line-based
+// heuristic: an outer attribute is attributed to the next `fn`, `impl`, `mod`,
+// or `trait` item (skipping other attributes and comments), and an inner
+// attribute (e.g., `#![coverage(off)]`) excludes the enclosing module or file.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use camino::Utf8PathBuf;
+use regex::Regex;
+use walkdir::WalkDir;
+
+use crate::{
+    context::Context,
+    fs,
+    json::{CoverageOffExport, CoverageOffItem, COVERAGE_OFF_SCHEMA_VERSION},
+};
+
+/// Collects the items excluded by `#[coverage(off)]` from the source files of
+/// the workspace members, except for files excluded from the report.
+pub(crate) fn collect(
+    cx: &Context,
+    ignore_filename_regex: Option<&str>,
+) -> Result<CoverageOffExport> {
+    let ignore = ignore_filename_regex.map(Regex::new).transpose()?;
+    let metadata = &cx.ws.metadata;
+    let mut files = BTreeSet::new();
+    for id in &cx.workspace_members.included {
+        let dir = metadata.packages[id].manifest_path.parent().unwrap();
+        let walker = WalkDir::new(dir).into_iter().filter_entry(|e| {
+            // Skip the target directory, hidden directories, and nested
+            // packages (nested workspace members are walked separately).
+            let nested_package = e.file_type().is_dir() && e.path().join("Cargo.toml").is_file();
+            e.depth() == 0
+                || !(e.file_name().to_str().is_none_or(|n| n.starts_with('.'))
+                    || e.path() == metadata.target_directory
+                    || nested_package)
+        });
+        for entry in walker.filter_map(Result::ok) {
+            if entry.file_type().is_file() && entry.path().extension().is_some_and(|e| e == "rs") {
+                let Ok(path) = Utf8PathBuf::from_path_buf(entry.into_path()) else { continue };
+                files.insert(path);
+            }
+        }
+    }
+
+    let item_re = item_regex();
+    let mut items = vec![];
+    for path in files {
+        if ignore.as_ref().is_some_and(|re| re.is_match(path.as_str())) {
+            continue;
+        }
+        let source = fs::read_to_string(&path)?;
+        let file = path.strip_prefix(&metadata.workspace_root).unwrap_or(&path);
+        for (line, kind, name, attribute) in scan(&source, &item_re) {
+            items.push(CoverageOffItem {
+                file: file.as_str().to_owned(),
+                line,
+                kind: kind.to_owned(),
+                name,
+                attribute,
+            });
+        }
+    }
+    items.sort();
+    Ok(CoverageOffExport { schema_version: COVERAGE_OFF_SCHEMA_VERSION, items })
+}
+
+fn item_regex() -> Regex {
+    Regex::new(concat!(
+        r#"^(?:pub(?:\([^)]*\))?[ \t]+)?"#,
+        r#"(?:(?:default|const|async|unsafe|extern(?:[ \t]+"[^"]*")?)[ \t]+)*"#,
+        r#"(fn|impl|mod|trait)([ \t<].*)?$"#,
+    ))
+    .unwrap()
+}
+
+/// Returns (line, kind, name, attribute) of each item excluded in the given source.
+fn scan(source: &str, item_re: &Regex) -> Vec<(u64, &'static str, String, String)> {
+    let mut found = vec![];
+    let mut pending: Option<(u64, String)> = None;
+    for (line, text) in (1..).zip(source.lines()) {
+        let mut rest = text.trim();
+        while let Some(len) = attribute_len(rest) {
+            let attribute = &rest[..len];
+            if is_coverage_off(attribute) {
+                if attribute.starts_with("#!") {
+                    found.push((line, "inner", String::new(), attribute.to_owned()));
+                } else if pending.is_none() {
+                    pending = Some((line, attribute.to_owned()));
+                }
+            }
+            rest = rest[len..].trim_start();
+        }
+        if pending.is_none() || rest.is_empty() || rest.starts_with("//") {
+            continue;
+        }
+        let (line, attribute) = pending.take().unwrap();
+        let Some(caps) = item_re.captures(rest) else { continue };
+        let kind = match &caps[1] {
+            "fn" => "fn",
+            "impl" => "impl",
+            "mod" => "mod",
+            _ => "trait",
+        };
+        let name = item_name(kind, caps.get(2).map_or("", |m| m.as_str()));
+        found.push((line, kind, name, attribute));
+    }
+    found
+}
+
+/// Returns the length of the attribute at the start of `s`.
+fn attribute_len(s: &str) -> Option<usize> {
+    if !s.starts_with("#[") && !s.starts_with("#![") {
+        return None;
+    }
+    let mut depth = 0_usize;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_coverage_off(attribute: &str) -> bool {
+    let attribute: String = attribute.chars().filter(|c| !c.is_whitespace()).collect();
+    // `#![feature(no_coverage)]` enables the attribute, and doesn't exclude anything.
+    attribute.contains("coverage(off)")
+        || !attribute.contains("feature(")
+            && attribute
+                .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .any(|token| token == "no_coverage")
+}
+
+fn item_name(kind: &str, rest: &str) -> String {
+    let rest = rest.trim_start();
+    if kind != "impl" {
+        let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+        return rest[..end].to_owned();
+    }
+    // Skip the generic parameters of impl (e.g., `impl<T> Trait for Foo<T>`).
+    let mut rest = rest;
+    if rest.starts_with('<') {
+        let mut depth = 0_usize;
+        for (i, c) in rest.char_indices() {
+            match c {
+                '<' => depth += 1,
+                '>' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        rest = &rest[i + 1..];
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    let end = [rest.find('{'), rest.find(" where")].into_iter().flatten().min();
+    rest[..end.unwrap_or(rest.len())].trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_attributes() {
+        let source = "#![cfg_attr(coverage_nightly, feature(coverage_attribute))]
+#![cfg_attr(coverage_nightly, feature(no_coverage))]
+
+#[coverage(off)]
+fn a() {}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Doc comment.
+#[inline]
+pub(crate) async unsafe fn b<'a>(x: &'a str) {}
+
+#[no_coverage] fn c() {}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<T: Clone> Foo for Bar<T> where T: Copy {
+    fn d() {}
+}
+
+#[coverage(off)]
+let closure = || {};
+
+#[cfg_attr(coverage_nightly, coverage(on))]
+fn e() {}
+
+mod tests {
+    #![coverage(off)]
+}
+";
+        let found = scan(source, &item_regex());
+        let found: Vec<_> =
+            found.iter().map(|(line, kind, name, _)| (*line, *kind, name.as_str())).collect();
+        assert_eq!(found, [
+            (4, "fn", "a"),
+            (7, "fn", "b"),
+            (12, "fn", "c"),
+            (14, "impl", "Foo for Bar<T>"),
+            (26, "inner", ""),
+        ]);
+        assert_eq!(scan("#[coverage(off)]\nfn a() {}", &item_regex())[0].3, "#[coverage(off)]");
+    }
+}
@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// `cargo llvm-cov coveralls-finish` support.
+//
+// When the Coveralls JSON written by --output-types has `parallel: true` (set
+// by COVERALLS_PARALLEL=true), Coveralls keeps the build open and combines the
+// jobs uploaded with the same build number. This sends the webhook request
+// that closes the build, which is done once after all jobs have been uploaded
+// (e.g., in a job that depends on the jobs of a CI matrix). This doesn't need
+// a workspace, so it can be called from a job that doesn't check out the
+// repository.
+//
+// The request body is passed to curl via stdin, so the repository token
+// doesn't appear in the command line (or errors that contain it).
+//
+// Refs:
+// - https://docs.coveralls.io/parallel-builds
+
+use anyhow::{format_err, Context as _, Result};
+use serde_derive::Serialize;
+
+use crate::{cli::Args, env, term};
+
+const DEFAULT_ENDPOINT: &str = "https://coveralls.io";
+
+#[derive(Serialize)]
+struct Webhook {
+    repo_token: String,
+    payload: WebhookPayload,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload {
+    build_num: String,
+    status: &'static str,
+}
+
+pub(crate) fn run(args: &mut Args) -> Result<()> {
+    term::set_coloring(&mut args.color);
+
+    let webhook = Webhook {
+        repo_token: env::var("COVERALLS_REPO_TOKEN")?.ok_or_else(|| {
+            format_err!("`cargo llvm-cov coveralls-finish` requires COVERALLS_REPO_TOKEN")
+        })?,
+        payload: WebhookPayload {
+            build_num: env::var("COVERALLS_SERVICE_NUMBER")?.ok_or_else(|| {
+                format_err!(
+                    "`cargo llvm-cov coveralls-finish` requires the build number of the \
+                     parallel jobs (COVERALLS_SERVICE_NUMBER)"
+                )
+            })?,
+            status: "done",
+        },
+    };
+    let endpoint = env::var("COVERALLS_ENDPOINT")?;
+    let url = webhook_url(endpoint.as_deref().unwrap_or(DEFAULT_ENDPOINT));
+    status!("Finishing", "parallel build {} on {url}", webhook.payload.build_num);
+    let output = crate::download::curl(&url)
+        .args(["-X", "POST", "-H", "Content-Type: application/json", "--data-binary", "@-"])
+        .run_with_stdin(serde_json::to_vec(&webhook)?)
+        .context("failed to close the parallel build on Coveralls")?;
+    info!("response: {}", String::from_utf8_lossy(&output.stdout).trim());
+    Ok(())
+}
+
+fn webhook_url(endpoint: &str) -> String {
+    format!("{}/webhook", endpoint.trim_end_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webhook() {
+        assert_eq!(webhook_url("https://coveralls.io"), "https://coveralls.io/webhook");
+        assert_eq!(
+            webhook_url("https://coveralls.example.com/"),
+            "https://coveralls.example.com/webhook"
+        );
+        let webhook = Webhook {
+            repo_token: "token".to_owned(),
+            payload: WebhookPayload { build_num: "42".to_owned(), status: "done" },
+        };
+        assert_eq!(
+            serde_json::to_string(&webhook).unwrap(),
+            r#"{"repo_token":"token","payload":{"build_num":"42","status":"done"}}"#
+        );
+    }
+}
@@ -0,0 +1,215 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Checks of the profile data the report is generated from.
+//
+// The numbers in the report are only as good as the profile data, and some
+// problems with it are silently ignored by llvm-profdata and llvm-cov:
+//
+// - Empty profraw files are ignored by llvm-profdata merge. The profiler
+//   runtime creates the file before writing profile data, so an empty file
+//   means the process was terminated in the meantime or the write failed.
+// - Profile data of a function whose structural hash doesn't match the one in
+//   the coverage mapping of the binary is dropped by llvm-cov. This happens when
+//   the binary was rebuilt after the profile data was generated. Since LLVM 15,
+//   profraw files record the build IDs of the binaries that generated them, so
+//   this checks them against the binaries in the target directory.
+// - llvm-profdata saturates counters that overflow when merging, and corrupted
+//   profile data may contain arbitrary counter values. Both result in counter
+//   values that are implausibly large for any real run.
+//
+// Partially written profraw files are rejected by llvm-profdata merge, and
+// --quarantine moves them to the quarantine directory.
+//
+// Refs:
+// - https://clang.llvm.org/docs/SourceBasedCodeCoverage.html#running-the-instrumented-program
+// - https://llvm.org/docs/CommandGuide/llvm-profdata.html#profdata-show
+
+use std::{collections::BTreeSet, ffi::OsString, fmt::Write as _, io::Read as _, path::Path};
+
+use anyhow::{Context as _, Result};
+use walkdir::WalkDir;
+
+use crate::{context::Context, fs, json::DataQuality, per_binary, toolchain};
+
+/// Counter values greater than or equal to this are considered implausible.
+///
+/// Even a loop incrementing a counter at 10 GHz takes about 29 years to reach
+/// this value.
+const IMPLAUSIBLE_COUNT: u64 = 1 << 63;
+
+/// Checks the profile data merged into `cx.ws.profdata_file`, and warns about
+/// the problems found.
+pub(crate) fn check(cx: &Context, object_files: &[OsString]) -> Result<DataQuality> {
+    let profraw_files = crate::profraw_files(cx)?;
+    let mut quality =
+        DataQuality { profraw_files: profraw_files.len() as u64, ..DataQuality::default() };
+
+    for f in &profraw_files {
+        if fs::metadata(f)?.len() == 0 {
+            quality.empty_files.push(f.display().to_string());
+        }
+    }
+    if cx.ws.quarantine_dir.is_dir() {
+        for entry in fs::read_dir(&cx.ws.quarantine_dir)?.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "profraw") {
+                quality.quarantined_files.push(path.display().to_string());
+            }
+        }
+        quality.quarantined_files.sort_unstable();
+    }
+    quality.unknown_binary_files = unknown_binary_files(cx, &profraw_files, object_files);
+    if cx.ws.profdata_file.exists() {
+        quality.max_count = max_count(cx).context("failed to read profile data summary")?;
+        quality.implausible_counts = quality.max_count >= IMPLAUSIBLE_COUNT;
+    }
+
+    if !quality.empty_files.is_empty() {
+        warn!(
+            "{} profraw files are empty and were ignored; this may occur if processes were \
+             terminated before writing profile data, or failed to write it",
+            quality.empty_files.len()
+        );
+    }
+    if !quality.unknown_binary_files.is_empty() {
+        warn!(
+            "{} profraw files were generated by binaries that no longer exist in the target \
+             directory; this may occur if binaries were rebuilt or removed after running them, in which \
+             case the profile data doesn't match the current binaries and is partially or \
+             entirely ignored, or if they were generated by binaries outside the target \
+             directory, which can be passed by --object",
+            quality.unknown_binary_files.len()
+        );
+    }
+    if quality.implausible_counts {
+        warn!(
+            "profile data contains an implausibly large counter value ({}); this may occur if \
+             counters overflowed or profile data is corrupted",
+            quality.max_count
+        );
+    }
+    Ok(quality)
+}
+
+/// Returns the profraw files that record binary IDs, none of which matches the
+/// build IDs of the binaries in the target directory.
+///
+/// The object files are checked first, and the rest of the target directory
+/// (e.g., build scripts, which are not included in the report by default) is
+/// only searched if some profraw files don't match any of them.
+///
+/// This returns an empty list if the binary IDs are not recorded in the
+/// profile data (e.g., the platform doesn't use ELF).
+fn unknown_binary_files(
+    cx: &Context,
+    profraw_files: &[impl AsRef<Path>],
+    object_files: &[OsString],
+) -> Vec<String> {
+    let mut profraw_ids = vec![];
+    for f in profraw_files {
+        let f = f.as_ref();
+        match toolchain::raw_profile_binary_ids(f) {
+            Some(ids) if !ids.is_empty() => profraw_ids.push((f, ids)),
+            _ => {}
+        }
+    }
+    let mut build_ids = BTreeSet::new();
+    let mut retain_unknown = |build_ids: &BTreeSet<String>| {
+        profraw_ids.retain(|(_, ids)| !ids.iter().any(|id| build_ids.contains(id)));
+        !profraw_ids.is_empty()
+    };
+    if !retain_unknown(&build_ids) {
+        return vec![];
+    }
+    build_ids.extend(object_files.iter().filter_map(|f| read_build_id(Path::new(f))));
+    if build_ids.is_empty() || !retain_unknown(&build_ids) {
+        return vec![];
+    }
+    let object_files: BTreeSet<_> = object_files.iter().map(|f| cx.current_dir.join(f)).collect();
+    let entries = WalkDir::new(&cx.ws.target_dir).into_iter().filter_entry(|e| {
+        // Ignore incremental compilation related files and output from build scripts.
+        !e.file_type().is_dir()
+            || e.file_name() != "incremental"
+                && e.file_name() != ".fingerprint"
+                && e.file_name() != "out"
+    });
+    for entry in entries.filter_map(Result::ok) {
+        if entry.file_type().is_file() && !object_files.contains(entry.path()) {
+            build_ids.extend(read_build_id(entry.path()));
+        }
+    }
+    retain_unknown(&build_ids);
+    profraw_ids.into_iter().map(|(f, _)| f.display().to_string()).collect()
+}
+
+/// Returns the build ID of the given file if it is an ELF binary.
+fn read_build_id(path: &Path) -> Option<String> {
+    // Check the magic number first to avoid reading large non-ELF files (e.g., rlib).
+    let mut magic = [0; 4];
+    fs::File::open(path).ok()?.read_exact(&mut magic).ok()?;
+    if magic != *b"\x7fELF" {
+        return None;
+    }
+    per_binary::build_id(&fs::read(path).ok()?)
+}
+
+/// Returns the largest counter value in the merged profile data.
+fn max_count(cx: &Context) -> Result<u64> {
+    let out = cx.process(&cx.llvm_profdata).arg("show").arg(cx.ws.profdata_file.as_str()).read()?;
+    Ok(parse_max_count(&out))
+}
+
+/// Parses the output of `llvm-profdata show`.
+fn parse_max_count(out: &str) -> u64 {
+    out.lines()
+        .filter_map(|l| {
+            let (key, value) = l.split_once(':')?;
+            if key == "Maximum function count" || key == "Maximum internal block count" {
+                value.trim().parse::<u64>().ok()
+            } else {
+                None
+            }
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns the data quality section printed after the summary table, or `None`
+/// if no problems were found.
+pub(crate) fn table(quality: &DataQuality) -> Option<String> {
+    if quality.is_ok() {
+        return None;
+    }
+    let mut out = String::from("Data quality:");
+    let _ = write!(out, "\n    profraw files: {}", quality.profraw_files);
+    for (name, files) in [
+        ("empty profraw files", &quality.empty_files),
+        ("quarantined profraw files", &quality.quarantined_files),
+        ("profraw files of unknown binaries", &quality.unknown_binary_files),
+    ] {
+        if !files.is_empty() {
+            let _ = write!(out, "\n    {name}: {}", files.len());
+        }
+    }
+    let _ = write!(
+        out,
+        "\n    maximum counter value: {}{}",
+        quality.max_count,
+        if quality.implausible_counts { " (implausible)" } else { "" }
+    );
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_count() {
+        let out = "Instrumentation level: Front-end\nTotal functions: 2\nMaximum function count: \
+                   1\nMaximum internal block count: 3\nTotal number of blocks: 3\nTotal count: 2";
+        assert_eq!(parse_max_count(out), 3);
+        assert_eq!(parse_max_count("Maximum function count: 18446744073709551615"), u64::MAX);
+        assert_eq!(parse_max_count(""), 0);
+    }
+}
@@ -11,13 +11,15 @@
 // - https://github.com/rust-lang/rust/blob/1.70.0/LICENSE-MIT
 
 use std::{
-    io::{self, Read, Write},
-    str::Lines,
+    borrow::Cow,
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, BufRead, BufWriter, Write},
 };
 
-use anyhow::Result;
+use anyhow::{bail, Context as _, Result};
 use regex::Regex;
-use rustc_demangle::demangle;
+use rustc_demangle::try_demangle;
 
 const REPLACE_COLONS: &str = "::";
 
@@ -25,23 +27,175 @@ fn create_disambiguator_re() -> Regex {
     Regex::new(r"\[[0-9a-f]{5,16}\]::").unwrap()
 }
 
-fn demangle_lines(lines: Lines<'_>) -> Vec<String> {
-    let strip_crate_disambiguators = create_disambiguator_re();
-    let mut demangled_lines = vec![];
-    for mangled in lines {
-        let mut demangled = demangle(mangled).to_string();
-        demangled = strip_crate_disambiguators.replace_all(&demangled, REPLACE_COLONS).to_string();
-        demangled_lines.push(demangled);
+/// C++ demangler commands, in order of preference.
+const CPP_DEMANGLERS: &[&str] = &["llvm-cxxfilt", "c++filt"];
+
+/// Demangles symbol names in the same way as `rust-demangler`.
+///
+/// By default, hashes in Rust symbol names (crate disambiguators of v0
+/// symbols and the hash suffix of legacy symbols) are stripped. C++ symbols
+/// (e.g., of C++ code linked via FFI) are left as is unless C++ demangling is
+/// enabled and the symbols are passed to [`prepare_cpp`](Self::prepare_cpp).
+pub(crate) struct Demangler {
+    /// `None` if hashes are kept.
+    strip_crate_disambiguators: Option<Regex>,
+    /// Demangled names of C++ symbols, or `None` if C++ demangling is disabled.
+    cpp: Option<HashMap<String, String>>,
+}
+
+impl Demangler {
+    pub(crate) fn new(keep_hashes: bool, cpp: bool) -> Self {
+        Self {
+            strip_crate_disambiguators: (!keep_hashes).then(create_disambiguator_re),
+            cpp: cpp.then(HashMap::new),
+        }
+    }
+
+    pub(crate) fn demangle(&self, mangled: &str) -> String {
+        let Ok(demangled) = try_demangle(mangled) else {
+            return match self.cpp.as_ref().and_then(|cpp| cpp.get(mangled)) {
+                Some(demangled) => demangled.clone(),
+                None => mangled.to_owned(),
+            };
+        };
+        let Some(strip_crate_disambiguators) = &self.strip_crate_disambiguators else {
+            return demangled.to_string();
+        };
+        // The alternate format omits the hash of legacy symbols. (It also omits
+        // the crate disambiguators of v0 symbols, but changes other parts of
+        // them such as the types of const generic arguments.)
+        let demangled = if is_legacy_symbol(mangled) {
+            format!("{demangled:#}")
+        } else {
+            demangled.to_string()
+        };
+        strip_crate_disambiguators.replace_all(&demangled, REPLACE_COLONS).into_owned()
+    }
+
+    /// Demangles C++ symbols in the given names by llvm-cxxfilt (or c++filt)
+    /// at once, so that [`demangle`](Self::demangle) can demangle them.
+    ///
+    /// This is no-op if C++ demangling is disabled.
+    pub(crate) fn prepare_cpp<'a>(
+        &mut self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<()> {
+        let Some(cpp) = &mut self.cpp else { return Ok(()) };
+        let mut symbols: Vec<&'a str> = names
+            .into_iter()
+            .filter(|name| {
+                is_cpp_symbol(name) && try_demangle(name).is_err() && !cpp.contains_key(*name)
+            })
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+        if symbols.is_empty() {
+            return Ok(());
+        }
+        let mut input = symbols.join("\n");
+        input.push('\n');
+        let mut last_error = None;
+        for program in CPP_DEMANGLERS {
+            match cmd!(*program).run_with_stdin(input.clone().into_bytes()) {
+                Ok(output) => {
+                    let output = String::from_utf8(output.stdout)
+                        .with_context(|| format!("{program} returned non-UTF-8 output"))?;
+                    let demangled: Vec<&str> = output.lines().collect();
+                    if demangled.len() != symbols.len() {
+                        bail!(
+                            "{program} returned {} lines for {} symbols",
+                            demangled.len(),
+                            symbols.len()
+                        );
+                    }
+                    for (symbol, demangled) in symbols.into_iter().zip(demangled) {
+                        cpp.insert(symbol.to_owned(), demangled.trim().to_owned());
+                    }
+                    return Ok(());
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap()).with_context(|| {
+            format!("C++ demangling requires {} in PATH", CPP_DEMANGLERS.join(" or "))
+        })
+    }
+
+    /// Demangles function names in FN and FNDA records of LCOV data.
+    ///
+    /// C++ symbols in the records are also demangled if C++ demangling is enabled.
+    pub(crate) fn demangle_lcov(&mut self, lcov: &str) -> Result<String> {
+        self.prepare_cpp(lcov_function_names(lcov))?;
+
+        let mut out = String::with_capacity(lcov.len());
+        for line in lcov.lines() {
+            let (kind, value) = line.split_once(':').unwrap_or((line, ""));
+            match (kind, value.split_once(',')) {
+                ("FN" | "FNDA", Some((n, name))) => {
+                    let _ = writeln!(out, "{kind}:{n},{}", self.demangle(name));
+                }
+                _ => {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            }
+        }
+        if !lcov.ends_with('\n') {
+            out.pop();
+        }
+        Ok(out)
     }
-    demangled_lines
 }
 
-pub(crate) fn run() -> Result<()> {
-    let mut buffer = String::new();
-    io::stdin().read_to_string(&mut buffer)?;
-    let mut demangled_lines = demangle_lines(buffer.lines());
-    demangled_lines.push(String::new()); // ensure a trailing newline
-    io::stdout().write_all(demangled_lines.join("\n").as_bytes())?;
+impl<'a> lcov2cobertura::Demangler<'a, '_> for Demangler {
+    fn demangle(&mut self, ident: &'a str) -> io::Result<Cow<'a, str>> {
+        Ok(Cow::Owned(Demangler::demangle(self, ident)))
+    }
+
+    fn stop(self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns function names in FN and FNDA records of LCOV data.
+pub(crate) fn lcov_function_names(lcov: &str) -> impl Iterator<Item = &str> {
+    lcov.lines().filter_map(|line| {
+        let (kind, value) = line.split_once(':')?;
+        let (_, name) = value.split_once(',')?;
+        matches!(kind, "FN" | "FNDA").then_some(name)
+    })
+}
+
+/// Returns `true` if the given symbol is mangled in the legacy Rust mangling scheme.
+fn is_legacy_symbol(mangled: &str) -> bool {
+    ["_ZN", "ZN", "__ZN"].iter().any(|prefix| mangled.starts_with(prefix))
+}
+
+/// Returns `true` if the given symbol may be mangled in the Itanium C++ ABI
+/// mangling scheme (which the legacy Rust mangling scheme is based on).
+fn is_cpp_symbol(mangled: &str) -> bool {
+    mangled.starts_with("_Z") || mangled.starts_with("__Z")
+}
+
+#[cfg(test)]
+fn demangle_lines(lines: std::str::Lines<'_>) -> Vec<String> {
+    let demangler = Demangler::new(false, false);
+    lines.map(|mangled| demangler.demangle(mangled)).collect()
+}
+
+/// Reads mangled names from stdin line by line and writes demangled names to stdout.
+///
+/// This is used as the demangler of llvm-cov (`-Xdemangler`), which writes all
+/// names before reading the output, so C++ symbols are demangled at once.
+pub(crate) fn run(keep_hashes: bool, cpp: bool) -> Result<()> {
+    let mut demangler = Demangler::new(keep_hashes, cpp);
+    let names = io::stdin().lock().lines().collect::<io::Result<Vec<_>>>()?;
+    demangler.prepare_cpp(names.iter().map(String::as_str))?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    for mangled in &names {
+        writeln!(stdout, "{}", demangler.demangle(mangled))?;
+    }
+    stdout.flush()?;
     Ok(())
 }
 
@@ -74,7 +228,7 @@ RYFG_FGyyEvRYFF_EvRYFFEvERLB_B_B_ERLRjB_B_B_
 ";
 
     const DEMANGLED_OUTPUT_NO_CRATE_DISAMBIGUATORS: &str = r"
-123foo[0]::bar
+123foo::bar
 utf8_idents::საჭმელად_გემრიელი_სადილი
 cc::spawn::{closure#0}::{closure#0}
 <core::slice::Iter<u8> as core::iter::iterator::Iterator>::rposition::<core::slice::memchr::memrchr::{closure#1}>::{closure#0}
@@ -89,14 +243,43 @@ INtC8arrayvec8ArrayVechKj7b_E
 <const_generic::Char<'\n'>>
 <const_generic::Char<'∂'>>
 <const_generic::Foo<_>>::foo::FOO
-foo[0]
-foo[0]
-backtrace[0]::foo
+foo
+foo
+backtrace::foo
 rand::rngs::adapter::reseeding::fork::FORK_HANDLER_REGISTERED.0.0
 {recursion limit reached}
 {size limit reached}
 ";
 
+    #[test]
+    fn test_demangle_lcov() {
+        let mut demangler = Demangler::new(false, false);
+        let lcov =
+            "SF:src/lib.rs\nFN:1,_RNvC6_123foo3bar\nFNDA:3,_RNvC6_123foo3bar\nFNF:1\nend_of_record";
+        assert_eq!(
+            demangler.demangle_lcov(lcov).unwrap(),
+            "SF:src/lib.rs\nFN:1,123foo::bar\nFNDA:3,123foo::bar\nFNF:1\nend_of_record"
+        );
+        assert_eq!(
+            demangler.demangle_lcov(&format!("{lcov}\n")).unwrap(),
+            format!("{}\n", demangler.demangle_lcov(lcov).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_demangle_hashes() {
+        let v0 = "_RNvCs4fqI2P2rA04_7mycrate3foo";
+        let legacy = "_ZN7mycrate3foo17h0123456789abcdefE";
+        let cpp = "_ZN2ns3fooEi";
+        let strip = Demangler::new(false, false);
+        assert_eq!(strip.demangle(v0), "mycrate::foo");
+        assert_eq!(strip.demangle(legacy), "mycrate::foo");
+        assert_eq!(strip.demangle(cpp), cpp);
+        let keep = Demangler::new(true, false);
+        assert_eq!(keep.demangle(v0), "mycrate[317d481089b8c8fe]::foo");
+        assert_eq!(keep.demangle(legacy), "mycrate::foo::h0123456789abcdef");
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)] // Miri is too slow
     fn test_demangle_lines_no_crate_disambiguators() {
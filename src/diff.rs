@@ -0,0 +1,294 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// `cargo llvm-cov diff <BASE> <HEAD>` support.
+//
+// This compares two exported reports (lcov or json) and generates an html
+// report that shows the lines whose coverage status changed: lines that were
+// not executed in <BASE> but executed in <HEAD> are newly covered, and lines
+// that were executed in <BASE> (or didn't exist) but not executed in <HEAD>
+// are newly uncovered. Lines are compared by line numbers, so the report is
+// most accurate when the source files of <HEAD> are checked out.
+
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use anyhow::{Context as _, Result};
+use camino::Utf8Path;
+
+use crate::{
+    cargo::Workspace,
+    cli::{self, Args},
+    fs,
+    json::{xml_escape, LcovExport, LcovRecord, LlvmCovJsonExport},
+    path_map::PathMap,
+    term,
+};
+
+const STYLE: &str = "
+body { font-family: sans-serif; }
+table { border-collapse: collapse; }
+td, th { padding: 0 8px; text-align: left; }
+td.number { text-align: right; }
+pre { margin: 0; }
+tr.newly-covered { background-color: #c8f0c8; }
+tr.newly-uncovered { background-color: #f8c8c8; }
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Change {
+    NewlyCovered,
+    NewlyUncovered,
+}
+
+impl Change {
+    pub(crate) fn new(base: Option<u64>, head: Option<u64>) -> Option<Self> {
+        match (base, head?) {
+            (None | Some(0), count) if count != 0 => Some(Self::NewlyCovered),
+            (None, 0) => Some(Self::NewlyUncovered),
+            (Some(count), 0) if count != 0 => Some(Self::NewlyUncovered),
+            _ => None,
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Self::NewlyCovered => "newly-covered",
+            Self::NewlyUncovered => "newly-uncovered",
+        }
+    }
+}
+
+/// The coverage of a source file in the two reports.
+pub(crate) struct FileDiff<'a> {
+    pub(crate) path: &'a str,
+    base: Option<&'a LcovRecord>,
+    head: Option<&'a LcovRecord>,
+    /// (line, execution count in base, execution count in head)
+    pub(crate) lines: Vec<(u64, Option<u64>, Option<u64>)>,
+}
+
+impl FileDiff<'_> {
+    fn count(&self, change: Change) -> usize {
+        self.lines
+            .iter()
+            .filter(|&&(_, base, head)| Change::new(base, head) == Some(change))
+            .count()
+    }
+}
+
+pub(crate) fn run(args: &mut Args) -> Result<()> {
+    let ws = Workspace::new(&args.manifest, None, false, false)?;
+    cli::merge_config_to_args(&ws, &mut None, &mut args.verbose, &mut args.color);
+    term::set_coloring(&mut args.color);
+
+    let [base_path, head_path] = &*args.diff_exports else { unreachable!() };
+    let base = load(base_path)?;
+    let head = load(head_path)?;
+    let files = diff(&base, &head);
+
+    let output_dir = args.cov.output_dir.as_ref().unwrap_or(&ws.output_dir).join("diff");
+    fs::remove_dir_all(&output_dir)?;
+    fs::create_dir_all(&output_dir)?;
+    fs::write(output_dir.join("style.css"), STYLE)?;
+    let mut index = vec![];
+    for (i, file) in files.iter().enumerate() {
+        let (covered, uncovered) =
+            (file.count(Change::NewlyCovered), file.count(Change::NewlyUncovered));
+        if covered == 0 && uncovered == 0 {
+            continue;
+        }
+        // --path-map has already been applied to the paths in the reports.
+        let path = PathMap::default().source_path(&ws.metadata.workspace_root, file.path);
+        let source = fs::read_to_string(path).ok();
+        let page = format!("{i}.html");
+        fs::write(output_dir.join(&page), file_page(file, source.as_deref()))?;
+        index.push((file, page, covered, uncovered));
+    }
+    fs::write(
+        output_dir.join("index.html"),
+        index_page(base_path, head_path, &base, &head, &index),
+    )?;
+
+    let covered: usize = index.iter().map(|&(_, _, covered, _)| covered).sum();
+    let uncovered: usize = index.iter().map(|&(_, _, _, uncovered)| uncovered).sum();
+    println!(
+        "lines: {:.2}% -> {:.2}%, newly covered lines: {covered}, newly uncovered lines: {uncovered}",
+        base.lines_percent(),
+        head.lines_percent()
+    );
+    let index = output_dir.join("index.html");
+    status!("Finished", "report saved to {index}");
+    if args.cov.open {
+        status!("Opening", "{index}");
+        crate::open_report(&ws, args.cov.browser.as_deref(), &index)?;
+    }
+    Ok(())
+}
+
+/// Loads the report exported in lcov or json format.
+pub(crate) fn load(path: &Utf8Path) -> Result<LcovExport> {
+    let s = fs::read_to_string(path)?;
+    if s.trim_start().starts_with('{') {
+        let json: LlvmCovJsonExport =
+            serde_json::from_str(&s).with_context(|| format!("failed to parse {path}"))?;
+        Ok(LcovExport::from_llvm_cov_json_export(&json))
+    } else {
+        s.parse().with_context(|| format!("failed to parse {path}"))
+    }
+}
+
+pub(crate) fn diff<'a>(base: &'a LcovExport, head: &'a LcovExport) -> Vec<FileDiff<'a>> {
+    let mut files: BTreeMap<&str, (Option<&LcovRecord>, Option<&LcovRecord>)> = BTreeMap::new();
+    for r in &base.records {
+        files.entry(&r.source_file).or_default().0 = Some(r);
+    }
+    for r in &head.records {
+        files.entry(&r.source_file).or_default().1 = Some(r);
+    }
+    files
+        .into_iter()
+        .map(|(path, (base, head))| {
+            let mut lines: BTreeMap<u64, (Option<u64>, Option<u64>)> = BTreeMap::new();
+            for l in base.iter().flat_map(|r| &r.lines) {
+                let count = &mut lines.entry(l.line).or_default().0;
+                *count = Some(count.unwrap_or(0).max(l.execution_count));
+            }
+            for l in head.iter().flat_map(|r| &r.lines) {
+                let count = &mut lines.entry(l.line).or_default().1;
+                *count = Some(count.unwrap_or(0).max(l.execution_count));
+            }
+            let lines = lines.into_iter().map(|(line, (b, h))| (line, b, h)).collect();
+            FileDiff { path, base, head, lines }
+        })
+        .collect()
+}
+
+fn percent(record: Option<&LcovRecord>) -> String {
+    record.map_or_else(|| "-".to_owned(), |r| format!("{:.2}%", r.lines_percent()))
+}
+
+fn page_header(out: &mut String, title: &str) {
+    let _ = write!(
+        out,
+        "<!doctype html><html><head><meta charset='UTF-8'><title>{title}</title>\
+         <link rel='stylesheet' type='text/css' href='style.css'></head><body><h2>{title}</h2>"
+    );
+}
+
+fn index_page(
+    base_path: &Utf8Path,
+    head_path: &Utf8Path,
+    base: &LcovExport,
+    head: &LcovExport,
+    files: &[(&FileDiff<'_>, String, usize, usize)],
+) -> String {
+    let mut out = String::new();
+    page_header(&mut out, "Coverage Diff");
+    let _ = write!(
+        out,
+        "<p>Base: <code>{}</code> ({:.2}%)<br>Head: <code>{}</code> ({:.2}%)</p>",
+        xml_escape(base_path.as_str()),
+        base.lines_percent(),
+        xml_escape(head_path.as_str()),
+        head.lines_percent()
+    );
+    if files.is_empty() {
+        out.push_str("<p>No lines changed their coverage status.</p>");
+    } else {
+        out.push_str(
+            "<table><tr><th>Filename</th><th>Base</th><th>Head</th><th>Newly covered</th>\
+             <th>Newly uncovered</th></tr>",
+        );
+        for (file, page, covered, uncovered) in files {
+            let _ = write!(
+                out,
+                "<tr><td><a href='{page}'>{}</a></td><td class='number'>{}</td>\
+                 <td class='number'>{}</td><td class='number'>{covered}</td>\
+                 <td class='number'>{uncovered}</td></tr>",
+                xml_escape(file.path),
+                percent(file.base),
+                percent(file.head)
+            );
+        }
+        out.push_str("</table>");
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+fn file_page(file: &FileDiff<'_>, source: Option<&str>) -> String {
+    let mut out = String::new();
+    page_header(&mut out, &xml_escape(file.path));
+    let _ = write!(
+        out,
+        "<p><a href='index.html'>Index</a> | lines: {} -&gt; {}</p>\
+         <table><tr><th>Line</th><th>Base</th><th>Head</th><th>Source</th></tr>",
+        percent(file.base),
+        percent(file.head)
+    );
+    let counts: BTreeMap<u64, (Option<u64>, Option<u64>)> =
+        file.lines.iter().map(|&(line, base, head)| (line, (base, head))).collect();
+    let last = file.lines.last().map_or(0, |&(line, _, _)| line);
+    let source_lines: Vec<&str> = source.map(|s| s.lines().collect()).unwrap_or_default();
+    let count = |c: Option<u64>| c.map(|c| c.to_string()).unwrap_or_default();
+    for line in 1..=last.max(source_lines.len() as u64) {
+        let (base, head) = counts.get(&line).copied().unwrap_or_default();
+        let class = Change::new(base, head).map_or("", Change::class);
+        let text = usize::try_from(line - 1).ok().and_then(|i| source_lines.get(i));
+        let _ = write!(
+            out,
+            "<tr class='{class}'><td class='number'><pre>{line}</pre></td>\
+             <td class='number'><pre>{}</pre></td><td class='number'><pre>{}</pre></td>\
+             <td><pre>{}</pre></td></tr>",
+            count(base),
+            count(head),
+            xml_escape(text.copied().unwrap_or_default())
+        );
+    }
+    out.push_str("</table></body></html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn change() {
+        assert_eq!(Change::new(Some(0), Some(1)), Some(Change::NewlyCovered));
+        assert_eq!(Change::new(None, Some(2)), Some(Change::NewlyCovered));
+        assert_eq!(Change::new(Some(3), Some(0)), Some(Change::NewlyUncovered));
+        assert_eq!(Change::new(None, Some(0)), Some(Change::NewlyUncovered));
+        assert_eq!(Change::new(Some(1), Some(2)), None);
+        assert_eq!(Change::new(Some(0), Some(0)), None);
+        assert_eq!(Change::new(Some(1), None), None);
+    }
+
+    #[test]
+    fn diff_lines() {
+        let base: LcovExport = "SF:a.rs\nDA:1,1\nDA:2,0\nDA:3,1\nend_of_record\n\
+                                SF:b.rs\nDA:1,1\nend_of_record\n"
+            .parse()
+            .unwrap();
+        let head: LcovExport =
+            "SF:a.rs\nDA:1,1\nDA:2,3\nDA:3,0\nDA:4,0\nend_of_record\n".parse().unwrap();
+        let files = diff(&base, &head);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "a.rs");
+        assert_eq!(files[0].lines, vec![
+            (1, Some(1), Some(1)),
+            (2, Some(0), Some(3)),
+            (3, Some(1), Some(0)),
+            (4, None, Some(0))
+        ]);
+        assert_eq!(files[0].count(Change::NewlyCovered), 1);
+        assert_eq!(files[0].count(Change::NewlyUncovered), 2);
+        assert_eq!(files[1].count(Change::NewlyCovered), 0);
+        assert_eq!(files[1].count(Change::NewlyUncovered), 0);
+        let page = file_page(&files[0], Some("a\nb\nc\nd\n"));
+        assert!(page.contains(
+            "<tr class='newly-covered'><td class='number'><pre>2</pre></td>\
+             <td class='number'><pre>0</pre></td><td class='number'><pre>3</pre></td>\
+             <td><pre>b</pre></td></tr>"
+        ));
+    }
+}
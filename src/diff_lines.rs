@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --fail-under-diff-lines support.
+//
+// The lines added or modified since the merge base of --diff-base and HEAD are
+// obtained by `git diff`, and the check fails if the coverage of these lines is
+// less than the threshold. The working tree is compared, so uncommitted changes
+// are also checked. Lines that have no coverage data (comments, blank lines,
+// files not included in the report, etc.) are not counted.
+//
+// Refs:
+// - https://git-scm.com/docs/git-diff#_generating_patch_text_with_p
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context as _, Result};
+use camino::Utf8PathBuf;
+
+use crate::{context::Context, json::LlvmCovJsonExport, percent};
+
+/// Checks the coverage of the lines changed since --diff-base.
+pub(crate) fn check(cx: &Context, json: &LlvmCovJsonExport, min: f64) -> Result<()> {
+    let workspace_root = &cx.ws.metadata.workspace_root;
+    let git = |args: &[&str]| cmd!("git").args(args).dir(workspace_root.as_std_path()).read();
+    let root: Utf8PathBuf = git(&["rev-parse", "--show-toplevel"])
+        .context("--fail-under-diff-lines requires the workspace to be in a git repository")?
+        .into();
+    let base = match &cx.args.cov.diff_base {
+        Some(base) => base.clone(),
+        None => ["@{upstream}", "origin/HEAD"]
+            .into_iter()
+            .find(|rev| git(&["rev-parse", "--verify", "--quiet", rev]).is_ok())
+            .with_context(|| {
+                "failed to determine the base of changes for --fail-under-diff-lines; \
+                 the current branch has no upstream and origin/HEAD does not exist, \
+                 please specify the base revision by --diff-base"
+            })?
+            .to_owned(),
+    };
+    let merge_base = git(&["merge-base", &base, "HEAD"])
+        .with_context(|| format!("failed to find the merge base of {base} and HEAD"))?;
+    let diff = git(&["diff", "--no-color", "--no-ext-diff", "--unified=0", &merge_base, "--"])?;
+
+    let mut total = 0_u64;
+    let mut uncovered: Vec<(String, Vec<u64>)> = vec![];
+    let file_names = json.file_names();
+    for (path, lines) in parse_diff(&diff)? {
+        let file = root.join(&path);
+        let Some(coverage) = file_names
+            .iter()
+            .find(|f| cx.source_path(f) == file)
+            .and_then(|f| json.get_line_coverage(f))
+        else {
+            continue;
+        };
+        let coverage: BTreeMap<u64, u64> = coverage.iter().map(|l| (l.line, l.count)).collect();
+        let mut file_uncovered = vec![];
+        for line in lines {
+            let Some(&count) = coverage.get(&line) else { continue };
+            total += 1;
+            if count == 0 {
+                file_uncovered.push(line);
+            }
+        }
+        if !file_uncovered.is_empty() {
+            uncovered.push((path, file_uncovered));
+        }
+    }
+
+    let uncovered_count: u64 = uncovered.iter().map(|(_, lines)| lines.len() as u64).sum();
+    #[allow(clippy::cast_precision_loss)]
+    let percent = percent::round(if total == 0 {
+        100.
+    } else {
+        (total - uncovered_count) as f64 * 100. / total as f64
+    });
+    info!(
+        "{} of {total} changed lines since {base} are covered ({}%)",
+        total - uncovered_count,
+        percent::format(percent)
+    );
+    if percent < min {
+        error!("coverage of changed lines ({}%) is less than {min}%", percent::format(percent));
+        for (path, lines) in &uncovered {
+            let lines: Vec<_> = lines.iter().map(ToString::to_string).collect();
+            eprintln!("    {path}: {}", lines.join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Parses the output of `git diff --unified=0`, and returns the added or
+/// modified lines of each file (the paths are relative to the repository root).
+fn parse_diff(diff: &str) -> Result<BTreeMap<String, Vec<u64>>> {
+    let mut files: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    let mut current = None;
+    // Whether the line is in the header of a file (an added line can also start with `+++ `).
+    let mut in_header = false;
+    for line in diff.lines() {
+        if line.starts_with("diff --git ") {
+            in_header = true;
+        } else if let (true, Some(path)) = (in_header, line.strip_prefix("+++ ")) {
+            // Deleted files are `+++ /dev/null`.
+            current = path.strip_prefix("b/").map(str::to_owned);
+        } else if let Some(hunk) = line.strip_prefix("@@ ") {
+            in_header = false;
+            let Some(path) = &current else { continue };
+            // @@ -<start>[,<count>] +<start>[,<count>] @@
+            let Some(added) = hunk.split(' ').find_map(|s| s.strip_prefix('+')) else {
+                bail!("invalid hunk header in git diff output: {line}");
+            };
+            let (start, count) = match added.split_once(',') {
+                Some((start, count)) => (start.parse::<u64>()?, count.parse::<u64>()?),
+                None => (added.parse::<u64>()?, 1),
+            };
+            files.entry(path.clone()).or_default().extend(start..start + count);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let diff = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 0123456..789abcd 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -3 +3 @@ fn a() {
+-    b();
++    c();
+@@ -10,0 +11,4 @@ fn d() {
++++ b/e
++fn e() {
++    f();
++}
+@@ -20,2 +24,0 @@ fn g() {
+-fn h() {}
+-fn i() {}
+diff --git a/src/old.rs b/src/old.rs
+deleted file mode 100644
+--- a/src/old.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-fn j() {}
+diff --git a/src/new.rs b/src/new.rs
+new file mode 100644
+--- /dev/null
++++ b/src/new.rs
+@@ -0,0 +1,2 @@
++fn k() {}
++fn l() {}
+";
+        let files = parse_diff(diff).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files["src/lib.rs"], [3, 11, 12, 13, 14]);
+        assert_eq!(files["src/new.rs"], [1, 2]);
+    }
+}
@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Downloads llvm-tools matching the current rustc from the Rust distribution
+// server, for environments where llvm-tools-preview cannot be installed via
+// rustup (e.g., CI images with rustc from the distribution packages).
+//
+// The archive is located using the channel manifest of the rustc release,
+// verified against the SHA-256 checksum in the manifest, and extracted to
+// `$CARGO_HOME/cargo-llvm-cov/llvm-tools-<commit-hash>-<host>`. This uses
+// `curl` and `tar` commands, which are available on all major platforms, and
+// `sha256sum` (or its equivalent on the platform) to compute the checksum.
+//
+// Refs:
+// - https://forge.rust-lang.org/infra/channel-layout.html
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, format_err, Context as _, Result};
+
+use crate::{cargo::Workspace, fs, term};
+
+const DIST_SERVER: &str = "https://static.rust-lang.org/dist";
+
+/// Returns the directory that contains llvm-cov and llvm-profdata, downloading
+/// them if they have not been downloaded yet.
+pub(crate) fn llvm_tools(ws: &Workspace) -> Result<PathBuf> {
+    let verbose_version = ws.rustc().args(["--version", "--verbose"]).read()?;
+    let field = |name: &str| {
+        verbose_version.lines().find_map(|line| line.strip_prefix(name)).ok_or_else(|| {
+            format_err!("failed to get {name} from rustc version output: {verbose_version}")
+        })
+    };
+    let release = field("release: ")?;
+    let host = field("host: ")?;
+    let commit_hash = field("commit-hash: ")?;
+    let commit_date = field("commit-date: ")?;
+    if commit_hash == "unknown" {
+        bail!("unable to determine the commit of rustc {release}");
+    }
+
+    let Some(cargo_home) = home::cargo_home().ok() else {
+        bail!("failed to determine cargo home directory")
+    };
+    let dir = cargo_home
+        .join("cargo-llvm-cov")
+        .join(format!("llvm-tools-{}-{host}", &commit_hash[..commit_hash.len().min(9)]));
+    let bin_dir = dir.join("bin");
+    let exists = |name: &str| bin_dir.join(format!("{name}{}", env::consts::EXE_SUFFIX)).exists();
+    if exists("llvm-cov") && exists("llvm-profdata") {
+        if term::verbose() {
+            status!("Fresh", "llvm-tools ({})", dir.display());
+        }
+        return Ok(bin_dir);
+    }
+
+    let (url, hash) = find_llvm_tools(release, host, commit_hash, commit_date)?;
+    status!("Downloading", "llvm-tools for rustc {release} from {url}");
+    fs::remove_dir_all(&dir)?;
+    fs::create_dir_all(&dir)?;
+    let archive = dir.join("llvm-tools.tar.xz");
+    curl(&url).arg("-o").arg(&archive).run_with_output()?;
+    if let Err(e) = verify_checksum(&archive, &hash) {
+        fs::remove_dir_all(&dir)?;
+        return Err(e.context(format!("failed to verify llvm-tools downloaded from {url}")));
+    }
+    let extract_dir = dir.join("extract");
+    fs::create_dir_all(&extract_dir)?;
+    cmd!("tar", "-xJf", &archive, "-C", &extract_dir).run_with_output()?;
+    // The archive contains llvm-tools-<version>-<host>/llvm-tools-preview/lib/rustlib/<host>/{bin,lib}.
+    // Keep the relative path from bin to lib, because the tools are linked to
+    // libLLVM in lib on some platforms.
+    let pattern = format!(
+        "{}/*/llvm-tools-preview/lib/rustlib/{host}",
+        glob::Pattern::escape(&extract_dir.to_string_lossy())
+    );
+    let extracted = glob::glob(&pattern)?.find_map(Result::ok).ok_or_else(|| {
+        format_err!("failed to find llvm-tools in the archive downloaded from {url}")
+    })?;
+    for name in ["bin", "lib"] {
+        if extracted.join(name).exists() {
+            fs::rename(extracted.join(name), dir.join(name))?;
+        }
+    }
+    fs::remove_dir_all(&extract_dir)?;
+    fs::remove_file(&archive)?;
+    if !exists("llvm-cov") || !exists("llvm-profdata") {
+        bail!("the archive downloaded from {url} doesn't contain llvm-cov and llvm-profdata");
+    }
+    Ok(bin_dir)
+}
+
+pub(crate) fn curl(url: &str) -> crate::process::ProcessBuilder {
+    cmd!("curl", "--proto", "=https", "--tlsv1.2", "-fsSL", "--retry", "3", url)
+}
+
+/// Returns the URL and SHA-256 checksum of the llvm-tools archive for the
+/// given rustc release.
+fn find_llvm_tools(
+    release: &str,
+    host: &str,
+    commit_hash: &str,
+    commit_date: &str,
+) -> Result<(String, String)> {
+    let manifests = match release.split_once('-') {
+        None => vec![format!("{DIST_SERVER}/channel-rust-{release}.toml")],
+        Some((_, pre)) => {
+            let channel = if pre.starts_with("beta") { "beta" } else { "nightly" };
+            // Nightly is usually published on the day after the commit date.
+            next_day(commit_date)
+                .into_iter()
+                .chain([commit_date.to_owned()])
+                .map(|date| format!("{DIST_SERVER}/{date}/channel-rust-{channel}.toml"))
+                .collect()
+        }
+    };
+    for url in &manifests {
+        let Ok(manifest) = curl(url).read() else { continue };
+        let manifest: toml::Table =
+            toml::from_str(&manifest).with_context(|| format!("failed to parse {url}"))?;
+        let pkg = |name: &str| manifest.get("pkg").and_then(|pkg| pkg.get(name));
+        // Make sure that the manifest is for the same build of rustc.
+        let rustc_version = pkg("rustc").and_then(|p| p.get("version")).and_then(|v| v.as_str());
+        if !rustc_version.is_some_and(|v| v.contains(&commit_hash[..commit_hash.len().min(9)])) {
+            continue;
+        }
+        let target = pkg("llvm-tools-preview")
+            .or_else(|| pkg("llvm-tools"))
+            .and_then(|p| p.get("target"))
+            .and_then(|t| t.get(host));
+        let get = |key: &str| target.and_then(|t| t.get(key)).and_then(|v| v.as_str());
+        if let (Some(url), Some(hash)) = (get("xz_url"), get("xz_hash")) {
+            return Ok((url.to_owned(), hash.to_owned()));
+        }
+        bail!("llvm-tools for {host} is not available in {url}");
+    }
+    bail!("failed to find the distribution manifest of rustc {release} ({commit_hash})")
+}
+
+/// Returns the day after the given date (YYYY-MM-DD).
+fn next_day(date: &str) -> Option<String> {
+    let mut parts = date.splitn(3, '-').map(str::parse::<u32>);
+    let (mut y, mut m, mut d) = (parts.next()?.ok()?, parts.next()?.ok()?, parts.next()?.ok()?);
+    let leap = y % 4 == 0 && (y % 100 != 0 || y % 400 == 0);
+    let days_in_month = match m {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    d += 1;
+    if d > days_in_month {
+        d = 1;
+        m += 1;
+        if m > 12 {
+            m = 1;
+            y += 1;
+        }
+    }
+    Some(format!("{y:04}-{m:02}-{d:02}"))
+}
+
+/// Returns an error if the SHA-256 digest of the given file doesn't match `expected`.
+fn verify_checksum(path: &Path, expected: &str) -> Result<()> {
+    let actual = sha256(path)?;
+    if actual != expected {
+        bail!("checksum mismatch: expected {expected}, but found {actual}");
+    }
+    Ok(())
+}
+
+/// Returns the SHA-256 digest of the given file as a lowercase hex string.
+fn sha256(path: &Path) -> Result<String> {
+    // sha256sum is available on Linux and in Git for Windows, shasum on macOS,
+    // and certutil on Windows.
+    let cmds = [
+        cmd!("sha256sum", path),
+        cmd!("shasum", "-a", "256", path),
+        cmd!("certutil", "-hashfile", path, "SHA256"),
+    ];
+    for mut cmd in cmds {
+        let Ok(out) = cmd.read() else { continue };
+        if let Some(digest) = parse_digest(&out) {
+            return Ok(digest);
+        }
+    }
+    bail!(
+        "failed to compute SHA-256 checksum of {}; sha256sum, shasum, or certutil command is \
+         required",
+        path.display()
+    )
+}
+
+/// Parses the output of sha256sum, shasum, or certutil.
+fn parse_digest(out: &str) -> Option<String> {
+    out.lines().find_map(|line| {
+        // sha256sum and shasum print `<digest>  <path>`, and prefix the line
+        // with `\` if the path contains a backslash.
+        let first = line.split_whitespace().next()?.trim_start_matches('\\');
+        // Old versions of certutil separate bytes with spaces.
+        let digest = if first.len() == 64 { first.to_owned() } else { line.replace(' ', "") };
+        (digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit()))
+            .then(|| digest.to_ascii_lowercase())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("llvm-tools.tar.xz");
+        fs::write(&archive, "abc").unwrap();
+        let expected = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(sha256(&archive).unwrap(), expected);
+        verify_checksum(&archive, expected).unwrap();
+
+        // Tampered archive.
+        fs::write(&archive, "abd").unwrap();
+        let err = verify_checksum(&archive, expected).unwrap_err().to_string();
+        assert!(err.starts_with(&format!("checksum mismatch: expected {expected}, but found ")));
+    }
+
+    #[test]
+    fn test_parse_digest() {
+        let digest = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(parse_digest(&format!("{digest}  /tmp/a")).unwrap(), digest);
+        assert_eq!(parse_digest(&format!("\\{digest}  C:\\a")).unwrap(), digest);
+        assert_eq!(
+            parse_digest(&format!(
+                "SHA256 hash of a:\r\n{}\r\nCertUtil: -hashfile command completed successfully.",
+                digest.to_ascii_uppercase()
+            ))
+            .unwrap(),
+            digest
+        );
+        let spaced = digest.as_bytes().chunks(2).map(|b| std::str::from_utf8(b).unwrap());
+        assert_eq!(parse_digest(&spaced.collect::<Vec<_>>().join(" ")).unwrap(), digest);
+        assert!(parse_digest("sha256sum: a: No such file or directory").is_none());
+    }
+
+    #[test]
+    fn test_next_day() {
+        assert_eq!(next_day("2024-02-28").unwrap(), "2024-02-29");
+        assert_eq!(next_day("2023-02-28").unwrap(), "2023-03-01");
+        assert_eq!(next_day("2024-04-30").unwrap(), "2024-05-01");
+        assert_eq!(next_day("2024-12-31").unwrap(), "2025-01-01");
+    }
+}
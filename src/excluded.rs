@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --show-excluded support.
+//
+// This exports the coverage data without any filters, and attributes each
+// source file (or function) that the filters removed from the report to the
+// first option or default rule that matches it, so users can verify that
+// exclusions don't hide untested production code. Lines are counted in the
+// same way as the line coverage (i.e., only lines that have coverage data).
+// Items excluded by `#[coverage(off)]` are not in the coverage data, and are
+// listed from the source files instead (see coverage_off.rs).
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    io::{self, Write as _},
+};
+
+use anyhow::{Context as _, Result};
+use regex::Regex;
+
+use crate::{
+    context::Context, coverage_off, function_filter::FunctionFilter, Format, IgnoreFilenameRules,
+};
+
+/// The code excluded by a rule.
+#[derive(Default)]
+struct Excluded {
+    /// filename -> number of lines
+    files: BTreeMap<String, usize>,
+    /// (filename, start line, demangled name)
+    functions: BTreeSet<(String, u64, String)>,
+    /// Lines of the code removed along with functions
+    lines: BTreeSet<(String, u64)>,
+}
+
+/// Prints the files and functions excluded from the report, grouped by the
+/// option or rule that excludes them.
+pub(crate) fn print(
+    cx: &Context,
+    object_files: &[OsString],
+    ignore_rules: &IgnoreFilenameRules,
+    function_filter: Option<&FunctionFilter>,
+) -> Result<()> {
+    let json =
+        Format::Json.get_json(cx, object_files, None, None, false).context("failed to get json")?;
+    let coverage_off = coverage_off::collect(cx, ignore_rules.regex().as_deref())
+        .context("failed to collect items excluded by #[coverage(off)]")?;
+
+    // Rules that exclude the same kind of files are combined into one regex.
+    let mut patterns: Vec<(&'static str, Vec<&str>)> = vec![];
+    for &(rule, ref pattern) in &ignore_rules.rules {
+        match patterns.iter_mut().find(|(r, _)| *r == rule) {
+            Some((_, v)) => v.push(pattern),
+            None => patterns.push((rule, vec![pattern])),
+        }
+    }
+    let file_rules = patterns
+        .into_iter()
+        .map(|(rule, patterns)| Ok((rule, Regex::new(&patterns.join("|"))?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut excluded: Vec<(&'static str, Excluded)> = vec![];
+    let mut excluded_files = BTreeSet::new();
+    for file in json.file_names() {
+        let Some(&(rule, _)) = file_rules.iter().find(|(_, re)| re.is_match(file)) else {
+            continue;
+        };
+        let lines = json.get_line_coverage(file).map_or(0, |lines| lines.len());
+        entry(&mut excluded, rule).files.insert(file.to_owned(), lines);
+        excluded_files.insert(file);
+    }
+    if let Some(function_filter) = function_filter {
+        let locations: BTreeMap<&str, (&str, u64)> = json
+            .function_locations()
+            .into_iter()
+            .map(|(name, file, start, _)| (name, (file, start.0)))
+            .collect();
+        let mut line_coverage = BTreeMap::new();
+        for (name, rule, lines) in function_filter.excluded() {
+            let Some(&(file, start)) = locations.get(name) else { continue };
+            if excluded_files.contains(file) {
+                continue;
+            }
+            let excluded = entry(&mut excluded, rule);
+            let demangled = format!("{:#}", rustc_demangle::demangle(name));
+            excluded.functions.insert((file.to_owned(), start, demangled));
+            let Some((start, end)) = lines else { continue };
+            let coverage =
+                line_coverage.entry(file).or_insert_with(|| json.get_line_coverage(file));
+            for line in coverage.iter().flatten().filter(|l| start <= l.line && l.line <= end) {
+                excluded.lines.insert((file.to_owned(), line.line));
+            }
+        }
+    }
+
+    // Paths are shown relative to the workspace root, like the paths in the report.
+    let relative =
+        |file: &str| cx.relative_source_path(file).map_or_else(|| file.to_owned(), Into::into);
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    writeln!(stdout, "Excluded from Report:")?;
+    if excluded.is_empty() && coverage_off.items.is_empty() {
+        writeln!(stdout, "    (nothing)")?;
+    }
+    for (rule, excluded) in &excluded {
+        if excluded.files.is_empty() {
+            writeln!(
+                stdout,
+                "{rule}: {} functions, {} lines",
+                excluded.functions.len(),
+                excluded.lines.len()
+            )?;
+            for (file, line, name) in &excluded.functions {
+                let file = relative(file);
+                writeln!(stdout, "    {file}:{line}: {name}")?;
+            }
+        } else {
+            let lines: usize = excluded.files.values().sum();
+            writeln!(stdout, "{rule}: {} files, {lines} lines", excluded.files.len())?;
+            for (file, lines) in &excluded.files {
+                let file = relative(file);
+                writeln!(stdout, "    {file}: {lines} lines")?;
+            }
+        }
+    }
+    if !coverage_off.items.is_empty() {
+        writeln!(stdout, "#[coverage(off)]: {} items", coverage_off.items.len())?;
+        for item in &coverage_off.items {
+            writeln!(stdout, "    {}:{}: {} {}", item.file, item.line, item.kind, item.name)?;
+        }
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Returns the code excluded by the given rule, keeping the order in which rules are found.
+fn entry<'a>(
+    excluded: &'a mut Vec<(&'static str, Excluded)>,
+    rule: &'static str,
+) -> &'a mut Excluded {
+    let i = match excluded.iter().position(|&(r, _)| r == rule) {
+        Some(i) => i,
+        None => {
+            excluded.push((rule, Excluded::default()));
+            excluded.len() - 1
+        }
+    };
+    &mut excluded[i].1
+}
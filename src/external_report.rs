@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --external-report support.
+//
+// Repositories that contain code in other languages (e.g., Python bindings or
+// a web frontend) get the coverage of that code from the coverage tools of
+// those languages, usually in the lcov or Cobertura format. This merges such
+// reports with the coverage of Rust code into a single --html or --cobertura
+// report, in which each language has its own section: the index of the html
+// report lists the files of each language with their totals, and the names of
+// Cobertura packages are prefixed with the language (e.g., `python:pkg.sub`).
+//
+// The merged html report is rendered by html.rs from the line coverage, like
+// `cargo llvm-cov html --from-lcov`, instead of by llvm-cov. Relative paths of
+// source files in the reports are relative to the workspace root, and records
+// of the same file in a section are merged.
+
+use anyhow::{Context as _, Result};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+
+use crate::{
+    context::Context,
+    fs, html,
+    json::{CoberturaExport, LcovExport, LcovRecord, LlvmCovJsonExport},
+    Format,
+};
+
+/// The name of the section of the coverage of Rust code.
+const RUST: &str = "rust";
+
+/// Generates the report of the given format (--html or --cobertura) that
+/// merges the coverage of Rust code with the reports specified by
+/// --external-report.
+pub(crate) fn generate(cx: &Context, format: Format, json: &LlvmCovJsonExport) -> Result<()> {
+    let workspace_root = &cx.ws.metadata.workspace_root;
+    let sections = sections(
+        &cx.args.cov.external_reports,
+        LcovExport::from_llvm_cov_json_export(json),
+        workspace_root,
+    )?;
+
+    if format == Format::Html {
+        let output_dir = cx.args.cov.output_dir.as_ref().unwrap_or(&cx.ws.output_dir).join("html");
+        let mut sources = vec![cx.ws.profdata_file.as_str()];
+        sources.extend(paths(&cx.args.cov.external_reports));
+        html::write(workspace_root, &output_dir, &sources, &sections)?;
+        eprintln!();
+        status!("Finished", "report saved to {output_dir}");
+        return Ok(());
+    }
+
+    let out = cobertura(&sections, workspace_root.as_str(), crate::timestamp(cx)?).to_xml();
+    if let Some(output_path) = &cx.args.cov.output_path {
+        fs::write(output_path, out)?;
+        eprintln!();
+        status!("Finished", "report saved to {output_path}");
+    } else {
+        // write XML to stdout
+        print!("{out}");
+    }
+    Ok(())
+}
+
+/// Returns the paths of the reports specified by --external-report.
+pub(crate) fn paths(reports: &[String]) -> impl Iterator<Item = &str> {
+    reports.iter().map(|report| report.split_once('=').unwrap().1)
+}
+
+/// Returns the sections of the merged report: the coverage of Rust code,
+/// followed by the languages of the reports specified by --external-report in
+/// the order they were first specified.
+pub(crate) fn sections(
+    reports: &[String],
+    rust: LcovExport,
+    workspace_root: &Utf8Path,
+) -> Result<Vec<(String, Vec<LcovRecord>)>> {
+    let mut sections = vec![(RUST.to_owned(), rust)];
+    for report in reports {
+        let (lang, path) = report.split_once('=').unwrap();
+        let lcov = load(Utf8Path::new(path), workspace_root)
+            .with_context(|| format!("failed to load --external-report {report}"))?;
+        match sections.iter_mut().find(|(name, _)| name == lang) {
+            Some((_, section)) => section.records.extend(lcov.records),
+            None => sections.push((lang.to_owned(), lcov)),
+        }
+    }
+    Ok(sections.into_iter().map(|(name, lcov)| (name, html::merge(&lcov))).collect())
+}
+
+/// Loads the report in the lcov or Cobertura format, and resolves relative
+/// paths of source files against the workspace root.
+fn load(path: &Utf8Path, workspace_root: &Utf8Path) -> Result<LcovExport> {
+    let s = fs::read_to_string(path)?;
+    let mut lcov: LcovExport = if s.trim_start().starts_with('<') {
+        s.parse::<CoberturaExport>().context("failed to parse Cobertura XML")?.to_lcov()
+    } else {
+        s.parse().context("failed to parse lcov")?
+    };
+    for record in &mut lcov.records {
+        let source_file = Utf8Path::new(&record.source_file);
+        if source_file.is_relative() {
+            record.source_file = workspace_root
+                .join(source_file)
+                .components()
+                .filter(|c| *c != Utf8Component::CurDir)
+                .collect::<Utf8PathBuf>()
+                .into_string();
+        }
+    }
+    Ok(lcov)
+}
+
+/// Converts the sections to the Cobertura format, prefixing the names of
+/// packages with the names of the sections.
+fn cobertura(
+    sections: &[(String, Vec<LcovRecord>)],
+    source: &str,
+    timestamp: u64,
+) -> CoberturaExport {
+    let mut cobertura =
+        CoberturaExport { timestamp, sources: vec![source.to_owned()], packages: vec![] };
+    for (name, records) in sections {
+        let lcov = LcovExport { records: records.clone() };
+        cobertura.packages.extend(
+            CoberturaExport::from_lcov(&lcov, source, timestamp).packages.into_iter().map(
+                |mut package| {
+                    package.name = format!("{name}:{}", package.name);
+                    package
+                },
+            ),
+        );
+    }
+    cobertura
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn merge_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let py = dir.join("coverage.xml");
+        fs::write(
+            &py,
+            "<?xml version=\"1.0\" ?>\n<coverage timestamp=\"1\"><sources><source>/ws</source>\
+             </sources><packages><package name=\"pkg\"><classes>\
+             <class name=\"a.py\" filename=\"pkg/a.py\"><methods/><lines>\
+             <line number=\"1\" hits=\"1\"/><line number=\"2\" hits=\"0\" branch=\"true\" \
+             condition-coverage=\"50% (1/2)\"/></lines></class></classes></package></packages>\
+             </coverage>\n",
+        )
+        .unwrap();
+        let js = dir.join("lcov.info");
+        fs::write(&js, "SF:./web/b.js\nDA:3,2\nend_of_record\n").unwrap();
+        let rust: LcovExport = "SF:/ws/src/lib.rs\nDA:1,1\nend_of_record\n".parse().unwrap();
+        let reports = [format!("python={py}"), format!("javascript={js}"), format!("python={js}")];
+        let merged = sections(&reports, rust, Utf8Path::new("/ws")).unwrap();
+        let names: Vec<_> = merged
+            .iter()
+            .map(|(name, records)| {
+                (&**name, records.iter().map(|r| &*r.source_file).collect::<Vec<_>>())
+            })
+            .collect();
+        assert_eq!(names, [
+            ("rust", vec!["/ws/src/lib.rs"]),
+            ("python", vec!["/ws/pkg/a.py", "/ws/web/b.js"]),
+            ("javascript", vec!["/ws/web/b.js"]),
+        ]);
+        assert_eq!(merged[1].1[0].branches_found(), 2);
+
+        let cobertura = cobertura(&merged, "/ws", 0);
+        let packages: Vec<_> = cobertura.packages.iter().map(|p| &*p.name).collect();
+        assert_eq!(packages, ["rust:src", "python:pkg", "python:web", "javascript:web"]);
+        assert_eq!(cobertura.packages[1].classes[0].filename, "pkg/a.py");
+
+        let err = sections(&["go=missing.out".to_owned()], LcovExport::default(), dir).unwrap_err();
+        assert!(format!("{err:#}").contains("failed to load --external-report go=missing.out"));
+    }
+}
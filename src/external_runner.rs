@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --external-runner support.
+//
+// Rust libraries used from other languages (e.g., pyo3 extension modules or
+// Node.js addons) are tested by the test runners of those languages, and built
+// by their build tools (e.g., `maturin develop`), which invoke cargo. So instead
+// of `cargo test`, this runs the given command with the environment variables
+// that `cargo llvm-cov show-env --separate-target-dir` prints: cargo invoked by
+// the command builds instrumented artifacts into the target directory of
+// cargo-llvm-cov, and the libraries loaded by the test runner write profile
+// data there. Then the report is generated as usual.
+//
+// The build tools usually copy the built library (e.g., into a virtualenv, or
+// to `*.node` files of Node.js addons built by napi-rs or neon), but the copy
+// has the same coverage mapping (and binary ID) as the artifact in the target
+// directory, which the report reads the coverage mapping from. The profiler
+// runtime in the library writes profile data when the process that loaded it
+// exits, so test runners that run tests in worker processes (e.g., jest) are
+// supported as long as the workers exit normally rather than being killed.
+//
+// The command can also be specified by the `external-runner` key of the
+// configuration file, typically in a profile selected by --cov-profile.
+
+use std::ffi::OsString;
+
+use anyhow::Result;
+
+use crate::{context::Context, run_failed, set_env, stdout_to_stderr, term, timings, IsNextest};
+
+pub(crate) fn run(cx: &Context, runner: &str) -> Result<()> {
+    let _phase = timings::phase("build and run");
+    let mut runner = runner.split_whitespace();
+    let mut cmd = cmd!(program(runner.next().unwrap_or_default()));
+    cmd.args(runner).args(&cx.args.rest).dir(&cx.ws.metadata.workspace_root);
+
+    set_env(cx, &mut cmd, IsNextest(false))?;
+    cmd.env("CARGO_TARGET_DIR", cx.ws.target_dir.as_str());
+    cmd.env("CARGO_LLVM_COV_TARGET_DIR", cx.ws.target_dir.as_str());
+
+    if term::verbose() {
+        status!("Running", "{cmd}");
+    }
+    stdout_to_stderr(cx, &mut cmd);
+    if let Err(e) = cmd.run() {
+        if !(cx.args.ignore_run_fail || cx.args.report_on_fail) {
+            return Err(e);
+        }
+        run_failed(cx, &e);
+    }
+    Ok(())
+}
+
+/// Returns the path of the given program.
+///
+/// On Windows, package managers of Node.js (e.g., npm and yarn) are batch files
+/// (`npm.cmd`), which `std::process::Command` doesn't search for, so this looks
+/// for the program with the extensions in PATHEXT.
+fn program(name: &str) -> OsString {
+    #[cfg(windows)]
+    {
+        use std::path::Path;
+
+        if Path::new(name).extension().is_none() && !name.contains(['/', '\\']) {
+            let exts = crate::env::var("PATHEXT")
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| ".COM;.EXE;.BAT;.CMD".to_owned());
+            let path = crate::env::var_os("PATH").unwrap_or_default();
+            for dir in std::env::split_paths(&path) {
+                for ext in exts.split(';').filter(|e| !e.is_empty()) {
+                    let candidate = dir.join(format!("{name}{}", ext.to_ascii_lowercase()));
+                    if candidate.is_file() {
+                        return candidate.into_os_string();
+                    }
+                }
+            }
+        }
+    }
+    name.into()
+}
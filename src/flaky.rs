@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --runs support.
+//
+// Tests are run the given number of times, and the profraw files written by
+// each run are merged into a separate profdata file, so the coverage of each run
+// can be compared. Lines that are covered in some runs but not in the others
+// are reported, as they indicate code paths that depend on timing, ordering,
+// randomness, etc., and make coverage-based gates unstable. The report itself
+// is generated from the profile data of all runs, as usual.
+//
+// The profraw files written by each run are identified by the modification
+// time as well as the path, since the profiler runtime may merge into an
+// existing file instead of creating a new one if the process ID is reused.
+
+use std::{collections::BTreeMap, ffi::OsString, mem, path::PathBuf};
+
+use anyhow::{Context as _, Result};
+
+use crate::{
+    context::Context,
+    fs,
+    function_filter::FunctionFilter,
+    json::{CoverageKind, LlvmCovJsonExport},
+    percent, Format,
+};
+
+/// Runs `run_once` the number of times specified by --runs, recording the
+/// profraw files written by each run.
+pub(crate) fn run(
+    cx: &mut Context,
+    mut run_once: impl FnMut(&Context) -> Result<()>,
+) -> Result<()> {
+    let runs = cx.args.runs.unwrap_or(1);
+    let mut modified = BTreeMap::new();
+    for i in 1..=runs {
+        status!("Running", "tests ({i}/{runs})");
+        run_once(cx)?;
+        let mut files = vec![];
+        for path in crate::profraw_files(cx)? {
+            let time = fs::metadata(&path)?.modified()?;
+            if modified.insert(path.clone(), time) != Some(time) {
+                files.push(path);
+            }
+        }
+        cx.runs.push(files);
+    }
+    Ok(())
+}
+
+/// Reports the lines whose coverage differs across the runs.
+pub(crate) fn report(
+    cx: &mut Context,
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+    function_filter: Option<&FunctionFilter>,
+) -> Result<()> {
+    let runs = mem::take(&mut cx.runs);
+    let mut percents = vec![];
+    // file -> line -> number of runs that covered the line
+    let mut lines: BTreeMap<String, BTreeMap<u64, usize>> = BTreeMap::new();
+    for (i, profraw_files) in runs.iter().enumerate() {
+        let json =
+            run_json(cx, i, profraw_files, object_files, ignore_filename_regex, function_filter)
+                .with_context(|| format!("failed to get json for run {}", i + 1))?;
+        percents.push(json.get_coverage_percent(CoverageKind::Lines)?);
+        for file in json.file_names() {
+            let file_lines = lines.entry(file.to_owned()).or_default();
+            for line in json.get_line_coverage(file).unwrap_or_default() {
+                *file_lines.entry(line.line).or_default() += usize::from(line.count != 0);
+            }
+        }
+    }
+
+    let n = runs.len();
+    let min = percents.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = percents.iter().copied().fold(0., f64::max);
+    let mut flaky: Vec<(String, Vec<(u64, usize)>)> = vec![];
+    for (file, file_lines) in lines {
+        let file_flaky: Vec<_> =
+            file_lines.into_iter().filter(|&(_, c)| c != 0 && c != n).collect();
+        if !file_flaky.is_empty() {
+            let file = match file.strip_prefix(cx.ws.metadata.workspace_root.as_str()) {
+                Some(relative) => relative.trim_start_matches(['/', '\\']).to_owned(),
+                None => file,
+            };
+            flaky.push((file, file_flaky));
+        }
+    }
+    if flaky.is_empty() {
+        info!("coverage of all lines is the same across {n} runs");
+        return Ok(());
+    }
+    let count: usize = flaky.iter().map(|(_, lines)| lines.len()).sum();
+    warn!(
+        "coverage of {count} lines differs across {n} runs (line coverage ranged from {}% to {}%)",
+        percent::format(min),
+        percent::format(max)
+    );
+    for (file, lines) in &flaky {
+        let lines: Vec<_> = lines.iter().map(|(line, c)| format!("{line} ({c}/{n})")).collect();
+        eprintln!("    {file}: {}", lines.join(", "));
+    }
+    Ok(())
+}
+
+/// Returns the coverage of the given run.
+fn run_json(
+    cx: &mut Context,
+    i: usize,
+    profraw_files: &[PathBuf],
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+    function_filter: Option<&FunctionFilter>,
+) -> Result<LlvmCovJsonExport> {
+    let profdata_file = cx.ws.target_dir.join(format!("{}-run{i}.profdata", cx.ws.name));
+    let input_files: Vec<_> = profraw_files.iter().filter_map(|f| f.to_str()).collect();
+    crate::merge_profdata_cmd(
+        cx,
+        &input_files,
+        &profdata_file,
+        &format!("profraw-list-run{i}"),
+        false,
+    )?
+    .run()?;
+    let profdata_file = mem::replace(&mut cx.ws.profdata_file, profdata_file);
+    let res =
+        Format::Json.get_json(cx, object_files, ignore_filename_regex, function_filter, false);
+    let profdata_file = mem::replace(&mut cx.ws.profdata_file, profdata_file);
+    fs::remove_file(profdata_file)?;
+    res
+}
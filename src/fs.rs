@@ -3,7 +3,12 @@
 pub(crate) use std::fs::Metadata;
 use std::{ffi::OsStr, io, path::Path};
 
-pub(crate) use fs_err::{create_dir_all, read_dir, symlink_metadata, write};
+#[cfg(unix)]
+pub(crate) use fs_err::set_permissions;
+pub(crate) use fs_err::{
+    canonicalize, copy, create_dir, create_dir_all, metadata, read, read_dir, read_to_string,
+    rename, symlink_metadata, write, File,
+};
 
 /// Removes a file from the filesystem **if exists**.
 pub(crate) fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
@@ -0,0 +1,421 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// llvm-cov only provides allowlist-based function filters (-name, -name-regex,
+// and -name-allowlist), and they are only respected by `llvm-cov show`. So we
+// collect the names of all functions from the JSON export, pass the ones to
+// keep to `llvm-cov show` via -name-allowlist, and remove the others from the
+// output of `llvm-cov export` by ourselves.
+//
+// Refs:
+// - https://llvm.org/docs/CommandGuide/llvm-cov.html#cmdoption-llvm-cov-show-name-allowlist
+// - https://clang.llvm.org/docs/SanitizerSpecialCaseList.html
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    fmt::Write as _,
+    io,
+};
+
+use anyhow::{Context as _, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use regex::Regex;
+
+use crate::{context::Context, fs, json::LlvmCovJsonExport, term, Format};
+
+fn derive_re() -> Regex {
+    Regex::new(concat!(
+        r"^<.+ as (core|std)::(",
+        r"fmt::Debug>::fmt",
+        r"|clone::Clone>::clone(_from)?",
+        r"|cmp::PartialEq>::(eq|ne)",
+        r"|cmp::Eq>::assert_receiver_is_total_eq",
+        r"|cmp::PartialOrd>::(partial_cmp|lt|le|gt|ge)",
+        r"|cmp::Ord>::cmp",
+        r"|hash::Hash>::hash(_slice)?",
+        r"|default::Default>::default",
+        r")(::<.*>)?$",
+        // Serialize/Deserialize impls, including visitors and field identifiers
+        // that are defined inside of them.
+        r"|^<.+ as serde(_core)?::(ser::Serialize>::serialize|de::Deserialize>::deserialize)($|::)",
+    ))
+    .unwrap()
+}
+
+/// Matches attributes that mark functions as tests or benchmarks (e.g.,
+/// `#[test]`, `#[tokio::test]`, `#[bench]`, `#[rstest]`).
+pub(crate) fn test_attr_re() -> Regex {
+    Regex::new(
+        r"#\[\s*(?:\w+\s*::\s*)*(?:test|bench|rstest|test_case|wasm_bindgen_test)\s*(?:\]|\()",
+    )
+    .unwrap()
+}
+
+pub(crate) struct FunctionFilter {
+    /// Mangled names of functions to be excluded from the report.
+    excluded: BTreeSet<String>,
+    /// Mangled names of test functions (including closures defined in them) to
+    /// be excluded from the report along with the code they contain, and the
+    /// lines they span.
+    excluded_with_code: BTreeMap<String, (u64, u64)>,
+    /// Path to the file that lists the functions to be included in the report.
+    allowlist: Utf8PathBuf,
+}
+
+impl FunctionFilter {
+    /// Returns `None` if no function-level filter is requested.
+    pub(crate) fn new(
+        cx: &Context,
+        object_files: &[OsString],
+        ignore_filename_regex: Option<&str>,
+    ) -> Result<Option<Self>> {
+        if !cx.args.cov.exclude_derives && !cx.args.cov.exclude_test_functions {
+            return Ok(None);
+        }
+
+        let json = Format::Json
+            .get_json(cx, object_files, ignore_filename_regex, None, false)
+            .context("failed to get json")?;
+        let mut excluded = BTreeSet::new();
+        if cx.args.cov.exclude_derives {
+            let derive_re = derive_re();
+            for name in json.function_names() {
+                let demangled = format!("{:#}", rustc_demangle::demangle(name));
+                if derive_re.is_match(&demangled) {
+                    excluded.insert(name.to_owned());
+                }
+            }
+            if term::verbose() {
+                info!(
+                    "excluding {} functions generated by derive macros from report",
+                    excluded.len()
+                );
+            }
+        }
+        let mut excluded_with_code = BTreeMap::new();
+        if cx.args.cov.exclude_test_functions {
+            excluded_with_code = test_functions(cx, &json);
+            if term::verbose() {
+                info!(
+                    "excluding {} test functions and their code from report",
+                    excluded_with_code.len()
+                );
+            }
+        }
+        let mut allowlist = String::new();
+        for name in json.function_names() {
+            if !excluded.contains(name) && !excluded_with_code.contains_key(name) {
+                let _ = writeln!(allowlist, "allowlist_fun:{}", escape(name));
+            }
+        }
+
+        let path = cx.ws.target_dir.join(format!("{}-name-allowlist", cx.ws.name));
+        fs::write(&path, allowlist)?;
+        Ok(Some(Self { excluded, excluded_with_code, allowlist: path }))
+    }
+
+    fn is_excluded(&self, name: &str) -> bool {
+        self.excluded.contains(name) || self.excluded_with_code.contains_key(name)
+    }
+
+    /// Returns the mangled names of excluded functions, with the option that
+    /// excludes them and the lines they span if their code is excluded as well.
+    pub(crate) fn excluded(
+        &self,
+    ) -> impl Iterator<Item = (&str, &'static str, Option<(u64, u64)>)> + '_ {
+        let derives = self.excluded.iter().map(|name| (&**name, "--exclude-derives", None));
+        let tests = self
+            .excluded_with_code
+            .iter()
+            .map(|(name, &lines)| (&**name, "--exclude-test-functions", Some(lines)));
+        derives.chain(tests)
+    }
+
+    /// Path to the file to be passed to `llvm-cov show -name-allowlist`.
+    pub(crate) fn allowlist(&self) -> &Utf8Path {
+        &self.allowlist
+    }
+
+    /// Parses JSON exported by llvm-cov, and removes excluded functions while
+    /// parsing.
+    pub(crate) fn read_json(&self, reader: impl io::Read) -> Result<LlvmCovJsonExport> {
+        LlvmCovJsonExport::from_reader(
+            reader,
+            &mut |name| !self.excluded_with_code.contains_key(name),
+            &mut |name| !self.excluded.contains(name),
+        )
+    }
+
+    /// Removes FN/FNDA records of excluded functions from LCOV data, and updates FNF/FNH.
+    ///
+    /// DA/BRDA records in the lines of test functions are also removed, and
+    /// LF/LH/BRF/BRH are updated accordingly.
+    pub(crate) fn apply_to_lcov(&self, lcov: &str, summary_only: bool) -> String {
+        let mut out = String::with_capacity(lcov.len());
+        let mut records = vec![];
+        for line in lcov.lines() {
+            records.push(line);
+            if line == "end_of_record" {
+                self.apply_to_lcov_record(&mut out, &records, summary_only);
+                records.clear();
+            }
+        }
+        for line in records {
+            out.push_str(line);
+            out.push('\n');
+        }
+        if !lcov.ends_with('\n') {
+            out.pop();
+        }
+        out
+    }
+
+    fn apply_to_lcov_record(&self, out: &mut String, record: &[&str], summary_only: bool) {
+        #[derive(Default)]
+        struct Group {
+            covered: bool,
+            kept: bool,
+            kept_covered: bool,
+        }
+
+        // LCOV only has the start line of the function, so group instantiations by it.
+        let mut lines = BTreeMap::new();
+        let mut excluded_lines = vec![];
+        for line in record {
+            if let Some((start, name)) = line.strip_prefix("FN:").and_then(|s| s.split_once(',')) {
+                lines.insert(name, start);
+                if let Some(&range) = self.excluded_with_code.get(name) {
+                    excluded_lines.push(range);
+                }
+            }
+        }
+        let is_excluded_line = |value: &str| {
+            let line = value.split(',').next().and_then(|l| l.parse::<u64>().ok());
+            line.is_some_and(|l| excluded_lines.iter().any(|&(start, end)| start <= l && l <= end))
+        };
+        // (found, hit) of removed lines and branches.
+        let (mut removed_lines, mut removed_branches) = ((0, 0), (0, 0));
+        for line in record {
+            let (kind, value) = line.split_once(':').unwrap_or((line, ""));
+            let removed = match kind {
+                "DA" => &mut removed_lines,
+                "BRDA" => &mut removed_branches,
+                _ => continue,
+            };
+            if is_excluded_line(value) {
+                let count = value.rsplit(',').next().unwrap_or_default();
+                removed.0 += 1;
+                removed.1 += usize::from(count != "0" && count != "-");
+            }
+        }
+        let mut groups: BTreeMap<&str, Group> = BTreeMap::new();
+        for line in record {
+            if let Some((count, name)) = line.strip_prefix("FNDA:").and_then(|s| s.split_once(','))
+            {
+                let Some(&start) = lines.get(name) else { continue };
+                let covered = count != "0";
+                let group = groups.entry(start).or_default();
+                group.covered |= covered;
+                if !self.is_excluded(name) {
+                    group.kept = true;
+                    group.kept_covered |= covered;
+                }
+            }
+        }
+        let removed = groups.values().filter(|g| !g.kept).count();
+        let removed_covered = groups.values().filter(|g| g.covered && !g.kept_covered).count();
+
+        for &line in record {
+            let (kind, value) = line.split_once(':').unwrap_or((line, ""));
+            match kind {
+                "FN" | "FNDA" | "DA" | "BRDA" if summary_only => continue,
+                "FN" | "FNDA"
+                    if value.split_once(',').is_some_and(|(_, name)| self.is_excluded(name)) =>
+                {
+                    continue;
+                }
+                "DA" | "BRDA" if is_excluded_line(value) => continue,
+                "FNF" => {
+                    let n = value.parse::<usize>().unwrap_or_default().saturating_sub(removed);
+                    let _ = writeln!(out, "FNF:{n}");
+                    continue;
+                }
+                "FNH" => {
+                    let n =
+                        value.parse::<usize>().unwrap_or_default().saturating_sub(removed_covered);
+                    let _ = writeln!(out, "FNH:{n}");
+                    continue;
+                }
+                "LF" | "LH" | "BRF" | "BRH" => {
+                    let removed = match kind {
+                        "LF" => removed_lines.0,
+                        "LH" => removed_lines.1,
+                        "BRF" => removed_branches.0,
+                        _ => removed_branches.1,
+                    };
+                    let n = value.parse::<usize>().unwrap_or_default().saturating_sub(removed);
+                    let _ = writeln!(out, "{kind}:{n}");
+                    continue;
+                }
+                _ => {}
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+}
+
+/// Returns the mangled names of test functions and closures defined in them,
+/// and the lines they span.
+///
+/// Test functions are detected by looking for test attributes (see
+/// [`test_attr_re`]) in the source code before the start of the functions.
+fn test_functions(cx: &Context, json: &LlvmCovJsonExport) -> BTreeMap<String, (u64, u64)> {
+    let re = test_attr_re();
+    let functions = json.function_locations();
+    let mut sources: BTreeMap<&str, Option<String>> = BTreeMap::new();
+    // filename -> extents of test functions
+    let mut tests: BTreeMap<&str, Vec<((u64, u64), (u64, u64))>> = BTreeMap::new();
+    for &(_, filename, start, end) in &functions {
+        let source = sources
+            .entry(filename)
+            .or_insert_with(|| fs::read_to_string(cx.source_path(filename)).ok());
+        if source.as_deref().is_some_and(|source| has_test_attr(source, start, &re)) {
+            tests.entry(filename).or_default().push((start, end));
+        }
+    }
+    let mut names = BTreeMap::new();
+    for &(name, filename, start, end) in &functions {
+        let Some(tests) = tests.get(filename) else { continue };
+        if tests.iter().any(|&(test_start, test_end)| test_start <= start && end <= test_end) {
+            names.insert(name.to_owned(), (start.0, end.0));
+        }
+    }
+    names
+}
+
+/// Returns `true` if the function that starts at the given location (line,
+/// column) in the given source code has an attribute matched by the given
+/// regex (e.g., a test attribute).
+pub(crate) fn has_test_attr(source: &str, (line, column): (u64, u64), re: &Regex) -> bool {
+    let Some(index) = usize::try_from(line).ok().and_then(|l| l.checked_sub(1)) else {
+        return false;
+    };
+    let lines: Vec<_> = source.lines().take(index + 1).collect();
+    let Some(first) = lines.get(index) else { return false };
+    let column = usize::try_from(column).unwrap_or(usize::MAX).saturating_sub(1);
+    let mut text = first.get(..column).unwrap_or(first).to_owned();
+    // Attributes are between the end of the previous item (or the start of the
+    // enclosing block) and the function.
+    for l in lines[..index].iter().rev().take(100) {
+        let trimmed = l.trim();
+        if !trimmed.starts_with("//") && trimmed.ends_with(['}', ';', '{']) {
+            break;
+        }
+        text.push('\n');
+        text.push_str(l);
+    }
+    re.is_match(&text)
+}
+
+/// Escapes characters that have special meaning in special case list.
+///
+/// Depending on the LLVM version, entries are interpreted as either glob or
+/// regular expression, so escape characters that are special in either.
+fn escape(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(
+            c,
+            '\\' | '*' | '?' | '[' | ']' | '{' | '}' | '(' | ')' | '.' | '$' | '^' | '+' | '|'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive() {
+        let re = derive_re();
+        for name in [
+            "<foo::Bar as core::fmt::Debug>::fmt",
+            "<foo::Bar<u8> as core::clone::Clone>::clone",
+            "<foo::Bar as core::cmp::PartialEq>::eq",
+            "<foo::Bar as core::cmp::Eq>::assert_receiver_is_total_eq",
+            "<foo::Bar as core::hash::Hash>::hash::<std::hash::random::DefaultHasher>",
+            "<foo::Bar as serde::ser::Serialize>::serialize::<serde_json::ser::Serializer<_>>",
+            "<<foo::Bar as serde::de::Deserialize>::deserialize::__Visitor as serde::de::Visitor>::visit_map::<_>",
+        ] {
+            assert!(re.is_match(name), "{name}");
+        }
+        for name in [
+            "foo::Bar::fmt",
+            "<foo::Bar as core::fmt::Display>::fmt",
+            "<foo::Bar as core::fmt::Debug>::fmt::{closure#0}",
+            "<foo::Bar as core::iter::traits::iterator::Iterator>::eq",
+            "<foo::Bar as foo::Serialize>::serialize",
+        ] {
+            assert!(!re.is_match(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn lcov() {
+        let filter = FunctionFilter {
+            excluded: ["b1".to_owned(), "c".to_owned()].into_iter().collect(),
+            excluded_with_code: BTreeMap::new(),
+            allowlist: Utf8PathBuf::new(),
+        };
+        let lcov = "SF:src/lib.rs\nFN:1,a\nFN:5,b1\nFN:5,b2\nFN:9,c\nFNDA:1,a\nFNDA:1,b1\nFNDA:0,b2\nFNDA:1,c\nFNF:3\nFNH:3\nDA:1,1\nBRF:0\nBRH:0\nLF:1\nLH:1\nend_of_record";
+        assert_eq!(
+            filter.apply_to_lcov(lcov, false),
+            "SF:src/lib.rs\nFN:1,a\nFN:5,b2\nFNDA:1,a\nFNDA:0,b2\nFNF:2\nFNH:1\nDA:1,1\nBRF:0\nBRH:0\nLF:1\nLH:1\nend_of_record"
+        );
+        assert_eq!(
+            filter.apply_to_lcov(lcov, true),
+            "SF:src/lib.rs\nFNF:2\nFNH:1\nBRF:0\nBRH:0\nLF:1\nLH:1\nend_of_record"
+        );
+    }
+
+    #[test]
+    fn lcov_test_functions() {
+        let filter = FunctionFilter {
+            excluded: BTreeSet::new(),
+            excluded_with_code: [("t".to_owned(), (3, 5))].into_iter().collect(),
+            allowlist: Utf8PathBuf::new(),
+        };
+        let lcov = "SF:src/lib.rs\nFN:1,a\nFN:3,t\nFNDA:1,a\nFNDA:1,t\nFNF:2\nFNH:2\nDA:1,1\nDA:2,0\nDA:3,1\nDA:4,1\nDA:5,1\nBRDA:2,0,0,0\nBRDA:2,0,1,-\nBRDA:4,0,0,1\nBRDA:4,0,1,0\nBRF:4\nBRH:1\nLF:5\nLH:4\nend_of_record";
+        assert_eq!(
+            filter.apply_to_lcov(lcov, false),
+            "SF:src/lib.rs\nFN:1,a\nFNDA:1,a\nFNF:1\nFNH:1\nDA:1,1\nDA:2,0\nBRDA:2,0,0,0\nBRDA:2,0,1,-\nBRF:2\nBRH:0\nLF:2\nLH:1\nend_of_record"
+        );
+    }
+
+    #[test]
+    fn test_attr() {
+        let re = test_attr_re();
+        let source = "fn a() {}\n#[test]\nfn b() {}\n#[tokio::test(flavor = \"multi_thread\")]\n/// doc\n#[ignore]\nasync fn c() {}\n#[cfg(test)]\nfn d() {}\n#[bench] fn e() {}\n#[test_log]\nfn f() {}";
+        assert!(!has_test_attr(source, (1, 1), &re));
+        assert!(has_test_attr(source, (3, 1), &re));
+        assert!(has_test_attr(source, (7, 1), &re));
+        assert!(!has_test_attr(source, (9, 1), &re));
+        assert!(has_test_attr(source, (10, 10), &re));
+        assert!(!has_test_attr(source, (12, 1), &re));
+        assert!(!has_test_attr(source, (13, 1), &re));
+    }
+
+    #[test]
+    fn escape_special_chars() {
+        assert_eq!(escape("_RNvCs4fqI2P2rA04_3foo3bar"), "_RNvCs4fqI2P2rA04_3foo3bar");
+        assert_eq!(
+            escape("_ZN4core3ptr33drop_in_place$LT$foo..Bar$GT$17h0123456789abcdefE"),
+            r"_ZN4core3ptr33drop_in_place\$LT\$foo\.\.Bar\$GT\$17h0123456789abcdefE"
+        );
+    }
+}
@@ -0,0 +1,450 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --gate support.
+//
+// A gate is a boolean expression evaluated over the coverage summary, which
+// allows combining conditions that would otherwise need a separate flag each:
+//
+//     lines >= 80 && (branches >= 60 || uncovered_functions == 0) && !regression(lines, 1.0)
+//
+// Grammar:
+//
+//     expr       = and ("||" and)*
+//     and        = unary ("&&" unary)*
+//     unary      = "!" unary | "(" expr ")" | regression | comparison
+//     regression = "regression" "(" kind "," number ")"
+//     comparison = operand ("<" | "<=" | ">" | ">=" | "==" | "!=") operand
+//     operand    = number | kind | "uncovered_" kind
+//     kind       = "lines" | "regions" | "functions" | "branches"
+//
+// `<kind>` is the coverage in percent, and `uncovered_<kind>` is the number of
+// uncovered items. `regression(<kind>, <max>)` is true if the coverage dropped
+// by more than <max> percentage points compared with the baseline report
+// specified by --gate-baseline.
+
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+use anyhow::{bail, format_err, Error, Result};
+
+use crate::{
+    json::{SummaryCounts, SummaryTotals},
+    percent,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Lines,
+    Regions,
+    Functions,
+    Branches,
+}
+
+impl Kind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lines" => Some(Self::Lines),
+            "regions" => Some(Self::Regions),
+            "functions" => Some(Self::Functions),
+            "branches" => Some(Self::Branches),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Lines => "lines",
+            Self::Regions => "regions",
+            Self::Functions => "functions",
+            Self::Branches => "branches",
+        }
+    }
+
+    fn counts(self, totals: &SummaryTotals) -> &SummaryCounts {
+        match self {
+            Self::Lines => &totals.lines,
+            Self::Regions => &totals.regions,
+            Self::Functions => &totals.functions,
+            Self::Branches => &totals.branches,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Var {
+    /// Coverage in percent.
+    Percent(Kind),
+    /// Number of uncovered items.
+    Uncovered(Kind),
+}
+
+impl Var {
+    #[allow(clippy::cast_precision_loss)]
+    fn value(self, totals: &SummaryTotals) -> f64 {
+        match self {
+            Self::Percent(kind) => kind.counts(totals).percent,
+            Self::Uncovered(kind) => {
+                let counts = kind.counts(totals);
+                counts.count.saturating_sub(counts.covered) as f64
+            }
+        }
+    }
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Percent(kind) => f.write_str(kind.as_str()),
+            Self::Uncovered(kind) => write!(f, "uncovered_{}", kind.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Number(f64),
+    Var(Var),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Operand, Op, Operand),
+    Regression(Kind, f64),
+}
+
+/// A parsed --gate expression.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Gate {
+    source: String,
+    expr: Expr,
+}
+
+impl FromStr for Gate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || {
+            let mut parser = Parser { tokens: tokenize(s)?, pos: 0 };
+            let expr = parser.expr()?;
+            if let Some(token) = parser.tokens.get(parser.pos) {
+                bail!("unexpected `{token}`");
+            }
+            Ok(expr)
+        };
+        let expr = parse().map_err(|e| format_err!("invalid --gate `{s}`: {e}"))?;
+        Ok(Self { source: s.to_owned(), expr })
+    }
+}
+
+impl Gate {
+    /// Returns `true` if the expression uses `regression()`, which requires a
+    /// baseline.
+    pub(crate) fn uses_baseline(&self) -> bool {
+        fn visit(expr: &Expr) -> bool {
+            match expr {
+                Expr::Not(e) => visit(e),
+                Expr::And(a, b) | Expr::Or(a, b) => visit(a) || visit(b),
+                Expr::Compare(..) => false,
+                Expr::Regression(..) => true,
+            }
+        }
+        visit(&self.expr)
+    }
+
+    /// Evaluates the expression, and reports an error with the values it refers
+    /// to if it is false.
+    pub(crate) fn check(&self, totals: &SummaryTotals, baseline: Option<&SummaryTotals>) {
+        if eval(&self.expr, totals, baseline) {
+            return;
+        }
+        error!("coverage gate `{}` failed", self.source);
+        let mut vars = BTreeSet::new();
+        collect_vars(&self.expr, &mut vars);
+        for var in vars {
+            let value = var.value(totals);
+            match (var, baseline) {
+                (Var::Percent(_), Some(baseline)) => {
+                    eprintln!(
+                        "    {var}: {}% (baseline: {}%)",
+                        percent::format(value),
+                        percent::format(var.value(baseline))
+                    );
+                }
+                (Var::Percent(_), None) => eprintln!("    {var}: {}%", percent::format(value)),
+                (Var::Uncovered(_), _) => eprintln!("    {var}: {value}"),
+            }
+        }
+    }
+}
+
+fn eval(expr: &Expr, totals: &SummaryTotals, baseline: Option<&SummaryTotals>) -> bool {
+    match expr {
+        Expr::Not(e) => !eval(e, totals, baseline),
+        Expr::And(a, b) => eval(a, totals, baseline) && eval(b, totals, baseline),
+        Expr::Or(a, b) => eval(a, totals, baseline) || eval(b, totals, baseline),
+        Expr::Compare(lhs, op, rhs) => {
+            let value = |operand: &Operand| match *operand {
+                Operand::Number(n) => n,
+                Operand::Var(var) => var.value(totals),
+            };
+            let (lhs, rhs) = (value(lhs), value(rhs));
+            match op {
+                Op::Lt => lhs < rhs,
+                Op::Le => lhs <= rhs,
+                Op::Gt => lhs > rhs,
+                Op::Ge => lhs >= rhs,
+                Op::Eq => (lhs - rhs).abs() < f64::EPSILON,
+                Op::Ne => (lhs - rhs).abs() >= f64::EPSILON,
+            }
+        }
+        Expr::Regression(kind, max) => {
+            // Parsing the arguments ensures a baseline is given if regression() is used.
+            let Some(baseline) = baseline else { return false };
+            kind.counts(baseline).percent - kind.counts(totals).percent > *max
+        }
+    }
+}
+
+fn collect_vars(expr: &Expr, vars: &mut BTreeSet<Var>) {
+    match expr {
+        Expr::Not(e) => collect_vars(e, vars),
+        Expr::And(a, b) | Expr::Or(a, b) => {
+            collect_vars(a, vars);
+            collect_vars(b, vars);
+        }
+        Expr::Compare(lhs, _, rhs) => {
+            for operand in [lhs, rhs] {
+                if let Operand::Var(var) = operand {
+                    vars.insert(*var);
+                }
+            }
+        }
+        Expr::Regression(kind, _) => {
+            vars.insert(Var::Percent(*kind));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Punct(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Ident(s) => f.write_str(s),
+            Self::Punct(s) => f.write_str(s),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    const PUNCTS: &[&str] = &["&&", "||", "<=", ">=", "==", "!=", "<", ">", "!", "(", ")", ","];
+    let mut tokens = vec![];
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        if let Some(&punct) = PUNCTS.iter().find(|&&p| rest.starts_with(p)) {
+            tokens.push(Token::Punct(punct));
+            rest = &rest[punct.len()..];
+        } else if rest.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            let end = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+            let n = rest[..end]
+                .parse()
+                .map_err(|_| format_err!("invalid number `{}`", &rest[..end]))?;
+            tokens.push(Token::Number(n));
+            rest = &rest[end..];
+        } else if rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            let end =
+                rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_owned()));
+            rest = &rest[end..];
+        } else {
+            bail!("unexpected `{}`", rest.chars().next().unwrap());
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, expected: &str) -> Error {
+        match self.tokens.get(self.pos) {
+            Some(token) => format_err!("expected {expected}, but found `{token}`"),
+            None => format_err!("expected {expected}, but found end of expression"),
+        }
+    }
+
+    fn eat(&mut self, punct: &'static str) -> bool {
+        if self.tokens.get(self.pos) == Some(&Token::Punct(punct)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &'static str) -> Result<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("`{punct}`")))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut lhs = self.and()?;
+        while self.eat("||") {
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        while self.eat("&&") {
+            lhs = Expr::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.expr()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        if self.tokens.get(self.pos) == Some(&Token::Ident("regression".to_owned())) {
+            self.pos += 1;
+            self.expect("(")?;
+            let kind = match self.tokens.get(self.pos) {
+                Some(Token::Ident(name)) => Kind::from_name(name),
+                _ => None,
+            }
+            .ok_or_else(|| self.error("lines, regions, functions, or branches"))?;
+            self.pos += 1;
+            self.expect(",")?;
+            let Some(&Token::Number(max)) = self.tokens.get(self.pos) else {
+                return Err(self.error("number"));
+            };
+            self.pos += 1;
+            self.expect(")")?;
+            return Ok(Expr::Regression(kind, max));
+        }
+        let lhs = self.operand()?;
+        let op = match self.tokens.get(self.pos) {
+            Some(Token::Punct("<")) => Op::Lt,
+            Some(Token::Punct("<=")) => Op::Le,
+            Some(Token::Punct(">")) => Op::Gt,
+            Some(Token::Punct(">=")) => Op::Ge,
+            Some(Token::Punct("==")) => Op::Eq,
+            Some(Token::Punct("!=")) => Op::Ne,
+            _ => return Err(self.error("comparison operator")),
+        };
+        self.pos += 1;
+        let rhs = self.operand()?;
+        Ok(Expr::Compare(lhs, op, rhs))
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        let operand = match self.tokens.get(self.pos) {
+            Some(&Token::Number(n)) => Some(Operand::Number(n)),
+            Some(Token::Ident(name)) => match name.strip_prefix("uncovered_") {
+                Some(kind) => Kind::from_name(kind).map(|k| Operand::Var(Var::Uncovered(k))),
+                None => Kind::from_name(name).map(|k| Operand::Var(Var::Percent(k))),
+            },
+            _ => None,
+        };
+        let operand = operand.ok_or_else(|| self.error("number or coverage kind"))?;
+        self.pos += 1;
+        Ok(operand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn totals(lines: (u64, u64), branches: (u64, u64)) -> SummaryTotals {
+        let counts = |(count, covered): (u64, u64)| SummaryCounts {
+            count,
+            covered,
+            #[allow(clippy::cast_precision_loss)]
+            percent: if count == 0 { 0. } else { covered as f64 * 100. / count as f64 },
+        };
+        SummaryTotals {
+            lines: counts(lines),
+            branches: counts(branches),
+            ..SummaryTotals::default()
+        }
+    }
+
+    fn eval(s: &str, totals: &SummaryTotals, baseline: Option<&SummaryTotals>) -> bool {
+        super::eval(&s.parse::<Gate>().unwrap().expr, totals, baseline)
+    }
+
+    #[test]
+    fn parse() {
+        for s in [
+            "lines >= 80",
+            "lines>=80&&branches>=60",
+            "!(lines < 80 || uncovered_functions != 0)",
+            "regression(lines, 1.0) || 50 <= regions",
+        ] {
+            s.parse::<Gate>().unwrap();
+        }
+        for (s, msg) in [
+            ("", "expected number or coverage kind, but found end of expression"),
+            ("lines", "expected comparison operator, but found end of expression"),
+            ("lines >= 80 &&", "expected number or coverage kind, but found end of expression"),
+            ("(lines >= 80", "expected `)`, but found end of expression"),
+            ("lines >= 80)", "unexpected `)`"),
+            ("line >= 80", "expected number or coverage kind, but found `line`"),
+            ("regression(lines)", "expected `,`, but found `)`"),
+            ("lines >= 1.2.3", "invalid number `1.2.3`"),
+            ("lines >= 80 & regions >= 80", "unexpected `&`"),
+        ] {
+            let err = s.parse::<Gate>().unwrap_err().to_string();
+            assert!(err.contains(msg), "{s}: {err}");
+        }
+        assert!(!"lines >= 80".parse::<Gate>().unwrap().uses_baseline());
+        assert!("!regression(lines, 1)".parse::<Gate>().unwrap().uses_baseline());
+    }
+
+    #[test]
+    fn evaluate() {
+        let current = totals((100, 75), (10, 6));
+        let baseline = totals((100, 80), (10, 6));
+        assert!(eval("lines >= 75 && branches >= 60", &current, None));
+        assert!(!eval("lines >= 80 && branches >= 60", &current, None));
+        assert!(eval("lines >= 80 || branches >= 60", &current, None));
+        assert!(eval("uncovered_lines == 25 && uncovered_branches < 5", &current, None));
+        assert!(eval("!(lines > 75)", &current, None));
+        assert!(eval("regression(lines, 4.5)", &current, Some(&baseline)));
+        assert!(!eval("regression(lines, 5)", &current, Some(&baseline)));
+        assert!(!eval("regression(branches, 0)", &current, Some(&baseline)));
+    }
+}
@@ -0,0 +1,340 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --output-types support.
+//
+// This writes reports in the output types of grcov, with the file names that
+// grcov uses when its output path is a directory, so pipelines that consume
+// reports generated by grcov can switch to cargo-llvm-cov without changes:
+// `lcov` (lcov), `cobertura.xml` (cobertura), `html/` (html), and `coveralls`
+// (coveralls and coveralls+). coveralls+ is the Coveralls JSON with the
+// functions of each source file.
+//
+// The fields of the Coveralls JSON other than the source files are taken from
+// the environment variables that other Coveralls clients also use
+// (COVERALLS_REPO_TOKEN, COVERALLS_SERVICE_NAME, COVERALLS_SERVICE_NUMBER,
+// COVERALLS_SERVICE_JOB_ID, COVERALLS_FLAG_NAME, and COVERALLS_PARALLEL), and
+// the commit checked out in the workspace. Parallel builds are closed by
+// `cargo llvm-cov coveralls-finish` (see coveralls.rs).
+//
+// Refs:
+// - https://github.com/mozilla/grcov#usage
+// - https://docs.coveralls.io/api-reference
+
+use std::{ffi::OsString, fmt, fmt::Write as _, str::FromStr};
+
+use anyhow::{bail, Context as _, Error, Result};
+use camino::Utf8Path;
+use serde_derive::Serialize;
+
+use crate::{context::Context, env, fs, function_filter::FunctionFilter, json::LcovExport, Format};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputType {
+    Lcov,
+    Cobertura,
+    Html,
+    Coveralls,
+    CoverallsPlus,
+}
+
+impl OutputType {
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            Self::Lcov => "lcov",
+            Self::Cobertura => "cobertura",
+            Self::Html => "html",
+            Self::Coveralls => "coveralls",
+            Self::CoverallsPlus => "coveralls+",
+        }
+    }
+}
+
+impl fmt::Display for OutputType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OutputType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lcov" => Ok(Self::Lcov),
+            "cobertura" => Ok(Self::Cobertura),
+            "html" => Ok(Self::Html),
+            "coveralls" => Ok(Self::Coveralls),
+            "coveralls+" => Ok(Self::CoverallsPlus),
+            _ => bail!(
+                "--output-types must be a comma-separated list of lcov, cobertura, html, \
+                 coveralls, or coveralls+, but found `{s}`"
+            ),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CoverallsJson {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repo_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service_job_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git: Option<CoverallsGit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flag_name: Option<String>,
+    parallel: bool,
+    source_files: Vec<CoverallsSourceFile>,
+}
+
+#[derive(Serialize)]
+struct CoverallsGit {
+    head: CoverallsHead,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CoverallsHead {
+    id: String,
+}
+
+#[derive(Serialize)]
+struct CoverallsSourceFile {
+    name: String,
+    /// MD5 digest of the source file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_digest: Option<String>,
+    /// Execution count of each line, or `None` if the line has no coverage data
+    coverage: Vec<Option<u64>>,
+    /// [line, block, branch, hits] of each branch
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    branches: Vec<u64>,
+    /// Only in coveralls+.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    functions: Option<Vec<CoverallsFunction>>,
+}
+
+#[derive(Serialize)]
+struct CoverallsFunction {
+    name: String,
+    start: u64,
+    exec: bool,
+}
+
+/// Writes the reports of the output types specified by --output-types.
+pub(crate) fn generate_reports(
+    cx: &mut Context,
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+    function_filter: Option<&FunctionFilter>,
+) -> Result<()> {
+    let output_dir = cx.args.cov.output_dir.clone();
+    let dir = output_dir.clone().unwrap_or_else(|| cx.ws.output_dir.clone());
+    fs::create_dir_all(&dir)?;
+    for ty in cx.args.cov.output_types.clone() {
+        let res = match ty {
+            OutputType::Lcov | OutputType::Cobertura => {
+                let (format, file) = if ty == OutputType::Lcov {
+                    (Format::LCov, "lcov")
+                } else {
+                    (Format::Cobertura, "cobertura.xml")
+                };
+                cx.args.cov.output_path = Some(dir.join(file));
+                format.generate_report(cx, object_files, ignore_filename_regex, function_filter)
+            }
+            OutputType::Html => {
+                cx.args.cov.output_dir = Some(dir.clone());
+                Format::Html.generate_report(
+                    cx,
+                    object_files,
+                    ignore_filename_regex,
+                    function_filter,
+                )
+            }
+            OutputType::Coveralls | OutputType::CoverallsPlus => write_coveralls(
+                cx,
+                object_files,
+                ignore_filename_regex,
+                function_filter,
+                ty == OutputType::CoverallsPlus,
+                &dir.join("coveralls"),
+            ),
+        };
+        cx.args.cov.output_path = None;
+        cx.args.cov.output_dir.clone_from(&output_dir);
+        res.with_context(|| format!("failed to generate {ty} report"))?;
+    }
+    Ok(())
+}
+
+fn write_coveralls(
+    cx: &Context,
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+    function_filter: Option<&FunctionFilter>,
+    with_functions: bool,
+    path: &Utf8Path,
+) -> Result<()> {
+    let mut json = Format::Json
+        .get_json(cx, object_files, ignore_filename_regex, function_filter, false)
+        .context("failed to get json")?;
+    if with_functions {
+        let mut demangler = crate::demangler(cx);
+        demangler.prepare_cpp(json.function_names())?;
+        json.map_function_names(|name| demangler.demangle(name));
+    }
+    cx.path_map.apply_to_json(&mut json);
+    let lcov = LcovExport::from_llvm_cov_json_export(&json);
+
+    let workspace_root = &cx.ws.metadata.workspace_root;
+    let mut source_files = vec![];
+    for record in &lcov.records {
+        let file = cx.source_path(&record.source_file);
+        // Coveralls expects relative paths, like the paths in grcov's reports.
+        let name = cx.relative_source_path(&record.source_file).unwrap_or_else(|| file.clone());
+        let name = name.as_str().replace('\\', "/");
+        let source = fs::read(&file).ok();
+        let source_lines =
+            source.as_ref().map_or(0, |s| String::from_utf8_lossy(s).lines().count());
+        let last = record.lines.iter().map(|l| l.line).max().unwrap_or(0);
+        let mut coverage = vec![None; usize::try_from(last)?.max(source_lines)];
+        for l in &record.lines {
+            let Some(i) = usize::try_from(l.line)?.checked_sub(1) else { continue };
+            coverage[i] = Some(coverage[i].unwrap_or(0) + l.execution_count);
+        }
+        let branches = record
+            .branches
+            .iter()
+            .flat_map(|b| [b.line, b.block, b.branch, b.taken.unwrap_or(0)])
+            .collect();
+        let functions = with_functions.then(|| {
+            record
+                .functions
+                .iter()
+                .map(|f| CoverallsFunction {
+                    name: f.name.clone(),
+                    start: f.line,
+                    exec: f.execution_count > 0,
+                })
+                .collect()
+        });
+        source_files.push(CoverallsSourceFile {
+            name,
+            source_digest: source.as_deref().map(md5),
+            coverage,
+            branches,
+            functions,
+        });
+    }
+    let git = crate::git_commit(workspace_root).map(|commit| CoverallsGit {
+        head: CoverallsHead { id: commit.sha },
+        branch: commit.branch,
+    });
+    let coveralls = CoverallsJson {
+        repo_token: env::var("COVERALLS_REPO_TOKEN")?,
+        service_name: env::var("COVERALLS_SERVICE_NAME")?,
+        service_number: env::var("COVERALLS_SERVICE_NUMBER")?,
+        service_job_id: env::var("COVERALLS_SERVICE_JOB_ID")?,
+        git,
+        flag_name: env::var("COVERALLS_FLAG_NAME")?,
+        parallel: env::var("COVERALLS_PARALLEL")?.is_some_and(|v| v == "true"),
+        source_files,
+    };
+    fs::write(path, serde_json::to_vec(&coveralls)?)?;
+    eprintln!();
+    status!("Finished", "report saved to {path}");
+    Ok(())
+}
+
+/// Returns the MD5 digest of the given data as a lowercase hex string.
+// https://datatracker.ietf.org/doc/html/rfc1321
+#[allow(clippy::many_single_char_names, clippy::unreadable_literal)] // Follow the names in the spec.
+fn md5(data: &[u8]) -> String {
+    const S: [u32; 64] = [
+        7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5,
+        9, 14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10,
+        15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+    ];
+    const K: [u32; 64] = [
+        0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613,
+        0xfd469501, 0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193,
+        0xa679438e, 0x49b40821, 0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d,
+        0x02441453, 0xd8a1e681, 0xe7d3fbc8, 0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed,
+        0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a, 0xfffa3942, 0x8771f681, 0x6d9d6122,
+        0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70, 0x289b7ec6, 0xeaa127fa,
+        0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665, 0xf4292244,
+        0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+        0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb,
+        0xeb86d391,
+    ];
+    let mut h: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut m = [0_u32; 16];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            m[i] = u32::from_le_bytes(word.try_into().unwrap());
+        }
+        let [mut a, mut b, mut c, mut d] = h;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(S[i]));
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut out = String::with_capacity(32);
+    for b in h.iter().flat_map(|v| v.to_le_bytes()) {
+        let _ = write!(out, "{b:02x}");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md5() {
+        assert_eq!(md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        // Longer than a block.
+        assert_eq!(md5(&[b'a'; 100]), "36a92cc94a9e0fa21f625f8bfb007adf");
+    }
+
+    #[test]
+    fn parse_output_type() {
+        for ty in [
+            OutputType::Lcov,
+            OutputType::Cobertura,
+            OutputType::Html,
+            OutputType::Coveralls,
+            OutputType::CoverallsPlus,
+        ] {
+            assert_eq!(ty.as_str().parse::<OutputType>().unwrap(), ty);
+        }
+        assert!("covdir".parse::<OutputType>().is_err());
+    }
+}
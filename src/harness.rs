@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Support for test targets with custom test harnesses (`harness = false`, e.g.,
+// ones using libtest-mimic).
+//
+// Such test binaries are ordinary executables, so they are instrumented and run
+// in the same way as the ones using libtest: they inherit the environment
+// variables set by cargo-llvm-cov (including LLVM_PROFILE_FILE), and the
+// profiler runtime writes the profile data when the process exits normally.
+// Since the file name contains the process ID (%p), each process (e.g., a child
+// process spawned by the harness to run a test in isolation) writes its own
+// file, as long as it inherits LLVM_PROFILE_FILE.
+//
+// However, the profile data is not written if the process is terminated without
+// running the exit handlers (e.g., by `std::process::abort`, `libc::_exit`, or a
+// signal), which custom harnesses are more likely to do than libtest. So after
+// running tests, this warns about the test binaries with custom harnesses whose
+// binary IDs don't appear in any profile data.
+//
+// cargo metadata doesn't provide the `harness` key of targets, so this reads the
+// manifests of the workspace members.
+//
+// Refs:
+// - https://doc.rust-lang.org/nightly/cargo/reference/cargo-targets.html#the-harness-field
+// - https://clang.llvm.org/docs/SourceBasedCodeCoverage.html#running-the-instrumented-program
+
+use std::{collections::BTreeSet, path::Path};
+
+use anyhow::{Context as _, Result};
+use toml::{Table, Value};
+
+use crate::{context::Context, fs, per_binary, toolchain};
+
+/// Returns the names of the test and bench targets with `harness = false` in the
+/// given manifest.
+fn custom_harness_targets(manifest: &Table) -> Vec<String> {
+    let mut targets = vec![];
+    for kind in ["test", "bench"] {
+        let Some(Value::Array(tables)) = manifest.get(kind) else { continue };
+        for table in tables.iter().filter_map(Value::as_table) {
+            if table.get("harness").and_then(Value::as_bool) != Some(false) {
+                continue;
+            }
+            // The name is inferred from the path if omitted.
+            let name = match (table.get("name"), table.get("path")) {
+                (Some(Value::String(name)), _) => name.clone(),
+                (None, Some(Value::String(path))) => {
+                    let Some(stem) = Path::new(path).file_stem() else { continue };
+                    stem.to_string_lossy().into_owned()
+                }
+                _ => continue,
+            };
+            targets.push(name);
+        }
+    }
+    targets
+}
+
+/// Warns about the test binaries with custom harnesses that were built but
+/// generated no profile data.
+///
+/// This does nothing if the binary IDs are not recorded in the profile data
+/// (e.g., the platform doesn't use ELF).
+pub(crate) fn check(cx: &Context) -> Result<()> {
+    let mut targets = vec![];
+    for id in &cx.workspace_members.included {
+        let package = &cx.ws.metadata.packages[id];
+        if cx.args.exclude_from_test.contains(&package.name) {
+            continue;
+        }
+        let manifest_path = &package.manifest_path;
+        let manifest: Table = toml::from_str(&fs::read_to_string(manifest_path)?)
+            .with_context(|| format!("failed to parse {manifest_path}"))?;
+        for name in custom_harness_targets(&manifest) {
+            targets.push((package.name.as_str(), name));
+        }
+    }
+    if targets.is_empty() {
+        return Ok(());
+    }
+
+    let mut ids = BTreeSet::new();
+    for file in crate::profraw_files(cx)? {
+        let Some(file_ids) = toolchain::raw_profile_binary_ids(&file) else { return Ok(()) };
+        if file_ids.is_empty() {
+            return Ok(());
+        }
+        ids.extend(file_ids);
+    }
+    let object_files = crate::object_files(cx)?;
+    for (package, name) in targets {
+        // Test binaries are `deps/<target-name>-<hash>`, where `-` in the
+        // target name is replaced with `_`.
+        let binary_name = name.replace('-', "_");
+        for f in &object_files {
+            let path = Path::new(f);
+            if path.parent().and_then(Path::file_name).is_none_or(|d| d != "deps")
+                || !fs::file_stem_recursive(path).is_some_and(|s| {
+                    s.to_string_lossy().rsplit_once('-').is_some_and(|(n, _)| n == binary_name)
+                })
+            {
+                continue;
+            }
+            let Some(id) = fs::read(path).ok().and_then(|data| per_binary::build_id(&data)) else {
+                continue;
+            };
+            if !ids.contains(&id) {
+                warn!(
+                    "`{name}` target of `{package}` (harness = false) generated no profile data; \
+                     profile data is written only when the process exits normally, so it is lost \
+                     if the process is terminated by `std::process::abort`, a signal, etc."
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets() {
+        let manifest = toml::from_str(
+            r#"
+[package]
+name = "a"
+
+[[test]]
+name = "libtest"
+
+[[test]]
+name = "mimic"
+harness = false
+
+[[test]]
+path = "tests/by-path.rs"
+harness = false
+
+[[bench]]
+name = "criterion"
+harness = false
+"#,
+        )
+        .unwrap();
+        assert_eq!(custom_harness_targets(&manifest), ["mimic", "by-path", "criterion"]);
+    }
+}
@@ -0,0 +1,112 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// `cargo llvm-cov install-hook` support.
+//
+// This installs a git hook that runs tests with --fail-under-diff-lines before
+// pushes. The hook uses --no-clean so that the build artifacts of the previous
+// runs are reused, and only the crates affected by the changes are rebuilt.
+// The base of the changes is the commit of the remote ref being updated, or
+// the default of --diff-base (the upstream branch) if the ref is new.
+//
+// The installed hook has a marker comment, and hooks without the marker (i.e.,
+// hooks not installed by cargo-llvm-cov) are never overwritten.
+
+use std::fmt::Write as _;
+
+use anyhow::{bail, Context as _, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    cargo::Workspace,
+    cli::{self, Args},
+    fs, term,
+};
+
+const MARKER: &str = "# This hook is managed by cargo-llvm-cov.";
+
+pub(crate) fn run(args: &mut Args) -> Result<()> {
+    let ws = Workspace::new(&args.manifest, None, false, false)?;
+    cli::merge_config_to_args(&ws, &mut None, &mut args.verbose, &mut args.color);
+    term::set_coloring(&mut args.color);
+
+    let workspace_root = &ws.metadata.workspace_root;
+    let git = |args: &[&str]| cmd!("git").args(args).dir(workspace_root.as_std_path()).read();
+    let root: Utf8PathBuf = git(&["rev-parse", "--show-toplevel"])
+        .context("`cargo llvm-cov install-hook` requires the workspace to be in a git repository")?
+        .into();
+    // This respects core.hooksPath.
+    let hooks_dir = Utf8PathBuf::from(git(&["rev-parse", "--git-path", "hooks"])?);
+    let hook = workspace_root.join(hooks_dir).join("pre-push");
+
+    if hook.exists() && !fs::read_to_string(&hook)?.contains(MARKER) {
+        bail!(
+            "{hook} already exists and is not managed by cargo-llvm-cov; remove it, or call \
+             `cargo llvm-cov --fail-under-diff-lines` from it manually"
+        );
+    }
+    // Cargo.toml at the root of the repository is used if --manifest-path is not passed.
+    let manifest_path = ws.current_manifest.strip_prefix(&root).ok().filter(|p| *p != "Cargo.toml");
+    let script = pre_push_script(
+        args.cov.fail_under_diff_lines.unwrap(),
+        args.cov.diff_base.as_deref(),
+        manifest_path,
+    );
+    if let Some(dir) = hook.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&hook, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755))?;
+    }
+    status!("Installed", "pre-push hook to {hook}");
+    Ok(())
+}
+
+fn pre_push_script(min: f64, diff_base: Option<&str>, manifest_path: Option<&Utf8Path>) -> String {
+    let mut command =
+        format!("cargo llvm-cov --no-clean --summary-only --fail-under-diff-lines {min}");
+    if let Some(manifest_path) = manifest_path {
+        let _ = write!(command, " --manifest-path {}", shell_escape(manifest_path.as_str()));
+    }
+    let default_base = match diff_base {
+        Some(base) => format!(" --diff-base {}", shell_escape(base)),
+        None => String::new(),
+    };
+    format!(
+        r#"#!/bin/sh
+{MARKER}
+# Regenerate it with `cargo llvm-cov install-hook --pre-push`, or remove this file to uninstall.
+set -eu
+
+pushed=''
+base=''
+while read -r local_ref local_sha remote_ref remote_sha; do
+    case "${{local_sha}}" in
+        *[!0]*) pushed=1 ;;
+        *) continue ;; # deleted refs
+    esac
+    # Compare with the commit of the remote ref if it is known locally.
+    case "${{remote_sha}}" in
+        *[!0]*) if git cat-file -e "${{remote_sha}}^{{commit}}" 2>/dev/null; then base="${{remote_sha}}"; fi ;;
+    esac
+done
+if [ -z "${{pushed}}" ]; then
+    exit 0
+fi
+if [ -n "${{base}}" ]; then
+    exec {command} --diff-base "${{base}}"
+fi
+exec {command}{default_base}
+"#
+    )
+}
+
+fn shell_escape(s: &str) -> String {
+    if s.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_./@:".contains(&b)) {
+        s.to_owned()
+    } else {
+        format!("'{}'", s.replace('\'', r"'\''"))
+    }
+}
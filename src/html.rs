@@ -0,0 +1,342 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// `cargo llvm-cov html --from-lcov <FILE>` support.
+//
+// This renders an html report from a report in the lcov format, like genhtml
+// of the lcov project, so reports exported on another machine or merged with
+// the coverage of code in other languages can be viewed without installing
+// the Perl lcov toolchain. Records of the same source file are merged. Source
+// files are read from the paths in the report (relative paths are relative to
+// the workspace root), and files that can't be read are rendered with only the
+// execution counts.
+
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use anyhow::{Context as _, Result};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+
+use crate::{
+    cargo::Workspace,
+    cli::{self, Args},
+    external_report, fs,
+    json::{xml_escape, LcovBranch, LcovExport, LcovFunction, LcovLine, LcovRecord},
+    term,
+};
+
+const STYLE: &str = "
+body { font-family: sans-serif; }
+table { border-collapse: collapse; }
+td, th { padding: 0 8px; text-align: left; }
+td.number { text-align: right; }
+pre { margin: 0; }
+tr.covered td.count { background-color: #c8f0c8; }
+tr.partial td.count { background-color: #f8f0c0; }
+tr.uncovered { background-color: #f8c8c8; }
+";
+
+pub(crate) fn run(args: &mut Args) -> Result<()> {
+    let ws = Workspace::new(&args.manifest, None, false, false)?;
+    cli::merge_config_to_args(&ws, &mut None, &mut args.verbose, &mut args.color);
+    term::set_coloring(&mut args.color);
+
+    let lcov_path = args.from_lcov.as_ref().unwrap();
+    let lcov: LcovExport = fs::read_to_string(lcov_path)?
+        .parse()
+        .with_context(|| format!("failed to parse {lcov_path}"))?;
+
+    let workspace_root = &ws.metadata.workspace_root;
+    let mut sources = vec![lcov_path.as_str()];
+    let sections = if args.cov.external_reports.is_empty() {
+        vec![(String::new(), merge(&lcov))]
+    } else {
+        sources.extend(external_report::paths(&args.cov.external_reports));
+        external_report::sections(&args.cov.external_reports, lcov, workspace_root)?
+    };
+    let output_dir = args.cov.output_dir.as_ref().unwrap_or(&ws.output_dir).join("html");
+    write(workspace_root, &output_dir, &sources, &sections)?;
+
+    eprintln!();
+    status!("Finished", "report saved to {output_dir}");
+    if args.cov.open {
+        let index = output_dir.join("index.html");
+        status!("Opening", "{index}");
+        crate::open_report(&ws, args.cov.browser.as_deref(), &index)?;
+    }
+    Ok(())
+}
+
+/// Writes an html report of the given sections of records to the given directory.
+///
+/// The index lists the files of each section with its totals, unless there is
+/// only one section without a name.
+pub(crate) fn write(
+    workspace_root: &Utf8Path,
+    output_dir: &Utf8Path,
+    sources: &[&str],
+    sections: &[(String, Vec<LcovRecord>)],
+) -> Result<()> {
+    fs::remove_dir_all(output_dir)?;
+    fs::create_dir_all(output_dir)?;
+    fs::write(output_dir.join("style.css"), STYLE)?;
+    let mut index = vec![];
+    for (name, records) in sections {
+        let mut files = vec![];
+        for record in records {
+            let source_path = workspace_root.join(&record.source_file);
+            let page = page_path(workspace_root, &source_path);
+            let source = fs::read_to_string(&source_path).ok();
+            let out = output_dir.join(&page);
+            fs::create_dir_all(out.parent().unwrap())?;
+            fs::write(out, file_page(record, &page, source.as_deref()))?;
+            files.push((record, page));
+        }
+        index.push((name.as_str(), files));
+    }
+    fs::write(output_dir.join("index.html"), index_page(sources, &index))?;
+    Ok(())
+}
+
+/// Merges records of the same source file, and sorts them by path.
+pub(crate) fn merge(lcov: &LcovExport) -> Vec<LcovRecord> {
+    #[derive(Default)]
+    struct Merged {
+        functions: BTreeMap<(u64, String), u64>,
+        lines: BTreeMap<u64, u64>,
+        branches: BTreeMap<(u64, u64, u64), Option<u64>>,
+    }
+    let mut files: BTreeMap<&str, Merged> = BTreeMap::new();
+    for r in &lcov.records {
+        let file = files.entry(&r.source_file).or_default();
+        for f in &r.functions {
+            *file.functions.entry((f.line, f.name.clone())).or_default() += f.execution_count;
+        }
+        for l in &r.lines {
+            *file.lines.entry(l.line).or_default() += l.execution_count;
+        }
+        for b in &r.branches {
+            let taken = file.branches.entry((b.line, b.block, b.branch)).or_default();
+            if let Some(n) = b.taken {
+                *taken = Some(taken.unwrap_or(0) + n);
+            }
+        }
+    }
+    files
+        .into_iter()
+        .map(|(source_file, file)| LcovRecord {
+            source_file: source_file.to_owned(),
+            functions: file
+                .functions
+                .into_iter()
+                .map(|((line, name), execution_count)| LcovFunction { name, line, execution_count })
+                .collect(),
+            lines: file
+                .lines
+                .into_iter()
+                .map(|(line, execution_count)| LcovLine { line, execution_count })
+                .collect(),
+            branches: file
+                .branches
+                .into_iter()
+                .map(|((line, block, branch), taken)| LcovBranch { line, block, branch, taken })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Returns the path to the page of the given source file, relative to the html directory.
+///
+/// Like the html report generated by llvm-cov, the page is at
+/// `coverage/<path>.html`, where the path is relative to the workspace root if
+/// the file is in the workspace.
+fn page_path(workspace_root: &Utf8Path, source_path: &Utf8Path) -> Utf8PathBuf {
+    let path = source_path.strip_prefix(workspace_root).unwrap_or(source_path);
+    let mut page = Utf8PathBuf::from("coverage");
+    for c in path.components() {
+        if let Utf8Component::Normal(c) = c {
+            page.push(c);
+        }
+    }
+    format!("{page}.html").into()
+}
+
+fn counts(hit: u64, found: u64) -> String {
+    if found == 0 {
+        "-".to_owned()
+    } else {
+        #[allow(clippy::cast_precision_loss)]
+        let percent = hit as f64 * 100. / found as f64;
+        format!("{percent:.2}% ({hit}/{found})")
+    }
+}
+
+fn page_header(out: &mut String, title: &str, root: &str) {
+    let _ = write!(
+        out,
+        "<!doctype html><html><head><meta charset='UTF-8'><title>{title}</title>\
+         <link rel='stylesheet' type='text/css' href='{root}style.css'></head><body><h2>{title}</h2>"
+    );
+}
+
+type Section<'a> = (&'a str, Vec<(&'a LcovRecord, Utf8PathBuf)>);
+
+fn index_page(sources: &[&str], sections: &[Section<'_>]) -> String {
+    fn totals_row(out: &mut String, label: &str, totals: [(u64, u64); 3]) {
+        let _ = write!(out, "<tr><th>{}</th>", xml_escape(label));
+        for (hit, found) in totals {
+            let _ = write!(out, "<th class='number'>{}</th>", counts(hit, found));
+        }
+        out.push_str("</tr>");
+    }
+
+    let mut out = String::new();
+    page_header(&mut out, "Coverage Report", "");
+    let sources: Vec<_> =
+        sources.iter().map(|s| format!("<code>{}</code>", xml_escape(s))).collect();
+    let _ = write!(out, "<p>Created from {}</p>", sources.join(", "));
+    out.push_str(
+        "<table><tr><th>Filename</th><th>Line Coverage</th><th>Function Coverage</th>\
+         <th>Branch Coverage</th></tr>",
+    );
+    let mut totals = [(0, 0); 3];
+    for (name, files) in sections {
+        if !name.is_empty() {
+            let _ = write!(out, "<tr><th colspan='4'>{}</th></tr>", xml_escape(name));
+        }
+        let mut section_totals = [(0, 0); 3];
+        for (record, page) in files {
+            let summary = [
+                (record.lines_hit(), record.lines_found()),
+                (record.functions_hit(), record.functions_found()),
+                (record.branches_hit(), record.branches_found()),
+            ];
+            let _ = write!(
+                out,
+                "<tr><td><a href='{}'>{}</a></td>",
+                xml_escape(page.as_str()),
+                xml_escape(&record.source_file)
+            );
+            for (total, (hit, found)) in section_totals.iter_mut().zip(summary) {
+                total.0 += hit;
+                total.1 += found;
+                let _ = write!(out, "<td class='number'>{}</td>", counts(hit, found));
+            }
+            out.push_str("</tr>");
+        }
+        if !name.is_empty() {
+            totals_row(&mut out, &format!("{name} Totals"), section_totals);
+        }
+        for (total, (hit, found)) in totals.iter_mut().zip(section_totals) {
+            total.0 += hit;
+            total.1 += found;
+        }
+    }
+    totals_row(&mut out, "Totals", totals);
+    out.push_str("</table></body></html>\n");
+    out
+}
+
+fn file_page(record: &LcovRecord, page: &Utf8Path, source: Option<&str>) -> String {
+    // Pages are in subdirectories of the html directory.
+    let root = "../".repeat(page.components().count() - 1);
+    let mut out = String::new();
+    page_header(&mut out, &xml_escape(&record.source_file), &root);
+    let _ = write!(
+        out,
+        "<p><a href='{root}index.html'>Index</a> | lines: {} | functions: {} | branches: {}</p>\
+         <table><tr><th>Line</th><th>Count</th><th>Source</th></tr>",
+        counts(record.lines_hit(), record.lines_found()),
+        counts(record.functions_hit(), record.functions_found()),
+        counts(record.branches_hit(), record.branches_found())
+    );
+    let source_lines: Vec<&str> = source.map(|s| s.lines().collect()).unwrap_or_default();
+    let last = record.lines.iter().map(|l| l.line).max().unwrap_or(0);
+    for line in 1..=last.max(source_lines.len() as u64) {
+        let count = record.line(line);
+        // A line is partially covered if some branches in it were not taken.
+        let partial = record.branches.iter().any(|b| b.line == line && b.taken.unwrap_or(0) == 0);
+        let class = match count {
+            None => "",
+            Some(0) => "uncovered",
+            Some(_) if partial => "partial",
+            Some(_) => "covered",
+        };
+        let text = usize::try_from(line - 1).ok().and_then(|i| source_lines.get(i));
+        let _ = write!(
+            out,
+            "<tr class='{class}'><td class='number'><pre>{line}</pre></td>\
+             <td class='number count'><pre>{}</pre></td><td><pre>{}</pre></td></tr>",
+            count.map(|c| c.to_string()).unwrap_or_default(),
+            xml_escape(text.copied().unwrap_or_default())
+        );
+    }
+    out.push_str("</table>");
+    if !record.functions.is_empty() {
+        out.push_str("<h3>Functions</h3><table><tr><th>Line</th><th>Count</th><th>Name</th></tr>");
+        for f in &record.functions {
+            let class = if f.execution_count == 0 { "uncovered" } else { "covered" };
+            let _ = write!(
+                out,
+                "<tr class='{class}'><td class='number'>{}</td>\
+                 <td class='number count'>{}</td><td><code>{}</code></td></tr>",
+                f.line,
+                f.execution_count,
+                xml_escape(&f.name)
+            );
+        }
+        out.push_str("</table>");
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_records() {
+        let lcov: LcovExport = "SF:b.rs\nFN:1,f\nFNDA:0,f\nDA:1,0\nDA:2,1\nBRDA:2,0,0,-\n\
+                                BRDA:2,0,1,-\nend_of_record\nSF:a.rs\nDA:1,1\nend_of_record\n\
+                                SF:b.rs\nFN:1,f\nFNDA:2,f\nDA:2,3\nDA:3,0\nBRDA:2,0,0,1\n\
+                                BRDA:2,0,1,0\nend_of_record\n"
+            .parse()
+            .unwrap();
+        let records = merge(&lcov);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].source_file, "a.rs");
+        let b = &records[1];
+        assert_eq!(b.functions, [LcovFunction {
+            name: "f".to_owned(),
+            line: 1,
+            execution_count: 2
+        }]);
+        assert_eq!(b.lines.iter().map(|l| (l.line, l.execution_count)).collect::<Vec<_>>(), [
+            (1, 0),
+            (2, 4),
+            (3, 0)
+        ]);
+        assert_eq!(b.branches.iter().map(|b| b.taken).collect::<Vec<_>>(), [Some(1), Some(0)]);
+
+        let page = file_page(b, Utf8Path::new("coverage/b.rs.html"), Some("a\nb\n"));
+        assert!(page.contains("href='../style.css'"), "{page}");
+        assert!(page.contains(
+            "<tr class='partial'><td class='number'><pre>2</pre></td>\
+             <td class='number count'><pre>4</pre></td><td><pre>b</pre></td></tr>"
+        ));
+        assert!(page.contains("<tr class='uncovered'><td class='number'><pre>3</pre></td>"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn page() {
+        let root = Utf8Path::new("/home/user/foo");
+        assert_eq!(
+            page_path(root, Utf8Path::new("/home/user/foo/src/lib.rs")),
+            "coverage/src/lib.rs.html"
+        );
+        assert_eq!(
+            page_path(root, Utf8Path::new("/usr/include/stdio.h")),
+            "coverage/usr/include/stdio.h.html"
+        );
+    }
+}
@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --assets-dir support, and the check for remote resources in the html report.
+//
+// The html report of llvm-cov consists of html pages, `style.css`, and
+// `control.js` (in recent versions), and loads nothing from the network, so it renders
+// identically in air-gapped environments. --assets-dir copies the files in the
+// given directory (e.g., a custom `style.css`, and the fonts and scripts it
+// uses) into the report, replacing the ones generated by llvm-cov.
+//
+// After the report is generated, the stylesheets and scripts that pages and
+// stylesheets load are checked, and a warning is emitted if some of them are
+// loaded from the network (e.g., a CDN), since such a report doesn't render
+// correctly without internet access. Links to other pages (`<a href>`) are not
+// resources and are not checked.
+
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Result};
+use camino::Utf8Path;
+use regex::Regex;
+use walkdir::WalkDir;
+
+use crate::{context::Context, fs};
+
+/// Copies the files in the directory specified by --assets-dir into the html
+/// report.
+pub(crate) fn copy(cx: &Context, html_dir: &Utf8Path) -> Result<()> {
+    let Some(assets_dir) = &cx.args.cov.assets_dir else { return Ok(()) };
+    if !assets_dir.is_dir() {
+        bail!("--assets-dir: {assets_dir} is not a directory");
+    }
+    let mut copied = 0;
+    for entry in WalkDir::new(assets_dir).into_iter().filter_map(Result::ok) {
+        let Ok(relative) = entry.path().strip_prefix(assets_dir) else { continue };
+        let to = html_dir.as_std_path().join(relative);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(to)?;
+        } else {
+            fs::copy(entry.path(), to)?;
+            copied += 1;
+        }
+    }
+    if copied == 0 {
+        warn!("--assets-dir: no files found in {assets_dir}");
+    }
+    Ok(())
+}
+
+/// Warns if the pages or stylesheets in the html report load resources from
+/// the network.
+pub(crate) fn check(html_dir: &Utf8Path) -> Result<()> {
+    let patterns = patterns();
+    let mut remote = BTreeSet::new();
+    for entry in WalkDir::new(html_dir).into_iter().filter_map(Result::ok) {
+        let path = entry.path();
+        if !path.extension().is_some_and(|e| e == "html" || e == "css") {
+            continue;
+        }
+        remote.extend(remote_resources(&patterns, &fs::read_to_string(path)?));
+    }
+    if !remote.is_empty() {
+        let remote: Vec<_> = remote.into_iter().collect();
+        warn!(
+            "html report loads resources from the network, so it doesn't render correctly \
+             without internet access: {} (pass --assets-dir to bundle them into the report)",
+            remote.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Returns the patterns that match the URLs of resources loaded by html pages
+/// or stylesheets.
+fn patterns() -> [Regex; 2] {
+    [
+        Regex::new(
+            r#"(?i)<(?:link|script|img|iframe|source|video|audio|embed)\b[^>]*?\s(?:href|src)\s*=\s*['"]?((?:https?:)?//[^'"\s>]+)"#,
+        )
+        .unwrap(),
+        // `url(...)` and `@import "..."` in stylesheets, including `<style>` and
+        // `style` attributes in html pages.
+        Regex::new(r#"(?i)(?:url\(\s*['"]?|@import\s+['"])((?:https?:)?//[^'"\s)]+)"#).unwrap(),
+    ]
+}
+
+/// Returns the URLs of the remote resources loaded by the given html page or
+/// stylesheet.
+fn remote_resources(patterns: &[Regex], text: &str) -> Vec<String> {
+    patterns.iter().flat_map(|re| re.captures_iter(text)).map(|c| c[1].to_owned()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote() {
+        let patterns = patterns();
+        let remote_resources = |text| remote_resources(&patterns, text);
+        assert_eq!(
+            remote_resources(
+                "<link rel='stylesheet' type='text/css' href='style.css'>\
+                 <script src='control.js'></script>\
+                 <a href='http://clang.llvm.org/docs/SourceBasedCodeCoverage.html'>docs</a>"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            remote_resources(
+                "<link href=\"https://cdn.example.com/a.css\" rel=stylesheet>\
+                 <script defer src=//cdn.example.com/b.js></script>\
+                 <style>@import 'https://fonts.example.com/c.css';</style>"
+            ),
+            [
+                "https://cdn.example.com/a.css",
+                "//cdn.example.com/b.js",
+                "https://fonts.example.com/c.css"
+            ]
+        );
+        assert_eq!(
+            remote_resources(
+                "@font-face { src: url(fonts/a.woff2), url( \"https://example.com/a.woff\" ); }"
+            ),
+            ["https://example.com/a.woff"]
+        );
+    }
+}
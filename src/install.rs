@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// `cargo llvm-cov install` support.
+//
+// This builds and installs binaries with instrumentation by `cargo install`, so
+// that external (black-box) test suites can run the installed binaries, and then
+// `cargo llvm-cov report` generates the report from the collected profile data.
+//
+// `cargo install` builds in a temporary directory by default, so this passes the
+// target directory of cargo-llvm-cov with --target-dir to keep the artifacts that
+// `cargo llvm-cov report` reads the coverage mappings from. The installed binaries
+// are copies of them. The installed binaries write profile data to the path
+// specified by LLVM_PROFILE_FILE at runtime, so the external test suite needs to
+// set it.
+//
+// Refs:
+// - https://doc.rust-lang.org/nightly/cargo/commands/cargo-install.html
+// - https://clang.llvm.org/docs/SourceBasedCodeCoverage.html#running-the-instrumented-program
+
+use anyhow::Result;
+use camino::Utf8PathBuf;
+
+use crate::{context::Context, set_env, stdout_to_stderr, term, timings, IsNextest};
+
+/// Returns the directory to install binaries into: the value of `--root` if it is
+/// passed, otherwise `<target_dir>/install`.
+///
+/// Unlike `cargo install`, this doesn't install into `$CARGO_HOME` by default to
+/// avoid replacing non-instrumented binaries that the user installed.
+fn root(cx: &Context) -> (Utf8PathBuf, bool) {
+    let mut iter = cx.args.cargo_args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--root" {
+            if let Some(root) = iter.next() {
+                return (root.into(), true);
+            }
+        } else if let Some(root) = arg.strip_prefix("--root=") {
+            return (root.into(), true);
+        }
+    }
+    (cx.ws.target_dir.join("install"), false)
+}
+
+pub(crate) fn run(cx: &Context) -> Result<()> {
+    let _phase = timings::phase("build and install");
+    let mut cargo = cx.cargo();
+
+    set_env(cx, &mut cargo, IsNextest(false))?;
+
+    cargo.arg("install");
+    if !cx.args.cargo_args.iter().any(|a| a == "--path" || a.starts_with("--path=")) {
+        cargo.arg("--path");
+        cargo.arg(cx.ws.current_manifest.parent().unwrap());
+    }
+    let (root, root_passed) = root(cx);
+    if !root_passed {
+        cargo.arg("--root");
+        cargo.arg(&root);
+    }
+    // Instrumented binaries are rebuilt on every run, so always replace the installed ones.
+    cargo.arg("--force");
+    cargo.arg("--target-dir");
+    cargo.arg(cx.ws.target_dir.as_str());
+    cx.args.manifest.cargo_args(&mut cargo);
+    cargo.args(&cx.args.cargo_args);
+    if term::verbose() {
+        status!("Running", "{cargo}");
+    }
+    stdout_to_stderr(cx, &mut cargo);
+    cargo.run()?;
+
+    status!("Installed", "instrumented binaries into {}", root.join("bin"));
+    let profile = match &cx.args.profile {
+        Some(profile) => format!(" --profile {profile}"),
+        None if cx.args.release => " --release".to_owned(),
+        None => String::new(),
+    };
+    info!(
+        "run the installed binaries with LLVM_PROFILE_FILE={}, and then run `cargo llvm-cov \
+         report{profile}` to generate the report",
+        crate::llvm_profile_file(cx, IsNextest(false))
+    );
+    Ok(())
+}
@@ -1,14 +1,18 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use std::{
-    collections::{BTreeMap, HashMap},
-    fmt,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Write as _},
+    io, str,
 };
 
 use anyhow::{Context as _, Result};
 use camino::Utf8PathBuf;
 use regex::Regex;
-use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde::{
+    de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    ser::{Serialize, SerializeMap, Serializer},
+};
 use serde_derive::{Deserialize, Serialize};
 
 // https://github.com/llvm/llvm-project/blob/llvmorg-17.0.0-rc2/llvm/tools/llvm-cov/CoverageExporterJson.cpp#L13-L47
@@ -113,6 +117,11 @@ impl CodeCovJsonExport {
         Self { coverage }
     }
 
+    /// Converts the coverage data exported by llvm-cov to the Codecov's custom coverage format.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ignore_filename_regex` is not a valid regular expression.
     #[must_use]
     pub fn from_llvm_cov_json_export(
         value: LlvmCovJsonExport,
@@ -146,6 +155,18 @@ impl CodeCovJsonExport {
 /// Files -> list of uncovered lines.
 type UncoveredLines = BTreeMap<String, Vec<u64>>;
 
+/// The coverage of a line of a source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub(crate) struct LineCoverage {
+    pub(crate) line: u64,
+    /// The execution count of the line, in the same way as llvm-cov.
+    pub(crate) count: u64,
+    /// `true` if the line was executed, but some of the regions or branches
+    /// in the line were not.
+    pub(crate) partial: bool,
+}
+
 #[non_exhaustive]
 #[derive(Clone, Copy)]
 pub enum CoverageKind {
@@ -179,9 +200,421 @@ impl LlvmCovJsonExport {
         self.cargo_llvm_cov = Some(CargoLlvmCov {
             version: env!("CARGO_PKG_VERSION"),
             manifest_path: manifest_path.into_string(),
+            tests_failed: false,
         });
     }
 
+    /// Marks the export as generated from a run in which tests failed.
+    ///
+    /// This is no-op if [`inject`](Self::inject) has not been called.
+    pub(crate) fn set_tests_failed(&mut self) {
+        if let Some(cargo_llvm_cov) = &mut self.cargo_llvm_cov {
+            cargo_llvm_cov.tests_failed = true;
+        }
+    }
+
+    /// Gets the list of names of all source files.
+    #[must_use]
+    pub fn file_names(&self) -> Vec<&str> {
+        self.data.iter().flat_map(|data| &data.files).map(|file| file.filename.as_str()).collect()
+    }
+
+    /// Replaces the names of all source files with the ones returned by `f`.
+    pub(crate) fn map_file_names(&mut self, mut f: impl FnMut(&str) -> Option<String>) {
+        for data in &mut self.data {
+            for file in &mut data.files {
+                if let Some(filename) = f(&file.filename) {
+                    file.filename = filename;
+                }
+            }
+            for function in data.functions.iter_mut().flatten() {
+                for filename in &mut function.filenames {
+                    if let Some(new) = f(filename) {
+                        *filename = new;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replaces the names of all functions with the ones returned by `f`.
+    pub(crate) fn map_function_names(&mut self, mut f: impl FnMut(&str) -> String) {
+        for data in &mut self.data {
+            for function in data.functions.iter_mut().flatten() {
+                function.name = f(&function.name);
+            }
+        }
+    }
+
+    /// Gets the list of mangled names of all functions.
+    #[must_use]
+    pub(crate) fn function_names(&self) -> BTreeSet<&str> {
+        self.data
+            .iter()
+            .filter_map(|data| data.functions.as_ref())
+            .flatten()
+            .map(|func| func.name.as_str())
+            .collect()
+    }
+
+    /// Gets the mangled name, the name of the file, and the start and end
+    /// locations (line, column) of all functions.
+    ///
+    /// The locations are of the code of the function in the file that
+    /// contains it, excluding expansions of macros defined in other files.
+    #[must_use]
+    pub(crate) fn function_locations(&self) -> Vec<(&str, &str, (u64, u64), (u64, u64))> {
+        self.data
+            .iter()
+            .filter_map(|data| data.functions.as_ref())
+            .flatten()
+            .filter_map(|func| {
+                let (start, end) = func.extent()?;
+                Some((func.name.as_str(), func.filenames.first()?.as_str(), start, end))
+            })
+            .collect()
+    }
+
+    /// Gets the name of the file, the start location (line, column), and the
+    /// execution count of all functions.
+    #[must_use]
+    pub(crate) fn function_counts(&self) -> Vec<(&str, (u64, u64), u64)> {
+        self.data
+            .iter()
+            .filter_map(|data| data.functions.as_ref())
+            .flatten()
+            .filter_map(|func| {
+                let (start, _) = func.extent()?;
+                Some((func.filenames.first()?.as_str(), start, func.count))
+            })
+            .collect()
+    }
+
+    /// Summarizes the coverage of functions, lines, and regions, distinguishing
+    /// code covered in at least one instantiation of each function from code
+    /// covered in all of its instantiations.
+    ///
+    /// Like llvm-cov, instantiations are grouped by the start location of the
+    /// function. Only the code of the function in the file that contains it is
+    /// counted (excluding expansions of macros), and branches are not counted.
+    /// This returns zero totals for summary-only export because it doesn't
+    /// contain functions.
+    #[must_use]
+    pub(crate) fn instantiation_totals(&self) -> InstantiationTotals {
+        #[derive(Default)]
+        struct Group {
+            instantiations: u64,
+            executed: u64,
+            /// line -> (instantiations that have the line, instantiations that cover it)
+            lines: BTreeMap<u64, (u64, u64)>,
+            /// location -> (instantiations that have the region, instantiations that cover it)
+            regions: BTreeMap<(u64, u64, u64, u64), (u64, u64)>,
+        }
+
+        // filename -> (line, column) -> group
+        let mut groups: BTreeMap<&str, BTreeMap<(u64, u64), Group>> = BTreeMap::new();
+        for func in self.data.iter().filter_map(|data| data.functions.as_ref()).flatten() {
+            let (Some(filename), Some((start, _))) = (func.filenames.first(), func.extent()) else {
+                continue;
+            };
+            let group = groups.entry(filename).or_default().entry(start).or_default();
+            group.instantiations += 1;
+            group.executed += u64::from(func.count > 0);
+            let code_regions: Vec<&Region> =
+                func.regions.iter().filter(|r| r.file_id() == 0 && r.kind() == 0).collect();
+            let mut regions = BTreeMap::new();
+            for r in &code_regions {
+                let location = (r.line_start(), r.column_start(), r.line_end(), r.column_end());
+                *regions.entry(location).or_insert(false) |= r.execution_count() > 0;
+            }
+            for (location, covered) in regions {
+                let e = group.regions.entry(location).or_default();
+                e.0 += 1;
+                e.1 += u64::from(covered);
+            }
+            for (line, count) in region_line_counts(&code_regions) {
+                let e = group.lines.entry(line).or_default();
+                e.0 += 1;
+                e.1 += u64::from(count > 0);
+            }
+        }
+
+        let mut totals = InstantiationTotals::default();
+        for group in groups.values().flat_map(BTreeMap::values) {
+            totals.instantiations += group.instantiations;
+            totals.generic_functions += u64::from(group.instantiations > 1);
+            totals.functions.add(group.executed > 0, group.executed == group.instantiations);
+            for &(count, covered) in group.lines.values() {
+                totals.lines.add(covered > 0, covered == count);
+            }
+            for &(count, covered) in group.regions.values() {
+                totals.regions.add(covered > 0, covered == count);
+            }
+        }
+        totals
+    }
+
+    /// Parses the export from `reader`, and removes functions while parsing,
+    /// one at a time, so that the removed functions are never held in memory.
+    ///
+    /// Functions for which `retain_code` returns `false` are removed along
+    /// with the code they contain, so their lines, regions, and branches are
+    /// not counted in any summaries. Functions for which `retain` returns
+    /// `false` are removed from function and instantiation summaries only.
+    /// The predicates take the mangled name of the function. Nothing is
+    /// removed from summary-only export because it doesn't contain functions.
+    pub(crate) fn from_reader(
+        reader: impl io::Read,
+        retain_code: &mut dyn FnMut(&str) -> bool,
+        retain: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<Self> {
+        let mut de = serde_json::Deserializer::from_reader(reader);
+        let json = ExportSeed { retain_code, retain }.deserialize(&mut de)?;
+        de.end()?;
+        Ok(json)
+    }
+
+    /// Sorts files by name, and functions by the name of the file that
+    /// contains them, the start location, and the mangled name.
+    ///
+    /// The order of functions in the output of `llvm-cov export` depends on
+    /// the order of object files, and the order of files may be changed by
+    /// remapping the file names, so this is called before writing reports to
+    /// make the output independent of them.
+    pub(crate) fn sort(&mut self) {
+        for data in &mut self.data {
+            data.files.sort_by(|a, b| a.filename.cmp(&b.filename));
+            if let Some(functions) = &mut data.functions {
+                functions.sort_by(|a, b| {
+                    let key = |f: &Function| {
+                        (
+                            f.filenames.first().cloned(),
+                            f.regions.first().map(|r| (r.line_start(), r.column_start())),
+                        )
+                    };
+                    key(a).cmp(&key(b)).then_with(|| a.name.cmp(&b.name))
+                });
+            }
+        }
+    }
+
+    /// Writes the export in the JSON Lines format of `--json-lines`.
+    ///
+    /// Each line is a `{"type": "file", ...}` object with the fields of a file
+    /// in the export and the functions whose first filename is the file,
+    /// followed by a `{"type": "totals", ...}` object per export, so consumers
+    /// can process one file at a time.
+    pub(crate) fn write_json_lines(&self, mut writer: impl io::Write) -> Result<()> {
+        #[derive(Serialize)]
+        struct FileLine<'a> {
+            #[serde(rename = "type")]
+            type_: &'static str,
+            #[serde(flatten)]
+            file: &'a File,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            functions: Vec<&'a Function>,
+        }
+        #[derive(Serialize)]
+        struct TotalsLine<'a> {
+            #[serde(rename = "type")]
+            type_: &'static str,
+            version: &'a str,
+            totals: &'a serde_json::Value,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            cargo_llvm_cov: Option<&'a CargoLlvmCov>,
+        }
+
+        for data in &self.data {
+            let mut functions: HashMap<&str, Vec<&Function>> = HashMap::new();
+            for function in data.functions.iter().flatten() {
+                if let Some(filename) = function.filenames.first() {
+                    functions.entry(filename).or_default().push(function);
+                }
+            }
+            for file in &data.files {
+                let functions = functions.remove(file.filename.as_str()).unwrap_or_default();
+                serde_json::to_writer(&mut writer, &FileLine { type_: "file", file, functions })?;
+                writer.write_all(b"\n")?;
+            }
+            serde_json::to_writer(&mut writer, &TotalsLine {
+                type_: "totals",
+                version: &self.version,
+                totals: &data.totals,
+                cargo_llvm_cov: self.cargo_llvm_cov.as_ref(),
+            })?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Removes details of files and functions, leaving only the information
+    /// `llvm-cov export -summary-only` outputs.
+    pub(crate) fn remove_details(&mut self) {
+        for data in &mut self.data {
+            data.functions = None;
+            for file in &mut data.files {
+                file.branches = None;
+                file.expansions = None;
+                file.segments = None;
+            }
+        }
+    }
+
+    /// Renders a summary table in the same format as `llvm-cov report`.
+    pub(crate) fn summary_table(&self) -> Result<String> {
+        // (header, width) of columns for each of regions, functions, lines, and branches.
+        const COLUMNS: [[(&str, usize); 3]; 4] = [
+            [("Regions", 12), ("Missed Regions", 18), ("Cover", 10)],
+            [("Functions", 12), ("Missed Functions", 18), ("Executed", 10)],
+            [("Lines", 12), ("Missed Lines", 18), ("Cover", 10)],
+            [("Branches", 12), ("Missed Branches", 18), ("Cover", 10)],
+        ];
+
+        fn counts(summary: &Summary) -> [&CoverageCounts; 4] {
+            [&summary.regions, &summary.functions, &summary.lines, &summary.branches]
+        }
+        fn cover(counts: &CoverageCounts) -> String {
+            if counts.count == 0 {
+                "-".to_owned()
+            } else {
+                crate::percent::format_summary(
+                    percent(counts.covered, counts.count),
+                    counts.covered,
+                    counts.count,
+                )
+            }
+        }
+        fn row(out: &mut String, name: &str, width: usize, widths: &[usize; 4], summary: &Summary) {
+            let _ = write!(out, "{name:<width$}");
+            for (counts, ([(_, w1), (_, w2), _], w3)) in
+                counts(summary).into_iter().zip(COLUMNS.iter().zip(widths))
+            {
+                let missed = counts.count.saturating_sub(counts.covered);
+                let _ = write!(out, "{:>w1$}{missed:>w2$}{:>w3$}", counts.count, cover(counts));
+            }
+            out.push('\n');
+        }
+
+        let files: Vec<_> = self.data.iter().flat_map(|data| &data.files).collect();
+        let totals = self
+            .data
+            .iter()
+            .map(|data| serde_json::from_value::<Summary>(data.totals.clone()))
+            .collect::<Result<Vec<_>, _>>()
+            .context("failed to parse totals")?;
+        // Like llvm-cov, strip the common directory of the files.
+        let prefix_len = common_dir_len(&files);
+        let width = files.iter().map(|f| f.filename.len() - prefix_len).fold(25, usize::max);
+        // The cover columns are widened if --show-counts makes them longer.
+        let mut widths = COLUMNS.map(|[.., (_, w)]| w);
+        for summary in files.iter().map(|f| &f.summary).chain(&totals) {
+            for (counts, w) in counts(summary).into_iter().zip(&mut widths) {
+                *w = (*w).max(cover(counts).len() + 2);
+            }
+        }
+        let total_width = width
+            + COLUMNS.iter().flat_map(|[(_, w1), (_, w2), _]| [w1, w2]).sum::<usize>()
+            + widths.iter().sum::<usize>();
+
+        let mut out = String::new();
+        let _ = write!(out, "{:<width$}", "Filename");
+        for ([(h1, w1), (h2, w2), (h3, _)], w3) in COLUMNS.iter().zip(&widths) {
+            let _ = write!(out, "{h1:>w1$}{h2:>w2$}{h3:>w3$}");
+        }
+        let _ = writeln!(out, "\n{}", "-".repeat(total_width));
+        for file in &files {
+            row(&mut out, &file.filename[prefix_len..], width, &widths, &file.summary);
+        }
+        let _ = writeln!(out, "{}", "-".repeat(total_width));
+        for totals in &totals {
+            row(&mut out, "TOTAL", width, &widths, totals);
+        }
+        // Like the output of llvm-cov we read, trailing newline is not included.
+        out.pop();
+        Ok(out)
+    }
+
+    /// Renders a summary table in Markdown, printed by `--summary-format markdown`.
+    pub(crate) fn summary_markdown(&self) -> Result<String> {
+        fn cell(counts: &CoverageCounts) -> String {
+            if counts.count == 0 {
+                "-".to_owned()
+            } else if crate::percent::counts() == Some(crate::percent::Counts::Only) {
+                format!("{}/{}", counts.covered, counts.count)
+            } else {
+                format!(
+                    "{}% ({}/{})",
+                    crate::percent::format(percent(counts.covered, counts.count)),
+                    counts.covered,
+                    counts.count
+                )
+            }
+        }
+        fn row(out: &mut String, name: &str, summary: &Summary) {
+            let _ = writeln!(
+                out,
+                "| {name} | {} | {} | {} | {} |",
+                cell(&summary.lines),
+                cell(&summary.functions),
+                cell(&summary.regions),
+                cell(&summary.branches)
+            );
+        }
+
+        let files: Vec<_> = self.data.iter().flat_map(|data| &data.files).collect();
+        let prefix_len = common_dir_len(&files);
+        let mut out = String::from(
+            "| Filename | Lines | Functions | Regions | Branches |\n| :--- | ---: | ---: | ---: | ---: |\n",
+        );
+        for file in &files {
+            row(&mut out, &format!("`{}`", &file.filename[prefix_len..]), &file.summary);
+        }
+        for data in &self.data {
+            let totals: Summary =
+                serde_json::from_value(data.totals.clone()).context("failed to parse totals")?;
+            row(&mut out, "**TOTAL**", &totals);
+        }
+        out.pop();
+        Ok(out)
+    }
+
+    /// Renders the summary of each file and the totals in CSV, printed by `--summary-format csv`.
+    ///
+    /// The percentages are empty if there is nothing to cover.
+    pub(crate) fn summary_csv(&self) -> Result<String> {
+        fn row(out: &mut String, name: &str, summary: &Summary) {
+            // Quote the name as file names may contain commas.
+            let _ = write!(out, "\"{}\"", name.replace('"', "\"\""));
+            for counts in [&summary.lines, &summary.functions, &summary.regions, &summary.branches]
+            {
+                let percent = if counts.count == 0 {
+                    String::new()
+                } else {
+                    crate::percent::format(percent(counts.covered, counts.count))
+                };
+                let _ = write!(out, ",{},{},{percent}", counts.count, counts.covered);
+            }
+            out.push('\n');
+        }
+
+        let mut out = String::from("filename");
+        for kind in ["lines", "functions", "regions", "branches"] {
+            let _ = write!(out, ",{kind}_count,{kind}_covered,{kind}_percent");
+        }
+        out.push('\n');
+        for file in self.data.iter().flat_map(|data| &data.files) {
+            row(&mut out, &file.filename, &file.summary);
+        }
+        for data in &self.data {
+            let totals: Summary =
+                serde_json::from_value(data.totals.clone()).context("failed to parse totals")?;
+            row(&mut out, "TOTAL", &totals);
+        }
+        out.pop();
+        Ok(out)
+    }
+
     /// Gets the minimal lines coverage of all files.
     pub fn get_coverage_percent(&self, kind: CoverageKind) -> Result<f64> {
         let mut count = 0_f64;
@@ -198,10 +631,53 @@ impl LlvmCovJsonExport {
             return Ok(0_f64);
         }
 
-        Ok(covered * 100_f64 / count)
+        Ok(crate::percent::round(covered * 100_f64 / count))
+    }
+
+    /// Gets the coverage percent of the files whose names `f` returns `true` for.
+    #[must_use]
+    pub(crate) fn get_coverage_percent_of_files(
+        &self,
+        kind: CoverageKind,
+        mut f: impl FnMut(&str) -> bool,
+    ) -> f64 {
+        let mut count = 0;
+        let mut covered = 0;
+        for file in self.data.iter().flat_map(|data| &data.files) {
+            if !f(&file.filename) {
+                continue;
+            }
+            let counts = match kind {
+                CoverageKind::Functions => &file.summary.functions,
+                CoverageKind::Lines => &file.summary.lines,
+                CoverageKind::Regions => &file.summary.regions,
+            };
+            count += counts.count;
+            covered += counts.covered;
+        }
+        if count == 0 {
+            return 0_f64;
+        }
+        percent(covered, count)
+    }
+
+    /// Gets the totals of the files for which `f` returns `true`.
+    #[must_use]
+    pub(crate) fn get_totals_of_files(&self, mut f: impl FnMut(&str) -> bool) -> SummaryTotals {
+        let mut totals = SummaryTotals::default();
+        for file in self.data.iter().flat_map(|data| &data.files) {
+            if f(&file.filename) {
+                totals.add(&file.summary);
+            }
+        }
+        totals
     }
 
     /// Gets the list of uncovered lines of all files.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ignore_filename_regex` is not a valid regular expression.
     #[must_use]
     pub fn get_uncovered_lines(&self, ignore_filename_regex: Option<&str>) -> UncoveredLines {
         let mut uncovered_files: UncoveredLines = BTreeMap::new();
@@ -278,6 +754,73 @@ impl LlvmCovJsonExport {
         uncovered_files
     }
 
+    /// Gets the coverage of each line that has an execution count in the given file.
+    ///
+    /// Returns `None` if the file is not found or the report is summary-only.
+    #[must_use]
+    pub(crate) fn get_line_coverage(&self, filename: &str) -> Option<Vec<LineCoverage>> {
+        let file =
+            self.data.iter().flat_map(|data| &data.files).find(|file| file.filename == filename)?;
+        let segments = file.segments.as_deref()?;
+        let mut partial_lines = BTreeSet::new();
+        // A line is partially covered if it is inside or contains a region that
+        // was not executed.
+        let uncovered = |s: &Segment| s.has_count() && !s.is_gap_region() && s.count() == 0;
+        let mut prev: Option<&Segment> = None;
+        for s in segments {
+            if let Some(prev) = prev.filter(|&prev| uncovered(prev)) {
+                partial_lines.extend(prev.line() + 1..s.line());
+                if s.line() > prev.line() && s.col() > 1 {
+                    partial_lines.insert(s.line());
+                }
+            }
+            if uncovered(s) {
+                partial_lines.insert(s.line());
+            }
+            prev = Some(s);
+        }
+        for branch in file.branches.iter().flatten() {
+            // LineStart, ColumnStart, LineEnd, ColumnEnd, ExecutionCount, FalseExecutionCount, ...
+            let Ok(branch) = serde_json::from_value::<Vec<u64>>(branch.clone()) else { continue };
+            if let &[line, _, _, _, true_count, false_count, ..] = &*branch {
+                if true_count == 0 || false_count == 0 {
+                    partial_lines.insert(line);
+                }
+            }
+        }
+        Some(
+            line_counts(segments)
+                .into_iter()
+                .map(|(line, count)| LineCoverage {
+                    line,
+                    count,
+                    partial: count != 0 && partial_lines.contains(&line),
+                })
+                .collect(),
+        )
+    }
+
+    /// Gets the lines of branches in the given file that were not taken in
+    /// both directions.
+    ///
+    /// Returns `None` if the file is not found or the report is summary-only.
+    #[must_use]
+    pub(crate) fn get_partial_branch_lines(&self, filename: &str) -> Option<Vec<u64>> {
+        let file =
+            self.data.iter().flat_map(|data| &data.files).find(|file| file.filename == filename)?;
+        let mut lines = BTreeSet::new();
+        for branch in file.branches.as_deref()? {
+            // LineStart, ColumnStart, LineEnd, ColumnEnd, ExecutionCount, FalseExecutionCount, ...
+            let Ok(branch) = serde_json::from_value::<Vec<u64>>(branch.clone()) else { continue };
+            if let &[line, _, _, _, true_count, false_count, ..] = &*branch {
+                if true_count == 0 || false_count == 0 {
+                    lines.insert(line);
+                }
+            }
+        }
+        Some(lines.into_iter().collect())
+    }
+
     pub fn count_uncovered_functions(&self) -> Result<u64> {
         let mut count = 0_u64;
         let mut covered = 0_u64;
@@ -299,22 +842,1513 @@ impl LlvmCovJsonExport {
             count += lines["count"].as_u64().context("no count")?;
             covered += lines["covered"].as_u64().context("no covered")?;
         }
-        Ok(count.saturating_sub(covered))
+        Ok(count.saturating_sub(covered))
+    }
+
+    pub fn count_uncovered_regions(&self) -> Result<u64> {
+        let mut count = 0_u64;
+        let mut covered = 0_u64;
+        for data in &self.data {
+            let totals = &data.totals.as_object().context("totals is not an object")?;
+            let regions = &totals["regions"].as_object().context("no regions")?;
+            count += regions["count"].as_u64().context("no count")?;
+            covered += regions["covered"].as_u64().context("no covered")?;
+        }
+        Ok(count.saturating_sub(covered))
+    }
+}
+
+/// Coverage data in the [lcov tracefile format][lcov].
+///
+/// This can be parsed from and formatted to the text format with [`FromStr`]
+/// and [`Display`](fmt::Display), and queried with [`record`](Self::record)
+/// and [`uncovered_lines`](Self::uncovered_lines).
+///
+/// # Examples
+///
+/// ```no_run
+/// use cargo_llvm_cov::json::LcovExport;
+///
+/// let lcov: LcovExport = std::fs::read_to_string("lcov.info")?.parse()?;
+/// if let Some(record) = lcov.record("src/lib.rs") {
+///     println!("{:.2}%", record.lines_percent());
+/// }
+/// for (file, line) in lcov.uncovered_lines() {
+///     println!("{file}:{line}");
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+/// [lcov]: https://github.com/linux-test-project/lcov/blob/v2.0/man/geninfo.1#L1370
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LcovExport {
+    /// List of records, one for each source file
+    pub records: Vec<LcovRecord>,
+}
+
+/// Coverage for a single source file in the lcov format
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LcovRecord {
+    /// The path to the source file (`SF`)
+    pub source_file: String,
+    /// List of functions (`FN` and `FNDA`)
+    pub functions: Vec<LcovFunction>,
+    /// List of lines that have execution counts (`DA`)
+    pub lines: Vec<LcovLine>,
+    /// List of branches (`BRDA`)
+    pub branches: Vec<LcovBranch>,
+}
+
+/// A function in the lcov format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LcovFunction {
+    pub name: String,
+    /// The line number of the start of the function
+    pub line: u64,
+    pub execution_count: u64,
+}
+
+/// A line in the lcov format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LcovLine {
+    pub line: u64,
+    pub execution_count: u64,
+}
+
+/// A branch in the lcov format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LcovBranch {
+    pub line: u64,
+    pub block: u64,
+    pub branch: u64,
+    /// The number of times the branch was taken, or `None` if the code that
+    /// contains the branch was never executed (`-`).
+    pub taken: Option<u64>,
+}
+
+impl LcovExport {
+    /// Converts the coverage data exported by llvm-cov to the lcov format, in
+    /// the same way as `llvm-cov export -format=lcov`.
+    ///
+    /// Summary-only export doesn't contain the details of files, so records
+    /// converted from it have no functions, lines, or branches.
+    #[must_use]
+    pub fn from_llvm_cov_json_export(value: &LlvmCovJsonExport) -> Self {
+        let mut records = vec![];
+        for data in &value.data {
+            for file in &data.files {
+                let mut functions: Vec<_> = data
+                    .functions
+                    .iter()
+                    .flatten()
+                    .filter(|func| func.filenames.first() == Some(&file.filename))
+                    .filter_map(|func| {
+                        Some(LcovFunction {
+                            name: func.name.clone(),
+                            line: func.regions.first()?.line_start(),
+                            execution_count: func.count,
+                        })
+                    })
+                    .collect();
+                functions.sort_by(|a, b| a.name.cmp(&b.name));
+                let lines = line_counts(file.segments.as_deref().unwrap_or_default())
+                    .into_iter()
+                    .map(|(line, execution_count)| LcovLine { line, execution_count })
+                    .collect();
+                let mut branches = vec![];
+                let mut prev_line = None;
+                let mut index = 0;
+                for branch in file.branches.iter().flatten() {
+                    // LineStart, ColumnStart, LineEnd, ColumnEnd, ExecutionCount, FalseExecutionCount, ...
+                    let Ok(branch) = serde_json::from_value::<Vec<u64>>(branch.clone()) else {
+                        continue;
+                    };
+                    let &[line, _, _, _, true_count, false_count, ..] = &*branch else {
+                        continue;
+                    };
+                    if prev_line != Some(line) {
+                        prev_line = Some(line);
+                        index = 0;
+                    }
+                    let executed = true_count != 0 || false_count != 0;
+                    for count in [true_count, false_count] {
+                        let taken = executed.then_some(count);
+                        branches.push(LcovBranch { line, block: 0, branch: index, taken });
+                        index += 1;
+                    }
+                }
+                records.push(LcovRecord {
+                    source_file: file.filename.clone(),
+                    functions,
+                    lines,
+                    branches,
+                });
+            }
+        }
+        records.sort_by(|a, b| a.source_file.cmp(&b.source_file));
+        Self { records }
+    }
+
+    /// Converts the coverage data in the lcov format to the format exported
+    /// by llvm-cov.
+    ///
+    /// The lcov format has no information about regions and columns, so each
+    /// line is converted to a region that covers the line, and the region
+    /// and instantiation summaries are the same as the line and function
+    /// summaries.
+    #[must_use]
+    pub fn to_llvm_cov_json_export(&self) -> LlvmCovJsonExport {
+        let mut files = vec![];
+        let mut functions = vec![];
+        let mut totals = LcovSummary::default();
+        for record in &self.records {
+            let mut segments = vec![];
+            let mut lines = record.lines.clone();
+            lines.sort_by_key(|l| l.line);
+            for l in &lines {
+                segments.push(Segment(l.line, 1, l.execution_count, true, true, false));
+                segments.push(Segment(l.line, 2, 0, false, false, false));
+            }
+            let mut branches = vec![];
+            for pair in record.branches.chunks(2) {
+                let count = |b: Option<&LcovBranch>| b.and_then(|b| b.taken).unwrap_or(0);
+                let line = pair[0].line;
+                branches.push(serde_json::json!([
+                    line,
+                    1,
+                    line,
+                    2,
+                    count(pair.first()),
+                    count(pair.get(1)),
+                    0,
+                    0,
+                    4
+                ]));
+            }
+            for func in &record.functions {
+                functions.push(Function {
+                    branches: vec![],
+                    count: func.execution_count,
+                    filenames: vec![record.source_file.clone()],
+                    name: func.name.clone(),
+                    regions: vec![Region(
+                        func.line,
+                        1,
+                        func.line,
+                        2,
+                        func.execution_count,
+                        0,
+                        0,
+                        0,
+                    )],
+                });
+            }
+            let summary = LcovSummary::new(record);
+            totals.add(&summary);
+            files.push(File {
+                branches: Some(branches),
+                expansions: Some(vec![]),
+                filename: record.source_file.clone(),
+                segments: Some(segments),
+                summary: summary.to_summary(),
+            });
+        }
+        LlvmCovJsonExport {
+            data: vec![Export {
+                files,
+                functions: Some(functions),
+                totals: serde_json::to_value(totals.to_summary()).unwrap_or_default(),
+            }],
+            type_: "llvm.coverage.json.export".to_owned(),
+            version: "2.0.1".to_owned(),
+            cargo_llvm_cov: None,
+        }
+    }
+    /// Gets the record of the given source file.
+    ///
+    /// If no record has the exact path, this returns the first record whose
+    /// path ends with the given path (e.g., `src/lib.rs` matches
+    /// `/path/to/project/src/lib.rs`). Both `/` and `\` are accepted as
+    /// path separators.
+    #[must_use]
+    pub fn record(&self, path: &str) -> Option<&LcovRecord> {
+        let path = path.replace('\\', "/");
+        let path = path.trim_start_matches("./");
+        self.records.iter().find(|r| r.source_file.replace('\\', "/") == path).or_else(|| {
+            self.records.iter().find(|r| {
+                let source_file = r.source_file.replace('\\', "/");
+                source_file
+                    .strip_suffix(path)
+                    .is_some_and(|prefix| prefix.is_empty() || prefix.ends_with('/'))
+            })
+        })
+    }
+
+    /// Returns an iterator over source files and line numbers of lines that
+    /// were never executed.
+    pub fn uncovered_lines(&self) -> impl Iterator<Item = (&str, u64)> + '_ {
+        self.records
+            .iter()
+            .flat_map(|r| r.uncovered_lines().map(move |line| (r.source_file.as_str(), line)))
+    }
+
+    /// Gets the coverage percent of lines of all files.
+    #[must_use]
+    pub fn lines_percent(&self) -> f64 {
+        let (count, covered) = self
+            .records
+            .iter()
+            .fold((0, 0), |(count, covered), r| (count + r.lines_found(), covered + r.lines_hit()));
+        if count == 0 {
+            return 0_f64;
+        }
+        percent(covered, count)
+    }
+}
+
+impl LcovRecord {
+    /// Returns the execution count of the given line, or `None` if the line
+    /// has no execution count (e.g., comments and blank lines).
+    #[must_use]
+    pub fn line(&self, line: u64) -> Option<u64> {
+        // Take the maximum in case the same line appears multiple times.
+        self.lines.iter().filter(|l| l.line == line).map(|l| l.execution_count).max()
+    }
+
+    /// Returns an iterator over line numbers of lines that were never executed.
+    pub fn uncovered_lines(&self) -> impl Iterator<Item = u64> + '_ {
+        let mut lines: Vec<_> =
+            self.lines.iter().filter(|l| self.line(l.line) == Some(0)).map(|l| l.line).collect();
+        lines.sort_unstable();
+        lines.dedup();
+        lines.into_iter()
+    }
+
+    /// Returns the number of lines that have execution counts (`LF`).
+    #[must_use]
+    pub fn lines_found(&self) -> u64 {
+        LcovSummary::new(self).lines.0
+    }
+
+    /// Returns the number of lines that were executed at least once (`LH`).
+    #[must_use]
+    pub fn lines_hit(&self) -> u64 {
+        LcovSummary::new(self).lines.1
+    }
+
+    /// Returns the number of functions (`FNF`).
+    #[must_use]
+    pub fn functions_found(&self) -> u64 {
+        LcovSummary::new(self).functions.0
+    }
+
+    /// Returns the number of functions that were executed at least once (`FNH`).
+    #[must_use]
+    pub fn functions_hit(&self) -> u64 {
+        LcovSummary::new(self).functions.1
+    }
+
+    /// Returns the number of branches (`BRF`).
+    #[must_use]
+    pub fn branches_found(&self) -> u64 {
+        LcovSummary::new(self).branches.0
+    }
+
+    /// Returns the number of branches that were taken at least once (`BRH`).
+    #[must_use]
+    pub fn branches_hit(&self) -> u64 {
+        LcovSummary::new(self).branches.1
+    }
+
+    /// Gets the coverage percent of lines of this file.
+    #[must_use]
+    pub fn lines_percent(&self) -> f64 {
+        let (count, covered) = LcovSummary::new(self).lines;
+        if count == 0 {
+            return 0_f64;
+        }
+        percent(covered, count)
+    }
+}
+
+impl str::FromStr for LcovExport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        fn num(s: &str, line: &str) -> Result<u64> {
+            s.trim().parse().with_context(|| format!("invalid number in lcov line `{line}`"))
+        }
+        let mut records = vec![];
+        let mut record: Option<LcovRecord> = None;
+        // FN lines come before FNDA lines, so remember the start lines by name.
+        let mut fn_lines = HashMap::new();
+        for line in s.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("TN:") {
+                continue;
+            }
+            if line == "end_of_record" {
+                records.extend(record.take());
+                fn_lines.clear();
+                continue;
+            }
+            let (key, value) =
+                line.split_once(':').with_context(|| format!("invalid lcov line `{line}`"))?;
+            if key == "SF" {
+                records.extend(record.take());
+                fn_lines.clear();
+                record = Some(LcovRecord { source_file: value.to_owned(), ..Default::default() });
+                continue;
+            }
+            let record =
+                record.as_mut().with_context(|| format!("lcov line `{line}` outside of record"))?;
+            let mut fields = value.splitn(4, ',');
+            let mut field = || fields.next().with_context(|| format!("invalid lcov line `{line}`"));
+            match key {
+                "FN" => {
+                    let start = num(field()?, line)?;
+                    fn_lines.insert(field()?.to_owned(), start);
+                }
+                "FNDA" => {
+                    let execution_count = num(field()?, line)?;
+                    let name = field()?.to_owned();
+                    let line = fn_lines.get(&name).copied().unwrap_or(0);
+                    record.functions.push(LcovFunction { name, line, execution_count });
+                }
+                "DA" => {
+                    let l = num(field()?, line)?;
+                    let execution_count = num(field()?, line)?;
+                    record.lines.push(LcovLine { line: l, execution_count });
+                }
+                "BRDA" => {
+                    let l = num(field()?, line)?;
+                    let block = num(field()?, line)?;
+                    let branch = num(field()?, line)?;
+                    let taken = match field()? {
+                        "-" => None,
+                        n => Some(num(n, line)?),
+                    };
+                    record.branches.push(LcovBranch { line: l, block, branch, taken });
+                }
+                // Summaries are calculated from the details.
+                _ => {}
+            }
+        }
+        records.extend(record);
+        Ok(Self { records })
+    }
+}
+
+impl fmt::Display for LcovExport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for record in &self.records {
+            writeln!(f, "SF:{}", record.source_file)?;
+            for func in &record.functions {
+                writeln!(f, "FN:{},{}", func.line, func.name)?;
+            }
+            for func in &record.functions {
+                writeln!(f, "FNDA:{},{}", func.execution_count, func.name)?;
+            }
+            let summary = LcovSummary::new(record);
+            writeln!(f, "FNF:{}", summary.functions.0)?;
+            writeln!(f, "FNH:{}", summary.functions.1)?;
+            for line in &record.lines {
+                writeln!(f, "DA:{},{}", line.line, line.execution_count)?;
+            }
+            for branch in &record.branches {
+                write!(f, "BRDA:{},{},{},", branch.line, branch.block, branch.branch)?;
+                match branch.taken {
+                    Some(taken) => writeln!(f, "{taken}")?,
+                    None => writeln!(f, "-")?,
+                }
+            }
+            writeln!(f, "BRF:{}", summary.branches.0)?;
+            writeln!(f, "BRH:{}", summary.branches.1)?;
+            writeln!(f, "LF:{}", summary.lines.0)?;
+            writeln!(f, "LH:{}", summary.lines.1)?;
+            writeln!(f, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
+
+/// (count, covered) of functions, lines, and branches of lcov records.
+#[derive(Default)]
+struct LcovSummary {
+    functions: (u64, u64),
+    lines: (u64, u64),
+    branches: (u64, u64),
+}
+
+impl LcovSummary {
+    fn new(record: &LcovRecord) -> Self {
+        fn count<T>(v: &[T], f: impl Fn(&T) -> bool) -> (u64, u64) {
+            (v.len() as u64, v.iter().filter(|x| f(x)).count() as u64)
+        }
+        // Like llvm-cov, count instantiations of the same function once.
+        let mut functions: BTreeMap<u64, bool> = BTreeMap::new();
+        for func in &record.functions {
+            *functions.entry(func.line).or_default() |= func.execution_count > 0;
+        }
+        Self {
+            functions: (functions.len() as u64, functions.values().filter(|&&c| c).count() as u64),
+            lines: count(&record.lines, |l| l.execution_count > 0),
+            branches: count(&record.branches, |b| b.taken.is_some_and(|n| n > 0)),
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        for (a, b) in [
+            (&mut self.functions, other.functions),
+            (&mut self.lines, other.lines),
+            (&mut self.branches, other.branches),
+        ] {
+            a.0 += b.0;
+            a.1 += b.1;
+        }
+    }
+
+    fn to_summary(&self) -> Summary {
+        fn counts((count, covered): (u64, u64), notcovered: bool) -> CoverageCounts {
+            CoverageCounts {
+                count,
+                covered,
+                notcovered: notcovered.then_some(count - covered),
+                percent: if count == 0 { 0_f64 } else { percent(covered, count) },
+            }
+        }
+        Summary {
+            branches: counts(self.branches, true),
+            functions: counts(self.functions, false),
+            instantiations: counts(self.functions, false),
+            lines: counts(self.lines, false),
+            regions: counts(self.lines, true),
+        }
+    }
+}
+
+/// Returns (line, execution count) of lines that have execution counts, in
+/// the same way as llvm-cov.
+// https://github.com/llvm/llvm-project/blob/llvmorg-17.0.0-rc2/llvm/lib/ProfileData/Coverage/CoverageMapping.cpp#L1210
+fn line_counts(segments: &[Segment]) -> Vec<(u64, u64)> {
+    let is_start_of_region =
+        |s: &&Segment| !s.is_gap_region() && s.has_count() && s.is_region_entry();
+    let mut lines = vec![];
+    let (Some(first), Some(last)) = (segments.first(), segments.last()) else { return lines };
+    let mut wrapped: Option<&Segment> = None;
+    let mut rest = segments;
+    for line in first.line()..=last.line() {
+        let n = rest.iter().take_while(|s| s.line() == line).count();
+        let (line_segments, next) = rest.split_at(n);
+        rest = next;
+        let start_of_skipped_region =
+            line_segments.first().is_some_and(|s| !s.has_count() && s.is_region_entry());
+        let has_region = line_segments.iter().any(|s| is_start_of_region(&s));
+        let mapped =
+            !start_of_skipped_region && (wrapped.is_some_and(Segment::has_count) || has_region);
+        if mapped {
+            let count = line_segments
+                .iter()
+                .filter(is_start_of_region)
+                .map(Segment::count)
+                .fold(wrapped.map_or(0, Segment::count), u64::max);
+            lines.push((line, count));
+        }
+        if let Some(last) = line_segments.last() {
+            wrapped = Some(last);
+        }
+    }
+    lines
+}
+
+/// Returns (line, execution count) of lines covered by the given code regions
+/// of a function.
+///
+/// Like [`line_counts`], the count of a line is the maximum of the counts of
+/// the regions that start in the line and the innermost region that contains
+/// the start of the line.
+fn region_line_counts(regions: &[&Region]) -> BTreeMap<u64, u64> {
+    // line -> (max count of regions starting in the line, (start, count) of innermost wrapping region)
+    let mut lines: BTreeMap<u64, (Option<u64>, Option<((u64, u64), u64)>)> = BTreeMap::new();
+    for r in regions {
+        let (start, count) = ((r.line_start(), r.column_start()), r.execution_count());
+        let e = lines.entry(r.line_start()).or_default();
+        e.0 = Some(e.0.map_or(count, |c| c.max(count)));
+        for line in r.line_start() + 1..=r.line_end() {
+            let e = lines.entry(line).or_default();
+            if e.1.is_none_or(|(s, _)| s < start) {
+                e.1 = Some((start, count));
+            }
+        }
+    }
+    lines
+        .into_iter()
+        .map(|(line, (starts, wrapped))| {
+            (line, starts.unwrap_or(0).max(wrapped.map_or(0, |(_, count)| count)))
+        })
+        .collect()
+}
+
+/// Coverage data in the [Cobertura XML format][cobertura].
+///
+/// This can be written to XML with [`to_xml`](Self::to_xml), and parsed from
+/// XML written by this or the coverage tools of other languages (e.g.,
+/// coverage.py and istanbul) with [`str::parse`]. Line and branch rates are
+/// calculated from the lines.
+///
+/// [cobertura]: https://github.com/cobertura/web/blob/master/htdocs/xml/coverage-04.dtd
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoberturaExport {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// List of directories that file names of classes are relative to
+    pub sources: Vec<String>,
+    pub packages: Vec<CoberturaPackage>,
+}
+
+/// A directory of source files in the Cobertura format
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoberturaPackage {
+    pub name: String,
+    pub classes: Vec<CoberturaClass>,
+}
+
+/// A source file in the Cobertura format
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoberturaClass {
+    pub name: String,
+    pub filename: String,
+    pub methods: Vec<CoberturaMethod>,
+    pub lines: Vec<CoberturaLine>,
+}
+
+/// A function in the Cobertura format
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoberturaMethod {
+    pub name: String,
+    pub signature: String,
+    pub lines: Vec<CoberturaLine>,
+}
+
+/// A line in the Cobertura format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoberturaLine {
+    pub number: u64,
+    pub hits: u64,
+    /// The number of branches in the line
+    pub branches_valid: u64,
+    /// The number of branches in the line that were taken
+    pub branches_covered: u64,
+}
+
+/// (lines, branches) of Cobertura elements.
+trait CoberturaLines {
+    fn cobertura_lines(&self) -> Box<dyn Iterator<Item = &CoberturaLine> + '_>;
+
+    fn line_rate(&self) -> f64 {
+        let lines: Vec<_> = self.cobertura_lines().collect();
+        rate(lines.iter().filter(|l| l.hits > 0).count() as u64, lines.len() as u64)
+    }
+
+    fn branch_rate(&self) -> f64 {
+        let (covered, valid) = self
+            .cobertura_lines()
+            .fold((0, 0), |(c, v), l| (c + l.branches_covered, v + l.branches_valid));
+        rate(covered, valid)
+    }
+}
+
+impl CoberturaExport {
+    /// Converts the coverage data exported by llvm-cov to the Cobertura
+    /// format, in the same way as `cargo llvm-cov --cobertura`.
+    ///
+    /// File names under `source` are made relative to it, and function names
+    /// are demangled.
+    #[must_use]
+    pub fn from_llvm_cov_json_export(
+        value: &LlvmCovJsonExport,
+        source: &str,
+        timestamp: u64,
+    ) -> Self {
+        Self::from_lcov(&LcovExport::from_llvm_cov_json_export(value), source, timestamp)
+    }
+
+    /// Converts the coverage data in the lcov format to the Cobertura format.
+    ///
+    /// File names under `source` are made relative to it, and function names
+    /// are demangled.
+    #[must_use]
+    pub fn from_lcov(value: &LcovExport, source: &str, timestamp: u64) -> Self {
+        let mut packages: BTreeMap<String, Vec<CoberturaClass>> = BTreeMap::new();
+        for record in &value.records {
+            let filename = record
+                .source_file
+                .strip_prefix(source)
+                .and_then(|s| s.strip_prefix(std::path::is_separator))
+                .filter(|_| !source.is_empty())
+                .unwrap_or(&record.source_file)
+                .replace('\\', "/");
+            let mut branches: BTreeMap<u64, (u64, u64)> = BTreeMap::new();
+            for branch in &record.branches {
+                let (valid, covered) = branches.entry(branch.line).or_default();
+                *valid += 1;
+                *covered += u64::from(branch.taken.is_some_and(|n| n > 0));
+            }
+            let line = |number: u64, hits: u64| {
+                let (branches_valid, branches_covered) =
+                    branches.get(&number).copied().unwrap_or_default();
+                CoberturaLine { number, hits, branches_valid, branches_covered }
+            };
+            let methods = record
+                .functions
+                .iter()
+                .map(|func| CoberturaMethod {
+                    name: format!("{:#}", rustc_demangle::demangle(&func.name)),
+                    signature: String::new(),
+                    lines: vec![line(func.line, func.execution_count)],
+                })
+                .collect();
+            let lines = record.lines.iter().map(|l| line(l.line, l.execution_count)).collect();
+            let package = match filename.rsplit_once('/') {
+                Some((dir, _)) => dir.replace('/', "."),
+                None => ".".to_owned(),
+            };
+            packages.entry(package).or_default().push(CoberturaClass {
+                name: filename.replace('/', "."),
+                filename,
+                methods,
+                lines,
+            });
+        }
+        Self {
+            timestamp,
+            sources: if source.is_empty() { vec![] } else { vec![source.to_owned()] },
+            packages: packages
+                .into_iter()
+                .map(|(name, classes)| CoberturaPackage { name, classes })
+                .collect(),
+        }
+    }
+
+    /// Converts the coverage data in the Cobertura format to the lcov format.
+    ///
+    /// File names are joined to the first source directory.
+    #[must_use]
+    pub fn to_lcov(&self) -> LcovExport {
+        let mut records = vec![];
+        for class in self.packages.iter().flat_map(|p| &p.classes) {
+            let source_file = match self.sources.first() {
+                Some(source) if !std::path::Path::new(&class.filename).is_absolute() => {
+                    format!("{}/{}", source.trim_end_matches(['/', '\\']), class.filename)
+                }
+                _ => class.filename.clone(),
+            };
+            let functions = class
+                .methods
+                .iter()
+                .filter_map(|method| {
+                    let line = method.lines.first()?;
+                    Some(LcovFunction {
+                        name: method.name.clone(),
+                        line: line.number,
+                        execution_count: line.hits,
+                    })
+                })
+                .collect();
+            let lines = class
+                .lines
+                .iter()
+                .map(|l| LcovLine { line: l.number, execution_count: l.hits })
+                .collect();
+            let mut branches = vec![];
+            for l in &class.lines {
+                for i in 0..l.branches_valid {
+                    let taken = Some(u64::from(i < l.branches_covered));
+                    branches.push(LcovBranch { line: l.number, block: 0, branch: i, taken });
+                }
+            }
+            records.push(LcovRecord { source_file, functions, lines, branches });
+        }
+        LcovExport { records }
+    }
+
+    /// Converts the coverage data in the Cobertura format to the format
+    /// exported by llvm-cov. See [`LcovExport::to_llvm_cov_json_export`] for
+    /// the information lost in the conversion.
+    #[must_use]
+    pub fn to_llvm_cov_json_export(&self) -> LlvmCovJsonExport {
+        self.to_lcov().to_llvm_cov_json_export()
+    }
+
+    #[must_use]
+    pub fn line_rate(&self) -> f64 {
+        CoberturaLines::line_rate(self)
+    }
+
+    #[must_use]
+    pub fn branch_rate(&self) -> f64 {
+        CoberturaLines::branch_rate(self)
+    }
+
+    /// Writes the coverage data as Cobertura XML.
+    #[must_use]
+    pub fn to_xml(&self) -> String {
+        fn line_xml(out: &mut String, indent: &str, line: &CoberturaLine) {
+            let _ = write!(
+                out,
+                "{indent}<line number=\"{}\" hits=\"{}\" branch=\"{}\"",
+                line.number,
+                line.hits,
+                line.branches_valid > 0
+            );
+            if let Some(percent) = (line.branches_covered * 100).checked_div(line.branches_valid) {
+                let _ = write!(
+                    out,
+                    " condition-coverage=\"{percent}% ({}/{})\"",
+                    line.branches_covered, line.branches_valid
+                );
+            }
+            out.push_str("/>\n");
+        }
+        fn rates(v: &impl CoberturaLines) -> String {
+            format!("line-rate=\"{}\" branch-rate=\"{}\"", v.line_rate(), v.branch_rate())
+        }
+
+        let lines: Vec<_> = self.cobertura_lines().collect();
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" ?>\n");
+        out.push_str(
+            "<!DOCTYPE coverage SYSTEM \"https://cobertura.sourceforge.net/xml/coverage-04.dtd\">\n",
+        );
+        let _ = writeln!(
+            out,
+            "<coverage {} lines-covered=\"{}\" lines-valid=\"{}\" branches-covered=\"{}\" \
+             branches-valid=\"{}\" complexity=\"0\" timestamp=\"{}\" version=\"{}\">",
+            rates(self),
+            lines.iter().filter(|l| l.hits > 0).count(),
+            lines.len(),
+            lines.iter().map(|l| l.branches_covered).sum::<u64>(),
+            lines.iter().map(|l| l.branches_valid).sum::<u64>(),
+            self.timestamp,
+            env!("CARGO_PKG_VERSION"),
+        );
+        out.push_str("    <sources>\n");
+        for source in &self.sources {
+            let _ = writeln!(out, "        <source>{}</source>", xml_escape(source));
+        }
+        out.push_str("    </sources>\n    <packages>\n");
+        for package in &self.packages {
+            let _ = writeln!(
+                out,
+                "        <package name=\"{}\" {} complexity=\"0\">\n            <classes>",
+                xml_escape(&package.name),
+                rates(package)
+            );
+            for class in &package.classes {
+                let _ = writeln!(
+                    out,
+                    "                <class name=\"{}\" filename=\"{}\" {} complexity=\"0\">\n                    <methods>",
+                    xml_escape(&class.name),
+                    xml_escape(&class.filename),
+                    rates(class)
+                );
+                for method in &class.methods {
+                    let _ = writeln!(
+                        out,
+                        "                        <method name=\"{}\" signature=\"{}\" {} complexity=\"0\">\n                            <lines>",
+                        xml_escape(&method.name),
+                        xml_escape(&method.signature),
+                        rates(method)
+                    );
+                    for line in &method.lines {
+                        line_xml(&mut out, "                                ", line);
+                    }
+                    out.push_str(
+                        "                            </lines>\n                        </method>\n",
+                    );
+                }
+                out.push_str("                    </methods>\n                    <lines>\n");
+                for line in &class.lines {
+                    line_xml(&mut out, "                        ", line);
+                }
+                out.push_str("                    </lines>\n                </class>\n");
+            }
+            out.push_str("            </classes>\n        </package>\n");
+        }
+        out.push_str("    </packages>\n</coverage>\n");
+        out
+    }
+}
+
+impl str::FromStr for CoberturaExport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        fn unescape(s: &str) -> Result<String> {
+            let mut out = String::with_capacity(s.len());
+            let mut rest = s;
+            while let Some(start) = rest.find('&') {
+                out.push_str(&rest[..start]);
+                rest = &rest[start + 1..];
+                let (entity, r) =
+                    rest.split_once(';').with_context(|| format!("invalid entity in `{s}`"))?;
+                out.push(match entity {
+                    "amp" => '&',
+                    "lt" => '<',
+                    "gt" => '>',
+                    "quot" => '"',
+                    "apos" => '\'',
+                    _ => entity
+                        .strip_prefix("#x")
+                        .map(|n| u32::from_str_radix(n, 16))
+                        .or_else(|| entity.strip_prefix('#').map(str::parse))
+                        .and_then(Result::ok)
+                        .and_then(char::from_u32)
+                        .with_context(|| format!("invalid entity `&{entity};`"))?,
+                });
+                rest = r;
+            }
+            out.push_str(rest);
+            Ok(out)
+        }
+        fn attrs(tag: &str) -> Result<HashMap<&str, String>> {
+            let mut attrs = HashMap::new();
+            let mut rest = tag;
+            while let Some((key, value)) = rest.split_once('=') {
+                let value = value.trim_start();
+                let quote = value
+                    .chars()
+                    .next()
+                    .filter(|&c| c == '"' || c == '\'')
+                    .with_context(|| format!("invalid attribute in Cobertura tag `<{tag}>`"))?;
+                let (value, r) = value[1..]
+                    .split_once(quote)
+                    .with_context(|| format!("invalid attribute in Cobertura tag `<{tag}>`"))?;
+                attrs.insert(key.trim(), unescape(value)?);
+                rest = r;
+            }
+            Ok(attrs)
+        }
+        fn num(attrs: &HashMap<&str, String>, key: &str, tag: &str) -> Result<u64> {
+            let value = attrs
+                .get(key)
+                .with_context(|| format!("missing attribute `{key}` in Cobertura tag `<{tag}>`"))?;
+            value.trim().parse().with_context(|| {
+                format!("invalid number in attribute `{key}` of Cobertura tag `<{tag}>`")
+            })
+        }
+
+        fn last_class<'a>(
+            packages: &'a mut [CoberturaPackage],
+            tag: &str,
+        ) -> Result<&'a mut CoberturaClass> {
+            packages
+                .last_mut()
+                .and_then(|p| p.classes.last_mut())
+                .with_context(|| format!("Cobertura tag `<{tag}>` outside of <class>"))
+        }
+
+        let mut cobertura = Self::default();
+        let mut in_method = false;
+        let mut rest = s;
+        while let Some(start) = rest.find('<') {
+            let text = &rest[..start];
+            rest = &rest[start + 1..];
+            if let Some(r) = rest.strip_prefix("!--") {
+                rest = r.split_once("-->").context("unterminated comment in Cobertura XML")?.1;
+                continue;
+            }
+            let (tag, r) = rest.split_once('>').context("unterminated tag in Cobertura XML")?;
+            rest = r;
+            if tag.starts_with(['?', '!']) {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                match name.trim() {
+                    "source" => cobertura.sources.push(unescape(text.trim())?),
+                    "method" => in_method = false,
+                    _ => {}
+                }
+                continue;
+            }
+            let (tag, empty) = match tag.strip_suffix('/') {
+                Some(tag) => (tag, true),
+                None => (tag, false),
+            };
+            let (name, attrs_str) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+            let attrs = attrs(attrs_str)?;
+            match name {
+                "coverage" => {
+                    cobertura.timestamp =
+                        attrs.get("timestamp").and_then(|t| t.trim().parse().ok()).unwrap_or(0);
+                }
+                "package" => cobertura.packages.push(CoberturaPackage {
+                    name: attrs.get("name").cloned().unwrap_or_default(),
+                    classes: vec![],
+                }),
+                "class" => {
+                    let filename = attrs.get("filename").cloned().with_context(|| {
+                        format!("missing attribute `filename` in Cobertura tag `<{tag}>`")
+                    })?;
+                    cobertura
+                        .packages
+                        .last_mut()
+                        .with_context(|| format!("Cobertura tag `<{tag}>` outside of <package>"))?
+                        .classes
+                        .push(CoberturaClass {
+                            name: attrs.get("name").cloned().unwrap_or_default(),
+                            filename,
+                            methods: vec![],
+                            lines: vec![],
+                        });
+                }
+                "method" => {
+                    last_class(&mut cobertura.packages, tag)?.methods.push(CoberturaMethod {
+                        name: attrs.get("name").cloned().unwrap_or_default(),
+                        signature: attrs.get("signature").cloned().unwrap_or_default(),
+                        lines: vec![],
+                    });
+                    in_method = !empty;
+                }
+                "line" => {
+                    // e.g., condition-coverage="50% (1/2)"
+                    let (branches_covered, branches_valid) = match attrs.get("condition-coverage") {
+                        Some(c) => c
+                            .split_once('(')
+                            .and_then(|(_, c)| c.trim().strip_suffix(')')?.split_once('/'))
+                            .and_then(|(covered, valid)| {
+                                Some((covered.trim().parse().ok()?, valid.trim().parse().ok()?))
+                            })
+                            .with_context(|| {
+                                format!("invalid condition-coverage in Cobertura tag `<{tag}>`")
+                            })?,
+                        None => (0, 0),
+                    };
+                    let line = CoberturaLine {
+                        number: num(&attrs, "number", tag)?,
+                        hits: num(&attrs, "hits", tag)?,
+                        branches_valid,
+                        branches_covered,
+                    };
+                    let class = last_class(&mut cobertura.packages, tag)?;
+                    match class.methods.last_mut() {
+                        Some(method) if in_method => method.lines.push(line),
+                        _ => class.lines.push(line),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(cobertura)
+    }
+}
+
+impl CoberturaLines for CoberturaExport {
+    fn cobertura_lines(&self) -> Box<dyn Iterator<Item = &CoberturaLine> + '_> {
+        Box::new(self.packages.iter().flat_map(CoberturaLines::cobertura_lines))
+    }
+}
+impl CoberturaLines for CoberturaPackage {
+    fn cobertura_lines(&self) -> Box<dyn Iterator<Item = &CoberturaLine> + '_> {
+        Box::new(self.classes.iter().flat_map(|c| &c.lines))
+    }
+}
+impl CoberturaLines for CoberturaClass {
+    fn cobertura_lines(&self) -> Box<dyn Iterator<Item = &CoberturaLine> + '_> {
+        Box::new(self.lines.iter())
+    }
+}
+impl CoberturaLines for CoberturaMethod {
+    fn cobertura_lines(&self) -> Box<dyn Iterator<Item = &CoberturaLine> + '_> {
+        Box::new(self.lines.iter())
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn rate(covered: u64, valid: u64) -> f64 {
+    if valid == 0 {
+        0_f64
+    } else {
+        covered as f64 / valid as f64
+    }
+}
+
+pub(crate) fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The version of the schema of [`SummaryJsonExport`].
+///
+/// This is incremented when fields are removed or their meaning changes. Adding
+/// fields is not considered a breaking change, so consumers should ignore
+/// unknown fields.
+pub(crate) const SUMMARY_JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Coverage summary in the stable schema output by `--summary-json`.
+///
+/// Unlike [`LlvmCovJsonExport`], whose format is defined by llvm-cov, this
+/// schema is defined by cargo-llvm-cov and versioned by
+/// [`SUMMARY_JSON_SCHEMA_VERSION`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SummaryJsonExport {
+    /// Version of the schema
+    pub(crate) schema_version: u32,
+    /// Version of cargo-llvm-cov that generated the summary
+    pub(crate) cargo_llvm_cov_version: String,
+    /// Arguments passed to cargo-llvm-cov (excluding `cargo llvm-cov`)
+    pub(crate) args: Vec<String>,
+    /// The commit checked out in the workspace, or `None` if the workspace is
+    /// not in a git repository.
+    pub(crate) commit: Option<SummaryCommit>,
+    /// Totals of all files
+    pub(crate) totals: SummaryTotals,
+    /// Totals of files in each package, sorted by package name
+    ///
+    /// Files that don't belong to any package are only included in `totals`.
+    pub(crate) packages: Vec<SummaryPackage>,
+    /// Totals of the coverage collected by running each kind of target, sorted
+    /// by kind
+    ///
+    /// This is only included if `--summary-by-target-kind` is used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) target_kinds: Vec<SummaryTargetKind>,
+    /// Totals of files owned by each code owner in the CODEOWNERS file, sorted
+    /// by owner
+    ///
+    /// This is only included if `--summary-by-owner` is used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) owners: Vec<SummaryOwner>,
+    /// Totals that distinguish code covered in at least one instantiation of
+    /// each function from code covered in all of its instantiations
+    ///
+    /// This is only included if `--summary-by-instantiation` is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) instantiations: Option<InstantiationTotals>,
+    /// Checks of the profile data the coverage was computed from
+    ///
+    /// This is not included if the summary is not generated by cargo-llvm-cov
+    /// from profile data (e.g., [`from_llvm_cov_json_export`](Self::from_llvm_cov_json_export)).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) data_quality: Option<DataQuality>,
+    /// Whether tests failed in the run the coverage was collected from
+    /// (--ignore-run-fail, --report-on-fail, or --keep-going)
+    ///
+    /// The coverage of such a run may be lower than usual, since failed tests
+    /// may stop before executing all the code they normally cover.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) tests_failed: bool,
+}
+
+/// Checks of the profile data that indicate whether the coverage numbers can be
+/// trusted
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct DataQuality {
+    /// Number of profraw files merged into the profile data
+    pub(crate) profraw_files: u64,
+    /// Empty profraw files, which llvm-profdata ignores
+    ///
+    /// These are usually written by processes that were terminated before
+    /// writing profile data, or failed to write it (e.g., the disk is full).
+    pub(crate) empty_files: Vec<String>,
+    /// Profraw files that llvm-profdata could not read (e.g., partially written
+    /// ones) and were moved to the quarantine directory by `--quarantine`
+    pub(crate) quarantined_files: Vec<String>,
+    /// Profraw files generated by binaries that no longer exist in the target
+    /// directory
+    ///
+    /// This usually means the binaries were rebuilt or removed after the
+    /// profile data was generated, so the profile data doesn't match the
+    /// coverage mappings of the current binaries.
+    pub(crate) unknown_binary_files: Vec<String>,
+    /// The largest counter value in the merged profile data
+    pub(crate) max_count: u64,
+    /// Whether `max_count` is implausibly large, which indicates counter
+    /// overflow (llvm-profdata saturates counters on overflow when merging)
+    /// or corrupted profile data
+    pub(crate) implausible_counts: bool,
+}
+
+impl DataQuality {
+    /// Returns `true` if no problems were found.
+    #[must_use]
+    pub(crate) fn is_ok(&self) -> bool {
+        self.empty_files.is_empty()
+            && self.quarantined_files.is_empty()
+            && self.unknown_binary_files.is_empty()
+            && !self.implausible_counts
+    }
+}
+
+/// Information about the commit checked out in the workspace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SummaryCommit {
+    /// Full hash of the commit
+    pub(crate) sha: String,
+    /// Name of the current branch, or `None` if HEAD is detached
+    pub(crate) branch: Option<String>,
+    /// Whether tracked files have uncommitted changes
+    pub(crate) dirty: bool,
+}
+
+/// Totals of files in a package
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SummaryPackage {
+    pub(crate) name: String,
+    pub(crate) totals: SummaryTotals,
+}
+
+/// Totals of the coverage collected by running one kind of target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SummaryTargetKind {
+    /// One of `unit-tests`, `integration-tests`, `doctests`, `examples`,
+    /// `benches`, `binaries` (executables run from tests or by run subcommand),
+    /// or `other`
+    pub(crate) kind: String,
+    pub(crate) totals: SummaryTotals,
+}
+
+/// Totals of files owned by a code owner
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SummaryOwner {
+    /// The owner as written in the CODEOWNERS file (e.g., `@org/team`), or
+    /// `(unowned)` for files that have no owners
+    pub(crate) owner: String,
+    pub(crate) totals: SummaryTotals,
+}
+
+/// Coverage counts for each kind of coverage
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SummaryTotals {
+    pub(crate) functions: SummaryCounts,
+    pub(crate) lines: SummaryCounts,
+    pub(crate) regions: SummaryCounts,
+    pub(crate) branches: SummaryCounts,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SummaryCounts {
+    pub(crate) count: u64,
+    pub(crate) covered: u64,
+    /// `covered / count * 100`, or 0 if `count` is 0
+    pub(crate) percent: f64,
+}
+
+impl SummaryJsonExport {
+    /// Summarizes the coverage data exported by llvm-cov.
+    ///
+    /// `package_of` returns the name of the package that contains the given
+    /// source file, or `None` if it doesn't belong to any package. `args` and
+    /// `commit` are left empty.
+    pub(crate) fn from_llvm_cov_json_export(
+        value: &LlvmCovJsonExport,
+        mut package_of: impl FnMut(&str) -> Option<String>,
+    ) -> Result<Self> {
+        let mut totals = SummaryTotals::default();
+        for data in &value.data {
+            let summary: Summary =
+                serde_json::from_value(data.totals.clone()).context("failed to parse totals")?;
+            totals.add(&summary);
+        }
+        let mut packages: BTreeMap<String, SummaryTotals> = BTreeMap::new();
+        for file in value.data.iter().flat_map(|data| &data.files) {
+            if let Some(package) = package_of(&file.filename) {
+                packages.entry(package).or_default().add(&file.summary);
+            }
+        }
+        Ok(Self {
+            schema_version: SUMMARY_JSON_SCHEMA_VERSION,
+            cargo_llvm_cov_version: env!("CARGO_PKG_VERSION").to_owned(),
+            args: vec![],
+            commit: None,
+            totals,
+            packages: packages
+                .into_iter()
+                .map(|(name, totals)| SummaryPackage { name, totals })
+                .collect(),
+            target_kinds: vec![],
+            owners: vec![],
+            instantiations: None,
+            data_quality: None,
+            tests_failed: false,
+        })
+    }
+}
+
+impl SummaryTotals {
+    fn add(&mut self, summary: &Summary) {
+        for (counts, other) in [
+            (&mut self.functions, &summary.functions),
+            (&mut self.lines, &summary.lines),
+            (&mut self.regions, &summary.regions),
+            (&mut self.branches, &summary.branches),
+        ] {
+            counts.count += other.count;
+            counts.covered += other.covered;
+            counts.percent =
+                if counts.count == 0 { 0_f64 } else { percent(counts.covered, counts.count) };
+        }
+    }
+}
+
+/// Coverage counts of functions, lines, and regions summarized over the
+/// instantiations of each function
+///
+/// llvm-cov (and [`SummaryTotals`]) considers code of a generic function
+/// covered if it is executed in at least one instantiation. `covered_all`
+/// counts code executed in all instantiations instead, which shows how much
+/// code is covered for every type a generic function is used with.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct InstantiationTotals {
+    /// Number of functions that have more than one instantiation
+    pub(crate) generic_functions: u64,
+    /// Number of instantiations of all functions
+    pub(crate) instantiations: u64,
+    pub(crate) functions: InstantiationCounts,
+    pub(crate) lines: InstantiationCounts,
+    pub(crate) regions: InstantiationCounts,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct InstantiationCounts {
+    pub(crate) count: u64,
+    /// Number of items covered in at least one instantiation
+    pub(crate) covered_any: u64,
+    /// Number of items covered in all instantiations
+    pub(crate) covered_all: u64,
+    /// `covered_any / count * 100`, or 0 if `count` is 0
+    pub(crate) percent_any: f64,
+    /// `covered_all / count * 100`, or 0 if `count` is 0
+    pub(crate) percent_all: f64,
+}
+
+impl InstantiationCounts {
+    fn add(&mut self, covered_any: bool, covered_all: bool) {
+        self.count += 1;
+        self.covered_any += u64::from(covered_any);
+        self.covered_all += u64::from(covered_all);
+        self.percent_any = percent(self.covered_any, self.count);
+        self.percent_all = percent(self.covered_all, self.count);
+    }
+}
+
+/// The version of the schema of [`UncoveredSpansExport`].
+pub(crate) const UNCOVERED_SPANS_SCHEMA_VERSION: u32 = 1;
+
+/// Source code not executed by any test, in the schema output by
+/// `--uncovered-spans-json`.
+///
+/// This is intended to be consumed by mutation testing tools such as
+/// cargo-mutants, so that they can skip mutants in code that no test executes
+/// (such mutants can never be caught). Locations are 1-based lines and columns
+/// reported by llvm-cov, and the end column is exclusive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct UncoveredSpansExport {
+    /// Version of the schema
+    pub(crate) schema_version: u32,
+    /// Files that contain uncovered code, sorted by name
+    pub(crate) files: Vec<UncoveredSpansFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct UncoveredSpansFile {
+    /// Path of the source file, in the same form as in the json export of llvm-cov
+    pub(crate) file: String,
+    /// Uncovered spans sorted by their start locations
+    ///
+    /// Spans nested in other uncovered spans are omitted.
+    pub(crate) spans: Vec<UncoveredSpan>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct UncoveredSpan {
+    pub(crate) start: SpanLocation,
+    pub(crate) end: SpanLocation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) struct SpanLocation {
+    pub(crate) line: u64,
+    pub(crate) column: u64,
+}
+
+impl UncoveredSpansExport {
+    /// Collects the code regions that are not executed in any instantiation.
+    ///
+    /// Returns no files if the report is summary-only.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ignore_filename_regex` is not a valid regular expression.
+    #[must_use]
+    pub(crate) fn from_llvm_cov_json_export(
+        value: &LlvmCovJsonExport,
+        ignore_filename_regex: Option<&str>,
+    ) -> Self {
+        let re = ignore_filename_regex.map(|s| Regex::new(s).unwrap());
+        // filename -> (start, end) -> covered
+        let mut regions: BTreeMap<&str, BTreeMap<(SpanLocation, SpanLocation), bool>> =
+            BTreeMap::new();
+        for func in value.data.iter().filter_map(|data| data.functions.as_ref()).flatten() {
+            // Expansion, skipped, gap, and branch regions are not code regions.
+            for region in func.regions.iter().filter(|r| r.kind() == 0) {
+                let Some(filename) =
+                    usize::try_from(region.file_id()).ok().and_then(|id| func.filenames.get(id))
+                else {
+                    continue;
+                };
+                if re.as_ref().is_some_and(|re| re.is_match(filename)) {
+                    continue;
+                }
+                let start =
+                    SpanLocation { line: region.line_start(), column: region.column_start() };
+                let end = SpanLocation { line: region.line_end(), column: region.column_end() };
+                let covered = regions.entry(filename).or_default().entry((start, end)).or_default();
+                *covered |= region.execution_count() > 0;
+            }
+        }
+        let mut files = vec![];
+        for (filename, regions) in regions {
+            let mut spans: Vec<UncoveredSpan> = vec![];
+            for ((start, end), covered) in regions {
+                if covered {
+                    continue;
+                }
+                if let Some(last) = spans.last() {
+                    if last.start <= start && end <= last.end {
+                        continue;
+                    }
+                }
+                spans.push(UncoveredSpan { start, end });
+            }
+            if !spans.is_empty() {
+                files.push(UncoveredSpansFile { file: filename.to_owned(), spans });
+            }
+        }
+        Self { schema_version: UNCOVERED_SPANS_SCHEMA_VERSION, files }
     }
+}
 
-    pub fn count_uncovered_regions(&self) -> Result<u64> {
-        let mut count = 0_u64;
-        let mut covered = 0_u64;
-        for data in &self.data {
-            let totals = &data.totals.as_object().context("totals is not an object")?;
-            let regions = &totals["regions"].as_object().context("no regions")?;
-            count += regions["count"].as_u64().context("no count")?;
-            covered += regions["covered"].as_u64().context("no covered")?;
+/// The version of the schema of [`UncoveredRegionsExport`].
+pub(crate) const UNCOVERED_REGIONS_SCHEMA_VERSION: u32 = 1;
+
+/// Code regions not executed by any test, in the schema output by
+/// `--list-uncovered=json`.
+///
+/// Unlike [`UncoveredSpansExport`], regions nested in other uncovered regions
+/// are also listed, each with the function that contains it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct UncoveredRegionsExport {
+    /// Version of the schema
+    pub(crate) schema_version: u32,
+    /// Uncovered regions sorted by file and start location
+    pub(crate) regions: Vec<UncoveredRegion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct UncoveredRegion {
+    /// Path of the source file, in the same form as in the json export of llvm-cov
+    pub(crate) file: String,
+    pub(crate) start: SpanLocation,
+    pub(crate) end: SpanLocation,
+    /// Name of the function that contains the region, as in the json export
+    /// of llvm-cov (mangled unless demangled)
+    ///
+    /// If the region is in a generic function, this is the name of the first
+    /// instantiation in the report.
+    pub(crate) function: String,
+}
+
+impl UncoveredRegionsExport {
+    /// Collects the code regions that are not executed in any instantiation.
+    ///
+    /// Returns no regions if the report is summary-only.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ignore_filename_regex` is not a valid regular expression.
+    #[must_use]
+    pub(crate) fn from_llvm_cov_json_export(
+        value: &LlvmCovJsonExport,
+        ignore_filename_regex: Option<&str>,
+    ) -> Self {
+        let re = ignore_filename_regex.map(|s| Regex::new(s).unwrap());
+        // (filename, start, end) -> (covered, function)
+        let mut regions: BTreeMap<(&str, SpanLocation, SpanLocation), (bool, &str)> =
+            BTreeMap::new();
+        for func in value.data.iter().filter_map(|data| data.functions.as_ref()).flatten() {
+            // Expansion, skipped, gap, and branch regions are not code regions.
+            for region in func.regions.iter().filter(|r| r.kind() == 0) {
+                let Some(filename) =
+                    usize::try_from(region.file_id()).ok().and_then(|id| func.filenames.get(id))
+                else {
+                    continue;
+                };
+                if re.as_ref().is_some_and(|re| re.is_match(filename)) {
+                    continue;
+                }
+                let start =
+                    SpanLocation { line: region.line_start(), column: region.column_start() };
+                let end = SpanLocation { line: region.line_end(), column: region.column_end() };
+                let (covered, _) =
+                    regions.entry((filename, start, end)).or_insert((false, &func.name));
+                *covered |= region.execution_count() > 0;
+            }
         }
-        Ok(count.saturating_sub(covered))
+        let regions = regions
+            .into_iter()
+            .filter(|(_, (covered, _))| !covered)
+            .map(|((file, start, end), (_, function))| UncoveredRegion {
+                file: file.to_owned(),
+                start,
+                end,
+                function: function.to_owned(),
+            })
+            .collect();
+        Self { schema_version: UNCOVERED_REGIONS_SCHEMA_VERSION, regions }
     }
 }
 
+/// The version of the schema of [`CoverageOffExport`].
+pub(crate) const COVERAGE_OFF_SCHEMA_VERSION: u32 = 1;
+
+/// Items excluded from instrumentation by `#[coverage(off)]` (or
+/// `#[no_coverage]`) attributes, in the schema output by `--coverage-off-json`.
+///
+/// Code in these items never appears in the coverage data, so this is
+/// collected from the source files of the workspace members.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CoverageOffExport {
+    /// Version of the schema
+    pub(crate) schema_version: u32,
+    /// Excluded items sorted by their locations
+    pub(crate) items: Vec<CoverageOffItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) struct CoverageOffItem {
+    /// Path of the source file, relative to the workspace root
+    pub(crate) file: String,
+    /// 1-based line of the attribute
+    pub(crate) line: u64,
+    /// `fn`, `impl`, `mod`, `trait`, or `file` (inner attribute, e.g., `#![coverage(off)]`)
+    pub(crate) kind: String,
+    /// Name of the item (the type and trait for `impl`, and empty for `file`)
+    pub(crate) name: String,
+    /// The attribute as written (e.g., `#[cfg_attr(coverage_nightly, coverage(off))]`)
+    pub(crate) attribute: String,
+}
+
 /// Json representation of one `CoverageMapping`
 #[derive(Debug, Serialize, Deserialize)]
 #[cfg_attr(test, serde(deny_unknown_fields))]
@@ -414,6 +2448,387 @@ struct Function {
     regions: Vec<Region>,
 }
 
+impl Function {
+    /// Returns the start and end locations of the code of this function in the
+    /// file that contains it.
+    fn extent(&self) -> Option<((u64, u64), (u64, u64))> {
+        let regions = self.regions.iter().filter(|r| r.file_id() == 0);
+        let start = regions.clone().map(|r| (r.line_start(), r.column_start())).min()?;
+        let end = regions.map(|r| (r.line_end(), r.column_end())).max()?;
+        Some((start, end))
+    }
+}
+
+/// Functions removed from an [`Export`], from which the function and
+/// instantiation summaries are updated.
+#[derive(Default)]
+struct RemovedFunctions {
+    // Like llvm-cov, instantiations are grouped by the start location of the function.
+    // filename -> (line, column) -> group
+    groups: BTreeMap<String, BTreeMap<(u64, u64), FunctionGroup>>,
+    // filename -> removed instantiations
+    removed: BTreeMap<String, (u64, u64)>,
+}
+
+#[derive(Default)]
+struct FunctionGroup {
+    covered: bool,
+    kept: bool,
+    kept_covered: bool,
+}
+
+impl RemovedFunctions {
+    /// Records the function, which is removed unless `keep` is `true`.
+    fn visit(&mut self, func: &Function, keep: bool) {
+        let (Some(filename), Some(region)) = (func.filenames.first(), func.regions.first()) else {
+            return;
+        };
+        let covered = func.count > 0;
+        let group = self
+            .groups
+            .entry(filename.clone())
+            .or_default()
+            .entry((region.line_start(), region.column_start()))
+            .or_default();
+        group.covered |= covered;
+        if keep {
+            group.kept = true;
+            group.kept_covered |= covered;
+        } else {
+            let removed = self.removed.entry(filename.clone()).or_default();
+            removed.0 += 1;
+            removed.1 += u64::from(covered);
+        }
+    }
+
+    fn apply(&self, data: &mut Export) {
+        let mut total_functions = (0, 0);
+        let mut total_instantiations = (0, 0);
+        for file in &mut data.files {
+            let Some(groups) = self.groups.get(&file.filename) else { continue };
+            let functions = groups.values().fold((0, 0), |(count, covered), g| {
+                (count + u64::from(!g.kept), covered + u64::from(g.covered && !g.kept_covered))
+            });
+            let instantiations = self.removed.get(&file.filename).copied().unwrap_or_default();
+            file.summary.functions.subtract(functions);
+            file.summary.instantiations.subtract(instantiations);
+            total_functions.0 += functions.0;
+            total_functions.1 += functions.1;
+            total_instantiations.0 += instantiations.0;
+            total_instantiations.1 += instantiations.1;
+        }
+        subtract_totals(&mut data.totals, [
+            ("functions", total_functions),
+            ("instantiations", total_instantiations),
+        ]);
+    }
+}
+
+/// Functions removed from an [`Export`] along with the code they contain, which
+/// is removed from the files when applied.
+#[derive(Default)]
+struct RemovedCode {
+    // filename -> extents of removed functions
+    extents: BTreeMap<String, Vec<((u64, u64), (u64, u64))>>,
+    // Like llvm-cov, instantiations are grouped by the start location of the function,
+    // and the coverage of the group is the maximum of the instantiations.
+    // filename -> (line, column) -> group
+    groups: BTreeMap<String, BTreeMap<(u64, u64), CodeGroup>>,
+}
+
+#[derive(Default)]
+struct CodeGroup {
+    regions: (u64, u64),
+    branches: (u64, u64),
+}
+
+impl RemovedCode {
+    /// Records the removed function.
+    fn visit(&mut self, func: &Function) {
+        let (Some(filename), Some((start, end))) = (func.filenames.first(), func.extent()) else {
+            return;
+        };
+        self.extents.entry(filename.clone()).or_default().push((start, end));
+        let group = self.groups.entry(filename.clone()).or_default().entry(start).or_default();
+        let code_regions = func.regions.iter().filter(|r| r.kind() == 0);
+        let regions = code_regions.fold((0, 0), |(count, covered), r| {
+            (count + 1, covered + u64::from(r.execution_count() > 0))
+        });
+        let branches = func.branches.iter().filter_map(|b| b.as_array()).fold(
+            (0, 0),
+            |(count, covered), b| {
+                let taken =
+                    |i: usize| b.get(i).and_then(serde_json::Value::as_u64).unwrap_or(0) > 0;
+                (count + 2, covered + u64::from(taken(4)) + u64::from(taken(5)))
+            },
+        );
+        group.regions = (regions.0.max(group.regions.0), regions.1.max(group.regions.1));
+        group.branches = (branches.0.max(group.branches.0), branches.1.max(group.branches.1));
+    }
+
+    fn apply(&self, data: &mut Export) {
+        if self.extents.is_empty() {
+            return;
+        }
+
+        let in_extents = |extents: &[((u64, u64), (u64, u64))], pos: (u64, u64)| {
+            extents.iter().any(|&(start, end)| start <= pos && pos < end)
+        };
+        let start_of = |v: &serde_json::Value| {
+            let v = v.as_array()?;
+            Some((v.first()?.as_u64()?, v.get(1)?.as_u64()?))
+        };
+        let mut total_lines = (0, 0);
+        let mut total_regions = (0, 0);
+        let mut total_branches = (0, 0);
+        for file in &mut data.files {
+            let Some(extents) = self.extents.get(&file.filename) else { continue };
+            if let Some(segments) = &mut file.segments {
+                let old_lines = line_counts(segments);
+                segments.retain(|s| !in_extents(extents, (s.line(), s.col())));
+                // Make the code that was in the removed functions unmapped.
+                for &((line, col), _) in extents {
+                    segments.push(Segment(line, col, 0, false, false, false));
+                }
+                segments.sort_by_key(|s| (s.line(), s.col()));
+                segments.dedup_by_key(|s| (s.line(), s.col()));
+                let new_lines = line_counts(segments);
+                let count = |lines: &[(u64, u64)]| {
+                    (lines.len() as u64, lines.iter().filter(|&&(_, c)| c > 0).count() as u64)
+                };
+                let (old, new) = (count(&old_lines), count(&new_lines));
+                let lines = (old.0 - new.0.min(old.0), old.1 - new.1.min(old.1));
+                file.summary.lines.subtract(lines);
+                total_lines.0 += lines.0;
+                total_lines.1 += lines.1;
+            }
+            if let Some(branches) = &mut file.branches {
+                branches.retain(|b| !start_of(b).is_some_and(|pos| in_extents(extents, pos)));
+            }
+            if let Some(expansions) = &mut file.expansions {
+                expansions.retain(|e| {
+                    !e.get("source_region")
+                        .and_then(start_of)
+                        .is_some_and(|pos| in_extents(extents, pos))
+                });
+            }
+            let Some(groups) = self.groups.get(&file.filename) else { continue };
+            let (regions, branches) =
+                groups.values().fold(((0, 0), (0, 0)), |(regions, branches), g| {
+                    (
+                        (regions.0 + g.regions.0, regions.1 + g.regions.1),
+                        (branches.0 + g.branches.0, branches.1 + g.branches.1),
+                    )
+                });
+            file.summary.regions.subtract(regions);
+            file.summary.branches.subtract(branches);
+            total_regions.0 += regions.0;
+            total_regions.1 += regions.1;
+            total_branches.0 += branches.0;
+            total_branches.1 += branches.1;
+        }
+        subtract_totals(&mut data.totals, [
+            ("lines", total_lines),
+            ("regions", total_regions),
+            ("branches", total_branches),
+        ]);
+    }
+}
+
+/// Subtracts `(count, covered)` from the counts of each kind in `totals`.
+fn subtract_totals<const N: usize>(
+    totals: &mut serde_json::Value,
+    counts: [(&str, (u64, u64)); N],
+) {
+    let Some(totals) = totals.as_object_mut() else { return };
+    for (kind, (count, covered)) in counts {
+        if let Some(Ok(mut counts)) =
+            totals.get(kind).map(|v| serde_json::from_value::<CoverageCounts>(v.clone()))
+        {
+            counts.subtract((count, covered));
+            if let Ok(counts) = serde_json::to_value(counts) {
+                totals.insert(kind.to_owned(), counts);
+            }
+        }
+    }
+}
+
+// The following seeds are used by `LlvmCovJsonExport::from_reader` to process
+// `functions` of each export one at a time.
+
+struct ExportSeed<'a> {
+    retain_code: &'a mut dyn FnMut(&str) -> bool,
+    retain: &'a mut dyn FnMut(&str) -> bool,
+}
+
+impl<'de> DeserializeSeed<'de> for ExportSeed<'_> {
+    type Value = LlvmCovJsonExport;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ExportSeed<'_> {
+    type Value = LlvmCovJsonExport;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("llvm-cov JSON export")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut data, mut type_, mut version) = (None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match &*key {
+                "data" => {
+                    data = Some(map.next_value_seed(DataSeed {
+                        retain_code: &mut *self.retain_code,
+                        retain: &mut *self.retain,
+                    })?);
+                }
+                "type" => type_ = Some(map.next_value()?),
+                "version" => version = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(LlvmCovJsonExport {
+            data: data.ok_or_else(|| de::Error::missing_field("data"))?,
+            type_: type_.ok_or_else(|| de::Error::missing_field("type"))?,
+            version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+            cargo_llvm_cov: None,
+        })
+    }
+}
+
+struct DataSeed<'a> {
+    retain_code: &'a mut dyn FnMut(&str) -> bool,
+    retain: &'a mut dyn FnMut(&str) -> bool,
+}
+
+impl<'de> DeserializeSeed<'de> for DataSeed<'_> {
+    type Value = Vec<Export>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DataSeed<'_> {
+    type Value = Vec<Export>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a list of exports")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut data = vec![];
+        loop {
+            let mut functions = FunctionsSeed {
+                retain_code: &mut *self.retain_code,
+                retain: &mut *self.retain,
+                code: RemovedCode::default(),
+                removed_with_code: RemovedFunctions::default(),
+                removed: RemovedFunctions::default(),
+            };
+            let Some(export) = seq.next_element_seed(ExportDataSeed(&mut functions))? else {
+                break;
+            };
+            data.push(export);
+        }
+        Ok(data)
+    }
+}
+
+struct ExportDataSeed<'a, 'b>(&'a mut FunctionsSeed<'b>);
+
+impl<'de> DeserializeSeed<'de> for ExportDataSeed<'_, '_> {
+    type Value = Export;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ExportDataSeed<'_, '_> {
+    type Value = Export;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an export object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut files, mut functions, mut totals) = (None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match &*key {
+                "files" => files = Some(map.next_value()?),
+                "functions" => functions = Some(map.next_value_seed(&mut *self.0)?),
+                "totals" => totals = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        let mut export = Export {
+            files: files.ok_or_else(|| de::Error::missing_field("files"))?,
+            functions,
+            totals: totals.ok_or_else(|| de::Error::missing_field("totals"))?,
+        };
+        if export.functions.is_some() {
+            // Remove the code first, as if functions were removed in two passes.
+            self.0.code.apply(&mut export);
+            self.0.removed_with_code.apply(&mut export);
+            self.0.removed.apply(&mut export);
+        }
+        Ok(export)
+    }
+}
+
+struct FunctionsSeed<'a> {
+    retain_code: &'a mut dyn FnMut(&str) -> bool,
+    retain: &'a mut dyn FnMut(&str) -> bool,
+    code: RemovedCode,
+    removed_with_code: RemovedFunctions,
+    removed: RemovedFunctions,
+}
+
+impl<'de> DeserializeSeed<'de> for &mut FunctionsSeed<'_> {
+    type Value = Vec<Function>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for &mut FunctionsSeed<'_> {
+    type Value = Vec<Function>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a list of functions")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut functions = vec![];
+        while let Some(func) = seq.next_element::<Function>()? {
+            let keep = (self.retain_code)(&func.name);
+            if !keep {
+                self.code.visit(&func);
+            }
+            self.removed_with_code.visit(&func, keep);
+            if !keep {
+                continue;
+            }
+            let keep = (self.retain)(&func.name);
+            self.removed.visit(&func, keep);
+            if keep {
+                functions.push(func);
+            }
+        }
+        Ok(functions)
+    }
+}
+
 #[derive(Copy, Clone, Serialize, Deserialize)]
 #[cfg_attr(test, serde(deny_unknown_fields))]
 struct Region(
@@ -521,6 +2936,42 @@ struct CoverageCounts {
     percent: f64,
 }
 
+impl CoverageCounts {
+    fn subtract(&mut self, (count, covered): (u64, u64)) {
+        if count == 0 && covered == 0 {
+            return;
+        }
+        self.count = self.count.saturating_sub(count);
+        self.covered = self.covered.saturating_sub(covered);
+        if let Some(notcovered) = &mut self.notcovered {
+            *notcovered = self.count - self.covered;
+        }
+        self.percent = if self.count == 0 { 0_f64 } else { percent(self.covered, self.count) };
+    }
+}
+
+/// Rounded if --precision or --rounding is specified.
+#[allow(clippy::cast_precision_loss)]
+fn percent(covered: u64, count: u64) -> f64 {
+    crate::percent::round(covered as f64 * 100_f64 / count as f64)
+}
+
+/// Returns the length of the common directory of the given files, including the trailing separator.
+fn common_dir_len(files: &[&File]) -> usize {
+    let [first, rest @ ..] = files else { return 0 };
+    if rest.is_empty() {
+        return 0;
+    }
+    let first = first.filename.as_bytes();
+    let mut len = rest.iter().fold(first.len(), |len, file| {
+        first[..len].iter().zip(file.filename.as_bytes()).take_while(|(a, b)| a == b).count()
+    });
+    while len > 0 && !std::path::is_separator(first[len - 1] as char) {
+        len -= 1;
+    }
+    len
+}
+
 /// Information that is not part of the llvm-cov JSON export, but instead injected afterwards by us.
 #[derive(Debug, Default, Serialize)]
 #[cfg_attr(test, derive(PartialEq))]
@@ -530,6 +2981,10 @@ struct CargoLlvmCov {
     version: &'static str,
     /// Resolved path to the `Cargo.toml` manifest.
     manifest_path: String,
+    /// Whether tests failed in the run the coverage was collected from
+    /// (--ignore-run-fail, --report-on-fail, or --keep-going).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    tests_failed: bool,
 }
 
 #[cfg(test)]
@@ -540,6 +2995,39 @@ mod tests {
 
     use super::*;
 
+    /// Removes functions from the whole parsed export, as a reference for
+    /// [`LlvmCovJsonExport::from_reader`].
+    fn retain_functions(
+        json: &mut LlvmCovJsonExport,
+        mut retain_code: impl FnMut(&str) -> bool,
+        mut retain: impl FnMut(&str) -> bool,
+    ) {
+        for data in &mut json.data {
+            let Some(functions) = &mut data.functions else { continue };
+            let mut code = RemovedCode::default();
+            let mut removed = RemovedFunctions::default();
+            functions.retain(|func| {
+                let keep = retain_code(&func.name);
+                if !keep {
+                    code.visit(func);
+                }
+                removed.visit(func, keep);
+                keep
+            });
+            code.apply(data);
+            removed.apply(data);
+
+            let Some(functions) = &mut data.functions else { continue };
+            let mut removed = RemovedFunctions::default();
+            functions.retain(|func| {
+                let keep = retain(&func.name);
+                removed.visit(func, keep);
+                keep
+            });
+            removed.apply(data);
+        }
+    }
+
     #[test]
     fn test_parse_llvm_cov_json() {
         let files: Vec<_> = glob::glob(&format!(
@@ -556,7 +3044,11 @@ mod tests {
             let s = fs::read_to_string(file).unwrap();
             let json = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
             assert_eq!(json.type_, "llvm.coverage.json.export");
-            assert!(json.version.starts_with("2.0."));
+            assert!(
+                json.version.starts_with("2.0.") || json.version.starts_with("3."),
+                "{}",
+                json.version
+            );
             assert_eq!(json.cargo_llvm_cov, None);
             serde_json::to_string(&json).unwrap();
         }
@@ -636,6 +3128,155 @@ mod tests {
         assert_eq!(uncovered_lines, expected);
     }
 
+    #[test]
+    fn test_retain_functions() {
+        let file = format!(
+            "{}/tests/fixtures/show-missing-lines-multi-missing.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let s = fs::read_to_string(file).unwrap();
+        let mut json = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+        assert_eq!(json.count_uncovered_functions().unwrap(), 2);
+
+        // Remove serde-generated functions at line 11.
+        retain_functions(&mut json, |_| true, |name| !name.contains("5serde2de11Deserialize"));
+
+        let data = &json.data[0];
+        assert_eq!(data.functions.as_ref().unwrap().len(), 7);
+        let summary = &data.files[0].summary;
+        assert_eq!((summary.functions.count, summary.functions.covered), (7, 5));
+        assert_eq!((summary.instantiations.count, summary.instantiations.covered), (7, 5));
+        assert_eq!(json.count_uncovered_functions().unwrap(), 2);
+        assert!(
+            (json.get_coverage_percent(CoverageKind::Functions).unwrap() - 500_f64 / 7_f64).abs()
+                < 1e-9
+        );
+        let uncovered_lines = json.get_uncovered_lines(None);
+        let expected: UncoveredLines =
+            vec![("src/lib.rs".to_string(), vec![15, 17])].into_iter().collect();
+        assert_eq!(uncovered_lines, expected);
+
+        json.remove_details();
+        assert!(json.data[0].functions.is_none());
+        assert!(json.data[0].files[0].segments.is_none());
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let file = format!(
+            "{}/tests/fixtures/show-missing-lines-multi-missing.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let s = fs::read_to_string(file).unwrap();
+        // Remove the test function and the closure in it with their code, and
+        // serde-generated functions.
+        let retain_code = |name: &str| !name.contains("5tests");
+        let retain = |name: &str| !name.contains("5serde2de11Deserialize");
+        let mut expected = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+        retain_functions(&mut expected, retain_code, retain);
+
+        let json =
+            LlvmCovJsonExport::from_reader(s.as_bytes(), &mut { retain_code }, &mut { retain })
+                .unwrap();
+        assert_eq!(
+            serde_json::to_string(&json).unwrap(),
+            serde_json::to_string(&expected).unwrap()
+        );
+        assert_eq!(json.data[0].functions.as_ref().unwrap().len(), 5);
+
+        // Summary-only export doesn't contain functions.
+        let mut summary = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+        summary.remove_details();
+        let s = serde_json::to_string(&summary).unwrap();
+        let json =
+            LlvmCovJsonExport::from_reader(s.as_bytes(), &mut |_| false, &mut |_| false).unwrap();
+        assert_eq!(serde_json::to_string(&json).unwrap(), s);
+    }
+
+    #[test]
+    fn test_sort() {
+        let file = format!(
+            "{}/tests/fixtures/show-missing-lines-multi-missing.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let s = fs::read_to_string(file).unwrap();
+        let mut json = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+        json.sort();
+        let expected = serde_json::to_string(&json).unwrap();
+
+        // The result doesn't depend on the original order.
+        let data = &mut json.data[0];
+        data.functions.as_mut().unwrap().reverse();
+        data.files.push(File {
+            filename: "src/a.rs".to_owned(),
+            ..serde_json::from_value(serde_json::to_value(&data.files[0]).unwrap()).unwrap()
+        });
+        json.sort();
+        let data = &json.data[0];
+        assert_eq!(data.files[0].filename, "src/a.rs");
+        json.data[0].files.remove(0);
+        assert_eq!(serde_json::to_string(&json).unwrap(), expected);
+
+        let functions = json.data[0].functions.as_ref().unwrap();
+        for w in functions.windows(2) {
+            let (a, b) = (&w[0].regions[0], &w[1].regions[0]);
+            assert!(
+                (a.line_start(), a.column_start(), &w[0].name)
+                    <= (b.line_start(), b.column_start(), &w[1].name)
+            );
+        }
+    }
+
+    #[test]
+    fn test_summary_formats() {
+        let file = format!(
+            "{}/tests/fixtures/show-missing-lines-multi-missing.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let s = fs::read_to_string(file).unwrap();
+        let json = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+        let files = json.data[0].files.len();
+
+        let markdown = json.summary_markdown().unwrap();
+        let lines: Vec<_> = markdown.lines().collect();
+        assert_eq!(lines.len(), files + 3);
+        assert_eq!(lines[0], "| Filename | Lines | Functions | Regions | Branches |");
+        assert!(lines[files + 2].starts_with("| **TOTAL** | "), "{markdown}");
+
+        let csv = json.summary_csv().unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), files + 2);
+        assert!(lines[0].starts_with("filename,lines_count,lines_covered,lines_percent,"));
+        assert!(lines.iter().all(|l| l.split(',').count() == 13), "{csv}");
+        assert!(lines[files + 1].starts_with("\"TOTAL\","), "{csv}");
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let file = format!(
+            "{}/tests/fixtures/show-missing-lines-multi-missing.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let s = fs::read_to_string(file).unwrap();
+        let json = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+        let mut out = vec![];
+        json.write_json_lines(&mut out).unwrap();
+        let lines: Vec<serde_json::Value> = str::from_utf8(&out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), json.data[0].files.len() + 1);
+        assert_eq!(lines[0]["type"], "file");
+        assert_eq!(lines[0]["filename"], json.data[0].files[0].filename);
+        let functions: usize =
+            lines.iter().map(|l| l["functions"].as_array().map_or(0, Vec::len)).sum();
+        assert_eq!(json.data[0].functions.as_ref().unwrap().len(), functions);
+        let totals = lines.last().unwrap();
+        assert_eq!(totals["type"], "totals");
+        assert_eq!(totals["totals"], json.data[0].totals);
+    }
+
     #[test]
     /// This was a case when counting line coverage based on the segments in files lead to
     /// incorrect results but doing it based on regions inside functions (the way `llvm-cov
@@ -679,4 +3320,264 @@ mod tests {
         // 2) only the last function with missing lines were reported, so 15 and 17 was missing.
         assert_eq!(uncovered_lines, expected);
     }
+
+    #[test]
+    fn test_get_line_coverage() {
+        let file = format!("{}/tests/fixtures/show-missing-lines.json", env!("CARGO_MANIFEST_DIR"));
+        let s = fs::read_to_string(file).unwrap();
+        let mut json = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+        let file = &mut json.data[0].files[0];
+        file.segments = Some(vec![
+            Segment(1, 1, 1, true, true, false),
+            Segment(1, 5, 0, true, true, false),
+            Segment(1, 8, 1, true, false, false),
+            Segment(2, 1, 0, true, true, false),
+            Segment(3, 1, 2, true, true, false),
+            Segment(4, 2, 0, false, false, false),
+        ]);
+        file.branches = Some(vec![serde_json::json!([4, 1, 4, 5, 2, 0, 0, 0, 4])]);
+
+        let line = |line, count, partial| LineCoverage { line, count, partial };
+        assert_eq!(json.get_line_coverage("src/lib.rs").unwrap(), vec![
+            line(1, 1, true),
+            line(2, 1, true),
+            line(3, 2, false),
+            line(4, 2, true)
+        ]);
+        assert_eq!(json.get_line_coverage("src/main.rs"), None);
+    }
+
+    #[test]
+    fn test_uncovered_spans() {
+        let file = format!("{}/tests/fixtures/show-missing-lines.json", env!("CARGO_MANIFEST_DIR"));
+        let s = fs::read_to_string(file).unwrap();
+        let mut json = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+        // Another instantiation of `bar` that executes its first region except
+        // for a region that contains another uncovered region.
+        let functions = json.data[0].functions.as_mut().unwrap();
+        functions.push(Function {
+            branches: vec![],
+            count: 1,
+            filenames: vec!["src/lib.rs".to_owned()],
+            name: functions[0].name.clone(),
+            regions: vec![
+                Region(7, 1, 8, 13, 1, 0, 0, 0),
+                Region(7, 3, 7, 5, 0, 0, 0, 0),
+                Region(7, 4, 7, 5, 0, 0, 0, 0),
+            ],
+        });
+
+        let location = |line, column| SpanLocation { line, column };
+        let span = |start: (u64, u64), end: (u64, u64)| UncoveredSpan {
+            start: location(start.0, start.1),
+            end: location(end.0, end.1),
+        };
+        let export = UncoveredSpansExport::from_llvm_cov_json_export(&json, None);
+        assert_eq!(export.schema_version, UNCOVERED_SPANS_SCHEMA_VERSION);
+        assert_eq!(export.files.len(), 1);
+        assert_eq!(export.files[0].file, "src/lib.rs");
+        assert_eq!(export.files[0].spans, [
+            span((7, 3), (7, 5)),
+            span((8, 13), (8, 14)),
+            span((9, 1), (9, 2))
+        ]);
+        assert_eq!(
+            serde_json::to_string(&export.files[0].spans[0]).unwrap(),
+            r#"{"start":{"line":7,"column":3},"end":{"line":7,"column":5}}"#
+        );
+        let export = UncoveredSpansExport::from_llvm_cov_json_export(&json, Some("lib"));
+        assert!(export.files.is_empty());
+    }
+
+    #[test]
+    fn test_uncovered_regions() {
+        let file = format!("{}/tests/fixtures/show-missing-lines.json", env!("CARGO_MANIFEST_DIR"));
+        let s = fs::read_to_string(file).unwrap();
+        let mut json = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+        // Another function that covers the first region of `bar`, and has a
+        // region nested in another uncovered region.
+        let functions = json.data[0].functions.as_mut().unwrap();
+        let bar = functions[0].name.clone();
+        functions.push(Function {
+            branches: vec![],
+            count: 1,
+            filenames: vec!["src/lib.rs".to_owned()],
+            name: "other".to_owned(),
+            regions: vec![
+                Region(7, 1, 8, 13, 1, 0, 0, 0),
+                Region(7, 3, 7, 5, 0, 0, 0, 0),
+                Region(7, 4, 7, 5, 0, 0, 0, 0),
+            ],
+        });
+
+        let export = UncoveredRegionsExport::from_llvm_cov_json_export(&json, None);
+        assert_eq!(export.schema_version, UNCOVERED_REGIONS_SCHEMA_VERSION);
+        let regions: Vec<_> = export
+            .regions
+            .iter()
+            .map(|r| {
+                (
+                    &*r.file,
+                    (r.start.line, r.start.column),
+                    (r.end.line, r.end.column),
+                    &*r.function,
+                )
+            })
+            .collect();
+        assert_eq!(regions, [
+            ("src/lib.rs", (7, 3), (7, 5), "other"),
+            ("src/lib.rs", (7, 4), (7, 5), "other"),
+            ("src/lib.rs", (8, 13), (8, 14), &*bar),
+            ("src/lib.rs", (9, 1), (9, 2), &*bar)
+        ]);
+        let export = UncoveredRegionsExport::from_llvm_cov_json_export(&json, Some("lib"));
+        assert!(export.regions.is_empty());
+    }
+
+    #[test]
+    fn test_instantiation_totals() {
+        let file = format!("{}/tests/fixtures/show-missing-lines.json", env!("CARGO_MANIFEST_DIR"));
+        let s = fs::read_to_string(file).unwrap();
+        let mut json = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+        let totals = json.instantiation_totals();
+        assert_eq!(totals.generic_functions, 0);
+        assert_eq!(totals.instantiations, 5);
+        assert_eq!(
+            (totals.lines.count, totals.lines.covered_any, totals.lines.covered_all),
+            (11, 8, 8)
+        );
+
+        // Another instantiation of `foo` that is not executed, and another
+        // instantiation of `bar` that only executes its first region.
+        let functions = json.data[0].functions.as_mut().unwrap();
+        for (name, count, regions) in [
+            (functions[3].name.clone(), 0, vec![Region(3, 1, 5, 2, 0, 0, 0, 0)]),
+            (functions[0].name.clone(), 1, vec![
+                Region(7, 1, 8, 13, 1, 0, 0, 0),
+                Region(8, 13, 8, 14, 0, 0, 0, 0),
+                Region(9, 1, 9, 2, 0, 0, 0, 0),
+            ]),
+        ] {
+            functions.push(Function {
+                branches: vec![],
+                count,
+                filenames: vec!["src/lib.rs".to_owned()],
+                name,
+                regions,
+            });
+        }
+        let totals = json.instantiation_totals();
+        assert_eq!(totals.generic_functions, 2);
+        assert_eq!(totals.instantiations, 7);
+        let counts = |c: &InstantiationCounts| (c.count, c.covered_any, c.covered_all);
+        // foo and bar are executed in only one of their instantiations.
+        assert_eq!(counts(&totals.functions), (5, 5, 3));
+        // Lines 7 and 8 of bar are covered in one instantiation, and lines 3-5 of foo are not
+        // covered in all instantiations.
+        assert_eq!(counts(&totals.lines), (11, 10, 5));
+        assert_eq!(counts(&totals.regions), (7, 5, 3));
+        assert_eq!(totals.lines.percent_all, 500. / 11.);
+    }
+
+    #[test]
+    fn test_lcov() {
+        let file = format!("{}/tests/fixtures/show-missing-lines.json", env!("CARGO_MANIFEST_DIR"));
+        let s = fs::read_to_string(file).unwrap();
+        let json = serde_json::from_str::<LlvmCovJsonExport>(&s).unwrap();
+
+        let lcov = LcovExport::from_llvm_cov_json_export(&json);
+        let record = &lcov.records[0];
+        assert_eq!(record.source_file, "src/lib.rs");
+        assert_eq!(record.functions.len(), 5);
+        let lines: Vec<_> = record.lines.iter().map(|l| (l.line, l.execution_count)).collect();
+        assert_eq!(lines, [
+            (1, 1),
+            (3, 1),
+            (4, 1),
+            (5, 1),
+            (7, 0),
+            (8, 0),
+            (9, 0),
+            (14, 1),
+            (15, 1),
+            (16, 1),
+            (17, 1)
+        ]);
+        let out = lcov.to_string();
+        assert!(out.contains("FNF:5\nFNH:4\n"), "{out}");
+        assert!(out.contains("LF:11\nLH:8\nend_of_record\n"), "{out}");
+        assert_eq!(out.parse::<LcovExport>().unwrap(), lcov);
+
+        // The lines, functions, and summaries are preserved.
+        let json = lcov.to_llvm_cov_json_export();
+        assert_eq!(LcovExport::from_llvm_cov_json_export(&json), lcov);
+        assert_eq!(json.count_uncovered_functions().unwrap(), 1);
+        assert_eq!(json.count_uncovered_lines().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_lcov_branches() {
+        let s = "TN:\nSF:/a/src/lib.rs\nFN:1,f\nFNDA:1,f\nFNF:1\nFNH:1\nDA:1,1\nDA:2,1\nDA:3,0\n\
+                 BRDA:2,0,0,1\nBRDA:2,0,1,0\nBRDA:3,0,0,-\nBRDA:3,0,1,-\nBRF:4\nBRH:1\nLF:3\nLH:2\n\
+                 end_of_record\n";
+        let lcov: LcovExport = s.parse().unwrap();
+        assert_eq!(lcov.records[0].branches.len(), 4);
+        assert_eq!(lcov.records[0].branches[2].taken, None);
+        assert_eq!(lcov.to_string(), s.strip_prefix("TN:\n").unwrap());
+        let json = lcov.to_llvm_cov_json_export();
+        assert_eq!(LcovExport::from_llvm_cov_json_export(&json), lcov);
+
+        let cobertura = CoberturaExport::from_lcov(&lcov, "/a", 0);
+        assert_eq!(cobertura.sources, ["/a"]);
+        assert_eq!(cobertura.packages[0].name, "src");
+        let class = &cobertura.packages[0].classes[0];
+        assert_eq!((&*class.name, &*class.filename), ("src.lib.rs", "src/lib.rs"));
+        assert_eq!(class.lines[1], CoberturaLine {
+            number: 2,
+            hits: 1,
+            branches_valid: 2,
+            branches_covered: 1
+        });
+        assert!((cobertura.line_rate() - 2_f64 / 3_f64).abs() < 1e-9);
+        assert!((cobertura.branch_rate() - 0.25).abs() < 1e-9);
+        let xml = cobertura.to_xml();
+        assert!(
+            xml.contains(
+                r#"<line number="2" hits="1" branch="true" condition-coverage="50% (1/2)"/>"#
+            ),
+            "{xml}"
+        );
+        assert_eq!(xml.parse::<CoberturaExport>().unwrap(), cobertura);
+        let back = cobertura.to_lcov();
+        assert_eq!(back.records[0].source_file, "/a/src/lib.rs");
+        assert_eq!(back.records[0].lines, lcov.records[0].lines);
+        assert_eq!(back.records[0].functions, lcov.records[0].functions);
+    }
+
+    #[test]
+    fn test_lcov_query() {
+        let lcov: LcovExport = "SF:/a/src/lib.rs\nDA:1,1\nDA:2,0\nDA:4,0\nDA:4,2\nend_of_record\n\
+                                SF:/a/src/main.rs\nDA:3,0\nend_of_record\n"
+            .parse()
+            .unwrap();
+        let record = lcov.record("src/lib.rs").unwrap();
+        assert_eq!(record.source_file, "/a/src/lib.rs");
+        assert_eq!(lcov.record("./src\\lib.rs"), Some(record));
+        assert_eq!(lcov.record("/a/src/main.rs").unwrap().source_file, "/a/src/main.rs");
+        assert_eq!(lcov.record("lib.rs"), Some(record));
+        assert_eq!(lcov.record("b/lib.rs"), None);
+        assert_eq!(lcov.record("c/src/lib.rs"), None);
+
+        assert_eq!(record.line(1), Some(1));
+        assert_eq!(record.line(3), None);
+        assert_eq!(record.line(4), Some(2));
+        assert_eq!(record.uncovered_lines().collect::<Vec<_>>(), [2]);
+        assert_eq!((record.lines_found(), record.lines_hit()), (4, 2));
+        assert!((record.lines_percent() - 50_f64).abs() < 1e-9);
+        assert_eq!(lcov.uncovered_lines().collect::<Vec<_>>(), [
+            ("/a/src/lib.rs", 2),
+            ("/a/src/main.rs", 3)
+        ]);
+        assert!((lcov.lines_percent() - 40_f64).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,352 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --junit support.
+//
+// The results of tests are recorded by parsing the output of `cargo test`
+// while running tests. libtest doesn't print which test binary it is running,
+// but cargo prints `Running <target> (<binary>)` and `Doc-tests <crate>` to
+// stderr before running each one, so stderr is read together with stdout. Both
+// the default human-readable format of libtest and its JSON format (`-- -Z
+// unstable-options --format json`, which also records the execution time of
+// each test) are recognized.
+//
+// After the report is generated, the coverage collected by running each test
+// binary is computed in the same way as --report-per-binary, and attached to
+// the corresponding test suite as properties. If tests fail (and
+// --ignore-run-fail is not used), the JUnit XML is written without coverage,
+// since the report is not generated.
+//
+// Refs:
+// - https://github.com/testmoapp/junitxml
+// - https://github.com/rust-lang/rust/blob/1.80.0/library/test/src/formatters/json.rs
+
+use std::{collections::BTreeMap, fmt::Write as _, mem};
+
+use anyhow::Result;
+use camino::Utf8Path;
+use serde_json::Value;
+
+use crate::{
+    context::Context,
+    fs,
+    json::{xml_escape, SummaryTotals},
+    percent,
+};
+
+#[derive(Debug, Default)]
+pub(crate) struct TestResults {
+    suites: Vec<TestSuite>,
+    /// The test whose output is being read, as an index into the tests of the
+    /// last suite.
+    output_of: Option<usize>,
+}
+
+#[derive(Debug)]
+struct TestSuite {
+    name: String,
+    /// The file name of the test binary, or `None` for doctests.
+    binary: Option<String>,
+    tests: Vec<TestCase>,
+    /// Execution time in seconds.
+    time: Option<f64>,
+}
+
+#[derive(Debug)]
+struct TestCase {
+    name: String,
+    outcome: Outcome,
+    /// Execution time in seconds, which is only available in the JSON format.
+    time: Option<f64>,
+    /// Captured output of a failed test.
+    output: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+impl TestResults {
+    /// Records the given line of the output of `cargo test`.
+    pub(crate) fn line(&mut self, line: &str) {
+        let line = line.trim_end();
+        if let Some(index) = self.output_of {
+            if line.starts_with("---- ") || line == "failures:" || line.starts_with("test result: ")
+            {
+                self.output_of = None;
+            } else {
+                let output = &mut self.suite().tests[index].output;
+                output.push_str(line);
+                output.push('\n');
+                return;
+            }
+        }
+        let trimmed = line.trim_start();
+        if let Some(rest) = trimmed.strip_prefix("Running ") {
+            // e.g., `Running unittests src/lib.rs (target/debug/deps/foo-0123456789abcdef)`
+            if let Some((target, binary)) =
+                rest.strip_suffix(')').and_then(|rest| rest.rsplit_once(" ("))
+            {
+                let binary = Utf8Path::new(binary).file_name().unwrap_or(binary);
+                let name = match binary.rsplit_once('-') {
+                    Some((name, hash)) if hash.len() == 16 => name,
+                    _ => binary,
+                };
+                self.start_suite(format!("{name} ({target})"), Some(binary.to_owned()));
+            }
+        } else if let Some(krate) = trimmed.strip_prefix("Doc-tests ") {
+            self.start_suite(format!("{krate} (doctests)"), None);
+        } else if line.starts_with('{') {
+            if let Ok(event) = serde_json::from_str::<Value>(line) {
+                self.json_event(&event);
+            }
+        } else if let Some(rest) = line.strip_prefix("test result: ") {
+            // e.g., `test result: ok. 1 passed; 0 failed; ...; finished in 0.00s`
+            let time = rest
+                .rsplit_once("finished in ")
+                .and_then(|(_, t)| t.strip_suffix('s').and_then(|t| t.parse().ok()));
+            self.suite().time = time;
+        } else if let Some(rest) = line.strip_prefix("test ") {
+            // e.g., `test tests::foo ... ok`
+            let Some((name, result)) = rest.rsplit_once(" ... ") else { return };
+            let outcome = match result {
+                "ok" => Outcome::Passed,
+                "FAILED" => Outcome::Failed,
+                _ if result.starts_with("ignored") => Outcome::Ignored,
+                _ => return,
+            };
+            self.add_test(name, outcome, None, String::new());
+        } else if let Some(name) =
+            line.strip_prefix("---- ").and_then(|rest| rest.strip_suffix(" stdout ----"))
+        {
+            self.output_of = self.suite().tests.iter().rposition(|t| t.name == name);
+        }
+    }
+
+    /// Records an event of the JSON format of libtest.
+    fn json_event(&mut self, event: &Value) {
+        let time = event["exec_time"].as_f64();
+        match (event["type"].as_str(), event["event"].as_str()) {
+            (Some("suite"), Some("ok" | "failed")) => self.suite().time = time,
+            (Some("test"), Some(result)) => {
+                let outcome = match result {
+                    "ok" => Outcome::Passed,
+                    "failed" => Outcome::Failed,
+                    "ignored" => Outcome::Ignored,
+                    _ => return,
+                };
+                let Some(name) = event["name"].as_str() else { return };
+                let output = event["stdout"].as_str().unwrap_or_default().to_owned();
+                self.add_test(name, outcome, time, output);
+            }
+            _ => {}
+        }
+    }
+
+    fn start_suite(&mut self, name: String, binary: Option<String>) {
+        self.output_of = None;
+        self.suites.push(TestSuite { name, binary, tests: vec![], time: None });
+    }
+
+    /// Returns the suite being run. Tests run without `Running` lines (e.g.,
+    /// with --quiet) are recorded in an unnamed suite.
+    fn suite(&mut self) -> &mut TestSuite {
+        if self.suites.is_empty() {
+            self.start_suite("tests".to_owned(), None);
+        }
+        self.suites.last_mut().unwrap()
+    }
+
+    fn add_test(&mut self, name: &str, outcome: Outcome, time: Option<f64>, output: String) {
+        let name = name.to_owned();
+        self.suite().tests.push(TestCase { name, outcome, time, output });
+    }
+}
+
+/// Writes the JUnit XML of the recorded test results to the path specified by
+/// --junit, along with the coverage collected by running each test binary
+/// (keyed by the file name of the binary) if available.
+pub(crate) fn write(
+    cx: &Context,
+    coverage: Option<&BTreeMap<String, SummaryTotals>>,
+) -> Result<()> {
+    let Some(path) = &cx.args.junit else { return Ok(()) };
+    let results = mem::take(&mut *cx.test_results.borrow_mut());
+    fs::write(path, to_xml(&results.suites, coverage))?;
+    status!("Finished", "JUnit XML saved to {path}");
+    Ok(())
+}
+
+fn to_xml(suites: &[TestSuite], coverage: Option<&BTreeMap<String, SummaryTotals>>) -> String {
+    let count =
+        |suite: &TestSuite, outcome| suite.tests.iter().filter(|t| t.outcome == outcome).count();
+    let time = |time: Option<f64>| time.map(|t| format!(" time=\"{t:.3}\"")).unwrap_or_default();
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        out,
+        "<testsuites name=\"cargo-llvm-cov\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">",
+        suites.iter().map(|s| s.tests.len()).sum::<usize>(),
+        suites.iter().map(|s| count(s, Outcome::Failed)).sum::<usize>(),
+        suites.iter().map(|s| count(s, Outcome::Ignored)).sum::<usize>(),
+    );
+    for suite in suites {
+        let name = xml_escape(&suite.name);
+        let _ = writeln!(
+            out,
+            "  <testsuite name=\"{name}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\"{}>",
+            suite.tests.len(),
+            count(suite, Outcome::Failed),
+            count(suite, Outcome::Ignored),
+            time(suite.time),
+        );
+        let totals = suite.binary.as_ref().and_then(|b| coverage?.get(b));
+        if let Some(totals) = totals {
+            out.push_str("    <properties>\n");
+            for (kind, counts) in [
+                ("lines", &totals.lines),
+                ("functions", &totals.functions),
+                ("regions", &totals.regions),
+                ("branches", &totals.branches),
+            ] {
+                // Branch coverage is only available if --branch is used.
+                if counts.count == 0 && kind == "branches" {
+                    continue;
+                }
+                for (key, value) in [
+                    (kind.to_owned(), percent::format(counts.percent)),
+                    (format!("{kind}.covered"), counts.covered.to_string()),
+                    (format!("{kind}.count"), counts.count.to_string()),
+                ] {
+                    let _ = writeln!(
+                        out,
+                        "      <property name=\"coverage.{key}\" value=\"{value}\"/>"
+                    );
+                }
+            }
+            out.push_str("    </properties>\n");
+        }
+        for test in &suite.tests {
+            let _ = write!(
+                out,
+                "    <testcase name=\"{}\" classname=\"{name}\"{}",
+                xml_escape(&test.name),
+                time(test.time)
+            );
+            match test.outcome {
+                Outcome::Passed => out.push_str("/>\n"),
+                Outcome::Ignored => out.push_str(">\n      <skipped/>\n    </testcase>\n"),
+                Outcome::Failed => {
+                    let _ = writeln!(
+                        out,
+                        ">\n      <failure message=\"test failed\">{}</failure>\n    </testcase>",
+                        xml_escape(test.output.trim())
+                    );
+                }
+            }
+        }
+        out.push_str("  </testsuite>\n");
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::SummaryCounts;
+
+    fn parse(output: &str) -> Vec<TestSuite> {
+        let mut results = TestResults::default();
+        for line in output.lines() {
+            results.line(line);
+        }
+        results.suites
+    }
+
+    #[test]
+    fn human() {
+        let suites = parse(
+            "     Running unittests src/lib.rs (target/debug/deps/foo-0123456789abcdef)
+
+running 3 tests
+test a ... ok
+test b ... ignored, slow
+test c ... FAILED
+
+failures:
+
+---- c stdout ----
+thread 'c' panicked at src/lib.rs:3:5:
+boom
+
+failures:
+    c
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.01s
+
+   Doc-tests foo
+
+running 1 test
+test src/lib.rs - f (line 1) ... ok
+
+test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.20s
+",
+        );
+        assert_eq!(suites.len(), 2);
+        assert_eq!(suites[0].name, "foo (unittests src/lib.rs)");
+        assert_eq!(suites[0].binary.as_deref(), Some("foo-0123456789abcdef"));
+        assert_eq!(suites[0].time, Some(0.01));
+        let outcomes: Vec<_> = suites[0].tests.iter().map(|t| (&*t.name, t.outcome)).collect();
+        assert_eq!(
+            outcomes,
+            [("a", Outcome::Passed), ("b", Outcome::Ignored), ("c", Outcome::Failed)]
+        );
+        assert_eq!(suites[0].tests[2].output, "thread 'c' panicked at src/lib.rs:3:5:\nboom\n\n");
+        assert_eq!(suites[1].name, "foo (doctests)");
+        assert_eq!(suites[1].binary, None);
+        assert_eq!(suites[1].tests[0].name, "src/lib.rs - f (line 1)");
+
+        let totals = SummaryTotals {
+            lines: SummaryCounts { count: 4, covered: 3, percent: 75. },
+            ..SummaryTotals::default()
+        };
+        let coverage = BTreeMap::from([("foo-0123456789abcdef".to_owned(), totals)]);
+        let xml = to_xml(&suites, Some(&coverage));
+        assert!(xml.contains(
+            "<testsuites name=\"cargo-llvm-cov\" tests=\"4\" failures=\"1\" skipped=\"1\">"
+        ));
+        assert!(xml.contains(
+            "  <testsuite name=\"foo (unittests src/lib.rs)\" tests=\"3\" failures=\"1\" \
+             skipped=\"1\" time=\"0.010\">\n    <properties>\n      <property \
+             name=\"coverage.lines\" value=\"75.00\"/>\n      <property \
+             name=\"coverage.lines.covered\" value=\"3\"/>"
+        ));
+        assert!(!xml.contains("coverage.branches"));
+        assert!(xml.contains(
+            "<testcase name=\"c\" classname=\"foo (unittests src/lib.rs)\">\n      <failure \
+             message=\"test failed\">thread &apos;c&apos; panicked at src/lib.rs:3:5:\nboom</failure>"
+        ));
+    }
+
+    #[test]
+    fn json() {
+        let suites = parse(
+            r#"     Running tests/test.rs (target/debug/deps/test-0123456789abcdef)
+{ "type": "suite", "event": "started", "test_count": 2 }
+{ "type": "test", "event": "started", "name": "a" }
+{ "type": "test", "name": "a", "event": "ok", "exec_time": 0.5 }
+{ "type": "test", "name": "b", "event": "failed", "exec_time": 0.25, "stdout": "boom\n" }
+{ "type": "suite", "event": "failed", "passed": 1, "failed": 1, "ignored": 0, "measured": 0, "filtered_out": 0, "exec_time": 0.75 }
+"#,
+        );
+        assert_eq!(suites.len(), 1);
+        assert_eq!(suites[0].name, "test (tests/test.rs)");
+        assert_eq!(suites[0].time, Some(0.75));
+        assert_eq!(suites[0].tests[0].time, Some(0.5));
+        assert_eq!(suites[0].tests[1].outcome, Outcome::Failed);
+        assert_eq!(suites[0].tests[1].output, "boom\n");
+    }
+}
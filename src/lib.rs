@@ -1,7 +1,3321 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
+//! Cargo subcommand to easily use LLVM source-based code coverage (-C instrument-coverage).
+//!
+//! This crate is primarily a binary crate, but also provides the following APIs for
+//! tools that drive coverage programmatically:
+//!
+//! - [`Coverage`]: A builder that runs the same pipeline as `cargo llvm-cov` and
+//!   returns the typed report.
+//! - [`json`]: Types for the coverage data exported by llvm-cov.
+//!
+//! Other items are not public APIs.
+
 #![forbid(unsafe_code)]
-// All items are not public APIs.
-#![doc(hidden)]
+#![allow(clippy::struct_field_names)]
+
+// Refs:
+// - https://doc.rust-lang.org/nightly/rustc/instrument-coverage.html
+// - https://llvm.org/docs/CommandGuide/llvm-profdata.html
+// - https://llvm.org/docs/CommandGuide/llvm-cov.html
+
+use std::{
+    borrow::Cow,
+    cell::OnceCell,
+    collections::{BTreeSet, HashMap},
+    ffi::{OsStr, OsString},
+    fmt::Write as _,
+    io::{self, BufRead, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context as _, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use cargo_config2::Flags;
+use regex::Regex;
+use walkdir::WalkDir;
+
+use crate::{
+    cargo::Workspace,
+    cli::{Args, ShowEnvOptions, Subcommand, SummaryFormat},
+    context::Context,
+    demangle::Demangler,
+    function_filter::FunctionFilter,
+    json::{
+        CodeCovJsonExport, CoverageKind, InstantiationCounts, InstantiationTotals,
+        LlvmCovJsonExport, SummaryCommit, SummaryCounts, SummaryJsonExport, SummaryTotals,
+        UncoveredSpansExport,
+    },
+    metadata::{Metadata, PackageId},
+    percent::Counts,
+    process::ProcessBuilder,
+    regex_vec::{RegexVec, RegexVecBuilder},
+    term::Coloring,
+};
+
+#[macro_use]
+mod term;
 
+#[macro_use]
+mod process;
+
+mod all_reports;
+mod annotate;
+mod blame;
+mod cache;
+mod cargo;
+mod cargo_profile;
+mod clean;
+mod cli;
+mod codeowners;
+mod collect;
+mod config;
+mod context;
+mod coverage_mapping;
+mod coverage_off;
+mod coveralls;
+mod data_quality;
+mod demangle;
+mod diff;
+mod diff_lines;
+mod download;
+mod env;
+mod excluded;
+mod external_report;
+mod external_runner;
+mod flaky;
+mod fs;
+mod function_filter;
+mod gate;
+mod grcov;
+mod harness;
+mod hook;
+mod html;
+mod html_assets;
+mod install;
+mod junit;
+mod list_uncovered;
+mod lock;
+mod metadata;
+mod msgpack;
+mod nextest_archive;
+mod nextest_runner;
+mod path_map;
+mod per_binary;
+mod percent;
+mod regex_vec;
+mod rustc_wrapper;
+mod should_panic;
+mod show;
+mod snapshot;
+mod test_contribution;
+mod timings;
+mod toolchain;
+mod trend;
+mod webhook;
+mod windows_path;
+
+mod api;
 pub mod json;
+
+pub use crate::api::{Coverage, ReportFormat};
+
+/// The entry point of the cargo-llvm-cov binary.
+#[doc(hidden)] // Not public API.
+pub fn main() {
+    term::init_coloring();
+    if let Err(e) = try_main() {
+        error!("{e:#}");
+    }
+    if term::error()
+        || term::warn() && env::var_os("CARGO_LLVM_COV_DENY_WARNINGS").is_some_and(|v| v == "true")
+    {
+        std::process::exit(1)
+    }
+}
+
+fn try_main() -> Result<()> {
+    // Internal API: `cargo-llvm-cov llvm-cov nextest-runner <test binary> [args]...`
+    if nextest_runner::try_run()? {
+        return Ok(());
+    }
+    // Internal API: `cargo-llvm-cov <rustc> [args]...`
+    if rustc_wrapper::try_run()? {
+        return Ok(());
+    }
+    run(Args::parse()?)
+}
+
+/// Runs the subcommand specified by `args`.
+fn run(mut args: Args) -> Result<()> {
+    term::verbose::set(args.verbose != 0);
+    let timings = timings::Recorder::start(&args);
+    let mut failed = vec![];
+
+    match args.subcommand {
+        Subcommand::Demangle => {
+            demangle::run(args.cov.demangle_keep_hashes, args.cov.demangle_cpp)?;
+        }
+        Subcommand::Clean => clean::run(&mut args)?,
+        Subcommand::Diff => diff::run(&mut args)?,
+        Subcommand::Trend => trend::run(&mut args)?,
+        Subcommand::Html => html::run(&mut args)?,
+        Subcommand::InstallHook => hook::run(&mut args)?,
+        Subcommand::CoverallsFinish => coveralls::run(&mut args)?,
+        Subcommand::Collect => {
+            let cx = &Context::new(args)?;
+            collect::run(cx)?;
+        }
+        Subcommand::ShowEnv => {
+            let cx = &Context::new(args)?;
+            let stdout = io::stdout();
+            let writer =
+                &mut ShowEnvWriter { writer: stdout.lock(), options: cx.args.show_env.clone() };
+            set_env(cx, writer, IsNextest(true))?; // Include envs for nextest.
+            if cx.args.show_env.separate_target_dir {
+                writer.set("CARGO_TARGET_DIR", cx.ws.target_dir.as_str())?;
+                writer.set("CARGO_LLVM_COV_TARGET_DIR", cx.ws.target_dir.as_str())?;
+            } else {
+                writer
+                    .set("CARGO_LLVM_COV_TARGET_DIR", cx.ws.metadata.target_directory.as_str())?;
+            }
+        }
+        Subcommand::Report | Subcommand::Show | Subcommand::Annotate | Subcommand::Snapshot => {
+            let cx = &mut Context::new(args)?;
+            create_dirs(cx)?;
+            generate_report(cx)?;
+        }
+        Subcommand::Run => {
+            let cx = &mut Context::new(args)?;
+            clean::clean_partial(cx)?;
+            create_dirs(cx)?;
+            run_run(cx)?;
+            if !cx.args.cov.no_report {
+                generate_report(cx)?;
+            }
+        }
+        Subcommand::Nextest => {
+            let cx = &mut Context::new(args)?;
+            clean::clean_partial(cx)?;
+            create_dirs(cx)?;
+            let res = run_nextest(cx);
+            if cx.args.discard_failed_attempts || cx.args.emit_test_contribution.is_some() {
+                nextest_runner::collect(cx).context("failed to collect profile data of tests")?;
+            }
+            res?;
+            harness::check(cx)?;
+            if !cx.args.cov.no_report {
+                generate_report(cx)?;
+            }
+        }
+        Subcommand::NextestArchive => {
+            let cx = &Context::new(args)?;
+            clean::clean_partial(cx)?;
+            create_dirs(cx)?;
+            archive_nextest(cx)?;
+        }
+        Subcommand::Install => {
+            let cx = &Context::new(args)?;
+            clean::clean_partial(cx)?;
+            create_dirs(cx)?;
+            install::run(cx)?;
+        }
+        Subcommand::None | Subcommand::Test => {
+            let cx = &mut Context::new(args)?;
+            clean::clean_partial(cx)?;
+            create_dirs(cx)?;
+            let res = if let Some(runner) = &cx.args.external_runner {
+                external_runner::run(cx, runner)
+            } else if cx.args.runs.is_some() {
+                flaky::run(cx, |cx| {
+                    for name in run_test(cx)? {
+                        if !failed.contains(&name) {
+                            failed.push(name);
+                        }
+                    }
+                    Ok(())
+                })
+            } else {
+                run_test(cx).map(|names| failed = names)
+            };
+            if res.is_err() || cx.args.cov.no_report {
+                // The report is not generated, so write the results of tests
+                // without coverage.
+                junit::write(cx, None).context("failed to write JUnit XML")?;
+            }
+            res?;
+            if cx.args.external_runner.is_none() {
+                harness::check(cx)?;
+            }
+            if !cx.args.cov.no_report {
+                generate_report(cx)?;
+            }
+        }
+    }
+    timings.finish()?;
+    if !failed.is_empty() {
+        bail!(
+            "tests failed in the following workspace members: {}",
+            failed.iter().map(|name| format!("`{name}`")).collect::<Vec<_>>().join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn create_dirs(cx: &Context) -> Result<()> {
+    fs::create_dir_all(&cx.ws.target_dir)?;
+
+    if let Some(output_dir) = &cx.args.cov.output_dir {
+        fs::create_dir_all(output_dir)?;
+        if cx.args.cov.html {
+            fs::create_dir_all(output_dir.join("html"))?;
+        }
+        if cx.args.cov.text {
+            fs::create_dir_all(output_dir.join("text"))?;
+        }
+    }
+
+    if cx.args.doctests {
+        fs::create_dir_all(&cx.ws.doctests_dir)?;
+    }
+    Ok(())
+}
+
+trait EnvTarget {
+    fn set(&mut self, key: &str, value: &str) -> Result<()>;
+    fn unset(&mut self, key: &str) -> Result<()>;
+}
+
+impl EnvTarget for ProcessBuilder {
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        self.env(key, value);
+        Ok(())
+    }
+    fn unset(&mut self, key: &str) -> Result<()> {
+        self.env_remove(key);
+        Ok(())
+    }
+}
+
+struct ShowEnvWriter<W: io::Write> {
+    writer: W,
+    options: ShowEnvOptions,
+}
+
+impl<W: io::Write> EnvTarget for ShowEnvWriter<W> {
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let prefix = if self.options.export_prefix { "export " } else { "" };
+        writeln!(self.writer, "{prefix}{key}={}", shell_escape::escape(value.into()))
+            .context("failed to write env to stdout")
+    }
+    fn unset(&mut self, key: &str) -> Result<()> {
+        if env::var_os(key).is_some() {
+            warn!("cannot unset environment variable `{key}`");
+        }
+        Ok(())
+    }
+}
+
+struct IsNextest(bool);
+
+/// Returns the value of LLVM_PROFILE_FILE that instrumented binaries write profile data to.
+fn llvm_profile_file(cx: &Context, IsNextest(is_nextest): IsNextest) -> String {
+    let mut llvm_profile_file_name = format!("{}-%p", cx.ws.name);
+    if is_nextest {
+        // https://github.com/taiki-e/cargo-llvm-cov/issues/258
+        // https://clang.llvm.org/docs/SourceBasedCodeCoverage.html#running-the-instrumented-program
+        // Select the number of threads that is the same as the one nextest uses by default here.
+        // https://github.com/nextest-rs/nextest/blob/c54694dfe7be016993983b5dedbcf2b50d4b1a6e/nextest-runner/src/config/test_threads.rs
+        // https://github.com/nextest-rs/nextest/blob/c54694dfe7be016993983b5dedbcf2b50d4b1a6e/nextest-runner/src/config/config_impl.rs#L30
+        // TODO: should we respect custom test-threads?
+        // - If the number of threads specified by the user is negative or
+        //   less or equal to available cores, it should not really be a problem
+        //   because it does not exceed the number of available cores.
+        // - Even if the number of threads specified by the user is greater than
+        //   available cores, it is expected that the number of threads that can
+        //   write simultaneously will not exceed the number of available cores.
+        let _ = write!(
+            llvm_profile_file_name,
+            "-%{}m",
+            std::thread::available_parallelism().map_or(1, usize::from)
+        );
+    } else {
+        llvm_profile_file_name.push_str("-%m");
+    }
+    if continuous_mode(cx) {
+        llvm_profile_file_name.push_str("%c");
+    }
+    llvm_profile_file_name.push_str(".profraw");
+    let llvm_profile_file = cx.ws.target_dir.join(llvm_profile_file_name);
+    match windows_path::to_verbatim(llvm_profile_file.as_str()) {
+        // The profiler runtime opens the file with the path as is, so use the
+        // verbatim path, which isn't subject to the MAX_PATH limitation.
+        Some(verbatim) if cx.args.windows_long_paths => verbatim,
+        _ => llvm_profile_file.into_string(),
+    }
+}
+
+/// Returns `true` if the profile data is written in continuous mode, in which
+/// the counters in the profraw file are updated while the program runs, so the
+/// coverage of a process killed by --timeout, or of a test process that aborts
+/// on the expected panic with `-Z panic-abort-tests`, is not lost, and the
+/// coverage of a running process can be reported by --dump-interval.
+///
+/// Continuous mode is supported on Linux (with runtime counter relocation)
+/// and Apple platforms.
+/// <https://clang.llvm.org/docs/SourceBasedCodeCoverage.html#running-the-instrumented-program>
+fn continuous_mode(cx: &Context) -> bool {
+    let triple = cx.ws.target_for_config.triple();
+    (cx.args.timeout.is_some()
+        || cx.args.dump_interval.is_some()
+        || should_panic::panic_abort_tests(cx))
+        && (triple.contains("-linux") || triple.contains("-apple-"))
+}
+
+fn set_env(cx: &Context, env: &mut dyn EnvTarget, IsNextest(is_nextest): IsNextest) -> Result<()> {
+    fn push_common_flags(cx: &Context, flags: &mut Flags) {
+        if cx.ws.stable_coverage {
+            flags.push("-C");
+            flags.push("instrument-coverage");
+        } else {
+            flags.push("-Z");
+            flags.push("instrument-coverage");
+            if cx.ws.target_for_config.triple().contains("-windows") {
+                // `-C codegen-units=1` is needed to work around link error on windows
+                // https://github.com/rust-lang/rust/issues/85461
+                // https://github.com/microsoft/windows-rs/issues/1006#issuecomment-887789950
+                // This has been fixed in https://github.com/rust-lang/rust/pull/91470,
+                // but old nightly compilers still need this.
+                flags.push("-C");
+                flags.push("codegen-units=1");
+            }
+        }
+        // Workaround for https://github.com/rust-lang/rust/issues/91092.
+        // Unnecessary since https://github.com/rust-lang/rust/pull/111469.
+        if cx.ws.rustc_version.nightly && cx.ws.rustc_version.minor <= 71
+            || !cx.ws.rustc_version.nightly && cx.ws.rustc_version.minor < 71
+        {
+            flags.push("-C");
+            flags.push("llvm-args=--instrprof-atomic-counter-update-all");
+        }
+        if !cx.args.cov.no_cfg_coverage {
+            flags.push("--cfg=coverage");
+        }
+        if cx.ws.rustc_version.nightly && !cx.args.cov.no_cfg_coverage_nightly {
+            flags.push("--cfg=coverage_nightly");
+        }
+        if cx.args.branch {
+            flags.push("-Z");
+            flags.push("coverage-options=branch");
+        }
+        if cx.args.keep_coverage_mapping {
+            // The coverage mapping is removed if the binary is stripped on some platforms.
+            flags.push("-C");
+            flags.push("strip=none");
+        }
+        if continuous_mode(cx) && cx.ws.target_for_config.triple().contains("-linux") {
+            flags.push("-C");
+            flags.push("llvm-args=-runtime-counter-relocation");
+        }
+        for cfg in &cx.args.cfgs {
+            flags.push(format!("--cfg={cfg}"));
+            // --check-cfg is stable since Rust 1.80, and cargo passes it to all crates
+            // since then, so declare the cfg as expected to avoid unexpected_cfgs warnings.
+            if cx.ws.rustc_version.minor >= 80 {
+                flags.push(format!("--check-cfg={}", config::check_cfg(cfg)));
+            }
+        }
+    }
+
+    let rustflags = &mut cx.ws.config.rustflags(&cx.ws.target_for_config)?.unwrap_or_default();
+    push_common_flags(cx, rustflags);
+    if cx.args.remap_path_prefix {
+        rustflags.push("--remap-path-prefix");
+        rustflags.push(format!("{}/=", cx.ws.metadata.workspace_root));
+        if cx.args.windows_long_paths {
+            if let Some(root) = windows_path::to_verbatim(cx.ws.metadata.workspace_root.as_str()) {
+                rustflags.push("--remap-path-prefix");
+                rustflags.push(format!("{root}\\="));
+            }
+        }
+    }
+    if cx.args.target.is_none() {
+        // https://github.com/dtolnay/trybuild/pull/121
+        // https://github.com/dtolnay/trybuild/issues/122
+        // https://github.com/dtolnay/trybuild/pull/123
+        rustflags.push("--cfg=trybuild_no_target");
+    }
+
+    // https://doc.rust-lang.org/nightly/rustc/instrument-coverage.html#including-doc-tests
+    let rustdocflags = &mut cx.ws.config.build.rustdocflags.clone();
+    if cx.args.doctests {
+        let rustdocflags = rustdocflags.get_or_insert_with(Flags::default);
+        push_common_flags(cx, rustdocflags);
+        rustdocflags.push("-Z");
+        rustdocflags.push("unstable-options");
+        rustdocflags.push("--persist-doctests");
+        rustdocflags.push(cx.ws.doctests_dir.as_str());
+    }
+
+    match (cx.args.coverage_target_only, &cx.args.target) {
+        (true, Some(coverage_target)) => {
+            env.set(
+                &format!("CARGO_TARGET_{}_RUSTFLAGS", target_u_upper(coverage_target)),
+                &rustflags.encode_space_separated()?,
+            )?;
+            env.unset("RUSTFLAGS")?;
+            env.unset("CARGO_ENCODED_RUSTFLAGS")?;
+        }
+        _ => {
+            // First, try with RUSTFLAGS because `nextest` subcommand sometimes doesn't work well with encoded flags.
+            if let Ok(v) = rustflags.encode_space_separated() {
+                env.set("RUSTFLAGS", &v)?;
+                env.unset("CARGO_ENCODED_RUSTFLAGS")?;
+            } else {
+                env.set("CARGO_ENCODED_RUSTFLAGS", &rustflags.encode()?)?;
+            }
+        }
+    }
+
+    if let Some(rustdocflags) = rustdocflags {
+        // First, try with RUSTDOCFLAGS because `nextest` subcommand sometimes doesn't work well with encoded flags.
+        if let Ok(v) = rustdocflags.encode_space_separated() {
+            env.set("RUSTDOCFLAGS", &v)?;
+            env.unset("CARGO_ENCODED_RUSTDOCFLAGS")?;
+        } else {
+            env.set("CARGO_ENCODED_RUSTDOCFLAGS", &rustdocflags.encode()?)?;
+        }
+    }
+    if cx.args.include_ffi {
+        // https://github.com/rust-lang/cc-rs/blob/1.0.73/src/lib.rs#L2347-L2365
+        // Environment variables that use hyphens are not available in many environments, so we ignore them for now.
+        let target_u = target_u_lower(cx.ws.target_for_config.triple());
+        let cflags_key = &format!("CFLAGS_{target_u}");
+        // Use std::env instead of crate::env to match cc-rs's behavior.
+        // https://github.com/rust-lang/cc-rs/blob/1.0.73/src/lib.rs#L2740
+        let mut cflags = match std::env::var(cflags_key) {
+            Ok(cflags) => cflags,
+            Err(_) => match std::env::var("TARGET_CFLAGS") {
+                Ok(cflags) => cflags,
+                Err(_) => std::env::var("CFLAGS").unwrap_or_default(),
+            },
+        };
+        let cxxflags_key = &format!("CXXFLAGS_{target_u}");
+        let mut cxxflags = match std::env::var(cxxflags_key) {
+            Ok(cxxflags) => cxxflags,
+            Err(_) => match std::env::var("TARGET_CXXFLAGS") {
+                Ok(cxxflags) => cxxflags,
+                Err(_) => std::env::var("CXXFLAGS").unwrap_or_default(),
+            },
+        };
+        let clang_flags = " -fprofile-instr-generate -fcoverage-mapping -fprofile-update=atomic";
+        cflags.push_str(clang_flags);
+        cxxflags.push_str(clang_flags);
+        env.set(cflags_key, &cflags)?;
+        env.set(cxxflags_key, &cxxflags)?;
+    }
+    let wrappers: Vec<_> =
+        [&cx.ws.config.build.rustc_wrapper, &cx.ws.config.build.rustc_workspace_wrapper]
+            .into_iter()
+            .flatten()
+            .filter(|w| !w.as_os_str().is_empty())
+            .map(|w| w.display().to_string())
+            .collect();
+    if !wrappers.is_empty() {
+        let wrappers = wrappers.join(", ");
+        if cx.args.no_rustc_wrapper {
+            // An empty value overrides build.rustc-wrapper and build.rustc-workspace-wrapper
+            // in cargo config as well.
+            env.set("RUSTC_WRAPPER", "")?;
+            env.set("RUSTC_WORKSPACE_WRAPPER", "")?;
+            status!("Disabling", "rustc wrapper ({wrappers}) for coverage build");
+        } else {
+            // The instrumentation flags are passed to rustc as command line arguments,
+            // which caching wrappers such as sccache include in their cache keys, so
+            // coverage builds never reuse artifacts of non-instrumented builds.
+            status!(
+                "Using",
+                "rustc wrapper ({wrappers}) for coverage build (pass --no-rustc-wrapper to disable it)"
+            );
+        }
+    }
+    // This overrides the empty RUSTC_WORKSPACE_WRAPPER set by --no-rustc-wrapper.
+    for (key, value) in rustc_wrapper::env(cx)? {
+        env.set(&key, &value)?;
+    }
+    env.set("LLVM_PROFILE_FILE", &llvm_profile_file(cx, IsNextest(is_nextest)))?;
+    if is_nextest && (cx.args.discard_failed_attempts || cx.args.emit_test_contribution.is_some()) {
+        for (key, value) in nextest_runner::env(cx)? {
+            env.set(&key, &value)?;
+        }
+    }
+    if cx.args.criterion {
+        // Keep the results of criterion separate from the ones of `cargo bench`,
+        // as the baselines are compared with the latest results.
+        env.set("CRITERION_HOME", cx.ws.target_dir.join("criterion").as_str())?;
+    }
+    env.set("CARGO_LLVM_COV", "1")?;
+    if cx.args.subcommand == Subcommand::ShowEnv {
+        env.set("CARGO_LLVM_COV_SHOW_ENV", "1")?;
+    }
+    Ok(())
+}
+
+fn has_z_flag(args: &[String], name: &str) -> bool {
+    let mut iter = args.iter().map(String::as_str);
+    while let Some(mut arg) = iter.next() {
+        if arg == "-Z" {
+            arg = iter.next().unwrap();
+        } else if let Some(a) = arg.strip_prefix("-Z") {
+            arg = a;
+        } else {
+            continue;
+        }
+        if let Some(rest) = arg.strip_prefix(name) {
+            if rest.is_empty() || rest.starts_with('=') {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Runs `cargo test`, and returns the names of the workspace members whose
+/// tests failed if --keep-going is passed.
+fn run_test(cx: &Context) -> Result<Vec<String>> {
+    let _phase = timings::phase("build and run");
+    let mut cargo = cx.cargo();
+
+    set_env(cx, &mut cargo, IsNextest(false))?;
+    let mut executed_tests = 0;
+
+    cargo.arg("test");
+    if cx.ws.need_doctest_in_workspace && !has_z_flag(&cx.args.cargo_args, "doctest-in-workspace") {
+        // https://github.com/rust-lang/cargo/issues/9427
+        cargo.arg("-Z");
+        cargo.arg("doctest-in-workspace");
+    }
+
+    let mut failed = vec![];
+    if cx.args.keep_going {
+        // Run tests of each member separately so that a build or test failure
+        // of one member doesn't prevent tests of the other members from running.
+        let members: Vec<&str> = cx
+            .ws
+            .metadata
+            .workspace_members
+            .iter()
+            .map(|id| cx.ws.metadata.packages[id].name.as_str())
+            .filter(|&name| {
+                !cx.args.exclude.iter().any(|e| e == name)
+                    && !cx.args.exclude_from_test.iter().any(|e| e == name)
+            })
+            .collect();
+        for &member in &members {
+            let mut cargo = cargo.clone();
+            for &other in &members {
+                if other != member {
+                    cargo.arg("--exclude").arg(other);
+                }
+            }
+            cargo::test_or_run_args(cx, &mut cargo);
+            if term::verbose() {
+                status!("Running", "{cargo}");
+            } else {
+                status!("Testing", "{member}");
+            }
+            stdout_to_stderr(cx, &mut cargo);
+            if let Err(e) = run_cargo_test(cx, &mut cargo, &mut executed_tests) {
+                warn!("{e:#}");
+                cx.tests_failed.set(true);
+                failed.push(member.to_owned());
+            }
+        }
+    } else if cx.args.ignore_run_fail || cx.args.report_on_fail {
+        {
+            let mut cargo = cargo.clone();
+            cargo.arg("--no-run");
+            cargo::test_or_run_args(cx, &mut cargo);
+            if term::verbose() {
+                status!("Running", "{cargo}");
+                cargo.stdout_to_stderr().run()?;
+            } else {
+                // Capture output to prevent duplicate warnings from appearing in two runs.
+                cargo.run_with_output()?;
+            }
+        }
+
+        cargo.arg("--no-fail-fast");
+        cargo::test_or_run_args(cx, &mut cargo);
+        if term::verbose() {
+            status!("Running", "{cargo}");
+        }
+        stdout_to_stderr(cx, &mut cargo);
+        if let Err(e) = run_cargo_test(cx, &mut cargo, &mut executed_tests) {
+            run_failed(cx, &e);
+        }
+    } else {
+        cargo::test_or_run_args(cx, &mut cargo);
+        if term::verbose() {
+            status!("Running", "{cargo}");
+        }
+        stdout_to_stderr(cx, &mut cargo);
+        run_cargo_test(cx, &mut cargo, &mut executed_tests)?;
+    }
+    if cx.args.fail_if_no_tests && executed_tests == 0 {
+        bail!(
+            "no tests were executed; this may occur if the test name filter or target \
+             selection flags don't match any tests"
+        );
+    }
+
+    Ok(failed)
+}
+
+/// Runs `cargo test`, counting the number of executed tests if
+/// --fail-if-no-tests is passed, and recording the results of tests if --junit
+/// is passed.
+fn run_cargo_test(
+    cx: &Context,
+    cargo: &mut ProcessBuilder,
+    executed_tests: &mut u64,
+) -> Result<()> {
+    if cx.args.junit.is_some() {
+        // Cargo prints which test binary is running to stderr, so read it
+        // together with the output of libtest.
+        cargo.stderr_to_stdout().stdout_to_stderr();
+        let results = &mut *cx.test_results.borrow_mut();
+        cargo.run_with_stdout_lines(|line| {
+            *executed_tests += count_executed_tests(line);
+            results.line(line);
+        })
+    } else if cx.args.fail_if_no_tests {
+        cargo.run_with_stdout_lines(|line| *executed_tests += count_executed_tests(line))
+    } else {
+        cargo.run().map(drop)
+    }
+}
+
+/// Returns the number of executed (passed or failed) tests if the given line
+/// is the summary of a test binary printed by libtest, otherwise returns 0.
+///
+/// e.g., `test result: ok. 1 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s`
+fn count_executed_tests(line: &str) -> u64 {
+    let Some((_, results)) =
+        line.trim().strip_prefix("test result: ").and_then(|s| s.split_once(". "))
+    else {
+        return 0;
+    };
+    results
+        .split("; ")
+        .filter_map(|r| {
+            let (n, kind) = r.split_once(' ')?;
+            matches!(kind, "passed" | "failed").then(|| n.parse::<u64>().ok()).flatten()
+        })
+        .sum()
+}
+
+fn archive_nextest(cx: &Context) -> Result<()> {
+    let _phase = timings::phase("build and archive");
+    let mut cargo = cx.cargo();
+
+    set_env(cx, &mut cargo, IsNextest(true))?;
+
+    cargo.arg("nextest").arg("archive");
+    let config = nextest_archive::prepare(cx)?;
+    cargo.arg("--tool-config-file").arg(format!("cargo-llvm-cov:{config}"));
+
+    cargo::test_or_run_args(cx, &mut cargo);
+    if term::verbose() {
+        status!("Running", "{cargo}");
+    }
+    stdout_to_stderr(cx, &mut cargo);
+    cargo.run()?;
+
+    Ok(())
+}
+
+fn run_nextest(cx: &Context) -> Result<()> {
+    let _phase = timings::phase("build and run");
+    let mut cargo = cx.cargo();
+
+    set_env(cx, &mut cargo, IsNextest(true))?;
+
+    cargo.arg("nextest").arg("run");
+    if cx.args.fail_if_no_tests && !cx.args.cargo_args.iter().any(|a| a.starts_with("--no-tests")) {
+        cargo.arg("--no-tests=fail");
+    }
+
+    if cx.args.ignore_run_fail || cx.args.report_on_fail {
+        {
+            let mut cargo = cargo.clone();
+            cargo.arg("--no-run");
+            cargo::test_or_run_args(cx, &mut cargo);
+            if term::verbose() {
+                status!("Running", "{cargo}");
+                cargo.stdout_to_stderr().run()?;
+            } else {
+                // Capture output to prevent duplicate warnings from appearing in two runs.
+                cargo.run_with_output()?;
+            }
+        }
+
+        cargo.arg("--no-fail-fast");
+        cargo::test_or_run_args(cx, &mut cargo);
+        if term::verbose() {
+            status!("Running", "{cargo}");
+        }
+        stdout_to_stderr(cx, &mut cargo);
+        if let Err(e) = cargo.run() {
+            run_failed(cx, &e);
+        }
+    } else {
+        cargo::test_or_run_args(cx, &mut cargo);
+        if term::verbose() {
+            status!("Running", "{cargo}");
+        }
+        stdout_to_stderr(cx, &mut cargo);
+        cargo.run()?;
+    }
+    Ok(())
+}
+
+fn run_run(cx: &Context) -> Result<()> {
+    if cx.args.dump_interval.is_some() && !continuous_mode(cx) {
+        // The profile data is not written until the binary exits.
+        bail!("--dump-interval is currently supported only on Linux and Apple platforms");
+    }
+    let _phase = timings::phase("build and run");
+    let mut cargo = cx.cargo();
+
+    set_env(cx, &mut cargo, IsNextest(false))?;
+
+    let arg_sets = match &cx.args.args_file {
+        Some(path) => {
+            let mut arg_sets = vec![];
+            for line in fs::read_to_string(path)?.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let args = split_args(line)
+                    .with_context(|| format!("failed to parse arguments in {path}: {line}"))?;
+                arg_sets.push([&*cx.args.rest, &args].concat());
+            }
+            if arg_sets.is_empty() {
+                bail!("no arguments found in {path}");
+            }
+            arg_sets
+        }
+        None => vec![cx.args.rest.clone()],
+    };
+    // `cargo run` can run at most one executable, so run the specified
+    // binaries one by one. Their profile data is merged into one report.
+    let bins: Vec<_> = if cx.args.bin.len() > 1 {
+        cx.args.bin.iter().map(|bin| Some(bin.as_str())).collect()
+    } else {
+        vec![None]
+    };
+    for bin in bins {
+        for args in &arg_sets {
+            run_bin(cx, &cargo, bin, args)?;
+        }
+    }
+    Ok(())
+}
+
+/// Splits a line of --args-file into arguments, in a shell-like manner
+/// (whitespace-separated, with single quotes, double quotes, and backslash escapes).
+fn split_args(line: &str) -> Result<Vec<String>> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(arg.take()),
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => bail!("unterminated single quote"),
+                    }
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => arg.push(c),
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => bail!("unterminated double quote"),
+                        },
+                        Some(c) => arg.push(c),
+                        None => bail!("unterminated double quote"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => arg.get_or_insert_with(String::new).push(c),
+                None => bail!("trailing backslash"),
+            },
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+fn run_bin(cx: &Context, cargo: &ProcessBuilder, bin: Option<&str>, rest: &[String]) -> Result<()> {
+    let args = |cargo: &mut ProcessBuilder, rest| cargo::run_args(cx, cargo, bin, rest);
+    let mut cargo = cargo.clone();
+    // With --timeout, the binary is built first so that the build time is not
+    // counted, and the report is generated even if the binary times out.
+    // The same applies to --dump-interval, since the binary may have run for a
+    // long time before failing.
+    if cx.args.ignore_run_fail
+        || cx.args.report_on_fail
+        || cx.args.timeout.is_some()
+        || cx.args.dump_interval.is_some()
+    {
+        {
+            let mut cargo = cargo.clone();
+            cargo.arg("build");
+            // The arguments for the binary are not passed to `cargo build`.
+            args(&mut cargo, &[]);
+            if term::verbose() {
+                status!("Running", "{cargo}");
+                cargo.stdout_to_stderr().run()?;
+            } else {
+                // Capture output to prevent duplicate warnings from appearing in two runs.
+                cargo.run_with_output()?;
+            }
+        }
+
+        cargo.arg("run");
+        args(&mut cargo, rest);
+        if term::verbose() {
+            status!("Running", "{cargo}");
+        }
+        stdout_to_stderr(cx, &mut cargo);
+        let res = if cx.args.timeout.is_some() || cx.args.dump_interval.is_some() {
+            cargo.run_with_timer(
+                cx.args.timeout.map(Duration::from_secs),
+                Duration::from_secs(cx.args.kill_after.unwrap_or(10)),
+                cx.args.dump_interval.map(Duration::from_secs),
+                || {
+                    if let Err(e) = dump_report(cx) {
+                        warn!("failed to generate report while running the binary: {e:#}");
+                    }
+                },
+            )
+        } else {
+            cargo.run()
+        };
+        if let Err(e) = res {
+            run_failed(cx, &e);
+        }
+    } else {
+        cargo.arg("run");
+        args(&mut cargo, rest);
+        if term::verbose() {
+            status!("Running", "{cargo}");
+        }
+        stdout_to_stderr(cx, &mut cargo);
+        cargo.run()?;
+    }
+    Ok(())
+}
+
+/// Reports the failure of the run with --ignore-run-fail, --report-on-fail,
+/// --timeout, or --dump-interval, which doesn't prevent the report from being
+/// generated. Unless --ignore-run-fail is passed, cargo-llvm-cov exits with a
+/// status of 1 after generating the report.
+fn run_failed(cx: &Context, e: &anyhow::Error) {
+    cx.tests_failed.set(true);
+    if cx.args.ignore_run_fail {
+        warn!("{e:#}");
+    } else {
+        error!("{e:#}");
+    }
+}
+
+fn stdout_to_stderr(cx: &Context, cargo: &mut ProcessBuilder) {
+    if cx.args.cov.no_report
+        || cx.args.cov.output_dir.is_some()
+        || cx.args.cov.output_path.is_some()
+    {
+        // Do not redirect if unnecessary.
+    } else {
+        // Redirect stdout to stderr as the report is output to stdout by default.
+        cargo.stdout_to_stderr();
+    }
+}
+
+fn generate_report(cx: &mut Context) -> Result<()> {
+    if nextest_archive::is_used(cx) {
+        nextest_archive::load(cx).context("failed to load options from nextest archive")?;
+    }
+    merge_profraw(cx).context("failed to merge profile data")?;
+
+    let object_files = object_files(cx).context("failed to collect object files")?;
+    if cx.args.cov.strict && object_files.is_empty() {
+        bail!(
+            "not found object files in {}; this may occur if no tests or binaries have been \
+             built with instrumentation, or target directory is accidentally cleared",
+            cx.ws.target_dir
+        );
+    }
+    cx.data_quality =
+        Some(data_quality::check(cx, &object_files).context("failed to check profile data")?);
+    let filters = timings::phase("prepare filters");
+    let (ignore_rules, function_filter) = report_filters(cx, &object_files)?;
+    let ignore_filename_regex = ignore_rules.regex();
+    should_panic::check(cx, &object_files, ignore_filename_regex.as_deref())
+        .context("failed to check coverage of #[should_panic] tests")?;
+    drop(filters);
+    let _phase = timings::phase("report");
+    status!("Exporting", "coverage data of {} object files", object_files.len());
+    let format = Format::from_args(cx);
+    let report_json = ReportJson {
+        object_files: &object_files,
+        ignore_filename_regex: ignore_filename_regex.as_deref(),
+        function_filter: function_filter.as_ref(),
+        summary_only: !needs_json_details(cx, format),
+        json: OnceCell::new(),
+    };
+    if cx.args.cov.strict {
+        let json = report_json.get(cx)?;
+        if json.file_names().is_empty() {
+            bail!(
+                "no source files to be included in the report; this may occur if all files are \
+                 excluded by --ignore-filename-regex, --exclude, or --exclude-from-report, or \
+                 object files don't contain coverage information"
+            );
+        }
+    }
+    if matches!(cx.args.subcommand, Subcommand::Show | Subcommand::Annotate | Subcommand::Snapshot)
+    {
+        let json = report_json.get(cx)?;
+        match cx.args.subcommand {
+            Subcommand::Show => show::print(cx, json)?,
+            Subcommand::Annotate => annotate::write(cx, json)?,
+            _ => snapshot::save(cx, json).context("failed to save snapshot")?,
+        }
+    } else {
+        if cx.args.cov.summary_by_target_kind {
+            cx.target_kinds = per_binary::target_kinds(
+                cx,
+                &object_files,
+                ignore_filename_regex.as_deref(),
+                function_filter.as_ref(),
+            )
+            .context("failed to get coverage of each kind of target")?;
+        }
+        if cx.args.cov.summary_by_owner {
+            cx.owners = codeowners::summary(cx, report_json.get(cx)?)
+                .context("failed to get coverage of each code owner")?;
+        }
+        if cx.args.cov.summary_by_instantiation {
+            cx.instantiations = Some(report_json.get(cx)?.instantiation_totals());
+        }
+        if !cx.runs.is_empty() {
+            flaky::report(
+                cx,
+                &object_files,
+                ignore_filename_regex.as_deref(),
+                function_filter.as_ref(),
+            )
+            .context("failed to compare coverage across runs")?;
+        }
+        test_contribution::write(
+            cx,
+            &object_files,
+            ignore_filename_regex.as_deref(),
+            function_filter.as_ref(),
+        )
+        .context("failed to write test contribution")?;
+        // --summary-format, --list-uncovered, and --since-snapshot replace the default
+        // summary table.
+        if format != Format::None
+            || cx.args.cov.summary_format.is_none()
+                && cx.args.cov.list_uncovered.is_none()
+                && cx.args.cov.since_snapshot.is_none()
+        {
+            if !cx.args.cov.external_reports.is_empty()
+                && matches!(format, Format::Html | Format::Cobertura)
+            {
+                external_report::generate(cx, format, report_json.get(cx)?)
+                    .context("failed to generate report merged with --external-report")?;
+            } else if format.generated_from_json(cx, function_filter.as_ref()) {
+                format
+                    .write_from_json(cx, report_json.get(cx)?)
+                    .context("failed to generate report")?;
+            } else {
+                format
+                    .generate_report(
+                        cx,
+                        &object_files,
+                        ignore_filename_regex.as_deref(),
+                        function_filter.as_ref(),
+                    )
+                    .context("failed to generate report")?;
+            }
+        }
+        if let Some(summary_format) = cx.args.cov.summary_format {
+            let out = summary_report(cx, report_json.get(cx)?, summary_format)?;
+            match &cx.args.cov.output_path {
+                // Without other report formats, the summary is the report.
+                Some(output_path) if format == Format::None => {
+                    fs::write(output_path, out)?;
+                    eprintln!();
+                    status!("Finished", "report saved to {output_path}");
+                }
+                _ => println!("{out}"),
+            }
+        }
+        if format == Format::None && !cx.target_kinds.is_empty() {
+            let rows = cx.target_kinds.iter().map(|k| (&*k.kind, &k.totals));
+            println!("\n{}", totals_table("Target Kind", rows));
+        }
+        if format == Format::None && !cx.owners.is_empty() {
+            let rows = cx.owners.iter().map(|o| (&*o.owner, &o.totals));
+            println!("\n{}", totals_table("Owner", rows));
+        }
+        if let (Format::None, Some(instantiations)) = (format, &cx.instantiations) {
+            println!("\n{}", instantiation_table(instantiations));
+        }
+        if let (Format::None, Some(table)) =
+            (format, cx.data_quality.as_ref().and_then(data_quality::table))
+        {
+            println!("\n{table}");
+        }
+        if !cx.args.cov.output_types.is_empty() {
+            grcov::generate_reports(
+                cx,
+                &object_files,
+                ignore_filename_regex.as_deref(),
+                function_filter.as_ref(),
+            )
+            .context("failed to generate reports for --output-types")?;
+        }
+        if cx.args.cov.all_reports {
+            all_reports::generate(
+                cx,
+                &object_files,
+                ignore_filename_regex.as_deref(),
+                function_filter.as_ref(),
+            )
+            .context("failed to generate reports for --all-reports")?;
+        }
+        if cx.args.cov.report_per_binary {
+            per_binary::generate_reports(
+                cx,
+                format,
+                &object_files,
+                ignore_filename_regex.as_deref(),
+                function_filter.as_ref(),
+            )
+            .context("failed to generate per-binary reports")?;
+        }
+        if cx.args.cov.summary_line {
+            let summary =
+                SummaryJsonExport::from_llvm_cov_json_export(report_json.get(cx)?, |_| None)?;
+            println!("{}", summary_line(&summary.totals));
+        }
+        if let Some(path) = &cx.args.cov.uncovered_spans_json {
+            let spans = UncoveredSpansExport::from_llvm_cov_json_export(
+                report_json.get(cx)?,
+                ignore_filename_regex.as_deref(),
+            );
+            fs::write(path, serde_json::to_vec_pretty(&spans)?)?;
+        }
+        if cx.args.cov.list_uncovered.is_some() {
+            list_uncovered::print(cx, report_json.get(cx)?, ignore_filename_regex.as_deref())
+                .context("failed to list uncovered regions")?;
+        }
+        if cx.args.cov.since_snapshot.is_some() {
+            snapshot::print_since(cx, report_json.get(cx)?)
+                .context("failed to compare with snapshot")?;
+        }
+        if let Some(path) = &cx.args.cov.coverage_off_json {
+            let export = coverage_off::collect(cx, ignore_filename_regex.as_deref())
+                .context("failed to collect items excluded by #[coverage(off)]")?;
+            fs::write(path, serde_json::to_vec_pretty(&export)?)?;
+        }
+        if cx.args.cov.show_excluded {
+            excluded::print(cx, &object_files, &ignore_rules, function_filter.as_ref())
+                .context("failed to collect excluded code")?;
+        }
+        if cx.args.junit.is_some() {
+            let coverage = match per_binary::binary_totals(
+                cx,
+                &object_files,
+                ignore_filename_regex.as_deref(),
+                function_filter.as_ref(),
+            ) {
+                Ok(coverage) => Some(coverage),
+                Err(e) => {
+                    warn!("coverage of each test binary is not included in --junit output: {e:#}");
+                    None
+                }
+            };
+            junit::write(cx, coverage.as_ref()).context("failed to write JUnit XML")?;
+        }
+    }
+
+    if cx.args.cov.fail_under_functions.is_some()
+        || cx.args.cov.fail_under_lines.is_some()
+        || cx.args.cov.fail_under_regions.is_some()
+        || cx.args.cov.fail_uncovered_functions.is_some()
+        || cx.args.cov.fail_uncovered_lines.is_some()
+        || cx.args.cov.fail_uncovered_regions.is_some()
+        || cx.args.cov.fail_under_diff_lines.is_some()
+        || cx.args.cov.show_missing_lines
+        || cx.package_configs.iter().any(|(_, config)| config.has_thresholds())
+        || !cx.args.path_thresholds.is_empty()
+        || !cx.args.owner_thresholds.is_empty()
+        || !cx.args.cov.require_full.is_empty()
+        || cx.args.cov.gate.is_some()
+    {
+        let json = report_json.get(cx)?;
+
+        if let Some(fail_under_functions) = cx.args.cov.fail_under_functions {
+            // Handle --fail-under-functions.
+            let functions_percent = json
+                .get_coverage_percent(CoverageKind::Functions)
+                .context("failed to get function coverage")?;
+            if functions_percent < fail_under_functions {
+                term::error::set(true);
+            }
+        }
+
+        if let Some(fail_under_lines) = cx.args.cov.fail_under_lines {
+            // Handle --fail-under-lines.
+            let lines_percent = json
+                .get_coverage_percent(CoverageKind::Lines)
+                .context("failed to get line coverage")?;
+            if lines_percent < fail_under_lines {
+                term::error::set(true);
+            }
+        }
+
+        if let Some(fail_under_regions) = cx.args.cov.fail_under_regions {
+            // Handle --fail-under-regions.
+            let regions_percent = json
+                .get_coverage_percent(CoverageKind::Regions)
+                .context("failed to get region coverage")?;
+            if regions_percent < fail_under_regions {
+                term::error::set(true);
+            }
+        }
+
+        if let Some(fail_uncovered_functions) = cx.args.cov.fail_uncovered_functions {
+            // Handle --fail-uncovered-functions.
+            let uncovered =
+                json.count_uncovered_functions().context("failed to count uncovered functions")?;
+            if uncovered > fail_uncovered_functions {
+                term::error::set(true);
+            }
+        }
+        if let Some(fail_uncovered_lines) = cx.args.cov.fail_uncovered_lines {
+            // Handle --fail-uncovered-lines.
+            let uncovered_files = json.get_uncovered_lines(ignore_filename_regex.as_deref());
+            let uncovered = uncovered_files
+                .iter()
+                .fold(0_u64, |uncovered, (_, lines)| uncovered + lines.len() as u64);
+
+            if uncovered > fail_uncovered_lines {
+                term::error::set(true);
+            }
+        }
+        if let Some(fail_uncovered_regions) = cx.args.cov.fail_uncovered_regions {
+            // Handle --fail-uncovered-regions.
+            let uncovered =
+                json.count_uncovered_regions().context("failed to count uncovered regions")?;
+            if uncovered > fail_uncovered_regions {
+                term::error::set(true);
+            }
+        }
+
+        if let Some(fail_under_diff_lines) = cx.args.cov.fail_under_diff_lines {
+            // Handle --fail-under-diff-lines.
+            diff_lines::check(cx, json, fail_under_diff_lines)
+                .context("failed to check coverage of changed lines")?;
+        }
+
+        // Handle fail-under-* in package.metadata.llvm-cov.
+        check_package_thresholds(cx, json);
+        // Handle path-thresholds in llvm-cov.toml.
+        check_path_thresholds(cx, json);
+        // Handle owner-thresholds in llvm-cov.toml.
+        codeowners::check_thresholds(cx, json)
+            .context("failed to check coverage of each code owner")?;
+        // Handle --require-full.
+        check_require_full(cx, json);
+        if let Some(gate) = &cx.args.cov.gate {
+            // Handle --gate.
+            let totals = SummaryJsonExport::from_llvm_cov_json_export(json, |_| None)?.totals;
+            let baseline = match &cx.args.cov.gate_baseline {
+                Some(path) => Some(
+                    trend::load_summary(path)
+                        .with_context(|| format!("failed to load --gate-baseline {path}"))?
+                        .totals,
+                ),
+                None => None,
+            };
+            gate.check(&totals, baseline.as_ref());
+        }
+
+        if cx.args.cov.show_missing_lines {
+            // Handle --show-missing-lines.
+            let uncovered_files = json.get_uncovered_lines(ignore_filename_regex.as_deref());
+            if !uncovered_files.is_empty() {
+                let stdout = io::stdout();
+                let mut stdout = stdout.lock();
+                writeln!(stdout, "Uncovered Lines:")?;
+                for (file, lines) in &uncovered_files {
+                    let lines: Vec<_> = lines.iter().map(ToString::to_string).collect();
+                    writeln!(stdout, "{file}: {}", lines.join(", "))?;
+                }
+                stdout.flush()?;
+            }
+        }
+    }
+
+    if let Some(url) = webhook::url(cx)? {
+        // Handle --webhook-url.
+        webhook::notify(cx, &url, report_json.get(cx)?).context("failed to notify webhook")?;
+    }
+
+    if cx.args.cov.open {
+        let html_dir = &cx.args.cov.output_dir.as_ref().unwrap().join("html");
+        let path = &match &cx.args.cov.open_entry {
+            Some(entry) => html_page(cx, html_dir, entry)?,
+            None => html_dir.join("index.html"),
+        };
+        status!("Opening", "{path}");
+        open_report(&cx.ws, cx.args.cov.browser.as_deref(), path)?;
+    }
+    Ok(())
+}
+
+/// The JSON export of the coverage data used by the reports and checks of
+/// generate_report, which is exported and parsed at most once and shared by them.
+struct ReportJson<'a> {
+    object_files: &'a [OsString],
+    ignore_filename_regex: Option<&'a str>,
+    function_filter: Option<&'a FunctionFilter>,
+    /// Whether none of the consumers need details of files and functions.
+    summary_only: bool,
+    json: OnceCell<LlvmCovJsonExport>,
+}
+
+impl ReportJson<'_> {
+    fn get(&self, cx: &Context) -> Result<&LlvmCovJsonExport> {
+        if let Some(json) = self.json.get() {
+            return Ok(json);
+        }
+        let json = Format::Json
+            .get_json(
+                cx,
+                self.object_files,
+                self.ignore_filename_regex,
+                self.function_filter,
+                self.summary_only,
+            )
+            .context("failed to get json")?;
+        Ok(self.json.get_or_init(|| json))
+    }
+}
+
+/// Returns whether any of the reports and checks of generate_report need details
+/// of files and functions in the JSON export, not only the summary.
+fn needs_json_details(cx: &Context, format: Format) -> bool {
+    matches!(cx.args.subcommand, Subcommand::Show | Subcommand::Annotate | Subcommand::Snapshot)
+        || cx.args.cov.summary_by_instantiation
+        || cx.args.cov.uncovered_spans_json.is_some()
+        || cx.args.cov.list_uncovered.is_some()
+        || cx.args.cov.since_snapshot.is_some()
+        || !cx.args.cov.external_reports.is_empty()
+            && matches!(format, Format::Html | Format::Cobertura)
+        || cx.args.cov.show_missing_lines
+        || cx.args.cov.fail_uncovered_lines.is_some()
+        || cx.args.cov.fail_under_diff_lines.is_some()
+        || !cx.args.cov.require_full.is_empty()
+}
+
+/// Returns the time of report creation for --cobertura, in seconds since the
+/// Unix epoch.
+fn timestamp(cx: &Context) -> Result<u64> {
+    if cx.args.cov.no_timestamps {
+        Ok(0)
+    } else if let Some(source_date_epoch) = cx.source_date_epoch {
+        Ok(source_date_epoch)
+    } else {
+        Ok(SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .context("SystemTime before UNIX EPOCH!")?
+            .as_secs())
+    }
+}
+
+/// Returns the demangler configured by --demangle-keep-hashes and --demangle-cpp.
+fn demangler(cx: &Context) -> Demangler {
+    Demangler::new(cx.args.cov.demangle_keep_hashes, cx.args.cov.demangle_cpp)
+}
+
+/// Returns the summary JSON of --summary-json.
+fn summary_json(cx: &Context, json: &LlvmCovJsonExport) -> Result<SummaryJsonExport> {
+    let package_of = package_of_file(cx);
+    let mut summary = SummaryJsonExport::from_llvm_cov_json_export(json, |filename| {
+        package_of(filename).map(|id| cx.ws.metadata.packages[id].name.clone())
+    })?;
+    summary.args.clone_from(&cx.args.raw_args);
+    summary.commit = git_commit(&cx.ws.metadata.workspace_root);
+    summary.target_kinds.clone_from(&cx.target_kinds);
+    summary.owners.clone_from(&cx.owners);
+    summary.instantiations.clone_from(&cx.instantiations);
+    summary.data_quality.clone_from(&cx.data_quality);
+    summary.tests_failed = cx.tests_failed.get();
+    Ok(summary)
+}
+
+/// Returns the summary printed by --summary-format.
+fn summary_report(
+    cx: &Context,
+    json: &LlvmCovJsonExport,
+    summary_format: SummaryFormat,
+) -> Result<String> {
+    match summary_format {
+        SummaryFormat::Table => json.summary_table(),
+        SummaryFormat::Json => Ok(serde_json::to_string(&summary_json(cx, json)?)?),
+        SummaryFormat::Markdown => json.summary_markdown(),
+        SummaryFormat::Csv => json.summary_csv(),
+    }
+}
+
+/// Returns the single line summary printed by --summary-line.
+fn summary_line(totals: &SummaryTotals) -> String {
+    let percent = |counts: &SummaryCounts| {
+        if counts.count == 0 {
+            return "-".to_owned();
+        }
+        let percent = summary_line_percent(counts.percent);
+        match percent::counts() {
+            None => format!("{percent}%"),
+            Some(Counts::Both) => format!("{percent}%({}/{})", counts.covered, counts.count),
+            Some(Counts::Only) => format!("{}/{}", counts.covered, counts.count),
+        }
+    };
+    format!(
+        "lines={} functions={} regions={} branches={}",
+        percent(&totals.lines),
+        percent(&totals.functions),
+        percent(&totals.regions),
+        percent(&totals.branches)
+    )
+}
+
+/// Formats the percentage with one decimal place, unless --precision or
+/// --rounding is specified.
+fn summary_line_percent(p: f64) -> String {
+    if percent::enabled() {
+        percent::format(p)
+    } else {
+        format!("{p:.1}")
+    }
+}
+
+/// Renders the coverage of each group of files or targets in the same layout as
+/// the summary table (--summary-by-target-kind and --summary-by-owner).
+fn totals_table<'a>(
+    header: &str,
+    rows: impl Iterator<Item = (&'a str, &'a SummaryTotals)> + Clone,
+) -> String {
+    const COLUMNS: [[(&str, usize); 3]; 4] = [
+        [("Regions", 12), ("Missed Regions", 18), ("Cover", 10)],
+        [("Functions", 12), ("Missed Functions", 18), ("Executed", 10)],
+        [("Lines", 12), ("Missed Lines", 18), ("Cover", 10)],
+        [("Branches", 12), ("Missed Branches", 18), ("Cover", 10)],
+    ];
+    fn counts(totals: &SummaryTotals) -> [&SummaryCounts; 4] {
+        [&totals.regions, &totals.functions, &totals.lines, &totals.branches]
+    }
+    fn cover(counts: &SummaryCounts) -> String {
+        if counts.count == 0 {
+            "-".to_owned()
+        } else {
+            percent::format_summary(counts.percent, counts.covered, counts.count)
+        }
+    }
+    // Names longer than the default width (e.g., `@org/team` of CODEOWNERS)
+    // widen the first column, and --show-counts widens the cover columns.
+    let width = rows.clone().map(|(name, _)| name.len() + 1).max().unwrap_or(0).max(25);
+    let mut widths = COLUMNS.map(|[.., (_, w)]| w);
+    for (_, totals) in rows.clone() {
+        for (counts, w) in counts(totals).into_iter().zip(&mut widths) {
+            *w = (*w).max(cover(counts).len() + 2);
+        }
+    }
+
+    let mut out = format!("{header:<width$}");
+    for ([(h1, w1), (h2, w2), (h3, _)], w3) in COLUMNS.iter().zip(&widths) {
+        let _ = write!(out, "{h1:>w1$}{h2:>w2$}{h3:>w3$}");
+    }
+    let total_width = width
+        + COLUMNS.iter().flat_map(|[(_, w1), (_, w2), _]| [w1, w2]).sum::<usize>()
+        + widths.iter().sum::<usize>();
+    let _ = writeln!(out, "\n{}", "-".repeat(total_width));
+    for (name, totals) in rows {
+        let _ = write!(out, "{name:<width$}");
+        for (counts, ([(_, w1), (_, w2), _], w3)) in
+            counts(totals).into_iter().zip(COLUMNS.iter().zip(&widths))
+        {
+            let missed = counts.count.saturating_sub(counts.covered);
+            let _ = write!(out, "{:>w1$}{missed:>w2$}{:>w3$}", counts.count, cover(counts));
+        }
+        out.push('\n');
+    }
+    out.pop();
+    out
+}
+
+/// Renders the table printed by --summary-by-instantiation.
+fn instantiation_table(totals: &InstantiationTotals) -> String {
+    const COLUMNS: [(&str, usize); 5] =
+        [("Count", 12), ("Covered (Any)", 16), ("Cover", 10), ("Covered (All)", 16), ("Cover", 10)];
+    const WIDTH: usize = 25;
+
+    let mut out = format!("{:<WIDTH$}", "Instantiations");
+    for (header, w) in COLUMNS {
+        let _ = write!(out, "{header:>w$}");
+    }
+    let total_width = WIDTH + COLUMNS.iter().map(|(_, w)| w).sum::<usize>();
+    let _ = writeln!(out, "\n{}", "-".repeat(total_width));
+    let rows: [(&str, &InstantiationCounts); 3] =
+        [("Functions", &totals.functions), ("Lines", &totals.lines), ("Regions", &totals.regions)];
+    for (name, counts) in rows {
+        let percent = |percent: f64| {
+            if counts.count == 0 {
+                "-".to_owned()
+            } else {
+                format!("{}%", percent::format(percent))
+            }
+        };
+        let [(_, w1), (_, w2), (_, w3), (_, w4), (_, w5)] = COLUMNS;
+        let _ = writeln!(
+            out,
+            "{name:<WIDTH$}{:>w1$}{:>w2$}{:>w3$}{:>w4$}{:>w5$}",
+            counts.count,
+            counts.covered_any,
+            percent(counts.percent_any),
+            counts.covered_all,
+            percent(counts.percent_all)
+        );
+    }
+    let _ = write!(
+        out,
+        "{}\n{} of {} functions are generic ({} instantiations in total)",
+        "-".repeat(total_width),
+        totals.generic_functions,
+        totals.functions.count,
+        totals.instantiations
+    );
+    out
+}
+
+/// Checks fail-under-* thresholds in `package.metadata.llvm-cov` table against
+/// the coverage of source files in each package.
+fn check_package_thresholds(cx: &Context, json: &LlvmCovJsonExport) {
+    let metadata = &cx.ws.metadata;
+    let package_of = package_of_file(cx);
+    for (id, config) in &cx.package_configs {
+        if !config.has_thresholds() {
+            continue;
+        }
+        let in_package = |filename: &str| package_of(filename) == Some(id);
+        let package = &metadata.packages[id];
+        for (kind, name, min) in [
+            (CoverageKind::Functions, "function", config.fail_under_functions),
+            (CoverageKind::Lines, "line", config.fail_under_lines),
+            (CoverageKind::Regions, "region", config.fail_under_regions),
+        ] {
+            let Some(min) = min else { continue };
+            let percent = json.get_coverage_percent_of_files(kind, in_package);
+            if percent < min {
+                error!(
+                    "{name} coverage of package `{}` ({}%) is less than {min}% specified in {}",
+                    package.name,
+                    percent::format(percent),
+                    package.manifest_path
+                );
+            }
+        }
+    }
+}
+
+/// Checks `path-thresholds` in the configuration file against the line
+/// coverage of the source files matching each pattern.
+fn check_path_thresholds(cx: &Context, json: &LlvmCovJsonExport) {
+    let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+    for (pattern, min) in &cx.args.path_thresholds {
+        // Patterns are validated when loading the configuration.
+        let Ok(glob) = glob::Pattern::new(pattern) else { continue };
+        let matches = |filename: &str| {
+            let path = cx.relative_source_path(filename).unwrap_or_else(|| filename.into());
+            glob.matches_path_with(path.as_std_path(), options)
+        };
+        if !json.file_names().iter().any(|f| matches(f)) {
+            warn!("no source files in the report match `{pattern}` in `path-thresholds`");
+            continue;
+        }
+        let percent = json.get_coverage_percent_of_files(CoverageKind::Lines, matches);
+        if percent < *min {
+            error!(
+                "line coverage of files matching `{pattern}` ({}%) is less than {min}% specified \
+                 in `path-thresholds`",
+                percent::format(percent)
+            );
+        }
+    }
+}
+
+/// Checks that the source files matching the patterns of --require-full have
+/// no uncovered lines (and no partially covered branches if --branch is used),
+/// and prints the missing ones.
+fn check_require_full(cx: &Context, json: &LlvmCovJsonExport) {
+    let options = glob::MatchOptions { require_literal_separator: true, ..Default::default() };
+    for pattern in &cx.args.cov.require_full {
+        // Patterns are validated when parsing the arguments.
+        let Ok(glob) = glob::Pattern::new(pattern) else { continue };
+        let mut matched = false;
+        for file in json.file_names() {
+            let path = cx.relative_source_path(file).unwrap_or_else(|| file.into());
+            if !glob.matches_path_with(path.as_std_path(), options) {
+                continue;
+            }
+            matched = true;
+            let uncovered: Vec<_> = json
+                .get_line_coverage(file)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|l| l.count == 0)
+                .map(|l| l.line.to_string())
+                .collect();
+            let branches: Vec<_> = if cx.args.branch {
+                let lines = json.get_partial_branch_lines(file).unwrap_or_default();
+                lines.iter().map(ToString::to_string).collect()
+            } else {
+                vec![]
+            };
+            if uncovered.is_empty() && branches.is_empty() {
+                continue;
+            }
+            error!("{path} is required to be fully covered by --require-full `{pattern}`");
+            if !uncovered.is_empty() {
+                eprintln!("    uncovered lines: {}", uncovered.join(", "));
+            }
+            if !branches.is_empty() {
+                eprintln!("    partially covered branches: {}", branches.join(", "));
+            }
+        }
+        if !matched {
+            warn!("no source files in the report match --require-full `{pattern}`");
+        }
+    }
+}
+
+/// Returns a function that returns the workspace member that contains the
+/// given source file.
+fn package_of_file<'a>(cx: &'a Context) -> impl Fn(&str) -> Option<&'a PackageId> + 'a {
+    let metadata = &cx.ws.metadata;
+    let package_dirs: Vec<_> = metadata
+        .workspace_members
+        .iter()
+        .map(|id| (id, metadata.packages[id].manifest_path.parent().unwrap()))
+        .collect();
+    move |filename| {
+        let path = cx.source_path(filename);
+        // Source files in nested packages belong to the innermost one.
+        package_dirs
+            .iter()
+            .filter(|(_, dir)| path.starts_with(dir))
+            .max_by_key(|(_, dir)| dir.as_str().len())
+            .map(|&(id, _)| id)
+    }
+}
+
+/// Returns the commit checked out in the given directory, or `None` if it is
+/// not in a git repository.
+fn git_commit(dir: &Utf8Path) -> Option<SummaryCommit> {
+    let git = |args: &[&str]| cmd!("git").args(args).dir(dir).read().ok();
+    let sha = git(&["rev-parse", "--verify", "-q", "HEAD"])?;
+    let branch = git(&["symbolic-ref", "-q", "--short", "HEAD"]);
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+        .is_some_and(|status| !status.trim().is_empty());
+    Some(SummaryCommit { sha, branch, dirty })
+}
+
+/// Removes the creation time from html report for --no-timestamps, or replaces
+/// it with the one specified by `SOURCE_DATE_EPOCH`, removes the version of
+/// llvm-cov from the footer for --reproducible-html, and recomputes the
+/// percentages for --precision and --rounding.
+fn fix_html(cx: &Context, dir: &Utf8Path) -> Result<()> {
+    let timestamp = if let Some(source_date_epoch) =
+        cx.source_date_epoch.filter(|_| !cx.args.cov.no_timestamps)
+    {
+        Some(format!("<h4>Created: {}</h4>", format_utc(source_date_epoch)))
+    } else if cx.args.cov.no_timestamps || cx.args.cov.reproducible_html {
+        Some(String::new())
+    } else {
+        None
+    };
+    // llvm-cov always embeds the current local time and its version, and has
+    // no option to change them.
+    let mut replacements = vec![];
+    if let Some(timestamp) = timestamp {
+        replacements.push((Regex::new("<h4>Created: [^<]*</h4>").unwrap(), timestamp));
+    }
+    if cx.args.cov.reproducible_html {
+        replacements
+            .push((Regex::new("<h5>Generated by llvm-cov[^<]*</h5>").unwrap(), String::new()));
+    }
+    if cx.tests_failed.get() {
+        replacements.push((
+            Regex::new("<body>").unwrap(),
+            "<body><p style='background-color: #fcc; border: 1px solid #c00; padding: 8px'>\
+             <b>Tests failed:</b> this report was generated from a run in which tests failed, \
+             so the coverage may be lower than usual.</p>"
+                .to_owned(),
+        ));
+    }
+    if replacements.is_empty() && !percent::enabled() {
+        return Ok(());
+    }
+    for entry in WalkDir::new(dir).into_iter().filter_map(Result::ok) {
+        let path = entry.path();
+        if path.extension().is_none_or(|e| e != "html") {
+            continue;
+        }
+        let mut html = fs::read_to_string(path)?;
+        let mut changed = false;
+        for (re, replacement) in &replacements {
+            if let Cow::Owned(new) = re.replace_all(&html, replacement.as_str()) {
+                html = new;
+                changed = true;
+            }
+        }
+        if percent::enabled() {
+            if let Some(new) = percent::rewrite_html(&html) {
+                html = new;
+                changed = true;
+            }
+        }
+        if changed {
+            fs::write(path, html)?;
+        }
+    }
+    Ok(())
+}
+
+/// Formats UNIX timestamp in the same format as llvm-cov (`%Y-%m-%d %H:%M`) in UTC.
+fn format_utc(timestamp: u64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let (days, secs) = (timestamp / 86400, timestamp % 86400);
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02} {:02}:{:02}", secs / 3600, secs % 3600 / 60)
+}
+
+/// Sorts records of LCOV data by the source file name (`SF:`).
+///
+/// Records are sorted by llvm-cov, but remapping paths may change the order.
+fn sort_lcov_records(lcov: &str) -> String {
+    let mut records = vec![];
+    let mut start = 0;
+    for (i, _) in lcov.match_indices("end_of_record") {
+        let end = lcov[i..].find('\n').map_or(lcov.len(), |n| i + n + 1);
+        records.push(&lcov[start..end]);
+        start = end;
+    }
+    let rest = &lcov[start..];
+    let file_name = |record: &str| {
+        record.lines().find_map(|line| line.strip_prefix("SF:")).unwrap_or_default().to_owned()
+    };
+    records.sort_by_cached_key(|record| file_name(record));
+    let mut out = String::with_capacity(lcov.len());
+    for record in records {
+        out.push_str(record);
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    out.push_str(rest);
+    if !lcov.ends_with('\n') && out.ends_with('\n') {
+        out.pop();
+    }
+    out
+}
+
+/// Writes JSON to the file without serializing the whole value into memory.
+fn write_json(path: &Utf8Path, value: &impl serde::Serialize) -> Result<()> {
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    serde_json::to_writer(&mut writer, value)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Returns the page in the HTML report specified by `--open=<ENTRY>`.
+fn html_page(cx: &Context, html_dir: &Utf8Path, entry: &str) -> Result<Utf8PathBuf> {
+    let metadata = &cx.ws.metadata;
+    let src_path = metadata
+        .workspace_members
+        .iter()
+        .map(|id| &metadata.packages[id])
+        .find(|p| p.name == entry)
+        .and_then(|p| {
+            // The library target, or the first binary target.
+            p.targets
+                .iter()
+                .find(|t| t.kind.iter().any(|k| k.contains("lib") || k == "proc-macro"))
+                .or_else(|| p.targets.iter().find(|t| t.kind.iter().any(|k| k == "bin")))
+        })
+        .map(|t| t.src_path.clone());
+    let mut candidates = vec![];
+    let src_path = match src_path {
+        Some(src_path) => src_path,
+        None => {
+            candidates.push(html_dir.join(entry));
+            env::current_dir()?.join(entry).try_into()?
+        }
+    };
+    // Pages of source files are at `coverage/<path>.html`, where <path> is the
+    // path in the coverage data (relative to the workspace root with --remap-path-prefix).
+    let mut page = |path: &Utf8Path| {
+        let path = path.as_str().trim_start_matches(['/', '\\']).replace(':', "");
+        candidates.insert(0, html_dir.join("coverage").join(format!("{path}.html")));
+    };
+    page(&src_path);
+    if let Ok(path) = src_path.strip_prefix(&metadata.workspace_root) {
+        page(path);
+    }
+    candidates
+        .into_iter()
+        .find(|path| path.is_file())
+        .with_context(|| format!("no page for `{entry}` found in the HTML report in {html_dir}"))
+}
+
+fn open_report(ws: &Workspace, browser: Option<&str>, path: &Utf8Path) -> Result<()> {
+    // --browser takes precedence over doc.browser config and BROWSER environment
+    // variable (cargo-config2 uses BROWSER if doc.browser is not set).
+    let browser = match browser {
+        Some(browser) => {
+            let mut iter = browser.split_whitespace();
+            let Some(program) = iter.next() else { bail!("--browser requires a command") };
+            Some((program.into(), iter.map(Into::into).collect()))
+        }
+        None => ws.config.doc.browser.as_ref().map(|b| (b.path.clone(), b.args.clone())),
+    };
+    match browser {
+        Some((program, args)) => {
+            // `%s` in the arguments is replaced with the path to the page, as
+            // in BROWSER environment variable supported by many tools.
+            let mut cmd = cmd!(&program);
+            let mut has_placeholder = false;
+            for arg in &args {
+                match arg.to_str() {
+                    Some(arg) if arg.contains("%s") => {
+                        has_placeholder = true;
+                        cmd.arg(arg.replace("%s", path.as_str()));
+                    }
+                    _ => {
+                        cmd.arg(arg);
+                    }
+                }
+            }
+            if !has_placeholder {
+                cmd.arg(path);
+            }
+            cmd.run().with_context(|| {
+                format!("couldn't open report with {}", Path::new(&program).display())
+            })?;
+        }
+        None => opener::open(path).context("couldn't open report")?,
+    }
+    Ok(())
+}
+
+/// Returns the profraw files in the target directory.
+fn profraw_files(cx: &Context) -> Result<Vec<PathBuf>> {
+    let mut profraw_files = glob::glob(
+        Utf8Path::new(&glob::Pattern::escape(cx.ws.target_dir.as_str()))
+            .join(format!("{}-*.profraw", cx.ws.name))
+            .as_str(),
+    )?
+    .filter_map(Result::ok)
+    .collect::<Vec<_>>();
+    // glob yields paths in alphabetical order, but sort explicitly so that the
+    // order of inputs to llvm-profdata doesn't depend on it.
+    profraw_files.sort_unstable();
+    Ok(profraw_files)
+}
+
+/// Returns the rules of source files and the filter of functions to be
+/// excluded from the report.
+fn report_filters(
+    cx: &Context,
+    object_files: &[OsString],
+) -> Result<(IgnoreFilenameRules, Option<FunctionFilter>)> {
+    let mut ignore_rules = ignore_filename_rules(cx);
+    dep_coverage(cx, object_files, &mut ignore_rules)
+        .context("failed to collect source files of the dependency")?;
+    include_deps(cx, object_files, &mut ignore_rules)
+        .context("failed to collect source files of dependencies")?;
+    skip_large_files(cx, object_files, &mut ignore_rules)
+        .context("failed to collect large source files")?;
+    let function_filter = FunctionFilter::new(cx, object_files, ignore_rules.regex().as_deref())
+        .context("failed to collect functions to be excluded from report")?;
+    Ok((ignore_rules, function_filter))
+}
+
+/// Generates the report from the profile data written so far by the running
+/// binary (--dump-interval).
+///
+/// Only the report specified by the report format options is generated, and
+/// the other outputs, such as summaries and coverage checks, are left to the
+/// report generated after the binary exits.
+fn dump_report(cx: &Context) -> Result<()> {
+    let profraw_files = profraw_files(cx)?;
+    let mut input_files = Vec::with_capacity(profraw_files.len());
+    for path in &profraw_files {
+        input_files.push(
+            path.to_str()
+                .with_context(|| format!("{} contains invalid utf-8 data", path.display()))?,
+        );
+    }
+    if input_files.is_empty() {
+        return Ok(());
+    }
+    merge_profraw_files(cx, &input_files)?;
+    let object_files = object_files(cx)?;
+    let (ignore_rules, function_filter) = report_filters(cx, &object_files)?;
+    Format::from_args(cx).generate_report(
+        cx,
+        &object_files,
+        ignore_rules.regex().as_deref(),
+        function_filter.as_ref(),
+    )
+}
+
+/// Returns `true` if any workspace member included in the report has a staticlib target.
+fn has_staticlib(cx: &Context) -> bool {
+    cx.workspace_members.included.iter().any(|id| {
+        cx.ws.metadata.packages[id].targets.iter().any(|t| t.kind.iter().any(|k| k == "staticlib"))
+    })
+}
+
+fn merge_profraw(cx: &mut Context) -> Result<()> {
+    let _phase = timings::phase("merge");
+    // Wait for `cargo llvm-cov collect` to finish merging.
+    let _lock = if cx.args.no_lock { None } else { Some(lock::lock_profile_data(&cx.ws)?) };
+    // Convert raw profile data.
+    let profraw_files = profraw_files(cx)?;
+    if profraw_files.is_empty() && !cx.ws.rolling_profdata_file.exists() {
+        let mut msg = format!(
+            "not found *.profraw files in {}; this may occur if target directory is accidentally \
+             cleared, or running report subcommand without running any tests or binaries",
+            cx.ws.target_dir
+        );
+        // On Linux, the profiler runtime is linked only if the linker is asked
+        // for it, which rustc and clang do when linking instrumented binaries,
+        // but linkers invoked by external build systems don't.
+        if cx.ws.target_for_config.triple().contains("-linux") && has_staticlib(cx) {
+            msg.push_str(
+                "; note that binaries linked to a staticlib by external build systems (e.g., C \
+                 test harnesses) write profile data only if they are linked with \
+                 `-Wl,-u,__llvm_profile_runtime`",
+            );
+        }
+        if cx.args.cov.strict {
+            bail!("{msg}");
+        }
+        warn!("{msg}");
+    }
+    let mut input_files = Vec::with_capacity(profraw_files.len());
+    for path in &profraw_files {
+        input_files.push(
+            path.to_str()
+                .with_context(|| format!("{} contains invalid utf-8 data", path.display()))?,
+        );
+    }
+
+    if cx.args.cov.merge_toolchains && merge_profraw_across_toolchains(cx, &input_files)? {
+        return Ok(());
+    }
+    let res = merge_profraw_cached(cx, &profraw_files, &input_files);
+    let Err(e) = res else { return Ok(()) };
+    let Some(mismatch) = e.downcast_ref::<toolchain::VersionMismatch>() else { return Err(e) };
+    if !cx.args.cov.find_llvm_tools && !cx.args.cov.merge_toolchains {
+        return Err(e);
+    }
+    let Some((llvm_cov, llvm_profdata)) = toolchain::find_llvm_tools(cx, mismatch.file())? else {
+        return Err(e);
+    };
+    info!(
+        "using {} and {} because the profile data was generated by a different toolchain",
+        llvm_cov.display(),
+        llvm_profdata.display()
+    );
+    cx.llvm_cov = llvm_cov;
+    cx.llvm_profdata = llvm_profdata;
+    merge_profraw_cached(cx, &profraw_files, &input_files)
+}
+
+/// Merges profraw files generated by different toolchains (--merge-toolchains).
+///
+/// Returns `false` without merging if all files use the same version of the raw
+/// profile format. Otherwise, the files of each version are merged by
+/// llvm-profdata that can read them, and the results are merged by llvm-profdata
+/// for the newest version, because llvm-profdata can read indexed profiles
+/// written by older versions. llvm-cov of the same toolchain, which can also
+/// read coverage mappings written by older versions, is used for reports.
+fn merge_profraw_across_toolchains(cx: &mut Context, input_files: &[&str]) -> Result<bool> {
+    let groups = toolchain::group_by_version(input_files);
+    if groups.len() <= 1 {
+        return Ok(false);
+    }
+    let mut tools = Vec::with_capacity(groups.len());
+    for files in groups.values() {
+        tools.push(toolchain::llvm_tools_for(cx, files)?);
+    }
+    let mut outputs = Vec::with_capacity(groups.len());
+    for (i, (files, (_, llvm_profdata))) in groups.values().zip(&tools).enumerate() {
+        cx.llvm_profdata.clone_from(llvm_profdata);
+        let output = cx.ws.target_dir.join(format!("{}-toolchain{i}.profdata", cx.ws.name));
+        merge_profdata_cmd(cx, files, &output, &format!("profraw-list-toolchain{i}"), false)?
+            .run()?;
+        outputs.push(output);
+    }
+    // Groups are sorted by the version, so the last one is the newest.
+    let (llvm_cov, llvm_profdata) = tools.pop().unwrap();
+    cx.llvm_cov = llvm_cov;
+    cx.llvm_profdata = llvm_profdata;
+    let mut outputs_str: Vec<_> = outputs.iter().map(|f| f.as_str()).collect();
+    // llvm-profdata for the newest version can read the rolling profdata written
+    // by `cargo llvm-cov collect` with any of them.
+    if cx.ws.rolling_profdata_file.exists() {
+        outputs_str.push(cx.ws.rolling_profdata_file.as_str());
+    }
+    merge_profdata_cmd(cx, &outputs_str, &cx.ws.profdata_file, "profraw-list", false)?.run()?;
+    for f in &outputs {
+        fs::remove_file(f)?;
+    }
+    info!(
+        "merged profile data generated with {} versions of the raw profile format using {}",
+        groups.len(),
+        cx.llvm_profdata.display()
+    );
+    Ok(true)
+}
+
+fn merge_profraw_cached(
+    cx: &Context,
+    profraw_files: &[PathBuf],
+    input_files: &[&str],
+) -> Result<()> {
+    let rolling_profdata = Some(cx.ws.rolling_profdata_file.as_std_path())
+        .filter(|path| path.exists())
+        .map(Path::as_os_str);
+    let inputs = profraw_files.iter().map(|path| path.as_os_str()).chain(rolling_profdata);
+    cache::merge_profdata(cx, &llvm_profdata_merge(cx), inputs, || {
+        match merge_profraw_files(cx, input_files) {
+            Err(e) => {
+                // Report the version mismatch, which cannot be fixed by removing
+                // some of the profraw files.
+                toolchain::check_versions(cx, input_files)?;
+                if !cx.args.cov.quarantine {
+                    return Err(e);
+                }
+                let valid_files = quarantine_profraw_files(cx, input_files)?;
+                if valid_files.len() == input_files.len() {
+                    // The failure is not caused by invalid profraw files.
+                    return Err(e);
+                }
+                merge_profraw_files(cx, &valid_files)
+            }
+            Ok(()) => Ok(()),
+        }
+    })
+}
+
+/// Moves profraw files that llvm-profdata cannot read to the quarantine
+/// directory, and returns the remaining files.
+fn quarantine_profraw_files<'a>(cx: &Context, input_files: &[&'a str]) -> Result<Vec<&'a str>> {
+    #[derive(serde_derive::Serialize)]
+    struct Quarantined {
+        file: String,
+        reason: String,
+    }
+
+    let mut valid_files = vec![];
+    let mut quarantined = vec![];
+    for &f in input_files {
+        let output = cx.process(&cx.llvm_profdata).arg("show").arg(f).run_unchecked()?;
+        if output.status.success() {
+            valid_files.push(f);
+            continue;
+        }
+        // llvm-profdata reports errors like "error: <file>: <reason>".
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason =
+            stderr.lines().rev().find_map(|l| l.strip_prefix("error: ")).unwrap_or(&stderr).trim();
+        let reason = reason.strip_prefix(&format!("{f}: ")).unwrap_or(reason).to_owned();
+        fs::create_dir_all(&cx.ws.quarantine_dir)?;
+        let file = cx.ws.quarantine_dir.join(Utf8Path::new(f).file_name().unwrap());
+        fs::rename(f, &file)?;
+        quarantined.push(Quarantined { file: file.into_string(), reason });
+    }
+    if !quarantined.is_empty() {
+        let mut msg = format!(
+            "{} profraw files could not be merged and were moved to {}:",
+            quarantined.len(),
+            cx.ws.quarantine_dir
+        );
+        for q in &quarantined {
+            let _ = write!(msg, "\n    {}: {}", q.file, q.reason);
+        }
+        warn!("{msg}");
+        write_json(&cx.ws.quarantine_dir.join("quarantine.json"), &quarantined)?;
+    }
+    Ok(valid_files)
+}
+
+fn merge_profraw_files(cx: &Context, input_files: &[&str]) -> Result<()> {
+    // Include the profraw files already merged by `cargo llvm-cov collect`.
+    let with_rolling_profdata;
+    let input_files = if cx.ws.rolling_profdata_file.exists() {
+        with_rolling_profdata = [input_files, &[cx.ws.rolling_profdata_file.as_str()]].concat();
+        &with_rolling_profdata
+    } else {
+        input_files
+    };
+    status!("Merging", "{} profile data files", input_files.len());
+    // Merging thousands of profraw files with a single llvm-profdata invocation
+    // takes a long time, so split them into batches, merge the batches in
+    // parallel, and then merge the results.
+    let batches = input_files.len().div_ceil(PROFRAW_BATCH_SIZE);
+    if batches <= 1 {
+        merge_profdata_cmd(cx, input_files, &cx.ws.profdata_file, "profraw-list", false)?.run()?;
+        return Ok(());
+    }
+    let jobs = std::thread::available_parallelism().map_or(1, usize::from).min(batches);
+    let chunk_size = input_files.len().div_ceil(batches);
+    let failure_mode = cx.args.cov.failure_mode.as_deref();
+    let mut temp_files = TempFiles(vec![]);
+    let mut batch_files = vec![];
+    let mut cmds = vec![];
+    for (i, inputs) in input_files.chunks(chunk_size).enumerate() {
+        let list_name = format!("profraw-list{i}");
+        let output = cx.ws.target_dir.join(format!("{}-batch{i}.profdata", cx.ws.name));
+        temp_files.0.push(cx.ws.target_dir.join(format!("{}-{list_name}", cx.ws.name)));
+        temp_files.0.push(output.clone());
+        cmds.push(merge_profdata_cmd(cx, inputs, &output, &list_name, jobs > 1)?);
+        batch_files.push(output);
+    }
+    let mut results = Vec::with_capacity(cmds.len());
+    for cmds in cmds.chunks_mut(jobs) {
+        std::thread::scope(|s| {
+            let handles: Vec<_> = cmds.iter_mut().map(|cmd| s.spawn(move || cmd.run())).collect();
+            results.extend(handles.into_iter().map(|h| h.join().unwrap()));
+        });
+        status!("Merged", "{}/{batches} batches", results.len());
+    }
+    // With -failure-mode=all, a batch fails if all profraw files in it are
+    // invalid, but merging all files at once only fails if all files are
+    // invalid, so skip the failed batches unless all of them failed.
+    let mut merged = vec![];
+    let mut first_err = None;
+    for (output, result) in batch_files.iter().zip(results) {
+        match result {
+            Ok(_) => merged.push(output.as_str()),
+            Err(e) if failure_mode == Some("all") => {
+                first_err.get_or_insert(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if merged.is_empty() {
+        return Err(first_err.unwrap());
+    }
+    // The outputs of the batches are always valid, so the final merge doesn't
+    // use the user-specified failure mode.
+    merge_profdata_cmd_with(cx, &merged, &cx.ws.profdata_file, "profraw-list", None, false)?
+        .run()?;
+    Ok(())
+}
+
+/// Temporary files removed when dropped, even if merging failed.
+struct TempFiles(Vec<Utf8PathBuf>);
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for f in &self.0 {
+            let _ = fs::remove_file(f);
+        }
+    }
+}
+
+/// Maximum number of profraw files merged by one llvm-profdata invocation.
+const PROFRAW_BATCH_SIZE: usize = 256;
+
+/// Returns `llvm-profdata merge` command with flags common to all invocations.
+fn llvm_profdata_merge(cx: &Context) -> ProcessBuilder {
+    llvm_profdata_merge_with(cx, cx.args.cov.failure_mode.as_deref())
+}
+
+fn llvm_profdata_merge_with(cx: &Context, failure_mode: Option<&str>) -> ProcessBuilder {
+    let mut cmd = cx.process(&cx.llvm_profdata);
+    cmd.args(["merge", "-sparse"]);
+    if let Some(mode) = failure_mode {
+        cmd.arg(format!("-failure-mode={mode}"));
+    }
+    if let Some(flags) = &cx.llvm_profdata_flags {
+        cmd.args(flags.split(' ').filter(|s| !s.trim().is_empty()));
+    }
+    cmd
+}
+
+fn merge_profdata_cmd(
+    cx: &Context,
+    input_files: &[&str],
+    output: &Utf8Path,
+    list_name: &str,
+    single_thread: bool,
+) -> Result<ProcessBuilder> {
+    let failure_mode = cx.args.cov.failure_mode.as_deref();
+    merge_profdata_cmd_with(cx, input_files, output, list_name, failure_mode, single_thread)
+}
+
+fn merge_profdata_cmd_with(
+    cx: &Context,
+    input_files: &[&str],
+    output: &Utf8Path,
+    list_name: &str,
+    failure_mode: Option<&str>,
+    single_thread: bool,
+) -> Result<ProcessBuilder> {
+    let mut list = String::new();
+    for f in input_files {
+        list.push_str(f);
+        list.push('\n');
+    }
+    let input_files_path = &cx.ws.target_dir.join(format!("{}-{list_name}", cx.ws.name));
+    fs::write(input_files_path, list)?;
+    let mut cmd = llvm_profdata_merge_with(cx, failure_mode);
+    cmd.arg("-f").arg(input_files_path).arg("-o").arg(output);
+    if single_thread && !cx.llvm_profdata_flags.as_ref().is_some_and(|f| f.contains("threads")) {
+        // Batches are already merged in parallel.
+        cmd.arg("-num-threads=1");
+    }
+    if term::verbose() {
+        status!("Running", "{cmd}");
+    }
+    cmd.stdout_to_stderr();
+    Ok(cmd)
+}
+
+fn object_files(cx: &Context) -> Result<Vec<OsString>> {
+    let _phase = timings::phase("collect object files");
+    // To support testing binary crate like tests that use the CARGO_BIN_EXE
+    // environment variable, pass all compiled executables.
+    // This is not the ideal way, but the way unstable book says it is cannot support them.
+    // https://doc.rust-lang.org/nightly/rustc/instrument-coverage.html#tips-for-listing-the-binaries-automatically
+    let mut target_dir = cx.ws.target_dir.clone();
+    if nextest_archive::is_used(cx) {
+        target_dir.push("target");
+    }
+    // https://doc.rust-lang.org/nightly/cargo/guide/build-cache.html
+    if let Some(target) = &cx.args.target {
+        target_dir.push(target);
+    }
+    // https://doc.rust-lang.org/nightly/cargo/reference/profiles.html#custom-profiles
+    let profile = match cx.args.profile.as_deref() {
+        None if cx.args.release => "release",
+        Some("release" | "bench") => "release",
+        None | Some("dev" | "test") => "debug",
+        Some(p) => p,
+    };
+    target_dir.push(profile);
+    let mut trybuild_target_dir = cx.ws.trybuild_target_dir();
+    if let Some(target) = &cx.args.target {
+        trybuild_target_dir.push(target);
+    }
+    // Currently, trybuild always use debug build.
+    trybuild_target_dir.push("debug");
+
+    // The list of object files found in the previous run is still valid if the
+    // files in these directories are unchanged.
+    let mut dirs = vec![target_dir.clone(), cx.ws.doctests_dir.clone()];
+    for dir in [&target_dir, &trybuild_target_dir] {
+        dirs.extend(["deps", "examples", "build"].iter().map(|d| dir.join(d)));
+    }
+    dirs.push(trybuild_target_dir.clone());
+    let files = cache::object_files(cx, &dirs, || {
+        find_object_files(cx, &target_dir, &trybuild_target_dir)
+    })?;
+    let mut files = if cx.args.cov.merge_toolchains {
+        toolchain::filter_object_files(&profraw_files(cx)?, files)
+    } else {
+        files
+    };
+    for object in &cx.args.cov.objects {
+        let path = cx.current_dir.join(object);
+        if !path.is_file() {
+            bail!("object file `{object}` specified by --object not found");
+        }
+        let path = make_relative(cx, &path).to_owned().into_os_string();
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    coverage_mapping::restore(cx, files)
+}
+
+fn find_object_files(
+    cx: &Context,
+    target_dir: &Utf8Path,
+    trybuild_target_dir: &Utf8Path,
+) -> Result<Vec<OsString>> {
+    fn walk_target_dir<'a>(
+        cx: &'a Context,
+        target_dir: &Utf8Path,
+    ) -> impl Iterator<Item = walkdir::DirEntry> + 'a {
+        WalkDir::new(target_dir)
+            .into_iter()
+            .filter_entry(move |e| {
+                let p = e.path();
+                if p.is_dir() {
+                    if p.file_name()
+                        .is_some_and(|f| f == "incremental" || f == ".fingerprint" || f == "out")
+                    {
+                        // Ignore incremental compilation related files and output from build scripts.
+                        return false;
+                    }
+                    if p.extension().is_some_and(|e| e == "dSYM") {
+                        // Ignore debug info split by -C split-debuginfo=packed on macOS,
+                        // which contains files with the same names as the binaries.
+                        return false;
+                    }
+                } else if let Some(stem) = p.file_stem() {
+                    let stem = stem.to_string_lossy();
+                    if stem == "build-script-build" || stem.starts_with("build_script_build-") {
+                        let p = p.parent().unwrap();
+                        if p.parent().unwrap().file_name().unwrap() == "build" {
+                            if cx.args.cov.include_build_script {
+                                let dir = p.file_name().unwrap().to_string_lossy();
+                                if !cx.build_script_re.is_match(&dir) {
+                                    return false;
+                                }
+                            } else {
+                                return false;
+                            }
+                        }
+                    }
+                }
+                true
+            })
+            .filter_map(Result::ok)
+    }
+    fn is_object(cx: &Context, f: &Path) -> bool {
+        let ext = f.extension().unwrap_or_default();
+        // is_executable::is_executable doesn't work well on WSL.
+        // https://github.com/taiki-e/cargo-llvm-cov/issues/316
+        if ext == "d" {
+            return false;
+        }
+        // Debug info split by -C split-debuginfo, which doesn't contain the coverage mapping.
+        if ext == "dwo" || ext == "dwp" {
+            return false;
+        }
+        if cx.ws.target_for_config.triple().contains("-windows")
+            && (ext.eq_ignore_ascii_case("exe") || ext.eq_ignore_ascii_case("dll"))
+        {
+            return true;
+        }
+        is_executable::is_executable(f)
+    }
+
+    let re = Targets::new(&cx.ws).pkg_hash_re()?;
+    let mut files = vec![];
+    let mut searched_dir = String::new();
+    for f in walk_target_dir(cx, target_dir) {
+        let f = f.path();
+        if is_object(cx, f) {
+            if let Some(file_stem) = fs::file_stem_recursive(f).unwrap().to_str() {
+                if re.is_match(file_stem) {
+                    files.push(make_relative(cx, f).to_owned().into_os_string());
+                }
+            }
+        }
+    }
+    searched_dir.push_str(target_dir.as_str());
+    // With --merge-toolchains, doctests may have been run with another (nightly) toolchain.
+    if cx.args.doctests || cx.args.cov.merge_toolchains {
+        for f in glob::glob(
+            Utf8Path::new(&glob::Pattern::escape(cx.ws.doctests_dir.as_str()))
+                .join("*/rust_out")
+                .as_str(),
+        )?
+        .filter_map(Result::ok)
+        {
+            if is_object(cx, &f) {
+                files.push(make_relative(cx, &f).to_owned().into_os_string());
+            }
+        }
+        searched_dir.push(',');
+        searched_dir.push_str(cx.ws.doctests_dir.as_str());
+    }
+
+    // trybuild
+    if trybuild_target_dir.is_dir() {
+        let mut trybuild_targets = vec![];
+        for metadata in trybuild_metadata(&cx.ws, &cx.ws.metadata.target_directory)? {
+            for package in metadata.packages.into_values() {
+                for target in package.targets {
+                    trybuild_targets.push(target.name);
+                }
+            }
+        }
+        trybuild_targets.sort_unstable();
+        if !trybuild_targets.is_empty() {
+            let re =
+                Regex::new(&format!("^({})(-[0-9a-f]+)?$", trybuild_targets.join("|"))).unwrap();
+            for entry in walk_target_dir(cx, trybuild_target_dir) {
+                let path = make_relative(cx, entry.path());
+                if let Some(file_stem) = fs::file_stem_recursive(path).unwrap().to_str() {
+                    if re.is_match(file_stem) {
+                        continue;
+                    }
+                }
+                if is_object(cx, path) {
+                    files.push(path.to_owned().into_os_string());
+                }
+            }
+            searched_dir.push(',');
+            searched_dir.push_str(trybuild_target_dir.as_str());
+        }
+    }
+
+    // This sort is necessary to make the result of `llvm-cov show` match between macos and linux.
+    files.sort_unstable();
+
+    if files.is_empty() && cx.args.cov.objects.is_empty() {
+        warn!(
+            "not found object files (searched directories: {searched_dir}); this may occur if \
+             show-env subcommand is used incorrectly (see docs or other warnings), or unsupported \
+             commands such as nextest archive are used",
+        );
+    }
+    Ok(files)
+}
+
+struct Targets {
+    packages: BTreeSet<String>,
+    targets: BTreeSet<String>,
+}
+
+impl Targets {
+    fn new(ws: &Workspace) -> Self {
+        let mut packages = BTreeSet::new();
+        let mut targets = BTreeSet::new();
+        for id in &ws.metadata.workspace_members {
+            let pkg = &ws.metadata.packages[id];
+            packages.insert(pkg.name.clone());
+            for t in &pkg.targets {
+                targets.insert(t.name.clone());
+            }
+        }
+        Self { packages, targets }
+    }
+
+    fn pkg_hash_re(&self) -> Result<RegexVec> {
+        let mut re = RegexVecBuilder::new("^(lib)?(", ")(-[0-9a-f]+)?$");
+        for pkg in &self.packages {
+            re.or(&pkg.replace('-', "(-|_)"));
+        }
+        for t in &self.targets {
+            re.or(&t.replace('-', "(-|_)"));
+        }
+        re.build()
+    }
+}
+
+/// Collects metadata for packages generated by trybuild. If the trybuild test
+/// directory is not found, it returns an empty vector.
+fn trybuild_metadata(ws: &Workspace, target_dir: &Utf8Path) -> Result<Vec<Metadata>> {
+    // https://github.com/dtolnay/trybuild/pull/219
+    let mut trybuild_dir = target_dir.join("tests").join("trybuild");
+    if !trybuild_dir.is_dir() {
+        trybuild_dir.pop();
+        if !trybuild_dir.is_dir() {
+            return Ok(vec![]);
+        }
+    }
+    let mut metadata = vec![];
+    for entry in fs::read_dir(trybuild_dir)?.filter_map(Result::ok) {
+        let manifest_path = &entry.path().join("Cargo.toml");
+        if !manifest_path.is_file() {
+            continue;
+        }
+        metadata.push(Metadata::new(manifest_path, ws.config.cargo())?);
+    }
+    Ok(metadata)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    /// `llvm-cov report`
+    None,
+    /// `llvm-cov export -format=text`
+    Json,
+    /// `llvm-cov export -format=lcov`
+    LCov,
+    /// `llvm-cov export -format=lcov` later converted to XML
+    Cobertura,
+    /// `llvm-cov show -format=lcov` later converted to Codecov JSON
+    Codecov,
+    /// `llvm-cov export -format=text -summary-only` later converted to summary JSON
+    SummaryJson,
+    /// `llvm-cov export -format=text` later passed to --exec-formatter
+    Exec,
+    /// `llvm-cov show -format=text`
+    Text,
+    /// `llvm-cov show -format=html`
+    Html,
+}
+
+impl Format {
+    fn from_args(cx: &Context) -> Self {
+        if cx.args.cov.json {
+            Self::Json
+        } else if cx.args.cov.lcov {
+            Self::LCov
+        } else if cx.args.cov.cobertura {
+            Self::Cobertura
+        } else if cx.args.cov.codecov {
+            Self::Codecov
+        } else if cx.args.cov.summary_json {
+            Self::SummaryJson
+        } else if cx.args.cov.exec_formatter.is_some() {
+            Self::Exec
+        } else if cx.args.cov.text {
+            Self::Text
+        } else if cx.args.cov.html {
+            Self::Html
+        } else {
+            Self::None
+        }
+    }
+
+    const fn llvm_cov_args(self) -> &'static [&'static str] {
+        match self {
+            Self::None => &["report"],
+            Self::Json | Self::Codecov | Self::SummaryJson | Self::Exec => {
+                &["export", "-format=text"]
+            }
+            Self::LCov | Self::Cobertura => &["export", "-format=lcov"],
+            Self::Text => &["show", "-format=text"],
+            Self::Html => &["show", "-format=html"],
+        }
+    }
+
+    fn use_color(self, cx: &Context) -> Option<&'static str> {
+        if matches!(self, Self::Json | Self::LCov | Self::SummaryJson | Self::Exec | Self::Html) {
+            // `llvm-cov export` doesn't have `-use-color` flag.
+            // https://llvm.org/docs/CommandGuide/llvm-cov.html#llvm-cov-export
+            // Color output cannot be disabled when generating html.
+            return None;
+        }
+        if self == Self::Text && cx.args.cov.output_dir.is_some() {
+            return Some("-use-color=0");
+        }
+        match cx.args.color {
+            Some(Coloring::Auto) | None => None,
+            Some(Coloring::Always) => Some("-use-color=1"),
+            Some(Coloring::Never) => Some("-use-color=0"),
+        }
+    }
+
+    /// Returns whether this report is generated from the JSON export instead of
+    /// by llvm-cov.
+    fn generated_from_json(self, cx: &Context, function_filter: Option<&FunctionFilter>) -> bool {
+        // `llvm-cov report` doesn't respect function filters, doesn't rewrite
+        // paths, and doesn't show covered counts, so the summary table is
+        // generated from the JSON in those cases.
+        self == Self::SummaryJson
+            || self == Self::None
+                && (function_filter.is_some()
+                    || !cx.path_map.is_empty()
+                    || percent::counts().is_some())
+    }
+
+    /// Writes the report generated from the JSON export. See also `generated_from_json`.
+    fn write_from_json(self, cx: &Context, json: &LlvmCovJsonExport) -> Result<()> {
+        if self == Self::SummaryJson {
+            let summary = summary_json(cx, json)?;
+            if let Some(output_path) = &cx.args.cov.output_path {
+                write_json(output_path, &summary)?;
+                eprintln!();
+                status!("Finished", "report saved to {output_path}");
+            } else {
+                println!("{}", serde_json::to_string(&summary)?);
+            }
+            return Ok(());
+        }
+        let out = json.summary_table()?;
+        if let Some(output_path) = &cx.args.cov.output_path {
+            fs::write(output_path, out)?;
+            eprintln!();
+            status!("Finished", "report saved to {output_path}");
+        } else {
+            println!("{out}");
+        }
+        Ok(())
+    }
+
+    fn generate_report(
+        self,
+        cx: &Context,
+        object_files: &[OsString],
+        ignore_filename_regex: Option<&str>,
+        function_filter: Option<&FunctionFilter>,
+    ) -> Result<()> {
+        if self.generated_from_json(cx, function_filter) {
+            let json = Self::Json
+                .get_json(cx, object_files, ignore_filename_regex, function_filter, true)
+                .context("failed to get json")?;
+            return self.write_from_json(cx, &json);
+        }
+
+        let mut cmd = cx.process(&cx.llvm_cov);
+
+        cmd.args(self.llvm_cov_args());
+        cmd.args(self.use_color(cx));
+        cmd.arg(format!("-instr-profile={}", cx.ws.profdata_file));
+        cmd.args(object_files.iter().flat_map(|f| [OsStr::new("-object"), f]));
+        if let Some(ignore_filename_regex) = ignore_filename_regex {
+            cmd.arg("-ignore-filename-regex");
+            cmd.arg(ignore_filename_regex);
+        }
+        cmd.args(cx.path_map.llvm_cov_args());
+
+        match self {
+            Self::Text | Self::Html => {
+                cmd.args([
+                    &format!("-show-instantiations={}", cx.args.cov.show_instantiations),
+                    "-show-line-counts-or-regions",
+                    "-show-expansions",
+                    "-show-branches=count",
+                    &format!("-Xdemangler={}", cx.current_exe.display()),
+                    "-Xdemangler=llvm-cov",
+                    "-Xdemangler=demangle",
+                ]);
+                if cx.args.cov.demangle_keep_hashes {
+                    cmd.arg("-Xdemangler=--demangle-keep-hashes");
+                }
+                if cx.args.cov.demangle_cpp {
+                    cmd.arg("-Xdemangler=--demangle-cpp");
+                }
+                if let Some(function_filter) = function_filter {
+                    cmd.arg(format!("-name-allowlist={}", function_filter.allowlist()));
+                }
+                if let Some(output_dir) = &cx.args.cov.output_dir {
+                    if self == Self::Html {
+                        cmd.arg(format!("-output-dir={}", output_dir.join("html")));
+                    } else {
+                        cmd.arg(format!("-output-dir={}", output_dir.join("text")));
+                    }
+                }
+            }
+            Self::Json | Self::LCov | Self::Cobertura | Self::Codecov | Self::Exec => {
+                // Function filters are applied to the full export, and then
+                // details are removed.
+                if cx.args.cov.summary_only && function_filter.is_none() {
+                    cmd.arg("-summary-only");
+                }
+            }
+            Self::None | Self::SummaryJson => {}
+        }
+
+        if let Some(flags) = &cx.llvm_cov_flags {
+            cmd.args(flags.split(' ').filter(|s| !s.trim().is_empty()));
+        }
+
+        if self == Self::Cobertura {
+            let lcov = cache::export(cx, &mut cmd, object_files)?;
+            let lcov = Self::process_lcov(cx, lcov, function_filter, false)?;
+            // Convert to XML
+            let cdata = lcov2cobertura::parse_lines(
+                lcov.as_bytes().lines(),
+                &cx.ws.metadata.workspace_root,
+                &[],
+            )?;
+            let mut demangler = demangler(cx);
+            demangler.prepare_cpp(demangle::lcov_function_names(&lcov))?;
+            let out = lcov2cobertura::coverage_to_string(&cdata, timestamp(cx)?, demangler)?;
+
+            if let Some(output_path) = &cx.args.cov.output_path {
+                fs::write(output_path, out)?;
+                eprintln!();
+                status!("Finished", "report saved to {output_path}");
+            } else {
+                // write XML to stdout
+                println!("{out}");
+            }
+            return Ok(());
+        }
+
+        if let Some(formatter) = &cx.args.cov.exec_formatter {
+            let cov = cache::export_json(cx, &mut cmd, object_files, function_filter)?;
+            let cov = Self::process_json(cx, cov, function_filter)?;
+            let mut formatter = formatter.split_whitespace();
+            let mut formatter_cmd = cmd!(formatter.next().unwrap_or_default());
+            formatter_cmd.args(formatter).dir(&cx.ws.metadata.workspace_root);
+            if term::verbose() {
+                status!("Running", "{formatter_cmd}");
+            }
+            let out = formatter_cmd.run_with_stdin(serde_json::to_vec(&cov)?)?.stdout;
+
+            if let Some(output_path) = &cx.args.cov.output_path {
+                fs::write(output_path, out)?;
+                eprintln!();
+                status!("Finished", "report saved to {output_path}");
+            } else {
+                std::io::stdout().lock().write_all(&out)?;
+            }
+            return Ok(());
+        }
+
+        if cx.args.cov.codecov {
+            let mut cov = cache::export_json(cx, &mut cmd, object_files, function_filter)?;
+            cx.path_map.apply_to_json(&mut cov);
+            let cov = CodeCovJsonExport::from_llvm_cov_json_export(cov, ignore_filename_regex);
+
+            if let Some(output_path) = &cx.args.cov.output_path {
+                write_json(output_path, &cov)?;
+                eprintln!();
+                status!("Finished", "report saved to {output_path}");
+            } else {
+                // write JSON to stdout
+                println!("{}", serde_json::to_string(&cov)?);
+            }
+            return Ok(());
+        }
+
+        if let Some(output_path) = &cx.args.cov.output_path {
+            if self == Self::Json {
+                let cov = cache::export_json(cx, &mut cmd, object_files, function_filter)?;
+                let cov = Self::process_json(cx, cov, function_filter)?;
+                if cx.args.cov.json_lines {
+                    cov.write_json_lines(BufWriter::new(fs::File::create(output_path)?))?;
+                } else if cx.args.cov.msgpack {
+                    msgpack::to_writer(BufWriter::new(fs::File::create(output_path)?), &cov)?;
+                } else {
+                    write_json(output_path, &cov)?;
+                }
+            } else if self == Self::LCov {
+                let out = cache::export(cx, &mut cmd, object_files)?;
+                let out = Self::process_lcov(cx, out, function_filter, cx.args.cov.summary_only)?;
+                fs::write(output_path, out)?;
+            } else {
+                fs::write(output_path, cache::export(cx, &mut cmd, object_files)?)?;
+            }
+
+            eprintln!();
+            status!("Finished", "report saved to {output_path}");
+            return Ok(());
+        }
+
+        if self == Self::Json {
+            let cov = cache::export_json(cx, &mut cmd, object_files, function_filter)?;
+            let cov = Self::process_json(cx, cov, function_filter)?;
+
+            let stdout = std::io::stdout().lock();
+            if cx.args.cov.json_lines {
+                cov.write_json_lines(BufWriter::new(stdout))?;
+            } else if cx.args.cov.msgpack {
+                msgpack::to_writer(BufWriter::new(stdout), &cov)?;
+            } else {
+                serde_json::to_writer(stdout, &cov)?;
+            }
+        } else if self == Self::LCov
+            && (function_filter.is_some()
+                || !cx.path_map.is_empty()
+                || cx.args.cov.demangle
+                || cx.args.cov.lcov_relative_to.is_some())
+        {
+            let out = cache::export(cx, &mut cmd, object_files)?;
+            println!("{}", Self::process_lcov(cx, out, function_filter, cx.args.cov.summary_only)?);
+        } else if self == Self::None && percent::enabled() {
+            // llvm-cov always shows two decimal places rounded to the nearest.
+            if term::verbose() {
+                status!("Running", "{cmd}");
+            }
+            println!("{}", percent::rewrite_table(&cmd.read()?));
+        } else {
+            if term::verbose() {
+                status!("Running", "{cmd}");
+            }
+            cmd.run()?;
+        }
+
+        if self == Self::Html {
+            if let Some(output_dir) = &cx.args.cov.output_dir {
+                fix_html(cx, &output_dir.join("html")).context("failed to rewrite html report")?;
+                html_assets::copy(cx, &output_dir.join("html"))
+                    .context("failed to copy assets to html report")?;
+                if cx.args.cov.blame {
+                    blame::annotate(cx, &output_dir.join("html"))
+                        .context("failed to add git blame to html report")?;
+                }
+                html_assets::check(&output_dir.join("html"))
+                    .context("failed to check html report")?;
+            }
+        }
+        if matches!(self, Self::Html | Self::Text) {
+            if let Some(output_dir) = &cx.args.cov.output_dir {
+                eprintln!();
+                if self == Self::Html {
+                    status!("Finished", "report saved to {}", output_dir.join("html"));
+                } else {
+                    status!("Finished", "report saved to {}", output_dir.join("text"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Processes JSON output of `llvm-cov export` for --json.
+    fn process_json(
+        cx: &Context,
+        mut cov: LlvmCovJsonExport,
+        function_filter: Option<&FunctionFilter>,
+    ) -> Result<LlvmCovJsonExport> {
+        // Function filters are applied by cache::export_json, which needs
+        // details of functions.
+        if function_filter.is_some() && cx.args.cov.summary_only {
+            cov.remove_details();
+        }
+        if cx.args.cov.demangle {
+            let mut demangler = demangler(cx);
+            demangler.prepare_cpp(cov.function_names())?;
+            cov.map_function_names(|name| demangler.demangle(name));
+        }
+        cx.path_map.apply_to_json(&mut cov);
+        cov.sort();
+        cov.inject(cx.ws.current_manifest.clone());
+        if cx.tests_failed.get() {
+            cov.set_tests_failed();
+        }
+        Ok(cov)
+    }
+
+    /// Applies function filters, --demangle, --path-map, and --lcov-relative-to to output of
+    /// `llvm-cov export -format=lcov`, and sorts records by file name.
+    fn process_lcov(
+        cx: &Context,
+        mut out: String,
+        function_filter: Option<&FunctionFilter>,
+        summary_only: bool,
+    ) -> Result<String> {
+        if let Some(function_filter) = function_filter {
+            out = function_filter.apply_to_lcov(&out, summary_only);
+        }
+        if cx.args.cov.demangle && !summary_only {
+            out = demangler(cx).demangle_lcov(&out)?;
+        }
+        out = cx.path_map.apply_to_lcov(out);
+        // --lcov-relative-to requires --lcov, so this doesn't affect --cobertura.
+        if let Some(dir) = &cx.args.cov.lcov_relative_to {
+            out = path_map::lcov_relative_to(
+                &out,
+                cx.ws.metadata.workspace_root.as_std_path(),
+                &cx.current_dir.join(dir),
+            );
+        }
+        Ok(sort_lcov_records(&out))
+    }
+
+    /// Generates JSON to perform further analysis on it.
+    ///
+    /// If `summary_only` is `true`, details of files and functions may be
+    /// omitted to reduce memory usage.
+    fn get_json(
+        self,
+        cx: &Context,
+        object_files: &[OsString],
+        ignore_filename_regex: Option<&str>,
+        function_filter: Option<&FunctionFilter>,
+        summary_only: bool,
+    ) -> Result<LlvmCovJsonExport> {
+        if let Self::Json = self {
+        } else {
+            bail!("requested JSON for non-JSON type");
+        }
+
+        let mut cmd = cx.process(&cx.llvm_cov);
+        cmd.args(self.llvm_cov_args());
+        cmd.arg(format!("-instr-profile={}", cx.ws.profdata_file));
+        cmd.args(object_files.iter().flat_map(|f| [OsStr::new("-object"), f]));
+        if let Some(ignore_filename_regex) = ignore_filename_regex {
+            cmd.arg("-ignore-filename-regex");
+            cmd.arg(ignore_filename_regex);
+        }
+        cmd.args(cx.path_map.llvm_cov_args());
+        // Function filters need details of functions.
+        if summary_only && function_filter.is_none() {
+            cmd.arg("-summary-only");
+        }
+        let mut json = cache::export_json(cx, &mut cmd, object_files, function_filter)?;
+        cx.path_map.apply_to_json(&mut json);
+        json.sort();
+        Ok(json)
+    }
+}
+
+/// Patterns of source files to be excluded from the report, with the option (or
+/// the reason) that excludes them, which is shown by --show-excluded.
+struct IgnoreFilenameRules {
+    rules: Vec<(&'static str, String)>,
+    /// Whether to match verbatim paths as well.
+    verbatim: bool,
+}
+
+impl IgnoreFilenameRules {
+    fn push(&mut self, rule: &'static str, s: impl Into<String>) {
+        self.rules.push((rule, s.into()));
+    }
+
+    fn push_abs_path(&mut self, rule: &'static str, path: impl AsRef<Path>) {
+        let path = path.as_ref().to_string_lossy();
+        if self.verbatim {
+            if let Some(verbatim) = windows_path::to_verbatim(&path) {
+                let verbatim = regex::escape(&verbatim);
+                self.push(rule, format!("^{verbatim}($|{IGNORE_SEPARATOR})"));
+            }
+        }
+        let path = regex::escape(&path);
+        self.push(rule, format!("^{path}($|{IGNORE_SEPARATOR})"));
+    }
+
+    /// Adds the given source file.
+    fn push_file(&mut self, rule: &'static str, filename: &str) {
+        self.push(rule, format!("^{}$", regex::escape(filename)));
+    }
+
+    /// Returns the regex to be passed to `llvm-cov -ignore-filename-regex`.
+    fn regex(&self) -> Option<String> {
+        if self.rules.is_empty() {
+            None
+        } else {
+            Some(self.rules.iter().map(|(_, re)| &**re).collect::<Vec<_>>().join("|"))
+        }
+    }
+}
+
+// On Windows, we should escape the separator.
+const IGNORE_SEPARATOR: &str = if cfg!(windows) { "\\\\" } else { "/" };
+
+fn ignore_filename_rules(cx: &Context) -> IgnoreFilenameRules {
+    const SEPARATOR: &str = IGNORE_SEPARATOR;
+
+    let mut out = IgnoreFilenameRules { rules: vec![], verbatim: cx.args.windows_long_paths };
+
+    if let Some(ignore_filename) = &cx.args.cov.ignore_filename_regex {
+        out.push("--ignore-filename-regex", ignore_filename);
+    }
+    for (id, config) in &cx.package_configs {
+        let Some(ignore_filename) = &config.ignore_filename_regex else { continue };
+        let mut dir = cx.ws.metadata.packages[id].manifest_path.parent().unwrap();
+        if cx.args.remap_path_prefix {
+            dir = dir.strip_prefix(&cx.ws.metadata.workspace_root).unwrap_or(dir);
+        }
+        let rule = "ignore-filename-regex in package metadata";
+        if dir.as_str().is_empty() {
+            out.push(rule, format!("({ignore_filename})"));
+        } else {
+            out.push(
+                rule,
+                format!("^{}{SEPARATOR}.*({ignore_filename})", regex::escape(dir.as_str())),
+            );
+        }
+    }
+    if !cx.args.cov.disable_default_ignore_filename_regex {
+        const STD: &str = "standard library and toolchain";
+        const TESTS: &str = "tests, examples, and benches directories";
+        const GENERATED: &str = "generated code in the target directory";
+        const DEPS: &str = "dependencies";
+        // TODO: Should we use the actual target path instead of using `tests|examples|benches`?
+        //       We may have a directory like tests/support, so maybe we need both?
+        if cx.args.remap_path_prefix {
+            out.push(
+                STD,
+                format!(
+                    r"(^|{SEPARATOR})rustc{SEPARATOR}([0-9a-f]+|[0-9]+\.[0-9]+\.[0-9]+){SEPARATOR}"
+                ),
+            );
+            out.push(TESTS, format!(r"(^|{SEPARATOR})(tests|examples|benches){SEPARATOR}"));
+        } else {
+            out.push(
+                STD,
+                format!(
+                    r"{SEPARATOR}rustc{SEPARATOR}([0-9a-f]+|[0-9]+\.[0-9]+\.[0-9]+){SEPARATOR}"
+                ),
+            );
+            out.push(
+                TESTS,
+                format!(
+                    r"^{}({SEPARATOR}.*)?{SEPARATOR}(tests|examples|benches){SEPARATOR}",
+                    regex::escape(cx.ws.metadata.workspace_root.as_str())
+                ),
+            );
+            if let Some(root) = &cx.archived_workspace_root {
+                out.push(
+                    TESTS,
+                    format!(
+                        r"^{}({SEPARATOR}.*)?{SEPARATOR}(tests|examples|benches){SEPARATOR}",
+                        regex::escape(root.as_str())
+                    ),
+                );
+            }
+        }
+        if !cx.args.cov.include_out_dir {
+            // Source files under the target directory are generated ones (e.g., bindgen,
+            // prost, and tonic output in OUT_DIR). The first pattern matches OUT_DIR of
+            // any crate regardless of where the target directory is, or whether the
+            // path has been made relative by --remap-path-prefix.
+            out.push(
+                GENERATED,
+                format!(
+                    r"(^|{SEPARATOR})build{SEPARATOR}[^{SEPARATOR}]+-[0-9a-f]+{SEPARATOR}out{SEPARATOR}"
+                ),
+            );
+            out.push_abs_path(GENERATED, &cx.ws.target_dir);
+        }
+        // Dependencies may be in the home directory or the cargo home. Files
+        // of dependencies not to be included are ignored by `dep_coverage`
+        // and `include_deps` for --dep-coverage and --include-deps-matching.
+        let include_deps = !cx.dep_coverage.is_empty() || !cx.included_deps.is_empty();
+        if cx.args.remap_path_prefix && !include_deps {
+            if let Some(path) = home::home_dir() {
+                out.push_abs_path(DEPS, path);
+            }
+        }
+        if let Ok(path) = home::cargo_home() {
+            if !include_deps {
+                let path = regex::escape(&path.as_os_str().to_string_lossy());
+                let path = format!("^{path}{SEPARATOR}(registry|git){SEPARATOR}");
+                out.push(DEPS, path);
+            }
+        }
+        if let Ok(path) = home::rustup_home() {
+            out.push_abs_path(STD, path.join("toolchains"));
+        }
+        for path in resolve_excluded_paths(cx) {
+            out.push_abs_path("--exclude-from-report", path);
+        }
+    }
+
+    out
+}
+
+/// Adds source files outside of the dependency specified by --dep-coverage to `ignore_rules`.
+fn dep_coverage(
+    cx: &Context,
+    object_files: &[OsString],
+    ignore_rules: &mut IgnoreFilenameRules,
+) -> Result<()> {
+    if cx.dep_coverage.is_empty() {
+        return Ok(());
+    }
+
+    let json = export_summary(cx, object_files, ignore_rules.regex().as_deref())?;
+    let mut found = false;
+    for filename in json.file_names() {
+        let local = cx.path_map.map(filename);
+        let path = Path::new(local.as_deref().unwrap_or(filename));
+        if cx.dep_coverage.iter().any(|dir| path.starts_with(dir)) {
+            found = true;
+        } else {
+            ignore_rules.push_file("--dep-coverage", filename);
+        }
+    }
+    if !found {
+        warn!(
+            "no source files of `{}` found in coverage data; this may occur if no code of the \
+             dependency was used by tests or binaries",
+            cx.args.cov.dep_coverage.as_deref().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Adds source files of dependencies not matched by --include-deps-matching to `ignore_rules`.
+fn include_deps(
+    cx: &Context,
+    object_files: &[OsString],
+    ignore_rules: &mut IgnoreFilenameRules,
+) -> Result<()> {
+    if cx.included_deps.is_empty() || !cx.dep_coverage.is_empty() {
+        return Ok(());
+    }
+
+    // The same directories as the ones ignored by the default --ignore-filename-regex.
+    let mut deps_dirs = vec![];
+    if cx.args.remap_path_prefix {
+        deps_dirs.extend(home::home_dir());
+    }
+    if let Ok(path) = home::cargo_home() {
+        deps_dirs.push(path.join("registry"));
+        deps_dirs.push(path.join("git"));
+    }
+    let json = export_summary(cx, object_files, ignore_rules.regex().as_deref())?;
+    for filename in json.file_names() {
+        let mapped = cx.path_map.map(filename);
+        let path = Path::new(mapped.as_deref().unwrap_or(filename));
+        if cx.included_deps.iter().any(|dir| path.starts_with(dir)) {
+            continue;
+        }
+        if deps_dirs.iter().any(|dir| path.starts_with(dir)) {
+            ignore_rules.push_file("--include-deps-matching", filename);
+        }
+    }
+    Ok(())
+}
+
+/// Exports the summary of the coverage data, which is used to list source files.
+fn export_summary(
+    cx: &Context,
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+) -> Result<LlvmCovJsonExport> {
+    let mut cmd = cx.process(&cx.llvm_cov);
+    cmd.args(["export", "-format=text", "-summary-only"]);
+    cmd.arg(format!("-instr-profile={}", cx.ws.profdata_file));
+    cmd.args(object_files.iter().flat_map(|f| [OsStr::new("-object"), f]));
+    if let Some(ignore_filename_regex) = ignore_filename_regex {
+        cmd.arg("-ignore-filename-regex");
+        cmd.arg(ignore_filename_regex);
+    }
+    cmd.args(cx.path_map.llvm_cov_args());
+    cache::export_json(cx, &mut cmd, object_files, None)
+}
+
+/// Adds source files that exceed --max-file-lines or --max-file-size to `ignore_rules`.
+fn skip_large_files(
+    cx: &Context,
+    object_files: &[OsString],
+    ignore_rules: &mut IgnoreFilenameRules,
+) -> Result<()> {
+    let (max_lines, max_size) = (cx.args.cov.max_file_lines, cx.args.cov.max_file_size);
+    if max_lines.is_none() && max_size.is_none() {
+        return Ok(());
+    }
+
+    let json = export_summary(cx, object_files, ignore_rules.regex().as_deref())?;
+    let mut skipped = vec![];
+    for filename in json.file_names() {
+        let path = cx.source_path(filename);
+        let Ok(metadata) = fs::metadata(&path) else { continue };
+        let size = metadata.len();
+        if max_size.is_some_and(|max| size > max) {
+            skipped.push(format!("{filename} ({size} bytes)"));
+        } else if let Some(max) = max_lines {
+            let lines = String::from_utf8_lossy(&fs::read(&path)?).lines().count() as u64;
+            if lines <= max {
+                continue;
+            }
+            skipped.push(format!("{filename} ({lines} lines)"));
+        } else {
+            continue;
+        }
+        ignore_rules.push_file("--max-file-lines or --max-file-size", filename);
+    }
+    if !skipped.is_empty() {
+        info!(
+            "skipped the following files that exceed --max-file-lines or --max-file-size:\n    {}",
+            skipped.join("\n    ")
+        );
+    }
+    Ok(())
+}
+
+fn resolve_excluded_paths(cx: &Context) -> Vec<Utf8PathBuf> {
+    let excluded: Vec<_> = cx
+        .workspace_members
+        .excluded
+        .iter()
+        .map(|id| cx.ws.metadata.packages[id].manifest_path.parent().unwrap())
+        .collect();
+    let included = cx
+        .workspace_members
+        .included
+        .iter()
+        .map(|id| cx.ws.metadata.packages[id].manifest_path.parent().unwrap());
+    // Paths in the report are relative to the workspace root only if --remap-path-prefix is used.
+    let remap = |path: &Utf8Path| -> Utf8PathBuf {
+        if cx.args.remap_path_prefix {
+            path.strip_prefix(&cx.ws.metadata.workspace_root).unwrap_or(path).to_owned()
+        } else {
+            path.to_owned()
+        }
+    };
+    let mut excluded_path = vec![];
+    let mut contains: HashMap<&Utf8Path, Vec<_>> = HashMap::new();
+    for included in included {
+        for &excluded in excluded.iter().filter(|e| included.starts_with(e)) {
+            if let Some(v) = contains.get_mut(&excluded) {
+                v.push(included);
+            } else {
+                contains.insert(excluded, vec![included]);
+            }
+        }
+    }
+    if contains.is_empty() {
+        for &manifest_dir in &excluded {
+            excluded_path.push(remap(manifest_dir));
+        }
+        return excluded_path;
+    }
+
+    for &excluded in &excluded {
+        let Some(included) = contains.get(&excluded) else {
+            excluded_path.push(remap(excluded));
+            continue;
+        };
+
+        for _ in WalkDir::new(excluded).sort_by_file_name().into_iter().filter_entry(|e| {
+            let p = e.path();
+            if !p.is_dir() {
+                if p.extension().is_some_and(|e| e == "rs") {
+                    excluded_path.push(remap(p.try_into().unwrap()));
+                }
+                return false;
+            }
+
+            let mut contains = false;
+            for included in included {
+                if included.starts_with(p) {
+                    if p.starts_with(included) {
+                        return false;
+                    }
+                    contains = true;
+                }
+            }
+            if contains {
+                // continue to walk
+                return true;
+            }
+            excluded_path.push(remap(p.try_into().unwrap()));
+            false
+        }) {}
+    }
+    excluded_path
+}
+
+fn target_u_lower(target: &str) -> String {
+    target.replace(['-', '.'], "_")
+}
+fn target_u_upper(target: &str) -> String {
+    let mut target = target_u_lower(target);
+    target.make_ascii_uppercase();
+    target
+}
+
+/// Make the path relative if it's a descendent of the current working dir, otherwise just return
+/// the original path
+fn make_relative<'a>(cx: &Context, p: &'a Path) -> &'a Path {
+    p.strip_prefix(&cx.current_dir).unwrap_or(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utc() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00");
+        assert_eq!(format_utc(951_782_400), "2000-02-29 00:00");
+        assert_eq!(format_utc(1_700_000_000), "2023-11-14 22:13");
+    }
+
+    #[test]
+    fn sort_lcov() {
+        let lcov = "SF:src/b.rs\nDA:1,1\nend_of_record\nSF:src/a.rs\nDA:2,0\nend_of_record\n";
+        let sorted = "SF:src/a.rs\nDA:2,0\nend_of_record\nSF:src/b.rs\nDA:1,1\nend_of_record\n";
+        assert_eq!(sort_lcov_records(lcov), sorted);
+        assert_eq!(sort_lcov_records(sorted), sorted);
+        assert_eq!(sort_lcov_records(lcov.trim_end()), sorted.trim_end());
+        assert_eq!(sort_lcov_records(""), "");
+    }
+
+    #[test]
+    fn summary_line_format() {
+        let counts = |count, covered, percent| SummaryCounts { count, covered, percent };
+        let totals = SummaryTotals {
+            functions: counts(4, 3, 75.0),
+            lines: counts(1000, 824, 82.4),
+            regions: counts(3, 2, 66.666),
+            branches: counts(0, 0, 0.0),
+        };
+        assert_eq!(summary_line(&totals), "lines=82.4% functions=75.0% regions=66.7% branches=-");
+    }
+
+    #[test]
+    fn args_file_line() {
+        assert_eq!(split_args("a  b\tc").unwrap(), ["a", "b", "c"]);
+        assert_eq!(split_args(r#"--x='a b' "c \"d\" \e" f\ g ''"#).unwrap(), [
+            "--x=a b",
+            r#"c "d" \e"#,
+            "f g",
+            ""
+        ]);
+        assert!(split_args("'a").is_err());
+        assert!(split_args("\"a").is_err());
+        assert!(split_args("a\\").is_err());
+    }
+
+    #[test]
+    fn executed_tests() {
+        assert_eq!(
+            count_executed_tests(
+                "test result: ok. 3 passed; 1 failed; 2 ignored; 0 measured; 4 filtered out; \
+                 finished in 0.00s\n"
+            ),
+            4
+        );
+        assert_eq!(
+            count_executed_tests(
+                "test result: ok. 0 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; \
+                 finished in 0.00s"
+            ),
+            0
+        );
+        assert_eq!(count_executed_tests("test foo ... ok"), 0);
+    }
+}
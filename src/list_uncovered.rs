@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --list-uncovered support.
+//
+// This lists every code region not executed by any test, one per line, so that
+// scripts (e.g., ones that file TODOs or post review comments) don't need to
+// parse the json export of llvm-cov. Paths are relative to the workspace root,
+// and function names are demangled in the same way as --demangle.
+
+use std::{
+    io::{self, Write as _},
+    str::FromStr,
+};
+
+use anyhow::{bail, Error, Result};
+
+use crate::{
+    context::Context,
+    json::{LlvmCovJsonExport, UncoveredRegionsExport},
+};
+
+/// The output format of --list-uncovered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ListUncoveredFormat {
+    /// `<file>:<start line>:<start column>-<end line>:<end column>\t<function>`
+    Text,
+    /// The schema of [`UncoveredRegionsExport`]
+    Json,
+}
+
+impl FromStr for ListUncoveredFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("--list-uncovered must be text or json, but found `{s}`"),
+        }
+    }
+}
+
+/// Prints the uncovered regions to stdout in the format specified by
+/// --list-uncovered.
+pub(crate) fn print(
+    cx: &Context,
+    json: &LlvmCovJsonExport,
+    ignore_filename_regex: Option<&str>,
+) -> Result<()> {
+    let Some(format) = cx.args.cov.list_uncovered else { return Ok(()) };
+    let mut export = UncoveredRegionsExport::from_llvm_cov_json_export(json, ignore_filename_regex);
+
+    let mut demangler = crate::demangler(cx);
+    demangler.prepare_cpp(export.regions.iter().map(|r| &*r.function))?;
+    for region in &mut export.regions {
+        if let Some(relative) = cx.relative_source_path(&region.file) {
+            region.file = relative.into_string();
+        }
+        region.function = demangler.demangle(&region.function);
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    match format {
+        ListUncoveredFormat::Text => {
+            for r in &export.regions {
+                writeln!(
+                    stdout,
+                    "{}:{}:{}-{}:{}\t{}",
+                    r.file, r.start.line, r.start.column, r.end.line, r.end.column, r.function
+                )?;
+            }
+        }
+        ListUncoveredFormat::Json => {
+            serde_json::to_writer(&mut stdout, &export)?;
+            writeln!(stdout)?;
+        }
+    }
+    stdout.flush()?;
+    Ok(())
+}
@@ -0,0 +1,136 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Advisory locking of the target directory, so that concurrent cargo-llvm-cov
+// invocations on the same workspace (e.g., editor integration and terminal)
+// don't remove or overwrite each other's profile data.
+//
+// `cargo llvm-cov collect` runs alongside the other invocations, so it doesn't
+// hold the lock of the target directory, and instead holds a separate lock
+// while merging profraw files into the rolling profdata (see collect.rs).
+//
+// On Unix-like platforms, this uses flock(2). On Windows, the lock file is
+// opened without sharing, which fails while another process has it open. In
+// both cases, the lock is released by the OS even if the process is killed.
+// This is no-op on other platforms, with a warning.
+
+use anyhow::Result;
+
+use crate::cargo::Workspace;
+
+/// The lock of the target directory, which is released when dropped.
+pub(crate) struct Lock {
+    #[cfg(any(unix, windows))]
+    _file: crate::fs::File,
+}
+
+/// Acquires the lock of the target directory, waiting for the other
+/// cargo-llvm-cov process to release it if necessary.
+pub(crate) fn lock(ws: &Workspace) -> Result<Lock> {
+    acquire(
+        ws,
+        &format!("{}.lock", ws.name),
+        "another cargo-llvm-cov process is using the same target directory",
+    )
+}
+
+/// Acquires the lock of the profraw files and the rolling profdata, waiting
+/// for `cargo llvm-cov collect` to finish merging them if necessary.
+pub(crate) fn lock_profile_data(ws: &Workspace) -> Result<Lock> {
+    acquire(
+        ws,
+        &format!("{}-collect.lock", ws.name),
+        "`cargo llvm-cov collect` is merging profile data in the same target directory",
+    )
+}
+
+#[cfg(unix)]
+fn acquire(ws: &Workspace, name: &str, reason: &str) -> Result<Lock> {
+    use anyhow::Context as _;
+    use rustix::{
+        fs::{flock, FlockOperation},
+        io::Errno,
+    };
+
+    use crate::fs;
+
+    fs::create_dir_all(&ws.target_dir)?;
+    let path = ws.target_dir.join(name);
+    let file = fs::File::create(&path)?;
+    match flock(file.file(), FlockOperation::NonBlockingLockExclusive) {
+        // If the file system doesn't support locking (e.g., some network file
+        // systems), continue without locking, as cargo does.
+        Ok(()) | Err(Errno::NOLCK | Errno::NOSYS | Errno::OPNOTSUPP) => {}
+        Err(Errno::WOULDBLOCK) => {
+            status!(
+                "Blocking",
+                "waiting for file lock on {path}; {reason} (pass --no-lock to run without locking)"
+            );
+            loop {
+                match flock(file.file(), FlockOperation::LockExclusive) {
+                    Ok(()) => break,
+                    Err(Errno::INTR) => {}
+                    Err(e) => return Err(e).with_context(|| format!("failed to lock {path}")),
+                }
+            }
+        }
+        Err(e) => return Err(e).with_context(|| format!("failed to lock {path}")),
+    }
+    Ok(Lock { _file: file })
+}
+
+#[cfg(windows)]
+fn acquire(ws: &Workspace, name: &str, reason: &str) -> Result<Lock> {
+    use std::{os::windows::fs::OpenOptionsExt as _, thread, time::Duration};
+
+    use anyhow::Context as _;
+
+    use crate::fs;
+
+    // https://learn.microsoft.com/en-us/windows/win32/debug/system-error-codes--0-499-
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    fs::create_dir_all(&ws.target_dir)?;
+    let path = ws.target_dir.join(name);
+    // fs::OpenOptions doesn't preserve the raw OS error, which is needed to
+    // distinguish the file opened by another process from other errors.
+    #[allow(clippy::disallowed_types)]
+    let open = || {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .share_mode(0)
+            .open(&path)
+    };
+    let mut blocking = false;
+    let file = loop {
+        match open() {
+            Ok(file) => break file,
+            Err(e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => {
+                // Unlike flock(2), there is no way to wait for the file to be
+                // closed, so poll it.
+                if !blocking {
+                    status!(
+                        "Blocking",
+                        "waiting for file lock on {path}; {reason} (pass --no-lock to run \
+                         without locking)"
+                    );
+                    blocking = true;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(e) => return Err(e).with_context(|| format!("failed to lock {path}")),
+        }
+    };
+    Ok(Lock { _file: fs::File::from_parts(file, path) })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn acquire(_ws: &Workspace, _name: &str, _reason: &str) -> Result<Lock> {
+    warn!(
+        "locking the target directory is not supported on this platform; concurrent \
+         cargo-llvm-cov invocations on the same workspace may interfere with each other"
+    );
+    Ok(Lock {})
+}
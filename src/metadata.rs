@@ -8,7 +8,7 @@ use anyhow::{format_err, Context as _, Result};
 use camino::Utf8PathBuf;
 use serde_json::{Map, Value};
 
-type Object = Map<String, Value>;
+pub(crate) type Object = Map<String, Value>;
 type ParseResult<T> = Result<T, &'static str>;
 
 /// An opaque unique identifier for referring to the package.
@@ -33,18 +33,26 @@ pub(crate) struct Metadata {
     /// The absolute path to the root of the workspace.
     pub(crate) workspace_root: Utf8PathBuf,
     pub(crate) target_directory: Utf8PathBuf,
+    /// The workspace-level metadata specified in `workspace.metadata` table.
+    pub(crate) workspace_metadata: Option<Object>,
 }
 
 impl Metadata {
     pub(crate) fn new(manifest_path: &Path, cargo: &OsStr) -> Result<Self> {
-        let mut cmd = cmd!(
-            cargo,
-            "metadata",
-            "--format-version=1",
-            "--no-deps",
-            "--manifest-path",
-            manifest_path
-        );
+        Self::read(manifest_path, cargo, true)
+    }
+
+    /// Returns metadata that also contains all feature-enabled dependencies.
+    pub(crate) fn with_deps(manifest_path: &Path, cargo: &OsStr) -> Result<Self> {
+        Self::read(manifest_path, cargo, false)
+    }
+
+    fn read(manifest_path: &Path, cargo: &OsStr, no_deps: bool) -> Result<Self> {
+        let mut cmd = cmd!(cargo, "metadata", "--format-version=1");
+        if no_deps {
+            cmd.arg("--no-deps");
+        }
+        cmd.arg("--manifest-path").arg(manifest_path);
         let json = cmd.read()?;
 
         let map = serde_json::from_str(&json)
@@ -67,6 +75,7 @@ impl Metadata {
             workspace_members,
             workspace_root: map.remove_string("workspace_root")?,
             target_directory: map.remove_string("target_directory")?,
+            workspace_metadata: map.remove_nullable("metadata", into_object)?,
         })
     }
 }
@@ -74,9 +83,13 @@ impl Metadata {
 pub(crate) struct Package {
     /// The name of the package.
     pub(crate) name: String,
+    /// The version of the package.
+    pub(crate) version: String,
     pub(crate) targets: Vec<Target>,
     /// Absolute path to this package's manifest.
     pub(crate) manifest_path: Utf8PathBuf,
+    /// The package-level metadata specified in `package.metadata` table.
+    pub(crate) metadata: Option<Object>,
 }
 
 impl Package {
@@ -86,25 +99,39 @@ impl Package {
         let id = map.remove_string("id")?;
         Ok((id, Self {
             name: map.remove_string("name")?,
+            version: map.remove_string("version")?,
             targets: map
                 .remove_array("targets")?
                 .into_iter()
                 .map(Target::from_value)
                 .collect::<Result<_, _>>()?,
             manifest_path: map.remove_string("manifest_path")?,
+            metadata: map.remove_nullable("metadata", into_object)?,
         }))
     }
 }
 
 pub(crate) struct Target {
     pub(crate) name: String,
+    /// The kinds of this target (e.g., `lib`, `bin`, `test`, `example`).
+    pub(crate) kind: Vec<String>,
+    /// Absolute path to the root source file of this target.
+    pub(crate) src_path: Utf8PathBuf,
 }
 
 impl Target {
     fn from_value(mut value: Value) -> ParseResult<Self> {
         let map = value.as_object_mut().ok_or("targets")?;
 
-        Ok(Self { name: map.remove_string("name")? })
+        Ok(Self {
+            name: map.remove_string("name")?,
+            kind: map
+                .remove_array("kind")?
+                .into_iter()
+                .map(|v| into_string(v).ok_or("kind"))
+                .collect::<Result<_, _>>()?,
+            src_path: map.remove_string("src_path")?,
+        })
     }
 }
 
@@ -142,7 +169,6 @@ fn into_object(value: Value) -> Option<Object> {
 trait ObjectExt {
     fn remove_string<S: From<String>>(&mut self, key: &'static str) -> ParseResult<S>;
     fn remove_array(&mut self, key: &'static str) -> ParseResult<Vec<Value>>;
-    fn remove_object(&mut self, key: &'static str) -> ParseResult<Object>;
     fn remove_nullable<T>(
         &mut self,
         key: &'static str,
@@ -157,9 +183,6 @@ impl ObjectExt for Object {
     fn remove_array(&mut self, key: &'static str) -> ParseResult<Vec<Value>> {
         self.remove(key).and_then(into_array).ok_or(key)
     }
-    fn remove_object(&mut self, key: &'static str) -> ParseResult<Object> {
-        self.remove(key).and_then(into_object).ok_or(key)
-    }
     fn remove_nullable<T>(
         &mut self,
         key: &'static str,
@@ -0,0 +1,760 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --msgpack support.
+//
+// This writes the report of --json in the MessagePack format, which is much
+// smaller and faster to parse than JSON for large workspaces, and readable
+// with any MessagePack library. The data model is the same as the JSON: structs
+// and maps are maps with string keys, sequences and tuples are arrays, `None`
+// and unit are nil, unit variants are strings, and other variants are maps of
+// one entry (`{variant: value}`). Integers use the smallest encoding that
+// fits the value.
+//
+// Refs:
+// - https://github.com/msgpack/msgpack/blob/master/spec.md
+
+use std::{fmt, io};
+
+use serde::ser::{self, Serialize};
+
+/// Serializes the given value as MessagePack into the writer.
+pub(crate) fn to_writer(writer: impl io::Write, value: &impl Serialize) -> Result<(), Error> {
+    let mut serializer = Serializer { writer };
+    value.serialize(&mut serializer)?;
+    serializer.writer.flush()?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub(crate) struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self(e.to_string())
+    }
+}
+
+struct Serializer<W> {
+    writer: W,
+}
+
+impl<W: io::Write> Serializer<W> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.writer.write_all(bytes)?;
+        Ok(())
+    }
+
+    fn write_uint(&mut self, n: u64) -> Result<(), Error> {
+        let b = n.to_be_bytes();
+        if n <= 0x7f {
+            self.write(&b[7..])
+        } else if u8::try_from(n).is_ok() {
+            self.write(&[0xcc, b[7]])
+        } else if u16::try_from(n).is_ok() {
+            self.write(&[0xcd, b[6], b[7]])
+        } else if u32::try_from(n).is_ok() {
+            self.write(&[0xce])?;
+            self.write(&b[4..])
+        } else {
+            self.write(&[0xcf])?;
+            self.write(&b)
+        }
+    }
+
+    fn write_int(&mut self, n: i64) -> Result<(), Error> {
+        if let Ok(n) = u64::try_from(n) {
+            return self.write_uint(n);
+        }
+        // The low bytes of a negative number in two's complement are the
+        // representation of the number in the smaller type.
+        let b = n.to_be_bytes();
+        if n >= -32 {
+            self.write(&b[7..])
+        } else if i8::try_from(n).is_ok() {
+            self.write(&[0xd0, b[7]])
+        } else if i16::try_from(n).is_ok() {
+            self.write(&[0xd1, b[6], b[7]])
+        } else if i32::try_from(n).is_ok() {
+            self.write(&[0xd2])?;
+            self.write(&b[4..])
+        } else {
+            self.write(&[0xd3])?;
+            self.write(&b)
+        }
+    }
+
+    /// Writes the header of a str, bin, array, or map.
+    ///
+    /// `fix` is the marker of the fix variant (if any) and its maximum length,
+    /// and `markers` are the markers of the 8-bit (if any), 16-bit, and 32-bit variants.
+    fn write_header(
+        &mut self,
+        len: usize,
+        fix: Option<(u8, usize)>,
+        markers: (Option<u8>, u8, u8),
+    ) -> Result<(), Error> {
+        let Ok(len32) = u32::try_from(len) else {
+            return Err(Error(format!("length {len} is too large for MessagePack")));
+        };
+        let b = len32.to_be_bytes();
+        match (fix, markers.0) {
+            (Some((marker, max)), _) if len <= max => self.write(&[marker | b[3]]),
+            (_, Some(marker)) if u8::try_from(len).is_ok() => self.write(&[marker, b[3]]),
+            _ if u16::try_from(len).is_ok() => self.write(&[markers.1, b[2], b[3]]),
+            _ => {
+                self.write(&[markers.2])?;
+                self.write(&b)
+            }
+        }
+    }
+
+    fn write_str(&mut self, s: &str) -> Result<(), Error> {
+        self.write_header(s.len(), Some((0xa0, 31)), (Some(0xd9), 0xda, 0xdb))?;
+        self.write(s.as_bytes())
+    }
+
+    fn write_array_header(&mut self, len: usize) -> Result<(), Error> {
+        self.write_header(len, Some((0x90, 15)), (None, 0xdc, 0xdd))
+    }
+
+    fn write_map_header(&mut self, len: usize) -> Result<(), Error> {
+        self.write_header(len, Some((0x80, 15)), (None, 0xde, 0xdf))
+    }
+
+    fn compound(&mut self, len: Option<usize>, map: bool) -> Result<Compound<'_, W>, Error> {
+        match len {
+            Some(len) => {
+                if map {
+                    self.write_map_header(len)?;
+                } else {
+                    self.write_array_header(len)?;
+                }
+                Ok(Compound { ser: self, buffered: None })
+            }
+            // The length is written before the elements, so elements are
+            // buffered until the length is known.
+            None => Ok(Compound {
+                ser: self,
+                buffered: Some(Buffered { ser: Serializer { writer: vec![] }, count: 0, map }),
+            }),
+        }
+    }
+}
+
+struct Compound<'a, W> {
+    ser: &'a mut Serializer<W>,
+    buffered: Option<Buffered>,
+}
+
+struct Buffered {
+    ser: Serializer<Vec<u8>>,
+    count: usize,
+    map: bool,
+}
+
+impl<W: io::Write> Compound<'_, W> {
+    fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        match &mut self.buffered {
+            Some(buffered) => {
+                buffered.count += 1;
+                value.serialize(&mut buffered.ser)
+            }
+            None => value.serialize(&mut *self.ser),
+        }
+    }
+
+    fn key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        // Keys and values are counted as pairs.
+        self.element(key)?;
+        if let Some(buffered) = &mut self.buffered {
+            buffered.count -= 1;
+        }
+        Ok(())
+    }
+
+    fn value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        if let Some(buffered) = self.buffered {
+            if buffered.map {
+                self.ser.write_map_header(buffered.count)?;
+            } else {
+                self.ser.write_array_header(buffered.count)?;
+            }
+            self.ser.write(&buffered.ser.writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W: io::Write> ser::Serializer for &'a mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a, W>;
+    type SerializeTuple = Compound<'a, W>;
+    type SerializeTupleStruct = Compound<'a, W>;
+    type SerializeTupleVariant = Compound<'a, W>;
+    type SerializeMap = Compound<'a, W>;
+    type SerializeStruct = Compound<'a, W>;
+    type SerializeStructVariant = Compound<'a, W>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.write(&[if v { 0xc3 } else { 0xc2 }])
+    }
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.write_int(v.into())
+    }
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.write_int(v.into())
+    }
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.write_int(v.into())
+    }
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.write_int(v)
+    }
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.write_uint(v.into())
+    }
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.write_uint(v.into())
+    }
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.write_uint(v.into())
+    }
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.write_uint(v)
+    }
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.write(&[0xca])?;
+        self.write(&v.to_be_bytes())
+    }
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        self.write(&[0xcb])?;
+        self.write(&v.to_be_bytes())
+    }
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.write_str(v.encode_utf8(&mut [0; 4]))
+    }
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        self.write_str(v)
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        self.write_header(v.len(), None, (Some(0xc4), 0xc5, 0xc6))?;
+        self.write(v)
+    }
+    fn serialize_none(self) -> Result<(), Error> {
+        self.write(&[0xc0])
+    }
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.write(&[0xc0])
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.write(&[0xc0])
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        self.write_str(variant)
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.write_map_header(1)?;
+        self.write_str(variant)?;
+        value.serialize(self)
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        self.compound(len, false)
+    }
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Error> {
+        self.compound(Some(len), false)
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        self.compound(Some(len), false)
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        self.write_map_header(1)?;
+        self.write_str(variant)?;
+        self.compound(Some(len), false)
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        self.compound(len, true)
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        self.compound(Some(len), true)
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        self.write_map_header(1)?;
+        self.write_str(variant)?;
+        self.compound(Some(len), true)
+    }
+}
+
+impl<W: io::Write> ser::SerializeSeq for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeTuple for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeTupleStruct for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeTupleVariant for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeMap for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+        self.key(key)
+    }
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        self.value(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeStruct for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.key(key)?;
+        self.value(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeStructVariant for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.key(key)?;
+        self.value(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn encode(value: &impl Serialize) -> Vec<u8> {
+        let mut out = vec![];
+        to_writer(&mut out, value).unwrap();
+        out
+    }
+
+    /// A MessagePack value decoded by [`decode`].
+    #[derive(Debug, Clone, PartialEq)]
+    enum Value {
+        Nil,
+        Bool(bool),
+        Int(i128),
+        F32(f32),
+        F64(f64),
+        Str(String),
+        Bin(Vec<u8>),
+        Array(Vec<Value>),
+        Map(Vec<(Value, Value)>),
+        Ext(i8, Vec<u8>),
+    }
+
+    /// Decodes a MessagePack value that spans the whole input.
+    ///
+    /// This is written directly from the specification, independently of the
+    /// serializer, to check that the output is readable by other MessagePack
+    /// implementations.
+    fn decode(bytes: &[u8]) -> Value {
+        let mut rest = bytes;
+        let value = decode_value(&mut rest);
+        assert!(rest.is_empty(), "{} trailing bytes", rest.len());
+        value
+    }
+
+    fn decode_value(input: &mut &[u8]) -> Value {
+        fn take<'a>(input: &mut &'a [u8], n: usize) -> &'a [u8] {
+            assert!(input.len() >= n, "unexpected end of input");
+            let (bytes, rest) = input.split_at(n);
+            *input = rest;
+            bytes
+        }
+        fn uint(input: &mut &[u8], n: usize) -> u64 {
+            take(input, n).iter().fold(0, |acc, &b| acc << 8 | u64::from(b))
+        }
+        fn int(input: &mut &[u8], n: usize) -> i128 {
+            // Sign-extend the big-endian two's complement number.
+            let bytes = take(input, n);
+            let mut b = if bytes[0] & 0x80 == 0 { [0; 8] } else { [0xff; 8] };
+            b[8 - n..].copy_from_slice(bytes);
+            i64::from_be_bytes(b).into()
+        }
+        fn str(input: &mut &[u8], len: usize) -> Value {
+            Value::Str(String::from_utf8(take(input, len).to_vec()).unwrap())
+        }
+        fn array(input: &mut &[u8], len: usize) -> Value {
+            Value::Array((0..len).map(|_| decode_value(input)).collect())
+        }
+        fn map(input: &mut &[u8], len: usize) -> Value {
+            Value::Map((0..len).map(|_| (decode_value(input), decode_value(input))).collect())
+        }
+        fn ext(input: &mut &[u8], len: usize) -> Value {
+            let ty = i8::from_be_bytes([take(input, 1)[0]]);
+            Value::Ext(ty, take(input, len).to_vec())
+        }
+        let len = |input: &mut &[u8], n| usize::try_from(uint(input, n)).unwrap();
+
+        let marker = take(input, 1)[0];
+        match marker {
+            0x00..=0x7f => Value::Int(marker.into()),
+            0x80..=0x8f => map(input, usize::from(marker & 0x0f)),
+            0x90..=0x9f => array(input, usize::from(marker & 0x0f)),
+            0xa0..=0xbf => str(input, usize::from(marker & 0x1f)),
+            0xc0 => Value::Nil,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xc4..=0xc6 => {
+                let n = len(input, 1 << (marker - 0xc4));
+                Value::Bin(take(input, n).to_vec())
+            }
+            0xc7..=0xc9 => {
+                let n = len(input, 1 << (marker - 0xc7));
+                ext(input, n)
+            }
+            0xca => Value::F32(f32::from_bits(u32::try_from(uint(input, 4)).unwrap())),
+            0xcb => Value::F64(f64::from_bits(uint(input, 8))),
+            0xcc..=0xcf => Value::Int(uint(input, 1 << (marker - 0xcc)).into()),
+            0xd0..=0xd3 => Value::Int(int(input, 1 << (marker - 0xd0))),
+            0xd4..=0xd8 => ext(input, 1 << (marker - 0xd4)),
+            0xd9..=0xdb => {
+                let n = len(input, 1 << (marker - 0xd9));
+                str(input, n)
+            }
+            0xdc | 0xdd => {
+                let n = len(input, 2 << (marker - 0xdc));
+                array(input, n)
+            }
+            0xde | 0xdf => {
+                let n = len(input, 2 << (marker - 0xde));
+                map(input, n)
+            }
+            0xe0..=0xff => Value::Int(i8::from_be_bytes([marker]).into()),
+            0xc1 => panic!("never used marker 0xc1"),
+        }
+    }
+
+    /// Converts the decoded value to JSON, in the same way as the data model
+    /// described in the module documentation.
+    fn to_json(value: Value) -> serde_json::Value {
+        match value {
+            Value::Nil => serde_json::Value::Null,
+            Value::Bool(v) => v.into(),
+            Value::Int(v) => match u64::try_from(v) {
+                Ok(v) => v.into(),
+                Err(_) => i64::try_from(v).unwrap().into(),
+            },
+            Value::F32(v) => f64::from(v).into(),
+            Value::F64(v) => v.into(),
+            Value::Str(v) => v.into(),
+            Value::Array(v) => v.into_iter().map(to_json).collect(),
+            Value::Map(v) => v
+                .into_iter()
+                .map(|(k, v)| match k {
+                    Value::Str(k) => (k, to_json(v)),
+                    k => panic!("non-string key {k:?}"),
+                })
+                .collect(),
+            v @ (Value::Bin(_) | Value::Ext(..)) => panic!("unexpected {v:?} in JSON data"),
+        }
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(encode(&0_u64), [0x00]);
+        assert_eq!(encode(&127_u64), [0x7f]);
+        assert_eq!(encode(&128_u64), [0xcc, 0x80]);
+        assert_eq!(encode(&256_u64), [0xcd, 0x01, 0x00]);
+        assert_eq!(encode(&65536_u64), [0xce, 0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(encode(&u64::MAX), [0xcf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(encode(&-1_i64), [0xff]);
+        assert_eq!(encode(&-32_i64), [0xe0]);
+        assert_eq!(encode(&-33_i64), [0xd0, 0xdf]);
+        assert_eq!(encode(&-129_i64), [0xd1, 0xff, 0x7f]);
+        assert_eq!(encode(&-32769_i64), [0xd2, 0xff, 0xff, 0x7f, 0xff]);
+        assert_eq!(encode(&i64::MIN), [0xd3, 0x80, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(encode(&"abc"), [0xa3, b'a', b'b', b'c']);
+        assert_eq!(encode(&"a".repeat(32))[..2], [0xd9, 32]);
+        assert_eq!(encode(&(true, false, None::<u8>, 1.5_f64)), [
+            0x94, 0xc3, 0xc2, 0xc0, 0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0
+        ]);
+        assert_eq!(encode(&vec![0_u8; 16])[..3], [0xdc, 0x00, 0x10]);
+        let map: BTreeMap<&str, u8> = [("a", 1), ("b", 2)].into_iter().collect();
+        assert_eq!(encode(&map), [0x82, 0xa1, b'a', 0x01, 0xa1, b'b', 0x02]);
+        // serde_json::Value and the structs in the export are encoded in the same way.
+        let value = serde_json::json!({ "a": [1, -1, "x"], "b": null });
+        assert_eq!(encode(&value), [
+            0x82, 0xa1, b'a', 0x93, 0x01, 0xff, 0xa1, b'x', 0xa1, b'b', 0xc0
+        ]);
+    }
+
+    #[test]
+    fn unknown_length() {
+        struct UnknownLength;
+        impl Serialize for UnknownLength {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use ser::SerializeMap as _;
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("a", &[1, 2])?;
+                map.serialize_entry("b", &3)?;
+                map.end()
+            }
+        }
+        assert_eq!(encode(&UnknownLength), [0x82, 0xa1, b'a', 0x92, 0x01, 0x02, 0xa1, b'b', 0x03]);
+    }
+
+    #[test]
+    fn decoder() {
+        // Check the reference decoder itself with the examples in the specification.
+        assert_eq!(decode(&[0xd4, 0x01, 0x02]), Value::Ext(1, vec![2]));
+        let fixext16 = [0xd8, 0xff, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        assert_eq!(decode(&fixext16), Value::Ext(-1, (0..16).collect()));
+        assert_eq!(decode(&[0xc7, 0x03, 0x05, 1, 2, 3]), Value::Ext(5, vec![1, 2, 3]));
+        assert_eq!(decode(&[0xc8, 0x00, 0x01, 0x05, 1]), Value::Ext(5, vec![1]));
+        assert_eq!(decode(&[0xc9, 0, 0, 0, 0x00, 0x05]), Value::Ext(5, vec![]));
+        assert_eq!(decode(&[0xd3, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe]), Value::Int(-2));
+        assert_eq!(decode(&[0xca, 0x3f, 0xc0, 0, 0]), Value::F32(1.5));
+    }
+
+    #[test]
+    fn round_trip_integers() {
+        let unsigned = [
+            0,
+            127,
+            128,
+            255,
+            256,
+            65535,
+            65536,
+            u64::from(u32::MAX),
+            u64::from(u32::MAX) + 1,
+            i64::MAX as u64,
+            i64::MAX as u64 + 1,
+            u64::MAX,
+        ];
+        for n in unsigned {
+            assert_eq!(decode(&encode(&n)), Value::Int(n.into()), "{n}");
+        }
+        let signed = [
+            -1,
+            -32,
+            -33,
+            -128,
+            -129,
+            -32768,
+            -32769,
+            i64::from(i32::MIN),
+            i64::from(i32::MIN) - 1,
+            i64::MIN,
+            i64::MAX,
+        ];
+        for n in signed {
+            assert_eq!(decode(&encode(&n)), Value::Int(n.into()), "{n}");
+        }
+        for n in [i8::MIN, -1, 0, i8::MAX] {
+            assert_eq!(decode(&encode(&n)), Value::Int(n.into()));
+        }
+        for n in [i16::MIN, i16::MAX] {
+            assert_eq!(decode(&encode(&n)), Value::Int(n.into()));
+        }
+        for n in [i32::MIN, i32::MAX] {
+            assert_eq!(decode(&encode(&n)), Value::Int(n.into()));
+        }
+        for n in [u8::MAX.into(), u16::MAX.into(), u32::MAX] {
+            assert_eq!(decode(&encode(&n)), Value::Int(n.into()));
+        }
+    }
+
+    #[test]
+    fn round_trip_floats() {
+        // f32 and f64 are written as is, even if the value fits in the other type.
+        assert_eq!(decode(&encode(&1.5_f32)), Value::F32(1.5));
+        assert_eq!(decode(&encode(&1.5_f64)), Value::F64(1.5));
+        assert_eq!(decode(&encode(&0.1_f64)), Value::F64(0.1));
+        assert_eq!(decode(&encode(&f64::MAX)), Value::F64(f64::MAX));
+        assert_eq!(decode(&encode(&-0.0_f64)), Value::F64(-0.0));
+        assert_eq!(decode(&encode(&f64::INFINITY)), Value::F64(f64::INFINITY));
+        let Value::F64(nan) = decode(&encode(&f64::NAN)) else { panic!() };
+        assert!(nan.is_nan());
+        // Integers in JSON are integers, and other numbers are f64.
+        let value = serde_json::json!([100, 66.666_666_666_666_67, -1]);
+        let expected = [Value::Int(100), Value::F64(66.666_666_666_666_67), Value::Int(-1)];
+        assert_eq!(decode(&encode(&value)), Value::Array(expected.to_vec()));
+    }
+
+    #[test]
+    fn round_trip_lengths() {
+        // No output is decoded as ext types, whose markers are next to the
+        // markers of bin, str, and integers.
+        for len in [0, 1, 2, 4, 8, 15, 16, 31, 32, 255, 256, 65535, 65536] {
+            let s = "a".repeat(len);
+            assert_eq!(decode(&encode(&s)), Value::Str(s), "{len}");
+            let bytes = serde_bytes(len);
+            assert_eq!(decode(&encode(&bytes)), Value::Bin(vec![0; len]), "{len}");
+            let array = vec![0_u8; len];
+            assert_eq!(decode(&encode(&array)), Value::Array(vec![Value::Int(0); len]), "{len}");
+        }
+        for len in [15, 16, 65535, 65536, 70000] {
+            let map: BTreeMap<u32, bool> = (0..len).map(|i| (i, true)).collect();
+            let Value::Map(entries) = decode(&encode(&map)) else { panic!() };
+            assert_eq!(entries.len(), map.len());
+            assert_eq!(entries.last(), Some(&(Value::Int((len - 1).into()), Value::Bool(true))));
+        }
+        assert_eq!(encode(&BTreeMap::<u32, bool>::new()), [0x80]);
+        let map: BTreeMap<u32, bool> = (0..65536).map(|i| (i, true)).collect();
+        assert_eq!(encode(&map)[..5], [0xdf, 0x00, 0x01, 0x00, 0x00]);
+    }
+
+    /// Returns a value serialized with `serialize_bytes`.
+    fn serde_bytes(len: usize) -> impl Serialize {
+        struct Bytes(Vec<u8>);
+        impl Serialize for Bytes {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(&self.0)
+            }
+        }
+        Bytes(vec![0; len])
+    }
+
+    #[test]
+    fn round_trip_unknown_length() {
+        // More entries than the 16-bit map header can hold, with the length
+        // only known after all entries are serialized.
+        struct UnknownLength(u32);
+        impl Serialize for UnknownLength {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use ser::SerializeMap as _;
+                let mut map = serializer.serialize_map(None)?;
+                for i in 0..self.0 {
+                    map.serialize_entry(&i, &[i])?;
+                }
+                map.end()
+            }
+        }
+        let Value::Map(entries) = decode(&encode(&UnknownLength(70000))) else { panic!() };
+        assert_eq!(entries.len(), 70000);
+        assert_eq!(entries[69999], (Value::Int(69999), Value::Array(vec![Value::Int(69999)])));
+    }
+
+    #[test]
+    fn round_trip_export() {
+        let file = format!("{}/tests/fixtures/show-missing-lines.json", env!("CARGO_MANIFEST_DIR"));
+        let s = fs_err::read_to_string(file).unwrap();
+        let json = serde_json::from_str::<crate::json::LlvmCovJsonExport>(&s).unwrap();
+        assert_eq!(to_json(decode(&encode(&json))), serde_json::to_value(&json).unwrap());
+    }
+}
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Support for running tests from nextest archives, possibly on another machine.
+//
+// `cargo llvm-cov nextest-archive` records the options that affect where the
+// test binaries are and how the paths in the coverage data look, and adds them
+// to the archive using nextest's archive.include config. `cargo llvm-cov nextest
+// --archive-file` and `cargo llvm-cov report --nextest-archive-file` apply them
+// after the archive is extracted to the target directory.
+//
+// Refs:
+// - https://nexte.st/docs/ci-features/archiving
+// - https://nexte.st/docs/configuration/#tool-specific-configuration
+
+use anyhow::{Context as _, Result};
+use camino::Utf8PathBuf;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{cli::Subcommand, context::Context, fs, term};
+
+/// The directory in the target directory that is included in the archive.
+const DIR: &str = "llvm-cov-archive";
+const OPTIONS_FILE: &str = "options.json";
+
+/// The options used to build the test binaries in the archive.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Options {
+    /// The workspace root on the machine where the archive was created.
+    workspace_root: String,
+    target: Option<String>,
+    release: bool,
+    profile: Option<String>,
+    remap_path_prefix: bool,
+}
+
+/// Returns `true` if the test binaries in a nextest archive are used.
+pub(crate) fn is_used(cx: &Context) -> bool {
+    cx.args.subcommand == Subcommand::Nextest
+        && cx.args.cargo_args.iter().any(|a| a == "--archive-file")
+        || cx.args.nextest_archive_file.is_some()
+}
+
+/// Writes the options to be included in the archive, and returns the path to
+/// the nextest tool config file that includes them in the archive.
+pub(crate) fn prepare(cx: &Context) -> Result<Utf8PathBuf> {
+    let dir = cx.ws.target_dir.join(DIR);
+    fs::create_dir_all(&dir)?;
+    let options = Options {
+        workspace_root: cx.ws.metadata.workspace_root.to_string(),
+        target: cx.args.target.clone(),
+        release: cx.args.release,
+        profile: cx.args.profile.clone(),
+        remap_path_prefix: cx.args.remap_path_prefix,
+    };
+    fs::write(dir.join(OPTIONS_FILE), serde_json::to_vec_pretty(&options)?)?;
+    let config = dir.join("nextest.toml");
+    fs::write(
+        &config,
+        format!(
+            "[profile.default]\narchive.include = [\n    {{ path = \"{DIR}\", relative-to = \"target\" }},\n]\n"
+        ),
+    )?;
+    Ok(config)
+}
+
+/// Extracts the archive specified by --nextest-archive-file if it has not been
+/// extracted to the target directory yet.
+fn extract(cx: &Context, archive_file: &str) -> Result<()> {
+    if cx.ws.target_dir.join("target").exists() {
+        return Ok(());
+    }
+    let mut cargo = cx.cargo();
+    // binaries-only doesn't run the test binaries, so no profile data is written.
+    cargo
+        .args(["nextest", "list", "--list-type", "binaries-only", "--archive-file", archive_file])
+        .arg("--workspace-remap")
+        .arg(&cx.ws.metadata.workspace_root)
+        .arg("--extract-to")
+        .arg(&cx.ws.target_dir)
+        .arg("--extract-overwrite");
+    if term::verbose() {
+        status!("Running", "{cargo}");
+    }
+    cargo.run_with_output()?;
+    Ok(())
+}
+
+/// Applies the options recorded in the extracted archive, unless they are
+/// specified explicitly.
+pub(crate) fn load(cx: &mut Context) -> Result<()> {
+    if let Some(archive_file) = cx.args.nextest_archive_file.clone() {
+        extract(cx, &archive_file).context("failed to extract nextest archive")?;
+    }
+    let path = cx.ws.target_dir.join("target").join(DIR).join(OPTIONS_FILE);
+    if !path.exists() {
+        // The archive was created by `cargo nextest archive` or an old cargo-llvm-cov.
+        warn!(
+            "the nextest archive doesn't contain the options used to build the test binaries; \
+             consider creating the archive with `cargo llvm-cov nextest-archive`"
+        );
+        return Ok(());
+    }
+    let options: Options = serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("failed to parse {path}"))?;
+    if cx.args.target.is_none() {
+        cx.args.target = options.target;
+    }
+    if !cx.args.release && cx.args.profile.is_none() {
+        cx.args.release = options.release;
+        cx.args.profile = options.profile;
+    }
+    cx.args.remap_path_prefix |= options.remap_path_prefix;
+    if !cx.args.remap_path_prefix && options.workspace_root != cx.ws.metadata.workspace_root {
+        // The paths in the coverage data are the ones on the machine where the
+        // archive was created.
+        cx.path_map.push(options.workspace_root.clone(), cx.ws.metadata.workspace_root.to_string());
+        cx.archived_workspace_root = Some(options.workspace_root.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --discard-failed-attempts support.
+//
+// When nextest retries flaky tests, the profile data of failed attempts is
+// counted in addition to that of the final attempt, so the coverage can differ
+// between runs depending on how many times tests are retried.
+//
+// To count only the final attempt of each test, cargo-llvm-cov is set as the
+// target runner of nextest, and each execution of a test binary writes its profile
+// data into its own directory (<target_dir>/<ws>-attempts/<key>/<index>, where
+// <key> is computed from the test binary and its arguments, and <index> is the
+// order of the executions of the same test). After nextest finished, the profile
+// data of the first passed attempt (or the last attempt if all attempts failed)
+// of each test is moved to the target directory and the others are discarded.
+//
+// The same mechanism is used by --emit-test-contribution to know which profile
+// data files were written by which test. In that case, all attempts are kept
+// unless --discard-failed-attempts is also used.
+//
+// Refs:
+// - https://nexte.st/docs/features/retries
+// - https://nexte.st/docs/features/target-runners
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    ffi::OsString,
+    hash::{Hash as _, Hasher as _},
+    io,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+use anyhow::{bail, Context as _, Result};
+use camino::Utf8PathBuf;
+
+use crate::{context::Context, env, fs};
+
+/// The name of the internal subcommand used as the target runner.
+const SUBCOMMAND: &str = "nextest-runner";
+/// The environment variable to pass the directory and the name of profile data
+/// files to the runner. (value: `<dir>/<ws>`)
+const ATTEMPTS_ENV: &str = "__CARGO_LLVM_COV_ATTEMPTS";
+const STATUS_FILE: &str = "status";
+/// The file that records the test binary and the name of the test run by the
+/// attempts in the same directory.
+const TEST_FILE: &str = "test";
+
+/// The profile data files written by a test.
+pub(crate) struct TestProfile {
+    /// The name of the test binary, without the hash suffix.
+    pub(crate) binary: String,
+    pub(crate) name: String,
+    pub(crate) profraw_files: Vec<PathBuf>,
+}
+
+fn attempts_dir(cx: &Context) -> Utf8PathBuf {
+    cx.ws.target_dir.join(format!("{}-attempts", cx.ws.name))
+}
+
+/// Returns environment variables to use cargo-llvm-cov as the target runner of nextest.
+pub(crate) fn env(cx: &Context) -> Result<Vec<(String, String)>> {
+    let flag = if cx.args.discard_failed_attempts {
+        "--discard-failed-attempts"
+    } else {
+        "--emit-test-contribution"
+    };
+    let target = &cx.ws.target_for_config;
+    if cx.ws.config.runner(target)?.is_some() {
+        bail!("{flag} may not be used together with target runner");
+    }
+    let exe = cx.current_exe.to_string_lossy();
+    if exe.contains(char::is_whitespace) {
+        bail!("{flag} requires the path to cargo-llvm-cov without whitespace");
+    }
+    let dir = attempts_dir(cx);
+    fs::remove_dir_all(&dir)?;
+    fs::create_dir_all(&dir)?;
+    let runner_env =
+        format!("CARGO_TARGET_{}_RUNNER", target.triple().to_uppercase().replace(['-', '.'], "_"));
+    Ok(vec![
+        (runner_env, format!("{exe} llvm-cov {SUBCOMMAND}")),
+        (ATTEMPTS_ENV.to_owned(), dir.join(&cx.ws.name).into_string()),
+    ])
+}
+
+/// Runs the test binary if cargo-llvm-cov is invoked as the target runner.
+///
+/// Returns `Ok(false)` if cargo-llvm-cov is not invoked as the target runner.
+pub(crate) fn try_run() -> Result<bool> {
+    let mut args = std::env::args_os().skip(1);
+    if args.next().as_deref() != Some("llvm-cov".as_ref())
+        || args.next().as_deref() != Some(SUBCOMMAND.as_ref())
+    {
+        return Ok(false);
+    }
+    let Some(attempts) = env::var(ATTEMPTS_ENV)? else { return Ok(false) };
+    let Some(binary) = args.next() else { bail!("{SUBCOMMAND}: test binary is not specified") };
+    let args: Vec<OsString> = args.collect();
+
+    let mut hasher = DefaultHasher::new();
+    binary.hash(&mut hasher);
+    args.hash(&mut hasher);
+    let attempts = Path::new(&attempts);
+    let (dir, name) = (attempts.parent().unwrap(), attempts.file_name().unwrap());
+    let dir = dir.join(format!("{:016x}", hasher.finish()));
+    fs::create_dir_all(&dir)?;
+    // nextest runs each test as `<binary> [--exact] <name> --nocapture [--ignored]`.
+    let test = args.iter().find(|a| !a.to_string_lossy().starts_with('-'));
+    fs::write(
+        dir.join(TEST_FILE),
+        format!(
+            "{}\n{}",
+            Path::new(&binary).file_stem().unwrap_or_default().to_string_lossy(),
+            test.map(|t| t.to_string_lossy()).unwrap_or_default()
+        ),
+    )?;
+    // create_dir fails if the directory exists, so concurrent executions get distinct indices.
+    let mut i = 0_u32;
+    let attempt = loop {
+        let attempt = dir.join(i.to_string());
+        match fs::create_dir(&attempt) {
+            Ok(()) => break attempt,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => i += 1,
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    let mut profile_file = name.to_owned();
+    profile_file.push("-%p-%m.profraw");
+    let status = Command::new(&binary)
+        .args(&args)
+        .env("LLVM_PROFILE_FILE", attempt.join(profile_file))
+        .status()
+        .with_context(|| format!("failed to run {}", binary.to_string_lossy()))?;
+    fs::write(attempt.join(STATUS_FILE), if status.success() { "pass" } else { "fail" })?;
+    process::exit(status.code().unwrap_or(101))
+}
+
+/// Moves the profile data of the final attempt of each test (or all attempts if
+/// --discard-failed-attempts is not used) to the target directory, and removes
+/// the others.
+pub(crate) fn collect(cx: &mut Context) -> Result<()> {
+    let dir = attempts_dir(cx);
+    if !dir.exists() {
+        return Ok(());
+    }
+    let (mut retried, mut discarded) = (0, 0);
+    for test in fs::read_dir(&dir)? {
+        let test = test?.path();
+        let mut attempts: Vec<(u64, PathBuf)> = fs::read_dir(&test)?
+            .filter_map(|e| {
+                let path = e.ok()?.path();
+                Some((path.file_name()?.to_str()?.parse().ok()?, path))
+            })
+            .collect();
+        attempts.sort_unstable();
+        let passed = attempts.iter().position(|(_, path)| {
+            fs::read_to_string(path.join(STATUS_FILE)).is_ok_and(|s| s == "pass")
+        });
+        let kept = passed.unwrap_or(attempts.len().saturating_sub(1));
+        if attempts.len() > 1 {
+            retried += 1;
+            discarded += attempts.len() - 1;
+        }
+        let kept = if cx.args.discard_failed_attempts {
+            attempts.get(kept..=kept).unwrap_or_default()
+        } else {
+            &attempts
+        };
+        let mut profraw_files = vec![];
+        for (_, path) in kept {
+            for file in fs::read_dir(path)? {
+                let file = file?.path();
+                if file.extension().is_some_and(|e| e == "profraw") {
+                    let to = cx.ws.target_dir.as_std_path().join(file.file_name().unwrap());
+                    fs::rename(&file, &to)?;
+                    profraw_files.push(to);
+                }
+            }
+        }
+        if cx.args.emit_test_contribution.is_some() {
+            let test = fs::read_to_string(test.join(TEST_FILE))?;
+            let (binary, name) = test.split_once('\n').unwrap_or((&test, ""));
+            cx.test_profiles.push(TestProfile {
+                binary: binary_name(binary).to_owned(),
+                name: name.to_owned(),
+                profraw_files,
+            });
+        }
+    }
+    fs::remove_dir_all(&dir)?;
+    if retried != 0 && cx.args.discard_failed_attempts {
+        info!(
+            "discarded coverage of {discarded} attempts of {retried} retried tests; only the final \
+             attempt of each test is counted"
+        );
+    }
+    Ok(())
+}
+
+/// Removes the hash suffix (e.g., `-0123456789abcdef`) from the file name of
+/// the test binary.
+fn binary_name(file_name: &str) -> &str {
+    match file_name.rsplit_once('-') {
+        Some((name, hash)) if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            name
+        }
+        _ => file_name,
+    }
+}
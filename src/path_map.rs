@@ -0,0 +1,287 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --path-map support.
+//
+// llvm-cov's -path-equivalence only affects where source files are read from,
+// and the paths in the output are still the ones recorded in the coverage data.
+// So we also rewrite the paths in the exported data by ourselves.
+//
+// This is also used to strip verbatim prefixes of Windows paths if
+// --windows-long-paths is passed, and to resolve symlinks in the paths
+// according to --canonicalize-paths.
+//
+// --lcov-relative-to is applied after these mappings, and only to LCOV data.
+//
+// Refs:
+// - https://llvm.org/docs/CommandGuide/llvm-cov.html#cmdoption-llvm-cov-show-path-equivalence
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Error};
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{env, fs, json::LlvmCovJsonExport, windows_path};
+
+/// How to resolve symlinks in the paths of source files (--canonicalize-paths).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) enum CanonicalizePaths {
+    /// Use the paths recorded in the coverage data as is.
+    #[default]
+    Never,
+    /// Resolve all symlinks in the paths.
+    Always,
+    /// If the workspace is reached via a symlink from the current directory,
+    /// rewrite the paths in the workspace to be under the un-resolved path of
+    /// the workspace root.
+    Auto,
+}
+
+impl FromStr for CanonicalizePaths {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Self::Never),
+            "always" => Ok(Self::Always),
+            "auto" => Ok(Self::Auto),
+            _ => bail!("--canonicalize-paths must be never, always, or auto, but found `{s}`"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct PathMap {
+    /// List of (FROM, TO). TO is an absolute path.
+    maps: Vec<(String, String)>,
+    /// Whether to strip verbatim prefixes (`\\?\`) of Windows paths before mapping.
+    strip_verbatim: bool,
+    /// Whether to resolve all symlinks in the mapped paths.
+    canonicalize: bool,
+}
+
+impl PathMap {
+    pub(crate) fn new(path_map: &[String], current_dir: &Path, strip_verbatim: bool) -> Self {
+        let maps = path_map
+            .iter()
+            .map(|map| {
+                // The format has already been checked in cli.rs.
+                let (from, to) = map.split_once('=').unwrap();
+                // Normalize `.` components, so that the mapped paths look like the other paths.
+                let to: PathBuf = current_dir.join(to).components().collect();
+                (from.trim_end_matches(['/', '\\']).to_owned(), to.to_string_lossy().into_owned())
+            })
+            .collect();
+        Self { maps, strip_verbatim, canonicalize: false }
+    }
+
+    /// Applies --canonicalize-paths.
+    pub(crate) fn canonicalize_paths(&mut self, mode: CanonicalizePaths, workspace_root: &Path) {
+        match mode {
+            CanonicalizePaths::Never => {}
+            CanonicalizePaths::Always => self.canonicalize = true,
+            CanonicalizePaths::Auto => {
+                if let Some(logical_root) = logical_path(workspace_root) {
+                    self.maps.push((
+                        workspace_root.to_string_lossy().into_owned(),
+                        logical_root.to_string_lossy().into_owned(),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Adds a mapping from FROM to TO, which is an absolute path.
+    ///
+    /// Mappings added by this are used after the ones specified by --path-map.
+    pub(crate) fn push(&mut self, from: String, to: String) {
+        self.maps.push((from, to));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.maps.is_empty() && !self.strip_verbatim && !self.canonicalize
+    }
+
+    /// Returns flags to be passed to llvm-cov to read source files from the mapped paths.
+    pub(crate) fn llvm_cov_args(&self) -> impl Iterator<Item = String> + '_ {
+        self.maps.iter().map(|(from, to)| format!("-path-equivalence={from},{to}"))
+    }
+
+    /// Returns the mapped path, or `None` if no mapping matches the given path.
+    ///
+    /// If multiple mappings match, the first one is used.
+    pub(crate) fn map(&self, path: &str) -> Option<String> {
+        let stripped = if self.strip_verbatim { windows_path::strip_verbatim(path) } else { None };
+        let path = stripped.as_deref().unwrap_or(path);
+        let mapped = self.maps.iter().find_map(|(from, to)| {
+            if !starts_with(path, from) {
+                return None;
+            }
+            Some(format!("{to}{}", &path[from.len()..]))
+        });
+        let mapped = mapped.or_else(|| stripped.clone());
+        if self.canonicalize {
+            let path = mapped.as_deref().unwrap_or(path);
+            if let Ok(canonical) = fs::canonicalize(path) {
+                let canonical = canonical.to_string_lossy();
+                // On Windows, fs::canonicalize returns verbatim paths.
+                let canonical = windows_path::strip_verbatim(&canonical)
+                    .unwrap_or_else(|| canonical.into_owned());
+                if canonical != path {
+                    return Some(canonical);
+                }
+            }
+        }
+        mapped
+    }
+
+    /// Returns the path on the local file system of the given source file in
+    /// the coverage data.
+    ///
+    /// The path in the coverage data is relative to the workspace root if
+    /// --remap-path-prefix is used, and is mapped by --path-map unless it has
+    /// already been mapped (e.g., by [`apply_to_json`](Self::apply_to_json)).
+    pub(crate) fn source_path(&self, workspace_root: &Utf8Path, filename: &str) -> Utf8PathBuf {
+        let already_mapped = self.maps.iter().any(|(_, to)| starts_with(filename, to));
+        let mapped = if already_mapped { None } else { self.map(filename) };
+        workspace_root.join(mapped.as_deref().unwrap_or(filename))
+    }
+
+    pub(crate) fn apply_to_json(&self, json: &mut LlvmCovJsonExport) {
+        if !self.is_empty() {
+            json.map_file_names(|filename| self.map(filename));
+        }
+    }
+
+    /// Rewrites SF records of LCOV data.
+    pub(crate) fn apply_to_lcov(&self, lcov: String) -> String {
+        if self.is_empty() {
+            return lcov;
+        }
+        map_lcov_source_files(&lcov, |path| self.map(path))
+    }
+}
+
+/// Returns `true` if `path` is `prefix` or a path under it.
+fn starts_with(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '\\']))
+}
+
+/// Rewrites SF records of LCOV data to be relative to `dir` (--lcov-relative-to).
+///
+/// Relative paths in the data (e.g., with --remap-path-prefix) are relative to
+/// the workspace root. Paths outside `dir` are kept as is.
+pub(crate) fn lcov_relative_to(lcov: &str, workspace_root: &Path, dir: &Path) -> String {
+    // Normalize `.` components, so that paths like `./` match.
+    let dir: PathBuf = dir.components().collect();
+    map_lcov_source_files(lcov, |path| {
+        let path = workspace_root.join(path);
+        Some(path.strip_prefix(&dir).ok()?.to_string_lossy().into_owned())
+    })
+}
+
+fn map_lcov_source_files(lcov: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(lcov.len());
+    for line in lcov.split_inclusive('\n') {
+        match line.strip_prefix("SF:") {
+            Some(path) => {
+                let (path, newline) = match path.strip_suffix('\n') {
+                    Some(path) => (path, "\n"),
+                    None => (path, ""),
+                };
+                out.push_str("SF:");
+                out.push_str(&f(path).unwrap_or_else(|| path.to_owned()));
+                out.push_str(newline);
+            }
+            None => out.push_str(line),
+        }
+    }
+    out
+}
+
+/// Returns the path through which the given directory is reached from the
+/// current directory, if it differs from the given path because of symlinks.
+///
+/// `env::current_dir` returns the path with symlinks resolved, so this uses
+/// `PWD` set by the shell, which keeps the path the user typed.
+fn logical_path(dir: &Path) -> Option<PathBuf> {
+    let pwd = PathBuf::from(env::var_os("PWD")?);
+    let dir_canonical = fs::canonicalize(dir).ok()?;
+    // PWD may be stale (e.g., if the current directory has been changed by the parent process).
+    if !fs::canonicalize(&pwd).is_ok_and(|p| p.starts_with(&dir_canonical)) {
+        return None;
+    }
+    let logical =
+        pwd.ancestors().find(|a| fs::canonicalize(a).is_ok_and(|a| a == dir_canonical))?.to_owned();
+    (logical != dir).then_some(logical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn map() {
+        let map = PathMap::new(
+            &["/build/src=.".to_owned(), "/nix/store/abc-foo/=vendor/foo".to_owned()],
+            Path::new("/home/user/foo"),
+            false,
+        );
+        assert_eq!(map.map("/build/src/lib.rs").as_deref(), Some("/home/user/foo/lib.rs"));
+        assert_eq!(map.map("/build/src").as_deref(), Some("/home/user/foo"));
+        assert_eq!(map.map("/build/srcs/lib.rs"), None);
+        assert_eq!(
+            map.map("/nix/store/abc-foo/src/lib.rs").as_deref(),
+            Some("/home/user/foo/vendor/foo/src/lib.rs")
+        );
+        assert_eq!(
+            map.apply_to_lcov("SF:/build/src/a.rs\nDA:1,1\nend_of_record\nSF:/other.rs".to_owned()),
+            "SF:/home/user/foo/a.rs\nDA:1,1\nend_of_record\nSF:/other.rs"
+        );
+        let map = PathMap::new(&[r"C:\build=.".to_owned()], Path::new("/home/user/foo"), true);
+        assert_eq!(map.map(r"\\?\C:\build\a.rs").as_deref(), Some(r"/home/user/foo\a.rs"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn source_path() {
+        let root = Utf8Path::new("/home/user/foo");
+        let map = PathMap::new(&["/build=/build/checkout".to_owned()], Path::new("/"), false);
+        assert_eq!(map.source_path(root, "src/lib.rs"), "/home/user/foo/src/lib.rs");
+        assert_eq!(map.source_path(root, "/build/src/lib.rs"), "/build/checkout/src/lib.rs");
+        // Paths that have already been mapped are not mapped again.
+        assert_eq!(
+            map.source_path(root, "/build/checkout/src/lib.rs"),
+            "/build/checkout/src/lib.rs"
+        );
+        assert_eq!(PathMap::default().source_path(root, "/a.rs"), "/a.rs");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn relative_to() {
+        let lcov = "SF:/home/user/foo/src/a.rs\nend_of_record\nSF:src/b.rs\nend_of_record\n\
+                    SF:/home/user/.cargo/registry/c.rs\nend_of_record\n";
+        assert_eq!(
+            lcov_relative_to(lcov, Path::new("/home/user/foo"), Path::new("/home/user/foo/./")),
+            "SF:src/a.rs\nend_of_record\nSF:src/b.rs\nend_of_record\n\
+             SF:/home/user/.cargo/registry/c.rs\nend_of_record\n"
+        );
+        assert_eq!(
+            lcov_relative_to(lcov, Path::new("/home/user/foo"), Path::new("/home/user")),
+            "SF:foo/src/a.rs\nend_of_record\nSF:foo/src/b.rs\nend_of_record\n\
+             SF:.cargo/registry/c.rs\nend_of_record\n"
+        );
+    }
+
+    #[test]
+    fn strip_verbatim() {
+        let map = PathMap::new(&[], Path::new("/home/user/foo"), true);
+        assert!(!map.is_empty());
+        assert_eq!(map.map(r"\\?\C:\foo\a.rs").as_deref(), Some(r"C:\foo\a.rs"));
+        assert_eq!(map.map(r"C:\foo\a.rs"), None);
+    }
+}
@@ -0,0 +1,438 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --report-per-binary, --summary-by-target-kind, and --junit support.
+//
+// All test binaries write profile data to the same directory, so the profraw
+// files are grouped by the binary that generated them using the binary IDs
+// (GNU build IDs) recorded in them, and each group is merged into a separate
+// profdata file. Then the report is generated for each binary from only that
+// binary and its profdata file, so that the reports show which code each test
+// suite actually exercises. Similarly, the coverage of each kind of target
+// (unit tests, integration tests, doctests, etc.) is computed from the binaries
+// of that kind and the profile data generated by them.
+//
+// The profiling runtime currently records binary IDs only on ELF targets.
+// https://github.com/llvm/llvm-project/blob/llvmorg-18.1.8/compiler-rt/lib/profile/InstrProfilingPlatformLinux.c
+
+use std::{collections::BTreeMap, ffi::OsString, fmt::Write as _, mem, path::Path};
+
+use anyhow::{bail, Context as _, Result};
+use camino::Utf8PathBuf;
+
+use crate::{
+    context::Context,
+    fs,
+    function_filter::FunctionFilter,
+    json::{SummaryJsonExport, SummaryTargetKind, SummaryTotals},
+    Format,
+};
+
+const SHT_NOTE: u64 = 7;
+const NT_GNU_BUILD_ID: u64 = 3;
+
+struct Binary<'a> {
+    name: String,
+    object_file: &'a OsString,
+    profraw_files: Vec<String>,
+}
+
+/// Generates the report of the given format for each binary that has profile data.
+pub(crate) fn generate_reports(
+    cx: &mut Context,
+    format: Format,
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+    function_filter: Option<&FunctionFilter>,
+) -> Result<()> {
+    if cx.args.cov.output_path.is_none() {
+        let flag = match format {
+            Format::Json => Some("--json"),
+            Format::LCov => Some("--lcov"),
+            Format::Cobertura => Some("--cobertura"),
+            Format::Codecov => Some("--codecov"),
+            Format::SummaryJson => Some("--summary-json"),
+            Format::Exec => Some("--exec-formatter"),
+            Format::None | Format::Text | Format::Html => None,
+        };
+        if let Some(flag) = flag {
+            bail!("--report-per-binary requires --output-path when used together with {flag}");
+        }
+    }
+
+    let binaries = binaries(cx, object_files)?;
+    let output_path = cx.args.cov.output_path.clone();
+    let output_dir = cx.args.cov.output_dir.clone();
+    for binary in &binaries {
+        let profdata_file = merge(cx, &binary.name, &binary.profraw_files)?;
+
+        cx.args.cov.output_path = output_path.as_ref().map(|path| {
+            let mut file_name = path.file_stem().unwrap_or_default().to_owned();
+            file_name.push('-');
+            file_name.push_str(&binary.name);
+            if let Some(ext) = path.extension() {
+                file_name.push('.');
+                file_name.push_str(ext);
+            }
+            path.with_file_name(file_name)
+        });
+        cx.args.cov.output_dir =
+            output_dir.as_ref().map(|dir| dir.join("per-binary").join(&binary.name));
+        if cx.args.cov.output_path.is_none()
+            && (format == Format::None || format == Format::Text && output_dir.is_none())
+        {
+            println!("\n{} ({}):", binary.name, Path::new(binary.object_file).display());
+        }
+        let profdata_file = mem::replace(&mut cx.ws.profdata_file, profdata_file);
+        let res = format.generate_report(
+            cx,
+            std::slice::from_ref(binary.object_file),
+            ignore_filename_regex,
+            function_filter,
+        );
+        cx.ws.profdata_file = profdata_file;
+        res.with_context(|| format!("failed to generate report for {}", binary.name))?;
+    }
+    cx.args.cov.output_path = output_path;
+    cx.args.cov.output_dir = output_dir;
+    Ok(())
+}
+
+/// Returns the coverage collected by running each kind of target.
+pub(crate) fn target_kinds(
+    cx: &mut Context,
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+    function_filter: Option<&FunctionFilter>,
+) -> Result<Vec<SummaryTargetKind>> {
+    let mut kinds: BTreeMap<&str, (Vec<OsString>, Vec<String>)> = BTreeMap::new();
+    for binary in binaries(cx, object_files)? {
+        let (object_files, profraw_files) =
+            kinds.entry(target_kind(cx, Path::new(binary.object_file))).or_default();
+        object_files.push(binary.object_file.clone());
+        profraw_files.extend(binary.profraw_files);
+    }
+    let mut target_kinds = vec![];
+    for (kind, (object_files, profraw_files)) in kinds {
+        let profdata_file = merge(cx, &format!("kind-{kind}"), &profraw_files)?;
+        let profdata_file = mem::replace(&mut cx.ws.profdata_file, profdata_file);
+        let res =
+            Format::Json.get_json(cx, &object_files, ignore_filename_regex, function_filter, true);
+        cx.ws.profdata_file = profdata_file;
+        let json = res.with_context(|| format!("failed to get json for {kind}"))?;
+        let totals = SummaryJsonExport::from_llvm_cov_json_export(&json, |_| None)?.totals;
+        target_kinds.push(SummaryTargetKind { kind: kind.to_owned(), totals });
+    }
+    Ok(target_kinds)
+}
+
+/// Returns the coverage collected by running each binary, keyed by the file
+/// name of the binary.
+pub(crate) fn binary_totals(
+    cx: &mut Context,
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+    function_filter: Option<&FunctionFilter>,
+) -> Result<BTreeMap<String, SummaryTotals>> {
+    let mut totals = BTreeMap::new();
+    for binary in binaries(cx, object_files)? {
+        let Some(file_name) = Path::new(binary.object_file).file_name() else { continue };
+        let profdata_file = merge(cx, &binary.name, &binary.profraw_files)?;
+        let profdata_file = mem::replace(&mut cx.ws.profdata_file, profdata_file);
+        let res = Format::Json.get_json(
+            cx,
+            std::slice::from_ref(binary.object_file),
+            ignore_filename_regex,
+            function_filter,
+            true,
+        );
+        cx.ws.profdata_file = profdata_file;
+        let json = res.with_context(|| format!("failed to get json for {}", binary.name))?;
+        totals.insert(
+            file_name.to_string_lossy().into_owned(),
+            SummaryJsonExport::from_llvm_cov_json_export(&json, |_| None)?.totals,
+        );
+    }
+    Ok(totals)
+}
+
+/// Returns the kind of the target that the given binary was built from.
+fn target_kind(cx: &Context, path: &Path) -> &'static str {
+    let stem = fs::file_stem_recursive(path).unwrap_or_default().to_string_lossy();
+    if stem == "rust_out" {
+        return "doctests";
+    }
+    match path.parent().and_then(Path::file_name).and_then(|dir| dir.to_str()) {
+        Some("examples") => "examples",
+        Some("deps") => {
+            // Test binaries are `deps/<target-name>-<hash>`, where `-` in the
+            // target name is replaced with `_`.
+            let name = binary_name(path, false);
+            let kinds = cx
+                .ws
+                .metadata
+                .workspace_members
+                .iter()
+                .flat_map(|id| &cx.ws.metadata.packages[id].targets)
+                .find(|t| t.name.replace('-', "_") == name)
+                .map(|t| &t.kind);
+            match kinds {
+                Some(kinds) if kinds.iter().any(|k| k == "test") => "integration-tests",
+                Some(kinds) if kinds.iter().any(|k| k == "bench") => "benches",
+                Some(_) => "unit-tests",
+                None => "other",
+            }
+        }
+        // Binaries are hard linked to `<profile-dir>/<name>` and used there
+        // (e.g., via CARGO_BIN_EXE_<name>).
+        _ => "binaries",
+    }
+}
+
+/// Merges the given profraw files into a separate profdata file and returns its path.
+fn merge(cx: &Context, name: &str, profraw_files: &[String]) -> Result<Utf8PathBuf> {
+    let profdata_file = cx.ws.target_dir.join(format!("{}-{name}.profdata", cx.ws.name));
+    let input_files: Vec<_> = profraw_files.iter().map(String::as_str).collect();
+    crate::merge_profdata_cmd(
+        cx,
+        &input_files,
+        &profdata_file,
+        &format!("profraw-list-{name}"),
+        false,
+    )?
+    .run()?;
+    Ok(profdata_file)
+}
+
+/// Returns the binaries that have profile data, along with their profraw files.
+fn binaries<'a>(cx: &Context, object_files: &'a [OsString]) -> Result<Vec<Binary<'a>>> {
+    let in_deps =
+        |f: &OsString| Path::new(f).parent().and_then(Path::file_name).is_some_and(|d| d == "deps");
+    let mut object_file_of_id: BTreeMap<String, &OsString> = BTreeMap::new();
+    for f in object_files {
+        let Ok(data) = fs::read(f) else { continue };
+        if let Some(id) = build_id(&data) {
+            // Binaries in the profile directory are hard links to the ones in
+            // the deps directory, so prefer the former, which is the one used
+            // by tests (e.g., via CARGO_BIN_EXE_<name>).
+            object_file_of_id
+                .entry(id)
+                .and_modify(|prev| {
+                    if in_deps(prev) && !in_deps(f) {
+                        *prev = f;
+                    }
+                })
+                .or_insert(f);
+        }
+    }
+
+    let mut binaries: BTreeMap<&OsString, Vec<String>> = BTreeMap::new();
+    for path in crate::profraw_files(cx)? {
+        let path = path
+            .into_os_string()
+            .into_string()
+            .map_err(|p| anyhow::format_err!("{p:?} contains invalid utf-8 data"))?;
+        let out = cx.process(&cx.llvm_profdata).args(["show", "--binary-ids"]).arg(&path).read()?;
+        let ids: Vec<_> = out
+            .lines()
+            .skip_while(|l| !l.starts_with("Binary IDs:"))
+            .skip(1)
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        if ids.is_empty() {
+            bail!(
+                "{path} doesn't contain binary IDs; --report-per-binary is currently only \
+                 supported on targets that use ELF binaries (e.g., Linux)"
+            );
+        }
+        // Profile data generated by binaries not included in the report
+        // (e.g., binaries of excluded packages) are ignored.
+        if let Some(f) = ids.iter().find_map(|id| object_file_of_id.get(*id)) {
+            binaries.entry(f).or_default().push(path);
+        }
+    }
+
+    let mut names: BTreeMap<String, usize> = BTreeMap::new();
+    let binaries: Vec<_> = binaries
+        .into_iter()
+        .map(|(object_file, profraw_files)| {
+            let name = binary_name(Path::new(object_file), false);
+            *names.entry(name.clone()).or_default() += 1;
+            Binary { name, object_file, profraw_files }
+        })
+        .collect();
+    // Use the name with the hash if the name without it is ambiguous (e.g., the
+    // unit tests of a library and the binary with the same name).
+    Ok(binaries
+        .into_iter()
+        .map(|mut binary| {
+            if names[&binary.name] > 1 {
+                binary.name = binary_name(Path::new(binary.object_file), true);
+            }
+            binary
+        })
+        .collect())
+}
+
+/// Returns the name of the given binary used in the file names of the reports.
+fn binary_name(path: &Path, with_hash: bool) -> String {
+    let stem = fs::file_stem_recursive(path).unwrap_or_default().to_string_lossy();
+    if stem == "rust_out" {
+        // Doctests are compiled to `<doctests-dir>/<test-name>/rust_out`.
+        if let Some(dir) = path.parent().and_then(Path::file_name) {
+            return dir.to_string_lossy().into_owned();
+        }
+    }
+    if !with_hash {
+        if let Some((name, hash)) = stem.rsplit_once('-') {
+            if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return name.to_owned();
+            }
+        }
+    }
+    stem.into_owned()
+}
+
+/// Returns the GNU build ID of the given ELF binary as a lowercase hex string.
+// https://refspecs.linuxfoundation.org/elf/gabi4+/ch4.sheader.html
+// https://refspecs.linuxfoundation.org/elf/gabi4+/ch5.pheader.html#note_section
+pub(crate) fn build_id(data: &[u8]) -> Option<String> {
+    if data.get(..4)? != b"\x7fELF" {
+        return None;
+    }
+    let word = match data.get(4)? {
+        1 => 4,
+        2 => 8,
+        _ => return None,
+    };
+    let little_endian = match data.get(5)? {
+        1 => true,
+        2 => false,
+        _ => return None,
+    };
+    let read = |offset: usize, size: usize| -> Option<usize> {
+        let bytes = data.get(offset..offset.checked_add(size)?)?;
+        let mut buf = [0; 8];
+        let v = if little_endian {
+            buf[..size].copy_from_slice(bytes);
+            u64::from_le_bytes(buf)
+        } else {
+            buf[8 - size..].copy_from_slice(bytes);
+            u64::from_be_bytes(buf)
+        };
+        usize::try_from(v).ok()
+    };
+    let align4 = |n: usize| n.checked_add(3).map(|n| n & !3);
+
+    let (shoff, shentsize, shnum) = if word == 8 {
+        (read(0x28, 8)?, read(0x3A, 2)?, read(0x3C, 2)?)
+    } else {
+        (read(0x20, 4)?, read(0x2E, 2)?, read(0x30, 2)?)
+    };
+    for i in 0..shnum {
+        let sh = shoff.checked_add(i.checked_mul(shentsize)?)?;
+        if read(sh + 4, 4)? as u64 != SHT_NOTE {
+            continue;
+        }
+        // sh_offset and sh_size follow sh_name, sh_type, sh_flags, and sh_addr.
+        let offset = read(sh + 8 + word * 2, word)?;
+        let end = offset.checked_add(read(sh + 8 + word * 3, word)?)?;
+        let mut pos = offset;
+        while pos.checked_add(12)? <= end {
+            let (namesz, descsz, ty) = (read(pos, 4)?, read(pos + 4, 4)?, read(pos + 8, 4)?);
+            let name = pos + 12;
+            let desc = name.checked_add(align4(namesz)?)?;
+            if ty as u64 == NT_GNU_BUILD_ID
+                && data.get(name..name.checked_add(namesz)?)? == b"GNU\0"
+            {
+                let mut id = String::with_capacity(descsz * 2);
+                for b in data.get(desc..desc.checked_add(descsz)?)? {
+                    let _ = write!(id, "{b:02x}");
+                }
+                return Some(id);
+            }
+            pos = desc.checked_add(align4(descsz)?)?;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a minimal ELF binary that only contains a note section with the
+    /// given build ID.
+    fn elf(is_64: bool, little_endian: bool, id: &[u8]) -> Vec<u8> {
+        let word = if is_64 { 8 } else { 4 };
+        let int = |v: usize, size: usize| -> Vec<u8> {
+            let v = v as u64;
+            if little_endian {
+                v.to_le_bytes()[..size].to_vec()
+            } else {
+                v.to_be_bytes()[8 - size..].to_vec()
+            }
+        };
+        let mut note = vec![];
+        note.extend(int(4, 4));
+        note.extend(int(id.len(), 4));
+        note.extend(int(3, 4));
+        note.extend(b"GNU\0");
+        note.extend(id);
+        let header_size = if is_64 { 0x40 } else { 0x34 };
+        let shentsize = if is_64 { 0x40 } else { 0x28 };
+        let note_offset = header_size;
+        let shoff = note_offset + note.len();
+
+        let mut data = vec![0; header_size];
+        data[..4].copy_from_slice(b"\x7fELF");
+        data[4] = if is_64 { 2 } else { 1 };
+        data[5] = if little_endian { 1 } else { 2 };
+        let (shoff_pos, shentsize_pos) = if is_64 { (0x28, 0x3A) } else { (0x20, 0x2E) };
+        data[shoff_pos..shoff_pos + word].copy_from_slice(&int(shoff, word));
+        data[shentsize_pos..shentsize_pos + 2].copy_from_slice(&int(shentsize, 2));
+        data[shentsize_pos + 2..shentsize_pos + 4].copy_from_slice(&int(2, 2));
+        data.extend(&note);
+        // The null section header.
+        data.extend(vec![0; shentsize]);
+        let mut sh = vec![0; shentsize];
+        sh[4..8].copy_from_slice(&int(7, 4));
+        sh[8 + word * 2..8 + word * 3].copy_from_slice(&int(note_offset, word));
+        sh[8 + word * 3..8 + word * 4].copy_from_slice(&int(note.len(), word));
+        data.extend(sh);
+        data
+    }
+
+    #[test]
+    fn test_build_id() {
+        let id = [0xc5, 0x61, 0x59, 0x35, 0x00, 0xff];
+        for is_64 in [true, false] {
+            for little_endian in [true, false] {
+                assert_eq!(
+                    build_id(&elf(is_64, little_endian, &id)).as_deref(),
+                    Some("c561593500ff")
+                );
+            }
+        }
+        assert_eq!(build_id(b"\x7fELF"), None);
+        assert_eq!(build_id(b"MZ"), None);
+        // Section headers are out of bounds.
+        let mut data = elf(true, true, &id);
+        data.truncate(0x40 + 20);
+        assert_eq!(build_id(&data), None);
+    }
+
+    #[test]
+    fn test_binary_name() {
+        assert_eq!(binary_name(Path::new("target/debug/deps/foo-0123456789abcdef"), false), "foo");
+        assert_eq!(
+            binary_name(Path::new("target/debug/deps/foo-0123456789abcdef"), true),
+            "foo-0123456789abcdef"
+        );
+        assert_eq!(binary_name(Path::new("target/debug/foo-bar"), false), "foo-bar");
+        assert_eq!(
+            binary_name(Path::new("target/doctests/src_lib_rs_3_0/rust_out"), false),
+            "src_lib_rs_3_0"
+        );
+    }
+}
@@ -0,0 +1,287 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --precision, --rounding, and --show-counts support.
+//
+// By default, percentages are shown with two decimal places, rounded to the
+// nearest, while thresholds (e.g., --fail-under-lines) are compared against the
+// exact value, so 79.996% is shown as 80.00% but fails --fail-under-lines 80.
+//
+// When either option is specified, percentages are rounded to the given number
+// of decimal places with the given mode as soon as they are computed, so the
+// shown value is the one compared against thresholds. For example, with
+// `--rounding floor`, 79.996% is shown as 79.99% and fails
+// --fail-under-lines 80. As llvm-cov always shows two decimal places rounded to
+// the nearest, the percentages in the summary table printed by llvm-cov and
+// the --html report are recomputed from the counts next to them.
+//
+// --show-counts shows the covered and total counts (e.g., `19/27`) next to or
+// instead of the percentages in summaries, since the number of lines left to
+// cover is often more meaningful than the percentage. Summaries that already
+// contain the counts (--summary-format json/csv and --summary-json) are not
+// affected.
+
+use std::{
+    borrow::Cow,
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+
+use anyhow::{bail, Error};
+use regex::{Captures, Regex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Rounding {
+    Nearest = 0,
+    Floor,
+}
+
+impl FromStr for Rounding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Self::Nearest),
+            "floor" => Ok(Self::Floor),
+            _ => bail!("--rounding must be nearest or floor, but found `{s}`"),
+        }
+    }
+}
+
+/// How --show-counts shows the counts in summaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Counts {
+    /// `<percent>% (<covered>/<count>)`
+    Both = 1,
+    /// `<covered>/<count>`
+    Only,
+}
+
+impl FromStr for Counts {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "both" => Ok(Self::Both),
+            "only" => Ok(Self::Only),
+            _ => bail!("--show-counts must be both or only, but found `{s}`"),
+        }
+    }
+}
+
+/// The maximum number of decimal places that --precision accepts.
+pub(crate) const MAX_PRECISION: u8 = 10;
+const DEFAULT_PRECISION: u8 = 2;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PRECISION: AtomicU8 = AtomicU8::new(DEFAULT_PRECISION);
+static ROUNDING: AtomicU8 = AtomicU8::new(Rounding::Nearest as _);
+static COUNTS: AtomicU8 = AtomicU8::new(0);
+
+pub(crate) fn set(precision: Option<u8>, rounding: Option<Rounding>) {
+    ENABLED.store(precision.is_some() || rounding.is_some(), Ordering::Relaxed);
+    PRECISION.store(precision.unwrap_or(DEFAULT_PRECISION), Ordering::Relaxed);
+    ROUNDING.store(rounding.unwrap_or(Rounding::Nearest) as _, Ordering::Relaxed);
+}
+
+pub(crate) fn set_counts(counts: Option<Counts>) {
+    COUNTS.store(counts.map_or(0, |c| c as _), Ordering::Relaxed);
+}
+
+/// Returns the value of --show-counts.
+pub(crate) fn counts() -> Option<Counts> {
+    match COUNTS.load(Ordering::Relaxed) {
+        1 => Some(Counts::Both),
+        2 => Some(Counts::Only),
+        _ => None,
+    }
+}
+
+/// Formats the coverage shown in summaries: the percentage with `%`,
+/// followed by or replaced with the counts if --show-counts is specified.
+pub(crate) fn format_summary(percent: f64, covered: u64, count: u64) -> String {
+    with_counts(counts(), &format(percent), covered, count)
+}
+
+fn with_counts(counts: Option<Counts>, percent: &str, covered: u64, count: u64) -> String {
+    match counts {
+        None => format!("{percent}%"),
+        Some(Counts::Both) => format!("{percent}% ({covered}/{count})"),
+        Some(Counts::Only) => format!("{covered}/{count}"),
+    }
+}
+
+/// Returns `true` if --precision or --rounding is specified.
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Rounds the given percentage if --precision or --rounding is specified.
+pub(crate) fn round(percent: f64) -> f64 {
+    Precision::current().round(percent)
+}
+
+/// Formats the given percentage (without `%`) with the number of decimal
+/// places specified by --precision.
+pub(crate) fn format(percent: f64) -> String {
+    Precision::current().format(percent)
+}
+
+/// Formats the change from `baseline` to `percent` with a sign (e.g., `+1.25`)
+/// and the number of decimal places specified by --precision.
+pub(crate) fn format_delta(percent: f64, baseline: f64) -> String {
+    Precision::current().format_delta(percent, baseline)
+}
+
+/// Recomputes the percentages in the summary table printed by `llvm-cov
+/// report` from the counts next to them, keeping the alignment of columns.
+pub(crate) fn rewrite_table(table: &str) -> String {
+    Precision::current().rewrite_table(table)
+}
+
+/// Recomputes the percentages in the html report generated by llvm-cov (e.g.,
+/// `85.71% (6/7)`) from the counts next to them. Returns `None` if there is
+/// nothing to rewrite.
+pub(crate) fn rewrite_html(html: &str) -> Option<String> {
+    Precision::current().rewrite_html(html)
+}
+
+#[derive(Clone, Copy)]
+struct Precision {
+    enabled: bool,
+    digits: u8,
+    rounding: Rounding,
+}
+
+impl Precision {
+    fn current() -> Self {
+        Self {
+            enabled: enabled(),
+            digits: PRECISION.load(Ordering::Relaxed),
+            rounding: if ROUNDING.load(Ordering::Relaxed) == Rounding::Floor as u8 {
+                Rounding::Floor
+            } else {
+                Rounding::Nearest
+            },
+        }
+    }
+
+    fn round(self, percent: f64) -> f64 {
+        if !self.enabled {
+            return percent;
+        }
+        let scale = 10_f64.powi(self.digits.into());
+        let scaled = percent * scale;
+        let rounded = match self.rounding {
+            Rounding::Nearest => scaled.round(),
+            // Percentages are computed from integer counts, so values that are
+            // exact in decimal may be slightly smaller in binary (e.g.,
+            // 29 * 100 / 100 = 28.999999999999996), and are nudged before flooring.
+            Rounding::Floor => (scaled + 1e-9 * scaled.abs().max(1.)).floor(),
+        };
+        rounded / scale
+    }
+
+    fn format(self, percent: f64) -> String {
+        format!("{:.*}", usize::from(self.digits), self.round(percent))
+    }
+
+    fn format_delta(self, percent: f64, baseline: f64) -> String {
+        // The difference of the shown values, so that it matches them.
+        format!("{:+.*}", usize::from(self.digits), self.round(percent) - self.round(baseline))
+    }
+
+    fn rewrite_table(self, table: &str) -> String {
+        // <count> <missed> <percent>%, where columns may be colored.
+        let re = Regex::new(
+            r"(\s)(\d+)((?:\x1b\[[0-9;]*m|\s)+)(\d+)((?:\x1b\[[0-9;]*m|\s)*?)(\s+)(\d+\.\d+%)",
+        )
+        .unwrap();
+        re.replace_all(table, |c: &Captures<'_>| {
+            let (count, missed): (u64, u64) = (c[2].parse().unwrap(), c[4].parse().unwrap());
+            let new = format!("{}%", self.format(ratio(count.saturating_sub(missed), count)));
+            let width = (c[6].len() + c[7].len()).saturating_sub(new.len()).max(1);
+            format!("{}{}{}{}{}{:width$}{new}", &c[1], &c[2], &c[3], &c[4], &c[5], "")
+        })
+        .into_owned()
+    }
+
+    fn rewrite_html(self, html: &str) -> Option<String> {
+        let re = Regex::new(r"\d+\.\d+% \((\d+)/(\d+)\)").unwrap();
+        match re.replace_all(html, |c: &Captures<'_>| {
+            let (covered, count): (u64, u64) = (c[1].parse().unwrap(), c[2].parse().unwrap());
+            format!("{}% ({covered}/{count})", self.format(ratio(covered, count)))
+        }) {
+            Cow::Owned(html) => Some(html),
+            Cow::Borrowed(_) => None,
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn ratio(covered: u64, count: u64) -> f64 {
+    if count == 0 {
+        0.
+    } else {
+        covered as f64 * 100. / count as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounding() {
+        let precision = |digits: Option<u8>, rounding: Option<Rounding>| Precision {
+            enabled: digits.is_some() || rounding.is_some(),
+            digits: digits.unwrap_or(DEFAULT_PRECISION),
+            rounding: rounding.unwrap_or(Rounding::Nearest),
+        };
+        assert_eq!(precision(None, None).round(79.996), 79.996);
+        assert_eq!(precision(None, None).format(79.996), "80.00");
+        let floor = precision(None, Some(Rounding::Floor));
+        assert_eq!(floor.format(79.996), "79.99");
+        assert_eq!(floor.format(28.999_999_999_999_996), "29.00");
+        assert_eq!(precision(Some(1), None).format(79.96), "80.0");
+        assert_eq!(precision(Some(1), None).round(79.96), 80.);
+        assert_eq!(precision(None, None).format_delta(80., 90.), "-10.00");
+        assert_eq!(precision(Some(1), None).format_delta(75., 75.), "+0.0");
+        let floor = precision(Some(0), Some(Rounding::Floor));
+        assert_eq!(floor.format(99.9), "99");
+        assert_eq!(floor.format_delta(80.9, 79.1), "+1");
+        assert_eq!(
+            floor.rewrite_table(
+                "Filename    Regions    Missed Regions     Cover\n\
+                 ----------------------------------------------\n\
+                 lib.rs            3                 1    66.67%\n\
+                 TOTAL          9999                 1    99.99%"
+            ),
+            "Filename    Regions    Missed Regions     Cover\n\
+             ----------------------------------------------\n\
+             lib.rs            3                 1       66%\n\
+             TOTAL          9999                 1       99%"
+        );
+        assert_eq!(
+            floor.rewrite_table(
+                "lib.rs  7\x1b[0;31m                 3\x1b[0m\x1b[0;31m    57.14%\x1b[0m           0"
+            ),
+            "lib.rs  7\x1b[0;31m                 3\x1b[0m\x1b[0;31m       57%\x1b[0m           0"
+        );
+        assert_eq!(
+            precision(Some(4), None)
+                .rewrite_html("<pre>  85.71% (6/7)</pre><pre>- (0/0)</pre>")
+                .unwrap(),
+            "<pre>  85.7143% (6/7)</pre><pre>- (0/0)</pre>"
+        );
+        assert_eq!(precision(Some(4), None).rewrite_html("<pre>- (0/0)</pre>"), None);
+    }
+
+    #[test]
+    fn show_counts() {
+        assert_eq!(with_counts(None, "70.37", 19, 27), "70.37%");
+        assert_eq!(with_counts(Some(Counts::Both), "70.37", 19, 27), "70.37% (19/27)");
+        assert_eq!(with_counts(Some(Counts::Only), "70.37", 19, 27), "19/27");
+    }
+}
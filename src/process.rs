@@ -5,9 +5,12 @@ use std::{
     collections::BTreeMap,
     ffi::OsString,
     fmt,
-    path::PathBuf,
+    io::{self, BufRead as _, Write as _},
+    mem,
+    path::{Path, PathBuf},
     process::{ExitStatus, Output},
-    str,
+    str, thread,
+    time::{Duration, Instant},
 };
 
 use anyhow::{Context as _, Error, Result};
@@ -36,6 +39,7 @@ pub(crate) struct ProcessBuilder {
     /// The working directory where the process will execute.
     dir: Option<PathBuf>,
     stdout_to_stderr: bool,
+    stderr_to_stdout: bool,
     /// `true` to include environment variables in display.
     display_env_vars: Cell<bool>,
 }
@@ -57,6 +61,7 @@ impl ProcessBuilder {
             env: BTreeMap::new(),
             dir: None,
             stdout_to_stderr: false,
+            stderr_to_stdout: false,
             display_env_vars: Cell::new(false),
         };
         this.env_remove("LLVM_COV_FLAGS");
@@ -103,6 +108,12 @@ impl ProcessBuilder {
         self
     }
 
+    /// Enables [`duct::Expression::stderr_to_stdout`].
+    pub(crate) fn stderr_to_stdout(&mut self) -> &mut Self {
+        self.stderr_to_stdout = true;
+        self
+    }
+
     /// Enables environment variables display.
     pub(crate) fn display_env_vars(&mut self) -> &mut Self {
         self.display_env_vars.set(true);
@@ -126,6 +137,122 @@ impl ProcessBuilder {
         }
     }
 
+    /// Executes a process like [`run`](Self::run), but terminates it if it
+    /// doesn't exit within `timeout`, and calls `on_interval` every `interval`
+    /// while it runs.
+    ///
+    /// The process is sent SIGTERM first (on Unix), so that it can exit
+    /// normally and write its profile data, and is killed if it doesn't exit
+    /// within `kill_after` after that.
+    pub(crate) fn run_with_timer(
+        &mut self,
+        timeout: Option<Duration>,
+        kill_after: Duration,
+        interval: Option<Duration>,
+        mut on_interval: impl FnMut(),
+    ) -> Result<Output> {
+        let handle = self.build().unchecked().start().with_context(|| {
+            process_error(format!("could not execute process {self}"), None, None)
+        })?;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut next_interval = interval.map(|interval| Instant::now() + interval);
+        let timeout = loop {
+            let output = match deadline.into_iter().chain(next_interval).min() {
+                Some(until) => {
+                    wait_timeout(&handle, until.saturating_duration_since(Instant::now()))?
+                }
+                None => Some(handle.wait()?.clone()),
+            };
+            if let Some(output) = output {
+                return if output.status.success() {
+                    Ok(output)
+                } else {
+                    Err(process_error(
+                        format!("process didn't exit successfully: {self}"),
+                        Some(output.status),
+                        Some(&output),
+                    ))
+                };
+            }
+            if let (Some(deadline), Some(timeout)) = (deadline, timeout) {
+                if Instant::now() >= deadline {
+                    break timeout;
+                }
+            }
+            if let (Some(next), Some(interval)) = (&mut next_interval, interval) {
+                if Instant::now() >= *next {
+                    on_interval();
+                    *next = Instant::now() + interval;
+                }
+            }
+        };
+        #[cfg(unix)]
+        {
+            info!("{self} timed out after {}s; sending SIGTERM", timeout.as_secs());
+            for pid in handle.pids() {
+                if let Some(pid) = i32::try_from(pid).ok().and_then(rustix::process::Pid::from_raw)
+                {
+                    // The process may have exited in the meantime.
+                    let _ = rustix::process::kill_process(pid, rustix::process::Signal::TERM);
+                }
+            }
+            if wait_timeout(&handle, kill_after)?.is_none() {
+                warn!(
+                    "{self} didn't exit within {}s after SIGTERM; killing it",
+                    kill_after.as_secs()
+                );
+                handle.kill()?;
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = kill_after;
+            warn!("{self} timed out after {}s; killing it", timeout.as_secs());
+            handle.kill()?;
+        }
+        let status = handle.wait()?.status;
+        Err(process_error(
+            format!("process timed out after {}s: {self}", timeout.as_secs()),
+            Some(status),
+            None,
+        ))
+    }
+
+    /// Executes a process, waiting for completion, and mapping non-zero exit
+    /// status to an error, while passing each line of its standard output to
+    /// `f` after forwarding it to the standard output (or standard error if
+    /// [`stdout_to_stderr`](Self::stdout_to_stderr) is enabled).
+    pub(crate) fn run_with_stdout_lines(&mut self, mut f: impl FnMut(&str)) -> Result<()> {
+        let mut cmd = self.clone();
+        let stdout_to_stderr = mem::take(&mut cmd.stdout_to_stderr);
+        let handle = cmd.build().unchecked().reader().with_context(|| {
+            process_error(format!("could not execute process {self}"), None, None)
+        })?;
+        let mut reader = io::BufReader::new(&handle);
+        let mut line = vec![];
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            if stdout_to_stderr {
+                io::stderr().write_all(&line)?;
+            } else {
+                io::stdout().write_all(&line)?;
+            }
+            f(&String::from_utf8_lossy(&line));
+        }
+        let status = handle.try_wait()?.map(|output| output.status);
+        match status {
+            Some(status) if status.success() => Ok(()),
+            _ => Err(process_error(
+                format!("process didn't exit successfully: {self}"),
+                status,
+                None,
+            )),
+        }
+    }
+
     /// Executes a process, captures its stdio output, returning the captured
     /// output, or an error if non-zero exit status.
     pub(crate) fn run_with_output(&mut self) -> Result<Output> {
@@ -144,6 +271,94 @@ impl ProcessBuilder {
         }
     }
 
+    /// Executes a process with the given data as its standard input, captures
+    /// its standard output, returning the captured output, or an error if
+    /// non-zero exit status.
+    pub(crate) fn run_with_stdin(&mut self, input: Vec<u8>) -> Result<Output> {
+        assert!(!self.stdout_to_stderr);
+        let output =
+            self.build().stdin_bytes(input).stdout_capture().unchecked().run().with_context(
+                || process_error(format!("could not execute process {self}"), None, None),
+            )?;
+        if output.status.success() {
+            Ok(output)
+        } else {
+            Err(process_error(
+                format!("process didn't exit successfully: {self}"),
+                Some(output.status),
+                Some(&output),
+            ))
+        }
+    }
+
+    /// Executes a process, captures its stdio output, returning the captured
+    /// output regardless of the exit status.
+    pub(crate) fn run_unchecked(&mut self) -> Result<Output> {
+        self.build()
+            .stdout_capture()
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .with_context(|| process_error(format!("could not execute process {self}"), None, None))
+    }
+
+    /// Executes a process, writes its standard output to the file at `path`, and
+    /// captures its standard error, or returns an error if non-zero exit status.
+    ///
+    /// This is useful for processes that output a huge amount of data.
+    pub(crate) fn run_with_stdout_path(&mut self, path: &Path) -> Result<()> {
+        assert!(!self.stdout_to_stderr);
+        let output =
+            self.build().stdout_path(path).stderr_capture().unchecked().run().with_context(
+                || process_error(format!("could not execute process {self}"), None, None),
+            )?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(process_error(
+                format!("process didn't exit successfully: {self}"),
+                Some(output.status),
+                Some(&output),
+            ))
+        }
+    }
+
+    /// Executes a process, passing a reader of its standard output to `f`, and
+    /// captures its standard error, or returns an error if non-zero exit status.
+    ///
+    /// Unlike [`run_with_stdout_path`](Self::run_with_stdout_path), this
+    /// allows the output to be consumed while the process is writing it.
+    pub(crate) fn run_with_stdout_reader<T>(
+        &mut self,
+        f: impl FnOnce(&mut dyn io::Read) -> Result<T>,
+    ) -> Result<T> {
+        assert!(!self.stdout_to_stderr);
+        let handle = self.build().stderr_capture().unchecked().reader().with_context(|| {
+            process_error(format!("could not execute process {self}"), None, None)
+        })?;
+        let mut reader = &handle;
+        let res = f(&mut reader);
+        // Read the rest of the output so that the process can exit.
+        if res.is_ok() {
+            io::copy(&mut reader, &mut io::sink())?;
+        } else {
+            handle.kill()?;
+        }
+        match handle.try_wait()? {
+            // If the process was killed above, report the error from `f` instead.
+            Some(output)
+                if !output.status.success() && (res.is_ok() || output.status.code().is_some()) =>
+            {
+                Err(process_error(
+                    format!("process didn't exit successfully: {self}"),
+                    Some(output.status),
+                    Some(output),
+                ))
+            }
+            _ => res,
+        }
+    }
+
     /// Executes a process, captures its stdio output, returning the captured
     /// standard output as a `String`.
     pub(crate) fn read(&mut self) -> Result<String> {
@@ -176,6 +391,9 @@ impl ProcessBuilder {
         if self.stdout_to_stderr {
             cmd = cmd.stdout_to_stderr();
         }
+        if self.stderr_to_stdout {
+            cmd = cmd.stderr_to_stdout();
+        }
 
         cmd
     }
@@ -211,6 +429,21 @@ impl fmt::Display for ProcessBuilder {
     }
 }
 
+/// Waits for the process to exit for up to `timeout`, and returns its output,
+/// or `None` if it is still running.
+fn wait_timeout(handle: &duct::Handle, timeout: Duration) -> Result<Option<Output>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(output) = handle.try_wait()? {
+            return Ok(Some(output.clone()));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
 // Based on https://github.com/rust-lang/cargo/blob/0.47.0/src/cargo/util/errors.rs
 /// Creates a new process error.
 ///
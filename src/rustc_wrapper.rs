@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Per-package rustflags support (`rustflags` key in `package.metadata.llvm-cov`
+// table) and --instrument support.
+//
+// RUSTFLAGS set by cargo-llvm-cov apply to all crates, and cargo doesn't provide
+// a stable way to pass extra flags to only some packages (`profile-rustflags` is
+// unstable). So cargo-llvm-cov is set as the rustc workspace wrapper, which cargo
+// uses only when compiling workspace members, and the wrapper appends the flags
+// for the package being compiled (identified by CARGO_PKG_NAME) to the arguments
+// of rustc. Flags appended later take precedence over the ones in RUSTFLAGS for
+// codegen options, so this can also be used to disable a flag set by
+// cargo-llvm-cov (e.g., `-C instrument-coverage=off`).
+//
+// For workspace members not specified by --instrument, `-C instrument-coverage=off`
+// is appended only when compiling libraries. Test binaries and executables need to
+// be instrumented to link the profiler runtime; otherwise, they don't record the
+// coverage of the instrumented libraries they use.
+//
+// Changes of the flags are not tracked by cargo, but workspace members are rebuilt
+// on every run unless --no-clean is used.
+//
+// Refs:
+// - https://doc.rust-lang.org/nightly/cargo/reference/config.html#buildrustc-workspace-wrapper
+// - https://doc.rust-lang.org/nightly/cargo/reference/environment-variables.html#environment-variables-cargo-sets-for-crates
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    process::{self, Command},
+};
+
+use anyhow::{bail, Context as _, Result};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{context::Context, env};
+
+/// The environment variable to pass the configuration to the wrapper.
+/// (value: JSON-serialized `WrapperConfig`)
+const CONFIG_ENV: &str = "__CARGO_LLVM_COV_RUSTC_WRAPPER";
+
+#[derive(Serialize, Deserialize)]
+struct WrapperConfig {
+    /// Package names and the flags to append.
+    rustflags: BTreeMap<String, Vec<String>>,
+    /// Names of packages whose libraries are built without instrumentation.
+    uninstrumented: Vec<String>,
+}
+
+/// Returns environment variables to use cargo-llvm-cov as the rustc workspace
+/// wrapper, or an empty list if the wrapper is not needed.
+pub(crate) fn env(cx: &Context) -> Result<Vec<(String, String)>> {
+    if cx.package_rustflags.is_empty() && cx.uninstrumented.is_empty() {
+        return Ok(vec![]);
+    }
+    if !cx.args.no_rustc_wrapper
+        && cx
+            .ws
+            .config
+            .build
+            .rustc_workspace_wrapper
+            .as_ref()
+            .is_some_and(|w| !w.as_os_str().is_empty())
+    {
+        bail!(
+            "`rustflags` in `package.metadata.llvm-cov` table and --instrument may not be used \
+             together with rustc workspace wrapper"
+        );
+    }
+    let exe = cx.current_exe.to_string_lossy();
+    let config = WrapperConfig {
+        rustflags: cx.package_rustflags.iter().cloned().collect(),
+        uninstrumented: cx.uninstrumented.clone(),
+    };
+    Ok(vec![
+        ("RUSTC_WORKSPACE_WRAPPER".to_owned(), exe.into_owned()),
+        (CONFIG_ENV.to_owned(), serde_json::to_string(&config)?),
+    ])
+}
+
+/// Runs rustc if cargo-llvm-cov is invoked as the rustc workspace wrapper.
+///
+/// Returns `Ok(false)` if cargo-llvm-cov is not invoked as the rustc workspace wrapper.
+pub(crate) fn try_run() -> Result<bool> {
+    let mut args = std::env::args_os().skip(1);
+    let Some(rustc) = args.next() else { return Ok(false) };
+    if rustc == "llvm-cov" {
+        return Ok(false);
+    }
+    let Some(config) = env::var(CONFIG_ENV)? else { return Ok(false) };
+    let mut config: WrapperConfig =
+        serde_json::from_str(&config).with_context(|| format!("failed to parse {CONFIG_ENV}"))?;
+    let args: Vec<OsString> = args.collect();
+    let mut flags = vec![];
+    if let Some(name) = env::var("CARGO_PKG_NAME")? {
+        if config.uninstrumented.contains(&name) && is_library(&args) {
+            flags.extend(["-C".to_owned(), "instrument-coverage=off".to_owned()]);
+        }
+        flags.extend(config.rustflags.remove(&name).unwrap_or_default());
+    }
+    let status = Command::new(&rustc)
+        .args(args)
+        .args(flags)
+        .status()
+        .with_context(|| format!("failed to run {}", rustc.to_string_lossy()))?;
+    process::exit(status.code().unwrap_or(101))
+}
+
+/// Returns `true` if the given rustc arguments compile a library (not a test).
+fn is_library(args: &[OsString]) -> bool {
+    let mut crate_types = vec![];
+    let mut iter = args.iter().map(|a| a.to_str().unwrap_or_default());
+    while let Some(arg) = iter.next() {
+        if arg == "--test" {
+            return false;
+        }
+        if arg == "--crate-type" {
+            crate_types.extend(iter.next());
+        } else if let Some(crate_type) = arg.strip_prefix("--crate-type=") {
+            crate_types.push(crate_type);
+        }
+    }
+    !crate_types.is_empty()
+        && crate_types.iter().flat_map(|t| t.split(',')).all(|t| matches!(t, "lib" | "rlib"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library() {
+        fn is_library(args: &[&str]) -> bool {
+            super::is_library(&args.iter().map(OsString::from).collect::<Vec<_>>())
+        }
+        assert!(is_library(&["--crate-name", "a", "--crate-type", "lib", "src/lib.rs"]));
+        assert!(is_library(&["--crate-type=rlib", "src/lib.rs"]));
+        assert!(!is_library(&["--crate-name", "a", "--test", "src/lib.rs"]));
+        assert!(!is_library(&["--crate-type", "bin", "src/main.rs"]));
+        assert!(!is_library(&["--crate-type", "lib,cdylib", "src/lib.rs"]));
+        assert!(!is_library(&["--crate-type", "proc-macro", "src/lib.rs"]));
+        assert!(!is_library(&["-vV"]));
+    }
+}
@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Support for #[should_panic] tests.
+//
+// The profiler runtime writes the counters to the profraw file when the process
+// exits normally, so the counters updated by a test before the expected panic
+// are persisted as long as the process running the test exits normally:
+//
+// - By default (panic=unwind), libtest catches the panic, and the test binary
+//   exits normally after running all tests.
+// - With `-Z panic-abort-tests`, libtest runs each test in a child process, and
+//   its panic hook exits the child process with `std::process::exit`, which
+//   runs the exit handlers. However, the counters are lost if the panic hook is
+//   replaced (e.g., by `std::panic::set_hook` in the code under test) with one
+//   that terminates the process without running the exit handlers.
+//
+// So when `-Z panic-abort-tests` is used, the profile data is written in
+// continuous mode on Linux and Apple platforms, in which the counters in the
+// profraw file are updated while the program runs. Continuous mode is not
+// supported on other platforms, where this relies on the exit handlers, and
+// after running tests, this warns if no #[should_panic] test in a file was
+// executed while other tests in the same file were, which suggests that their
+// counters were lost.
+//
+// Refs:
+// - https://clang.llvm.org/docs/SourceBasedCodeCoverage.html#running-the-instrumented-program
+// - https://github.com/rust-lang/rust/issues/67650
+
+use std::{collections::BTreeMap, ffi::OsString};
+
+use anyhow::{Context as _, Result};
+use regex::Regex;
+
+use crate::{
+    cli::Subcommand,
+    context::Context,
+    fs,
+    function_filter::{has_test_attr, test_attr_re},
+    Format,
+};
+
+/// Returns `true` if tests are run with `-Z panic-abort-tests`, in which each
+/// test is run in a child process that aborts on panic.
+pub(crate) fn panic_abort_tests(cx: &Context) -> bool {
+    matches!(cx.args.subcommand, Subcommand::None | Subcommand::Test | Subcommand::Nextest)
+        && crate::has_z_flag(&cx.args.cargo_args, "panic-abort-tests")
+}
+
+struct Test {
+    should_panic: bool,
+    executed: bool,
+}
+
+/// Warns if no #[should_panic] test in a file was executed while other tests in
+/// the same file were.
+///
+/// This does nothing if `-Z panic-abort-tests` is not used, since the counters
+/// of #[should_panic] tests are not lost in that case, or if tests are filtered,
+/// since the #[should_panic] tests may not have been run at all.
+pub(crate) fn check(
+    cx: &Context,
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+) -> Result<()> {
+    if !panic_abort_tests(cx) || cx.args.test_filter || has_test_filter(&cx.args.rest) {
+        return Ok(());
+    }
+    let json = Format::Json
+        .get_json(cx, object_files, ignore_filename_regex, None, false)
+        .context("failed to get json")?;
+    let (test_re, should_panic_re, ignore_re) = (test_attr_re(), should_panic_re(), ignore_re());
+    let mut sources: BTreeMap<&str, Option<String>> = BTreeMap::new();
+    // filename -> start location -> test
+    let mut tests: BTreeMap<&str, BTreeMap<(u64, u64), Test>> = BTreeMap::new();
+    for (filename, start, count) in json.function_counts() {
+        let source = sources
+            .entry(filename)
+            .or_insert_with(|| fs::read_to_string(cx.source_path(filename)).ok());
+        let Some(source) = source.as_deref() else { continue };
+        if !has_test_attr(source, start, &test_re) || has_test_attr(source, start, &ignore_re) {
+            continue;
+        }
+        // Instantiations of the same test (e.g., in the test binaries of
+        // different targets) are grouped by the start location.
+        let test = tests.entry(filename).or_default().entry(start).or_insert_with(|| Test {
+            should_panic: has_test_attr(source, start, &should_panic_re),
+            executed: false,
+        });
+        test.executed |= count != 0;
+    }
+    let files = suspicious_files(&tests);
+    if !files.is_empty() {
+        let files: Vec<_> = files
+            .iter()
+            .map(|f| cx.relative_source_path(f).map_or_else(|| (*f).to_owned(), Into::into))
+            .collect();
+        warn!(
+            "no #[should_panic] test was executed in {} while other tests in the same files were; \
+             this may occur if the counters updated before the expected panic were lost because \
+             the test processes were terminated without writing profile data (e.g., by a panic \
+             hook set by `std::panic::set_hook`)",
+            files.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Returns the files in which #[should_panic] tests have zero execution counts
+/// while other tests were executed.
+fn suspicious_files<'a>(tests: &BTreeMap<&'a str, BTreeMap<(u64, u64), Test>>) -> Vec<&'a str> {
+    tests
+        .iter()
+        .filter(|(_, tests)| {
+            let mut should_panic = tests.values().filter(|t| t.should_panic).peekable();
+            should_panic.peek().is_some()
+                && should_panic.all(|t| !t.executed)
+                && tests.values().any(|t| !t.should_panic && t.executed)
+        })
+        .map(|(&filename, _)| filename)
+        .collect()
+}
+
+/// Returns `true` if the given arguments for the test binary filter tests
+/// (e.g., `<TESTNAME>`, `--skip`, or `--ignored`).
+///
+/// Values of options are not distinguished from test names, so this may return
+/// `true` for unfiltered tests.
+fn has_test_filter(rest: &[String]) -> bool {
+    rest.iter().any(|arg| !arg.starts_with('-') || arg.starts_with("--skip") || arg == "--ignored")
+}
+
+fn should_panic_re() -> Regex {
+    Regex::new(r"#\[\s*should_panic\b").unwrap()
+}
+
+fn ignore_re() -> Regex {
+    Regex::new(r"#\[\s*ignore\b").unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suspicious() {
+        let test = |should_panic, executed| Test { should_panic, executed };
+        let tests = BTreeMap::from_iter([
+            // no #[should_panic] test was executed
+            (
+                "a.rs",
+                BTreeMap::from_iter([((1, 1), test(false, true)), ((5, 1), test(true, false))]),
+            ),
+            // some #[should_panic] tests were executed
+            (
+                "b.rs",
+                BTreeMap::from_iter([
+                    ((1, 1), test(false, true)),
+                    ((5, 1), test(true, false)),
+                    ((9, 1), test(true, true)),
+                ]),
+            ),
+            // no test was executed
+            (
+                "c.rs",
+                BTreeMap::from_iter([((1, 1), test(false, false)), ((5, 1), test(true, false))]),
+            ),
+            // no #[should_panic] test
+            ("d.rs", BTreeMap::from_iter([((1, 1), test(false, true))])),
+        ]);
+        assert_eq!(suspicious_files(&tests), ["a.rs"]);
+    }
+
+    #[test]
+    fn test_filter() {
+        let args = |args: &[&str]| args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>();
+        assert!(!has_test_filter(&[]));
+        assert!(!has_test_filter(&args(&["--test-threads=1", "--nocapture"])));
+        assert!(has_test_filter(&args(&["name"])));
+        assert!(has_test_filter(&args(&["--skip=name"])));
+        assert!(has_test_filter(&args(&["--ignored"])));
+    }
+}
@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// `cargo llvm-cov show <PATH>` support.
+//
+// This prints the source file with the execution count of each line in the
+// same layout as `llvm-cov show -format=text`, but highlights lines based on
+// the coverage of the whole line: uncovered lines are red and partially
+// covered lines (some regions or branches in the line were not executed) are
+// yellow.
+
+use std::io::Write as _;
+
+use anyhow::{Context as _, Result};
+use camino::Utf8PathBuf;
+use termcolor::{Color, ColorSpec, WriteColor};
+
+use crate::{
+    context::Context,
+    fs,
+    json::{LineCoverage, LlvmCovJsonExport},
+    term,
+};
+
+/// Prints the source file specified by the argument of the show subcommand.
+pub(crate) fn print(cx: &Context, json: &LlvmCovJsonExport) -> Result<()> {
+    let path = cx.args.show_file.as_ref().unwrap();
+    let path: Utf8PathBuf = cx.current_dir.join(path).try_into()?;
+    let path = fs::canonicalize(&path)
+        .ok()
+        .and_then(|p| Utf8PathBuf::from_path_buf(p).ok())
+        .unwrap_or(path);
+    let lines = json
+        .file_names()
+        .into_iter()
+        .find(|f| cx.source_path(f) == path)
+        .and_then(|f| json.get_line_coverage(f))
+        .with_context(|| {
+            format!(
+                "no coverage data for {path}; this may occur if the file is not compiled, or \
+                 excluded from the report"
+            )
+        })?;
+    let source = fs::read_to_string(&path)?;
+
+    let stdout = term::stdout();
+    let mut stdout = stdout.lock();
+    write_lines(&mut stdout, &source, &lines)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn write_lines(w: &mut impl WriteColor, source: &str, lines: &[LineCoverage]) -> Result<()> {
+    let width = source.lines().count().to_string().len();
+    let mut lines = lines.iter().peekable();
+    for (i, text) in (1..).zip(source.lines()) {
+        while lines.next_if(|l| l.line < i).is_some() {}
+        let line = lines.next_if(|l| l.line == i);
+        let color = match line {
+            Some(l) if l.count == 0 => Some(Color::Red),
+            Some(l) if l.partial => Some(Color::Yellow),
+            _ => None,
+        };
+        let count = line.map(|l| l.count.to_string()).unwrap_or_default();
+        if color.is_some() {
+            w.set_color(ColorSpec::new().set_fg(color))?;
+        }
+        write!(w, "{i:>width$}|{count:>7}|{text}")?;
+        if color.is_some() {
+            w.reset()?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use termcolor::Buffer;
+
+    use super::*;
+
+    #[test]
+    fn write_lines_format() {
+        let source = "fn a() {\n    b();\n}\n\n// comment\n";
+        let lines = [(1, 1, false), (2, 1, true), (3, 0, false)]
+            .map(|(line, count, partial)| LineCoverage { line, count, partial });
+        let mut buf = Buffer::no_color();
+        write_lines(&mut buf, source, &lines).unwrap();
+        assert_eq!(
+            String::from_utf8(buf.into_inner()).unwrap(),
+            "1|      1|fn a() {\n2|      1|    b();\n3|      0|}\n4|       |\n5|       |// comment\n"
+        );
+        let mut buf = Buffer::ansi();
+        write_lines(&mut buf, source, &lines[1..]).unwrap();
+        let s = String::from_utf8(buf.into_inner()).unwrap();
+        assert!(s.contains("\x1b[0m\x1b[33m2|      1|    b();\x1b[0m\n"), "{s:?}");
+        assert!(s.contains("\x1b[0m\x1b[31m3|      0|}\x1b[0m\n"), "{s:?}");
+    }
+}
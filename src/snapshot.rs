@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// `cargo llvm-cov snapshot save <NAME>` and --since-snapshot support.
+//
+// A snapshot is the line coverage of the report at the time it was saved,
+// which is stored in the lcov format in the target directory. Unlike the
+// profile data, it is not removed before running tests (see clean.rs), so the
+// reports of later runs can be compared with it. The comparison is the same as
+// `cargo llvm-cov diff`, and --since-snapshot prints the newly covered lines.
+// Paths are stored relative to the workspace root, so that a snapshot can be
+// compared with a report regardless of --remap-path-prefix.
+
+use std::fmt::Write as _;
+
+use anyhow::{bail, Context as _, Result};
+use camino::Utf8PathBuf;
+
+use crate::{
+    cargo::Workspace,
+    context::Context,
+    diff::{self, Change},
+    fs,
+    json::{LcovExport, LlvmCovJsonExport},
+};
+
+/// Checks that the given name can be used as the file name of a snapshot.
+pub(crate) fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!(
+            "invalid snapshot name `{name}`; the name must not be empty, start with `.`, or \
+             contain path separators"
+        );
+    }
+    Ok(())
+}
+
+fn path(ws: &Workspace, name: &str) -> Utf8PathBuf {
+    ws.snapshot_dir.join(format!("{name}.lcov"))
+}
+
+/// Checks that the snapshot specified by --since-snapshot exists, so that
+/// a missing snapshot is reported before running tests.
+pub(crate) fn check(ws: &Workspace, name: &str) -> Result<()> {
+    if !path(ws, name).exists() {
+        bail!(
+            "snapshot `{name}` not found in {}; save it by `cargo llvm-cov snapshot save {name}`",
+            ws.snapshot_dir
+        );
+    }
+    Ok(())
+}
+
+/// Saves the line coverage of the given report as the snapshot specified by
+/// `cargo llvm-cov snapshot save <NAME>`.
+pub(crate) fn save(cx: &Context, json: &LlvmCovJsonExport) -> Result<()> {
+    let name = cx.args.snapshot_name.as_deref().unwrap();
+    let path = path(&cx.ws, name);
+    fs::create_dir_all(&cx.ws.snapshot_dir)?;
+    fs::write(&path, lcov(cx, json).to_string())?;
+    status!("Finished", "snapshot `{name}` saved to {path}");
+    Ok(())
+}
+
+/// Prints the lines executed in the given report but not in the snapshot
+/// specified by --since-snapshot.
+pub(crate) fn print_since(cx: &Context, json: &LlvmCovJsonExport) -> Result<()> {
+    let Some(name) = &cx.args.cov.since_snapshot else { return Ok(()) };
+    let base = diff::load(&path(&cx.ws, name))
+        .with_context(|| format!("failed to load snapshot `{name}`"))?;
+    print!("{}", newly_covered(name, &base, &lcov(cx, json)));
+    Ok(())
+}
+
+fn newly_covered(name: &str, base: &LcovExport, head: &LcovExport) -> String {
+    let mut files = String::new();
+    let mut total = 0;
+    for file in diff::diff(base, head) {
+        let lines: Vec<_> = file
+            .lines
+            .iter()
+            .filter(|&&(_, base, head)| Change::new(base, head) == Some(Change::NewlyCovered))
+            .map(|(line, ..)| line.to_string())
+            .collect();
+        if lines.is_empty() {
+            continue;
+        }
+        total += lines.len();
+        let _ = write!(files, "\n    {}: {}", file.path, lines.join(", "));
+    }
+    format!(
+        "lines: {:.2}% -> {:.2}% since snapshot `{name}`, newly covered lines: {total}{files}\n",
+        base.lines_percent(),
+        head.lines_percent()
+    )
+}
+
+/// Converts the given report to the lcov format with paths relative to the
+/// workspace root.
+fn lcov(cx: &Context, json: &LlvmCovJsonExport) -> LcovExport {
+    let mut lcov = LcovExport::from_llvm_cov_json_export(json);
+    for record in &mut lcov.records {
+        if let Some(relative) = cx.relative_source_path(&record.source_file) {
+            record.source_file = relative.into_string();
+        }
+    }
+    lcov
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name() {
+        validate_name("before-refactor").unwrap();
+        validate_name("2024-01-01").unwrap();
+        validate_name("").unwrap_err();
+        validate_name(".hidden").unwrap_err();
+        validate_name("a/b").unwrap_err();
+        validate_name("a\\b").unwrap_err();
+    }
+
+    #[test]
+    fn newly_covered_lines() {
+        let base: LcovExport =
+            "SF:src/a.rs\nDA:1,1\nDA:2,0\nDA:3,0\nend_of_record\n".parse().unwrap();
+        let head: LcovExport = "SF:src/a.rs\nDA:1,1\nDA:2,2\nDA:3,1\nDA:4,0\nend_of_record\n\
+                                SF:src/b.rs\nDA:1,1\nend_of_record\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            newly_covered("today", &base, &head),
+            "lines: 33.33% -> 80.00% since snapshot `today`, newly covered lines: 3\n    \
+             src/a.rs: 2, 3\n    src/b.rs: 1\n"
+        );
+        assert_eq!(
+            newly_covered("today", &head, &head),
+            "lines: 80.00% -> 80.00% since snapshot `today`, newly covered lines: 0\n"
+        );
+    }
+}
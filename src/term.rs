@@ -105,6 +105,17 @@ global_flag!(verbose: bool = AtomicBool::new(false));
 global_flag!(error: bool = AtomicBool::new(false));
 global_flag!(warn: bool = AtomicBool::new(false));
 
+/// Returns stdout stream that respects --color.
+pub(crate) fn stdout() -> StandardStream {
+    let coloring = match coloring() {
+        ColorChoice::Auto if !std::io::IsTerminal::is_terminal(&std::io::stdout()) => {
+            ColorChoice::Never
+        }
+        coloring => coloring,
+    };
+    StandardStream::stdout(coloring)
+}
+
 pub(crate) fn print_status(status: &str, color: Option<Color>, justified: bool) -> StandardStream {
     let mut stream = StandardStream::stderr(coloring());
     let _ = stream.set_color(ColorSpec::new().set_bold(true).set_fg(color));
@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --emit-test-contribution support.
+//
+// The profile data written by each test (recorded by the nextest target runner,
+// see nextest_runner.rs) is merged into a separate profdata file, and the lines
+// covered by each test are compared. Lines covered by only one test are the
+// unique contribution of that test: removing the test would make them
+// uncovered. Tests with no unique lines are redundant as far as line coverage
+// is concerned, since every line they cover is also covered by another test.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    mem,
+    path::PathBuf,
+};
+
+use anyhow::{Context as _, Result};
+use serde_derive::Serialize;
+
+use crate::{
+    context::Context, fs, function_filter::FunctionFilter, json::LlvmCovJsonExport, Format,
+};
+
+#[derive(Debug, PartialEq, Serialize)]
+struct TestContributions {
+    /// Tests sorted by the number of unique lines in descending order.
+    tests: Vec<TestContribution>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct TestContribution {
+    binary: String,
+    name: String,
+    /// The number of lines covered by this test.
+    covered_lines: usize,
+    /// The number of lines covered by this test and no other tests.
+    unique_lines: usize,
+}
+
+/// Writes the ranking of tests by unique lines covered to the path specified by
+/// --emit-test-contribution.
+pub(crate) fn write(
+    cx: &mut Context,
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+    function_filter: Option<&FunctionFilter>,
+) -> Result<()> {
+    let Some(path) = cx.args.emit_test_contribution.clone() else { return Ok(()) };
+    let tests = mem::take(&mut cx.test_profiles);
+    let mut covered = vec![];
+    for (i, test) in tests.iter().enumerate() {
+        let json = test_json(
+            cx,
+            i,
+            &test.profraw_files,
+            object_files,
+            ignore_filename_regex,
+            function_filter,
+        )
+        .with_context(|| format!("failed to get json for test {} {}", test.binary, test.name))?;
+        let mut lines = BTreeSet::new();
+        for file in json.file_names() {
+            for line in json.get_line_coverage(file).unwrap_or_default() {
+                if line.count != 0 {
+                    lines.insert((file.to_owned(), line.line));
+                }
+            }
+        }
+        covered.push(((test.binary.clone(), test.name.clone()), lines));
+    }
+    let contributions = rank(&covered);
+
+    let redundant = contributions.tests.iter().filter(|t| t.unique_lines == 0).count();
+    if let Some(top) = contributions.tests.first().filter(|t| t.unique_lines != 0) {
+        info!(
+            "`{} {}` covers the most lines that no other test covers ({} lines)",
+            top.binary, top.name, top.unique_lines
+        );
+    }
+    if redundant != 0 {
+        info!(
+            "{redundant} of {} tests cover no lines that no other test covers",
+            contributions.tests.len()
+        );
+    }
+    fs::write(&path, serde_json::to_vec_pretty(&contributions)?)?;
+    status!("Finished", "test contribution saved to {path}");
+    Ok(())
+}
+
+/// Computes the unique lines of each test from the lines covered by each test,
+/// and sorts the tests by the number of unique lines, then by the number of
+/// covered lines, in descending order.
+fn rank(covered: &[((String, String), BTreeSet<(String, u64)>)]) -> TestContributions {
+    // (file, line) -> number of tests that covered the line
+    let mut counts: BTreeMap<&(String, u64), usize> = BTreeMap::new();
+    for (_, lines) in covered {
+        for line in lines {
+            *counts.entry(line).or_default() += 1;
+        }
+    }
+    let mut tests: Vec<_> = covered
+        .iter()
+        .map(|((binary, name), lines)| TestContribution {
+            binary: binary.clone(),
+            name: name.clone(),
+            covered_lines: lines.len(),
+            unique_lines: lines.iter().filter(|line| counts[line] == 1).count(),
+        })
+        .collect();
+    tests.sort_by(|a, b| {
+        (b.unique_lines, b.covered_lines)
+            .cmp(&(a.unique_lines, a.covered_lines))
+            .then_with(|| (&a.binary, &a.name).cmp(&(&b.binary, &b.name)))
+    });
+    TestContributions { tests }
+}
+
+/// Returns the coverage of the given test.
+fn test_json(
+    cx: &mut Context,
+    i: usize,
+    profraw_files: &[PathBuf],
+    object_files: &[OsString],
+    ignore_filename_regex: Option<&str>,
+    function_filter: Option<&FunctionFilter>,
+) -> Result<LlvmCovJsonExport> {
+    let profdata_file = cx.ws.target_dir.join(format!("{}-test{i}.profdata", cx.ws.name));
+    let input_files: Vec<_> = profraw_files.iter().filter_map(|f| f.to_str()).collect();
+    crate::merge_profdata_cmd(
+        cx,
+        &input_files,
+        &profdata_file,
+        &format!("profraw-list-test{i}"),
+        false,
+    )?
+    .run()?;
+    let profdata_file = mem::replace(&mut cx.ws.profdata_file, profdata_file);
+    let res =
+        Format::Json.get_json(cx, object_files, ignore_filename_regex, function_filter, false);
+    let profdata_file = mem::replace(&mut cx.ws.profdata_file, profdata_file);
+    fs::remove_file(profdata_file)?;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranking() {
+        let test = |name: &str, lines: &[(&str, u64)]| {
+            (
+                ("crate1".to_owned(), name.to_owned()),
+                lines.iter().map(|&(f, l)| (f.to_owned(), l)).collect(),
+            )
+        };
+        let ranked = rank(&[
+            test("a", &[("lib.rs", 1), ("lib.rs", 2)]),
+            test("b", &[("lib.rs", 1), ("lib.rs", 2), ("lib.rs", 3), ("main.rs", 1)]),
+            test("c", &[("lib.rs", 1), ("lib.rs", 3)]),
+            test("d", &[("lib.rs", 4)]),
+            test("e", &[]),
+        ]);
+        let ranked: Vec<_> =
+            ranked.tests.iter().map(|t| (&*t.name, t.covered_lines, t.unique_lines)).collect();
+        assert_eq!(ranked, [("b", 4, 1), ("d", 1, 1), ("a", 2, 0), ("c", 2, 0), ("e", 0, 0)]);
+    }
+}
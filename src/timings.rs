@@ -0,0 +1,103 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --timings and --timings-json support.
+//
+// Each phase records how long it took when its guard is dropped, and the
+// times of phases with the same name (e.g., `cargo test` runs of each
+// workspace member with --keep-going) are summed up.
+
+use std::{
+    io::Write as _,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use camino::Utf8PathBuf;
+use serde_derive::Serialize;
+
+use crate::{cli::Args, fs};
+
+static PHASES: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+/// Records the time taken by the phase until the returned guard is dropped.
+pub(crate) fn phase(name: &'static str) -> Phase {
+    Phase { name, start: Instant::now() }
+}
+
+#[must_use]
+pub(crate) struct Phase {
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        let mut phases = PHASES.lock().unwrap_or_else(PoisonError::into_inner);
+        match phases.iter_mut().find(|(name, _)| *name == self.name) {
+            Some((_, time)) => *time += elapsed,
+            None => phases.push((self.name, elapsed)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Timings {
+    phases: Vec<PhaseTime>,
+    /// The time taken by the whole operation in seconds.
+    total: f64,
+}
+
+#[derive(Serialize)]
+struct PhaseTime {
+    name: &'static str,
+    /// The time taken by the phase in seconds.
+    seconds: f64,
+}
+
+pub(crate) struct Recorder {
+    print: bool,
+    json: Option<Utf8PathBuf>,
+    start: Instant,
+}
+
+impl Recorder {
+    /// Starts recording the times of phases of the operation.
+    pub(crate) fn start(args: &Args) -> Self {
+        PHASES.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        Self { print: args.timings, json: args.timings_json.clone(), start: Instant::now() }
+    }
+
+    /// Prints the recorded times if --timings is passed, and writes them to the
+    /// file if --timings-json is passed.
+    pub(crate) fn finish(self) -> Result<()> {
+        if !self.print && self.json.is_none() {
+            return Ok(());
+        }
+        let total = self.start.elapsed();
+        let phases = PHASES.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        if self.print {
+            let width = phases.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(5);
+            let stderr = std::io::stderr();
+            let mut stderr = stderr.lock();
+            writeln!(stderr, "Timings:")?;
+            for (name, time) in &phases {
+                writeln!(stderr, "  {name:<width$}  {:>8.2}s", time.as_secs_f64())?;
+            }
+            writeln!(stderr, "  {:<width$}  {:>8.2}s", "total", total.as_secs_f64())?;
+        }
+        if let Some(path) = &self.json {
+            let timings = Timings {
+                phases: phases
+                    .into_iter()
+                    .map(|(name, time)| PhaseTime { name, seconds: time.as_secs_f64() })
+                    .collect(),
+                total: total.as_secs_f64(),
+            };
+            fs::write(path, serde_json::to_vec_pretty(&timings)?)?;
+            status!("Finished", "timings saved to {path}");
+        }
+        Ok(())
+    }
+}
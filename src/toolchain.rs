@@ -0,0 +1,422 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Detection of profile data that cannot be read by the llvm-tools being used
+// because it was generated by a different toolchain, lookup of llvm-tools
+// that can read it from other installed toolchains, and lookup of llvm-tools
+// for toolchains not managed by rustup (e.g., distribution packages and Bazel).
+// This also implements the parts of --merge-toolchains that group profraw
+// files by the version of the format and read the binary IDs recorded in them.
+//
+// The raw profile (profraw) format is tied to the LLVM version used by rustc,
+// and llvm-profdata only accepts the version it was built with.
+// https://github.com/llvm/llvm-project/blob/llvmorg-18.1.8/llvm/include/llvm/ProfileData/InstrProfData.inc
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    ffi::OsString,
+    fmt::{self, Write as _},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{cargo::Workspace, context::Context, fs, per_binary, term};
+
+const INSTR_PROF_RAW_MAGIC_64: u64 = (255_u64) << 56
+    | ('l' as u64) << 48
+    | ('p' as u64) << 40
+    | ('r' as u64) << 32
+    | ('o' as u64) << 24
+    | ('f' as u64) << 16
+    | ('r' as u64) << 8
+    | 0x81_u64;
+/// The bits of the version field used for variant flags (e.g., IR-level instrumentation).
+const VARIANT_MASKS_ALL: u64 = 0xffff_ffff_0000_0000;
+
+/// The error returned when llvm-profdata doesn't support the version of the
+/// raw profile format of the profraw file.
+#[derive(Debug)]
+pub(crate) struct VersionMismatch {
+    file: String,
+    version: Option<u64>,
+    expected: Option<u64>,
+    llvm_profdata: PathBuf,
+    llvm_profdata_llvm: Option<String>,
+    rustc_llvm: Option<String>,
+    /// Whether --find-llvm-tools was passed. If this error is returned in that
+    /// case, no installed toolchain has llvm-tools that support the version.
+    find_llvm_tools: bool,
+}
+
+impl VersionMismatch {
+    pub(crate) fn file(&self) -> &str {
+        &self.file
+    }
+}
+
+impl fmt::Display for VersionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} was generated with ", self.file)?;
+        match self.version {
+            Some(version) => write!(f, "raw profile format version {version}")?,
+            None => f.write_str("unknown raw profile format version")?,
+        }
+        write!(f, ", but {} ", self.llvm_profdata.display())?;
+        if let Some(llvm) = &self.llvm_profdata_llvm {
+            write!(f, "(LLVM {llvm}) ")?;
+        }
+        f.write_str("only supports ")?;
+        match self.expected {
+            Some(expected) => write!(f, "version {expected}")?,
+            None => f.write_str("a different version")?,
+        }
+        f.write_str("; this may occur if coverage data was collected using a different toolchain")?;
+        if let Some(llvm) = &self.rustc_llvm {
+            write!(f, " than the current rustc (LLVM {llvm})")?;
+        }
+        f.write_str("; consider re-collecting coverage data using the current toolchain")?;
+        if self.find_llvm_tools {
+            f.write_str(" (no other installed toolchain has llvm-tools that support this version)")
+        } else {
+            f.write_str(
+                ", or passing --find-llvm-tools to use llvm-tools from another installed toolchain",
+            )
+        }
+    }
+}
+
+impl std::error::Error for VersionMismatch {}
+
+/// Reads the magic and the version at the start of the given profraw file, and
+/// returns whether the file is big-endian and the version of the raw profile format.
+fn read_raw_profile_header(file: &mut impl Read) -> Option<(bool, u64)> {
+    let mut header = [0; 16];
+    file.read_exact(&mut header).ok()?;
+    let (magic, version) = header.split_at(8);
+    let (magic, version): ([u8; 8], [u8; 8]) = (magic.try_into().ok()?, version.try_into().ok()?);
+    let (big_endian, version) = if u64::from_le_bytes(magic) == INSTR_PROF_RAW_MAGIC_64 {
+        (false, u64::from_le_bytes(version))
+    } else if u64::from_be_bytes(magic) == INSTR_PROF_RAW_MAGIC_64 {
+        (true, u64::from_be_bytes(version))
+    } else {
+        return None;
+    };
+    Some((big_endian, version & !VARIANT_MASKS_ALL))
+}
+
+/// Returns the version of the raw profile format of the given profraw file.
+fn raw_profile_version(path: &Path) -> Option<u64> {
+    read_raw_profile_header(&mut fs::File::open(path).ok()?).map(|(_, version)| version)
+}
+
+/// Returns the binary IDs recorded in the given profraw file, or `None` if the
+/// file cannot be read or uses a version of the raw profile format whose header
+/// layout is not known.
+///
+/// Unlike `llvm-profdata show --binary-ids` (used by --report-per-binary), this
+/// doesn't depend on llvm-profdata, which may not be able to read files
+/// generated by other toolchains.
+///
+/// Since version 8 (LLVM 15), the header is followed by the binary IDs section,
+/// whose size is the third field of the header. Each binary ID in the section is
+/// the length (u64) followed by the bytes of the ID, padded to 8 bytes.
+pub(crate) fn raw_profile_binary_ids(path: &Path) -> Option<Vec<String>> {
+    read_binary_ids(&mut fs::File::open(path).ok()?)
+}
+
+fn read_binary_ids(file: &mut impl Read) -> Option<Vec<String>> {
+    let (big_endian, version) = read_raw_profile_header(file)?;
+    let read_u64 = |bytes: &[u8]| {
+        let bytes: [u8; 8] = bytes.try_into().unwrap();
+        if big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        }
+    };
+    // The number of the u64 fields of the header.
+    let header_fields = match version {
+        8 => 11,
+        9 => 14,
+        10 => 16,
+        _ => return None,
+    };
+    let mut header = vec![0; (header_fields - 2) * 8];
+    file.read_exact(&mut header).ok()?;
+    let size = usize::try_from(read_u64(&header[..8])).ok()?;
+    // Binary IDs are usually 20 bytes (SHA-1 GNU build ID), so a large section
+    // means the file is broken.
+    if size > 1 << 20 {
+        return None;
+    }
+    let mut section = vec![0; size];
+    file.read_exact(&mut section).ok()?;
+    let mut ids = vec![];
+    let mut rest = &section[..];
+    while rest.len() >= 8 {
+        let len = usize::try_from(read_u64(&rest[..8])).ok()?;
+        rest = &rest[8..];
+        let padded = len.checked_add(7)? / 8 * 8;
+        if padded > rest.len() {
+            return None;
+        }
+        let mut id = String::with_capacity(len * 2);
+        for b in &rest[..len] {
+            let _ = write!(id, "{b:02x}");
+        }
+        ids.push(id);
+        rest = &rest[padded..];
+    }
+    Some(ids)
+}
+
+/// Groups the given profraw files by the version of the raw profile format.
+pub(crate) fn group_by_version<'a>(input_files: &[&'a str]) -> BTreeMap<Option<u64>, Vec<&'a str>> {
+    let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for &file in input_files {
+        groups.entry(raw_profile_version(Path::new(file))).or_default().push(file);
+    }
+    groups
+}
+
+/// Returns llvm-cov and llvm-profdata that can read the given profraw files,
+/// which use the same version of the raw profile format: the ones being used if
+/// possible, otherwise ones in another installed toolchain.
+pub(crate) fn llvm_tools_for(cx: &Context, files: &[&str]) -> Result<(PathBuf, PathBuf)> {
+    if version_mismatch(cx, files[0])?.is_none() {
+        return Ok((cx.llvm_cov.clone(), cx.llvm_profdata.clone()));
+    }
+    if let Some(tools) = find_llvm_tools(cx, files[0])? {
+        return Ok(tools);
+    }
+    check_versions(cx, &files[..1])?;
+    bail!("no llvm-profdata that can read {} was found", files[0])
+}
+
+/// Removes object files whose binary ID doesn't appear in the given profraw
+/// files (--merge-toolchains).
+///
+/// When coverage is collected with multiple toolchains, the target directory
+/// contains binaries of the same crates built by each toolchain, and the
+/// coverage mappings of the binaries that were not run conflict with the ones
+/// that were. If the binary IDs are not available (e.g., the profraw files use
+/// an old format, or the platform doesn't use ELF), all object files are kept.
+pub(crate) fn filter_object_files(
+    profraw_files: &[PathBuf],
+    object_files: Vec<OsString>,
+) -> Vec<OsString> {
+    let mut ids = BTreeSet::new();
+    for file in profraw_files {
+        let Some(file_ids) = raw_profile_binary_ids(file) else { return object_files };
+        ids.extend(file_ids);
+    }
+    if ids.is_empty() {
+        return object_files;
+    }
+    let (kept, removed): (Vec<_>, Vec<_>) = object_files.into_iter().partition(|f| {
+        fs::read(f)
+            .ok()
+            .and_then(|data| per_binary::build_id(&data))
+            .is_none_or(|id| ids.contains(&id))
+    });
+    if !removed.is_empty() {
+        info!(
+            "excluded {} object files whose binary ID doesn't appear in the profile data",
+            removed.len()
+        );
+        if term::verbose() {
+            for f in &removed {
+                status!("Excluded", "{}", Path::new(f).display());
+            }
+        }
+    }
+    kept
+}
+
+/// Returns the version of LLVM that the given llvm tool was built with.
+fn llvm_version(cx: &Context, tool: &Path) -> Option<String> {
+    let out = cx.process(tool).arg("--version").read().ok()?;
+    out.lines().find_map(|l| l.trim().strip_prefix("LLVM version ")).map(str::to_owned)
+}
+
+/// Returns the error message of llvm-profdata if it rejects the given profraw
+/// file because of the version mismatch.
+fn version_mismatch(cx: &Context, file: &str) -> Result<Option<String>> {
+    let output = cx.process(&cx.llvm_profdata).arg("show").arg(file).run_unchecked()?;
+    if output.status.success() {
+        return Ok(None);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // LLVM 15+ reports "raw profile version mismatch: Profile uses raw profile
+    // format version = <N>; expected version = <M>", and older LLVM reports
+    // "unsupported instrumentation profile format version".
+    if stderr.contains("raw profile version mismatch")
+        || stderr.contains("unsupported instrumentation profile format version")
+    {
+        Ok(Some(stderr.into_owned()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Returns an error if any of the given profraw files cannot be read by the
+/// current llvm-profdata because of the version mismatch.
+///
+/// This runs llvm-profdata only once for each raw profile format version found.
+pub(crate) fn check_versions(cx: &Context, input_files: &[&str]) -> Result<()> {
+    let mut checked = vec![];
+    for &file in input_files {
+        let version = raw_profile_version(Path::new(file));
+        if checked.contains(&version) {
+            continue;
+        }
+        checked.push(version);
+        if let Some(stderr) = version_mismatch(cx, file)? {
+            let expected = stderr.split_once("expected version = ").and_then(|(_, s)| {
+                s.split(|c: char| !c.is_ascii_digit()).next().and_then(|s| s.parse().ok())
+            });
+            return Err(VersionMismatch {
+                file: file.to_owned(),
+                version,
+                expected,
+                llvm_profdata: cx.llvm_profdata.clone(),
+                llvm_profdata_llvm: llvm_version(cx, &cx.llvm_profdata),
+                rustc_llvm: cx.ws.rustc_version.llvm.clone(),
+                find_llvm_tools: cx.args.cov.find_llvm_tools || cx.args.cov.merge_toolchains,
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Searches llvm-tools installed in other rustup toolchains for llvm-profdata
+/// that can read the given profraw file, and returns the paths to llvm-cov and
+/// llvm-profdata in the same toolchain.
+pub(crate) fn find_llvm_tools(cx: &Context, file: &str) -> Result<Option<(PathBuf, PathBuf)>> {
+    let Some(rustup_home) = home::rustup_home().ok() else { return Ok(None) };
+    let Some(rustup_home) = rustup_home.to_str() else { return Ok(None) };
+    // llvm-tools are installed in $sysroot/lib/rustlib/$host_triple/bin.
+    let pattern = Path::new(&glob::Pattern::escape(rustup_home))
+        .join("toolchains/*/lib/rustlib/*/bin")
+        .to_string_lossy()
+        .into_owned();
+    let mut dirs: Vec<_> = glob::glob(&pattern)?.filter_map(Result::ok).collect();
+    dirs.sort_unstable();
+    for dir in dirs {
+        let llvm_cov = dir.join(format!("llvm-cov{}", env::consts::EXE_SUFFIX));
+        let llvm_profdata = dir.join(format!("llvm-profdata{}", env::consts::EXE_SUFFIX));
+        if llvm_profdata == cx.llvm_profdata || !llvm_cov.exists() || !llvm_profdata.exists() {
+            continue;
+        }
+        if cx.process(&llvm_profdata).arg("show").arg(file).run_with_output().is_ok() {
+            return Ok(Some((llvm_cov, llvm_profdata)));
+        }
+    }
+    Ok(None)
+}
+
+/// Searches llvm-tools for toolchains not managed by rustup, and returns the
+/// paths to llvm-cov and llvm-profdata.
+///
+/// This looks for the tools in the following order, and uses the first ones
+/// found whose LLVM major version matches the one used by rustc:
+///
+/// - `$sysroot/bin`
+/// - `$sysroot/lib/llvm-<major>/bin`, `/usr/lib/llvm-<major>/bin` (Debian/Ubuntu)
+/// - `llvm-cov-<major>` and `llvm-profdata-<major>` in `PATH`
+/// - `llvm-cov` and `llvm-profdata` in `PATH`
+pub(crate) fn find_system_llvm_tools(ws: &Workspace) -> Result<Option<(PathBuf, PathBuf)>> {
+    let sysroot = PathBuf::from(ws.rustc_print("sysroot")?);
+    let major = ws.rustc_version.llvm.as_deref().and_then(|v| v.split('.').next());
+    let exe = |name: &str| format!("{name}{}", env::consts::EXE_SUFFIX);
+
+    let mut candidates = vec![(
+        sysroot.join("bin").join(exe("llvm-cov")),
+        sysroot.join("bin").join(exe("llvm-profdata")),
+    )];
+    if let Some(major) = major {
+        for dir in [sysroot.join("lib"), PathBuf::from("/usr/lib")] {
+            let bin = dir.join(format!("llvm-{major}")).join("bin");
+            candidates.push((bin.join(exe("llvm-cov")), bin.join(exe("llvm-profdata"))));
+        }
+    }
+    let paths: Vec<_> =
+        env::var_os("PATH").map(|p| env::split_paths(&p).collect()).unwrap_or_default();
+    let mut names = vec![];
+    if let Some(major) = major {
+        names.push((exe(&format!("llvm-cov-{major}")), exe(&format!("llvm-profdata-{major}"))));
+    }
+    names.push((exe("llvm-cov"), exe("llvm-profdata")));
+    for (llvm_cov, llvm_profdata) in &names {
+        for dir in &paths {
+            candidates.push((dir.join(llvm_cov), dir.join(llvm_profdata)));
+        }
+    }
+
+    for (llvm_cov, llvm_profdata) in candidates {
+        if !llvm_cov.is_file() || !llvm_profdata.is_file() {
+            continue;
+        }
+        let Some(version) = cmd!(&llvm_cov, "--version").read().ok().and_then(|out| {
+            out.lines().find_map(|l| l.trim().strip_prefix("LLVM version ")).map(str::to_owned)
+        }) else {
+            continue;
+        };
+        // The profile format is tied to the LLVM major version.
+        if major.is_some_and(|major| version.split('.').next() != Some(major)) {
+            continue;
+        }
+        return Ok(Some((llvm_cov, llvm_profdata)));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profraw(big_endian: bool, version: u64, header_fields: usize, ids: &[&[u8]]) -> Vec<u8> {
+        let u64_bytes = |n: u64| if big_endian { n.to_be_bytes() } else { n.to_le_bytes() };
+        let mut section = vec![];
+        for id in ids {
+            section.extend_from_slice(&u64_bytes(id.len() as u64));
+            section.extend_from_slice(id);
+            section.resize(section.len().div_ceil(8) * 8, 0);
+        }
+        let mut data = vec![];
+        data.extend_from_slice(&u64_bytes(INSTR_PROF_RAW_MAGIC_64));
+        data.extend_from_slice(&u64_bytes(version));
+        data.extend_from_slice(&u64_bytes(section.len() as u64));
+        data.resize(header_fields * 8, 0);
+        data.extend_from_slice(&section);
+        // Data, counters, and names follow the binary IDs.
+        data.extend_from_slice(&[0xff; 32]);
+        data
+    }
+
+    #[test]
+    fn binary_ids() {
+        let id = [0x81, 0x7d, 0x27, 0x9a, 0xc1, 0x36, 0x17, 0xf3, 0x5c, 0x06];
+        for big_endian in [false, true] {
+            for (version, header_fields) in [(8, 11), (9, 14), (10, 16)] {
+                let data = profraw(big_endian, version, header_fields, &[&id, &[1, 2, 3]]);
+                assert_eq!(
+                    read_binary_ids(&mut &data[..]),
+                    Some(vec!["817d279ac13617f35c06".to_owned(), "010203".to_owned()])
+                );
+                let data = profraw(big_endian, version, header_fields, &[]);
+                assert_eq!(read_binary_ids(&mut &data[..]), Some(vec![]));
+            }
+        }
+        // The header layout of old versions is not supported.
+        assert_eq!(read_binary_ids(&mut &profraw(false, 5, 11, &[&id])[..]), None);
+        // Truncated section.
+        let mut data = profraw(false, 10, 16, &[&id]);
+        data.truncate(16 * 8 + 12);
+        assert_eq!(read_binary_ids(&mut &data[..]), None);
+        assert_eq!(read_binary_ids(&mut &b"\x7fELF"[..]), None);
+    }
+}
@@ -0,0 +1,246 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// `cargo llvm-cov trend [--plot <PATH>] <FILE|DIR>...` support.
+//
+// This reads reports exported by --summary-json (or --json) in the given
+// order (the JSON files in a directory are read in the order of their names,
+// so a directory can be used as the history of reports, e.g., by naming files
+// by date), and prints the coverage of each report or renders it as an SVG
+// chart that can be embedded in READMEs and dashboards.
+
+use std::{fmt::Write as _, io::Write as _};
+
+use anyhow::{bail, Context as _, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    cli::Args,
+    fs,
+    json::{xml_escape, LlvmCovJsonExport, SummaryCounts, SummaryJsonExport, SummaryTotals},
+    percent, term,
+};
+
+const WIDTH: f64 = 640.;
+const HEIGHT: f64 = 320.;
+const LEFT: f64 = 48.;
+const RIGHT: f64 = 112.;
+const TOP: f64 = 16.;
+const BOTTOM: f64 = 48.;
+
+struct Point {
+    label: String,
+    totals: SummaryTotals,
+}
+
+pub(crate) fn run(args: &mut Args) -> Result<()> {
+    term::set_coloring(&mut args.color);
+
+    let mut files = vec![];
+    for path in &args.trend_files {
+        if path.is_dir() {
+            let mut entries: Vec<Utf8PathBuf> = fs::read_dir(path)?
+                .filter_map(|e| Utf8PathBuf::from_path_buf(e.ok()?.path()).ok())
+                .filter(|p| p.extension() == Some("json"))
+                .collect();
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    if files.is_empty() {
+        bail!("no json files found in {}", args.trend_files[0]);
+    }
+    let points = files.iter().map(|f| load(f)).collect::<Result<Vec<_>>>()?;
+
+    match &args.plot {
+        Some(path) => {
+            fs::write(path, plot(&points))?;
+            status!("Finished", "chart saved to {path}");
+        }
+        None => {
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            let width = points.iter().map(|p| p.label.len()).max().unwrap_or(0).max(6);
+            writeln!(
+                stdout,
+                "{:<width$}  {:>9}  {:>9}  {:>9}",
+                "Report", "Lines", "Functions", "Regions"
+            )?;
+            for p in &points {
+                let t = &p.totals;
+                writeln!(
+                    stdout,
+                    "{:<width$}  {:>9}  {:>9}  {:>9}",
+                    p.label,
+                    percent(&t.lines),
+                    percent(&t.functions),
+                    percent(&t.regions)
+                )?;
+            }
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the totals from a report exported by --summary-json or --json.
+fn load(path: &Utf8Path) -> Result<Point> {
+    let summary = load_summary(path)?;
+    let label = match &summary.commit {
+        Some(commit) => commit.sha.get(..7).unwrap_or(&commit.sha).to_owned(),
+        None => path.file_stem().unwrap_or(path.as_str()).to_owned(),
+    };
+    Ok(Point { label, totals: summary.totals })
+}
+
+/// Loads the summary of a report exported by --summary-json or --json.
+pub(crate) fn load_summary(path: &Utf8Path) -> Result<SummaryJsonExport> {
+    let s = fs::read_to_string(path)?;
+    let value: serde_json::Value =
+        serde_json::from_str(&s).with_context(|| format!("failed to parse {path}"))?;
+    let summary = if value.get("schema_version").is_some() {
+        serde_json::from_value(value).with_context(|| format!("failed to parse {path}"))?
+    } else {
+        let json: LlvmCovJsonExport =
+            serde_json::from_value(value).with_context(|| format!("failed to parse {path}"))?;
+        SummaryJsonExport::from_llvm_cov_json_export(&json, |_| None)?
+    };
+    Ok(summary)
+}
+
+fn percent(counts: &SummaryCounts) -> String {
+    if counts.count == 0 {
+        "-".to_owned()
+    } else {
+        format!("{}%", percent::format(counts.percent))
+    }
+}
+
+/// Renders the coverage of each report as a line chart in SVG.
+fn plot(points: &[Point]) -> String {
+    let (w, h) = (WIDTH - LEFT - RIGHT, HEIGHT - TOP - BOTTOM);
+    #[allow(clippy::cast_precision_loss)]
+    let x = |i: usize| {
+        if points.len() == 1 {
+            LEFT + w / 2.
+        } else {
+            LEFT + w * i as f64 / (points.len() - 1) as f64
+        }
+    };
+    let y = |percent: f64| TOP + h * (100. - percent) / 100.;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg xmlns='http://www.w3.org/2000/svg' width='{WIDTH}' height='{HEIGHT}' \
+         viewBox='0 0 {WIDTH} {HEIGHT}' font-family='sans-serif' font-size='11'>\
+         <rect width='100%' height='100%' fill='white'/>"
+    );
+    for percent in [0., 25., 50., 75., 100.] {
+        let _ = write!(
+            svg,
+            "<line x1='{LEFT}' y1='{0}' x2='{1}' y2='{0}' stroke='#ddd'/>\
+             <text x='{2}' y='{0}' text-anchor='end' dominant-baseline='middle'>{percent}%</text>",
+            y(percent),
+            LEFT + w,
+            LEFT - 6.
+        );
+    }
+    // Show all labels if they fit, otherwise only the first and the last.
+    let show_all = points.len() <= 10;
+    for (i, p) in points.iter().enumerate() {
+        if show_all || i == 0 || i == points.len() - 1 {
+            let _ = write!(
+                svg,
+                "<text x='{}' y='{}' text-anchor='middle'>{}</text>",
+                x(i),
+                TOP + h + 16.,
+                xml_escape(&p.label)
+            );
+        }
+    }
+    let series: [(&str, &str, fn(&SummaryTotals) -> &SummaryCounts); 4] = [
+        ("lines", "#1f77b4", |t| &t.lines),
+        ("functions", "#2ca02c", |t| &t.functions),
+        ("regions", "#ff7f0e", |t| &t.regions),
+        ("branches", "#d62728", |t| &t.branches),
+    ];
+    let mut legend_y = TOP;
+    for (name, color, counts) in series {
+        // Reports without the kind of coverage (e.g., branches without --branch) are skipped.
+        let values: Vec<(f64, f64, &str, f64)> = points
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| counts(&p.totals).count != 0)
+            .map(|(i, p)| {
+                let percent = counts(&p.totals).percent;
+                (x(i), y(percent), &*p.label, percent)
+            })
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+        let line: Vec<String> = values.iter().map(|(x, y, ..)| format!("{x:.1},{y:.1}")).collect();
+        let _ = write!(
+            svg,
+            "<polyline fill='none' stroke='{color}' stroke-width='2' points='{}'/>",
+            line.join(" ")
+        );
+        for &(x, y, label, percent) in &values {
+            let _ = write!(
+                svg,
+                "<circle cx='{x:.1}' cy='{y:.1}' r='3' fill='{color}'>\
+                 <title>{} {name}: {percent:.2}%</title></circle>",
+                xml_escape(label)
+            );
+        }
+        let _ = write!(
+            svg,
+            "<rect x='{0}' y='{1}' width='10' height='10' fill='{color}'/>\
+             <text x='{2}' y='{3}' dominant-baseline='middle'>{name}</text>",
+            LEFT + w + 12.,
+            legend_y,
+            LEFT + w + 26.,
+            legend_y + 5.
+        );
+        legend_y += 18.;
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(label: &str, lines: (u64, u64)) -> Point {
+        #[allow(clippy::cast_precision_loss)]
+        let counts = |(count, covered): (u64, u64)| SummaryCounts {
+            count,
+            covered,
+            percent: if count == 0 { 0. } else { covered as f64 * 100. / count as f64 },
+        };
+        Point {
+            label: label.to_owned(),
+            totals: SummaryTotals {
+                lines: counts(lines),
+                functions: counts((2, 2)),
+                regions: counts(lines),
+                branches: counts((0, 0)),
+            },
+        }
+    }
+
+    #[test]
+    fn plot_svg() {
+        let svg = plot(&[point("a", (4, 2)), point("b", (4, 3)), point("c", (4, 4))]);
+        assert!(svg.starts_with("<svg "), "{svg}");
+        // lines: 50%, 75%, 100% at the left, center, and right of the chart
+        assert!(svg.contains("points='48.0,144.0 288.0,80.0 528.0,16.0'"), "{svg}");
+        assert!(svg.contains("<title>b lines: 75.00%</title>"), "{svg}");
+        assert!(svg.contains(">functions</text>"), "{svg}");
+        // No report has branch coverage.
+        assert!(!svg.contains(">branches</text>"), "{svg}");
+    }
+}
@@ -0,0 +1,83 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// --windows-long-paths support.
+//
+// On Windows, paths that exceed MAX_PATH (260 characters) can only be opened
+// by programs that don't opt in to long paths if they are written in the
+// verbatim form (`\\?\C:\...` or `\\?\UNC\server\share\...`). However,
+// verbatim paths that end up in the coverage data don't match the paths
+// given to llvm-cov and rustc (e.g., for --ignore-filename-regex and
+// --remap-path-prefix), and commonly used tools don't understand them.
+//
+// These functions only manipulate strings, so they work for paths generated on
+// Windows regardless of the host platform.
+//
+// Refs:
+// - https://learn.microsoft.com/en-us/windows/win32/fileio/naming-a-file#win32-file-namespaces
+// - https://learn.microsoft.com/en-us/windows/win32/fileio/maximum-file-path-limitation
+
+/// Returns the given path without the verbatim prefix, or `None` if the path
+/// doesn't have a verbatim prefix.
+///
+/// `\\?\C:\foo` is converted to `C:\foo`, and `\\?\UNC\server\share\foo` is
+/// converted to `\\server\share\foo`. Verbatim paths that have no
+/// non-verbatim equivalents (e.g., `\\?\Volume{...}\foo`) are left as is.
+pub(crate) fn strip_verbatim(path: &str) -> Option<String> {
+    let rest = path.strip_prefix(r"\\?\")?;
+    if let Some(unc) = rest.strip_prefix(r"UNC\") {
+        return Some(format!(r"\\{unc}"));
+    }
+    if is_drive_absolute(rest) {
+        return Some(rest.to_owned());
+    }
+    None
+}
+
+/// Returns the verbatim form of the given absolute Windows path, or `None` if
+/// the path is not an absolute Windows path or already in the verbatim form.
+///
+/// `/` is converted to `\`, because verbatim paths don't accept `/` as a
+/// separator.
+pub(crate) fn to_verbatim(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") {
+        return None;
+    }
+    let path = path.replace('/', "\\");
+    if is_drive_absolute(&path) {
+        return Some(format!(r"\\?\{path}"));
+    }
+    let unc = path.strip_prefix(r"\\")?;
+    if unc.is_empty() || unc.starts_with(['\\', '.', '?']) {
+        return None;
+    }
+    Some(format!(r"\\?\UNC\{unc}"))
+}
+
+fn is_drive_absolute(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbatim() {
+        assert_eq!(strip_verbatim(r"\\?\C:\foo\bar.rs").as_deref(), Some(r"C:\foo\bar.rs"));
+        assert_eq!(
+            strip_verbatim(r"\\?\UNC\server\share\a.rs").as_deref(),
+            Some(r"\\server\share\a.rs")
+        );
+        assert_eq!(strip_verbatim(r"\\?\Volume{0}\a.rs"), None);
+        assert_eq!(strip_verbatim(r"C:\foo\bar.rs"), None);
+        assert_eq!(strip_verbatim("/foo/bar.rs"), None);
+
+        assert_eq!(to_verbatim(r"C:\foo/bar").as_deref(), Some(r"\\?\C:\foo\bar"));
+        assert_eq!(to_verbatim(r"\\server\share\a").as_deref(), Some(r"\\?\UNC\server\share\a"));
+        assert_eq!(to_verbatim(r"\\?\C:\foo"), None);
+        assert_eq!(to_verbatim(r"\\.\pipe\foo"), None);
+        assert_eq!(to_verbatim("/foo/bar"), None);
+        assert_eq!(to_verbatim("foo"), None);
+    }
+}
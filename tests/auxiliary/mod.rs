@@ -3,7 +3,7 @@
 use std::{
     env,
     ffi::OsStr,
-    io::{Read, Seek, Write},
+    io::{Read, Seek, SeekFrom, Write},
     mem,
     path::{Path, PathBuf},
     process::{Command, ExitStatus, Stdio},
@@ -154,20 +154,30 @@ fn git_ls_files(dir: &Path, filters: &[&str]) -> Result<Vec<(String, PathBuf)>>
         .collect())
 }
 
-pub(crate) fn perturb_one_header(workspace_root: &Path) -> Result<Option<PathBuf>> {
+fn find_one_profraw(workspace_root: &Path) -> Result<Option<PathBuf>> {
     let target_dir = workspace_root.join("target").join("llvm-cov-target");
-    let path = fs::read_dir(target_dir)?.filter_map(Result::ok).find_map(|entry| {
+    Ok(fs::read_dir(target_dir)?.filter_map(Result::ok).find_map(|entry| {
         let path = entry.path();
         if path.extension() == Some(OsStr::new("profraw")) {
             Some(path)
         } else {
             None
         }
-    });
+    }))
+}
+
+pub(crate) fn perturb_one_header(workspace_root: &Path) -> Result<Option<PathBuf>> {
+    let path = find_one_profraw(workspace_root)?;
     path.as_ref().map(perturb_header).transpose()?;
     Ok(path)
 }
 
+pub(crate) fn perturb_one_version(workspace_root: &Path) -> Result<Option<PathBuf>> {
+    let path = find_one_profraw(workspace_root)?;
+    path.as_ref().map(perturb_version).transpose()?;
+    Ok(path)
+}
+
 const INSTR_PROF_RAW_MAGIC_64: u64 = (255_u64) << 56
     | ('l' as u64) << 48
     | ('p' as u64) << 40
@@ -175,9 +185,9 @@ const INSTR_PROF_RAW_MAGIC_64: u64 = (255_u64) << 56
     | ('o' as u64) << 24
     | ('f' as u64) << 16
     | ('r' as u64) << 8
-    | (129_u64);
+    | 0x81_u64;
 
-fn perturb_header(path: impl AsRef<Path>) -> Result<()> {
+pub(crate) fn perturb_header(path: impl AsRef<Path>) -> Result<()> {
     let mut file = fs::OpenOptions::new().read(true).write(true).open(path.as_ref())?;
     let mut magic = {
         let mut buf = vec![0_u8; mem::size_of::<u64>()];
@@ -191,6 +201,20 @@ fn perturb_header(path: impl AsRef<Path>) -> Result<()> {
     Ok(())
 }
 
+// Sets the raw profile format version to one that no llvm-profdata supports.
+fn perturb_version(path: impl AsRef<Path>) -> Result<()> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(path.as_ref())?;
+    let mut buf = vec![0_u8; mem::size_of::<u64>() * 2];
+    file.read_exact(&mut buf)?;
+    let magic = u64::from_ne_bytes(buf[..8].try_into().unwrap());
+    assert_eq!(magic, INSTR_PROF_RAW_MAGIC_64);
+    let version = u64::from_ne_bytes(buf[8..].try_into().unwrap());
+    let version = version & 0xffff_ffff_0000_0000 | 3;
+    file.seek(SeekFrom::Start(8))?;
+    file.write_all(&version.to_ne_bytes())?;
+    Ok(())
+}
+
 #[ext(CommandExt)]
 impl Command {
     #[track_caller]
@@ -231,8 +255,8 @@ impl Command {
 }
 
 pub(crate) struct AssertOutput {
-    stdout: String,
-    stderr: String,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
     status: ExitStatus,
 }
 
@@ -255,6 +279,20 @@ impl AssertOutput {
         self
     }
 
+    /// Receives a line(`\n`)-separated list of patterns and asserts whether stderr does not contain each pattern.
+    #[track_caller]
+    pub(crate) fn stderr_not_contains(&self, pats: impl AsRef<str>) -> &Self {
+        for pat in line_separated(pats.as_ref()) {
+            assert!(
+                !self.stderr.contains(pat),
+                "assertion failed: `!self.stderr.contains(..)`:\n\nEXPECTED:\n{0}\n{pat}\n{0}\n\nACTUAL:\n{0}\n{1}\n{0}\n",
+                "-".repeat(60),
+                self.stderr
+            );
+        }
+        self
+    }
+
     /// Receives a line(`\n`)-separated list of patterns and asserts whether stdout contains each pattern.
     #[track_caller]
     pub(crate) fn stdout_contains(&self, pats: impl AsRef<str>) -> &Self {
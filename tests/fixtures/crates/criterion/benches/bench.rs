@@ -0,0 +1,8 @@
+// Mimics how criterion decides the mode: `cargo bench` passes `--bench`, and
+// `cargo test` doesn't, which makes criterion run each benchmark only once.
+fn main() {
+    assert!(!std::env::args().any(|arg| arg == "--bench"));
+    let home = std::env::var("CRITERION_HOME").unwrap();
+    assert!(home.ends_with("criterion"), "{home}");
+    assert_eq!(criterion::fibonacci(5), 8);
+}
@@ -0,0 +1,14 @@
+pub fn fibonacci(n: u64) -> u64 {
+    match n {
+        0 | 1 => 1,
+        n => fibonacci(n - 1) + fibonacci(n - 2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn fibonacci() {
+        assert_eq!(super::fibonacci(0), 1);
+    }
+}
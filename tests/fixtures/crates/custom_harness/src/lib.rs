@@ -0,0 +1,3 @@
+pub fn parent() {}
+
+pub fn child() {}
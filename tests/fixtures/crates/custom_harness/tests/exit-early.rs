@@ -0,0 +1,8 @@
+extern "C" {
+    fn _exit(status: i32) -> !;
+}
+
+fn main() {
+    // Exit without running the exit handlers that write profile data.
+    unsafe { _exit(0) }
+}
@@ -0,0 +1,15 @@
+use std::{env, process::Command};
+
+fn main() {
+    // The environment variables set by cargo-llvm-cov are passed to custom harnesses.
+    assert_eq!(env::var("CARGO_LLVM_COV").unwrap(), "1");
+    assert!(env::var_os("LLVM_PROFILE_FILE").is_some());
+    if env::args().nth(1).as_deref() == Some("child") {
+        custom_harness::child();
+        return;
+    }
+    custom_harness::parent();
+    // Child processes write their own profile data.
+    let status = Command::new(env::current_exe().unwrap()).arg("child").status().unwrap();
+    assert!(status.success());
+}
@@ -0,0 +1,9 @@
+pub fn used(x: u32) -> u32 {
+    if x > 1 {
+        x * 2
+    } else {
+        x
+    }
+}
+
+pub fn unused() {}
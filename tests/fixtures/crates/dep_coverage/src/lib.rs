@@ -0,0 +1,8 @@
+pub fn func() -> u32 {
+    helper::used(2)
+}
+
+#[test]
+fn test() {
+    assert_eq!(func(), 4);
+}
@@ -0,0 +1,44 @@
+use std::fmt;
+
+// Recent compilers don't instrument #[automatically_derived] impls, so use
+// impls generated by a macro_rules to emulate old compilers or derive macros
+// that don't emit that attribute.
+macro_rules! impl_debug {
+    ($ty:ident) => {
+        impl fmt::Debug for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                if self.x == 0 {
+                    f.write_str("zero")
+                } else {
+                    f.debug_struct(stringify!($ty)).field("x", &self.x).finish()
+                }
+            }
+        }
+    };
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl_debug!(Point);
+
+impl Point {
+    pub fn manhattan(self) -> i32 {
+        if self.x < 0 {
+            -self.x + self.y.abs()
+        } else {
+            self.x + self.y.abs()
+        }
+    }
+}
+
+#[test]
+fn test() {
+    let p = Point { x: 1, y: -2 };
+    assert_eq!(p.clone().manhattan(), 3);
+    assert!(p > Point::default());
+    assert_eq!(format!("{p:?}"), "Point { x: 1 }");
+}
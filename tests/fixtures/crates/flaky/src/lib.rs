@@ -0,0 +1,19 @@
+use std::path::Path;
+
+// Takes a different path after the first run.
+pub fn visit(marker: &Path) -> bool {
+    if marker.exists() {
+        false
+    } else {
+        std::fs::write(marker, "").unwrap();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn visit() {
+        super::visit(&std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("visited"));
+    }
+}
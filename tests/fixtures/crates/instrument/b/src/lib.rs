@@ -0,0 +1,8 @@
+pub fn func(x: u32) -> u32 {
+    a::func(x)
+}
+
+#[test]
+fn test() {
+    assert_eq!(func(0), 0);
+}
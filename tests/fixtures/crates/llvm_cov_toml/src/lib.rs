@@ -0,0 +1,14 @@
+mod generated;
+
+pub fn func(x: u32) -> u32 {
+    if x == 0 {
+        generated::zero()
+    } else {
+        x + 1
+    }
+}
+
+#[test]
+fn test() {
+    assert_eq!(func(1), 2);
+}
@@ -0,0 +1,3 @@
+fn main() {
+    multi_bin::func(0);
+}
@@ -0,0 +1,4 @@
+fn main() {
+    let x = std::env::args().nth(1).unwrap().parse::<u8>().unwrap();
+    multi_bin::func(x);
+}
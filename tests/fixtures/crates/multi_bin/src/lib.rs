@@ -0,0 +1,8 @@
+pub fn func(x: u8) {
+    match x {
+        0 => {}
+        1 => {}
+        2 => {}
+        _ => {}
+    }
+}
@@ -0,0 +1,12 @@
+use std::{env, fs, path::Path};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("generated.rs"),
+        "pub fn generated(x: u32) -> u32 {\n    if x == 0 {\n        0\n    } else {\n        x - 1\n    }\n}\n",
+    )
+    .unwrap();
+}
@@ -0,0 +1,12 @@
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/generated.rs"));
+}
+
+pub fn func(x: u32) -> u32 {
+    generated::generated(x)
+}
+
+#[test]
+fn test() {
+    assert_eq!(func(1), 0);
+}
@@ -0,0 +1,3 @@
+pub(crate) fn zero() -> u32 {
+    0
+}
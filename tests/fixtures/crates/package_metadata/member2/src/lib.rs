@@ -0,0 +1,15 @@
+mod generated;
+mod skipped;
+
+pub fn func(x: u32) -> u32 {
+    if x == 0 {
+        generated::zero() + skipped::zero()
+    } else {
+        x + 1
+    }
+}
+
+#[test]
+fn test() {
+    assert_eq!(func(1), 2);
+}
@@ -0,0 +1,12 @@
+pub fn func(x: u32) -> u32 {
+    if x == 0 {
+        0
+    } else {
+        x + 1
+    }
+}
+
+#[test]
+fn test() {
+    assert_eq!(func(1), 2);
+}
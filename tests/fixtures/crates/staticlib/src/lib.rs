@@ -0,0 +1,8 @@
+#[no_mangle]
+pub extern "C" fn add(a: i32, b: i32) -> i32 {
+    if a > 0 {
+        a + b
+    } else {
+        b
+    }
+}
@@ -0,0 +1,34 @@
+pub fn func(x: i32) -> bool {
+    if x < 0 {
+        true
+    } else {
+        false
+    }
+}
+
+#[test]
+fn test() {
+    let f = |x| {
+        if func(x) {
+            1
+        } else {
+            0
+        }
+    };
+    assert_eq!(f(1), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn helper() -> bool {
+        func(-1)
+    }
+
+    #[test]
+    #[allow(unused_variables)]
+    fn test() {
+        assert!(helper());
+    }
+}
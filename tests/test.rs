@@ -4,17 +4,34 @@
 
 mod auxiliary;
 
-use std::path::Path;
+use std::{ffi::OsStr, path::Path, process::Command, str, thread, time::Duration};
 
 use anyhow::Context as _;
 use auxiliary::{
-    assert_output, cargo_llvm_cov, fixtures_path, normalize_output, perturb_one_header,
-    test_project, test_report, CommandExt,
+    assert_output, cargo_llvm_cov, fixtures_path, normalize_output, perturb_header,
+    perturb_one_header, perturb_one_version, test_project, test_report, CommandExt,
 };
 use fs_err as fs;
 use tempfile::tempdir;
 
-const SUBCOMMANDS: &[&str] = &["", "run", "report", "clean", "show-env", "nextest"];
+const SUBCOMMANDS: &[&str] = &[
+    "",
+    "run",
+    "report",
+    "show",
+    "annotate",
+    "diff",
+    "trend",
+    "html",
+    "install-hook",
+    "coveralls-finish",
+    "collect",
+    "snapshot",
+    "clean",
+    "show-env",
+    "nextest",
+    "install",
+];
 
 fn test_set() -> Vec<(&'static str, &'static [&'static str])> {
     vec![
@@ -49,6 +66,7 @@ fn real1() {
     run("real1", "manifest_path", &["--manifest-path", "member1/member2/Cargo.toml"], &[]);
     run("real1", "package1", &["--package", "member2"], &[]);
     run("real1", "exclude", &["--all", "--exclude", "crate1"], &[]);
+    run("real1", "max_file_lines", &["--all", "--max-file-lines", "10"], &[]);
 }
 
 #[test]
@@ -86,6 +104,173 @@ fn no_test() {
     }
 }
 
+#[test]
+fn out_dir() {
+    // Code generated in OUT_DIR is excluded by default.
+    run("out_dir", "out_dir", &[], &[]);
+}
+
+#[test]
+fn derives() {
+    run("derives", "derives", &[], &[]);
+    run("derives", "exclude_derives", &["--exclude-derives"], &[]);
+}
+
+#[test]
+fn dep_coverage() {
+    run("dep_coverage", "dep_coverage", &[], &[]);
+    run("dep_coverage", "helper", &["--dep-coverage", "helper"], &[]);
+
+    let workspace_root = test_project("dep_coverage").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--dep-coverage", "dep_coverage"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("`dep_coverage` is a member of the workspace");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--include-deps-matching", "("])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("invalid --include-deps-matching `(`");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--include-deps-matching", "^help"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("helper/src/lib.rs");
+}
+
+#[test]
+fn test_functions() {
+    run("test_functions", "test_functions", &[], &[]);
+    // Helpers in #[cfg(test)] modules are kept.
+    run("test_functions", "exclude_test_functions", &["--exclude-test-functions"], &[]);
+}
+
+#[test]
+fn llvm_cov_toml() {
+    // Options in llvm-cov.toml are applied unless the corresponding flags are passed.
+    run("llvm_cov_toml", "llvm_cov_toml", &[], &[]);
+    run(
+        "llvm_cov_toml",
+        "override",
+        &["--ignore-filename-regex", "lib", "--fail-under-lines", "0"],
+        &[],
+    );
+    run("llvm_cov_toml", "cov_profile", &["--cov-profile", "generated"], &[]);
+}
+
+#[test]
+fn path_thresholds() {
+    let workspace_root = test_project("llvm_cov_toml").unwrap();
+    let config = workspace_root.path().join("llvm-cov.toml");
+    fs::write(&config, "[path-thresholds]\n\"src/generated.rs\" = 100\n\"src/*.rs\" = 50\n")
+        .unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains(
+            "line coverage of files matching `src/generated.rs` (0.00%) is less than 100% \
+             specified in `path-thresholds`",
+        );
+    fs::write(&config, "[path-thresholds]\n\"src/*.rs\" = 50\n\"tests/**\" = 100\n").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("no source files in the report match `tests/**` in `path-thresholds`");
+    fs::write(&config, "[path-thresholds]\n\"src/*.rs\" = 50\n").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+}
+
+#[cfg_attr(not(unix), ignore = "uses touch command to set modification time")]
+#[test]
+fn clean_expired() {
+    let workspace_root = test_project("llvm_cov_toml").unwrap();
+    fs::write(workspace_root.path().join("llvm-cov.toml"), "profraw-max-age = 1\n").unwrap();
+    let target_dir = workspace_root.path().join("target").join("llvm-cov-target");
+    fs::create_dir_all(&target_dir).unwrap();
+    let expired = target_dir.join("expired.profraw");
+    let fresh = target_dir.join("fresh.profraw");
+    fs::write(&expired, "").unwrap();
+    fs::write(&fresh, "").unwrap();
+    let touch = Command::new("touch").args(["-t", "200001010000"]).arg(&expired).status();
+    assert!(touch.unwrap().success());
+    cargo_llvm_cov("clean")
+        .args(["--color", "never", "--expired"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("removed 1 expired profile data files");
+    assert!(!expired.exists());
+    assert!(fresh.exists());
+
+    let config = "profraw-max-age = 9223372036854775807\n";
+    fs::write(workspace_root.path().join("llvm-cov.toml"), config).unwrap();
+    cargo_llvm_cov("clean")
+        .args(["--color", "never", "--expired"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("`profraw-max-age` must be less than or equal to");
+}
+
+#[test]
+fn summary_by_owner() {
+    let workspace_root = test_project("llvm_cov_toml").unwrap();
+    let config = workspace_root.path().join("llvm-cov.toml");
+    fs::write(&config, "[owner-thresholds]\n\"@org/codegen\" = 100\n").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("CODEOWNERS file not found");
+    fs::create_dir(workspace_root.path().join(".github")).unwrap();
+    fs::write(
+        workspace_root.path().join(".github/CODEOWNERS"),
+        "* @org/core\n/src/generated.rs @org/codegen\n",
+    )
+    .unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--summary-by-owner"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stdout_contains("Owner")
+        .stdout_contains("@org/codegen")
+        .stdout_contains("@org/core")
+        .stderr_contains(
+            "line coverage of files owned by `@org/codegen` (0.00%) is less than 100% specified \
+             in `owner-thresholds`",
+        );
+    fs::write(&config, "[owner-thresholds]\n\"@org/core\" = 50\n\"@org/docs\" = 50\n").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("no source files in the report are owned by `@org/docs`");
+
+    fs::write(&config, "").unwrap();
+    let output_path = &workspace_root.path().join("summary.json");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--summary-by-owner", "--summary-json", "--output-path"])
+        .arg(output_path)
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(output_path).unwrap()).unwrap();
+    let owners = summary["owners"].as_array().unwrap();
+    let owners: Vec<_> = owners.iter().map(|o| o["owner"].as_str().unwrap()).collect();
+    assert_eq!(owners, ["@org/codegen", "@org/core"]);
+    assert_eq!(summary["owners"][0]["totals"]["lines"]["covered"], 0);
+    assert!(summary["owners"][1]["totals"]["lines"]["covered"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn package_metadata() {
+    run("package_metadata", "package_metadata", &[], &[]);
+}
+
 #[test]
 fn bin_crate() {
     run("bin_crate", "bin_crate", &[], &[]);
@@ -100,183 +285,2216 @@ fn bin_crate() {
     }
 }
 
-// nightly-2023-12-10 fixed bug in report generation, so the latest report is not the same as the old report.
-#[rustversion::attr(before(1.76), ignore)]
 #[test]
-fn instantiations() {
-    // TODO: fix https://github.com/taiki-e/cargo-llvm-cov/issues/43
-    run("instantiations", "instantiations", &[], &[]);
+fn multi_bin() {
+    let model = "multi_bin";
+    let name = "multi_bin";
+    let id = format!("{model}/{name}");
+    for (extension, args2) in test_set() {
+        let args = &[args2, &["--bin", "a", "--bin", "b", "--", "1"]].concat();
+        test_report(model, name, extension, Some("run"), args, &[]).context(id.clone()).unwrap();
+    }
+
+    let name = "args_file";
+    let id = format!("{model}/{name}");
+    for (extension, args2) in test_set() {
+        let args = &[args2, &["--bin", "b", "--args-file", "args.txt"]].concat();
+        test_report(model, name, extension, Some("run"), args, &[]).context(id.clone()).unwrap();
+    }
+}
+
+// nightly-2023-12-10 fixed bug in report generation, so the latest report is not the same as the old report.
+#[rustversion::attr(before(1.76), ignore)]
+#[test]
+fn instantiations() {
+    // TODO: fix https://github.com/taiki-e/cargo-llvm-cov/issues/43
+    run("instantiations", "instantiations", &[], &[]);
+}
+
+// nightly-2023-12-10 fixed bug in report generation, so the latest report is not the same as the old report.
+#[rustversion::attr(before(1.76), ignore)]
+#[test]
+fn cargo_config() {
+    run("cargo_config", "cargo_config", &[], &[]);
+    run("cargo_config_toml", "cargo_config_toml", &[], &[]);
+}
+
+// feature(coverage_attribute) requires nightly
+#[rustversion::attr(not(nightly), ignore = "requires nightly")]
+#[test]
+fn no_coverage() {
+    let model = "no_coverage";
+    let id = format!("{model}/{model}");
+    for (extension, args2) in test_set() {
+        // TODO: On windows, the order of the instantiations in the generated coverage report will be different.
+        if extension == "full.json" && cfg!(windows) {
+            continue;
+        }
+        test_report(model, model, extension, None, args2, &[]).context(id.clone()).unwrap();
+    }
+
+    let name = "no_cfg_coverage";
+    let id = format!("{model}/{name}");
+    for (extension, args2) in test_set() {
+        // TODO: On windows, the order of the instantiations in the generated coverage report will be different.
+        if extension == "full.json" && cfg!(windows) {
+            continue;
+        }
+        test_report(model, name, extension, None, &[args2, &["--no-cfg-coverage"]].concat(), &[])
+            .context(id.clone())
+            .unwrap();
+    }
+}
+
+// feature(coverage_attribute) requires nightly
+#[rustversion::attr(not(nightly), ignore = "requires nightly")]
+#[test]
+fn coverage_helper() {
+    let model = "coverage_helper";
+    let id = format!("{model}/{model}");
+    for (extension, args2) in test_set() {
+        // TODO: On windows, the order of the instantiations in the generated coverage report will be different.
+        if extension == "full.json" && cfg!(windows) {
+            continue;
+        }
+        test_report(model, model, extension, None, args2, &[]).context(id.clone()).unwrap();
+    }
+}
+
+// The order of the instantiations in the generated coverage report will be different depending on the version.
+#[rustversion::attr(
+    not(nightly),
+    ignore = "order of instantiations in the report differs between toolchain versions"
+)]
+#[test]
+fn merge() {
+    // The order of the instantiations in the generated coverage report will be different depending on the platform.
+    if !cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+        return;
+    }
+    let output_dir = fixtures_path().join("coverage-reports").join("merge");
+    merge_with_failure_mode(&output_dir, false);
+}
+
+#[test]
+fn merge_failure_mode_all() {
+    let tempdir = tempdir().unwrap();
+    merge_with_failure_mode(tempdir.path(), true);
+}
+
+#[test]
+fn merge_quarantine() {
+    let workspace_root = test_project("merge").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--features", "a"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--features", "b"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let perturbed = perturb_one_header(workspace_root.path()).unwrap().unwrap();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--quarantine", "--summary-only", "--json"])
+        .env_remove("CARGO_LLVM_COV_DENY_WARNINGS")
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("1 profraw files could not be merged")
+        .stderr_contains("unrecognized instrumentation profile encoding format");
+    assert!(!perturbed.exists());
+    let quarantine_dir = perturbed.with_file_name(format!(
+        "{}-quarantine",
+        workspace_root.path().file_name().unwrap().to_str().unwrap()
+    ));
+    assert!(quarantine_dir.join(perturbed.file_name().unwrap()).exists());
+    assert!(quarantine_dir.join("quarantine.json").exists());
+}
+
+#[test]
+fn merge_batches() {
+    let workspace_root = test_project("merge").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--features", "a"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let target_dir = workspace_root.path().join("target").join("llvm-cov-target");
+    let name = workspace_root.path().file_name().unwrap().to_str().unwrap();
+    let profraw = fs::read_dir(&target_dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.extension() == Some(OsStr::new("profraw")))
+        .unwrap();
+    // The corrupt files are sorted before the others, so that the first batch
+    // only contains corrupt files.
+    for i in 0..300 {
+        let corrupt = target_dir.join(format!("{name}-0-corrupt{i:03}.profraw"));
+        fs::copy(&profraw, &corrupt).unwrap();
+        perturb_header(&corrupt).unwrap();
+        fs::copy(&profraw, target_dir.join(format!("{name}-valid{i:03}.profraw"))).unwrap();
+    }
+    let no_temp_files = || {
+        for e in fs::read_dir(&target_dir).unwrap() {
+            let file_name = e.unwrap().file_name();
+            let file_name = file_name.to_str().unwrap();
+            assert!(!file_name.contains("-batch"), "{file_name}");
+            let list = file_name.strip_prefix(&format!("{name}-profraw-list"));
+            assert!(list.is_none_or(str::is_empty), "{file_name}");
+        }
+    };
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--summary-only"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("unrecognized instrumentation profile encoding format");
+    no_temp_files();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--summary-only", "--failure-mode", "all"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("Merged 3/3 batches")
+        .stdout_contains("TOTAL");
+    no_temp_files();
+}
+
+#[test]
+fn version_mismatch() {
+    let workspace_root = test_project("merge").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--features", "a"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    perturb_one_version(workspace_root.path()).unwrap().unwrap();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--quarantine"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("was generated with raw profile format version 3")
+        .stderr_contains("--find-llvm-tools");
+}
+
+#[cfg_attr(not(target_os = "linux"), ignore)] // binary IDs are only recorded in ELF binaries
+#[test]
+fn merge_toolchains() {
+    let workspace_root = test_project("merge").unwrap();
+    let target_dir = workspace_root.path().join("target/llvm-cov-target");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--features", "a"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    // Simulate binaries built by another toolchain that were not run this time.
+    for entry in fs::read_dir(&target_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "profraw") {
+            fs::remove_file(path).unwrap();
+        }
+    }
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--features", "b"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--merge-toolchains"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains(
+            "excluded 1 object files whose binary ID doesn't appear in the profile data",
+        )
+        .stdout_contains("TOTAL");
+
+    perturb_one_version(workspace_root.path()).unwrap().unwrap();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--merge-toolchains"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("was generated with raw profile format version 3")
+        .stderr_contains("no other installed toolchain has llvm-tools that support this version");
+}
+
+#[test]
+fn object_files_cache() {
+    let workspace_root = test_project("real1").unwrap();
+    let target_dir = workspace_root.path().join("target/llvm-cov-target");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    for fresh in [false, true] {
+        let output = cargo_llvm_cov("report")
+            .args(["--color", "never", "-v"])
+            .current_dir(workspace_root.path())
+            .assert_success();
+        if fresh {
+            output.stderr_contains("Fresh list of object files");
+        } else {
+            output.stderr_not_contains("Fresh list of object files");
+        }
+    }
+    // Overwriting a file in place doesn't change the modification time of the
+    // directory that contains it.
+    let list = fs::read_dir(&target_dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.to_string_lossy().ends_with("-object-files"))
+        .unwrap();
+    let object = fs::read_to_string(list).unwrap().lines().nth(1).unwrap().to_owned();
+    let object = workspace_root.path().join(object);
+    thread::sleep(Duration::from_millis(10));
+    fs::write(&object, fs::read(&object).unwrap()).unwrap();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "-v"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_not_contains("Fresh list of object files");
+}
+
+#[test]
+fn package_rustflags() {
+    let workspace_root = test_project("package_rustflags").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--workspace"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("member2/src/lib.rs")
+        .stdout_not_contains("member1/src/lib.rs");
+
+    // Conflicts with the rustc workspace wrapper configured by the user.
+    if cfg!(unix) {
+        cargo_llvm_cov("")
+            .args(["--color", "never", "--workspace"])
+            .env("RUSTC_WORKSPACE_WRAPPER", "env")
+            .current_dir(workspace_root.path())
+            .assert_failure()
+            .stderr_contains("may not be used together with rustc workspace wrapper");
+    }
+}
+
+#[test]
+fn instrument() {
+    let workspace_root = test_project("instrument").unwrap();
+    // Tests of b are still instrumented, but b is not included in the report.
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--workspace", "--instrument", "a"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("a/src/lib.rs")
+        .stdout_not_contains("b/src/lib.rs");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--workspace", "--instrument", "a", "--report-include", "b"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("b/src/lib.rs")
+        .stdout_not_contains("a/src/lib.rs");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--workspace", "--instrument", "c"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("`c` specified by --instrument is not a member of the workspace");
+}
+
+#[test]
+fn install() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    // Use --debug to avoid the warning about opt-level of the release profile.
+    let output = cargo_llvm_cov("install")
+        .args(["--color", "never", "--debug"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    output.stderr_contains("then run `cargo llvm-cov report`");
+    let llvm_profile_file =
+        output.stderr.split_once("LLVM_PROFILE_FILE=").unwrap().1.split_once(',').unwrap().0;
+    let target_dir = workspace_root.path().join("target/llvm-cov-target");
+    let bin = format!("install/bin/bin_crate{}", std::env::consts::EXE_SUFFIX);
+    std::process::Command::new(target_dir.join(bin))
+        .arg("1")
+        .env("LLVM_PROFILE_FILE", llvm_profile_file)
+        .assert_success();
+    cargo_llvm_cov("report")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("src/main.rs");
+    cargo_llvm_cov("install")
+        .args(["--color", "never", "--release"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("invalid option '--release' for subcommand 'install'");
+}
+
+// Uses cc to build the external harness.
+#[cfg(target_os = "linux")]
+#[test]
+fn external_harness() {
+    let workspace_root = test_project("staticlib").unwrap();
+    let dir = workspace_root.path();
+    let output =
+        cargo_llvm_cov("show-env").arg("--separate-target-dir").current_dir(dir).assert_success();
+    let env: Vec<_> = output
+        .stdout
+        .lines()
+        .filter_map(|l| l.split_once('='))
+        .map(|(k, v)| (k.to_owned(), v.trim_matches('\'').to_owned()))
+        .collect();
+    Command::new("cargo").arg("build").envs(env.iter().cloned()).current_dir(dir).assert_success();
+    let harness = dir.join("target/harness");
+    let build_harness = |flags: &[&str]| {
+        Command::new("cc")
+            .arg("harness.c")
+            .args(flags)
+            .arg("target/llvm-cov-target/debug/libstaticlib.a")
+            .args(["-lpthread", "-ldl", "-lm", "-o"])
+            .arg(&harness)
+            .current_dir(dir)
+            .assert_success();
+        Command::new(&harness).envs(env.clone()).assert_success();
+    };
+    // The profiler runtime is not linked without -u __llvm_profile_runtime.
+    build_harness(&[]);
+    cargo_llvm_cov("report")
+        .args(["--color", "never"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("linked with `-Wl,-u,__llvm_profile_runtime`");
+    build_harness(&["-Wl,-u,__llvm_profile_runtime"]);
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--lcov", "--object", "target/harness"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains("DA:2,1\nDA:3,1\nDA:4,1\nDA:6,0\nDA:8,1");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--object", "target/missing"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("object file `target/missing` specified by --object not found");
+}
+
+#[cfg_attr(not(target_os = "linux"), ignore)] // binary IDs are only recorded in ELF binaries
+#[test]
+fn custom_harness() {
+    let workspace_root = test_project("custom_harness").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--test", "exit-early"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains(
+            "`exit-early` target of `custom_harness` (harness = false) generated no profile data",
+        );
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--test", "spawn", "--fail-uncovered-functions", "0"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+}
+
+#[test]
+fn criterion() {
+    let workspace_root = test_project("criterion").unwrap();
+    // Both unit tests and benches are run.
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--criterion", "--fail-uncovered-lines", "0"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("Running benches/bench.rs");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--criterion", "--doctests"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--criterion may not be used together with --doctests");
+}
+
+#[test]
+fn append() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    // The binary is still run even though the warning is promoted to an error.
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--append", "--no-report", "--", "0"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--append is passed, but no profile data of the previous runs was found");
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--no-report", "--", "1"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--append", "--lcov", "--", "2"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("DA:3,1\nDA:4,1\nDA:5,1\nDA:6,0");
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--append", "--no-clean"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--append may not be used together with --no-clean");
+}
+
+#[test]
+fn timeout() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    let main = workspace_root.path().join("src/main.rs");
+    let src = fs::read_to_string(&main).unwrap().replace(
+        "_ => {}",
+        "_ => loop {\n            std::thread::sleep(std::time::Duration::from_millis(10));\n        },",
+    );
+    fs::write(&main, src).unwrap();
+    let output = cargo_llvm_cov("run")
+        .args(["--color", "never", "--timeout", "1", "--lcov", "--", "3"])
+        .current_dir(workspace_root.path())
+        .assert_failure();
+    output.stderr_contains("error: process timed out after 1s");
+    // Continuous mode keeps the coverage of the process killed by the signal.
+    if cfg!(target_os = "linux") {
+        output.stdout_contains("DA:3,0\nDA:4,0\nDA:5,0\nDA:6,");
+    }
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--kill-after", "1", "--", "0"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--kill-after can only be used together with --timeout");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--timeout", "1"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("invalid option '--timeout'");
+}
+
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+#[test]
+fn dump_interval() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    let main = workspace_root.path().join("src/main.rs");
+    // The binary exits when the report is generated while it runs.
+    let src = fs::read_to_string(&main).unwrap().replace(
+        "_ => {}",
+        "_ => while !std::path::Path::new(\"out.lcov\").exists() {\n            \
+         std::thread::sleep(std::time::Duration::from_millis(10));\n        },",
+    );
+    fs::write(&main, src).unwrap();
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--dump-interval", "1", "--timeout", "60"])
+        .args(["--lcov", "--output-path", "out.lcov", "--", "3"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let lcov = fs::read_to_string(workspace_root.path().join("out.lcov")).unwrap();
+    assert!(lcov.contains("DA:3,0\nDA:4,0\nDA:5,0\nDA:6,"), "{lcov}");
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--dump-interval", "0", "--", "0"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--dump-interval must be greater than 0");
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--dump-interval", "1", "--no-report", "--", "0"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--dump-interval may not be used together with --no-report");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--dump-interval", "1"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("invalid option '--dump-interval'");
+}
+
+#[test]
+fn collect() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    let target_dir = workspace_root.path().join("target/llvm-cov-target");
+    let profraw_count = || {
+        fs::read_dir(&target_dir)
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension() == Some(OsStr::new("profraw")))
+            .count()
+    };
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--no-report", "--", "1"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    assert_eq!(profraw_count(), 1);
+    // Profraw files modified within 2 seconds may still be being written.
+    thread::sleep(Duration::from_secs(3));
+    cargo_llvm_cov("collect")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("Merged 1 profraw files into");
+    assert_eq!(profraw_count(), 0);
+    cargo_llvm_cov("collect")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("no profraw files to merge in");
+    // The report includes both the rolling profdata and the new profraw files.
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--append", "--lcov", "--output-path", "out.lcov", "--", "2"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let lcov = fs::read_to_string(workspace_root.path().join("out.lcov")).unwrap();
+    assert!(lcov.contains("DA:3,0\nDA:4,1\nDA:5,1\nDA:6,0"), "{lcov}");
+    // The rolling profdata is removed along with the profraw files.
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--lcov", "--output-path", "out.lcov", "--", "2"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let lcov = fs::read_to_string(workspace_root.path().join("out.lcov")).unwrap();
+    assert!(lcov.contains("DA:3,0\nDA:4,0\nDA:5,1\nDA:6,0"), "{lcov}");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--daemon"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("invalid option '--daemon' for subcommand 'report'");
+}
+
+#[test]
+fn collect_quarantine() {
+    let workspace_root = test_project("merge").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--features", "a"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--features", "b"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let perturbed = perturb_one_header(workspace_root.path()).unwrap().unwrap();
+    // Profraw files modified within 2 seconds may still be being written.
+    thread::sleep(Duration::from_secs(3));
+    // The invalid profraw file is moved to the quarantine directory instead of
+    // making every pass of --daemon fail.
+    cargo_llvm_cov("collect")
+        .args(["--color", "never"])
+        .env_remove("CARGO_LLVM_COV_DENY_WARNINGS")
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("1 profraw files could not be merged")
+        .stderr_contains("Merged 1 profraw files into");
+    assert!(!perturbed.exists());
+    let quarantine_dir = perturbed.with_file_name(format!(
+        "{}-quarantine",
+        workspace_root.path().file_name().unwrap().to_str().unwrap()
+    ));
+    assert!(quarantine_dir.join(perturbed.file_name().unwrap()).exists());
+    cargo_llvm_cov("collect")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("no profraw files to merge in");
+}
+
+#[test]
+fn snapshot() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--no-report", "--", "1"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    cargo_llvm_cov("snapshot")
+        .args(["--color", "never", "save", "before"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("snapshot `before` saved to");
+    // The snapshot is kept when the profile data is removed before running tests.
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--since-snapshot", "before", "--", "2"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains(
+            "lines: 57.14% -> 57.14% since snapshot `before`, newly covered lines: 1\n    \
+             src/main.rs: 5",
+        )
+        .stdout_not_contains("TOTAL");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--since-snapshot", "after"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("snapshot `after` not found in");
+    cargo_llvm_cov("snapshot")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("`cargo llvm-cov snapshot` requires a command: save");
+    cargo_llvm_cov("snapshot")
+        .args(["--color", "never", "save", "../before"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("invalid snapshot name `../before`");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn stripped_binary() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--keep-coverage-mapping", "--no-report", "--", "1"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let sysroot = Command::new("rustc").args(["--print", "sysroot"]).output().unwrap().stdout;
+    let rustlib = Path::new(str::from_utf8(&sysroot).unwrap().trim()).join("lib/rustlib");
+    let objcopy = fs::read_dir(rustlib)
+        .unwrap()
+        .map(|e| e.unwrap().path().join("bin/llvm-objcopy"))
+        .find(|p| p.exists())
+        .unwrap();
+    let bin = workspace_root.path().join("target/llvm-cov-target/debug/bin_crate");
+    let debug = workspace_root.path().join("target/llvm-cov-target/debug/bin_crate.debug");
+    Command::new(&objcopy).arg("--only-keep-debug").arg(&bin).arg(&debug).assert_success();
+    Command::new(&objcopy).arg("--strip-all").arg(&bin).assert_success();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--lcov"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("DA:3,0\nDA:4,1\nDA:5,0\nDA:6,0")
+        .stderr_contains("was removed, and is recovered from");
+    fs::remove_file(&debug).unwrap();
+    cargo_llvm_cov("report")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains(
+            "1 object files were built with instrumentation, but have no coverage mapping:\n    \
+             target/llvm-cov-target/debug/bin_crate",
+        );
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--keep-coverage-mapping"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("invalid option '--keep-coverage-mapping' for subcommand 'report'");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn split_debuginfo() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    let manifest = workspace_root.path().join("Cargo.toml");
+    let mut toml = fs::read_to_string(&manifest).unwrap();
+    toml.push_str("\n[profile.dev]\nsplit-debuginfo = \"packed\"\n");
+    fs::write(&manifest, toml).unwrap();
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--lcov", "-v", "--", "1"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("DA:3,0\nDA:4,1\nDA:5,0\nDA:6,0")
+        .stderr_contains(
+            "profile `dev` uses split-debuginfo=packed; building instrumented artifacts with \
+             split-debuginfo=off",
+        );
+    assert!(!workspace_root.path().join("target/llvm-cov-target/debug/bin_crate.dwp").exists());
+}
+
+#[test]
+fn external_runner() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    // Cargo invoked by the command builds instrumented binaries into the
+    // target directory of cargo-llvm-cov.
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov", "--external-runner", "cargo run --", "--", "1"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("DA:3,0\nDA:4,1\nDA:5,0\nDA:6,0");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov", "--external-runner", "cargo run", "--report-on-fail"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stdout_contains("DA:2,1\nDA:3,1\nDA:4,0\nDA:5,0\nDA:6,0");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--external-runner", "cargo run", "--doctests"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--external-runner may not be used together with --doctests");
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--external-runner", "cargo run"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("invalid option '--external-runner' for subcommand 'run'");
+
+    // The command can be configured in llvm-cov.toml.
+    fs::write(
+        workspace_root.path().join("llvm-cov.toml"),
+        "[profile.runner]\nexternal-runner = \"cargo run -- 2\"\n",
+    )
+    .unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov", "--cov-profile", "runner"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("DA:3,0\nDA:4,0\nDA:5,1\nDA:6,0");
+}
+
+#[test]
+fn runs() {
+    let workspace_root = test_project("flaky").unwrap();
+    // The test takes a different path after the first run.
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--runs", "3"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains(
+            "coverage of 3 lines differs across 3 runs (line coverage ranged from 77.78% to \
+             88.89%)\n    src/lib.rs: 6 (2/3), 8 (1/3), 9 (1/3)",
+        );
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--runs", "2"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("coverage of all lines is the same across 2 runs");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--runs", "0"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--runs must be greater than 0");
+}
+
+#[test]
+fn require_full() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--require-full", "src/**", "--", "1"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains(
+            "src/main.rs is required to be fully covered by --require-full `src/**`\n    \
+             uncovered lines: 3, 5, 6",
+        );
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--require-full", "tests/**"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("no source files in the report match --require-full `tests/**`");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--require-full", "src/["])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("invalid --require-full `src/[`");
+}
+
+#[test]
+fn gate() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    let baseline = workspace_root.path().join("baseline.json");
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--summary-json", "--output-path"])
+        .arg(&baseline)
+        .args(["--", "1"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--gate", "lines >= 50 && uncovered_lines <= 3"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--gate", "lines >= 80 || uncovered_functions != 0"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains(
+            "coverage gate `lines >= 80 || uncovered_functions != 0` failed\n    lines: \
+             57.14%\n    uncovered_functions: 0",
+        );
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--gate", "!regression(lines, 0)", "--gate-baseline"])
+        .arg(&baseline)
+        .current_dir(workspace_root.path())
+        .assert_success();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--gate", "!regression(lines, 0)"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("regression() in --gate requires --gate-baseline");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--gate", "lines >= 80 &&"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains(
+            "invalid --gate `lines >= 80 &&`: expected number or coverage kind, but found end of \
+             expression",
+        );
+}
+
+#[test]
+fn data_quality() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    let target_dir = workspace_root.path().join("target/llvm-cov-target");
+    let name = workspace_root.path().file_name().unwrap().to_str().unwrap();
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--no-report", "--", "1"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--summary-json"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains(r#""data_quality":{"profraw_files":1,"empty_files":[]"#);
+
+    fs::write(target_dir.join(format!("{name}-empty.profraw")), "").unwrap();
+    cargo_llvm_cov("report")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("1 profraw files are empty and were ignored")
+        .stdout_contains("Data quality:\n    profraw files: 2\n    empty profraw files: 1");
+
+    // Rebuild the binary after running it.
+    let main_rs = workspace_root.path().join("src/main.rs");
+    let src = fs::read_to_string(&main_rs).unwrap();
+    fs::write(&main_rs, src.replace("1 => {}", "1 => println!(),")).unwrap();
+    cargo_llvm_cov("run")
+        .args(["--color", "never", "--no-report", "--", "2"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    cargo_llvm_cov("report")
+        .args(["--color", "never"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("1 profraw files were generated by binaries that no longer exist")
+        .stdout_contains("    profraw files of unknown binaries: 1");
+}
+
+#[test]
+fn strict() {
+    let workspace_root = test_project("real1").unwrap();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--strict"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("not found *.profraw files");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--strict", "--ignore-filename-regex", "."])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("no source files to be included in the report");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--strict", "--summary-only"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+}
+
+#[test]
+fn fail_if_no_tests() {
+    let workspace_root = test_project("real1").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--fail-if-no-tests", "--", "no_such_test"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("no tests were executed");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--no-report", "--fail-if-no-tests"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+}
+
+#[test]
+fn keep_going() {
+    let workspace_root = test_project("virtual1").unwrap();
+    let lib = workspace_root.path().join("member1/src/lib.rs");
+    let mut src = fs::read_to_string(&lib).unwrap();
+    src.push_str("\n#[test]\nfn fail() {\n    panic!();\n}\n");
+    fs::write(&lib, src).unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--workspace", "--keep-going", "--summary-only"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stdout_contains("member2/src/lib.rs")
+        .stderr_contains("tests failed in the following workspace members: `member1`");
+}
+
+#[test]
+fn report_on_fail() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    let lib = dir.join("src/lib.rs");
+    let mut src = fs::read_to_string(&lib).unwrap();
+    src.push_str("\n#[test]\nfn fail() {\n    panic!();\n}\n");
+    fs::write(&lib, src).unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--report-on-fail", "--html"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("error: process didn't exit successfully");
+    let index = fs::read_to_string(dir.join("target/llvm-cov/html/index.html")).unwrap();
+    assert!(index.contains("<b>Tests failed:</b>"), "{index}");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--summary-json"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_not_contains("tests_failed");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--report-on-fail", "--summary-json"])
+        .current_dir(dir)
+        .assert_failure()
+        .stdout_contains(r#""tests_failed":true"#);
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--report-on-fail", "--ignore-run-fail"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("--report-on-fail may not be used together with --ignore-run-fail");
+}
+
+const SHOULD_PANIC_TEST: &str =
+    "\n#[test]\n#[should_panic = \"expected\"]\nfn panics() {\n    func(2);\n    panic!(\"expected\");\n}\n";
+
+#[test]
+fn should_panic() {
+    let workspace_root = test_project("no_test").unwrap();
+    let dir = workspace_root.path();
+    let lib = dir.join("src/lib.rs");
+    let mut src = fs::read_to_string(&lib).unwrap();
+    src.push_str(SHOULD_PANIC_TEST);
+    fs::write(&lib, src).unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains("DA:1,1\nDA:2,1\nDA:3,0\nDA:4,0\nDA:5,1\nDA:6,0");
+}
+
+// -Z panic-abort-tests requires nightly
+#[rustversion::attr(not(nightly), ignore = "requires nightly")]
+#[test]
+fn should_panic_abort() {
+    let workspace_root = test_project("no_test").unwrap();
+    let dir = workspace_root.path();
+    let manifest = dir.join("Cargo.toml");
+    let mut toml = fs::read_to_string(&manifest).unwrap();
+    toml.push_str("\n[profile.dev]\npanic = \"abort\"\n");
+    fs::write(&manifest, toml).unwrap();
+    let lib = dir.join("src/lib.rs");
+    let mut src = fs::read_to_string(&lib).unwrap();
+    src.push_str(SHOULD_PANIC_TEST);
+    fs::write(&lib, src.clone()).unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov", "-Z", "panic-abort-tests"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains("DA:1,1\nDA:2,1\nDA:3,0\nDA:4,0\nDA:5,1\nDA:6,0");
+    if cfg!(any(target_os = "linux", target_vendor = "apple")) {
+        // Continuous mode keeps the counters of the test process terminated
+        // without running the exit handlers. 50 is the exit code of test
+        // processes for passed tests.
+        src.push_str(
+            "\n#[test]\n#[should_panic]\nfn panics_in_hook() {\n    extern \"C\" {\n        \
+             fn _exit(code: i32) -> !;\n    }\n    std::panic::set_hook(Box::new(|_| unsafe { \
+             _exit(50) }));\n    func(1);\n    panic!();\n}\n",
+        );
+        fs::write(&lib, src).unwrap();
+        cargo_llvm_cov("")
+            .args(["--color", "never", "--lcov", "-Z", "panic-abort-tests"])
+            .current_dir(dir)
+            .assert_success()
+            .stdout_contains("DA:1,2\nDA:2,2\nDA:3,0\nDA:4,1\nDA:5,1\nDA:6,0");
+    }
+}
+
+#[test]
+fn library_api() {
+    let workspace_root = test_project("real1").unwrap();
+    let report = cargo_llvm_cov::Coverage::new()
+        .workspace(workspace_root.path())
+        .summary_only(true)
+        .arg("--color=never")
+        .run()
+        .unwrap();
+    assert!(report.file_names().iter().any(|f| f.ends_with("lib.rs")));
+    assert!(report.get_coverage_percent(cargo_llvm_cov::json::CoverageKind::Lines).unwrap() > 0.);
+
+    let lcov = cargo_llvm_cov::Coverage::new()
+        .workspace(workspace_root.path())
+        .subcommand("report")
+        .format(cargo_llvm_cov::ReportFormat::Lcov)
+        .arg("--color=never")
+        .render()
+        .unwrap();
+    let lcov: cargo_llvm_cov::json::LcovExport = lcov.parse().unwrap();
+    assert!(lcov.record("src/lib.rs").is_some());
+    let cobertura = cargo_llvm_cov::Coverage::new()
+        .workspace(workspace_root.path())
+        .subcommand("report")
+        .format(cargo_llvm_cov::ReportFormat::Cobertura)
+        .render()
+        .unwrap();
+    assert!(cobertura.starts_with("<?xml"), "{cobertura}");
+    let err = cargo_llvm_cov::Coverage::new()
+        .workspace(workspace_root.path())
+        .format(cargo_llvm_cov::ReportFormat::Text)
+        .run()
+        .unwrap_err();
+    assert!(err.to_string().contains("use Coverage::render instead"), "{err:#}");
+
+    let err = cargo_llvm_cov::Coverage::new()
+        .workspace(workspace_root.path())
+        .subcommand("show-env")
+        .run()
+        .unwrap_err();
+    assert!(err.to_string().contains("show-env subcommand is not supported"), "{err:#}");
+}
+
+#[test]
+fn summary_json() {
+    let workspace_root = test_project("virtual1").unwrap();
+    let output_path = &workspace_root.path().join("summary.json");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--workspace", "--summary-json", "--output-path"])
+        .arg(output_path)
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(output_path).unwrap()).unwrap();
+    assert_eq!(summary["schema_version"], 1);
+    let args = summary["args"].as_array().unwrap();
+    assert!(args.iter().any(|arg| arg == "--workspace"), "{args:?}");
+    let packages = summary["packages"].as_array().unwrap();
+    let names: Vec<_> = packages.iter().map(|p| p["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["member1", "member2", "member3", "member4"]);
+    let lines: u64 = packages.iter().map(|p| p["totals"]["lines"]["count"].as_u64().unwrap()).sum();
+    assert_eq!(lines, summary["totals"]["lines"]["count"]);
+
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--summary-json", "--json"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--summary-json may not be used together with --json");
+}
+
+fn merge_with_failure_mode(output_dir: &Path, failure_mode_all: bool) {
+    let model = "merge";
+    fs::create_dir_all(output_dir).unwrap();
+    for (extension, args) in test_set() {
+        let workspace_root = test_project(model).unwrap();
+        let output_path = &output_dir.join(model).with_extension(extension);
+        let expected = &fs::read_to_string(output_path).unwrap_or_default();
+        cargo_llvm_cov("")
+            .args(["--color", "never", "--no-report", "--features", "a"])
+            .arg("--remap-path-prefix")
+            .current_dir(workspace_root.path())
+            .assert_success();
+        cargo_llvm_cov("")
+            .args(["--color", "never", "--no-report", "--features", "b"])
+            .arg("--remap-path-prefix")
+            .current_dir(workspace_root.path())
+            .assert_success();
+        let mut cmd = cargo_llvm_cov("report");
+        cmd.args(["--color", "never", "--output-path"])
+            .arg(output_path)
+            .arg("--remap-path-prefix")
+            .args(args)
+            .current_dir(workspace_root.path());
+        cmd.assert_success();
+
+        if failure_mode_all {
+            perturb_one_header(workspace_root.path()).unwrap().unwrap();
+            cmd.assert_failure()
+                .stderr_contains("unrecognized instrumentation profile encoding format");
+            cmd.args(["--failure-mode", "all"]);
+            cmd.assert_success();
+        } else {
+            normalize_output(output_path, args).unwrap();
+            assert_output(output_path, expected).unwrap();
+        }
+    }
+}
+
+// nightly-2023-12-10 fixed bug in report generation, so the latest report is not the same as the old report.
+#[rustversion::attr(before(1.76), ignore)]
+#[test]
+fn clean_ws() {
+    let model = "merge";
+    let name = "clean_ws";
+    let output_dir = fixtures_path().join("coverage-reports").join(model);
+    fs::create_dir_all(&output_dir).unwrap();
+    for (extension, args) in test_set() {
+        let workspace_root = test_project(model).unwrap();
+        let output_path = &output_dir.join(name).with_extension(extension);
+        let expected = &fs::read_to_string(output_path).unwrap_or_default();
+        cargo_llvm_cov("")
+            .args(["--color", "never", "--no-report", "--features", "a"])
+            .arg("--remap-path-prefix")
+            .current_dir(workspace_root.path())
+            .assert_success();
+        cargo_llvm_cov("report")
+            .args(["--color", "never", "--output-path"])
+            .arg(output_path)
+            .arg("--remap-path-prefix")
+            .args(args)
+            .current_dir(workspace_root.path())
+            .assert_success();
+
+        normalize_output(output_path, args).unwrap();
+        assert_output(output_path, expected).unwrap();
+
+        cargo_llvm_cov("")
+            .args(["clean", "--color", "never", "--workspace"])
+            .current_dir(workspace_root.path())
+            .assert_success();
+        cargo_llvm_cov("")
+            .args(["--color", "never", "--no-report", "--features", "a"])
+            .arg("--remap-path-prefix")
+            .current_dir(workspace_root.path())
+            .assert_success();
+        cargo_llvm_cov("report")
+            .args(["--color", "never", "--output-path"])
+            .arg(output_path)
+            .arg("--remap-path-prefix")
+            .args(args)
+            .current_dir(workspace_root.path())
+            .assert_success();
+
+        normalize_output(output_path, args).unwrap();
+        assert_output(output_path, expected).unwrap();
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), ignore)] // binary IDs are only recorded in ELF binaries
+#[test]
+fn report_per_binary() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    let output_path = &workspace_root.path().join("report.json");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--report-per-binary", "--json", "--summary-only"])
+        .arg("--output-path")
+        .arg(output_path)
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("report saved to")
+        .stderr_contains("report-test.json");
+    let mut reports: Vec<_> = fs::read_dir(workspace_root.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|f| f.starts_with("report"))
+        .collect();
+    reports.sort_unstable();
+    // The combined report, the binary run by the integration test, the unit
+    // tests of the binary, and the integration test.
+    assert_eq!(reports.len(), 4, "{reports:?}");
+    assert!(reports[0].starts_with("report-bin_crate-"), "{reports:?}");
+    assert_eq!(reports[1], "report-bin_crate.json");
+    assert_eq!(reports[2..], ["report-test.json", "report.json"]);
+    let read = |name: &str| -> cargo_llvm_cov::json::LlvmCovJsonExport {
+        serde_json::from_str(&fs::read_to_string(workspace_root.path().join(name)).unwrap())
+            .unwrap()
+    };
+    let lines = |name: &str| {
+        read(name).get_coverage_percent(cargo_llvm_cov::json::CoverageKind::Lines).unwrap()
+    };
+    // Only the binary executes main.
+    assert!(lines("report-bin_crate.json") > 0.0);
+    assert!(lines(&reports[0]) == 0.0);
+    assert!(lines("report.json") > 0.0);
+
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--report-per-binary", "--json"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--report-per-binary requires --output-path");
+}
+
+#[cfg_attr(not(target_os = "linux"), ignore)] // binary IDs are only recorded in ELF binaries
+#[test]
+fn summary_by_target_kind() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    let output_path = &workspace_root.path().join("summary.json");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--summary-by-target-kind", "--summary-json", "--output-path"])
+        .arg(output_path)
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(output_path).unwrap()).unwrap();
+    let target_kinds = summary["target_kinds"].as_array().unwrap();
+    let kinds: Vec<_> = target_kinds.iter().map(|k| k["kind"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["binaries", "integration-tests", "unit-tests"]);
+    // Only the binary run by the integration test executes main.
+    assert!(target_kinds[0]["totals"]["lines"]["covered"].as_u64().unwrap() > 0);
+    assert_eq!(target_kinds[2]["totals"]["lines"]["covered"], 0);
+
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--summary-by-target-kind"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("Target Kind")
+        .stdout_contains("binaries");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--summary-by-target-kind", "--lcov"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--summary-by-target-kind may not be used together with --lcov");
+}
+
+#[test]
+fn summary_line() {
+    let workspace_root = test_project("real1").unwrap();
+    let output_path = &workspace_root.path().join("lcov.info");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--summary-line", "--lcov", "--output-path"])
+        .arg(output_path)
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("lines=70.4% functions=100.0% regions=70.4% branches=-");
+}
+
+#[test]
+fn lcov_relative_to() {
+    let workspace_root = test_project("real1").unwrap();
+    let output_path = &workspace_root.path().join("lcov.info");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov", "--lcov-relative-to", ".", "--output-path"])
+        .arg(output_path)
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let lcov = fs::read_to_string(output_path).unwrap();
+    assert!(lcov.lines().any(|l| l == "SF:src/lib.rs"), "{lcov}");
+    assert!(!lcov.contains(&*workspace_root.path().to_string_lossy()), "{lcov}");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov", "--lcov-relative-to", "src"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("SF:lib.rs");
+
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov-relative-to", "."])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--lcov-relative-to can only be used together with --lcov");
+}
+
+#[test]
+fn output_types() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--output-types", "lcov,cobertura,html,coveralls+"])
+        .args(["--output-dir", "coverage"])
+        .env("COVERALLS_SERVICE_NAME", "test")
+        .env("COVERALLS_PARALLEL", "true")
+        .current_dir(dir)
+        .assert_success();
+    let out = dir.join("coverage");
+    assert!(fs::read_to_string(out.join("lcov")).unwrap().contains("SF:"));
+    assert!(fs::read_to_string(out.join("cobertura.xml")).unwrap().contains("<coverage "));
+    assert!(out.join("html/index.html").exists());
+    let coveralls: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(out.join("coveralls")).unwrap()).unwrap();
+    assert_eq!(coveralls["service_name"], "test");
+    assert_eq!(coveralls["parallel"], true);
+    let file = coveralls["source_files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["name"] == "src/lib.rs")
+        .unwrap();
+    assert_eq!(file["source_digest"].as_str().unwrap().len(), 32);
+    assert_eq!(file["coverage"][3], 4);
+    assert_eq!(file["coverage"][0], serde_json::Value::Null);
+    assert_eq!(file["functions"][0]["name"], "crate1::func");
+
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--output-types", "covdir"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("but found `covdir`");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--output-types", "lcov", "--lcov"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("--output-types may not be used together with --lcov");
+}
+
+#[test]
+fn coveralls_finish() {
+    cargo_llvm_cov("coveralls-finish")
+        .args(["--color", "never"])
+        .env("COVERALLS_REPO_TOKEN", "")
+        .assert_failure()
+        .stderr_contains("`cargo llvm-cov coveralls-finish` requires COVERALLS_REPO_TOKEN");
+    cargo_llvm_cov("coveralls-finish")
+        .args(["--color", "never"])
+        .env("COVERALLS_REPO_TOKEN", "token")
+        .env("COVERALLS_SERVICE_NUMBER", "")
+        .assert_failure()
+        .stderr_contains("(COVERALLS_SERVICE_NUMBER)");
+}
+
+#[test]
+fn junit() {
+    let workspace_root = test_project("real1").unwrap();
+    let lib = workspace_root.path().join("src/lib.rs");
+    let src = fs::read_to_string(&lib).unwrap();
+    fs::write(
+        &lib,
+        format!("{src}\n#[test]\nfn fail() {{\n    panic!(\"boom\");\n}}\n#[test]\n#[ignore]\nfn skip() {{}}\n"),
+    )
+    .unwrap();
+    let junit = workspace_root.path().join("junit.xml");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--ignore-run-fail", "--junit"])
+        .arg(&junit)
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("JUnit XML saved to");
+    let xml = fs::read_to_string(&junit).unwrap();
+    for pat in [
+        "<testsuites name=\"cargo-llvm-cov\" tests=\"4\" failures=\"1\" skipped=\"1\">",
+        "<testsuite name=\"crate1 (unittests src/lib.rs)\" tests=\"4\" failures=\"1\" skipped=\"1\"",
+        "<property name=\"coverage.lines\" value=\"",
+        "<testcase name=\"tests::test\" classname=\"crate1 (unittests src/lib.rs)\"/>",
+        "<failure message=\"test failed\">thread &apos;fail&apos;",
+        "boom\n",
+        "<testcase name=\"skip\" classname=\"crate1 (unittests src/lib.rs)\">\n      <skipped/>",
+    ] {
+        assert!(xml.contains(pat), "{pat} not found in:\n{xml}");
+    }
+
+    // Without --ignore-run-fail, the results are written without coverage.
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--junit"])
+        .arg(&junit)
+        .current_dir(workspace_root.path())
+        .assert_failure();
+    let xml = fs::read_to_string(&junit).unwrap();
+    assert!(xml.contains("failures=\"1\""), "{xml}");
+    assert!(!xml.contains("coverage.lines"), "{xml}");
+
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--junit"])
+        .arg(&junit)
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("invalid option '--junit' for subcommand 'report'");
+}
+
+#[test]
+fn webhook() {
+    let workspace_root = test_project("bin_crate").unwrap();
+    // Nothing listens on port 1, so curl fails without retrying.
+    cargo_llvm_cov("")
+        .args(["--color", "never"])
+        .env("CARGO_LLVM_COV_WEBHOOK_URL", "https://127.0.0.1:1/services/secret-token")
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains(
+            "Notifying 127.0.0.1:1\nfailed to send coverage summary to webhook: process didn't exit \
+             successfully",
+        )
+        .stderr_not_contains("secret-token");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--webhook-url", "https://127.0.0.1:1/", "--webhook-baseline"])
+        .arg(workspace_root.path().join("missing.json"))
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("failed to load --webhook-baseline");
+}
+
+#[test]
+fn show() {
+    let workspace_root = test_project("real1").unwrap();
+    cargo_llvm_cov("").arg("--no-report").current_dir(workspace_root.path()).assert_success();
+    cargo_llvm_cov("show")
+        .args(["--color", "never", "src/lib.rs"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains(" 4|      4|pub fn func(x: u32) {")
+        .stdout_contains(" 6|      0|        0 => {}")
+        .stdout_contains(" 7|      2|        1 => {}")
+        .stdout_contains("13|       |#[test]");
+    cargo_llvm_cov("show")
+        .args(["--color", "never", "src/foo.rs"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("no coverage data for");
+}
+
+#[test]
+fn annotate() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    cargo_llvm_cov("").arg("--no-report").current_dir(dir).assert_success();
+    cargo_llvm_cov("annotate")
+        .args(["--color", "never"])
+        .current_dir(dir)
+        .assert_success()
+        .stderr_contains("target/llvm-cov/annotate");
+    let annotate = dir.join("target/llvm-cov/annotate");
+    let lib = fs::read_to_string(annotate.join("src/lib.rs.gcov")).unwrap();
+    assert!(lib.starts_with("        -:    0:Source:src/lib.rs\n"), "{lib}");
+    assert!(lib.contains("        4:    4:pub fn func(x: u32) {\n"), "{lib}");
+    assert!(lib.contains("    #####:    6:        0 => {}\n"), "{lib}");
+    assert!(lib.contains("        -:   13:#[test]\n"), "{lib}");
+    assert!(annotate.join("member1/src/lib.rs.gcov").exists());
+    cargo_llvm_cov("annotate")
+        .args(["--color", "never", "--html"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("invalid option '--html' for subcommand 'annotate'");
+}
+
+#[test]
+fn uncovered_spans_json() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--remap-path-prefix", "--uncovered-spans-json", "spans.json"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains("TOTAL");
+    let spans: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("spans.json")).unwrap()).unwrap();
+    assert_eq!(spans["schema_version"], 1);
+    let files = spans["files"].as_array().unwrap();
+    let lib = files.iter().find(|f| f["file"] == "src/lib.rs").unwrap();
+    // Line 6 (`0 => {}`) is not executed by the tests.
+    let lines: Vec<_> = lib["spans"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| (s["start"]["line"].as_u64().unwrap(), s["end"]["line"].as_u64().unwrap()))
+        .collect();
+    assert!(lines.contains(&(6, 6)), "{lines:?}");
+    assert!(!lines.contains(&(7, 7)), "{lines:?}");
+}
+
+#[test]
+fn list_uncovered() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    // Line 6 (`0 => {}`) is not executed by the tests.
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--remap-path-prefix", "--list-uncovered"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains("src/lib.rs:6:14-6:16\tcrate1::func")
+        .stdout_not_contains("src/lib.rs:7:")
+        .stdout_not_contains("TOTAL");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--list-uncovered=json", "--text"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains(
+            r#"{"file":"src/lib.rs","start":{"line":6,"column":14},"end":{"line":6,"column":16},"function":"crate1::func"}"#,
+        );
+    cargo_llvm_cov("report")
+        .args(["--list-uncovered=yaml"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("--list-uncovered must be text or json, but found `yaml`");
+}
+
+#[test]
+fn json_lines() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--remap-path-prefix", "--json", "--json-lines"])
+        .args(["--output-path", "cov.jsonl"])
+        .current_dir(dir)
+        .assert_success();
+    let out = fs::read_to_string(dir.join("cov.jsonl")).unwrap();
+    let lines: Vec<serde_json::Value> =
+        out.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let lib = lines.iter().find(|l| l["filename"] == "src/lib.rs").unwrap();
+    assert_eq!(lib["type"], "file");
+    assert!(lib["functions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|f| f["name"].as_str().unwrap().contains("4func")));
+    let totals = lines.last().unwrap();
+    assert_eq!(totals["type"], "totals");
+    assert!(totals["totals"]["lines"]["count"].as_u64().unwrap() > 0);
+
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--json-lines"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("--json-lines can only be used together with --json");
+}
+
+#[test]
+fn msgpack() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--json", "--msgpack", "--output-path", "cov.msgpack"])
+        .current_dir(dir)
+        .assert_success();
+    let out = fs::read(dir.join("cov.msgpack")).unwrap();
+    // A map with 4 entries (`data`, `type`, `version`, and `cargo_llvm_cov`), starting with `data`.
+    assert_eq!(out[..6], [0x84, 0xa4, b'd', b'a', b't', b'a']);
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--json", "--output-path", "cov.json"])
+        .current_dir(dir)
+        .assert_success();
+    assert!(out.len() < fs::read(dir.join("cov.json")).unwrap().len());
+
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--json", "--msgpack", "--json-lines"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("--msgpack may not be used together with --json-lines");
+}
+
+#[test]
+fn summary_format() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov", "--output-path", "lcov.info"])
+        .args(["--summary-format", "markdown"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains(
+            "| Filename | Lines | Functions | Regions | Branches |
+             | :--- | ---: | ---: | ---: | ---: |",
+        )
+        .stdout_contains("| **TOTAL** | ");
+    assert!(fs::read_to_string(dir.join("lcov.info")).unwrap().contains("SF:"));
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--summary-format", "csv"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains("filename,lines_count,lines_covered,lines_percent,")
+        .stdout_not_contains("Missed Regions");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--summary-format", "xml"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("--summary-format must be table, json, markdown, or csv, but found `xml`");
+}
+
+#[test]
+fn all_reports() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--all-reports", "--output-dir", "coverage/{package}"])
+        .current_dir(dir)
+        .assert_success()
+        .stderr_contains("reports saved to coverage/crate1");
+    let output_dir = dir.join("coverage/crate1");
+    assert!(output_dir.join("html/index.html").is_file());
+    assert!(fs::read_to_string(output_dir.join("lcov.info")).unwrap().contains("SF:"));
+    let json: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(output_dir.join("coverage.json")).unwrap())
+            .unwrap();
+    assert!(json["data"][0]["files"].is_array());
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(output_dir.join("summary.json")).unwrap())
+            .unwrap();
+    assert!(summary["totals"]["lines"]["count"].as_u64().unwrap() > 0);
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(output_dir.join("manifest.json")).unwrap())
+            .unwrap();
+    assert_eq!(manifest["schema_version"], 1);
+    assert_eq!(manifest["package"], "crate1");
+    assert_eq!(manifest["reports"]["lcov"], "lcov.info");
+
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--all-reports", "--lcov"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("--all-reports may not be used together with --lcov");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--all-reports", "--output-dir", "coverage/{commit}"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains(
+            "{commit} in --output-dir requires the workspace to be in a git repository",
+        );
+}
+
+#[test]
+fn summary_by_instantiation() {
+    // `func` in this crate has two instantiations, and each of them only covers one of the branches.
+    let workspace_root = test_project("instantiations").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--summary-by-instantiation"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains(
+            "Functions                           2               2   100.00%               2   100.00%
+             Lines                               9               9   100.00%               7    77.78%
+             1 of 2 functions are generic (3 instantiations in total)",
+        );
+    let output_path = &workspace_root.path().join("summary.json");
+    cargo_llvm_cov("report")
+        .args(["--summary-by-instantiation", "--summary-json", "--output-path"])
+        .arg(output_path)
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(output_path).unwrap()).unwrap();
+    let instantiations = &summary["instantiations"];
+    assert_eq!(instantiations["generic_functions"], 1);
+    assert_eq!(instantiations["lines"]["covered_any"], 9);
+    assert_eq!(instantiations["lines"]["covered_all"], 7);
+}
+
+#[test]
+fn demangle_keep_hashes() {
+    let workspace_root = test_project("instantiations").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--text", "--show-instantiations", "--demangle-keep-hashes"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("]::func::<f32>:")
+        .stdout_not_contains("| instantiations::func::<f32>:");
+}
+
+#[test]
+fn show_excluded() {
+    let workspace_root = test_project("real1").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--show-excluded", "--exclude-test-functions"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains(
+            "Excluded from Report:
+             tests, examples, and benches directories: 1 files, 6 lines
+                 src/tests/mod.rs: 6 lines
+             --exclude-test-functions: 1 functions, 6 lines
+                 src/lib.rs:14: crate1::test",
+        );
+}
+
+#[test]
+fn coverage_off() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    // The items are found in the source files, so this doesn't need to be compiled.
+    fs::write(
+        dir.join("src/off.rs"),
+        "#[cfg_attr(coverage_nightly, coverage(off))]\n#[inline]\nfn excluded() {}\n",
+    )
+    .unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--show-excluded", "--coverage-off-json", "off.json"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains(
+            "#[coverage(off)]: 1 items
+                 src/off.rs:1: fn excluded",
+        );
+    let export: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(dir.join("off.json")).unwrap()).unwrap();
+    assert_eq!(export["items"].as_array().unwrap().len(), 1);
+    assert_eq!(export["items"][0]["kind"], "fn");
+    assert_eq!(export["items"][0]["attribute"], "#[cfg_attr(coverage_nightly, coverage(off))]");
+}
+
+#[test]
+fn diff() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--remap-path-prefix", "--lcov", "--output-path", "head.info"])
+        .current_dir(dir)
+        .assert_success();
+    // Make line 6 (`0 => {}`, not executed by the tests) covered and line 7
+    // (`1 => {}`) uncovered in the base report.
+    let head = fs::read_to_string(dir.join("head.info")).unwrap();
+    let (member, lib) = head.split_at(head.find("SF:src/lib.rs").unwrap());
+    let lib = lib.replacen("DA:6,0\n", "DA:6,1\n", 1).replacen("DA:7,2\n", "DA:7,0\n", 1);
+    fs::write(dir.join("base.info"), format!("{member}{lib}")).unwrap();
+    cargo_llvm_cov("diff")
+        .args(["--color", "never", "base.info", "head.info"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains("newly covered lines: 1, newly uncovered lines: 1")
+        .stderr_contains("target/llvm-cov/diff/index.html");
+    let index = fs::read_to_string(dir.join("target/llvm-cov/diff/index.html")).unwrap();
+    assert!(index.contains(">src/lib.rs</a>"));
+    assert!(!index.contains("member1"));
+    cargo_llvm_cov("diff")
+        .args(["--color", "never", "base.info"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("`cargo llvm-cov diff` requires the paths to two exported reports");
+}
+
+#[test]
+fn trend() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    fs::create_dir(dir.join("history")).unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--summary-json", "--output-path", "history/2024-01-01.json"])
+        .current_dir(dir)
+        .assert_success();
+    fs::copy(dir.join("history/2024-01-01.json"), dir.join("history/2024-02-01.json")).unwrap();
+    cargo_llvm_cov("trend")
+        .args(["--color", "never", "history"])
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains("Report")
+        .stdout_contains("2024-01-01")
+        .stdout_contains("2024-02-01");
+    cargo_llvm_cov("trend")
+        .args(["--color", "never", "--plot", "trend.svg", "history"])
+        .current_dir(dir)
+        .assert_success()
+        .stderr_contains("trend.svg");
+    let svg = fs::read_to_string(dir.join("trend.svg")).unwrap();
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.contains(">2024-02-01</text>"));
+    cargo_llvm_cov("trend")
+        .args(["--color", "never"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("`cargo llvm-cov trend` requires the paths to exported reports");
+}
+
+#[test]
+fn html_from_lcov() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov", "--output-path", "lcov.info"])
+        .current_dir(dir)
+        .assert_success();
+    cargo_llvm_cov("html")
+        .args(["--color", "never", "--from-lcov", "lcov.info"])
+        .current_dir(dir)
+        .assert_success()
+        .stderr_contains("report saved to");
+    let html = dir.join("target/llvm-cov/html");
+    let index = fs::read_to_string(html.join("index.html")).unwrap();
+    assert!(index.contains("<a href='coverage/src/lib.rs.html'>"), "{index}");
+    let page = fs::read_to_string(html.join("coverage/src/lib.rs.html")).unwrap();
+    assert!(page.contains("href='../../style.css'"), "{page}");
+    assert!(page.contains("<pre>pub fn func(x: u32) {</pre>"), "{page}");
+    cargo_llvm_cov("html")
+        .args(["--color", "never"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("`cargo llvm-cov html` requires the path to a report in the lcov format");
 }
 
-// nightly-2023-12-10 fixed bug in report generation, so the latest report is not the same as the old report.
-#[rustversion::attr(before(1.76), ignore)]
 #[test]
-fn cargo_config() {
-    run("cargo_config", "cargo_config", &[], &[]);
-    run("cargo_config_toml", "cargo_config_toml", &[], &[]);
+fn external_report() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    fs::create_dir_all(dir.join("py")).unwrap();
+    fs::write(dir.join("py/app.py"), "def f():\n    return 1\n").unwrap();
+    fs::write(
+        dir.join("coverage.xml"),
+        "<?xml version=\"1.0\" ?>\n<coverage><sources><source>.</source></sources><packages>\
+         <package name=\"py\"><classes><class name=\"app.py\" filename=\"py/app.py\"><methods/>\
+         <lines><line number=\"1\" hits=\"1\"/><line number=\"2\" hits=\"0\"/></lines>\
+         </class></classes></package></packages></coverage>\n",
+    )
+    .unwrap();
+    fs::write(dir.join("js.info"), "SF:web/index.js\nDA:1,3\nend_of_record\n").unwrap();
+
+    let out = cargo_llvm_cov("")
+        .args(["--color", "never", "--cobertura", "--no-timestamps"])
+        .args(["--external-report", "python=coverage.xml", "--external-report", "js=js.info"])
+        .current_dir(dir)
+        .assert_success();
+    out.stdout_contains("<package name=\"rust:src\"")
+        .stdout_contains("<package name=\"python:py\"")
+        .stdout_contains("<class name=\"py.app.py\" filename=\"py/app.py\"")
+        .stdout_contains("<package name=\"js:web\"")
+        .stdout_contains("timestamp=\"0\"");
+
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--html", "--external-report", "python=coverage.xml"])
+        .current_dir(dir)
+        .assert_success()
+        .stderr_contains("report saved to");
+    let html = dir.join("target/llvm-cov/html");
+    let index = fs::read_to_string(html.join("index.html")).unwrap();
+    assert!(index.contains("<tr><th colspan='4'>rust</th></tr>"), "{index}");
+    assert!(index.contains("<a href='coverage/src/lib.rs.html'>"), "{index}");
+    assert!(index.contains("<tr><th colspan='4'>python</th></tr>"), "{index}");
+    assert!(
+        index.contains("<th>python Totals</th><th class='number'>50.00% (1/2)</th>"),
+        "{index}"
+    );
+    let page = fs::read_to_string(html.join("coverage/py/app.py.html")).unwrap();
+    assert!(page.contains("<pre>def f():</pre>"), "{page}");
+
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov", "--output-path", "lcov.info"])
+        .current_dir(dir)
+        .assert_success();
+    cargo_llvm_cov("html")
+        .args(["--color", "never", "--from-lcov", "lcov.info", "--external-report", "js=js.info"])
+        .current_dir(dir)
+        .assert_success();
+    let index = fs::read_to_string(html.join("index.html")).unwrap();
+    assert!(index.contains("<tr><th colspan='4'>js</th></tr>"), "{index}");
+
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--lcov", "--external-report", "python=coverage.xml"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains(
+            "--external-report can only be used together with --html, --open, or --cobertura",
+        );
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--cobertura", "--external-report", "coverage.xml"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("--external-report must be in the form of LANG=PATH");
 }
 
-// feature(coverage_attribute) requires nightly
-#[rustversion::attr(not(nightly), ignore)]
 #[test]
-fn no_coverage() {
-    let model = "no_coverage";
-    let id = format!("{model}/{model}");
-    for (extension, args2) in test_set() {
-        // TODO: On windows, the order of the instantiations in the generated coverage report will be different.
-        if extension == "full.json" && cfg!(windows) {
-            continue;
-        }
-        test_report(model, model, extension, None, args2, &[]).context(id.clone()).unwrap();
-    }
-
-    let name = "no_cfg_coverage";
-    let id = format!("{model}/{name}");
-    for (extension, args2) in test_set() {
-        // TODO: On windows, the order of the instantiations in the generated coverage report will be different.
-        if extension == "full.json" && cfg!(windows) {
-            continue;
-        }
-        test_report(model, name, extension, None, &[args2, &["--no-cfg-coverage"]].concat(), &[])
-            .context(id.clone())
-            .unwrap();
-    }
+fn timings() {
+    let workspace_root = test_project("real1").unwrap();
+    let output_path = &workspace_root.path().join("timings.json");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--summary-only", "--timings", "--timings-json"])
+        .arg(output_path)
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_contains("Timings:")
+        .stderr_contains("build and run")
+        .stderr_contains("Merging")
+        .stderr_contains("Exporting coverage data of");
+    let timings: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(output_path).unwrap()).unwrap();
+    let phases: Vec<_> =
+        timings["phases"].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+    assert_eq!(phases, [
+        "clean",
+        "build and run",
+        "merge",
+        "collect object files",
+        "prepare filters",
+        "report"
+    ]);
+    assert!(timings["total"].as_f64().unwrap() > 0.0);
 }
 
-// feature(coverage_attribute) requires nightly
-#[rustversion::attr(not(nightly), ignore)]
+#[cfg_attr(windows, ignore)] // `echo` may not be available
 #[test]
-fn coverage_helper() {
-    let model = "coverage_helper";
-    let id = format!("{model}/{model}");
-    for (extension, args2) in test_set() {
-        // TODO: On windows, the order of the instantiations in the generated coverage report will be different.
-        if extension == "full.json" && cfg!(windows) {
-            continue;
-        }
-        test_report(model, model, extension, None, args2, &[]).context(id.clone()).unwrap();
-    }
+fn open_report() {
+    let model = "real1";
+    let workspace_root = test_project(model).unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--open"])
+        .current_dir(workspace_root.path())
+        .env("BROWSER", "echo")
+        .assert_success()
+        .stdout_contains(
+            workspace_root.path().join("target/llvm-cov/html/index.html").to_string_lossy(),
+        );
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--remap-path-prefix", "--open=src/lib.rs"])
+        .args(["--browser", "echo opening %s"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains(format!(
+            "opening {}",
+            workspace_root.path().join("target/llvm-cov/html/coverage/src/lib.rs.html").display()
+        ));
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--open=no_such_file.rs"])
+        .current_dir(workspace_root.path())
+        .env("BROWSER", "echo")
+        .assert_failure()
+        .stderr_contains("no page for `no_such_file.rs` found in the HTML report");
 }
 
-// The order of the instantiations in the generated coverage report will be different depending on the version.
-#[rustversion::attr(not(nightly), ignore)]
 #[test]
-fn merge() {
-    // The order of the instantiations in the generated coverage report will be different depending on the platform.
-    if !cfg!(all(target_arch = "x86_64", target_os = "linux")) {
-        return;
-    }
-    let output_dir = fixtures_path().join("coverage-reports").join("merge");
-    merge_with_failure_mode(&output_dir, false);
+fn blame() {
+    let workspace_root = test_project("real1").unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Alice", "-c", "user.email=alice@example.com"])
+            .args(args)
+            .current_dir(workspace_root.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["init", "-q"]);
+    git(&["add", "src/lib.rs"]);
+    git(&["commit", "-q", "-m", "initial commit"]);
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--html", "--blame", "--remap-path-prefix"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let html = workspace_root.path().join("target/llvm-cov/html");
+    let page = fs::read_to_string(html.join("coverage/src/lib.rs.html")).unwrap();
+    assert!(page.contains("<td><pre>Blame</pre></td>"));
+    assert!(page.contains("<pre>Alice (today)</pre>"));
+    assert!(fs::read_to_string(html.join("style.css")).unwrap().contains(".blame"));
+    // Files not tracked by git are not annotated.
+    let page = fs::read_to_string(html.join("coverage/member1/src/lib.rs.html")).unwrap();
+    assert!(!page.contains("Blame"));
+
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--blame"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--blame can only be used together with either --html or --open");
 }
 
 #[test]
-fn merge_failure_mode_all() {
-    let tempdir = tempdir().unwrap();
-    merge_with_failure_mode(tempdir.path(), true);
+fn reproducible_html() {
+    let workspace_root = test_project("real1").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--html", "--reproducible-html"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let html = workspace_root.path().join("target/llvm-cov/html");
+    let index = fs::read_to_string(html.join("index.html")).unwrap();
+    assert!(!index.contains("Generated by llvm-cov"), "{index}");
+    assert!(!index.contains("Created:"), "{index}");
+    assert!(!index.contains(&*workspace_root.path().to_string_lossy()), "{index}");
+    assert!(html.join("coverage/src/lib.rs.html").exists());
+
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--reproducible-html"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains(
+            "--reproducible-html can only be used together with either --html or --open",
+        );
 }
 
-fn merge_with_failure_mode(output_dir: &Path, failure_mode_all: bool) {
-    let model = "merge";
-    fs::create_dir_all(output_dir).unwrap();
-    for (extension, args) in test_set() {
-        let workspace_root = test_project(model).unwrap();
-        let output_path = &output_dir.join(model).with_extension(extension);
-        let expected = &fs::read_to_string(output_path).unwrap_or_default();
-        cargo_llvm_cov("")
-            .args(["--color", "never", "--no-report", "--features", "a"])
-            .arg("--remap-path-prefix")
-            .current_dir(workspace_root.path())
-            .assert_success();
-        cargo_llvm_cov("")
-            .args(["--color", "never", "--no-report", "--features", "b"])
-            .arg("--remap-path-prefix")
-            .current_dir(workspace_root.path())
-            .assert_success();
-        let mut cmd = cargo_llvm_cov("report");
-        cmd.args(["--color", "never", "--output-path"])
-            .arg(output_path)
-            .arg("--remap-path-prefix")
-            .args(args)
-            .current_dir(workspace_root.path());
-        cmd.assert_success();
+#[test]
+fn assets_dir() {
+    let workspace_root = test_project("real1").unwrap();
+    let assets = workspace_root.path().join("assets");
+    fs::create_dir_all(assets.join("fonts")).unwrap();
+    fs::write(assets.join("fonts/a.woff2"), "").unwrap();
+    fs::write(assets.join("style.css"), "@font-face { src: url(fonts/a.woff2); }\n").unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--html", "--assets-dir", "assets"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stderr_not_contains("loads resources from the network");
+    let html = workspace_root.path().join("target/llvm-cov/html");
+    assert!(html.join("fonts/a.woff2").exists());
+    let style = fs::read_to_string(html.join("style.css")).unwrap();
+    assert_eq!(style, "@font-face { src: url(fonts/a.woff2); }\n");
 
-        if failure_mode_all {
-            perturb_one_header(workspace_root.path()).unwrap().unwrap();
-            cmd.assert_failure()
-                .stderr_contains("unrecognized instrumentation profile encoding format");
-            cmd.args(["--failure-mode", "all"]);
-            cmd.assert_success();
-        } else {
-            normalize_output(output_path, args).unwrap();
-            assert_output(output_path, expected).unwrap();
-        }
-    }
+    fs::write(assets.join("style.css"), "@import 'https://cdn.example.com/a.css';\n").unwrap();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--html", "--assets-dir", "assets"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains(
+            "html report loads resources from the network, so it doesn't render correctly without \
+             internet access: https://cdn.example.com/a.css",
+        );
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--assets-dir", "assets"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--assets-dir can only be used together with either --html or --open");
 }
 
-// nightly-2023-12-10 fixed bug in report generation, so the latest report is not the same as the old report.
-#[rustversion::attr(before(1.76), ignore)]
 #[test]
-fn clean_ws() {
-    let model = "merge";
-    let name = "clean_ws";
-    let output_dir = fixtures_path().join("coverage-reports").join(model);
-    fs::create_dir_all(&output_dir).unwrap();
-    for (extension, args) in test_set() {
-        let workspace_root = test_project(model).unwrap();
-        let output_path = &output_dir.join(name).with_extension(extension);
-        let expected = &fs::read_to_string(output_path).unwrap_or_default();
-        cargo_llvm_cov("")
-            .args(["--color", "never", "--no-report", "--features", "a"])
-            .arg("--remap-path-prefix")
-            .current_dir(workspace_root.path())
-            .assert_success();
-        cargo_llvm_cov("report")
-            .args(["--color", "never", "--output-path"])
-            .arg(output_path)
-            .arg("--remap-path-prefix")
-            .args(args)
-            .current_dir(workspace_root.path())
-            .assert_success();
+fn lcov_api() {
+    let workspace_root = test_project("real1").unwrap();
+    cargo_llvm_cov("").arg("--no-report").current_dir(workspace_root.path()).assert_success();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--lcov", "--output-path", "out.lcov"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let s = fs::read_to_string(workspace_root.path().join("out.lcov")).unwrap();
+    let lcov: cargo_llvm_cov::json::LcovExport = s.parse().unwrap();
+    // 8 of 27 lines are not covered.
+    assert_eq!(lcov.uncovered_lines().count(), 8);
+    assert!((lcov.lines_percent() - 19_f64 / 27_f64 * 100_f64).abs() < 1e-9);
+    let record = lcov.record("src/lib.rs").unwrap();
+    assert!(record.source_file.ends_with("lib.rs"));
+    assert!(record.lines_found() > 0);
+    assert!(lcov.record("src/missing.rs").is_none());
 
-        normalize_output(output_path, args).unwrap();
-        assert_output(output_path, expected).unwrap();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--cobertura", "--output-path", "cobertura.xml"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let s = fs::read_to_string(workspace_root.path().join("cobertura.xml")).unwrap();
+    let cobertura: cargo_llvm_cov::json::CoberturaExport = s.parse().unwrap();
+    assert!((cobertura.line_rate() - 19_f64 / 27_f64).abs() < 1e-9);
+    assert_eq!(cobertura.to_lcov().uncovered_lines().count(), 8);
+}
 
-        cargo_llvm_cov("")
-            .args(["clean", "--color", "never", "--workspace"])
-            .current_dir(workspace_root.path())
-            .assert_success();
-        cargo_llvm_cov("")
-            .args(["--color", "never", "--no-report", "--features", "a"])
-            .arg("--remap-path-prefix")
-            .current_dir(workspace_root.path())
-            .assert_success();
-        cargo_llvm_cov("report")
-            .args(["--color", "never", "--output-path"])
-            .arg(output_path)
-            .arg("--remap-path-prefix")
-            .args(args)
-            .current_dir(workspace_root.path())
-            .assert_success();
+#[test]
+fn fail_uncovered_lines() {
+    let workspace_root = test_project("real1").unwrap();
+    cargo_llvm_cov("").arg("--no-report").current_dir(workspace_root.path()).assert_success();
+    // 8 of 27 lines are not covered.
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--fail-uncovered-lines", "0"])
+        .current_dir(workspace_root.path())
+        .assert_failure();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--fail-uncovered-lines", "7", "--summary-line"])
+        .current_dir(workspace_root.path())
+        .assert_failure();
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--fail-uncovered-lines", "8"])
+        .current_dir(workspace_root.path())
+        .assert_success();
+}
 
-        normalize_output(output_path, args).unwrap();
-        assert_output(output_path, expected).unwrap();
-    }
+#[test]
+fn precision() {
+    let workspace_root = test_project("real1").unwrap();
+    cargo_llvm_cov("").arg("--no-report").current_dir(workspace_root.path()).assert_success();
+    // 19 of 27 lines (70.370...%) are covered.
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--fail-under-lines", "70.4"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stdout_contains("70.37%");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--fail-under-lines", "70.4", "--precision", "1"])
+        .args(["--summary-line"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("      70.4%")
+        .stdout_contains("lines=70.4%");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--fail-under-lines", "70.4", "--precision", "1"])
+        .args(["--rounding", "floor", "--summary-format", "json"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stdout_contains(r#""lines":{"count":27,"covered":19,"percent":70.3}"#);
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--precision", "11"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--precision must be less than or equal to 10");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--rounding", "up"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--rounding must be nearest or floor, but found `up`");
 }
 
-#[cfg_attr(windows, ignore)] // `echo` may not be available
 #[test]
-fn open_report() {
-    let model = "real1";
-    let workspace_root = test_project(model).unwrap();
+fn show_counts() {
+    let workspace_root = test_project("real1").unwrap();
     cargo_llvm_cov("")
-        .args(["--color", "never", "--open"])
+        .args(["--color", "never", "--show-counts", "--summary-line"])
         .current_dir(workspace_root.path())
-        .env("BROWSER", "echo")
         .assert_success()
+        .stdout_contains("  8  70.37% (19/27)")
         .stdout_contains(
-            workspace_root.path().join("target/llvm-cov/html/index.html").to_string_lossy(),
+            "lines=70.4%(19/27) functions=100.0%(4/4) regions=70.4%(19/27) branches=-",
         );
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--show-counts=only", "--summary-format", "markdown"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("| **TOTAL** | 19/27 | 4/4 | 19/27 | - |");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--show-counts=all"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("--show-counts must be both or only, but found `all`");
+}
+
+#[test]
+fn fail_under_diff_lines() {
+    let workspace_root = test_project("real1").unwrap();
+    let dir = workspace_root.path();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Alice", "-c", "user.email=alice@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["init", "-q"]);
+    git(&["add", "."]);
+    git(&["commit", "-q", "-m", "initial commit"]);
+    // Change line 6 (`0 => {}`, not executed by the tests) and line 7 (`1 => {}`, executed).
+    let lib = fs::read_to_string(dir.join("src/lib.rs")).unwrap();
+    let lib = lib.replacen("0 => {}", "0 => {} // changed", 1).replacen("1 => {}", "1 => { }", 1);
+    fs::write(dir.join("src/lib.rs"), lib).unwrap();
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--fail-under-diff-lines", "50", "--diff-base", "HEAD"])
+        .current_dir(dir)
+        .assert_success()
+        .stderr_contains("1 of 2 changed lines since HEAD are covered (50.00%)");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--fail-under-diff-lines", "60", "--diff-base", "HEAD"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("coverage of changed lines (50.00%) is less than 60%")
+        .stderr_contains("src/lib.rs: 6");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--diff-base", "HEAD"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("--diff-base can only be used together with --fail-under-diff-lines");
+
+    cargo_llvm_cov("install-hook")
+        .args(["--color", "never", "--pre-push", "--fail-under-diff-lines", "90"])
+        .current_dir(dir)
+        .assert_success()
+        .stderr_contains(".git/hooks/pre-push");
+    let hook = dir.join(".git/hooks/pre-push");
+    let script = fs::read_to_string(&hook).unwrap();
+    assert!(script.starts_with("#!/bin/sh\n"));
+    assert!(script.contains("cargo llvm-cov --no-clean --summary-only --fail-under-diff-lines 90"));
+    // Hooks installed by cargo-llvm-cov can be overwritten.
+    cargo_llvm_cov("install-hook")
+        .args(["--color", "never", "--pre-push", "--fail-under-diff-lines", "80"])
+        .current_dir(dir)
+        .assert_success();
+    assert!(fs::read_to_string(&hook).unwrap().contains("--fail-under-diff-lines 80"));
+    fs::write(&hook, "#!/bin/sh\n").unwrap();
+    cargo_llvm_cov("install-hook")
+        .args(["--color", "never", "--pre-push", "--fail-under-diff-lines", "80"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("is not managed by cargo-llvm-cov");
+    cargo_llvm_cov("install-hook")
+        .args(["--color", "never", "--fail-under-diff-lines", "80"])
+        .current_dir(dir)
+        .assert_failure()
+        .stderr_contains("requires the kind of hook to install (--pre-push)");
+}
+
+#[cfg_attr(windows, ignore)] // `cat` and `grep` may not be available
+#[test]
+fn exec_formatter() {
+    let workspace_root = test_project("real1").unwrap();
+    let output_path = &workspace_root.path().join("report.json");
+    cargo_llvm_cov("")
+        .args(["--color", "never", "--exec-formatter", "cat", "--output-path"])
+        .arg(output_path)
+        .current_dir(workspace_root.path())
+        .assert_success();
+    let json: cargo_llvm_cov::json::LlvmCovJsonExport =
+        serde_json::from_str(&fs::read_to_string(output_path).unwrap()).unwrap();
+    assert!(json.file_names().iter().any(|f| f.ends_with("lib.rs")));
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--exec-formatter", "grep -c cargo_llvm_cov"])
+        .current_dir(workspace_root.path())
+        .assert_success()
+        .stdout_contains("1");
+    cargo_llvm_cov("report")
+        .args(["--color", "never", "--exec-formatter", "false"])
+        .current_dir(workspace_root.path())
+        .assert_failure()
+        .stderr_contains("process didn't exit successfully: `false`");
+}
+
+#[rustversion::attr(before(1.80), ignore = "requires stable --check-cfg")]
+#[test]
+fn custom_cfg() {
+    let workspace_root = test_project("no_test").unwrap();
+    let dir = workspace_root.path();
+    fs::write(dir.join("llvm-cov.toml"), "cfg = [\"coverage_slow_paths\", 'mode=\"fast\"']\n")
+        .unwrap();
+    fs::write(
+        dir.join("src/lib.rs"),
+        "#[test]\nfn f() {\n    assert!(cfg!(coverage_slow_paths));\n    assert!(cfg!(mode = \"fast\"));\n}\n",
+    )
+    .unwrap();
+    // unexpected_cfgs warnings are denied, so this also checks --check-cfg.
+    cargo_llvm_cov("")
+        .args(["--color", "never"])
+        .env("RUSTFLAGS", "-D unexpected_cfgs")
+        .current_dir(dir)
+        .assert_success();
+    cargo_llvm_cov("show-env")
+        .current_dir(dir)
+        .assert_success()
+        .stdout_contains("--cfg=coverage_slow_paths --check-cfg=cfg(coverage_slow_paths)");
 }
 
 #[test]
@@ -356,6 +2574,34 @@ fn invalid_arg() {
                 ));
             }
         }
+        if subcommand != "run" {
+            cargo_llvm_cov(subcommand)
+                .arg("--args-file=v")
+                .assert_failure()
+                .stderr_contains("invalid option '--args-file'");
+        }
+        if !matches!(subcommand, "" | "test") {
+            cargo_llvm_cov(subcommand)
+                .arg("--keep-going")
+                .assert_failure()
+                .stderr_contains("invalid option '--keep-going'");
+        }
+        if subcommand != "nextest" {
+            cargo_llvm_cov(subcommand)
+                .arg("--discard-failed-attempts")
+                .assert_failure()
+                .stderr_contains("invalid option '--discard-failed-attempts'");
+            cargo_llvm_cov(subcommand)
+                .arg("--emit-test-contribution=v")
+                .assert_failure()
+                .stderr_contains("invalid option '--emit-test-contribution'");
+        }
+        if subcommand != "report" {
+            cargo_llvm_cov(subcommand)
+                .arg("--nextest-archive-file=v")
+                .assert_failure()
+                .stderr_contains("invalid option '--nextest-archive-file'");
+        }
         if !matches!(subcommand, "" | "nextest" | "clean") {
             for arg in ["--workspace"] {
                 cargo_llvm_cov(subcommand).arg(arg).assert_failure().stderr_contains(format!(